@@ -0,0 +1,599 @@
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::str::FromStr;
+
+use bitcoincore_rpc::bitcoin::BlockHash;
+use tokio::sync::mpsc::UnboundedSender;
+
+use log::{error, info};
+use serde::Deserialize;
+use warp::http::{Response, StatusCode};
+use warp::{reject, Filter, Rejection, Reply};
+
+use crate::config::Network;
+use crate::db;
+use crate::domain::Tree;
+use crate::events::EventLog;
+use crate::expanded::ExpandedForks;
+use crate::export;
+use crate::headertree;
+use crate::incident::{self, ActiveIncidents};
+use crate::logging::{self, Logger};
+use crate::paused::PausedNodes;
+use crate::preflight::{self, NodeDescriptor};
+use crate::quota::Quarantine;
+use crate::suppressed::{self, SuppressedTips};
+use crate::types::{
+    now_timestamp, AdminNodeJson, AdminNodesJsonResponse, Caches, Db, DeadLetterJsonResponse,
+    ExpandedForkJson, ExpandedForksJsonResponse, IncidentJson, IncidentsJsonResponse, LogLevelJson,
+    LogLevelsJsonResponse, QuarantineJsonResponse, QuarantinedHeaderJson, QuarantinedNodeJson,
+    QueuedNotificationJson, SuppressedTipJson, SuppressedTipsJsonResponse, Versioned,
+};
+
+#[derive(Debug)]
+struct Unauthorized;
+
+impl reject::Reject for Unauthorized {}
+
+// If the authorization header carries the bearer token. Compared in
+// constant time, so the time taken doesn't leak how much of the token
+// matched.
+pub fn bearer_token_matches(authorization: Option<&str>, token: &str) -> bool {
+    let expected = format!("Bearer {}", token);
+    match authorization {
+        Some(authorization) if authorization.len() == expected.len() => {
+            authorization
+                .bytes()
+                .zip(expected.bytes())
+                .fold(0, |diff, (a, b)| diff | (a ^ b))
+                == 0
+        }
+        _ => false,
+    }
+}
+
+// Only lets requests with the configured bearer token pass. Without a
+// configured token, the admin API is disabled and requests are rejected as
+// not found.
+pub fn with_admin_auth(
+    admin_token: Option<String>,
+) -> impl Filter<Extract = (), Error = Rejection> + Clone {
+    warp::header::optional::<String>("authorization")
+        .and_then(move |authorization: Option<String>| {
+            let admin_token = admin_token.clone();
+            async move {
+                match admin_token {
+                    None => Err(reject::not_found()),
+                    Some(token) => {
+                        if bearer_token_matches(authorization.as_deref(), &token) {
+                            Ok(())
+                        } else {
+                            Err(reject::custom(Unauthorized))
+                        }
+                    }
+                }
+            }
+        })
+        .untuple_one()
+}
+
+pub async fn handle_rejection(err: Rejection) -> Result<impl Reply, Rejection> {
+    if err.find::<Unauthorized>().is_some() {
+        Ok(warp::reply::with_status(
+            "Unauthorized",
+            StatusCode::UNAUTHORIZED,
+        ))
+    } else {
+        Err(err)
+    }
+}
+
+fn internal_error(e: impl std::fmt::Display) -> warp::reply::WithStatus<String> {
+    error!("Admin API error: {}", e);
+    warp::reply::with_status(
+        "Internal Server Error".to_string(),
+        StatusCode::INTERNAL_SERVER_ERROR,
+    )
+}
+
+pub async fn dead_letter_response(db: Db) -> Result<Box<dyn Reply>, Infallible> {
+    match db::load_dead_notifications(db).await {
+        Ok(notifications) => Ok(Box::new(warp::reply::json(&Versioned::new(
+            DeadLetterJsonResponse {
+                notifications: notifications
+                    .iter()
+                    .map(QueuedNotificationJson::from)
+                    .collect(),
+            },
+        )))),
+        Err(e) => Ok(Box::new(internal_error(e))),
+    }
+}
+
+pub async fn dead_letter_retry_response(id: u64, db: Db) -> Result<Box<dyn Reply>, Infallible> {
+    match db::requeue_dead_notification(db, id, now_timestamp()).await {
+        Ok(true) => Ok(Box::new(StatusCode::NO_CONTENT)),
+        Ok(false) => Ok(Box::new(StatusCode::NOT_FOUND)),
+        Err(e) => Ok(Box::new(internal_error(e))),
+    }
+}
+
+pub async fn dead_letter_delete_response(id: u64, db: Db) -> Result<Box<dyn Reply>, Infallible> {
+    match db::delete_dead_notification(db, id).await {
+        Ok(true) => Ok(Box::new(StatusCode::NO_CONTENT)),
+        Ok(false) => Ok(Box::new(StatusCode::NOT_FOUND)),
+        Err(e) => Ok(Box::new(internal_error(e))),
+    }
+}
+
+pub async fn preflight_response(running: Vec<NodeDescriptor>) -> Result<impl Reply, Infallible> {
+    Ok(warp::reply::json(&Versioned::new(
+        preflight::run(&running).await,
+    )))
+}
+
+// The status of the nodes including the addresses they are queried at.
+pub async fn nodes_response(
+    networks: Vec<Network>,
+    caches: Caches,
+) -> Result<impl Reply, Infallible> {
+    let caches_locked = caches.lock().await;
+    let nodes = networks
+        .iter()
+        .flat_map(|network| {
+            let node_data = caches_locked.get(&network.id).map(|c| &c.node_data);
+            network.nodes.iter().map(move |node| {
+                let data = node_data.and_then(|d| d.get(&node.info().id));
+                AdminNodeJson {
+                    network_id: network.id,
+                    node_id: node.info().id,
+                    name: node.info().name,
+                    rpc_url: node.rpc_url(),
+                    address: data.and_then(|d| d.address.clone()),
+                    reachable: data.map(|d| d.reachable).unwrap_or_default(),
+                    paused: data.map(|d| d.paused).unwrap_or_default(),
+                }
+            })
+        })
+        .collect();
+    Ok(warp::reply::json(&Versioned::new(AdminNodesJsonResponse {
+        nodes,
+    })))
+}
+
+pub async fn quarantine_response(quarantine: Quarantine) -> Result<impl Reply, Infallible> {
+    let quarantine_locked = quarantine.lock().await;
+    Ok(warp::reply::json(&Versioned::new(QuarantineJsonResponse {
+        nodes: quarantine_locked
+            .iter()
+            .map(|((network_id, node_id), headers)| QuarantinedNodeJson {
+                network_id: *network_id,
+                node_id: *node_id,
+                headers: headers
+                    .iter()
+                    .map(|h| QuarantinedHeaderJson {
+                        height: h.height,
+                        hash: h.block_hash().to_string(),
+                        prev_blockhash: h.header.prev_blockhash.to_string(),
+                    })
+                    .collect(),
+            })
+            .collect(),
+    })))
+}
+
+pub async fn log_levels_response(logger: &'static Logger) -> Result<impl Reply, Infallible> {
+    Ok(warp::reply::json(&Versioned::new(LogLevelsJsonResponse {
+        default: logger.base().to_string(),
+        overrides: logger
+            .overrides()
+            .iter()
+            .map(|(module, level)| LogLevelJson {
+                module: module.clone(),
+                level: level.to_string().to_lowercase(),
+            })
+            .collect(),
+    })))
+}
+
+#[derive(Deserialize)]
+pub struct LogLevelQuery {
+    // One of off, error, warn, info, debug, or trace.
+    pub level: String,
+}
+
+// Sets the log level of a module (e.g. fork_observer::node) until the next
+// restart.
+pub async fn log_level_set_response(
+    module: String,
+    query: LogLevelQuery,
+    logger: &'static Logger,
+) -> Result<Box<dyn Reply>, Infallible> {
+    match logging::parse_level(&query.level) {
+        Some(level) => {
+            info!("Setting the log level of '{}' to {}", module, level);
+            logger.set_level(&module, Some(level));
+            Ok(Box::new(StatusCode::NO_CONTENT))
+        }
+        None => Ok(Box::new(warp::reply::with_status(
+            "'level' must be one of off, error, warn, info, debug, or trace",
+            StatusCode::BAD_REQUEST,
+        ))),
+    }
+}
+
+// Resets the log level of a module to the one set via RUST_LOG.
+pub async fn log_level_reset_response(
+    module: String,
+    logger: &'static Logger,
+) -> Result<impl Reply, Infallible> {
+    info!("Resetting the log level of '{}'", module);
+    logger.set_level(&module, None);
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[derive(Deserialize)]
+pub struct ExportQuery {
+    pub from: u64,
+    // Defaults to now.
+    pub to: Option<u64>,
+}
+
+pub async fn export_response(
+    network_id: u32,
+    query: ExportQuery,
+    db: Db,
+    networks: Vec<Network>,
+) -> Result<Box<dyn Reply>, Infallible> {
+    let network = match networks.iter().find(|n| n.id == network_id) {
+        Some(network) => network,
+        None => return Ok(Box::new(StatusCode::NOT_FOUND)),
+    };
+    let now = now_timestamp();
+    let to = query.to.unwrap_or(now);
+    if query.from > to {
+        return Ok(Box::new(warp::reply::with_status(
+            "'from' must not be after 'to'",
+            StatusCode::BAD_REQUEST,
+        )));
+    }
+    let files = match export::create(db, network, query.from, to, vec![]).await {
+        Ok(files) => files,
+        Err(e) => return Ok(Box::new(internal_error(e))),
+    };
+    let name = export::bundle_name(network, query.from, to);
+    let archive = match export::tar(&name, &files, now) {
+        Ok(archive) => archive,
+        Err(e) => return Ok(Box::new(internal_error(e))),
+    };
+    match Response::builder()
+        .header("content-type", "application/x-tar")
+        .header(
+            "content-disposition",
+            format!("attachment; filename=\"{}.tar\"", name),
+        )
+        .body(archive)
+    {
+        Ok(response) => Ok(Box::new(response)),
+        Err(e) => Ok(Box::new(internal_error(e))),
+    }
+}
+
+pub async fn incidents_response(db: Db) -> Result<Box<dyn Reply>, Infallible> {
+    match db::load_incidents(db).await {
+        Ok(incidents) => Ok(Box::new(warp::reply::json(&Versioned::new(
+            IncidentsJsonResponse {
+                incidents: incidents.iter().map(IncidentJson::from).collect(),
+            },
+        )))),
+        Err(e) => Ok(Box::new(internal_error(e))),
+    }
+}
+
+#[derive(Deserialize)]
+pub struct IncidentQuery {
+    pub reason: Option<String>,
+}
+
+// Switches a network into incident mode.
+pub async fn incident_start_response(
+    network_id: u32,
+    query: IncidentQuery,
+    db: Db,
+    networks: Vec<Network>,
+    incidents: ActiveIncidents,
+    event_log: EventLog,
+) -> Result<Box<dyn Reply>, Infallible> {
+    let network = match networks.iter().find(|n| n.id == network_id) {
+        Some(network) => network,
+        None => return Ok(Box::new(StatusCode::NOT_FOUND)),
+    };
+    let reason = query
+        .reason
+        .unwrap_or_else(|| "no reason given".to_string());
+    match incident::start(network, reason, db, &incidents, &event_log).await {
+        Ok(Some(incident)) => {
+            info!(
+                "Switched network '{}' into incident mode (incident {})",
+                network.name, incident.id
+            );
+            Ok(Box::new(warp::reply::with_status(
+                warp::reply::json(&Versioned::new(IncidentJson::from(&incident))),
+                StatusCode::CREATED,
+            )))
+        }
+        Ok(None) => Ok(Box::new(warp::reply::with_status(
+            "The network is already in incident mode",
+            StatusCode::CONFLICT,
+        ))),
+        Err(e) => Ok(Box::new(internal_error(e))),
+    }
+}
+
+// Switches a network out of incident mode and attaches the collected
+// bundle to the incident.
+pub async fn incident_stop_response(
+    network_id: u32,
+    db: Db,
+    networks: Vec<Network>,
+    incidents: ActiveIncidents,
+    event_log: EventLog,
+) -> Result<Box<dyn Reply>, Infallible> {
+    let network = match networks.iter().find(|n| n.id == network_id) {
+        Some(network) => network,
+        None => return Ok(Box::new(StatusCode::NOT_FOUND)),
+    };
+    match incident::stop(network, db, &incidents, &event_log).await {
+        Ok(Some(incident)) => Ok(Box::new(warp::reply::json(&Versioned::new(
+            IncidentJson::from(&incident),
+        )))),
+        Ok(None) => Ok(Box::new(StatusCode::NOT_FOUND)),
+        Err(e) => Ok(Box::new(internal_error(e))),
+    }
+}
+
+// The bundle collected during an incident as a tar archive.
+pub async fn incident_bundle_response(id: u64, db: Db) -> Result<Box<dyn Reply>, Infallible> {
+    let bundle = match db::load_incident_bundle(db, id).await {
+        Ok(Some(bundle)) => bundle,
+        Ok(None) => return Ok(Box::new(StatusCode::NOT_FOUND)),
+        Err(e) => return Ok(Box::new(internal_error(e))),
+    };
+    match Response::builder()
+        .header("content-type", "application/x-tar")
+        .header(
+            "content-disposition",
+            format!("attachment; filename=\"fork-observer-incident-{}.tar\"", id),
+        )
+        .body(bundle)
+    {
+        Ok(response) => Ok(Box::new(response)),
+        Err(e) => Ok(Box::new(internal_error(e))),
+    }
+}
+
+pub async fn suppressed_tips_response(
+    suppressed: SuppressedTips,
+) -> Result<impl Reply, Infallible> {
+    let suppressed_locked = suppressed.lock().await;
+    Ok(warp::reply::json(&Versioned::new(
+        SuppressedTipsJsonResponse {
+            tips: suppressed_locked
+                .values()
+                .flat_map(|tips| tips.values().map(SuppressedTipJson::from))
+                .collect(),
+        },
+    )))
+}
+
+#[derive(Deserialize)]
+pub struct SuppressQuery {
+    pub reason: Option<String>,
+}
+
+// Drops a chain tip, e.g. a stuck headers-only tip, from the tips of the
+// network's nodes from their next query on.
+pub async fn tip_suppress_response(
+    network_id: u32,
+    hash: String,
+    query: SuppressQuery,
+    db: Db,
+    networks: Vec<Network>,
+    suppressed: SuppressedTips,
+) -> Result<Box<dyn Reply>, Infallible> {
+    if BlockHash::from_str(&hash).is_err() {
+        return Ok(Box::new(warp::reply::with_status(
+            "Invalid block hash",
+            StatusCode::BAD_REQUEST,
+        )));
+    }
+    if !networks.iter().any(|n| n.id == network_id) {
+        return Ok(Box::new(StatusCode::NOT_FOUND));
+    }
+    let reason = query
+        .reason
+        .unwrap_or_else(|| "no reason given".to_string());
+    match suppressed::suppress(&suppressed, db, network_id, hash, reason).await {
+        Ok(tip) => {
+            info!(
+                "Suppressing the chain tip {} on network {}: {}",
+                tip.hash, network_id, tip.reason
+            );
+            Ok(Box::new(StatusCode::NO_CONTENT))
+        }
+        Err(e) => Ok(Box::new(internal_error(e))),
+    }
+}
+
+pub async fn tip_unsuppress_response(
+    network_id: u32,
+    hash: String,
+    db: Db,
+    suppressed: SuppressedTips,
+) -> Result<Box<dyn Reply>, Infallible> {
+    match suppressed::unsuppress(&suppressed, db, network_id, &hash).await {
+        Ok(true) => Ok(Box::new(StatusCode::NO_CONTENT)),
+        Ok(false) => Ok(Box::new(StatusCode::NOT_FOUND)),
+        Err(e) => Ok(Box::new(internal_error(e))),
+    }
+}
+
+pub async fn expanded_forks_response(expanded: ExpandedForks) -> Result<impl Reply, Infallible> {
+    let expanded_locked = expanded.lock().await;
+    Ok(warp::reply::json(&Versioned::new(
+        ExpandedForksJsonResponse {
+            forks: expanded_locked
+                .iter()
+                .flat_map(|(network_id, forks)| {
+                    forks
+                        .iter()
+                        .map(move |(hash, (from, to))| ExpandedForkJson {
+                            network_id: *network_id,
+                            common: hash.to_string(),
+                            from: *from,
+                            to: *to,
+                        })
+                })
+                .collect(),
+        },
+    )))
+}
+
+// Serves an old fork, identified by its last common block, via the API
+// again.
+pub async fn fork_expand_response(
+    network_id: u32,
+    hash: String,
+    networks: Vec<Network>,
+    trees: HashMap<u32, Tree>,
+    expanded: ExpandedForks,
+    refresh_tx: UnboundedSender<u32>,
+) -> Result<Box<dyn Reply>, Infallible> {
+    let hash = match BlockHash::from_str(&hash) {
+        Ok(hash) => hash,
+        Err(_) => {
+            return Ok(Box::new(warp::reply::with_status(
+                "Invalid block hash",
+                StatusCode::BAD_REQUEST,
+            )))
+        }
+    };
+    let tree = match networks
+        .iter()
+        .find(|n| n.id == network_id)
+        .and_then(|n| trees.get(&n.header_store))
+    {
+        Some(tree) => tree,
+        None => return Ok(Box::new(StatusCode::NOT_FOUND)),
+    };
+    let heights = match headertree::fork_heights(tree, &hash).await {
+        Some(heights) => heights,
+        None => {
+            return Ok(Box::new(warp::reply::with_status(
+                "The block isn't the last common block of a fork",
+                StatusCode::NOT_FOUND,
+            )))
+        }
+    };
+    info!(
+        "Expanding the fork at block {} (heights {} to {}) on network {}",
+        hash, heights.0, heights.1, network_id
+    );
+    expanded
+        .lock()
+        .await
+        .entry(network_id)
+        .or_default()
+        .insert(hash, heights);
+    if let Err(e) = refresh_tx.send(network_id) {
+        error!(
+            "Could not request a refresh of network {}: {}",
+            network_id, e
+        );
+    }
+    Ok(Box::new(StatusCode::NO_CONTENT))
+}
+
+pub async fn fork_collapse_response(
+    network_id: u32,
+    hash: String,
+    expanded: ExpandedForks,
+    refresh_tx: UnboundedSender<u32>,
+) -> Result<Box<dyn Reply>, Infallible> {
+    let removed = match BlockHash::from_str(&hash) {
+        Ok(hash) => expanded
+            .lock()
+            .await
+            .get_mut(&network_id)
+            .and_then(|forks| forks.remove(&hash))
+            .is_some(),
+        Err(_) => false,
+    };
+    if !removed {
+        return Ok(Box::new(StatusCode::NOT_FOUND));
+    }
+    if let Err(e) = refresh_tx.send(network_id) {
+        error!(
+            "Could not request a refresh of network {}: {}",
+            network_id, e
+        );
+    }
+    Ok(Box::new(StatusCode::NO_CONTENT))
+}
+
+// Pauses or resumes the polling of a node. The data.json shows the node as
+// paused.
+pub async fn node_pause_response(
+    network_id: u32,
+    node_id: u32,
+    pause: bool,
+    networks: Vec<Network>,
+    caches: Caches,
+    paused: PausedNodes,
+) -> Result<Box<dyn Reply>, Infallible> {
+    let node = networks
+        .iter()
+        .find(|n| n.id == network_id)
+        .and_then(|n| n.nodes.iter().find(|node| node.info().id == node_id));
+    let node = match node {
+        Some(node) => node,
+        None => return Ok(Box::new(StatusCode::NOT_FOUND)),
+    };
+    {
+        let mut paused_locked = paused.lock().await;
+        if pause {
+            paused_locked.insert((network_id, node_id));
+        } else if !paused_locked.remove(&(network_id, node_id)) {
+            return Ok(Box::new(StatusCode::NOT_FOUND));
+        }
+    }
+    info!(
+        "{} the polling of {} on network {}",
+        if pause { "Pausing" } else { "Resuming" },
+        node.info(),
+        network_id
+    );
+    if let Some(data) = caches
+        .lock()
+        .await
+        .get_mut(&network_id)
+        .and_then(|cache| cache.node_data.get_mut(&node_id))
+    {
+        data.paused = pause;
+    }
+    Ok(Box::new(StatusCode::NO_CONTENT))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bearer_token_matches() {
+        assert!(bearer_token_matches(Some("Bearer secret"), "secret"));
+        assert!(!bearer_token_matches(Some("Bearer secreT"), "secret"));
+        assert!(!bearer_token_matches(Some("Bearer secret2"), "secret"));
+        assert!(!bearer_token_matches(Some("secret"), "secret"));
+        assert!(!bearer_token_matches(None, "secret"));
+    }
+}
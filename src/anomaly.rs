@@ -0,0 +1,230 @@
+use bitcoincore_rpc::bitcoin::BlockHash;
+use tokio::sync::Mutex;
+
+use crate::config::{BlockIntervalAnomalies, ChainParams};
+use crate::domain::{EventKind, EventSeverity, HeaderInfo, Tree};
+use crate::events::EventLog;
+use crate::headertree;
+
+// Clusters are detected over this many consecutive blocks.
+const CLUSTER_BLOCKS: u64 = 6;
+// At most this many new blocks are checked at once, e.g. when a node
+// catches up after being offline.
+const MAX_CHECKED_BLOCKS: u64 = 144;
+
+#[derive(Debug, PartialEq)]
+enum Anomaly {
+    // CLUSTER_BLOCKS blocks up to height were found within seconds.
+    Cluster {
+        height: u64,
+        seconds: u64,
+        probability: f64,
+    },
+    // No block was found for seconds before the block at height.
+    Drought {
+        height: u64,
+        seconds: u64,
+        probability: f64,
+    },
+}
+
+// Blocks arrive as a Poisson process with the target spacing as mean
+// interval. The probability of an interval of at least the given seconds.
+fn drought_probability(seconds: u64, target_spacing: u64) -> f64 {
+    (-(seconds as f64) / target_spacing as f64).exp()
+}
+
+// The probability that the given number of consecutive intervals sum up to
+// at most the given seconds (the CDF of the Erlang distribution).
+fn cluster_probability(intervals: u64, seconds: u64, target_spacing: u64) -> f64 {
+    let x = seconds as f64 / target_spacing as f64;
+    let mut term = (-x).exp();
+    let mut sum = 0.0;
+    for n in 0..intervals {
+        if n > 0 {
+            term *= x / n as f64;
+        }
+        sum += term;
+    }
+    (1.0 - sum).max(0.0)
+}
+
+// The anomalies of the headers above old_height. The headers are ordered by
+// height and include the CLUSTER_BLOCKS headers below old_height. Only the
+// start of a cluster is reported, not every block of it.
+fn anomalies(
+    headers: &[HeaderInfo],
+    old_height: u64,
+    target_spacing: u64,
+    max_probability: f64,
+) -> Vec<Anomaly> {
+    let intervals = CLUSTER_BLOCKS - 1;
+    // Block times aren't monotonic, blocks can have an earlier time than
+    // their parent.
+    let span = |from: &HeaderInfo, to: &HeaderInfo| {
+        (to.header.time as u64).saturating_sub(from.header.time as u64)
+    };
+    let cluster_at = |i: usize| -> Option<(u64, f64)> {
+        let first = headers.get(i.checked_sub(intervals as usize)?)?;
+        let seconds = span(first, &headers[i]);
+        let probability = cluster_probability(intervals, seconds, target_spacing);
+        Some((seconds, probability)).filter(|(_, p)| *p < max_probability)
+    };
+
+    let mut anomalies: Vec<Anomaly> = vec![];
+    for (i, header) in headers.iter().enumerate() {
+        if header.height <= old_height || i == 0 {
+            continue;
+        }
+        let seconds = span(&headers[i - 1], header);
+        let probability = drought_probability(seconds, target_spacing);
+        if probability < max_probability {
+            anomalies.push(Anomaly::Drought {
+                height: header.height,
+                seconds,
+                probability,
+            });
+        }
+        if let Some((seconds, probability)) = cluster_at(i) {
+            if cluster_at(i - 1).is_none() {
+                anomalies.push(Anomaly::Cluster {
+                    height: header.height,
+                    seconds,
+                    probability,
+                });
+            }
+        }
+    }
+    anomalies
+}
+
+// Emits events for unusual block intervals on the active chain since the
+// last checked active tip height. The first call only records the height,
+// like the milestone checks.
+#[allow(clippy::too_many_arguments)]
+pub async fn check_block_intervals(
+    network_id: u32,
+    network_name: &str,
+    params: &ChainParams,
+    config: &BlockIntervalAnomalies,
+    tree: &Tree,
+    active_tip: &BlockHash,
+    active_height: u64,
+    last_height: &Mutex<Option<u64>>,
+    event_log: &EventLog,
+) {
+    let old_height = {
+        let mut last_height_locked = last_height.lock().await;
+        match *last_height_locked {
+            Some(old_height) if old_height >= active_height => return,
+            Some(old_height) => {
+                *last_height_locked = Some(active_height);
+                old_height.max(active_height.saturating_sub(MAX_CHECKED_BLOCKS))
+            }
+            None => {
+                *last_height_locked = Some(active_height);
+                return;
+            }
+        }
+    };
+
+    let headers =
+        headertree::active_chain(tree, active_tip, old_height.saturating_sub(CLUSTER_BLOCKS)).await;
+    for anomaly in anomalies(
+        &headers,
+        old_height,
+        params.target_spacing,
+        config.max_probability,
+    ) {
+        let (title, description) = match anomaly {
+            Anomaly::Cluster {
+                height,
+                seconds,
+                probability,
+            } => (
+                format!(
+                    "{} blocks within {} seconds on {}",
+                    CLUSTER_BLOCKS, seconds, network_name
+                ),
+                format!(
+                    "The {} blocks up to height {} on {} were found within {} seconds. With a target spacing of {} seconds, this happens with a probability of {:.2e}.",
+                    CLUSTER_BLOCKS, height, network_name, seconds, params.target_spacing, probability
+                ),
+            ),
+            Anomaly::Drought {
+                height,
+                seconds,
+                probability,
+            } => (
+                format!(
+                    "No block for {} minutes on {}",
+                    seconds / 60,
+                    network_name
+                ),
+                format!(
+                    "Block {} on {} was found {} minutes after its parent. With a target spacing of {} seconds, this happens with a probability of {:.2e}.",
+                    height,
+                    network_name,
+                    seconds / 60,
+                    params.target_spacing,
+                    probability
+                ),
+            ),
+        };
+        event_log
+            .emit(
+                network_id,
+                EventKind::BlockIntervalAnomaly,
+                EventSeverity::Info,
+                title,
+                description,
+            )
+            .await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::at_height;
+
+    #[test]
+    fn test_anomalies() {
+        assert!((drought_probability(600, 600) - (-1.0f64).exp()).abs() < 1e-9);
+        assert!((cluster_probability(1, 600, 600) - (1.0 - (-1.0f64).exp())).abs() < 1e-9);
+
+        // regular blocks
+        let mut headers: Vec<HeaderInfo> =
+            (0..10).map(|h| at_height(h, h as u32 * 600, 0)).collect();
+        assert_eq!(anomalies(&headers, 5, 600, 0.001), vec![]);
+
+        // a drought of five hours before block 10
+        headers.push(at_height(10, 9 * 600 + 5 * 3600, 0));
+        let found = anomalies(&headers, 9, 600, 0.001);
+        assert_eq!(found.len(), 1);
+        assert!(matches!(
+            found[0],
+            Anomaly::Drought {
+                height: 10,
+                seconds: 18000,
+                ..
+            }
+        ));
+
+        // seven blocks within seconds are reported once
+        let start = headers[10].header.time;
+        for h in 11..17 {
+            headers.push(at_height(h, start + (h as u32 - 10) * 5, 0));
+        }
+        let found = anomalies(&headers, 10, 600, 0.001);
+        assert_eq!(found.len(), 1);
+        assert!(matches!(
+            found[0],
+            Anomaly::Cluster {
+                height: 15,
+                seconds: 25,
+                ..
+            }
+        ));
+    }
+}
@@ -0,0 +1,61 @@
+use crate::domain::{ChainTip, ChainTipStatus, Tree};
+
+// The height a node's headers are fetched from with backfill_blocks: a tree
+// without headers starts backfill_blocks below the active tip instead of at
+// the min_fork_height. A tree with headers continues from its highest
+// header, so the headers missed during a downtime are filled in.
+fn start_height(
+    min_fork_height: u64,
+    backfill_blocks: u64,
+    active_height: u64,
+    max_tree_height: Option<u64>,
+) -> u64 {
+    let start = active_height.saturating_sub(backfill_blocks);
+    let start = match max_tree_height {
+        Some(max_tree_height) => start.min(max_tree_height),
+        None => start,
+    };
+    start.max(min_fork_height)
+}
+
+// The start height for the node's first query. None without an active tip.
+pub async fn first_start_height(
+    min_fork_height: u64,
+    backfill_blocks: u64,
+    tree: &Tree,
+    tips: &[ChainTip],
+) -> Option<u64> {
+    let active_height = tips
+        .iter()
+        .find(|tip| tip.status == ChainTipStatus::Active)?
+        .height;
+    let max_tree_height = tree
+        .lock()
+        .await
+        .0
+        .node_weights()
+        .map(|header_info| header_info.height)
+        .max();
+    Some(start_height(
+        min_fork_height,
+        backfill_blocks,
+        active_height,
+        max_tree_height,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_start_height() {
+        // a new tree
+        assert_eq!(start_height(0, 1000, 850_000, None), 849_000);
+        assert_eq!(start_height(849_500, 1000, 850_000, None), 849_500);
+        assert_eq!(start_height(0, 1000, 500, None), 0);
+        // after a downtime of 2000 blocks
+        assert_eq!(start_height(0, 1000, 850_000, Some(848_000)), 848_000);
+        assert_eq!(start_height(0, 1000, 850_000, Some(849_990)), 849_000);
+    }
+}
@@ -0,0 +1,253 @@
+// Benchmarks of the hot paths, run with
+//
+//   cargo run --release --features bench -- bench [--save-baseline <name>] [--baseline <name>] [filter]
+//
+// The fixtures are generated to resemble mainnet: a year of blocks with
+// mainnet versions, times and bits and a stale block every STALE_EVERY
+// blocks.
+
+use std::collections::{BTreeSet, HashMap};
+use std::panic;
+use std::sync::Arc;
+use std::thread;
+
+use bitcoincore_rpc::bitcoin;
+use bitcoincore_rpc::bitcoin::block::{Header, Version};
+use bitcoincore_rpc::bitcoin::hashes::Hash;
+use bitcoincore_rpc::bitcoin::{BlockHash, CompactTarget, TxMerkleNode};
+use criterion::{BatchSize, Criterion};
+use petgraph::graph::DiGraph;
+use tokio::runtime::Runtime;
+use tokio::sync::Mutex;
+
+use crate::config::ChainParams;
+use crate::domain::{HeaderInfo, HeaderSource, Tree, TreeInfo};
+use crate::error::MainError;
+use crate::headertree;
+use crate::node;
+use crate::stale;
+use crate::types::{DataJsonResponse, Versioned};
+
+const START_HEIGHT: u64 = 800_000;
+const START_TIME: u32 = 1_690_168_629;
+// A year of blocks.
+const CHAIN_LENGTH: u64 = 52_560;
+const STALE_EVERY: u64 = 1_000;
+const MAX_INTERESTING_HEIGHTS: usize = 100;
+const MAX_FORKS: usize = 50;
+// The step size of the REST header requests.
+const REST_HEADERS: usize = 2_000;
+
+struct BenchArgs {
+    save_baseline: Option<String>,
+    baseline: Option<String>,
+    filter: Option<String>,
+}
+
+fn parse_args(args: &[String]) -> Result<BenchArgs, MainError> {
+    let mut bench_args = BenchArgs {
+        save_baseline: None,
+        baseline: None,
+        filter: None,
+    };
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        let mut value = || {
+            iter.next()
+                .cloned()
+                .ok_or_else(|| MainError::InvalidArguments(format!("missing value for '{}'", arg)))
+        };
+        match arg.as_str() {
+            "--save-baseline" => bench_args.save_baseline = Some(value()?),
+            "--baseline" => bench_args.baseline = Some(value()?),
+            _ if arg.starts_with("--") => {
+                return Err(MainError::InvalidArguments(format!(
+                    "unknown argument '{}'",
+                    arg
+                )))
+            }
+            _ => bench_args.filter = Some(arg.clone()),
+        }
+    }
+    Ok(bench_args)
+}
+
+fn header_info(height: u64, prev_blockhash: BlockHash, nonce: u32) -> HeaderInfo {
+    HeaderInfo {
+        height,
+        header: Header {
+            version: Version::from_consensus(0x2000_0000),
+            prev_blockhash,
+            merkle_root: TxMerkleNode::from_byte_array([(height % 256) as u8; 32]),
+            time: START_TIME + ((height - START_HEIGHT) * 600) as u32,
+            bits: CompactTarget::from_consensus(0x1705_3894),
+            nonce,
+        },
+        miner: String::new(),
+        own: false,
+        source: HeaderSource::Rpc,
+        elements: None,
+    }
+}
+
+// The headers of the fixture chain, parents before their children.
+fn fixture_headers() -> Vec<HeaderInfo> {
+    let mut headers: Vec<HeaderInfo> = Vec::with_capacity(CHAIN_LENGTH as usize * 2);
+    let mut prev = BlockHash::all_zeros();
+    for height in START_HEIGHT..START_HEIGHT + CHAIN_LENGTH {
+        let active = header_info(height, prev, 0);
+        if height % STALE_EVERY == 0 {
+            headers.push(header_info(height, prev, 1));
+        }
+        prev = active.block_hash();
+        headers.push(active);
+    }
+    headers
+}
+
+fn empty_tree() -> Tree {
+    Arc::new(Mutex::new((DiGraph::new(), HashMap::new(), HashMap::new())))
+}
+
+fn bench_insertion(c: &mut Criterion, rt: &Runtime, headers: &[HeaderInfo], full: &TreeInfo) {
+    let mut group = c.benchmark_group("insert");
+    group.sample_size(10);
+    group.bench_function("year", |b| {
+        b.to_async(rt).iter_batched(
+            empty_tree,
+            // The tree is returned to drop it outside of the measurement.
+            |tree| async move {
+                headertree::insert_new_headers_into_tree(&tree, headers).await;
+                tree
+            },
+            BatchSize::PerIteration,
+        )
+    });
+    // A new block on top of a year of blocks, as on each query.
+    let tip = headers.last().unwrap();
+    let next = header_info(tip.height + 1, tip.block_hash(), 0);
+    group.bench_function("next block", |b| {
+        b.to_async(rt).iter_batched(
+            || Arc::new(Mutex::new(full.clone())),
+            |tree| {
+                let next = next.clone();
+                async move {
+                    headertree::insert_new_headers_into_tree(&tree, std::slice::from_ref(&next))
+                        .await;
+                    tree
+                }
+            },
+            BatchSize::PerIteration,
+        )
+    });
+    group.finish();
+}
+
+fn bench_forks(c: &mut Criterion, rt: &Runtime, tree: &Tree) {
+    c.bench_function("forks/recent", |b| {
+        b.to_async(rt)
+            .iter(|| headertree::recent_forks(tree, MAX_FORKS))
+    });
+    let tip = rt.block_on(async {
+        let tree_locked = tree.lock().await;
+        tree_locked
+            .0
+            .node_weights()
+            .max_by_key(|h| h.height)
+            .map(|h| h.block_hash())
+            .unwrap()
+    });
+    c.bench_function("forks/stale blocks", |b| {
+        b.to_async(rt)
+            .iter(|| headertree::stale_blocks(tree, &tip, stale::STALE_BLOCK_DEPTH))
+    });
+}
+
+fn bench_snapshot(c: &mut Criterion, rt: &Runtime, tree: &Tree) {
+    let params = ChainParams::default();
+    let strip = || {
+        headertree::strip_tree(
+            tree,
+            MAX_INTERESTING_HEIGHTS,
+            BTreeSet::new(),
+            BTreeSet::new(),
+            &params,
+        )
+    };
+    c.bench_function("snapshot/strip tree", |b| b.to_async(rt).iter(strip));
+    let header_infos = rt.block_on(strip());
+    c.bench_function("snapshot/serialize", |b| {
+        b.iter(|| {
+            serde_json::to_vec(&Versioned::new(DataJsonResponse {
+                network_id: Some(0),
+                header_infos: header_infos.clone(),
+                nodes: vec![],
+            }))
+            .unwrap()
+        })
+    });
+}
+
+fn bench_rest(c: &mut Criterion, headers: &[HeaderInfo]) {
+    let body: Vec<u8> = headers
+        .iter()
+        .take(REST_HEADERS)
+        .flat_map(|h| bitcoin::consensus::serialize(&h.header))
+        .collect();
+    c.bench_function("rest/parse headers", |b| {
+        b.iter(|| node::parse_rest_headers(&body).unwrap())
+    });
+}
+
+fn run_benchmarks(args: BenchArgs) {
+    let rt = Runtime::new().expect("could not start the benchmark runtime");
+    let mut c = Criterion::default();
+    if let Some(filter) = args.filter {
+        c = c.with_filter(filter);
+    }
+    if let Some(baseline) = args.save_baseline {
+        c = c.save_baseline(baseline);
+    }
+    if let Some(baseline) = args.baseline {
+        c = c.retain_baseline(baseline, true);
+    }
+
+    let headers = fixture_headers();
+    let tree = empty_tree();
+    rt.block_on(headertree::insert_new_headers_into_tree(&tree, &headers));
+    let full = rt.block_on(tree.lock()).clone();
+
+    bench_insertion(&mut c, &rt, &headers, &full);
+    bench_forks(&mut c, &rt, &tree);
+    bench_snapshot(&mut c, &rt, &tree);
+    bench_rest(&mut c, &headers);
+    c.final_summary();
+}
+
+// The benchmarks run on their own runtime, which can't be started from
+// within the runtime of main().
+pub fn run(args: &[String]) -> Result<(), MainError> {
+    let args = parse_args(args)?;
+    if let Err(e) = thread::spawn(move || run_benchmarks(args)).join() {
+        panic::resume_unwind(e);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fixture_headers() {
+        let headers = fixture_headers();
+        let stale = (CHAIN_LENGTH / STALE_EVERY + 1) as usize;
+        assert_eq!(headers.len(), CHAIN_LENGTH as usize + stale);
+        // every header but the first has its parent before it
+        let rt = Runtime::new().unwrap();
+        let tree = empty_tree();
+        rt.block_on(headertree::insert_new_headers_into_tree(&tree, &headers));
+        let forks = rt.block_on(headertree::recent_forks(&tree, usize::MAX));
+        assert_eq!(forks.len(), stale - 1);
+    }
+}
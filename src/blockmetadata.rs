@@ -0,0 +1,227 @@
+use std::collections::HashMap;
+use std::convert::Infallible;
+
+use bitcoincore_rpc::bitcoin::Block;
+use log::{debug, error, info, warn};
+use tokio::time::Duration;
+use warp::http::StatusCode;
+use warp::Reply;
+
+use crate::chainparams;
+use crate::config::{ChainParams, Network, DEFAULT_BLOCK_METADATA_DEPTH};
+use crate::db;
+use crate::domain::{BlockStats, Fork, HeaderInfo, Tree};
+use crate::headertree;
+use crate::paused::{self, PausedNodes};
+use crate::rss::response_unknown_network;
+use crate::types::{
+    BlockMetadataJson, BlockMetadataJsonResponse, Caches, Db, NetworkJson, Versioned,
+};
+
+pub const BLOCK_METADATA_INTERVAL: Duration = Duration::from_secs(10 * 60);
+
+// The blocks around the forks with the height of their last common block:
+// the blocks of each branch up to depth blocks above the last common block.
+pub async fn fork_blocks(tree: &Tree, forks: &[Fork], depth: u64) -> Vec<(u64, HeaderInfo)> {
+    let mut blocks: Vec<(u64, HeaderInfo)> = vec![];
+    for fork in forks.iter() {
+        for child in fork.children.iter() {
+            blocks.extend(
+                headertree::branch_headers(tree, &child.block_hash(), depth - 1)
+                    .await
+                    .into_iter()
+                    .map(|header_info| (fork.common.height, header_info)),
+            );
+        }
+    }
+    blocks.sort_by_key(|(_, header_info)| header_info.height);
+    blocks
+}
+
+// The fees are what the coinbase claims beyond the block subsidy. A miner
+// claiming less than allowed lowers them.
+pub fn stats(block: &Block, height: u64, params: &ChainParams) -> BlockStats {
+    let coinbase_value: u64 = block
+        .txdata
+        .first()
+        .map(|coinbase| coinbase.output.iter().map(|out| out.value.to_sat()).sum())
+        .unwrap_or_default();
+    BlockStats {
+        hash: block.block_hash().to_string(),
+        size: block.total_size() as u64,
+        weight: block.weight().to_wu(),
+        tx_count: block.txdata.len() as u64,
+        fees: coinbase_value.saturating_sub(chainparams::subsidy(params, height)),
+    }
+}
+
+// Fetches the blocks around the forks of the network without recorded
+// metadata from its nodes and records it. Stale blocks are only known to
+// some nodes, so the nodes are asked in turn. Returns the number of
+// recorded blocks.
+pub async fn record(
+    network: &Network,
+    depth: u64,
+    tree: &Tree,
+    forks: &[Fork],
+    db: Db,
+    paused_nodes: &PausedNodes,
+) -> usize {
+    let blocks = fork_blocks(tree, forks, depth).await;
+    let hashes: Vec<String> = blocks
+        .iter()
+        .map(|(_, header_info)| header_info.block_hash().to_string())
+        .collect();
+    let known = match db::load_block_stats(db.clone(), network.header_store, &hashes).await {
+        Ok(known) => known,
+        Err(e) => {
+            error!(
+                "Could not load the block metadata of network '{}': {}",
+                network.name, e
+            );
+            return 0;
+        }
+    };
+
+    let mut recorded = 0;
+    for (_, header_info) in blocks.iter() {
+        let hash = header_info.block_hash();
+        if known.contains_key(&hash.to_string()) {
+            continue;
+        }
+        for node in network.nodes.iter() {
+            if paused::is_paused(paused_nodes, network.id, node.info().id).await {
+                continue;
+            }
+            let block = match node.block(&hash).await {
+                Ok(Some(block)) if block.block_hash() == hash => block,
+                Ok(Some(_)) => {
+                    warn!(
+                        "Node {} returned another block than {} on network '{}'",
+                        node.info(),
+                        hash,
+                        network.name
+                    );
+                    continue;
+                }
+                // The node doesn't serve full blocks.
+                Ok(None) => continue,
+                Err(e) => {
+                    debug!(
+                        "Could not fetch block {} from node {} on network '{}': {}",
+                        hash,
+                        node.info(),
+                        network.name,
+                        e
+                    );
+                    continue;
+                }
+            };
+            let block_stats = stats(&block, header_info.height, &network.chain_params);
+            match db::write_block_stats(db.clone(), network.header_store, &block_stats).await {
+                Ok(_) => recorded += 1,
+                Err(e) => error!(
+                    "Could not write the metadata of block {} to database: {}",
+                    hash, e
+                ),
+            }
+            break;
+        }
+    }
+    if recorded > 0 {
+        info!(
+            "Recorded the metadata of {} blocks around forks on network '{}'",
+            recorded, network.name
+        );
+    }
+    recorded
+}
+
+pub async fn block_metadata_response(
+    network_id: u32,
+    caches: Caches,
+    db: Db,
+    networks: Vec<Network>,
+    trees: HashMap<u32, Tree>,
+    network_infos: Vec<NetworkJson>,
+) -> Result<Box<dyn Reply>, Infallible> {
+    let forks = {
+        let caches_locked = caches.lock().await;
+        match caches_locked.get(&network_id) {
+            Some(cache) => cache.forks.clone(),
+            None => return Ok(Box::new(response_unknown_network(network_infos))),
+        }
+    };
+    let (network, tree) = match networks
+        .iter()
+        .find(|n| n.id == network_id)
+        .and_then(|n| trees.get(&n.header_store).map(|tree| (n, tree)))
+    {
+        Some(network_tree) => network_tree,
+        None => return Ok(Box::new(response_unknown_network(network_infos))),
+    };
+    let depth = network
+        .block_metadata
+        .as_ref()
+        .map_or(DEFAULT_BLOCK_METADATA_DEPTH, |block_metadata| {
+            block_metadata.depth
+        });
+    let blocks = fork_blocks(tree, &forks, depth).await;
+    let hashes: Vec<String> = blocks
+        .iter()
+        .map(|(_, header_info)| header_info.block_hash().to_string())
+        .collect();
+    match db::load_block_stats(db, network.header_store, &hashes).await {
+        Ok(block_stats) => Ok(Box::new(warp::reply::json(&Versioned::new(
+            BlockMetadataJsonResponse {
+                depth,
+                blocks: blocks
+                    .iter()
+                    .map(|(fork_height, header_info)| {
+                        let stats = block_stats.get(&header_info.block_hash().to_string());
+                        BlockMetadataJson {
+                            hash: header_info.block_hash().to_string(),
+                            height: header_info.height,
+                            fork_height: *fork_height,
+                            miner: header_info.miner.clone(),
+                            size: stats.map(|s| s.size),
+                            weight: stats.map(|s| s.weight),
+                            tx_count: stats.map(|s| s.tx_count),
+                            fees: stats.map(|s| s.fees),
+                        }
+                    })
+                    .collect(),
+            },
+        )))),
+        Err(e) => {
+            error!(
+                "Could not load the block metadata of network {}: {}",
+                network_id, e
+            );
+            Ok(Box::new(StatusCode::INTERNAL_SERVER_ERROR))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoincore_rpc::bitcoin::blockdata::constants::genesis_block;
+    use bitcoincore_rpc::bitcoin::Amount;
+
+    #[test]
+    fn test_stats() {
+        let params = ChainParams::default();
+        let mut block = genesis_block(bitcoincore_rpc::bitcoin::Network::Bitcoin);
+        let block_stats = stats(&block, 0, &params);
+        assert_eq!(block_stats.hash, block.block_hash().to_string());
+        assert_eq!(block_stats.size, 285);
+        assert_eq!(block_stats.weight, 1140);
+        assert_eq!(block_stats.tx_count, 1);
+        assert_eq!(block_stats.fees, 0);
+
+        // a coinbase claiming 0.1 BTC of fees after the first halving
+        block.txdata[0].output[0].value = Amount::from_sat(25 * 100_000_000 + 10_000_000);
+        assert_eq!(stats(&block, 210_000, &params).fees, 10_000_000);
+    }
+}
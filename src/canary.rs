@@ -0,0 +1,209 @@
+use log::debug;
+
+use crate::config::Canary;
+use crate::domain::{EventKind, HeaderInfo, Tree};
+use crate::events::EventLog;
+use crate::expr::{Type, Value};
+
+// The variables available to canary conditions. Each new header is
+// evaluated together with its parent.
+pub fn variables() -> Vec<(&'static str, Type)> {
+    vec![
+        ("height", Type::Int),
+        ("version", Type::Int),
+        ("time", Type::Int),
+        ("bits", Type::Int),
+        ("nonce", Type::Int),
+        ("prev_version", Type::Int),
+        ("prev_time", Type::Int),
+        ("prev_bits", Type::Int),
+        // seconds since the parent, negative if the block has an earlier
+        // time than its parent
+        ("interval", Type::Int),
+        // change of the difficulty relative to the parent in percent,
+        // rounded towards zero
+        ("difficulty_change_percent", Type::Int),
+    ]
+}
+
+// The target encoded in the compact bits. Only the ratio of two targets is
+// needed, so a float is precise enough.
+fn target(bits: u32) -> f64 {
+    let exponent = (bits >> 24) as i32;
+    let mantissa = (bits & 0x007f_ffff) as f64;
+    mantissa * 256f64.powi(exponent - 3)
+}
+
+// The difficulty is inversely proportional to the target.
+fn difficulty_change_percent(prev_bits: u32, bits: u32) -> i64 {
+    let (prev_target, target) = (target(prev_bits), target(bits));
+    if prev_target == 0.0 || target == 0.0 {
+        return 0;
+    }
+    ((prev_target / target - 1.0) * 100.0) as i64
+}
+
+fn values(header_info: &HeaderInfo, parent: &HeaderInfo) -> Vec<(&'static str, Value)> {
+    let header = &header_info.header;
+    let prev = &parent.header;
+    vec![
+        ("height", Value::Int(header_info.height as i64)),
+        ("version", Value::Int(header.version.to_consensus() as i64)),
+        ("time", Value::Int(header.time as i64)),
+        ("bits", Value::Int(header.bits.to_consensus() as i64)),
+        ("nonce", Value::Int(header.nonce as i64)),
+        (
+            "prev_version",
+            Value::Int(prev.version.to_consensus() as i64),
+        ),
+        ("prev_time", Value::Int(prev.time as i64)),
+        ("prev_bits", Value::Int(prev.bits.to_consensus() as i64)),
+        (
+            "interval",
+            Value::Int(header.time as i64 - prev.time as i64),
+        ),
+        (
+            "difficulty_change_percent",
+            Value::Int(difficulty_change_percent(
+                prev.bits.to_consensus(),
+                header.bits.to_consensus(),
+            )),
+        ),
+    ]
+}
+
+// The canaries whose condition holds for the header. Conditions that fail
+// to evaluate, e.g. due to a division by zero, don't trigger.
+fn triggered<'a>(
+    canaries: &'a [Canary],
+    header_info: &HeaderInfo,
+    parent: &HeaderInfo,
+) -> Vec<&'a Canary> {
+    let values = values(header_info, parent);
+    canaries
+        .iter()
+        .filter(|canary| match canary.condition.eval(&values) {
+            Ok(value) => value == Value::Bool(true),
+            Err(e) => {
+                debug!("Could not evaluate canary '{}': {}", canary.name, e);
+                false
+            }
+        })
+        .collect()
+}
+
+// Emits an event for each canary triggered by a header newly inserted into
+// the tree. Headers whose parent isn't in the tree, e.g. the root, aren't
+// checked.
+pub async fn check_headers(
+    network_id: u32,
+    network_name: &str,
+    canaries: &[Canary],
+    tree: &Tree,
+    new_headers: &[HeaderInfo],
+    event_log: &EventLog,
+) {
+    let with_parents: Vec<(HeaderInfo, HeaderInfo)> = {
+        let tree_locked = tree.lock().await;
+        new_headers
+            .iter()
+            .filter_map(|header_info| {
+                let parent_idx = tree_locked.1.get(&header_info.header.prev_blockhash)?;
+                Some((header_info.clone(), tree_locked.0[*parent_idx].clone()))
+            })
+            .collect()
+    };
+
+    for (header_info, parent) in with_parents.iter() {
+        for canary in triggered(canaries, header_info, parent) {
+            event_log
+                .emit(
+                    network_id,
+                    EventKind::Canary,
+                    canary.severity.clone(),
+                    format!(
+                        "Canary '{}' triggered at height {} on {}",
+                        canary.name, header_info.height, network_name
+                    ),
+                    format!(
+                        "The block {} at height {} on {} (version {:#010x}, time {}, bits {:#010x}) meets the condition of canary '{}'.",
+                        header_info.block_hash(),
+                        header_info.height,
+                        network_name,
+                        header_info.header.version.to_consensus(),
+                        header_info.header.time,
+                        header_info.header.bits.to_consensus(),
+                        canary.name
+                    ),
+                )
+                .await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::EventSeverity;
+    use crate::expr::Expr;
+    use crate::testutil::{self, header_info};
+    use bitcoincore_rpc::bitcoin::block::Version;
+    use bitcoincore_rpc::bitcoin::hashes::Hash;
+    use bitcoincore_rpc::bitcoin::{BlockHash, CompactTarget};
+
+    fn header(height: u64, version: i32, time: u32, bits: u32) -> HeaderInfo {
+        let mut header = testutil::header(BlockHash::all_zeros(), time, 0);
+        header.version = Version::from_consensus(version);
+        header.bits = CompactTarget::from_consensus(bits);
+        header_info(height, header)
+    }
+
+    fn canary(name: &str, condition: &str) -> Canary {
+        Canary {
+            name: name.to_string(),
+            condition: Expr::parse(condition, &variables()).unwrap().0,
+            severity: EventSeverity::Warning,
+        }
+    }
+
+    #[test]
+    fn test_difficulty_change_percent() {
+        assert_eq!(difficulty_change_percent(0x1d00ffff, 0x1d00ffff), 0);
+        // half the target, double the difficulty
+        assert_eq!(difficulty_change_percent(0x1d00ffff, 0x1c7fff80), 100);
+        assert_eq!(difficulty_change_percent(0x1c7fff80, 0x1d00ffff), -50);
+        assert_eq!(difficulty_change_percent(0, 0x1d00ffff), 0);
+    }
+
+    #[test]
+    fn test_triggered() {
+        let canaries = vec![
+            canary("version-floor", "height >= 100 && version < 4"),
+            canary(
+                "difficulty-jump",
+                "difficulty_change_percent > 25 || difficulty_change_percent < -20",
+            ),
+            canary("time-warp", "interval < 0"),
+        ];
+        let names = |header_info: &HeaderInfo, parent: &HeaderInfo| -> Vec<String> {
+            triggered(&canaries, header_info, parent)
+                .iter()
+                .map(|c| c.name.clone())
+                .collect()
+        };
+
+        let parent = header(99, 4, 1000, 0x1d00ffff);
+        assert!(names(&header(100, 4, 1600, 0x1d00ffff), &parent).is_empty());
+        assert_eq!(
+            names(&header(100, 2, 1600, 0x1d00ffff), &parent),
+            vec!["version-floor"]
+        );
+        assert_eq!(
+            names(&header(100, 4, 900, 0x1c7fff80), &parent),
+            vec!["difficulty-jump", "time-warp"]
+        );
+        // below the activation height of the version floor
+        let parent = header(49, 1, 1000, 0x1d00ffff);
+        assert!(names(&header(50, 1, 1600, 0x1d00ffff), &parent).is_empty());
+    }
+}
@@ -0,0 +1,373 @@
+use tokio::sync::Mutex;
+
+use crate::config::ChainParams;
+use crate::domain::{EventKind, EventSeverity, HeaderInfo};
+use crate::events::EventLog;
+use crate::types::ChainParamsJson;
+
+// After 64 halvings the subsidy is zero. Shifting by 64 or more would
+// overflow.
+const MAX_HALVINGS: u64 = 64;
+
+// Number of blocks before a halving or difficulty adjustment at which a
+// countdown event is emitted.
+const COUNTDOWN_MILESTONES: [u64; 4] = [1000, 100, 10, 1];
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Milestone {
+    Halving { height: u64, subsidy: u64 },
+    HalvingCountdown { height: u64, blocks_left: u64 },
+    DifficultyAdjustment { height: u64 },
+    DifficultyAdjustmentCountdown { height: u64, blocks_left: u64 },
+}
+
+pub fn subsidy(params: &ChainParams, height: u64) -> u64 {
+    let halvings = height / params.halving_interval;
+    if halvings >= MAX_HALVINGS {
+        return 0;
+    }
+    params.initial_subsidy >> halvings
+}
+
+pub fn next_halving_height(params: &ChainParams, height: u64) -> u64 {
+    (height / params.halving_interval + 1) * params.halving_interval
+}
+
+pub fn next_difficulty_adjustment_height(params: &ChainParams, height: u64) -> u64 {
+    (height / params.difficulty_adjustment_interval + 1) * params.difficulty_adjustment_interval
+}
+
+// If the header hash meets its target and the target is within the pow
+// limit. None if the proof of work can't be checked: for Elements headers,
+// which are signed instead of mined, and when the validation is disabled,
+// e.g. for scrypt chains.
+pub fn pow_verified(params: &ChainParams, header_info: &HeaderInfo) -> Option<bool> {
+    if header_info.elements.is_some() || !params.validate_pow {
+        return None;
+    }
+    let target = header_info.header.target();
+    let within_limit = params.pow_limit.is_none_or(|limit| target <= limit);
+    Some(within_limit && header_info.header.validate_pow(target).is_ok())
+}
+
+// Returns the countdown milestone, as number of blocks left, that was passed
+// when moving from old_height to new_height towards target_height. If
+// multiple milestones were passed, only the closest to the target is
+// returned.
+fn passed_countdown(
+    old_height: u64,
+    new_height: u64,
+    target_height: u64,
+    interval: u64,
+) -> Option<u64> {
+    COUNTDOWN_MILESTONES
+        .iter()
+        .filter(|blocks_left| **blocks_left < interval && **blocks_left <= target_height)
+        .filter(|blocks_left| {
+            let milestone_height = target_height - **blocks_left;
+            old_height < milestone_height && milestone_height <= new_height
+        })
+        .min()
+        .copied()
+}
+
+// Returns the milestones passed when the tip moved from old_height to
+// new_height. At most one milestone of each type (halving and difficulty
+// adjustment) is returned to avoid flooding events when catching up.
+pub fn milestones(params: &ChainParams, old_height: u64, new_height: u64) -> Vec<Milestone> {
+    let mut milestones: Vec<Milestone> = vec![];
+    if new_height <= old_height {
+        return milestones;
+    }
+
+    let halving_epoch = new_height / params.halving_interval;
+    if halving_epoch > old_height / params.halving_interval {
+        let height = halving_epoch * params.halving_interval;
+        milestones.push(Milestone::Halving {
+            height,
+            subsidy: subsidy(params, height),
+        });
+    } else {
+        let target = next_halving_height(params, old_height);
+        if let Some(blocks_left) =
+            passed_countdown(old_height, new_height, target, params.halving_interval)
+        {
+            milestones.push(Milestone::HalvingCountdown {
+                height: target,
+                blocks_left,
+            });
+        }
+    }
+
+    let interval = params.difficulty_adjustment_interval;
+    let adjustment_epoch = new_height / interval;
+    if adjustment_epoch > old_height / interval {
+        milestones.push(Milestone::DifficultyAdjustment {
+            height: adjustment_epoch * interval,
+        });
+    } else {
+        let target = next_difficulty_adjustment_height(params, old_height);
+        if let Some(blocks_left) = passed_countdown(old_height, new_height, target, interval) {
+            milestones.push(Milestone::DifficultyAdjustmentCountdown {
+                height: target,
+                blocks_left,
+            });
+        }
+    }
+
+    milestones
+}
+
+// Emits events for the milestones passed since the last known active tip
+// height of the network. The first call only records the height, as we
+// don't know which milestones were already announced before.
+pub async fn check_milestones(
+    network_id: u32,
+    network_name: &str,
+    params: &ChainParams,
+    last_height: &Mutex<Option<u64>>,
+    active_height: u64,
+    event_log: &EventLog,
+) {
+    let old_height = {
+        let mut last_height_locked = last_height.lock().await;
+        match *last_height_locked {
+            Some(old_height) if old_height >= active_height => return,
+            Some(old_height) => {
+                *last_height_locked = Some(active_height);
+                old_height
+            }
+            None => {
+                *last_height_locked = Some(active_height);
+                return;
+            }
+        }
+    };
+
+    for milestone in milestones(params, old_height, active_height) {
+        let (kind, title, description) = match milestone {
+            Milestone::Halving { height, subsidy } => (
+                EventKind::Halving,
+                format!("Halving at height {} on {}", height, network_name),
+                format!(
+                    "The block subsidy on {} is now {} sat starting with block {}.",
+                    network_name, subsidy, height
+                ),
+            ),
+            Milestone::HalvingCountdown {
+                height,
+                blocks_left,
+            } => (
+                EventKind::HalvingCountdown,
+                format!(
+                    "{} block{} until the halving on {}",
+                    blocks_left,
+                    if blocks_left > 1 { "s" } else { "" },
+                    network_name
+                ),
+                format!(
+                    "The next halving on {} happens at height {}.",
+                    network_name, height
+                ),
+            ),
+            Milestone::DifficultyAdjustment { height } => (
+                EventKind::DifficultyAdjustment,
+                format!(
+                    "Difficulty adjustment at height {} on {}",
+                    height, network_name
+                ),
+                format!(
+                    "A new difficulty adjustment period started on {} with block {}.",
+                    network_name, height
+                ),
+            ),
+            Milestone::DifficultyAdjustmentCountdown {
+                height,
+                blocks_left,
+            } => (
+                EventKind::DifficultyAdjustmentCountdown,
+                format!(
+                    "{} block{} until the difficulty adjustment on {}",
+                    blocks_left,
+                    if blocks_left > 1 { "s" } else { "" },
+                    network_name
+                ),
+                format!(
+                    "The next difficulty adjustment on {} happens at height {}.",
+                    network_name, height
+                ),
+            ),
+        };
+        event_log
+            .emit(network_id, kind, EventSeverity::Info, title, description)
+            .await;
+    }
+}
+
+// Estimates when a future height is reached, assuming blocks arrive exactly
+// every target_spacing seconds after the current tip.
+pub fn estimated_timestamp(
+    params: &ChainParams,
+    tip_height: u64,
+    tip_time: u64,
+    height: u64,
+) -> u64 {
+    tip_time + height.saturating_sub(tip_height) * params.target_spacing
+}
+
+// Builds the chain parameters response. The tip is the highest header we
+// know of. Without a tip, the height dependent fields are omitted.
+pub fn chain_params_json(
+    network_id: u32,
+    params: &ChainParams,
+    tip: Option<(u64, u64)>,
+) -> ChainParamsJson {
+    let mut json = ChainParamsJson {
+        network_id,
+        genesis_hash: params.genesis_hash.map(|h| h.to_string()),
+        target_spacing: params.target_spacing,
+        halving_interval: params.halving_interval,
+        initial_subsidy: params.initial_subsidy,
+        difficulty_adjustment_interval: params.difficulty_adjustment_interval,
+        tip_height: None,
+        current_subsidy: None,
+        next_halving_height: None,
+        blocks_until_next_halving: None,
+        next_halving_estimated_timestamp: None,
+        next_difficulty_adjustment_height: None,
+        blocks_until_next_difficulty_adjustment: None,
+        next_difficulty_adjustment_estimated_timestamp: None,
+    };
+    if let Some((tip_height, tip_time)) = tip {
+        let next_halving = next_halving_height(params, tip_height);
+        let next_adjustment = next_difficulty_adjustment_height(params, tip_height);
+        json.tip_height = Some(tip_height);
+        json.current_subsidy = Some(subsidy(params, tip_height));
+        json.next_halving_height = Some(next_halving);
+        json.blocks_until_next_halving = Some(next_halving - tip_height);
+        json.next_halving_estimated_timestamp = Some(estimated_timestamp(
+            params,
+            tip_height,
+            tip_time,
+            next_halving,
+        ));
+        json.next_difficulty_adjustment_height = Some(next_adjustment);
+        json.blocks_until_next_difficulty_adjustment = Some(next_adjustment - tip_height);
+        json.next_difficulty_adjustment_estimated_timestamp = Some(estimated_timestamp(
+            params,
+            tip_height,
+            tip_time,
+            next_adjustment,
+        ));
+    }
+    json
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mainnet_params() -> ChainParams {
+        ChainParams {
+            genesis_hash: None,
+            target_spacing: 600,
+            halving_interval: 210_000,
+            initial_subsidy: 50 * 100_000_000,
+            difficulty_adjustment_interval: 2016,
+            validate_pow: true,
+            pow_limit: None,
+        }
+    }
+
+    #[test]
+    fn test_subsidy() {
+        let params = mainnet_params();
+        assert_eq!(subsidy(&params, 0), 5_000_000_000);
+        assert_eq!(subsidy(&params, 209_999), 5_000_000_000);
+        assert_eq!(subsidy(&params, 210_000), 2_500_000_000);
+        assert_eq!(subsidy(&params, 840_000), 312_500_000);
+        assert_eq!(subsidy(&params, 64 * 210_000), 0);
+    }
+
+    #[test]
+    fn test_next_halving_height() {
+        let params = mainnet_params();
+        assert_eq!(next_halving_height(&params, 0), 210_000);
+        assert_eq!(next_halving_height(&params, 839_999), 840_000);
+        assert_eq!(next_halving_height(&params, 840_000), 1_050_000);
+        assert_eq!(next_difficulty_adjustment_height(&params, 2015), 2016);
+        assert_eq!(next_difficulty_adjustment_height(&params, 2016), 4032);
+    }
+
+    #[test]
+    fn test_pow_verified() {
+        use crate::testutil::header_info;
+        use bitcoincore_rpc::bitcoin::blockdata::constants::genesis_block;
+        use bitcoincore_rpc::bitcoin::{Network, Target};
+
+        let genesis = |network: Network| header_info(0, genesis_block(network).header);
+        let mut params = mainnet_params();
+        assert_eq!(
+            pow_verified(&params, &genesis(Network::Bitcoin)),
+            Some(true)
+        );
+        assert_eq!(
+            pow_verified(&params, &genesis(Network::Regtest)),
+            Some(true)
+        );
+
+        // the regtest target is easier than the mainnet pow limit
+        params.pow_limit = Some(Target::MAX_ATTAINABLE_MAINNET);
+        assert_eq!(
+            pow_verified(&params, &genesis(Network::Bitcoin)),
+            Some(true)
+        );
+        assert_eq!(
+            pow_verified(&params, &genesis(Network::Regtest)),
+            Some(false)
+        );
+
+        params.validate_pow = false;
+        assert_eq!(pow_verified(&params, &genesis(Network::Regtest)), None);
+    }
+
+    #[test]
+    fn test_milestones() {
+        let params = mainnet_params();
+        assert_eq!(milestones(&params, 100, 100), vec![]);
+        assert_eq!(milestones(&params, 101, 100), vec![]);
+        assert_eq!(
+            milestones(&params, 2014, 2016),
+            vec![Milestone::DifficultyAdjustment { height: 2016 }]
+        );
+        assert_eq!(
+            milestones(&params, 2004, 2006),
+            vec![Milestone::DifficultyAdjustmentCountdown {
+                height: 2016,
+                blocks_left: 10
+            }]
+        );
+        // only the closest countdown is reported when catching up
+        assert_eq!(
+            milestones(&params, 1900, 2015),
+            vec![Milestone::DifficultyAdjustmentCountdown {
+                height: 2016,
+                blocks_left: 1
+            }]
+        );
+        assert_eq!(
+            milestones(&params, 839_999, 840_001),
+            vec![Milestone::Halving {
+                height: 840_000,
+                subsidy: 312_500_000
+            }]
+        );
+        assert_eq!(
+            milestones(&params, 838_999, 839_000),
+            vec![Milestone::HalvingCountdown {
+                height: 840_000,
+                blocks_left: 1000
+            }]
+        );
+    }
+}
@@ -0,0 +1,123 @@
+use std::collections::HashMap;
+
+use crate::domain::Fork;
+use crate::types::{CompetingBlockJson, CompetingBlocksJsonResponse, CompetitionJson};
+
+pub const DEFAULT_COMPETING_BLOCKS_WINDOW: u64 = 60; // seconds
+
+// The hashes of the competing blocks of the forks.
+pub fn hashes(forks: &[Fork]) -> Vec<String> {
+    forks
+        .iter()
+        .flat_map(|fork| fork.children.iter())
+        .map(|child| child.block_hash().to_string())
+        .collect()
+}
+
+// Statistics about blocks competing for the same height. Competitions where
+// the blocks were first seen within the window are likely natural races.
+// Blocks first seen well after their competitor can indicate fee sniping,
+// i.e. a miner deliberately trying to replace the block at the tip. The
+// transactions of the blocks aren't compared, as only headers and coinbase
+// transactions are fetched from the nodes.
+pub fn statistics(
+    forks: &[Fork],
+    first_seen: &HashMap<String, u64>,
+    window: u64,
+) -> CompetingBlocksJsonResponse {
+    let mut details: Vec<CompetitionJson> = forks
+        .iter()
+        .map(|fork| {
+            let mut blocks: Vec<CompetingBlockJson> = fork
+                .children
+                .iter()
+                .map(|child| {
+                    let hash = child.block_hash().to_string();
+                    CompetingBlockJson {
+                        first_seen: first_seen.get(&hash).copied(),
+                        hash,
+                        miner: child.miner.clone(),
+                        time: child.header.time,
+                    }
+                })
+                .collect();
+            blocks.sort_by_key(|b| b.first_seen.unwrap_or(u64::MAX));
+
+            let seen: Vec<u64> = blocks.iter().filter_map(|b| b.first_seen).collect();
+            let first_seen_delta = match (seen.first(), seen.last()) {
+                (Some(first), Some(last)) if seen.len() > 1 => Some(last - first),
+                _ => None,
+            };
+            CompetitionJson {
+                height: fork.common.height + 1,
+                blocks,
+                first_seen_delta,
+                within_window: first_seen_delta.map(|delta| delta <= window),
+            }
+        })
+        .collect();
+    details.sort_by_key(|c| c.height);
+
+    CompetingBlocksJsonResponse {
+        window,
+        competitions: details.len(),
+        competitions_with_timing: details
+            .iter()
+            .filter(|c| c.first_seen_delta.is_some())
+            .count(),
+        competitions_within_window: details
+            .iter()
+            .filter(|c| c.within_window == Some(true))
+            .count(),
+        details,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::HeaderInfo;
+    use crate::testutil::at_height;
+
+    fn header_info(height: u64, nonce: u32) -> HeaderInfo {
+        at_height(height, 0, nonce)
+    }
+
+    #[test]
+    fn test_statistics() {
+        let forks = vec![
+            Fork {
+                common: header_info(10, 0),
+                children: vec![header_info(11, 1), header_info(11, 2)],
+            },
+            Fork {
+                common: header_info(20, 3),
+                children: vec![header_info(21, 4), header_info(21, 5)],
+            },
+            Fork {
+                common: header_info(30, 6),
+                children: vec![header_info(31, 7), header_info(31, 8)],
+            },
+        ];
+        let hashes = hashes(&forks);
+        let first_seen: HashMap<String, u64> = vec![
+            (hashes[0].clone(), 1000),
+            (hashes[1].clone(), 1010),
+            (hashes[2].clone(), 2000),
+            (hashes[3].clone(), 1500),
+            // the blocks of the third fork were never seen as a tip
+        ]
+        .into_iter()
+        .collect();
+
+        let stats = statistics(&forks, &first_seen, 60);
+        assert_eq!(stats.competitions, 3);
+        assert_eq!(stats.competitions_with_timing, 2);
+        assert_eq!(stats.competitions_within_window, 1);
+        assert_eq!(stats.details[0].height, 11);
+        assert_eq!(stats.details[0].first_seen_delta, Some(10));
+        assert_eq!(stats.details[1].first_seen_delta, Some(500));
+        assert_eq!(stats.details[1].blocks[0].hash, hashes[3]);
+        assert_eq!(stats.details[2].within_window, None);
+    }
+}
@@ -0,0 +1,89 @@
+use log::debug;
+
+use crate::config::{ComputedField, ComputedFieldTarget};
+use crate::domain::ChainTipStatus;
+use crate::expr::{Type, Value};
+use crate::types::{HeaderInfoJson, NodeDataJson};
+
+// The variables available to computed field expressions of a target.
+pub fn variables(target: ComputedFieldTarget) -> Vec<(&'static str, Type)> {
+    match target {
+        ComputedFieldTarget::Block => vec![
+            ("height", Type::Int),
+            ("version", Type::Int),
+            ("time", Type::Int),
+            ("bits", Type::Int),
+            ("nonce", Type::Int),
+        ],
+        ComputedFieldTarget::Node => vec![
+            ("id", Type::Int),
+            ("reachable", Type::Bool),
+            // height of the active tip or 0 if unknown
+            ("height", Type::Int),
+            ("tips", Type::Int),
+            ("last_changed_timestamp", Type::Int),
+        ],
+    }
+}
+
+fn block_values(header_info: &HeaderInfoJson) -> Vec<(&'static str, Value)> {
+    vec![
+        ("height", Value::Int(header_info.height as i64)),
+        ("version", Value::Int(header_info.version as i64)),
+        ("time", Value::Int(header_info.time as i64)),
+        ("bits", Value::Int(header_info.bits as i64)),
+        ("nonce", Value::Int(header_info.nonce as i64)),
+    ]
+}
+
+fn node_values(node: &NodeDataJson) -> Vec<(&'static str, Value)> {
+    let active_height = node
+        .tips
+        .iter()
+        .find(|tip| tip.status == ChainTipStatus::Active.to_string())
+        .map(|tip| tip.height)
+        .unwrap_or_default();
+    vec![
+        ("id", Value::Int(node.id as i64)),
+        ("reachable", Value::Bool(node.reachable)),
+        ("height", Value::Int(active_height as i64)),
+        ("tips", Value::Int(node.tips.len() as i64)),
+        (
+            "last_changed_timestamp",
+            Value::Int(node.last_changed_timestamp as i64),
+        ),
+    ]
+}
+
+// Evaluates the fields of the target. Fields that fail to evaluate, e.g.
+// due to a division by zero, are set to null.
+fn evaluate(
+    fields: &[ComputedField],
+    target: ComputedFieldTarget,
+    values: &[(&str, Value)],
+) -> Vec<(String, serde_json::Value)> {
+    fields
+        .iter()
+        .filter(|field| field.target == target)
+        .map(|field| match field.expression.eval(values) {
+            Ok(value) => (field.name.clone(), value.into()),
+            Err(e) => {
+                debug!("Could not evaluate computed field '{}': {}", field.name, e);
+                (field.name.clone(), serde_json::Value::Null)
+            }
+        })
+        .collect()
+}
+
+pub fn add_to_header_info(fields: &[ComputedField], header_info: &mut HeaderInfoJson) {
+    let values = block_values(header_info);
+    header_info
+        .computed
+        .extend(evaluate(fields, ComputedFieldTarget::Block, &values));
+}
+
+pub fn add_to_node(fields: &[ComputedField], node: &mut NodeDataJson) {
+    let values = node_values(node);
+    node.computed
+        .extend(evaluate(fields, ComputedFieldTarget::Node, &values));
+}
@@ -1,3 +1,5 @@
+use std::collections::{BTreeMap, HashMap};
+use std::convert::TryFrom;
 use std::hash::Hash;
 use std::net::SocketAddr;
 use std::path::PathBuf;
@@ -6,18 +8,95 @@ use std::sync::Arc;
 use std::time::Duration;
 use std::{env, fmt, fs};
 
-use bitcoincore_rpc::bitcoin::Network as BitcoinNetwork;
+use bitcoincore_rpc::bitcoin::blockdata::constants::genesis_block;
+use bitcoincore_rpc::bitcoin::{
+    BlockHash, Network as BitcoinNetwork, ScriptBuf, Target, Transaction,
+};
 use bitcoincore_rpc::Auth;
-use log::{error, info};
+use log::{error, info, warn};
 use serde::Deserialize;
+use warp::http::header::HeaderName;
+use warp::http::{Method, Uri};
 
+use crate::canary;
+use crate::computed;
+use crate::customrpc::{self, CustomRpc, CustomRpcNode};
+use crate::domain::{ChainKind, EventKind, EventSeverity, NodeInfo};
+use crate::elements::ElementsNode;
 use crate::error::ConfigError;
-use crate::node::{BitcoinCoreNode, BtcdNode, Node, NodeInfo};
+use crate::expr::{Expr, Type};
+use crate::http::{ClientAuth, HttpEndpoint, UNIX_SOCKET_PREFIX};
+use crate::node::{
+    BitcoinCoreNode, BtcdNode, ElectrumNode, EsploraNode, ForkObserverNode, LightClientNode, Node,
+    P2PNode, DEFAULT_REST_MAX_STEP_SIZE,
+};
+use crate::replay::ReplayNode;
+use crate::retry::RetryPolicy;
+use crate::stratum::StratumEndpoint;
+use crate::zmq::ZmqEndpoint;
 
 pub const ENVVAR_CONFIG_FILE: &str = "CONFIG_FILE";
 const DEFAULT_CONFIG: &str = "config.toml";
-const DEFAULT_NODE_IMPL: NodeImplementation = NodeImplementation::BitcoinCore;
-const DEFAULT_USE_REST: bool = true;
+pub const DEFAULT_NODE_IMPL: NodeImplementation = NodeImplementation::BitcoinCore;
+pub const DEFAULT_CHAIN_KIND: &str = "bitcoin";
+pub const DEFAULT_USE_REST: bool = true;
+pub const DEFAULT_RPC_HTTPS: bool = false;
+pub const DEFAULT_RPC_LONGPOLL: bool = false;
+pub const DEFAULT_RESOURCE_TELEMETRY: bool = false;
+// The default timeout of the stock RPC client.
+pub const DEFAULT_RPC_TIMEOUT: u64 = 15; // seconds
+pub const DEFAULT_REST_TIMEOUT: u64 = 8; // seconds
+pub const DEFAULT_BTCD_WEBSOCKET: bool = false;
+pub const DEFAULT_MEMPOOL_URL: &str = "https://mempool.space/api";
+pub const DEFAULT_NODE_TRUST: f64 = 1.0;
+pub const DEFAULT_NODE_ON_REMOVAL: &str = "retire";
+pub const DEFAULT_NODE_PURGE_AFTER_DAYS: u64 = 30;
+pub const DEFAULT_NODE_MAX_RETRIES: u32 = 2;
+pub const DEFAULT_NODE_RETRY_BACKOFF: u64 = 1; // seconds
+pub const DEFAULT_NODE_REACHABILITY_CORRELATION_WINDOW: u64 = 3600; // seconds
+pub const DEFAULT_STORAGE_CHECK_INTERVAL: u64 = 300; // seconds
+pub const DEFAULT_STORAGE_WARN_THRESHOLD_PERCENT: u8 = 90;
+pub const DEFAULT_STORAGE_EMERGENCY_COMPACTION: bool = false;
+pub const DEFAULT_P2P_PROBES_CHECK_INTERVAL: u64 = 300; // seconds
+pub const DEFAULT_TARGET_SPACING: u64 = 600; // seconds
+pub const DEFAULT_HALVING_INTERVAL: u64 = 210_000; // blocks
+pub const DEFAULT_REGTEST_HALVING_INTERVAL: u64 = 150; // blocks
+pub const DEFAULT_INITIAL_SUBSIDY: u64 = 50 * 100_000_000; // sat
+pub const DEFAULT_DIFFICULTY_ADJUSTMENT_INTERVAL: u64 = 2016; // blocks
+pub const DEFAULT_VALIDATE_POW: bool = true;
+pub const DEFAULT_NOTIFICATION_MAX_ATTEMPTS: u32 = 10;
+pub const DEFAULT_NOTIFICATION_MIN_SEVERITY: EventSeverity = EventSeverity::Warning;
+pub const DEFAULT_OWN_STALE_BLOCK_SEVERITY: EventSeverity = EventSeverity::Critical;
+pub const DEFAULT_MAX_FEE_DIVERGENCE_PERCENT: u32 = 10;
+pub const DEFAULT_MAX_TX_COUNT_DIVERGENCE_PERCENT: u32 = 20;
+pub const DEFAULT_ANOMALY_MAX_PROBABILITY: f64 = 0.001;
+pub const DEFAULT_TIMESTAMP_MAX_FUTURE: u64 = 3600; // seconds
+pub const DEFAULT_TIMESTAMP_MTP_MARGIN: u64 = 0; // seconds
+pub const DEFAULT_CANARY_SEVERITY: EventSeverity = EventSeverity::Warning;
+pub const DEFAULT_HEADER_GAP_MAX_GAP: u64 = 6; // blocks
+pub const DEFAULT_HEADER_GAP_ALERT_AFTER: u64 = 1800; // seconds
+pub const DEFAULT_HISTORY_CACHE_CONFIRMATIONS: u64 = 100; // blocks
+pub const DEFAULT_HISTORY_CACHE_CHUNK_SIZE: u64 = 1000; // headers
+pub const DEFAULT_HISTORY_CACHE_MAX_AGE: u64 = 31_536_000; // seconds
+pub const DEFAULT_READ_THROUGH_MAX_FETCHES_PER_MINUTE: u32 = 10;
+pub const DEFAULT_TREE_COMPACTION_KEEP_DEPTH: u64 = 2016; // blocks
+pub const DEFAULT_BLOCK_METADATA_DEPTH: u64 = 2; // blocks
+pub const DEFAULT_STRATUM_USER: &str = "fork-observer";
+pub const DEFAULT_STRATUM_PASSWORD: &str = "x";
+pub const DEFAULT_PROFILE: Profile = Profile::Default;
+pub const DEFAULT_FULL_QUERY_INTERVAL: u64 = 60; // seconds
+pub const DEFAULT_CORS_ALLOWED_METHODS: [&str; 1] = ["GET"];
+pub const DEFAULT_TRACING_SERVICE_NAME: &str = "fork-observer";
+pub const DEFAULT_NOTARIZATION_INTERVAL: u64 = 3600; // seconds
+pub const DEFAULT_NOTARIZATION_CALENDARS: [&str; 2] = [
+    "https://a.pool.opentimestamps.org",
+    "https://b.pool.opentimestamps.org",
+];
+pub const DEFAULT_TIP_OBSERVATION_RETENTION_DAYS: u64 = 365;
+pub const LOW_RESOURCE_MIN_QUERY_INTERVAL: u64 = 60; // seconds
+pub const LOW_RESOURCE_MAX_INTERESTING_HEIGHTS: usize = 50;
+pub const LOW_RESOURCE_TIP_OBSERVATION_RETENTION_DAYS: u64 = 7;
+pub const LOW_RESOURCE_MAX_DATABASE_SIZE_MB: u64 = 256;
 
 pub type BoxedSyncSendNode = Arc<dyn Node + Send + Sync>;
 
@@ -38,6 +117,25 @@ impl PoolIdentificationNetwork {
     }
 }
 
+#[derive(Clone, Deserialize, Debug)]
+pub enum ChainNetwork {
+    Mainnet,
+    Testnet,
+    Signet,
+    Regtest,
+}
+
+impl ChainNetwork {
+    pub fn to_network(&self) -> BitcoinNetwork {
+        match self {
+            ChainNetwork::Mainnet => BitcoinNetwork::Bitcoin,
+            ChainNetwork::Testnet => BitcoinNetwork::Testnet,
+            ChainNetwork::Signet => BitcoinNetwork::Signet,
+            ChainNetwork::Regtest => BitcoinNetwork::Regtest,
+        }
+    }
+}
+
 #[derive(Deserialize)]
 struct TomlConfig {
     address: String,
@@ -45,8 +143,51 @@ struct TomlConfig {
     www_path: String,
     rss_base_url: Option<String>,
     query_interval: u64,
+    full_query_interval: Option<u64>,
     networks: Vec<TomlNetwork>,
     footer_html: String,
+    storage_monitoring: Option<TomlStorageMonitoring>,
+    p2p_probes: Option<TomlP2PProbes>,
+    admin_token: Option<String>,
+    notifications: Option<TomlNotifications>,
+    computed_fields: Option<Vec<TomlComputedField>>,
+    external_observers: Option<Vec<TomlExternalObserver>>,
+    event_correlation: Option<BTreeMap<String, u64>>,
+    profile: Option<String>,
+    tip_observation_retention_days: Option<u64>,
+    notarization: Option<TomlNotarization>,
+    tracing: Option<TomlTracing>,
+    cors: Option<TomlCors>,
+}
+
+/// Bundles of defaults and caps for the environment fork-observer runs in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Profile {
+    Default,
+    /// For running on constrained hardware, e.g. on the same Raspberry Pi
+    /// as the node. See apply_low_resource_profile().
+    LowResource,
+}
+
+impl FromStr for Profile {
+    type Err = ConfigError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "default" => Ok(Profile::Default),
+            "low-resource" => Ok(Profile::LowResource),
+            _ => Err(ConfigError::UnknownProfile(s.to_string())),
+        }
+    }
+}
+
+impl fmt::Display for Profile {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Profile::Default => write!(f, "default"),
+            Profile::LowResource => write!(f, "low-resource"),
+        }
+    }
 }
 
 #[derive(Clone)]
@@ -54,16 +195,218 @@ pub struct Config {
     pub database_path: PathBuf,
     pub www_path: PathBuf,
     pub query_interval: Duration,
+    /// Between full queries, a node's chain tips are only queried if its
+    /// best block changed. Always queried if unset.
+    pub full_query_interval: Option<Duration>,
     pub address: SocketAddr,
     pub networks: Vec<Network>,
     pub footer_html: String,
     pub rss_base_url: String,
+    pub storage_monitoring: Option<StorageMonitoring>,
+    pub p2p_probes: Option<P2PProbes>,
+    /// Bearer token for the admin API. The admin API is disabled if unset.
+    pub admin_token: Option<String>,
+    pub notifications: Notifications,
+    pub computed_fields: Vec<ComputedField>,
+    pub external_observers: Vec<ExternalObserver>,
+    /// The window in which events of a kind are correlated.
+    pub event_correlation: HashMap<EventKind, Duration>,
+    pub profile: Profile,
+    /// Tip observations older than this are deleted.
+    pub tip_observation_retention: Duration,
+    pub notarization: Option<Notarization>,
+    pub tracing: Option<Tracing>,
+    /// CORS policy of the JSON API. Served without CORS headers if unset.
+    pub cors: Option<Cors>,
+}
+
+#[derive(Debug, Deserialize)]
+struct TomlExternalObserver {
+    name: String,
+    token: String,
+}
+
+/// A trusted reporter, e.g. a light client or monitoring probe, submitting
+/// block sightings via the API.
+#[derive(Debug, Clone)]
+pub struct ExternalObserver {
+    pub name: String,
+    /// Bearer token the observer authenticates with.
+    pub token: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct TomlComputedField {
+    name: String,
+    target: ComputedFieldTarget,
+    expression: String,
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub enum ComputedFieldTarget {
+    #[serde(rename = "block")]
+    Block,
+    #[serde(rename = "node")]
+    Node,
+}
+
+/// An operator-defined field computed for each block or node and included
+/// in the API output.
+#[derive(Debug, Clone)]
+pub struct ComputedField {
+    pub name: String,
+    pub target: ComputedFieldTarget,
+    pub expression: Expr,
+}
+
+#[derive(Debug, Deserialize)]
+struct TomlNotifications {
+    max_attempts: Option<u32>,
+    sinks: Option<Vec<TomlNotificationSink>>,
+}
+
+#[derive(Debug, Deserialize)]
+struct TomlNotificationSink {
+    name: String,
+    kind: String,
+    min_severity: Option<String>,
+    url: Option<String>,
+    homeserver: Option<String>,
+    room_id: Option<String>,
+    access_token: Option<String>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Notifications {
+    /// Failed deliveries are retried with backoff until max_attempts is
+    /// reached. Then the notification is moved to the dead-letter queue.
+    pub max_attempts: u32,
+    pub sinks: Vec<NotificationSink>,
+}
+
+#[derive(Debug, Clone)]
+pub struct NotificationSink {
+    pub name: String,
+    pub min_severity: EventSeverity,
+    pub target: NotificationTarget,
+}
+
+#[derive(Debug, Clone)]
+pub enum NotificationTarget {
+    /// POSTs the event as JSON to the URL.
+    Webhook { url: String },
+    /// Sends the event as text message into a Matrix room.
+    Matrix {
+        homeserver: String,
+        room_id: String,
+        access_token: String,
+    },
+}
+
+#[derive(Debug, Deserialize)]
+struct TomlStorageMonitoring {
+    max_database_size_mb: Option<u64>,
+    log_paths: Option<Vec<PathBuf>>,
+    max_log_size_mb: Option<u64>,
+    warn_threshold_percent: Option<u8>,
+    check_interval: Option<u64>,
+    emergency_compaction: Option<bool>,
+}
+
+#[derive(Debug, Clone)]
+pub struct StorageMonitoring {
+    /// Limit for the database file(s) in bytes.
+    pub max_database_size: Option<u64>,
+    /// Log files or directories to keep an eye on.
+    pub log_paths: Vec<PathBuf>,
+    /// Limit for the combined size of all log_paths in bytes.
+    pub max_log_size: Option<u64>,
+    /// Percentage of a limit at which we start warning.
+    pub warn_threshold_percent: u8,
+    pub check_interval: Duration,
+    /// If the database is over its limit, try to compact it.
+    pub emergency_compaction: bool,
+}
+
+#[derive(Debug, Deserialize)]
+struct TomlCors {
+    allowed_origins: Vec<String>,
+    allowed_methods: Option<Vec<String>>,
+    allowed_headers: Option<Vec<String>>,
+    max_age: Option<u64>,
+}
+
+/// Lets web frontends on other origins use the JSON API from browsers.
+#[derive(Debug, Clone)]
+pub struct Cors {
+    /// Origins like https://example.com. "*" allows any origin.
+    pub allowed_origins: Vec<String>,
+    pub allowed_methods: Vec<String>,
+    pub allowed_headers: Vec<String>,
+    /// How long browsers may cache the result of a preflight request.
+    pub max_age: Option<Duration>,
+}
+
+#[derive(Debug, Deserialize)]
+struct TomlTracing {
+    otlp_endpoint: String,
+    service_name: Option<String>,
+}
+
+/// Export the traces of the API requests to an OpenTelemetry collector.
+#[derive(Debug, Clone)]
+pub struct Tracing {
+    /// Base URL of the collector's OTLP/HTTP receiver, e.g.
+    /// http://127.0.0.1:4318.
+    pub otlp_endpoint: String,
+    pub service_name: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct TomlNotarization {
+    output_dir: PathBuf,
+    interval: Option<u64>,
+    calendars: Option<Vec<String>>,
+}
+
+/// Makes the event log tamper-evident: the head of a hash chain over the
+/// events is periodically written to a log file and timestamped with
+/// OpenTimestamps calendars. See notarization.
+#[derive(Debug, Clone)]
+pub struct Notarization {
+    /// Directory the notarizations.log and the .ots files are written to.
+    pub output_dir: PathBuf,
+    pub interval: Duration,
+    /// OpenTimestamps calendar servers. Only the log file is written if empty.
+    pub calendars: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct TomlP2PProbes {
+    check_interval: Option<u64>,
+    tor_proxy: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct P2PProbes {
+    pub check_interval: Duration,
+    /// SOCKS5 proxy used to probe onion addresses. Onion addresses aren't
+    /// probed without a proxy.
+    pub tor_proxy: Option<SocketAddr>,
 }
 
 #[derive(Debug, Deserialize, Clone, Default)]
 pub struct PoolIdentification {
     pub enable: bool,
     pub network: Option<PoolIdentificationNetwork>,
+    /// Check the blocks fetched for the identification against their
+    /// headers, including the merkle root of their transactions.
+    #[serde(default)]
+    pub validate_merkle_root: bool,
+    /// The id of the node of the network asked for the coinbases first,
+    /// e.g. an archive node. The other nodes are asked for the blocks it
+    /// doesn't know, e.g. stale ones.
+    pub node: Option<u32>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -72,9 +415,295 @@ struct TomlNetwork {
     name: String,
     description: String,
     min_fork_height: u64,
+    backfill_blocks: Option<u64>,
     max_interesting_heights: usize,
     nodes: Vec<TomlNode>,
     pool_identification: Option<PoolIdentification>,
+    chain_params: Option<TomlChainParams>,
+    share_headers_with: Option<u32>,
+    chain_kind: Option<String>,
+    own_mining: Option<TomlOwnMining>,
+    tree_quota: Option<TreeQuota>,
+    template_comparison: Option<TomlTemplateComparison>,
+    block_interval_anomalies: Option<TomlBlockIntervalAnomalies>,
+    timestamp_anomalies: Option<TomlTimestampAnomalies>,
+    #[serde(default)]
+    canaries: Vec<TomlCanary>,
+    header_gap: Option<TomlHeaderGap>,
+    history_cache: Option<TomlHistoryCache>,
+    read_through: Option<TomlReadThrough>,
+    fork_pruning: Option<TomlForkPruning>,
+    tree_compaction: Option<TomlTreeCompaction>,
+    block_metadata: Option<TomlBlockMetadata>,
+    #[serde(default)]
+    stratum_pools: Vec<TomlStratumPool>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+struct TomlStratumPool {
+    name: String,
+    endpoint: String,
+    user: Option<String>,
+    password: Option<String>,
+}
+
+/// A mining pool whose Stratum V1 jobs are monitored. The block the pool
+/// builds on is shown next to the tips of the nodes.
+#[derive(Debug, Clone)]
+pub struct StratumPool {
+    pub name: String,
+    pub endpoint: StratumEndpoint,
+    pub user: String,
+    pub password: String,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+struct TomlHeaderGap {
+    max_gap: Option<u64>,
+    alert_after: Option<u64>,
+}
+
+/// Compares the best known header of each node with its validated tip each
+/// query interval. A node knowing of headers far ahead of its tip for long
+/// doesn't make progress validating the blocks, e.g. because it stalled or
+/// can't download them.
+#[derive(Debug, Clone)]
+pub struct HeaderGap {
+    /// Number of headers a node may know of beyond its validated tip.
+    pub max_gap: u64,
+    /// How long the gap has to exceed max_gap before a node-stalled event
+    /// is emitted.
+    pub alert_after: Duration,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+struct TomlHistoryCache {
+    confirmations: Option<u64>,
+    chunk_size: Option<u64>,
+    max_age: Option<u64>,
+}
+
+/// Serves the header history in chunks of chunk_size heights. Chunks buried
+/// under at least confirmations blocks don't change anymore and are served
+/// from memory with long-lived cache headers, e.g. for a CDN in front of a
+/// public instance.
+#[derive(Debug, Clone)]
+pub struct HistoryCache {
+    pub confirmations: u64,
+    pub chunk_size: u64,
+    /// How long clients and CDNs may cache an immutable chunk.
+    pub max_age: Duration,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+struct TomlReadThrough {
+    max_fetches_per_minute: Option<u32>,
+}
+
+/// Fetches headers the API is asked about but the tree doesn't contain
+/// from a reachable node on demand. Only headers building on a header in
+/// the tree are inserted.
+#[derive(Debug, Clone)]
+pub struct ReadThrough {
+    /// Fetches per network and minute, so API clients can't flood the
+    /// nodes with requests.
+    pub max_fetches_per_minute: u32,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+struct TomlForkPruning {
+    keep_depth: Option<u64>,
+    max_forks: Option<usize>,
+}
+
+/// Prunes the stale branches forking off the active chain long ago from the
+/// header tree and the database, e.g. the many old one-block forks of test
+/// networks. A branch is pruned if any of the limits is exceeded.
+#[derive(Debug, Clone)]
+pub struct ForkPruning {
+    /// Keep the branches forking off within this many blocks of the active
+    /// tip.
+    pub keep_depth: Option<u64>,
+    /// Keep the branches of the max_forks most recent forks.
+    pub max_forks: Option<usize>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+struct TomlTreeCompaction {
+    keep_depth: Option<u64>,
+}
+
+/// Collapses the long linear runs of headers between forks in the header
+/// tree into a single edge, so the tree in memory only grows with the
+/// forks. The headers stay in the database.
+#[derive(Debug, Clone)]
+pub struct TreeCompaction {
+    /// Keep the headers within this many blocks of the lowest active tip of
+    /// the nodes. Deeper reorgs would fetch collapsed headers again.
+    pub keep_depth: u64,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+struct TomlBlockMetadata {
+    depth: Option<u64>,
+}
+
+/// Fetches the blocks around the recent forks from the nodes and records
+/// their size, weight, number of transactions and fees.
+#[derive(Debug, Clone)]
+pub struct BlockMetadata {
+    /// The blocks of each branch up to this many blocks above the last
+    /// common block.
+    pub depth: u64,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+struct TomlBlockIntervalAnomalies {
+    max_probability: Option<f64>,
+}
+
+/// Detects block intervals that are unlikely given the target spacing of
+/// the network: clusters of blocks found within a short time and long
+/// droughts without blocks.
+#[derive(Debug, Clone)]
+pub struct BlockIntervalAnomalies {
+    /// Intervals with a probability below this are reported.
+    pub max_probability: f64,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+struct TomlTimestampAnomalies {
+    max_future: Option<u64>,
+    mtp_margin: Option<u64>,
+}
+
+/// Flags headers with a time far ahead of the current time or not clearly
+/// after the median time past of their parent.
+#[derive(Debug, Clone)]
+pub struct TimestampAnomalies {
+    /// Headers with a time more than this many seconds ahead of the
+    /// current time are flagged as future.
+    pub max_future: u64,
+    /// Headers with a time at most this many seconds after the median time
+    /// past are flagged as past. With 0, only headers violating the
+    /// consensus rule are flagged.
+    pub mtp_margin: u64,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+struct TomlCanary {
+    name: String,
+    condition: String,
+    severity: Option<String>,
+}
+
+/// An operator-defined consensus-policy check evaluated against each new
+/// header and its parent, e.g. a minimum block version after an activation
+/// height on a private chain. A canary event is emitted when the condition
+/// holds.
+#[derive(Debug, Clone)]
+pub struct Canary {
+    pub name: String,
+    pub condition: Expr,
+    pub severity: EventSeverity,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+struct TomlTemplateComparison {
+    max_fee_divergence_percent: Option<u32>,
+    max_tx_count_divergence_percent: Option<u32>,
+}
+
+/// Compares the getblocktemplate results of the nodes each query interval.
+/// Templates diverging by more than the configured percentages in total fees
+/// or transaction count indicate inconsistent mempools, e.g. due to policy
+/// differences between node versions.
+#[derive(Debug, Clone)]
+pub struct TemplateComparison {
+    pub max_fee_divergence_percent: u32,
+    pub max_tx_count_divergence_percent: u32,
+}
+
+/// Limits how many new branches and headers a single node can introduce
+/// into the shared header tree per hour. Further headers of the node are
+/// quarantined.
+#[derive(Debug, Deserialize, Clone)]
+pub struct TreeQuota {
+    pub max_branches_per_hour: Option<u32>,
+    pub max_headers_per_hour: Option<u32>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+struct TomlOwnMining {
+    coinbase_tags: Option<Vec<String>>,
+    payout_scripts: Option<Vec<String>>,
+    stale_block_severity: Option<String>,
+}
+
+/// Identifies blocks mined by the instance operator's own pool or keys.
+#[derive(Debug, Clone)]
+pub struct OwnMining {
+    /// Byte strings contained in the coinbase input script, e.g. a pool tag.
+    pub coinbase_tags: Vec<String>,
+    /// Output scripts the coinbase pays to.
+    pub payout_scripts: Vec<ScriptBuf>,
+    /// Severity of the event emitted when an own block becomes stale.
+    pub stale_block_severity: EventSeverity,
+}
+
+impl OwnMining {
+    pub fn matches(&self, coinbase: &Transaction) -> bool {
+        let script_sig = match coinbase.input.first() {
+            Some(input) => input.script_sig.as_bytes(),
+            None => &[],
+        };
+        self.coinbase_tags.iter().any(|tag| {
+            script_sig
+                .windows(tag.len())
+                .any(|window| window == tag.as_bytes())
+        }) || coinbase
+            .output
+            .iter()
+            .any(|output| self.payout_scripts.contains(&output.script_pubkey))
+    }
+}
+
+#[derive(Debug, Deserialize, Clone, Default)]
+struct TomlChainParams {
+    network: Option<ChainNetwork>,
+    genesis_hash: Option<String>,
+    target_spacing: Option<u64>,
+    halving_interval: Option<u64>,
+    initial_subsidy: Option<u64>,
+    difficulty_adjustment_interval: Option<u64>,
+    validate_pow: Option<bool>,
+    pow_limit: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ChainParams {
+    /// Either configured or the genesis block of the configured network.
+    pub genesis_hash: Option<BlockHash>,
+    /// Target time between blocks in seconds.
+    pub target_spacing: u64,
+    pub halving_interval: u64,
+    /// Block subsidy in sat before the first halving.
+    pub initial_subsidy: u64,
+    pub difficulty_adjustment_interval: u64,
+    /// If the header hashes are checked against their target. Chains with
+    /// another proof of work hash, e.g. scrypt, can't be checked.
+    pub validate_pow: bool,
+    /// The easiest target allowed. Headers with an easier target don't pass
+    /// the proof of work check.
+    pub pow_limit: Option<Target>,
+}
+
+impl Default for ChainParams {
+    // The Bitcoin mainnet parameters without a genesis hash.
+    fn default() -> Self {
+        parse_toml_chain_params(&TomlChainParams::default())
+            .expect("the default chain params should be valid")
+    }
 }
 
 #[derive(Clone)]
@@ -83,9 +712,41 @@ pub struct Network {
     pub description: String,
     pub name: String,
     pub min_fork_height: u64,
+    /// Without headers in the database, the headers are fetched from this
+    /// many blocks below the active tip of a node on instead of from the
+    /// min_fork_height.
+    pub backfill_blocks: Option<u64>,
     pub max_interesting_heights: usize,
     pub nodes: Vec<BoxedSyncSendNode>,
     pub pool_identification: PoolIdentification,
+    pub chain_params: ChainParams,
+    /// Id of the network whose header store (header tree and database
+    /// headers) this network uses. This is the network's own id unless it
+    /// shares the header store of another network on the same chain.
+    pub header_store: u32,
+    /// Elements networks have their own header format. Their nodes are
+    /// Elements nodes, and the enrichments needing Bitcoin blocks are
+    /// disabled.
+    pub chain_kind: ChainKind,
+    /// Blocks mined by the operator. For networks sharing a header store,
+    /// the own_mining of the network owning the store identifies them.
+    pub own_mining: Option<OwnMining>,
+    pub tree_quota: Option<TreeQuota>,
+    pub template_comparison: Option<TemplateComparison>,
+    pub block_interval_anomalies: Option<BlockIntervalAnomalies>,
+    pub timestamp_anomalies: Option<TimestampAnomalies>,
+    pub canaries: Vec<Canary>,
+    pub header_gap: Option<HeaderGap>,
+    pub history_cache: Option<HistoryCache>,
+    pub read_through: Option<ReadThrough>,
+    /// For networks sharing a header store, the fork_pruning of the network
+    /// owning the store prunes it.
+    pub fork_pruning: Option<ForkPruning>,
+    /// For networks sharing a header store, the tree_compaction of the
+    /// network owning the store compacts it.
+    pub tree_compaction: Option<TreeCompaction>,
+    pub block_metadata: Option<BlockMetadata>,
+    pub stratum_pools: Vec<StratumPool>,
 }
 
 impl fmt::Display for TomlNetwork {
@@ -107,19 +768,71 @@ struct TomlNode {
     id: u32,
     description: String,
     name: String,
+    // Not used by Esplora nodes.
+    #[serde(default)]
     rpc_host: String,
+    #[serde(default)]
     rpc_port: u16,
     rpc_cookie_file: Option<PathBuf>,
     rpc_user: Option<String>,
     rpc_password: Option<String>,
+    rpc_https: Option<bool>,
+    rpc_https_ca_file: Option<PathBuf>,
+    rpc_https_insecure: Option<bool>,
+    rpc_https_client_cert: Option<PathBuf>,
+    rpc_https_client_key: Option<PathBuf>,
+    socks5_proxy: Option<String>,
+    zmq_hashblock: Option<String>,
+    rpc_longpoll: Option<bool>,
+    resource_telemetry: Option<bool>,
+    rpc_timeout: Option<u64>,
     use_rest: Option<bool>,
+    rest_timeout: Option<u64>,
+    rest_max_step_size: Option<u64>,
     implementation: Option<String>,
+    trust: Option<f64>,
+    shadow: Option<bool>,
+    esplora_url: Option<String>,
+    mempool_url: Option<String>,
+    remote_url: Option<String>,
+    remote_network_id: Option<u32>,
+    remote_node_id: Option<u32>,
+    replay_file: Option<PathBuf>,
+    btcd_websocket: Option<bool>,
+    custom_rpc: Option<TomlCustomRpc>,
+    p2p_network: Option<ChainNetwork>,
+    on_removal: Option<String>,
+    purge_after_days: Option<u64>,
+    max_retries: Option<u32>,
+    retry_backoff: Option<u64>,
+}
+
+#[derive(Debug, Deserialize, Clone, Default)]
+struct TomlCustomRpc {
+    tips_method: Option<String>,
+    tips_params: Option<String>,
+    tips_path: Option<String>,
+    tip_height: Option<String>,
+    tip_hash: Option<String>,
+    tip_branchlen: Option<String>,
+    tip_status: Option<String>,
+    #[serde(default)]
+    statuses: BTreeMap<String, String>,
+    header_method: Option<String>,
+    header_params: Option<String>,
+    header_path: Option<String>,
+    block_hash_method: Option<String>,
+    block_hash_params: Option<String>,
+    block_hash_path: Option<String>,
+    version_method: Option<String>,
+    version_params: Option<String>,
+    version_path: Option<String>,
 }
 
 impl fmt::Display for TomlNode {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(
-            f,"Node (id={}, description='{}', name='{}', rpc_host='{}', rpc_port={}, rpc_user='{}', rpc_password='***', rpc_cookie_file={:?}, use_rest={}, implementation='{}')",
+            f,"Node (id={}, description='{}', name='{}', rpc_host='{}', rpc_port={}, rpc_user='{}', rpc_password='***', rpc_cookie_file={:?}, use_rest={}, rest_max_step_size={}, implementation='{}')",
             self.id,
             self.description,
             self.name,
@@ -128,15 +841,25 @@ impl fmt::Display for TomlNode {
             self.rpc_user.as_ref().unwrap_or(&"".to_string()),
             self.rpc_cookie_file,
             self.use_rest.unwrap_or(DEFAULT_USE_REST),
+            self.rest_max_step_size.unwrap_or(DEFAULT_REST_MAX_STEP_SIZE),
             self.implementation.as_ref().unwrap_or(&"".to_string()),
         )
     }
 }
 
-#[derive(Hash, Clone)]
+#[derive(Hash, Clone, PartialEq)]
 pub enum NodeImplementation {
     BitcoinCore,
     Btcd,
+    Electrum,
+    Esplora,
+    Mempool,
+    ForkObserver,
+    LightClient,
+    P2P,
+    Replay,
+    CustomRpc,
+    Elements,
 }
 
 impl FromStr for NodeImplementation {
@@ -148,6 +871,20 @@ impl FromStr for NodeImplementation {
             "bitcoin core" => Ok(NodeImplementation::BitcoinCore),
             "core" => Ok(NodeImplementation::BitcoinCore),
             "btcd" => Ok(NodeImplementation::Btcd),
+            "electrum" => Ok(NodeImplementation::Electrum),
+            "esplora" => Ok(NodeImplementation::Esplora),
+            "mempool" => Ok(NodeImplementation::Mempool),
+            "mempool.space" => Ok(NodeImplementation::Mempool),
+            "forkobserver" => Ok(NodeImplementation::ForkObserver),
+            "fork-observer" => Ok(NodeImplementation::ForkObserver),
+            "lightclient" => Ok(NodeImplementation::LightClient),
+            "light client" => Ok(NodeImplementation::LightClient),
+            "p2p" => Ok(NodeImplementation::P2P),
+            "replay" => Ok(NodeImplementation::Replay),
+            "customrpc" => Ok(NodeImplementation::CustomRpc),
+            "custom rpc" => Ok(NodeImplementation::CustomRpc),
+            "custom-rpc" => Ok(NodeImplementation::CustomRpc),
+            "elements" => Ok(NodeImplementation::Elements),
             _ => Err(ConfigError::UnknownImplementation),
         }
     }
@@ -158,10 +895,84 @@ impl fmt::Display for NodeImplementation {
         match self {
             NodeImplementation::BitcoinCore => write!(f, "Bitcoin Core"),
             NodeImplementation::Btcd => write!(f, "btcd"),
+            NodeImplementation::Electrum => write!(f, "Electrum"),
+            NodeImplementation::Esplora => write!(f, "Esplora"),
+            NodeImplementation::Mempool => write!(f, "mempool.space"),
+            NodeImplementation::ForkObserver => write!(f, "fork-observer"),
+            NodeImplementation::LightClient => write!(f, "Light client"),
+            NodeImplementation::P2P => write!(f, "P2P"),
+            NodeImplementation::Replay => write!(f, "Replay"),
+            NodeImplementation::CustomRpc => write!(f, "Custom RPC"),
+            NodeImplementation::Elements => write!(f, "Elements"),
         }
     }
 }
 
+fn parse_custom_rpc_method(
+    name: &Option<String>,
+    params: &Option<String>,
+    path: &Option<String>,
+    default_name: &str,
+    default_params: &str,
+    default_path: &str,
+) -> Result<customrpc::Method, ConfigError> {
+    let params = params.as_deref().unwrap_or(default_params);
+    Ok(customrpc::Method {
+        name: name.clone().unwrap_or_else(|| default_name.to_string()),
+        params: match serde_json::from_str::<Vec<serde_json::Value>>(params) {
+            Ok(params) => params,
+            Err(_) => return Err(ConfigError::InvalidCustomRpcParams(params.to_string())),
+        },
+        path: path.clone().unwrap_or_else(|| default_path.to_string()),
+    })
+}
+
+// Unset methods and fields default to the Bitcoin Core ones.
+fn parse_toml_custom_rpc(toml_custom_rpc: &TomlCustomRpc) -> Result<CustomRpc, ConfigError> {
+    let field = |field: &Option<String>, default: &str| {
+        field.clone().unwrap_or_else(|| default.to_string())
+    };
+    Ok(CustomRpc {
+        tips: parse_custom_rpc_method(
+            &toml_custom_rpc.tips_method,
+            &toml_custom_rpc.tips_params,
+            &toml_custom_rpc.tips_path,
+            customrpc::DEFAULT_TIPS_METHOD,
+            "[]",
+            "",
+        )?,
+        tip_height: field(&toml_custom_rpc.tip_height, "height"),
+        tip_hash: field(&toml_custom_rpc.tip_hash, "hash"),
+        tip_branchlen: field(&toml_custom_rpc.tip_branchlen, "branchlen"),
+        tip_status: field(&toml_custom_rpc.tip_status, "status"),
+        statuses: toml_custom_rpc.statuses.clone(),
+        header: parse_custom_rpc_method(
+            &toml_custom_rpc.header_method,
+            &toml_custom_rpc.header_params,
+            &toml_custom_rpc.header_path,
+            customrpc::DEFAULT_HEADER_METHOD,
+            customrpc::DEFAULT_HEADER_PARAMS,
+            "",
+        )?,
+        block_hash: parse_custom_rpc_method(
+            &toml_custom_rpc.block_hash_method,
+            &toml_custom_rpc.block_hash_params,
+            &toml_custom_rpc.block_hash_path,
+            customrpc::DEFAULT_BLOCK_HASH_METHOD,
+            customrpc::DEFAULT_BLOCK_HASH_PARAMS,
+            "",
+        )?,
+        version: parse_custom_rpc_method(
+            &toml_custom_rpc.version_method,
+            &toml_custom_rpc.version_params,
+            &toml_custom_rpc.version_path,
+            customrpc::DEFAULT_VERSION_METHOD,
+            "[]",
+            customrpc::DEFAULT_VERSION_PATH,
+        )?,
+    })
+}
+
 fn parse_rpc_auth(node_config: &TomlNode) -> Result<Auth, ConfigError> {
     if node_config.rpc_cookie_file.is_some() {
         if let Some(rpc_cookie_file) = node_config.rpc_cookie_file.clone() {
@@ -179,15 +990,18 @@ fn parse_rpc_auth(node_config: &TomlNode) -> Result<Auth, ConfigError> {
     Err(ConfigError::NoBitcoinCoreRpcAuth)
 }
 
+pub fn config_file_path() -> String {
+    env::var(ENVVAR_CONFIG_FILE).unwrap_or_else(|_| DEFAULT_CONFIG.to_string())
+}
+
 pub fn load_config() -> Result<Config, ConfigError> {
-    let config_file_path =
-        env::var(ENVVAR_CONFIG_FILE).unwrap_or_else(|_| DEFAULT_CONFIG.to_string());
+    let config_file_path = config_file_path();
     info!("Reading configuration file from {}.", config_file_path);
     let config_string = fs::read_to_string(config_file_path)?;
     parse_config(&config_string)
 }
 
-fn parse_config(config_str: &str) -> Result<Config, ConfigError> {
+pub fn parse_config(config_str: &str) -> Result<Config, ConfigError> {
     let toml_config: TomlConfig = toml::from_str(config_str)?;
 
     let mut networks: Vec<Network> = vec![];
@@ -243,14 +1057,432 @@ fn parse_config(config_str: &str) -> Result<Config, ConfigError> {
         return Err(ConfigError::NoNetworks);
     }
 
-    Ok(Config {
+    check_shared_header_stores(&networks)?;
+
+    let computed_fields = parse_toml_computed_fields(&toml_config.computed_fields)?;
+    let external_observers = parse_toml_external_observers(&toml_config.external_observers)?;
+
+    let event_correlation = parse_toml_event_correlation(&toml_config.event_correlation)?;
+
+    let notifications = match toml_config.notifications {
+        Some(ref toml_notifications) => parse_toml_notifications(toml_notifications)?,
+        None => Notifications::default(),
+    };
+
+    let storage_monitoring = match toml_config.storage_monitoring {
+        Some(ref toml_storage_monitoring) => {
+            Some(parse_toml_storage_monitoring(toml_storage_monitoring)?)
+        }
+        None => None,
+    };
+
+    let p2p_probes = match toml_config.p2p_probes {
+        Some(ref toml_p2p_probes) => Some(P2PProbes {
+            check_interval: Duration::from_secs(
+                toml_p2p_probes
+                    .check_interval
+                    .unwrap_or(DEFAULT_P2P_PROBES_CHECK_INTERVAL),
+            ),
+            tor_proxy: match toml_p2p_probes.tor_proxy {
+                Some(ref tor_proxy) => Some(SocketAddr::from_str(tor_proxy)?),
+                None => None,
+            },
+        }),
+        None => None,
+    };
+
+    let profile = match toml_config.profile {
+        Some(ref profile) => profile.parse::<Profile>()?,
+        None => DEFAULT_PROFILE,
+    };
+
+    let tip_observation_retention_days = match toml_config.tip_observation_retention_days {
+        Some(0) => return Err(ConfigError::InvalidTipObservationRetention),
+        Some(days) => days,
+        None if profile == Profile::LowResource => LOW_RESOURCE_TIP_OBSERVATION_RETENTION_DAYS,
+        None => DEFAULT_TIP_OBSERVATION_RETENTION_DAYS,
+    };
+    let tip_observation_retention =
+        Duration::from_secs(tip_observation_retention_days * 24 * 60 * 60);
+
+    let notarization = match toml_config.notarization {
+        Some(ref toml_notarization) => Some(parse_toml_notarization(toml_notarization)?),
+        None => None,
+    };
+
+    let tracing = match toml_config.tracing {
+        Some(ref toml_tracing) => {
+            if is_https_unsupported(&toml_tracing.otlp_endpoint) {
+                return Err(ConfigError::HttpsNotSupported);
+            }
+            Some(Tracing {
+                otlp_endpoint: toml_tracing.otlp_endpoint.clone(),
+                service_name: toml_tracing
+                    .service_name
+                    .clone()
+                    .unwrap_or_else(|| DEFAULT_TRACING_SERVICE_NAME.to_string()),
+            })
+        }
+        None => None,
+    };
+
+    let cors = match toml_config.cors {
+        Some(ref toml_cors) => Some(parse_toml_cors(toml_cors)?),
+        None => None,
+    };
+
+    let mut config = Config {
         database_path: PathBuf::from(toml_config.database_path),
         www_path: PathBuf::from(toml_config.www_path),
         query_interval: Duration::from_secs(toml_config.query_interval),
+        full_query_interval: match toml_config
+            .full_query_interval
+            .unwrap_or(DEFAULT_FULL_QUERY_INTERVAL)
+        {
+            0 => None,
+            seconds => Some(Duration::from_secs(seconds)),
+        },
         address: SocketAddr::from_str(&toml_config.address)?,
         footer_html: toml_config.footer_html.clone(),
         rss_base_url: toml_config.rss_base_url.unwrap_or_default().clone(),
         networks,
+        storage_monitoring,
+        p2p_probes,
+        admin_token: toml_config.admin_token.clone(),
+        notifications,
+        computed_fields,
+        external_observers,
+        event_correlation,
+        profile,
+        tip_observation_retention,
+        notarization,
+        tracing,
+        cors,
+    };
+    if profile == Profile::LowResource {
+        apply_low_resource_profile(&mut config);
+    }
+    Ok(config)
+}
+
+// Caps the query interval and the heights kept in the cache, disables the
+// enrichments needing extra RPC calls or connections (pool identification,
+// template comparison and P2P probes), and unless configured otherwise,
+// deletes old tip observations and compacts the database when it grows too
+// large.
+fn apply_low_resource_profile(config: &mut Config) {
+    let min_query_interval = Duration::from_secs(LOW_RESOURCE_MIN_QUERY_INTERVAL);
+    if config.query_interval < min_query_interval {
+        warn!(
+            "The low-resource profile raises the query_interval to {}s",
+            LOW_RESOURCE_MIN_QUERY_INTERVAL
+        );
+        config.query_interval = min_query_interval;
+    }
+    for network in config.networks.iter_mut() {
+        network.max_interesting_heights = network
+            .max_interesting_heights
+            .min(LOW_RESOURCE_MAX_INTERESTING_HEIGHTS);
+        if network.pool_identification.enable || network.template_comparison.is_some() {
+            warn!(
+                "The low-resource profile disables the pool identification and template comparison of network {}",
+                network.id
+            );
+        }
+        network.pool_identification.enable = false;
+        network.template_comparison = None;
+    }
+    if config.p2p_probes.take().is_some() {
+        warn!("The low-resource profile disables the P2P probes");
+    }
+    config
+        .storage_monitoring
+        .get_or_insert_with(|| StorageMonitoring {
+            max_database_size: Some(LOW_RESOURCE_MAX_DATABASE_SIZE_MB * 1024 * 1024),
+            log_paths: vec![],
+            max_log_size: None,
+            warn_threshold_percent: DEFAULT_STORAGE_WARN_THRESHOLD_PERCENT,
+            check_interval: Duration::from_secs(DEFAULT_STORAGE_CHECK_INTERVAL),
+            emergency_compaction: true,
+        });
+}
+
+// The reachability events of a node are correlated by default, so a flapping
+// node doesn't flood the event log. A window of zero disables the
+// correlation of a kind.
+fn parse_toml_event_correlation(
+    toml_event_correlation: &Option<BTreeMap<String, u64>>,
+) -> Result<HashMap<EventKind, Duration>, ConfigError> {
+    let mut windows = HashMap::from([
+        (
+            EventKind::NodeUnreachable,
+            Duration::from_secs(DEFAULT_NODE_REACHABILITY_CORRELATION_WINDOW),
+        ),
+        (
+            EventKind::NodeReachable,
+            Duration::from_secs(DEFAULT_NODE_REACHABILITY_CORRELATION_WINDOW),
+        ),
+    ]);
+    for (kind, window) in toml_event_correlation.iter().flatten() {
+        match EventKind::from(kind.clone()) {
+            EventKind::Unknown => return Err(ConfigError::UnknownEventKind(kind.clone())),
+            kind => windows.insert(kind, Duration::from_secs(*window)),
+        };
+    }
+    Ok(windows)
+}
+
+fn parse_toml_external_observers(
+    toml_observers: &Option<Vec<TomlExternalObserver>>,
+) -> Result<Vec<ExternalObserver>, ConfigError> {
+    let mut observers: Vec<ExternalObserver> = vec![];
+    for toml_observer in toml_observers.iter().flatten() {
+        if toml_observer.name.is_empty()
+            || toml_observer.token.is_empty()
+            || observers
+                .iter()
+                .any(|o| o.name == toml_observer.name || o.token == toml_observer.token)
+        {
+            error!(
+                "External observers need a unique, non-empty name and token: '{}'",
+                toml_observer.name
+            );
+            return Err(ConfigError::InvalidExternalObserver);
+        }
+        observers.push(ExternalObserver {
+            name: toml_observer.name.clone(),
+            token: toml_observer.token.clone(),
+        });
+    }
+    Ok(observers)
+}
+
+fn parse_toml_computed_fields(
+    toml_computed_fields: &Option<Vec<TomlComputedField>>,
+) -> Result<Vec<ComputedField>, ConfigError> {
+    let mut computed_fields: Vec<ComputedField> = vec![];
+    for toml_field in toml_computed_fields.iter().flatten() {
+        if toml_field.name.is_empty()
+            || computed_fields
+                .iter()
+                .any(|f| f.name == toml_field.name && f.target == toml_field.target)
+        {
+            error!(
+                "Computed field names must be non-empty and unique per target: '{}'",
+                toml_field.name
+            );
+            return Err(ConfigError::InvalidComputedField);
+        }
+        let variables = computed::variables(toml_field.target);
+        match Expr::parse(&toml_field.expression, &variables) {
+            Ok((expression, _)) => computed_fields.push(ComputedField {
+                name: toml_field.name.clone(),
+                target: toml_field.target,
+                expression,
+            }),
+            Err(e) => {
+                error!(
+                    "Invalid expression '{}' of computed field '{}': {}",
+                    toml_field.expression, toml_field.name, e
+                );
+                return Err(ConfigError::InvalidComputedField);
+            }
+        }
+    }
+    Ok(computed_fields)
+}
+
+fn is_https_unsupported(url: &str) -> bool {
+    !cfg!(feature = "https") && url.to_lowercase().starts_with("https://")
+}
+
+fn parse_toml_notifications(
+    toml_notifications: &TomlNotifications,
+) -> Result<Notifications, ConfigError> {
+    let mut sinks: Vec<NotificationSink> = vec![];
+    for toml_sink in toml_notifications.sinks.iter().flatten() {
+        if sinks.iter().any(|sink| sink.name == toml_sink.name) {
+            error!("Duplicate notification sink name '{}'", toml_sink.name);
+            return Err(ConfigError::DuplicateNotificationSinkName);
+        }
+        let target = match toml_sink.kind.to_lowercase().as_str() {
+            "webhook" => match toml_sink.url {
+                Some(ref url) if is_https_unsupported(url) => {
+                    error!(
+                        "Notification sink '{}' uses HTTPS, but fork-observer was built without the 'https' feature",
+                        toml_sink.name
+                    );
+                    return Err(ConfigError::HttpsNotSupported);
+                }
+                Some(ref url) => NotificationTarget::Webhook { url: url.clone() },
+                None => return Err(ConfigError::IncompleteNotificationSink),
+            },
+            "matrix" => match (
+                &toml_sink.homeserver,
+                &toml_sink.room_id,
+                &toml_sink.access_token,
+            ) {
+                (Some(homeserver), _, _) if is_https_unsupported(homeserver) => {
+                    error!(
+                        "Notification sink '{}' uses HTTPS, but fork-observer was built without the 'https' feature",
+                        toml_sink.name
+                    );
+                    return Err(ConfigError::HttpsNotSupported);
+                }
+                (Some(homeserver), Some(room_id), Some(access_token)) => {
+                    NotificationTarget::Matrix {
+                        homeserver: homeserver.trim_end_matches('/').to_string(),
+                        room_id: room_id.clone(),
+                        access_token: access_token.clone(),
+                    }
+                }
+                _ => return Err(ConfigError::IncompleteNotificationSink),
+            },
+            _ => return Err(ConfigError::UnknownNotificationSinkKind),
+        };
+        let min_severity = match toml_sink.min_severity {
+            Some(ref min_severity) => match min_severity.parse::<EventSeverity>() {
+                Ok(min_severity) => min_severity,
+                Err(e) => {
+                    error!("Invalid notification sink '{}': {}", toml_sink.name, e);
+                    return Err(ConfigError::InvalidNotificationSeverity);
+                }
+            },
+            None => DEFAULT_NOTIFICATION_MIN_SEVERITY,
+        };
+        sinks.push(NotificationSink {
+            name: toml_sink.name.clone(),
+            min_severity,
+            target,
+        });
+    }
+    Ok(Notifications {
+        max_attempts: toml_notifications
+            .max_attempts
+            .unwrap_or(DEFAULT_NOTIFICATION_MAX_ATTEMPTS),
+        sinks,
+    })
+}
+
+fn parse_toml_storage_monitoring(
+    toml_storage_monitoring: &TomlStorageMonitoring,
+) -> Result<StorageMonitoring, ConfigError> {
+    const BYTES_PER_MB: u64 = 1024 * 1024;
+
+    let warn_threshold_percent = toml_storage_monitoring
+        .warn_threshold_percent
+        .unwrap_or(DEFAULT_STORAGE_WARN_THRESHOLD_PERCENT);
+    if warn_threshold_percent > 100 {
+        return Err(ConfigError::InvalidStorageWarnThreshold);
+    }
+    let check_interval = toml_storage_monitoring
+        .check_interval
+        .unwrap_or(DEFAULT_STORAGE_CHECK_INTERVAL);
+    if check_interval == 0 {
+        return Err(ConfigError::InvalidStorageCheckInterval);
+    }
+
+    Ok(StorageMonitoring {
+        max_database_size: toml_storage_monitoring
+            .max_database_size_mb
+            .map(|mb| mb * BYTES_PER_MB),
+        log_paths: toml_storage_monitoring
+            .log_paths
+            .clone()
+            .unwrap_or_default(),
+        max_log_size: toml_storage_monitoring
+            .max_log_size_mb
+            .map(|mb| mb * BYTES_PER_MB),
+        warn_threshold_percent,
+        check_interval: Duration::from_secs(check_interval),
+        emergency_compaction: toml_storage_monitoring
+            .emergency_compaction
+            .unwrap_or(DEFAULT_STORAGE_EMERGENCY_COMPACTION),
+    })
+}
+
+// Validated here, as warp panics on invalid origins, methods or headers.
+fn parse_toml_cors(toml_cors: &TomlCors) -> Result<Cors, ConfigError> {
+    if toml_cors.allowed_origins.is_empty() {
+        return Err(ConfigError::InvalidCors(
+            "allowed_origins is empty".to_string(),
+        ));
+    }
+    for origin in toml_cors.allowed_origins.iter() {
+        if origin == "*" {
+            continue;
+        }
+        let valid = match origin.parse::<Uri>() {
+            Ok(uri) => {
+                uri.scheme().is_some()
+                    && uri.authority().is_some()
+                    && uri.path_and_query().is_none_or(|p| p.as_str() == "/")
+                    && !origin.ends_with('/')
+            }
+            Err(_) => false,
+        };
+        if !valid {
+            return Err(ConfigError::InvalidCors(format!(
+                "the origin '{}' isn't of the form scheme://host[:port]",
+                origin
+            )));
+        }
+    }
+    let allowed_methods = toml_cors.allowed_methods.clone().unwrap_or_else(|| {
+        DEFAULT_CORS_ALLOWED_METHODS
+            .iter()
+            .map(|m| m.to_string())
+            .collect()
+    });
+    if let Some(method) = allowed_methods
+        .iter()
+        .find(|m| Method::from_bytes(m.as_bytes()).is_err())
+    {
+        return Err(ConfigError::InvalidCors(format!(
+            "the method '{}' is invalid",
+            method
+        )));
+    }
+    let allowed_headers = toml_cors.allowed_headers.clone().unwrap_or_default();
+    if let Some(header) = allowed_headers
+        .iter()
+        .find(|h| HeaderName::from_bytes(h.as_bytes()).is_err())
+    {
+        return Err(ConfigError::InvalidCors(format!(
+            "the header '{}' is invalid",
+            header
+        )));
+    }
+    Ok(Cors {
+        allowed_origins: toml_cors.allowed_origins.clone(),
+        allowed_methods,
+        allowed_headers,
+        max_age: toml_cors.max_age.map(Duration::from_secs),
+    })
+}
+
+fn parse_toml_notarization(
+    toml_notarization: &TomlNotarization,
+) -> Result<Notarization, ConfigError> {
+    let interval = toml_notarization
+        .interval
+        .unwrap_or(DEFAULT_NOTARIZATION_INTERVAL);
+    if interval == 0 {
+        return Err(ConfigError::InvalidNotarizationInterval);
+    }
+    let calendars = toml_notarization.calendars.clone().unwrap_or_else(|| {
+        DEFAULT_NOTARIZATION_CALENDARS
+            .iter()
+            .map(|c| c.to_string())
+            .collect()
+    });
+    if calendars.iter().any(|c| is_https_unsupported(c)) {
+        return Err(ConfigError::HttpsNotSupported);
+    }
+    Ok(Notarization {
+        output_dir: toml_notarization.output_dir.clone(),
+        interval: Duration::from_secs(interval),
+        calendars,
     })
 }
 
@@ -258,16 +1490,449 @@ fn parse_toml_network(
     toml_network: &TomlNetwork,
     nodes: Vec<BoxedSyncSendNode>,
 ) -> Result<Network, ConfigError> {
+    let chain_kind = toml_network
+        .chain_kind
+        .as_deref()
+        .unwrap_or(DEFAULT_CHAIN_KIND);
+    let chain_kind = chain_kind
+        .parse::<ChainKind>()
+        .map_err(|_| ConfigError::UnknownChainKind(chain_kind.to_string()))?;
+    let elements_implementation = NodeImplementation::Elements.to_string();
+    if let Some(node) = nodes.iter().find(|n| {
+        (n.info().implementation == elements_implementation) != (chain_kind == ChainKind::Elements)
+    }) {
+        return Err(ConfigError::NodeNotOnChainKind(
+            node.info().implementation,
+            chain_kind.to_string(),
+        ));
+    }
+    if let Some(node_id) = toml_network
+        .pool_identification
+        .as_ref()
+        .and_then(|p| p.node)
+    {
+        if !nodes.iter().any(|node| node.info().id == node_id) {
+            return Err(ConfigError::UnknownPoolIdentificationNode(node_id));
+        }
+    }
+    if chain_kind == ChainKind::Elements {
+        // These need the coinbase or block templates of Bitcoin blocks.
+        let pool_identification = toml_network
+            .pool_identification
+            .as_ref()
+            .is_some_and(|p| p.enable);
+        for (enabled, option) in [
+            (pool_identification, "pool_identification"),
+            (toml_network.own_mining.is_some(), "own_mining"),
+            (
+                toml_network.template_comparison.is_some(),
+                "template_comparison",
+            ),
+            // The fetched headers are checked against the Bitcoin block
+            // hash.
+            (toml_network.read_through.is_some(), "read_through"),
+            // Elements blocks have no fees in the coinbase.
+            (toml_network.block_metadata.is_some(), "block_metadata"),
+        ] {
+            if enabled {
+                return Err(ConfigError::NotSupportedOnElements(option.to_string()));
+            }
+        }
+    }
+
     Ok(Network {
         id: toml_network.id,
         name: toml_network.name.clone(),
         description: toml_network.description.clone(),
         min_fork_height: toml_network.min_fork_height,
+        backfill_blocks: toml_network.backfill_blocks,
         max_interesting_heights: toml_network.max_interesting_heights,
         nodes,
         pool_identification: toml_network.pool_identification.clone().unwrap_or_default(),
-    })
-}
+        chain_params: parse_toml_chain_params(
+            &toml_network.chain_params.clone().unwrap_or_default(),
+        )?,
+        header_store: toml_network.share_headers_with.unwrap_or(toml_network.id),
+        chain_kind,
+        own_mining: match toml_network.own_mining {
+            Some(ref toml_own_mining) => Some(parse_toml_own_mining(toml_own_mining)?),
+            None => None,
+        },
+        tree_quota: match toml_network.tree_quota {
+            Some(ref tree_quota) => {
+                if tree_quota.max_branches_per_hour == Some(0)
+                    || tree_quota.max_headers_per_hour == Some(0)
+                {
+                    return Err(ConfigError::InvalidTreeQuota);
+                }
+                Some(tree_quota.clone())
+            }
+            None => None,
+        },
+        template_comparison: match toml_network.template_comparison {
+            Some(ref template_comparison) => {
+                Some(parse_toml_template_comparison(template_comparison)?)
+            }
+            None => None,
+        },
+        block_interval_anomalies: match toml_network.block_interval_anomalies {
+            Some(ref anomalies) => {
+                let max_probability = anomalies
+                    .max_probability
+                    .unwrap_or(DEFAULT_ANOMALY_MAX_PROBABILITY);
+                if !(max_probability > 0.0 && max_probability < 1.0) {
+                    return Err(ConfigError::InvalidBlockIntervalAnomalies);
+                }
+                Some(BlockIntervalAnomalies { max_probability })
+            }
+            None => None,
+        },
+        timestamp_anomalies: toml_network.timestamp_anomalies.as_ref().map(|anomalies| {
+            TimestampAnomalies {
+                max_future: anomalies.max_future.unwrap_or(DEFAULT_TIMESTAMP_MAX_FUTURE),
+                mtp_margin: anomalies.mtp_margin.unwrap_or(DEFAULT_TIMESTAMP_MTP_MARGIN),
+            }
+        }),
+        canaries: parse_toml_canaries(&toml_network.canaries)?,
+        header_gap: match toml_network.header_gap {
+            Some(ref header_gap) => {
+                let max_gap = header_gap.max_gap.unwrap_or(DEFAULT_HEADER_GAP_MAX_GAP);
+                if max_gap == 0 {
+                    return Err(ConfigError::InvalidHeaderGap);
+                }
+                Some(HeaderGap {
+                    max_gap,
+                    alert_after: Duration::from_secs(
+                        header_gap
+                            .alert_after
+                            .unwrap_or(DEFAULT_HEADER_GAP_ALERT_AFTER),
+                    ),
+                })
+            }
+            None => None,
+        },
+        history_cache: match toml_network.history_cache {
+            Some(ref history_cache) => {
+                let confirmations = history_cache
+                    .confirmations
+                    .unwrap_or(DEFAULT_HISTORY_CACHE_CONFIRMATIONS);
+                let chunk_size = history_cache
+                    .chunk_size
+                    .unwrap_or(DEFAULT_HISTORY_CACHE_CHUNK_SIZE);
+                if confirmations == 0 || chunk_size == 0 {
+                    return Err(ConfigError::InvalidHistoryCache);
+                }
+                Some(HistoryCache {
+                    confirmations,
+                    chunk_size,
+                    max_age: Duration::from_secs(
+                        history_cache
+                            .max_age
+                            .unwrap_or(DEFAULT_HISTORY_CACHE_MAX_AGE),
+                    ),
+                })
+            }
+            None => None,
+        },
+        read_through: match toml_network.read_through {
+            Some(ref read_through) => {
+                let max_fetches_per_minute = read_through
+                    .max_fetches_per_minute
+                    .unwrap_or(DEFAULT_READ_THROUGH_MAX_FETCHES_PER_MINUTE);
+                if max_fetches_per_minute == 0 {
+                    return Err(ConfigError::InvalidReadThrough);
+                }
+                Some(ReadThrough {
+                    max_fetches_per_minute,
+                })
+            }
+            None => None,
+        },
+        fork_pruning: match toml_network.fork_pruning {
+            Some(ref fork_pruning) => {
+                if (fork_pruning.keep_depth.is_none() && fork_pruning.max_forks.is_none())
+                    || fork_pruning.keep_depth == Some(0)
+                    || fork_pruning.max_forks == Some(0)
+                {
+                    return Err(ConfigError::InvalidForkPruning);
+                }
+                Some(ForkPruning {
+                    keep_depth: fork_pruning.keep_depth,
+                    max_forks: fork_pruning.max_forks,
+                })
+            }
+            None => None,
+        },
+        tree_compaction: match toml_network.tree_compaction {
+            Some(ref tree_compaction) => {
+                let keep_depth = tree_compaction
+                    .keep_depth
+                    .unwrap_or(DEFAULT_TREE_COMPACTION_KEEP_DEPTH);
+                if keep_depth == 0 {
+                    return Err(ConfigError::InvalidTreeCompaction);
+                }
+                Some(TreeCompaction { keep_depth })
+            }
+            None => None,
+        },
+        block_metadata: match toml_network.block_metadata {
+            Some(ref block_metadata) => {
+                let depth = block_metadata.depth.unwrap_or(DEFAULT_BLOCK_METADATA_DEPTH);
+                if depth == 0 {
+                    return Err(ConfigError::InvalidBlockMetadata);
+                }
+                Some(BlockMetadata { depth })
+            }
+            None => None,
+        },
+        stratum_pools: toml_network
+            .stratum_pools
+            .iter()
+            .map(parse_toml_stratum_pool)
+            .collect::<Result<Vec<StratumPool>, ConfigError>>()?,
+    })
+}
+
+fn parse_toml_canaries(toml_canaries: &[TomlCanary]) -> Result<Vec<Canary>, ConfigError> {
+    let mut canaries: Vec<Canary> = vec![];
+    for toml_canary in toml_canaries.iter() {
+        let invalid = || ConfigError::InvalidCanary(toml_canary.name.clone());
+        if toml_canary.name.is_empty() || canaries.iter().any(|c| c.name == toml_canary.name) {
+            error!(
+                "Canary names must be non-empty and unique per network: '{}'",
+                toml_canary.name
+            );
+            return Err(invalid());
+        }
+        let condition = match Expr::parse(&toml_canary.condition, &canary::variables()) {
+            Ok((condition, Type::Bool)) => condition,
+            Ok((_, t)) => {
+                error!(
+                    "The condition '{}' of canary '{}' is an {}, not a boolean",
+                    toml_canary.condition, toml_canary.name, t
+                );
+                return Err(invalid());
+            }
+            Err(e) => {
+                error!(
+                    "Invalid condition '{}' of canary '{}': {}",
+                    toml_canary.condition, toml_canary.name, e
+                );
+                return Err(invalid());
+            }
+        };
+        let severity = match toml_canary.severity {
+            Some(ref severity) => match severity.parse::<EventSeverity>() {
+                Ok(severity) => severity,
+                Err(e) => {
+                    error!("Invalid severity of canary '{}': {}", toml_canary.name, e);
+                    return Err(invalid());
+                }
+            },
+            None => DEFAULT_CANARY_SEVERITY,
+        };
+        canaries.push(Canary {
+            name: toml_canary.name.clone(),
+            condition,
+            severity,
+        });
+    }
+    Ok(canaries)
+}
+
+fn parse_toml_stratum_pool(
+    toml_stratum_pool: &TomlStratumPool,
+) -> Result<StratumPool, ConfigError> {
+    Ok(StratumPool {
+        name: toml_stratum_pool.name.clone(),
+        endpoint: match StratumEndpoint::parse(&toml_stratum_pool.endpoint) {
+            Some(endpoint) => endpoint,
+            None => {
+                return Err(ConfigError::InvalidStratumEndpoint(
+                    toml_stratum_pool.endpoint.clone(),
+                ))
+            }
+        },
+        user: toml_stratum_pool
+            .user
+            .clone()
+            .unwrap_or_else(|| DEFAULT_STRATUM_USER.to_string()),
+        password: toml_stratum_pool
+            .password
+            .clone()
+            .unwrap_or_else(|| DEFAULT_STRATUM_PASSWORD.to_string()),
+    })
+}
+
+fn parse_toml_template_comparison(
+    toml_template_comparison: &TomlTemplateComparison,
+) -> Result<TemplateComparison, ConfigError> {
+    let template_comparison = TemplateComparison {
+        max_fee_divergence_percent: toml_template_comparison
+            .max_fee_divergence_percent
+            .unwrap_or(DEFAULT_MAX_FEE_DIVERGENCE_PERCENT),
+        max_tx_count_divergence_percent: toml_template_comparison
+            .max_tx_count_divergence_percent
+            .unwrap_or(DEFAULT_MAX_TX_COUNT_DIVERGENCE_PERCENT),
+    };
+    for percent in [
+        template_comparison.max_fee_divergence_percent,
+        template_comparison.max_tx_count_divergence_percent,
+    ]
+    .iter()
+    {
+        if *percent == 0 || *percent > 100 {
+            return Err(ConfigError::InvalidTemplateComparison);
+        }
+    }
+    Ok(template_comparison)
+}
+
+fn parse_toml_own_mining(toml_own_mining: &TomlOwnMining) -> Result<OwnMining, ConfigError> {
+    let coinbase_tags: Vec<String> = toml_own_mining
+        .coinbase_tags
+        .clone()
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|tag| !tag.is_empty())
+        .collect();
+    let mut payout_scripts: Vec<ScriptBuf> = vec![];
+    for script in toml_own_mining.payout_scripts.iter().flatten() {
+        match ScriptBuf::from_hex(script) {
+            Ok(script) => payout_scripts.push(script),
+            Err(e) => {
+                error!("Invalid own_mining payout script '{}': {}", script, e);
+                return Err(ConfigError::InvalidOwnMining);
+            }
+        }
+    }
+    if coinbase_tags.is_empty() && payout_scripts.is_empty() {
+        error!("The own_mining section needs at least one coinbase tag or payout script");
+        return Err(ConfigError::InvalidOwnMining);
+    }
+    let stale_block_severity = match toml_own_mining.stale_block_severity {
+        Some(ref severity) => match severity.parse::<EventSeverity>() {
+            Ok(severity) => severity,
+            Err(e) => {
+                error!("Invalid own_mining stale_block_severity: {}", e);
+                return Err(ConfigError::InvalidOwnMining);
+            }
+        },
+        None => DEFAULT_OWN_STALE_BLOCK_SEVERITY,
+    };
+    Ok(OwnMining {
+        coinbase_tags,
+        payout_scripts,
+        stale_block_severity,
+    })
+}
+
+// A network can only share the header store of another network that owns
+// its header store. Networks with different genesis blocks can't share.
+fn check_shared_header_stores(networks: &[Network]) -> Result<(), ConfigError> {
+    for network in networks.iter().filter(|n| n.header_store != n.id) {
+        let owner = match networks.iter().find(|n| n.id == network.header_store) {
+            Some(owner) => owner,
+            None => {
+                error!(
+                    "Network '{}' shares headers with the unknown network id {}",
+                    network.name, network.header_store
+                );
+                return Err(ConfigError::InvalidSharedHeaderStore);
+            }
+        };
+        if owner.header_store != owner.id {
+            error!(
+                "Network '{}' shares headers with network '{}', which shares its headers itself",
+                network.name, owner.name
+            );
+            return Err(ConfigError::InvalidSharedHeaderStore);
+        }
+        if network.chain_kind != owner.chain_kind {
+            error!(
+                "Network '{}' shares headers with network '{}', but they are of different chain kinds",
+                network.name, owner.name
+            );
+            return Err(ConfigError::InvalidSharedHeaderStore);
+        }
+        if let (Some(a), Some(b)) = (
+            network.chain_params.genesis_hash,
+            owner.chain_params.genesis_hash,
+        ) {
+            if a != b {
+                error!(
+                    "Network '{}' shares headers with network '{}', but they have different genesis blocks",
+                    network.name, owner.name
+                );
+                return Err(ConfigError::InvalidSharedHeaderStore);
+            }
+        }
+    }
+    Ok(())
+}
+
+fn parse_toml_chain_params(
+    toml_chain_params: &TomlChainParams,
+) -> Result<ChainParams, ConfigError> {
+    let genesis_hash = match toml_chain_params.genesis_hash {
+        Some(ref hash) => match BlockHash::from_str(hash) {
+            Ok(hash) => Some(hash),
+            Err(_) => return Err(ConfigError::InvalidGenesisHash),
+        },
+        None => toml_chain_params
+            .network
+            .as_ref()
+            .map(|network| genesis_block(network.to_network()).block_hash()),
+    };
+
+    let default_halving_interval = match toml_chain_params.network {
+        Some(ChainNetwork::Regtest) => DEFAULT_REGTEST_HALVING_INTERVAL,
+        _ => DEFAULT_HALVING_INTERVAL,
+    };
+
+    let halving_interval = toml_chain_params
+        .halving_interval
+        .unwrap_or(default_halving_interval);
+    let difficulty_adjustment_interval = toml_chain_params
+        .difficulty_adjustment_interval
+        .unwrap_or(DEFAULT_DIFFICULTY_ADJUSTMENT_INTERVAL);
+    if halving_interval == 0 || difficulty_adjustment_interval == 0 {
+        return Err(ConfigError::InvalidChainParams);
+    }
+
+    // Big-endian like the pow limits in the chain parameters of Bitcoin Core
+    // and its derivatives.
+    let pow_limit = match toml_chain_params.pow_limit {
+        Some(ref pow_limit) => match hex::decode(pow_limit).map(<[u8; 32]>::try_from) {
+            Ok(Ok(bytes)) => Some(Target::from_be_bytes(bytes)),
+            _ => return Err(ConfigError::InvalidPowLimit),
+        },
+        None => None,
+    };
+
+    Ok(ChainParams {
+        genesis_hash,
+        target_spacing: toml_chain_params
+            .target_spacing
+            .unwrap_or(DEFAULT_TARGET_SPACING),
+        halving_interval,
+        initial_subsidy: toml_chain_params
+            .initial_subsidy
+            .unwrap_or(DEFAULT_INITIAL_SUBSIDY),
+        difficulty_adjustment_interval,
+        validate_pow: toml_chain_params
+            .validate_pow
+            .unwrap_or(DEFAULT_VALIDATE_POW),
+        pow_limit,
+    })
+}
+
+// Parses a single [[networks.nodes]] table, e.g. to validate a node before
+// it's added to the configuration file.
+pub fn parse_node(node_str: &str) -> Result<BoxedSyncSendNode, ConfigError> {
+    let toml_node: TomlNode = toml::from_str(node_str)?;
+    parse_toml_node(&toml_node)
+}
 
 fn parse_toml_node(toml_node: &TomlNode) -> Result<BoxedSyncSendNode, ConfigError> {
     let implementation = toml_node
@@ -276,19 +1941,181 @@ fn parse_toml_node(toml_node: &TomlNode) -> Result<BoxedSyncSendNode, ConfigErro
         .unwrap_or(&DEFAULT_NODE_IMPL.to_string())
         .parse::<NodeImplementation>()?;
 
+    let trust = toml_node.trust.unwrap_or(DEFAULT_NODE_TRUST);
+    if !(0.0..=1.0).contains(&trust) {
+        return Err(ConfigError::InvalidNodeTrust);
+    }
+    let retry_backoff = toml_node
+        .retry_backoff
+        .unwrap_or(DEFAULT_NODE_RETRY_BACKOFF);
+    if retry_backoff == 0 {
+        return Err(ConfigError::InvalidNodeRetryBackoff);
+    }
+
     let node_info = NodeInfo {
         id: toml_node.id,
         name: toml_node.name.clone(),
         description: toml_node.description.clone(),
         implementation: implementation.to_string(),
+        trust,
+        shadow: toml_node.shadow.unwrap_or_default(),
+        purge_after: match toml_node
+            .on_removal
+            .as_deref()
+            .unwrap_or(DEFAULT_NODE_ON_REMOVAL)
+        {
+            "retire" => None,
+            "purge" => Some(
+                toml_node
+                    .purge_after_days
+                    .unwrap_or(DEFAULT_NODE_PURGE_AFTER_DAYS)
+                    * 24
+                    * 60
+                    * 60,
+            ),
+            _ => return Err(ConfigError::UnknownRemovalPolicy),
+        },
+        retry: RetryPolicy {
+            max_retries: toml_node.max_retries.unwrap_or(DEFAULT_NODE_MAX_RETRIES),
+            backoff: Duration::from_secs(retry_backoff),
+        },
+    };
+
+    let rest_max_step_size = toml_node
+        .rest_max_step_size
+        .unwrap_or(DEFAULT_REST_MAX_STEP_SIZE);
+    if rest_max_step_size == 0 {
+        return Err(ConfigError::InvalidRestMaxStepSize);
+    }
+
+    let rpc_https = toml_node.rpc_https.unwrap_or(DEFAULT_RPC_HTTPS);
+    if rpc_https
+        && !matches!(
+            implementation,
+            NodeImplementation::BitcoinCore
+                | NodeImplementation::Btcd
+                | NodeImplementation::CustomRpc
+                | NodeImplementation::Elements
+        )
+    {
+        return Err(ConfigError::RpcHttpsNotSupported);
+    }
+    let client_auth = match (
+        toml_node.rpc_https_client_cert.as_deref(),
+        toml_node.rpc_https_client_key.as_deref(),
+    ) {
+        (Some(cert_file), Some(key_file)) if rpc_https => Some(ClientAuth {
+            cert_file,
+            key_file,
+        }),
+        (None, None) => None,
+        _ => return Err(ConfigError::InvalidRpcHttpsClientCert),
+    };
+    let socks5_proxy: Option<SocketAddr> = match toml_node.socks5_proxy {
+        Some(ref proxy) => {
+            if !matches!(
+                implementation,
+                NodeImplementation::BitcoinCore | NodeImplementation::Elements
+            ) {
+                return Err(ConfigError::Socks5ProxyNotSupported);
+            }
+            Some(proxy.parse()?)
+        }
+        None => None,
+    };
+    let zmq_hashblock = match toml_node.zmq_hashblock {
+        Some(ref endpoint) => {
+            if !matches!(
+                implementation,
+                NodeImplementation::BitcoinCore | NodeImplementation::Elements
+            ) {
+                return Err(ConfigError::ZmqNotSupported);
+            }
+            match ZmqEndpoint::parse(endpoint, socks5_proxy) {
+                Some(endpoint) => Some(endpoint),
+                None => return Err(ConfigError::InvalidZmqEndpoint(endpoint.clone())),
+            }
+        }
+        None => None,
+    };
+    let rpc_longpoll = toml_node.rpc_longpoll.unwrap_or(DEFAULT_RPC_LONGPOLL);
+    if rpc_longpoll
+        && !matches!(
+            implementation,
+            NodeImplementation::BitcoinCore | NodeImplementation::Elements
+        )
+    {
+        return Err(ConfigError::RpcLongpollNotSupported);
+    }
+    let resource_telemetry = toml_node
+        .resource_telemetry
+        .unwrap_or(DEFAULT_RESOURCE_TELEMETRY);
+    if resource_telemetry
+        && !matches!(
+            implementation,
+            NodeImplementation::BitcoinCore | NodeImplementation::Elements
+        )
+    {
+        return Err(ConfigError::ResourceTelemetryNotSupported);
+    }
+    if (toml_node.rpc_timeout.is_some() || toml_node.rest_timeout.is_some())
+        && !matches!(
+            implementation,
+            NodeImplementation::BitcoinCore | NodeImplementation::Elements
+        )
+    {
+        return Err(ConfigError::NodeTimeoutNotSupported);
+    }
+    let rpc_timeout = toml_node.rpc_timeout.unwrap_or(DEFAULT_RPC_TIMEOUT);
+    let rest_timeout = toml_node.rest_timeout.unwrap_or(DEFAULT_REST_TIMEOUT);
+    if rpc_timeout == 0 || rest_timeout == 0 {
+        return Err(ConfigError::InvalidNodeTimeout);
+    }
+    let unix_socket = toml_node.rpc_host.strip_prefix(UNIX_SOCKET_PREFIX);
+    if unix_socket.is_some()
+        && (!cfg!(unix)
+            || !matches!(
+                implementation,
+                NodeImplementation::BitcoinCore | NodeImplementation::Elements
+            )
+            || rpc_https
+            || socks5_proxy.is_some())
+    {
+        return Err(ConfigError::UnixSocketNotSupported);
+    }
+    // Nodes with none of these use the stock RPC client and minreq.
+    let endpoint = match (unix_socket, rpc_https, socks5_proxy) {
+        (Some(path), _, _) => Some(HttpEndpoint::unix(PathBuf::from(path))),
+        (None, false, None) => None,
+        (None, https, proxy) => {
+            let endpoint = HttpEndpoint::new(toml_node.rpc_host.clone(), toml_node.rpc_port, proxy);
+            Some(match https {
+                true => endpoint.with_https(
+                    toml_node.rpc_https_ca_file.as_deref(),
+                    toml_node.rpc_https_insecure.unwrap_or_default(),
+                    client_auth,
+                )?,
+                false => endpoint,
+            })
+        }
     };
 
     let node: BoxedSyncSendNode = match implementation {
         NodeImplementation::BitcoinCore => Arc::new(BitcoinCoreNode::new(
             node_info,
-            format!("{}:{}", toml_node.rpc_host, toml_node.rpc_port),
+            match unix_socket {
+                Some(_) => toml_node.rpc_host.clone(),
+                None => format!("{}:{}", toml_node.rpc_host, toml_node.rpc_port),
+            },
             parse_rpc_auth(toml_node)?,
             toml_node.use_rest.unwrap_or(DEFAULT_USE_REST),
+            rest_max_step_size,
+            endpoint,
+            zmq_hashblock,
+            rpc_longpoll,
+            resource_telemetry,
+            Duration::from_secs(rpc_timeout),
+            Duration::from_secs(rest_timeout),
         )),
         NodeImplementation::Btcd => {
             if toml_node.rpc_user.is_none() || toml_node.rpc_password.is_none() {
@@ -298,13 +2125,97 @@ fn parse_toml_node(toml_node: &TomlNode) -> Result<BoxedSyncSendNode, ConfigErro
             Arc::new(BtcdNode::new(
                 node_info,
                 format!("{}:{}", toml_node.rpc_host, toml_node.rpc_port),
+                endpoint.unwrap_or_else(|| {
+                    HttpEndpoint::new(toml_node.rpc_host.clone(), toml_node.rpc_port, None)
+                }),
                 toml_node.rpc_user.clone().expect("a rpc_user for btcd"),
                 toml_node
                     .rpc_password
                     .clone()
                     .expect("a rpc_password for btcd"),
+                toml_node.btcd_websocket.unwrap_or(DEFAULT_BTCD_WEBSOCKET),
             ))
         }
+        NodeImplementation::CustomRpc => Arc::new(CustomRpcNode::new(
+            node_info,
+            format!("{}:{}", toml_node.rpc_host, toml_node.rpc_port),
+            endpoint.unwrap_or_else(|| {
+                HttpEndpoint::new(toml_node.rpc_host.clone(), toml_node.rpc_port, None)
+            }),
+            toml_node.rpc_user.clone().unwrap_or_default(),
+            toml_node.rpc_password.clone().unwrap_or_default(),
+            parse_toml_custom_rpc(&toml_node.custom_rpc.clone().unwrap_or_default())?,
+        )),
+        NodeImplementation::Elements => Arc::new(ElementsNode::new(BitcoinCoreNode::new(
+            node_info,
+            match unix_socket {
+                Some(_) => toml_node.rpc_host.clone(),
+                None => format!("{}:{}", toml_node.rpc_host, toml_node.rpc_port),
+            },
+            parse_rpc_auth(toml_node)?,
+            false,
+            rest_max_step_size,
+            endpoint,
+            zmq_hashblock,
+            rpc_longpoll,
+            resource_telemetry,
+            Duration::from_secs(rpc_timeout),
+            Duration::from_secs(rest_timeout),
+        ))),
+        NodeImplementation::Electrum => Arc::new(ElectrumNode::new(
+            node_info,
+            format!("{}:{}", toml_node.rpc_host, toml_node.rpc_port),
+        )),
+        NodeImplementation::Esplora => match toml_node.esplora_url {
+            Some(ref url) => Arc::new(EsploraNode::new(node_info, url.clone())),
+            None => return Err(ConfigError::NoEsploraUrl),
+        },
+        NodeImplementation::Mempool => {
+            let url = toml_node
+                .mempool_url
+                .as_deref()
+                .unwrap_or(DEFAULT_MEMPOOL_URL);
+            if is_https_unsupported(url) {
+                return Err(ConfigError::HttpsNotSupported);
+            }
+            Arc::new(EsploraNode::mempool(node_info, url.to_string()))
+        }
+        NodeImplementation::ForkObserver => match (
+            toml_node.remote_url.as_ref(),
+            toml_node.remote_network_id,
+            toml_node.remote_node_id,
+        ) {
+            (Some(url), Some(network_id), Some(node_id)) => Arc::new(ForkObserverNode::new(
+                node_info,
+                url.clone(),
+                network_id,
+                node_id,
+            )),
+            _ => return Err(ConfigError::NoRemoteNode),
+        },
+        NodeImplementation::LightClient => match toml_node.p2p_network {
+            Some(ref network) => Arc::new(LightClientNode::new(
+                node_info,
+                format!("{}:{}", toml_node.rpc_host, toml_node.rpc_port),
+                network.to_network(),
+            )),
+            None => return Err(ConfigError::NoP2PNetwork),
+        },
+        NodeImplementation::P2P => match toml_node.p2p_network {
+            Some(ref network) => Arc::new(P2PNode::new(
+                node_info,
+                format!("{}:{}", toml_node.rpc_host, toml_node.rpc_port),
+                network.to_network(),
+            )),
+            None => return Err(ConfigError::NoP2PNetwork),
+        },
+        NodeImplementation::Replay => match toml_node.replay_file {
+            Some(ref path) => match ReplayNode::new(node_info, path) {
+                Ok(node) => Arc::new(node),
+                Err(e) => return Err(ConfigError::InvalidReplayFile(path.clone(), e)),
+            },
+            None => return Err(ConfigError::NoReplayFile),
+        },
     };
     Ok(node)
 }
@@ -315,6 +2226,7 @@ mod tests {
     use crate::error::ConfigError;
 
     #[test]
+    #[allow(clippy::expect_fun_call, clippy::bool_assert_comparison)]
     fn load_example_config() {
         use std::env;
 
@@ -329,6 +2241,28 @@ mod tests {
         assert_eq!(cfg.networks.len(), 2);
         assert_eq!(cfg.query_interval, std::time::Duration::from_secs(15));
         assert_eq!(cfg.networks[0].pool_identification.enable, true);
+        let storage_monitoring = cfg
+            .storage_monitoring
+            .expect("example config should have storage_monitoring");
+        assert_eq!(
+            storage_monitoring.max_database_size,
+            Some(1024 * 1024 * 1024)
+        );
+        assert_eq!(storage_monitoring.warn_threshold_percent, 90);
+        assert!(!storage_monitoring.emergency_compaction);
+        assert_eq!(
+            cfg.networks[0]
+                .chain_params
+                .genesis_hash
+                .expect("mainnet genesis hash")
+                .to_string(),
+            "000000000019d6689c085ae165831e934ff763ae46a2a6c172b3f1b60a8ce26f"
+        );
+        assert_eq!(cfg.networks[1].chain_params.genesis_hash, None);
+        assert_eq!(cfg.notifications.max_attempts, 10);
+        assert!(cfg.notifications.sinks.is_empty());
+        assert!(cfg.admin_token.is_none());
+        assert!(cfg.external_observers.is_empty());
     }
 
     #[test]
@@ -374,6 +2308,47 @@ mod tests {
         }
     }
 
+    #[test]
+    fn error_on_unknown_pool_identification_node_test() {
+        let config = |node: u32| {
+            format!(
+                r#"
+            database_path = ""
+            www_path = "./www"
+            query_interval = 15
+            address = "127.0.0.1:2323"
+            rss_base_url = ""
+            footer_html = ""
+
+            [[networks]]
+            id = 1
+            name = ""
+            description = ""
+            min_fork_height = 0
+            max_interesting_heights = 0
+            pool_identification = {{ enable = true, node = {} }}
+
+                [[networks.nodes]]
+                id = 0
+                name = "Node A"
+                description = ""
+                rpc_host = "127.0.0.1"
+                rpc_port = 0
+                rpc_user = ""
+                rpc_password = ""
+        "#,
+                node
+            )
+        };
+
+        let cfg = parse_config(&config(0)).unwrap();
+        assert_eq!(cfg.networks[0].pool_identification.node, Some(0));
+        assert!(matches!(
+            parse_config(&config(1)),
+            Err(ConfigError::UnknownPoolIdentificationNode(1))
+        ));
+    }
+
     #[test]
     fn error_on_duplicate_network_id_test() {
         if let Err(ConfigError::DuplicateNetworkId) = parse_config(
@@ -422,4 +2397,615 @@ mod tests {
             panic!("Test did not error!");
         }
     }
+
+    #[test]
+    fn error_on_zero_storage_check_interval_test() {
+        if let Err(ConfigError::InvalidStorageCheckInterval) = parse_config(
+            r#"
+            database_path = ""
+            www_path = "./www"
+            query_interval = 15
+            address = "127.0.0.1:2323"
+            rss_base_url = ""
+            footer_html = ""
+
+            [storage_monitoring]
+            check_interval = 0
+
+            [[networks]]
+            id = 1
+            name = ""
+            description = ""
+            min_fork_height = 0
+            max_interesting_heights = 0
+
+                [[networks.nodes]]
+                id = 0
+                name = "Node B"
+                description = ""
+                rpc_host = "127.0.0.1"
+                rpc_port = 0
+                rpc_user = ""
+                rpc_password = ""
+        "#,
+        ) {
+            // test OK, as we expect this to error
+        } else {
+            panic!("Test did not error!");
+        }
+    }
+
+    // The sinks use HTTPS URLs.
+    #[cfg(feature = "https")]
+    #[test]
+    fn parse_notification_sinks_test() {
+        let toml_notifications: TomlNotifications = toml::from_str(
+            r#"
+            [[sinks]]
+            name = "hook"
+            kind = "webhook"
+            url = "https://example.com/hook"
+
+            [[sinks]]
+            name = "matrix"
+            kind = "matrix"
+            min_severity = "critical"
+            homeserver = "https://matrix.example.com/"
+            room_id = "!abc:example.com"
+            access_token = "token"
+        "#,
+        )
+        .unwrap();
+        let notifications = parse_toml_notifications(&toml_notifications).unwrap();
+        assert_eq!(
+            notifications.max_attempts,
+            DEFAULT_NOTIFICATION_MAX_ATTEMPTS
+        );
+        assert_eq!(notifications.sinks.len(), 2);
+        assert_eq!(notifications.sinks[0].min_severity, EventSeverity::Warning);
+        assert_eq!(notifications.sinks[1].min_severity, EventSeverity::Critical);
+        match notifications.sinks[1].target {
+            NotificationTarget::Matrix { ref homeserver, .. } => {
+                assert_eq!(homeserver, "https://matrix.example.com")
+            }
+            _ => panic!("expected a matrix sink"),
+        }
+
+        let incomplete: TomlNotifications = toml::from_str(
+            r#"
+            [[sinks]]
+            name = "hook"
+            kind = "webhook"
+        "#,
+        )
+        .unwrap();
+        assert!(matches!(
+            parse_toml_notifications(&incomplete),
+            Err(ConfigError::IncompleteNotificationSink)
+        ));
+    }
+
+    #[test]
+    fn https_notification_sinks_test() {
+        let sinks = |url: &str| -> TomlNotifications {
+            toml::from_str(&format!(
+                r#"
+            [[sinks]]
+            name = "hook"
+            kind = "webhook"
+            url = "{url}"
+
+            [[sinks]]
+            name = "matrix"
+            kind = "matrix"
+            homeserver = "{url}"
+            room_id = "!abc:example.com"
+            access_token = "token"
+        "#,
+                url = url
+            ))
+            .unwrap()
+        };
+        assert!(parse_toml_notifications(&sinks("http://localhost:8080")).is_ok());
+        let https = parse_toml_notifications(&sinks("HTTPS://example.com"));
+        if cfg!(feature = "https") {
+            assert!(https.is_ok());
+        } else {
+            assert!(matches!(https, Err(ConfigError::HttpsNotSupported)));
+        }
+    }
+
+    #[test]
+    fn error_on_invalid_shared_header_store_test() {
+        let config = |share_headers_with: &str| {
+            format!(
+                r#"
+            database_path = ""
+            www_path = "./www"
+            query_interval = 15
+            address = "127.0.0.1:2323"
+            rss_base_url = ""
+            footer_html = ""
+
+            [[networks]]
+            id = 1
+            name = "A"
+            description = ""
+            min_fork_height = 0
+            max_interesting_heights = 0
+            nodes = []
+
+            [[networks]]
+            id = 2
+            name = "B"
+            description = ""
+            min_fork_height = 0
+            max_interesting_heights = 0
+            share_headers_with = {}
+            nodes = []
+        "#,
+                share_headers_with
+            )
+        };
+
+        let cfg = parse_config(&config("1")).expect("sharing headers with network 1 is valid");
+        assert_eq!(cfg.networks[0].header_store, 1);
+        assert_eq!(cfg.networks[1].header_store, 1);
+
+        assert!(matches!(
+            parse_config(&config("3")),
+            Err(ConfigError::InvalidSharedHeaderStore)
+        ));
+    }
+
+    #[cfg(feature = "https")]
+    #[test]
+    fn error_on_incomplete_rpc_https_client_cert_test() {
+        let config = |tls: &str| {
+            format!(
+                r#"
+            database_path = ""
+            www_path = "./www"
+            query_interval = 15
+            address = "127.0.0.1:2323"
+            rss_base_url = ""
+            footer_html = ""
+
+            [[networks]]
+            id = 1
+            name = ""
+            description = ""
+            min_fork_height = 0
+            max_interesting_heights = 0
+
+                [[networks.nodes]]
+                id = 0
+                name = "btcd"
+                description = ""
+                implementation = "btcd"
+                rpc_host = "127.0.0.1"
+                rpc_port = 8334
+                rpc_user = "user"
+                rpc_password = "password"
+                {}
+        "#,
+                tls
+            )
+        };
+
+        parse_config(&config("rpc_https = true")).expect("btcd supports rpc_https");
+        assert!(matches!(
+            parse_config(&config(
+                "rpc_https = true\n rpc_https_client_cert = \"client.pem\""
+            )),
+            Err(ConfigError::InvalidRpcHttpsClientCert)
+        ));
+        assert!(matches!(
+            parse_config(&config(
+                "rpc_https_client_cert = \"client.pem\"\n rpc_https_client_key = \"key.pem\""
+            )),
+            Err(ConfigError::InvalidRpcHttpsClientCert)
+        ));
+    }
+
+    #[test]
+    fn low_resource_profile_test() {
+        let config = |profile: &str| {
+            format!(
+                r#"
+            database_path = ""
+            www_path = "./www"
+            query_interval = 15
+            address = "127.0.0.1:2323"
+            rss_base_url = ""
+            footer_html = ""
+            {}
+
+            [p2p_probes]
+
+            [[networks]]
+            id = 1
+            name = ""
+            description = ""
+            min_fork_height = 0
+            max_interesting_heights = 100
+            nodes = []
+            pool_identification = {{ enable = true }}
+        "#,
+                profile
+            )
+        };
+
+        let cfg = parse_config(&config("")).unwrap();
+        assert_eq!(cfg.profile, Profile::Default);
+        assert_eq!(cfg.query_interval, Duration::from_secs(15));
+        assert!(cfg.p2p_probes.is_some());
+        assert_eq!(
+            cfg.tip_observation_retention,
+            Duration::from_secs(DEFAULT_TIP_OBSERVATION_RETENTION_DAYS * 24 * 60 * 60)
+        );
+
+        let cfg = parse_config(&config("profile = \"low-resource\"")).unwrap();
+        assert_eq!(cfg.profile, Profile::LowResource);
+        assert_eq!(
+            cfg.query_interval,
+            Duration::from_secs(LOW_RESOURCE_MIN_QUERY_INTERVAL)
+        );
+        assert_eq!(
+            cfg.networks[0].max_interesting_heights,
+            LOW_RESOURCE_MAX_INTERESTING_HEIGHTS
+        );
+        assert!(!cfg.networks[0].pool_identification.enable);
+        assert!(cfg.p2p_probes.is_none());
+        assert_eq!(
+            cfg.tip_observation_retention,
+            Duration::from_secs(7 * 24 * 60 * 60)
+        );
+        assert!(cfg.storage_monitoring.unwrap().emergency_compaction);
+
+        let cfg = parse_config(&config(
+            "profile = \"low-resource\"\n tip_observation_retention_days = 1",
+        ))
+        .unwrap();
+        assert_eq!(
+            cfg.tip_observation_retention,
+            Duration::from_secs(24 * 60 * 60)
+        );
+
+        assert!(matches!(
+            parse_config(&config("profile = \"tiny\"")),
+            Err(ConfigError::UnknownProfile(_))
+        ));
+    }
+
+    #[test]
+    fn parse_cors_test() {
+        let config = |cors: &str| {
+            format!(
+                r#"
+            database_path = ""
+            www_path = "./www"
+            query_interval = 15
+            address = "127.0.0.1:2323"
+            footer_html = ""
+
+            [cors]
+            {}
+
+            [[networks]]
+            id = 1
+            name = ""
+            description = ""
+            min_fork_height = 0
+            max_interesting_heights = 100
+            nodes = []
+        "#,
+                cors
+            )
+        };
+
+        let cors = parse_config(&config(
+            "allowed_origins = [\"https://dashboard.example.com\", \"http://localhost:8080\"]\nmax_age = 600",
+        ))
+        .unwrap()
+        .cors
+        .unwrap();
+        assert_eq!(cors.allowed_methods, vec!["GET".to_string()]);
+        assert!(cors.allowed_headers.is_empty());
+        assert_eq!(cors.max_age, Some(Duration::from_secs(600)));
+
+        assert!(parse_config(&config("allowed_origins = [\"*\"]")).is_ok());
+        for invalid in [
+            "allowed_origins = []",
+            "allowed_origins = [\"example.com\"]",
+            "allowed_origins = [\"https://example.com/\"]",
+            "allowed_origins = [\"*\"]\nallowed_methods = [\"G T\"]",
+            "allowed_origins = [\"*\"]\nallowed_headers = [\"x:y\"]",
+        ] {
+            assert!(
+                matches!(
+                    parse_config(&config(invalid)),
+                    Err(ConfigError::InvalidCors(_))
+                ),
+                "{}",
+                invalid
+            );
+        }
+    }
+
+    #[test]
+    fn parse_custom_rpc_test() {
+        let config = |custom_rpc: &str| {
+            format!(
+                r#"
+            database_path = ""
+            www_path = "./www"
+            query_interval = 15
+            address = "127.0.0.1:2323"
+            footer_html = ""
+
+            [[networks]]
+            id = 1
+            name = ""
+            description = ""
+            min_fork_height = 0
+            max_interesting_heights = 100
+
+                [[networks.nodes]]
+                id = 0
+                name = "Node A"
+                description = ""
+                rpc_host = "127.0.0.1"
+                rpc_port = 8332
+                implementation = "customrpc"
+
+                    [networks.nodes.custom_rpc]
+                    {}
+        "#,
+                custom_rpc
+            )
+        };
+
+        let network = &parse_config(&config(
+            "tips_method = \"listtips\"\ntips_path = \"tips\"\nheader_params = '[\"$hash\"]'",
+        ))
+        .unwrap()
+        .networks[0];
+        assert_eq!(
+            network.nodes[0].info().implementation,
+            NodeImplementation::CustomRpc.to_string()
+        );
+        assert!(matches!(
+            parse_config(&config("header_params = '\"$hash\"'")),
+            Err(ConfigError::InvalidCustomRpcParams(_))
+        ));
+    }
+
+    #[test]
+    fn parse_chain_kind_test() {
+        let config = |network: &str, implementation: &str| {
+            format!(
+                r#"
+            database_path = ""
+            www_path = "./www"
+            query_interval = 15
+            address = "127.0.0.1:2323"
+            footer_html = ""
+
+            [[networks]]
+            id = 1
+            name = ""
+            description = ""
+            min_fork_height = 0
+            max_interesting_heights = 100
+            {}
+
+                [[networks.nodes]]
+                id = 0
+                name = "Node A"
+                description = ""
+                rpc_host = "127.0.0.1"
+                rpc_port = 7041
+                rpc_user = "user"
+                rpc_password = "password"
+                implementation = "{}"
+        "#,
+                network, implementation
+            )
+        };
+
+        let cfg = parse_config(&config("chain_kind = \"elements\"", "elements")).unwrap();
+        assert_eq!(cfg.networks[0].chain_kind, ChainKind::Elements);
+        assert_eq!(
+            cfg.networks[0].nodes[0].info().implementation,
+            NodeImplementation::Elements.to_string()
+        );
+        let cfg = parse_config(&config("", "bitcoincore")).unwrap();
+        assert_eq!(cfg.networks[0].chain_kind, ChainKind::Bitcoin);
+
+        assert!(matches!(
+            parse_config(&config("chain_kind = \"liquid\"", "elements")),
+            Err(ConfigError::UnknownChainKind(_))
+        ));
+        assert!(matches!(
+            parse_config(&config("chain_kind = \"elements\"", "bitcoincore")),
+            Err(ConfigError::NodeNotOnChainKind(_, _))
+        ));
+        assert!(matches!(
+            parse_config(&config("", "elements")),
+            Err(ConfigError::NodeNotOnChainKind(_, _))
+        ));
+        assert!(matches!(
+            parse_config(&config(
+                "chain_kind = \"elements\"\npool_identification = { enable = true }",
+                "elements"
+            )),
+            Err(ConfigError::NotSupportedOnElements(_))
+        ));
+    }
+
+    #[test]
+    fn parse_computed_fields_test() {
+        let toml_config: TomlConfig = toml::from_str(
+            r#"
+            database_path = ""
+            www_path = "./www"
+            query_interval = 15
+            address = "127.0.0.1:2323"
+            footer_html = ""
+            networks = []
+
+            [[computed_fields]]
+            name = "is_taproot_signalling"
+            target = "block"
+            expression = "bit(version, 2)"
+
+            [[computed_fields]]
+            name = "lagging"
+            target = "node"
+            expression = "!reachable || height < 100"
+        "#,
+        )
+        .unwrap();
+        let computed_fields = parse_toml_computed_fields(&toml_config.computed_fields).unwrap();
+        assert_eq!(computed_fields.len(), 2);
+        assert_eq!(computed_fields[0].target, ComputedFieldTarget::Block);
+        assert_eq!(computed_fields[1].target, ComputedFieldTarget::Node);
+
+        // 'reachable' is not available for blocks
+        let invalid = Some(vec![TomlComputedField {
+            name: String::from("invalid"),
+            target: ComputedFieldTarget::Block,
+            expression: String::from("reachable"),
+        }]);
+        assert!(matches!(
+            parse_toml_computed_fields(&invalid),
+            Err(ConfigError::InvalidComputedField)
+        ));
+    }
+
+    #[test]
+    fn parse_chain_params_pow_test() {
+        let params = ChainParams::default();
+        assert!(params.validate_pow);
+        assert_eq!(params.pow_limit, None);
+
+        let params = parse_toml_chain_params(&TomlChainParams {
+            validate_pow: Some(false),
+            pow_limit: Some(String::from(
+                "00000000ffff0000000000000000000000000000000000000000000000000000",
+            )),
+            ..Default::default()
+        })
+        .unwrap();
+        assert!(!params.validate_pow);
+        assert_eq!(params.pow_limit, Some(Target::MAX_ATTAINABLE_MAINNET));
+
+        for pow_limit in ["ffff", "not hex"].iter() {
+            assert!(matches!(
+                parse_toml_chain_params(&TomlChainParams {
+                    pow_limit: Some(pow_limit.to_string()),
+                    ..Default::default()
+                }),
+                Err(ConfigError::InvalidPowLimit)
+            ));
+        }
+    }
+
+    #[test]
+    fn parse_canaries_test() {
+        let canary = |name: &str, condition: &str, severity: Option<&str>| TomlCanary {
+            name: name.to_string(),
+            condition: condition.to_string(),
+            severity: severity.map(String::from),
+        };
+        let canaries = parse_toml_canaries(&[
+            canary("version-floor", "height >= 1000 && version < 4", None),
+            canary(
+                "difficulty-jump",
+                "difficulty_change_percent > 25",
+                Some("critical"),
+            ),
+        ])
+        .unwrap();
+        assert_eq!(canaries.len(), 2);
+        assert_eq!(canaries[0].severity, DEFAULT_CANARY_SEVERITY);
+        assert_eq!(canaries[1].severity, EventSeverity::Critical);
+
+        for invalid in [
+            // not a boolean
+            vec![canary("a", "height + 1", None)],
+            // unknown variable
+            vec![canary("a", "reachable", None)],
+            vec![canary("a", "height > 1", Some("loud"))],
+            vec![
+                canary("a", "height > 1", None),
+                canary("a", "height > 2", None),
+            ],
+        ]
+        .iter()
+        {
+            assert!(matches!(
+                parse_toml_canaries(invalid),
+                Err(ConfigError::InvalidCanary(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn parse_own_mining_test() {
+        use bitcoincore_rpc::bitcoin::absolute::LockTime;
+        use bitcoincore_rpc::bitcoin::transaction::Version;
+        use bitcoincore_rpc::bitcoin::{Amount, TxIn, TxOut};
+
+        let own_mining = parse_toml_own_mining(&TomlOwnMining {
+            coinbase_tags: Some(vec![String::from("/OurPool/")]),
+            payout_scripts: Some(vec![String::from(
+                "0014751e76e8199196d454941c45d1b3a323f1433bd6",
+            )]),
+            stale_block_severity: None,
+        })
+        .unwrap();
+        assert_eq!(own_mining.stale_block_severity, EventSeverity::Critical);
+
+        let coinbase = |script_sig: &[u8], script_pubkey: ScriptBuf| Transaction {
+            version: Version::ONE,
+            lock_time: LockTime::ZERO,
+            input: vec![TxIn {
+                script_sig: ScriptBuf::from_bytes(script_sig.to_vec()),
+                ..Default::default()
+            }],
+            output: vec![TxOut {
+                value: Amount::from_sat(0),
+                script_pubkey,
+            }],
+        };
+        let our_script = own_mining.payout_scripts[0].clone();
+        assert!(own_mining.matches(&coinbase(b"\x03abc/OurPool/xyz", ScriptBuf::new())));
+        assert!(own_mining.matches(&coinbase(b"\x03abc", our_script)));
+        assert!(!own_mining.matches(&coinbase(b"\x03abc/Other/", ScriptBuf::new())));
+
+        for invalid in [
+            TomlOwnMining {
+                coinbase_tags: None,
+                payout_scripts: None,
+                stale_block_severity: None,
+            },
+            TomlOwnMining {
+                coinbase_tags: None,
+                payout_scripts: Some(vec![String::from("not hex")]),
+                stale_block_severity: None,
+            },
+            TomlOwnMining {
+                coinbase_tags: Some(vec![String::from("/OurPool/")]),
+                payout_scripts: None,
+                stale_block_severity: Some(String::from("Critical")),
+            },
+        ] {
+            assert!(matches!(
+                parse_toml_own_mining(&invalid),
+                Err(ConfigError::InvalidOwnMining)
+            ));
+        }
+    }
 }
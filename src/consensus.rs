@@ -0,0 +1,219 @@
+use std::convert::Infallible;
+
+use log::info;
+use tokio::time::{interval, Duration};
+use warp::Reply;
+
+use crate::domain::{EventKind, EventSeverity};
+use crate::events::EventLog;
+use crate::rss::response_unknown_network;
+use crate::types::{
+    now_timestamp, Caches, ConsensusJsonResponse, ConsensusTipJson, NetworkJson, NodeDataJson,
+    Versioned,
+};
+
+// A partition has to last this long before an event is emitted, so the
+// nodes briefly disagreeing while a new block propagates aren't reported.
+const MIN_PARTITION_DURATION: u64 = 5 * 60; // seconds
+
+// The consensus tip is the active tip of the reachable nodes with the
+// highest sum of node trust, so nodes with a low trust (e.g. third-party
+// explorers) can't outvote trusted nodes. Ties are broken by the height.
+// Shadow nodes are ignored.
+pub fn consensus<'a>(nodes: impl Iterator<Item = &'a NodeDataJson>) -> ConsensusJsonResponse {
+    let mut tips: Vec<ConsensusTipJson> = vec![];
+    for node in nodes.filter(|node| node.reachable && !node.shadow) {
+        let active_tip = match node.tips.iter().rfind(|tip| tip.status == "active") {
+            Some(tip) => tip,
+            None => continue,
+        };
+        match tips.iter_mut().find(|tip| tip.hash == active_tip.hash) {
+            Some(tip) => {
+                tip.weight += node.trust;
+                tip.node_ids.push(node.id);
+            }
+            None => tips.push(ConsensusTipJson {
+                hash: active_tip.hash.clone(),
+                height: active_tip.height,
+                weight: node.trust,
+                node_ids: vec![node.id],
+            }),
+        }
+    }
+    tips.sort_by(|a, b| b.weight.total_cmp(&a.weight).then(b.height.cmp(&a.height)));
+
+    let total_weight: f64 = tips.iter().map(|tip| tip.weight).sum();
+    let consensus_tip = tips.first().filter(|tip| tip.weight > 0.0).cloned();
+    let agreement = consensus_tip.as_ref().map(|tip| tip.weight / total_weight);
+    ConsensusJsonResponse {
+        partitioned: tips.len() > 1 && agreement.is_none_or(|agreement| agreement <= 0.5),
+        consensus_tip,
+        agreement,
+        tips,
+    }
+}
+
+// Tracks since when the network is partitioned.
+#[derive(Default)]
+struct PartitionTracker {
+    since: Option<u64>,
+    reported: bool,
+}
+
+impl PartitionTracker {
+    // Returns true once per partition, when it lasted MIN_PARTITION_DURATION.
+    fn update(&mut self, partitioned: bool, now: u64) -> bool {
+        if !partitioned {
+            *self = PartitionTracker::default();
+            return false;
+        }
+        let since = *self.since.get_or_insert(now);
+        if self.reported || now.saturating_sub(since) < MIN_PARTITION_DURATION {
+            return false;
+        }
+        self.reported = true;
+        true
+    }
+}
+
+// Checks the consensus of the network each query interval and emits a
+// network-partition event when no tip has the majority of the node trust.
+pub async fn run(
+    network_id: u32,
+    network_name: String,
+    query_interval: Duration,
+    caches: Caches,
+    event_log: EventLog,
+) {
+    let mut interval = interval(query_interval);
+    let mut tracker = PartitionTracker::default();
+    loop {
+        interval.tick().await;
+        let result = match caches.lock().await.get(&network_id) {
+            Some(cache) => consensus(cache.node_data.values()),
+            None => continue,
+        };
+        let was_partitioned = tracker.since.is_some();
+        if tracker.update(result.partitioned, now_timestamp()) {
+            let tips: Vec<String> = result
+                .tips
+                .iter()
+                .map(|tip| {
+                    format!(
+                        "{} at height {} (trust {:.2}, nodes {:?})",
+                        tip.hash, tip.height, tip.weight, tip.node_ids
+                    )
+                })
+                .collect();
+            event_log
+                .emit(
+                    network_id,
+                    EventKind::NetworkPartition,
+                    EventSeverity::Warning,
+                    format!("Nodes on {} are partitioned", network_name),
+                    format!(
+                        "The nodes on {} disagree on the active tip and no tip has the majority of the node trust: {}.",
+                        network_name,
+                        tips.join(", "),
+                    ),
+                )
+                .await;
+        } else if was_partitioned && !result.partitioned {
+            info!("The nodes on network '{}' agree again", network_name);
+        }
+    }
+}
+
+pub async fn consensus_response(
+    network_id: u32,
+    caches: Caches,
+    network_infos: Vec<NetworkJson>,
+) -> Result<Box<dyn Reply>, Infallible> {
+    let caches_locked = caches.lock().await;
+    match caches_locked.get(&network_id) {
+        Some(cache) => Ok(Box::new(warp::reply::json(&Versioned::new(consensus(
+            cache.node_data.values(),
+        ))))),
+        None => Ok(Box::new(response_unknown_network(network_infos))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::NodeInfo;
+    use crate::retry::RetryPolicy;
+    use crate::types::TipInfoJson;
+
+    fn node(id: u32, trust: f64, hash: &str, height: u64) -> NodeDataJson {
+        let mut node = NodeDataJson::new(
+            NodeInfo {
+                id,
+                name: String::new(),
+                description: String::new(),
+                implementation: String::new(),
+                trust,
+                shadow: false,
+                purge_after: None,
+                retry: RetryPolicy::default(),
+            },
+            &[],
+            String::new(),
+            0,
+            true,
+        );
+        node.tips = vec![TipInfoJson {
+            hash: hash.to_string(),
+            status: "active".to_string(),
+            height,
+            chainwork: None,
+        }];
+        node
+    }
+
+    #[test]
+    fn test_partition_tracker() {
+        let mut tracker = PartitionTracker::default();
+        assert!(!tracker.update(true, 1000));
+        assert!(!tracker.update(true, 1000 + MIN_PARTITION_DURATION - 1));
+        assert!(tracker.update(true, 1000 + MIN_PARTITION_DURATION));
+        // reported once
+        assert!(!tracker.update(true, 2000 + MIN_PARTITION_DURATION));
+        // a brief partition isn't reported
+        assert!(!tracker.update(false, 3000));
+        assert!(!tracker.update(true, 3000));
+        assert!(!tracker.update(false, 3060));
+        assert!(!tracker.update(true, 3000 + MIN_PARTITION_DURATION));
+    }
+
+    #[test]
+    fn test_consensus() {
+        // two untrusted explorers can't outvote a trusted node
+        let nodes = [
+            node(0, 1.0, "a", 100),
+            node(1, 0.3, "b", 101),
+            node(2, 0.3, "b", 101),
+        ];
+        let result = consensus(nodes.iter());
+        let tip = result.consensus_tip.unwrap();
+        assert_eq!(tip.hash, "a");
+        assert_eq!(tip.node_ids, vec![0]);
+        assert!(!result.partitioned);
+
+        let nodes = [node(0, 1.0, "a", 100), node(1, 1.0, "b", 101)];
+        let result = consensus(nodes.iter());
+        assert_eq!(result.consensus_tip.unwrap().hash, "b");
+        assert!(result.partitioned);
+
+        // shadow nodes are ignored
+        let mut shadow = node(2, 1.0, "b", 101);
+        shadow.shadow = true;
+        let result = consensus([node(0, 1.0, "a", 100), shadow].iter());
+        assert_eq!(result.tips.len(), 1);
+        assert!(!result.partitioned);
+
+        let mut unreachable = node(0, 1.0, "a", 100);
+        unreachable.reachable = false;
+        assert!(consensus([unreachable].iter()).consensus_tip.is_none());
+    }
+}
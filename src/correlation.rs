@@ -0,0 +1,163 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use crate::domain::{Event, EventKind};
+
+// Events correlated within a window. The first event of the window is
+// emitted right away, the later ones are suppressed and emitted as one
+// aggregated event once the window ends.
+struct Window {
+    opened: u64,
+    length: u64,
+    suppressed: u32,
+    // The most recent suppressed event, with the highest severity of the
+    // suppressed events.
+    last: Option<Event>,
+}
+
+// Correlates events of the same network and correlation key, e.g. the
+// reachability changes of a flapping node, so sinks and the event log
+// aren't flooded.
+#[derive(Default)]
+pub struct Correlator {
+    windows: HashMap<EventKind, Duration>,
+    open: HashMap<(u32, String), Window>,
+}
+
+impl Correlator {
+    pub fn new(windows: HashMap<EventKind, Duration>) -> Self {
+        Correlator {
+            windows,
+            open: HashMap::new(),
+        }
+    }
+
+    // Returns true if the event should be emitted now. Otherwise it's
+    // suppressed and part of the aggregated event of its window.
+    pub fn correlate(&mut self, event: &Event, key: String) -> bool {
+        let length = match self.windows.get(&event.kind) {
+            Some(length) if !length.is_zero() => length.as_secs(),
+            _ => return true,
+        };
+        match self.open.get_mut(&(event.network_id, key.clone())) {
+            Some(window) if event.timestamp < window.opened + window.length => {
+                let mut last = event.clone();
+                if let Some(ref previous) = window.last {
+                    last.severity = last.severity.max(previous.severity.clone());
+                }
+                window.suppressed += 1;
+                window.last = Some(last);
+                false
+            }
+            // An expired window without suppressed events is replaced.
+            _ => {
+                self.open.insert(
+                    (event.network_id, key),
+                    Window {
+                        opened: event.timestamp,
+                        length,
+                        suppressed: 0,
+                        last: None,
+                    },
+                );
+                true
+            }
+        }
+    }
+
+    // Closes the windows ended by now and returns the aggregated events of
+    // the ones with suppressed events.
+    pub fn expired(&mut self, now: u64) -> Vec<Event> {
+        let mut aggregated = vec![];
+        self.open.retain(|_, window| {
+            if now < window.opened + window.length {
+                return true;
+            }
+            if let Some(mut event) = window.last.take() {
+                event.title = format!(
+                    "{} ({} similar events within {} minutes)",
+                    event.title,
+                    window.suppressed + 1,
+                    window.length / 60
+                );
+                event.description = format!(
+                    "{} This event aggregates {} similar events, the first one was emitted separately.",
+                    event.description,
+                    window.suppressed + 1
+                );
+                event.timestamp = now;
+                aggregated.push(event);
+            }
+            false
+        });
+        aggregated
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::EventSeverity;
+
+    fn event(timestamp: u64, kind: EventKind, severity: EventSeverity) -> Event {
+        Event {
+            id: 0,
+            network_id: 1,
+            timestamp,
+            title: format!("{}", kind),
+            kind,
+            severity,
+            description: String::new(),
+            sequence: 0,
+        }
+    }
+
+    #[test]
+    fn test_correlator() {
+        let mut correlator = Correlator::new(HashMap::from([
+            (EventKind::NodeUnreachable, Duration::from_secs(3600)),
+            (EventKind::NodeReachable, Duration::from_secs(3600)),
+        ]));
+        let key = || String::from("node-0");
+
+        // a node flapping ten times within an hour
+        assert!(correlator.correlate(
+            &event(0, EventKind::NodeUnreachable, EventSeverity::Warning),
+            key()
+        ));
+        for i in 1..20 {
+            let e = if i % 2 == 0 {
+                event(i * 60, EventKind::NodeUnreachable, EventSeverity::Warning)
+            } else {
+                event(i * 60, EventKind::NodeReachable, EventSeverity::Info)
+            };
+            assert!(!correlator.correlate(&e, key()));
+        }
+        // other keys and kinds without a window aren't correlated
+        assert!(correlator.correlate(
+            &event(60, EventKind::NodeUnreachable, EventSeverity::Warning),
+            String::from("node-1")
+        ));
+        assert!(correlator.correlate(
+            &event(60, EventKind::StaleBlock, EventSeverity::Info),
+            key()
+        ));
+
+        assert!(correlator.expired(3599).is_empty());
+        let aggregated = correlator.expired(3600);
+        assert_eq!(aggregated.len(), 1);
+        // the kind of the last event, the highest severity
+        assert_eq!(aggregated[0].kind, EventKind::NodeReachable);
+        assert_eq!(aggregated[0].severity, EventSeverity::Warning);
+        assert_eq!(
+            aggregated[0].title,
+            "node-reachable (20 similar events within 60 minutes)"
+        );
+
+        // a new window starts with the next event
+        assert!(correlator.correlate(
+            &event(4000, EventKind::NodeUnreachable, EventSeverity::Warning),
+            key()
+        ));
+    }
+}
@@ -0,0 +1,328 @@
+use std::collections::BTreeMap;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use bitcoincore_rpc::bitcoin::blockdata::block::Header;
+use bitcoincore_rpc::bitcoin::{self, BlockHash, Transaction};
+use serde_json::Value;
+use tokio::task;
+
+use crate::domain::{ChainTip, NodeInfo};
+use crate::error::{FetchError, JsonRPCError};
+use crate::http::HttpEndpoint;
+use crate::node::{Node, RestStepSize, DEFAULT_REST_MAX_STEP_SIZE};
+
+// The defaults match Bitcoin Core, so only what differs has to be
+// configured.
+pub const DEFAULT_TIPS_METHOD: &str = "getchaintips";
+pub const DEFAULT_HEADER_METHOD: &str = "getblockheader";
+pub const DEFAULT_HEADER_PARAMS: &str = r#"["$hash", false]"#;
+pub const DEFAULT_BLOCK_HASH_METHOD: &str = "getblockhash";
+pub const DEFAULT_BLOCK_HASH_PARAMS: &str = r#"["$height"]"#;
+pub const DEFAULT_VERSION_METHOD: &str = "getnetworkinfo";
+pub const DEFAULT_VERSION_PATH: &str = "subversion";
+
+// Placeholders in the params, replaced by the hash or height queried.
+const PLACEHOLDER_HASH: &str = "$hash";
+const PLACEHOLDER_HEIGHT: &str = "$height";
+
+// An RPC method and where its result is found in the response.
+#[derive(Debug, Clone)]
+pub struct Method {
+    pub name: String,
+    pub params: Vec<Value>,
+    /// Dot-separated path to the value in the result, e.g. "tips" or
+    /// "header.hex". Numeric segments index arrays. Empty for the result
+    /// itself.
+    pub path: String,
+}
+
+// The RPC methods of the node and the fields of its chain tips.
+#[derive(Debug, Clone)]
+pub struct CustomRpc {
+    /// Returns the array of chain tips.
+    pub tips: Method,
+    pub tip_height: String,
+    pub tip_hash: String,
+    pub tip_branchlen: String,
+    pub tip_status: String,
+    /// Maps the tip statuses of the node to the Bitcoin Core ones, e.g.
+    /// main to active. Statuses not in the map are taken as they are.
+    pub statuses: BTreeMap<String, String>,
+    /// Returns the hex encoded 80-byte header of the block $hash.
+    pub header: Method,
+    /// Returns the hash of the active block at $height.
+    pub block_hash: Method,
+    /// Returns the user agent of the node.
+    pub version: Method,
+}
+
+// The value at the dot-separated path.
+fn lookup<'a>(value: &'a Value, path: &str) -> Option<&'a Value> {
+    if path.is_empty() {
+        return Some(value);
+    }
+    path.split('.')
+        .try_fold(value, |value, segment| match value {
+            Value::Array(values) => values.get(segment.parse::<usize>().ok()?),
+            _ => value.get(segment),
+        })
+}
+
+fn substitute(params: &[Value], hash: Option<&BlockHash>, height: Option<u64>) -> Vec<Value> {
+    params
+        .iter()
+        .map(|param| match (param.as_str(), hash, height) {
+            (Some(PLACEHOLDER_HASH), Some(hash), _) => Value::from(hash.to_string()),
+            (Some(PLACEHOLDER_HEIGHT), _, Some(height)) => Value::from(height),
+            _ => param.clone(),
+        })
+        .collect()
+}
+
+fn data_error(method: &Method, message: String) -> FetchError {
+    FetchError::DataError(format!("the result of {}: {}", method.name, message))
+}
+
+fn string_at<'a>(method: &Method, value: &'a Value, path: &str) -> Result<&'a str, FetchError> {
+    lookup(value, path)
+        .and_then(Value::as_str)
+        .ok_or_else(|| data_error(method, format!("no string at '{}'", path)))
+}
+
+fn u64_at(method: &Method, value: &Value, path: &str) -> Result<u64, FetchError> {
+    lookup(value, path)
+        .and_then(Value::as_u64)
+        .ok_or_else(|| data_error(method, format!("no integer at '{}'", path)))
+}
+
+impl CustomRpc {
+    // Parses the tips at the path of the tips method.
+    fn parse_tips(&self, tips: &Value) -> Result<Vec<ChainTip>, FetchError> {
+        let tips = tips
+            .as_array()
+            .ok_or_else(|| data_error(&self.tips, format!("no array at '{}'", self.tips.path)))?;
+        tips.iter()
+            .map(|tip| {
+                let status = string_at(&self.tips, tip, &self.tip_status)?;
+                let status = self
+                    .statuses
+                    .get(status)
+                    .map(String::as_str)
+                    .unwrap_or(status);
+                let hash = string_at(&self.tips, tip, &self.tip_hash)?;
+                BlockHash::from_str(hash).map_err(|e| {
+                    data_error(&self.tips, format!("invalid tip hash '{}': {}", hash, e))
+                })?;
+                Ok(ChainTip {
+                    height: u64_at(&self.tips, tip, &self.tip_height)?,
+                    hash: hash.to_string(),
+                    // Not all node software reports the branch length.
+                    branchlen: lookup(tip, &self.tip_branchlen)
+                        .and_then(Value::as_u64)
+                        .unwrap_or_default() as usize,
+                    status: status.to_string().into(),
+                })
+            })
+            .collect()
+    }
+}
+
+// A node whose RPC methods and result fields are configured, for node
+// software without a dedicated backend, e.g. forks of Bitcoin Core with
+// renamed methods.
+pub struct CustomRpcNode {
+    info: NodeInfo,
+    rpc_url: String,
+    endpoint: Arc<HttpEndpoint>,
+    rpc_user: String,
+    rpc_password: String,
+    custom_rpc: CustomRpc,
+    rest_step_size: RestStepSize,
+}
+
+impl CustomRpcNode {
+    pub fn new(
+        info: NodeInfo,
+        rpc_url: String,
+        endpoint: HttpEndpoint,
+        rpc_user: String,
+        rpc_password: String,
+        custom_rpc: CustomRpc,
+    ) -> Self {
+        CustomRpcNode {
+            info,
+            rpc_url,
+            endpoint: Arc::new(endpoint),
+            rpc_user,
+            rpc_password,
+            custom_rpc,
+            rest_step_size: RestStepSize::new(DEFAULT_REST_MAX_STEP_SIZE),
+        }
+    }
+
+    // Calls the method and returns the value at its path.
+    async fn call(
+        &self,
+        method: &Method,
+        hash: Option<&BlockHash>,
+        height: Option<u64>,
+    ) -> Result<Value, FetchError> {
+        let endpoint = self.endpoint.clone();
+        let user = self.rpc_user.clone();
+        let password = self.rpc_password.clone();
+        let name = method.name.clone();
+        let params = substitute(&method.params, hash, height);
+        let result = task::spawn_blocking(move || {
+            crate::jsonrpc::call(&endpoint, user, password, &name, params)
+        })
+        .await?
+        .map_err(FetchError::CustomRPC)?;
+        lookup(&result, &method.path)
+            .cloned()
+            .ok_or_else(|| data_error(method, format!("nothing at '{}'", method.path)))
+    }
+}
+
+#[async_trait]
+impl Node for CustomRpcNode {
+    fn info(&self) -> NodeInfo {
+        self.info.clone()
+    }
+
+    fn use_rest(&self) -> bool {
+        false
+    }
+
+    fn rest_step_size(&self) -> &RestStepSize {
+        &self.rest_step_size
+    }
+
+    fn rpc_url(&self) -> String {
+        self.rpc_url.clone()
+    }
+
+    fn host(&self) -> Option<String> {
+        Some(self.rpc_url.clone()).filter(|_| !self.endpoint.proxied())
+    }
+
+    async fn version(&self) -> Result<String, FetchError> {
+        let method = &self.custom_rpc.version;
+        match self.call(method, None, None).await? {
+            Value::String(version) => Ok(version),
+            _ => Err(data_error(
+                method,
+                String::from("the version isn't a string"),
+            )),
+        }
+    }
+
+    async fn block_header(&self, hash: &BlockHash) -> Result<Header, FetchError> {
+        let method = &self.custom_rpc.header;
+        let header_hex = match self.call(method, Some(hash), None).await? {
+            Value::String(header_hex) => header_hex,
+            _ => {
+                return Err(data_error(
+                    method,
+                    String::from("the header isn't a string"),
+                ))
+            }
+        };
+        let bytes = hex::decode(&header_hex)
+            .map_err(|e| data_error(method, format!("invalid header hex: {}", e)))?;
+        bitcoin::consensus::deserialize(&bytes)
+            .map_err(|e| data_error(method, format!("invalid header: {}", e)))
+    }
+
+    async fn coinbase(&self, _hash: &BlockHash) -> Result<Transaction, FetchError> {
+        Err(FetchError::CustomRPC(JsonRPCError::NotImplemented))
+    }
+
+    async fn block_hash(&self, height: u64) -> Result<BlockHash, FetchError> {
+        let method = &self.custom_rpc.block_hash;
+        match self.call(method, None, Some(height)).await? {
+            Value::String(hash) => BlockHash::from_str(&hash)
+                .map_err(|e| data_error(method, format!("invalid block hash: {}", e))),
+            _ => Err(data_error(method, String::from("the hash isn't a string"))),
+        }
+    }
+
+    async fn tips(&self) -> Result<Vec<ChainTip>, FetchError> {
+        Ok(self.tips_raw().await?.0)
+    }
+
+    async fn tips_raw(&self) -> Result<(Vec<ChainTip>, Value), FetchError> {
+        let raw = self.call(&self.custom_rpc.tips, None, None).await?;
+        Ok((self.custom_rpc.parse_tips(&raw)?, raw))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::ChainTipStatus;
+    use serde_json::json;
+
+    fn method(name: &str, path: &str) -> Method {
+        Method {
+            name: name.to_string(),
+            params: vec![],
+            path: path.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_parse_tips() {
+        let custom_rpc = CustomRpc {
+            tips: method("listtips", "result.tips"),
+            tip_height: "height".to_string(),
+            tip_hash: "id.hash".to_string(),
+            tip_branchlen: "branchlen".to_string(),
+            tip_status: "state".to_string(),
+            statuses: vec![("main".to_string(), "active".to_string())]
+                .into_iter()
+                .collect(),
+            header: method("header", ""),
+            block_hash: method("hash", ""),
+            version: method("version", ""),
+        };
+        let (aa, bb) = (format!("{:064x}", 0xaa), format!("{:064x}", 0xbb));
+        let raw = json!([
+            {"height": 10, "id": {"hash": aa}, "state": "main"},
+            {"height": 8, "id": {"hash": bb}, "branchlen": 2, "state": "invalid"},
+        ]);
+        let tips = custom_rpc.parse_tips(&raw).unwrap();
+        assert_eq!(tips.len(), 2);
+        assert_eq!(tips[0].height, 10);
+        assert_eq!(tips[0].hash, aa);
+        assert_eq!(tips[0].branchlen, 0);
+        assert_eq!(tips[0].status, ChainTipStatus::Active);
+        assert_eq!(tips[1].branchlen, 2);
+        assert_eq!(tips[1].status, ChainTipStatus::Invalid);
+
+        // a tip without a hash
+        let raw = json!([{"height": 10, "state": "main"}]);
+        assert!(custom_rpc.parse_tips(&raw).is_err());
+        // a tip with a malformed hash
+        let raw = json!([{"height": 10, "id": {"hash": "aa"}, "state": "main"}]);
+        assert!(custom_rpc.parse_tips(&raw).is_err());
+    }
+
+    #[test]
+    fn test_lookup_and_substitute() {
+        let value = json!({"a": [{"b": 1}, {"b": 2}]});
+        assert_eq!(lookup(&value, "a.1.b"), Some(&json!(2)));
+        assert_eq!(lookup(&value, ""), Some(&value));
+        assert_eq!(lookup(&value, "a.x"), None);
+
+        let params: Vec<Value> = serde_json::from_str(DEFAULT_HEADER_PARAMS).unwrap();
+        let hash = BlockHash::from_str(&format!("{:064x}", 1)).unwrap();
+        assert_eq!(
+            substitute(&params, Some(&hash), None),
+            vec![json!(format!("{:064x}", 1)), json!(false)]
+        );
+        let params: Vec<Value> = serde_json::from_str(DEFAULT_BLOCK_HASH_PARAMS).unwrap();
+        assert_eq!(substitute(&params, None, Some(7)), vec![json!(7)]);
+    }
+}
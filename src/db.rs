@@ -1,4 +1,4 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 
 use petgraph::graph::DiGraph;
 use petgraph::graph::NodeIndex;
@@ -8,52 +8,1773 @@ use bitcoincore_rpc::bitcoin::BlockHash;
 
 use log::{debug, info, warn};
 
+use rusqlite::{params, Connection, Row};
+use tokio::sync::MutexGuard;
+
+use crate::domain::{
+    BlockStats, ChainKind, ChainTip, ChainTipStatus, Event, EventKind, EventSeverity, FirstReport,
+    HeaderInfo, HeaderSource, Incident, IncidentRecording, NodeInfo, Observer, QueuedNotification,
+    Rejection, Reorg, RetiredNode, SeenBy, Sighting, SuppressedTip, TipObservation, TreeInfo,
+};
+use crate::elements::ElementsHeader;
 use crate::error::DbError;
-use crate::types::{Db, HeaderInfo, TreeInfo};
+use crate::headertree;
+use crate::trace;
+use crate::types::Db;
+
+// Version of the database schema, stored as SQLite user_version. Bump it
+// and add a migration to setup_db() when changing the schema. Databases
+// created before the schema was versioned have user_version 0 and the same
+// layout as version 1.
+//
+// Version 2 adds the tip_observations table.
+// Version 3 adds the own column to the headers table.
+// Version 4 adds an index on the hashes of the tip observations.
+// Version 5 adds the external_sightings table.
+// Version 6 adds the nodes table.
+// Version 7 adds the incidents and incident_recordings tables.
+// Version 8 adds the sequence column to the events table.
+// Version 9 adds the suppressed_tips table.
+// Version 10 adds the source column to the headers table.
+// Version 11 adds the node_tips table.
+// Version 12 adds the reorgs table.
+// Version 13 adds the block_stats table.
+// Version 14 adds the first_reports table.
+// Version 15 adds the tip_history table.
+// Version 16 adds the rejections table.
+// Version 17 adds the prune_horizons table.
+const DB_SCHEMA_VERSION: u32 = 17;
+
+// With incremental auto-vacuum, the free pages of the database can be
+// returned to the file system without rebuilding it.
+const AUTO_VACUUM_INCREMENTAL: u32 = 2;
+
+const SELECT_STMT_HEADER_HEIGHT: &str = "
+SELECT
+    height, header, miner, own, source
+FROM
+    headers
+WHERE
+    network = ?1
+ORDER BY
+    height
+    ASC
+";
+
+const CREATE_STMT_TABLE_HEADERS: &str = "
+CREATE TABLE IF NOT EXISTS headers (
+    height     INT,
+    network    INT,
+    hash       BLOB,
+    header     BLOB,
+    miner      TEXT,
+    PRIMARY KEY (network, hash, header)
+)
+";
+
+const CREATE_STMT_TABLE_EVENTS: &str = "
+CREATE TABLE IF NOT EXISTS events (
+    id          INTEGER PRIMARY KEY AUTOINCREMENT,
+    network     INT,
+    timestamp   INT,
+    kind        TEXT,
+    severity    TEXT,
+    title       TEXT,
+    description TEXT
+)
+";
+
+const INSERT_STMT_EVENT: &str = "
+INSERT INTO events
+    (network, timestamp, kind, severity, title, description, sequence)
+VALUES
+    (?1, ?2, ?3, ?4, ?5, ?6, ?7)
+";
+
+const SELECT_STMT_NEXT_EVENT_SEQUENCE: &str = "
+SELECT
+    COALESCE(MAX(sequence), 0) + 1
+FROM
+    events
+WHERE
+    network = ?1
+";
+
+const SELECT_STMT_EVENT_SEQUENCES: &str = "
+SELECT
+    network, MAX(sequence)
+FROM
+    events
+GROUP BY
+    network
+";
+
+const SELECT_STMT_EVENTS_AFTER: &str = "
+SELECT
+    id, network, timestamp, kind, severity, title, description, sequence
+FROM
+    events
+WHERE
+    network = ?1
+    AND sequence > ?2
+ORDER BY
+    sequence
+LIMIT ?3
+";
+
+const SELECT_STMT_EVENTS_AFTER_ID: &str = "
+SELECT
+    id, network, timestamp, kind, severity, title, description, sequence
+FROM
+    events
+WHERE
+    id > ?1
+ORDER BY
+    id
+LIMIT ?2
+";
+
+const SELECT_STMT_RECENT_EVENTS: &str = "
+SELECT
+    id, network, timestamp, kind, severity, title, description, sequence
+FROM
+    events
+WHERE
+    network = ?1
+ORDER BY
+    id
+    DESC
+LIMIT ?2
+";
+
+const CREATE_STMT_TABLE_NOTIFICATION_QUEUE: &str = "
+CREATE TABLE IF NOT EXISTS notification_queue (
+    id           INTEGER PRIMARY KEY AUTOINCREMENT,
+    sink         TEXT,
+    payload      TEXT,
+    attempts     INT,
+    next_attempt INT,
+    last_error   TEXT,
+    dead         INT,
+    created      INT
+)
+";
+
+const INSERT_STMT_NOTIFICATION: &str = "
+INSERT INTO notification_queue
+    (sink, payload, attempts, next_attempt, last_error, dead, created)
+VALUES
+    (?1, ?2, 0, ?3, '', 0, ?3)
+";
+
+const SELECT_STMT_DUE_NOTIFICATIONS: &str = "
+SELECT
+    id, sink, payload, attempts, next_attempt, last_error, created
+FROM
+    notification_queue
+WHERE
+    dead = 0 AND next_attempt <= ?1
+ORDER BY
+    id
+    ASC
+LIMIT ?2
+";
+
+const SELECT_STMT_DEAD_NOTIFICATIONS: &str = "
+SELECT
+    id, sink, payload, attempts, next_attempt, last_error, created
+FROM
+    notification_queue
+WHERE
+    dead = 1
+ORDER BY
+    id
+    ASC
+";
+
+const UPDATE_STMT_NOTIFICATION_FAILED: &str = "
+UPDATE
+    notification_queue
+SET
+    attempts = ?2,
+    next_attempt = ?3,
+    last_error = ?4,
+    dead = ?5
+WHERE
+    id = ?1
+";
+
+const UPDATE_STMT_NOTIFICATION_REQUEUE: &str = "
+UPDATE
+    notification_queue
+SET
+    attempts = 0,
+    next_attempt = ?2,
+    dead = 0
+WHERE
+    id = ?1 AND dead = 1
+";
+
+const DELETE_STMT_NOTIFICATION: &str = "
+DELETE FROM
+    notification_queue
+WHERE
+    id = ?1
+";
+
+const DELETE_STMT_DEAD_NOTIFICATION: &str = "
+DELETE FROM
+    notification_queue
+WHERE
+    id = ?1 AND dead = 1
+";
+
+const CREATE_STMT_TABLE_TIP_OBSERVATIONS: &str = "
+CREATE TABLE IF NOT EXISTS tip_observations (
+    network    INT,
+    node       INT,
+    timestamp  INT,
+    height     INT,
+    hash       TEXT,
+    status     TEXT
+)
+";
+
+const CREATE_STMT_INDEX_TIP_OBSERVATIONS: &str = "
+CREATE INDEX IF NOT EXISTS tip_observations_network_timestamp
+ON tip_observations (network, timestamp)
+";
+
+const CREATE_STMT_INDEX_TIP_OBSERVATIONS_HASH: &str = "
+CREATE INDEX IF NOT EXISTS tip_observations_network_hash
+ON tip_observations (network, hash)
+";
+
+const CREATE_STMT_TABLE_EXTERNAL_SIGHTINGS: &str = "
+CREATE TABLE IF NOT EXISTS external_sightings (
+    network    INT,
+    observer   TEXT,
+    hash       TEXT,
+    height     INT,
+    timestamp  INT,
+    received   INT
+)
+";
+
+const CREATE_STMT_INDEX_EXTERNAL_SIGHTINGS_HASH: &str = "
+CREATE INDEX IF NOT EXISTS external_sightings_network_hash
+ON external_sightings (network, hash)
+";
+
+const CREATE_STMT_INDEX_EXTERNAL_SIGHTINGS_OBSERVER: &str = "
+CREATE INDEX IF NOT EXISTS external_sightings_network_observer
+ON external_sightings (network, observer)
+";
+
+const INSERT_STMT_EXTERNAL_SIGHTING: &str = "
+INSERT INTO external_sightings
+    (network, observer, hash, height, timestamp, received)
+VALUES
+    (?1, ?2, ?3, ?4, ?5, ?6)
+";
+
+const SELECT_STMT_FIRST_SEEN: &str = "
+SELECT
+    MIN(timestamp)
+FROM (
+    SELECT timestamp FROM tip_observations WHERE network = ?1 AND hash = ?2
+    UNION ALL
+    SELECT timestamp FROM external_sightings WHERE network = ?1 AND hash = ?2
+)
+";
+
+const SELECT_STMT_SEEN_BY_NODES: &str = "
+SELECT
+    node, MIN(timestamp)
+FROM
+    tip_observations
+WHERE
+    network = ?1 AND hash = ?2
+GROUP BY
+    node
+";
+
+const SELECT_STMT_SEEN_BY_EXTERNAL_OBSERVERS: &str = "
+SELECT
+    observer, MIN(timestamp)
+FROM
+    external_sightings
+WHERE
+    network = ?1 AND hash = ?2
+GROUP BY
+    observer
+";
+
+const INSERT_STMT_TIP_OBSERVATION: &str = "
+INSERT INTO tip_observations
+    (network, node, timestamp, height, hash, status)
+VALUES
+    (?1, ?2, ?3, ?4, ?5, ?6)
+";
+
+const SELECT_STMT_TIP_OBSERVATIONS: &str = "
+SELECT
+    network, node, timestamp, height, hash, status
+FROM
+    tip_observations
+WHERE
+    network = ?1 AND timestamp >= ?2 AND timestamp <= ?3
+ORDER BY
+    timestamp, node, height
+    ASC
+";
+
+// The nodes configured at some point. A retired timestamp is set for nodes
+// missing from the configuration. A NULL purge_after keeps their data.
+const CREATE_STMT_TABLE_NODES: &str = "
+CREATE TABLE IF NOT EXISTS nodes (
+    network        INT,
+    node           INT,
+    name           TEXT,
+    description    TEXT,
+    implementation TEXT,
+    purge_after    INT,
+    retired        INT,
+    PRIMARY KEY (network, node)
+)
+";
+
+const UPSERT_STMT_NODE: &str = "
+INSERT INTO nodes
+    (network, node, name, description, implementation, purge_after, retired)
+VALUES
+    (?1, ?2, ?3, ?4, ?5, ?6, NULL)
+ON CONFLICT (network, node) DO UPDATE SET
+    name = excluded.name,
+    description = excluded.description,
+    implementation = excluded.implementation,
+    purge_after = excluded.purge_after,
+    retired = NULL
+";
+
+const SELECT_STMT_ACTIVE_NODES: &str = "
+SELECT
+    network, node
+FROM
+    nodes
+WHERE
+    retired IS NULL
+";
+
+const UPDATE_STMT_NODE_RETIRED: &str = "
+UPDATE
+    nodes
+SET
+    retired = ?3
+WHERE
+    network = ?1 AND node = ?2
+";
+
+const SELECT_STMT_RETIRED_NODES: &str = "
+SELECT
+    network, node, name, description, implementation, retired, purge_after
+FROM
+    nodes
+WHERE
+    retired IS NOT NULL
+ORDER BY
+    network, node
+    ASC
+";
+
+const DELETE_STMT_NODE: &str = "
+DELETE FROM
+    nodes
+WHERE
+    network = ?1 AND node = ?2
+";
+
+const DELETE_STMT_NODE_TIP_OBSERVATIONS: &str = "
+DELETE FROM
+    tip_observations
+WHERE
+    network = ?1 AND node = ?2
+";
+
+// The chain tips each node reported last, restored into the cache at
+// startup. Unlike the tip observations, these are kept for shadow nodes and
+// aren't subject to the retention.
+const CREATE_STMT_TABLE_NODE_TIPS: &str = "
+CREATE TABLE IF NOT EXISTS node_tips (
+    network      INT,
+    node         INT,
+    height       INT,
+    hash         TEXT,
+    branchlen    INT,
+    status       TEXT,
+    last_changed INT
+)
+";
+
+const INSERT_STMT_NODE_TIP: &str = "
+INSERT INTO node_tips
+    (network, node, height, hash, branchlen, status, last_changed)
+VALUES
+    (?1, ?2, ?3, ?4, ?5, ?6, ?7)
+";
+
+const DELETE_STMT_NODE_TIPS: &str = "
+DELETE FROM
+    node_tips
+WHERE
+    network = ?1 AND node = ?2
+";
+
+const SELECT_STMT_NODE_TIPS: &str = "
+SELECT
+    node, height, hash, branchlen, status, last_changed
+FROM
+    node_tips
+WHERE
+    network = ?1
+ORDER BY
+    node, height
+    DESC
+";
+
+// The reorgs of the active tips of the nodes. Unlike the tip observations,
+// these aren't subject to the retention.
+const CREATE_STMT_TABLE_REORGS: &str = "
+CREATE TABLE IF NOT EXISTS reorgs (
+    id                     INTEGER PRIMARY KEY AUTOINCREMENT,
+    network                INT,
+    node                   INT,
+    timestamp              INT,
+    old_tip_hash           TEXT,
+    old_tip_height         INT,
+    old_tip_time           INT,
+    new_tip_hash           TEXT,
+    new_tip_height         INT,
+    new_tip_time           INT,
+    common_ancestor_hash   TEXT,
+    common_ancestor_height INT,
+    depth                  INT
+)
+";
+
+const CREATE_STMT_INDEX_REORGS: &str = "
+CREATE INDEX IF NOT EXISTS reorgs_network ON reorgs (network, id)
+";
+
+const INSERT_STMT_REORG: &str = "
+INSERT INTO reorgs
+    (network, node, timestamp, old_tip_hash, old_tip_height, old_tip_time,
+     new_tip_hash, new_tip_height, new_tip_time, common_ancestor_hash,
+     common_ancestor_height, depth)
+VALUES
+    (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12)
+";
+
+const SELECT_STMT_RECENT_REORGS: &str = "
+SELECT
+    id, network, node, timestamp, old_tip_hash, old_tip_height, old_tip_time,
+    new_tip_hash, new_tip_height, new_tip_time, common_ancestor_hash,
+    common_ancestor_height, depth
+FROM
+    reorgs
+WHERE
+    network = ?1
+ORDER BY
+    id
+    DESC
+LIMIT ?2
+";
+
+const DELETE_STMT_NODE_REORGS: &str = "
+DELETE FROM
+    reorgs
+WHERE
+    network = ?1 AND node = ?2
+";
+
+// The block metadata of the blocks around forks, by header store.
+const CREATE_STMT_TABLE_BLOCK_STATS: &str = "
+CREATE TABLE IF NOT EXISTS block_stats (
+    network  INT,
+    hash     TEXT,
+    size     INT,
+    weight   INT,
+    tx_count INT,
+    fees     INT,
+    PRIMARY KEY (network, hash)
+)
+";
+
+const INSERT_STMT_BLOCK_STATS: &str = "
+INSERT OR REPLACE INTO block_stats
+    (network, hash, size, weight, tx_count, fees)
+VALUES
+    (?1, ?2, ?3, ?4, ?5, ?6)
+";
+
+const SELECT_STMT_BLOCK_STATS: &str = "
+SELECT
+    hash, size, weight, tx_count, fees
+FROM
+    block_stats
+WHERE
+    network = ?1 AND hash = ?2
+";
+
+// The node that first reported each block of a network and when. Only the
+// first report of a block is kept.
+const CREATE_STMT_TABLE_FIRST_REPORTS: &str = "
+CREATE TABLE IF NOT EXISTS first_reports (
+    network    INT,
+    hash       TEXT,
+    node       INT,
+    timestamp  INT,
+    PRIMARY KEY (network, hash)
+)
+";
+
+const INSERT_STMT_FIRST_REPORT: &str = "
+INSERT OR IGNORE INTO first_reports
+    (network, hash, node, timestamp)
+VALUES
+    (?1, ?2, ?3, ?4)
+";
+
+const SELECT_STMT_FIRST_REPORT: &str = "
+SELECT
+    node, timestamp
+FROM
+    first_reports
+WHERE
+    network = ?1 AND hash = ?2
+";
+
+const DELETE_STMT_NODE_FIRST_REPORTS: &str = "
+DELETE FROM
+    first_reports
+WHERE
+    network = ?1 AND node = ?2
+";
+
+// The tips of the nodes that appeared or changed their status. Unlike the
+// tip observations, the history isn't subject to the retention.
+const CREATE_STMT_TABLE_TIP_HISTORY: &str = "
+CREATE TABLE IF NOT EXISTS tip_history (
+    network    INT,
+    node       INT,
+    timestamp  INT,
+    height     INT,
+    hash       TEXT,
+    status     TEXT
+)
+";
+
+const CREATE_STMT_INDEX_TIP_HISTORY: &str = "
+CREATE INDEX IF NOT EXISTS tip_history_network_timestamp
+ON tip_history (network, timestamp)
+";
+
+const INSERT_STMT_TIP_HISTORY: &str = "
+INSERT INTO tip_history
+    (network, node, timestamp, height, hash, status)
+VALUES
+    (?1, ?2, ?3, ?4, ?5, ?6)
+";
+
+const SELECT_STMT_TIP_HISTORY: &str = "
+SELECT
+    network, node, timestamp, height, hash, status
+FROM
+    tip_history
+WHERE
+    network = ?1 AND (?2 IS NULL OR node = ?2) AND timestamp >= ?3 AND timestamp <= ?4
+ORDER BY
+    timestamp, rowid
+    ASC
+LIMIT ?5
+";
+
+const DELETE_STMT_NODE_TIP_HISTORY: &str = "
+DELETE FROM
+    tip_history
+WHERE
+    network = ?1 AND node = ?2
+";
+
+// Why the blocks of the invalid tips of the nodes were rejected, see
+// rejection.
+const CREATE_STMT_TABLE_REJECTIONS: &str = "
+CREATE TABLE IF NOT EXISTS rejections (
+    network    INT,
+    hash       TEXT,
+    node       INT,
+    reason     TEXT,
+    timestamp  INT,
+    PRIMARY KEY (network, hash)
+)
+";
+
+const INSERT_STMT_REJECTION: &str = "
+INSERT OR IGNORE INTO rejections
+    (network, hash, node, reason, timestamp)
+VALUES
+    (?1, ?2, ?3, ?4, ?5)
+";
+
+const SELECT_STMT_REJECTION: &str = "
+SELECT
+    hash, node, reason, timestamp
+FROM
+    rejections
+WHERE
+    network = ?1 AND hash = ?2
+";
+
+const DELETE_STMT_NODE_REJECTIONS: &str = "
+DELETE FROM
+    rejections
+WHERE
+    network = ?1 AND node = ?2
+";
+
+const DELETE_STMT_TIP_OBSERVATIONS_BEFORE: &str = "
+DELETE FROM
+    tip_observations
+WHERE
+    timestamp < ?1
+";
+
+const DELETE_STMT_EXTERNAL_SIGHTINGS_BEFORE: &str = "
+DELETE FROM
+    external_sightings
+WHERE
+    received < ?1
+";
+
+// Keeps the ?3 most recently received sightings of the observer.
+const DELETE_STMT_EXCESS_EXTERNAL_SIGHTINGS: &str = "
+DELETE FROM
+    external_sightings
+WHERE
+    rowid IN (
+        SELECT rowid FROM external_sightings
+        WHERE network = ?1 AND observer = ?2
+        ORDER BY rowid DESC
+        LIMIT -1 OFFSET ?3
+    )
+";
+
+// Incident mode windows. The bundle is a tar archive of the collected data,
+// attached when the incident ends.
+const CREATE_STMT_TABLE_INCIDENTS: &str = "
+CREATE TABLE IF NOT EXISTS incidents (
+    id        INTEGER PRIMARY KEY AUTOINCREMENT,
+    network   INT,
+    reason    TEXT,
+    started   INT,
+    ended     INT,
+    bundle    BLOB
+)
+";
+
+const CREATE_STMT_TABLE_INCIDENT_RECORDINGS: &str = "
+CREATE TABLE IF NOT EXISTS incident_recordings (
+    incident  INT,
+    timestamp INT,
+    node      INT,
+    kind      TEXT,
+    data      TEXT
+)
+";
+
+const CREATE_STMT_INDEX_INCIDENT_RECORDINGS: &str = "
+CREATE INDEX IF NOT EXISTS incident_recordings_incident ON incident_recordings (incident)
+";
+
+const INSERT_STMT_INCIDENT: &str = "
+INSERT INTO incidents
+    (network, reason, started, ended, bundle)
+VALUES
+    (?1, ?2, ?3, NULL, NULL)
+";
+
+const UPDATE_STMT_INCIDENT_ENDED: &str = "
+UPDATE
+    incidents
+SET
+    ended = ?2,
+    bundle = ?3
+WHERE
+    id = ?1
+";
+
+const CREATE_STMT_TABLE_PRUNE_HORIZONS: &str = "
+CREATE TABLE IF NOT EXISTS prune_horizons (
+    header_store INT PRIMARY KEY,
+    height       INT
+)
+";
+
+const INSERT_STMT_PRUNE_HORIZON: &str = "
+INSERT OR REPLACE INTO prune_horizons
+    (header_store, height)
+VALUES
+    (?1, ?2)
+";
+
+const SELECT_STMT_PRUNE_HORIZONS: &str = "
+SELECT
+    header_store, height
+FROM
+    prune_horizons
+";
+
+const CREATE_STMT_TABLE_SUPPRESSED_TIPS: &str = "
+CREATE TABLE IF NOT EXISTS suppressed_tips (
+    network INT,
+    hash    TEXT,
+    reason  TEXT,
+    since   INT,
+    PRIMARY KEY (network, hash)
+)
+";
+
+const INSERT_STMT_SUPPRESSED_TIP: &str = "
+INSERT OR REPLACE INTO suppressed_tips
+    (network, hash, reason, since)
+VALUES
+    (?1, ?2, ?3, ?4)
+";
+
+const DELETE_STMT_SUPPRESSED_TIP: &str = "
+DELETE FROM
+    suppressed_tips
+WHERE
+    network = ?1
+    AND hash = ?2
+";
+
+const SELECT_STMT_SUPPRESSED_TIPS: &str = "
+SELECT
+    network, hash, reason, since
+FROM
+    suppressed_tips
+";
+
+const SELECT_STMT_INCIDENTS: &str = "
+SELECT
+    id, network, reason, started, ended
+FROM
+    incidents
+ORDER BY
+    id
+    DESC
+";
+
+const SELECT_STMT_INCIDENT_BUNDLE: &str = "
+SELECT
+    bundle
+FROM
+    incidents
+WHERE
+    id = ?1
+";
+
+const INSERT_STMT_INCIDENT_RECORDING: &str = "
+INSERT INTO incident_recordings
+    (incident, timestamp, node, kind, data)
+VALUES
+    (?1, ?2, ?3, ?4, ?5)
+";
+
+const DELETE_STMT_INCIDENT_RECORDINGS: &str = "
+DELETE FROM
+    incident_recordings
+WHERE
+    incident = ?1
+";
+
+const SELECT_STMT_INCIDENT_RECORDINGS: &str = "
+SELECT
+    timestamp, node, kind, data
+FROM
+    incident_recordings
+WHERE
+    incident = ?1
+ORDER BY
+    rowid
+    ASC
+";
+
+// The headers table is created with the version 1 layout. Newer columns
+// are added by migrations.
+const MIGRATION_STMT_HEADERS_OWN: &str = "
+ALTER TABLE headers ADD COLUMN own INT NOT NULL DEFAULT 0
+";
+
+// Headers stored before the source was recorded have an unknown source.
+const MIGRATION_STMT_HEADERS_SOURCE: &str = "
+ALTER TABLE headers ADD COLUMN source TEXT NOT NULL DEFAULT 'unknown'
+";
+
+const MIGRATION_STMT_EVENTS_SEQUENCE: &str = "
+ALTER TABLE events ADD COLUMN sequence INT NOT NULL DEFAULT 0
+";
+
+// Numbers the existing events of each network in the order they were written.
+const MIGRATION_STMT_EVENTS_SEQUENCE_BACKFILL: &str = "
+UPDATE
+    events
+SET
+    sequence = (
+        SELECT
+            COUNT(*)
+        FROM
+            events AS earlier
+        WHERE
+            earlier.network = events.network
+            AND earlier.id <= events.id
+    )
+";
+
+const MIGRATION_STMT_INDEX_EVENTS_SEQUENCE: &str = "
+CREATE INDEX IF NOT EXISTS events_network_sequence ON events (network, sequence)
+";
+
+// The schema migrations by the version they migrate to. Each one runs in a
+// transaction with its version bump, so an interrupted migration is retried
+// from the start on the next startup.
+const MIGRATIONS: [(u32, &[&str]); 3] = [
+    (3, &[MIGRATION_STMT_HEADERS_OWN]),
+    (
+        8,
+        &[
+            MIGRATION_STMT_EVENTS_SEQUENCE,
+            MIGRATION_STMT_EVENTS_SEQUENCE_BACKFILL,
+            MIGRATION_STMT_INDEX_EVENTS_SEQUENCE,
+        ],
+    ),
+    (10, &[MIGRATION_STMT_HEADERS_SOURCE]),
+];
+
+const UPDATE_STMT_HEADER_MINER: &str = "
+UPDATE
+    headers
+SET
+    miner = ?1,
+    own = ?2
+WHERE
+    hash = ?3;
+";
+
+const DELETE_STMT_HEADER: &str = "
+DELETE FROM
+    headers
+WHERE
+    network = ?1 AND hash = ?2
+";
+
+// Locks the database. Waits while answering an API request are recorded in
+// its trace.
+async fn lock(db: &Db) -> MutexGuard<'_, Connection> {
+    trace::span("db lock", db.lock()).await
+}
+
+pub async fn setup_db(db: Db) -> Result<(), DbError> {
+    let db_locked = lock(&db).await;
+    let auto_vacuum: u32 = db_locked.query_row("PRAGMA auto_vacuum", [], |row| row.get(0))?;
+    if auto_vacuum != AUTO_VACUUM_INCREMENTAL {
+        // A new database uses it once the first table is created, an
+        // existing one once it's rebuilt by compact().
+        db_locked.pragma_update(None, "auto_vacuum", AUTO_VACUUM_INCREMENTAL)?;
+    }
+    db_locked.execute(CREATE_STMT_TABLE_HEADERS, [])?;
+    db_locked.execute(CREATE_STMT_TABLE_EVENTS, [])?;
+    db_locked.execute(CREATE_STMT_TABLE_NOTIFICATION_QUEUE, [])?;
+    db_locked.execute(CREATE_STMT_TABLE_TIP_OBSERVATIONS, [])?;
+    db_locked.execute(CREATE_STMT_INDEX_TIP_OBSERVATIONS, [])?;
+    db_locked.execute(CREATE_STMT_INDEX_TIP_OBSERVATIONS_HASH, [])?;
+    db_locked.execute(CREATE_STMT_TABLE_EXTERNAL_SIGHTINGS, [])?;
+    db_locked.execute(CREATE_STMT_INDEX_EXTERNAL_SIGHTINGS_HASH, [])?;
+    db_locked.execute(CREATE_STMT_INDEX_EXTERNAL_SIGHTINGS_OBSERVER, [])?;
+    db_locked.execute(CREATE_STMT_TABLE_NODES, [])?;
+    db_locked.execute(CREATE_STMT_TABLE_INCIDENTS, [])?;
+    db_locked.execute(CREATE_STMT_TABLE_INCIDENT_RECORDINGS, [])?;
+    db_locked.execute(CREATE_STMT_INDEX_INCIDENT_RECORDINGS, [])?;
+    db_locked.execute(CREATE_STMT_TABLE_SUPPRESSED_TIPS, [])?;
+    db_locked.execute(CREATE_STMT_TABLE_NODE_TIPS, [])?;
+    db_locked.execute(CREATE_STMT_TABLE_REORGS, [])?;
+    db_locked.execute(CREATE_STMT_INDEX_REORGS, [])?;
+    db_locked.execute(CREATE_STMT_TABLE_BLOCK_STATS, [])?;
+    db_locked.execute(CREATE_STMT_TABLE_FIRST_REPORTS, [])?;
+    db_locked.execute(CREATE_STMT_TABLE_TIP_HISTORY, [])?;
+    db_locked.execute(CREATE_STMT_INDEX_TIP_HISTORY, [])?;
+    db_locked.execute(CREATE_STMT_TABLE_REJECTIONS, [])?;
+    db_locked.execute(CREATE_STMT_TABLE_PRUNE_HORIZONS, [])?;
+
+    let version: u32 = db_locked.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+    if version > DB_SCHEMA_VERSION {
+        return Err(DbError::UnsupportedSchemaVersion(version));
+    }
+    if version < DB_SCHEMA_VERSION {
+        info!(
+            "Migrating database schema from version {} to {}",
+            version, DB_SCHEMA_VERSION
+        );
+        for (migration_version, stmts) in MIGRATIONS.iter() {
+            if version >= *migration_version {
+                continue;
+            }
+            let tx = db_locked.unchecked_transaction()?;
+            for stmt in stmts.iter() {
+                tx.execute(stmt, [])?;
+            }
+            tx.pragma_update(None, "user_version", migration_version)?;
+            tx.commit()?;
+        }
+        db_locked.pragma_update(None, "user_version", DB_SCHEMA_VERSION)?;
+    }
+    Ok(())
+}
+
+pub async fn enqueue_notification(
+    db: Db,
+    sink: &str,
+    payload: &str,
+    now: u64,
+) -> Result<(), DbError> {
+    let db_locked = lock(&db).await;
+    db_locked.execute(INSERT_STMT_NOTIFICATION, params![sink, payload, now])?;
+    Ok(())
+}
+
+pub async fn load_due_notifications(
+    db: Db,
+    now: u64,
+    limit: u32,
+) -> Result<Vec<QueuedNotification>, DbError> {
+    let db_locked = lock(&db).await;
+    let mut stmt = db_locked.prepare(SELECT_STMT_DUE_NOTIFICATIONS)?;
+    let mut rows = stmt.query(params![now, limit])?;
+    let mut notifications: Vec<QueuedNotification> = vec![];
+    while let Some(row) = rows.next()? {
+        notifications.push(notification_from_row(row)?);
+    }
+    Ok(notifications)
+}
+
+pub async fn load_dead_notifications(db: Db) -> Result<Vec<QueuedNotification>, DbError> {
+    let db_locked = lock(&db).await;
+    let mut stmt = db_locked.prepare(SELECT_STMT_DEAD_NOTIFICATIONS)?;
+    let mut rows = stmt.query([])?;
+    let mut notifications: Vec<QueuedNotification> = vec![];
+    while let Some(row) = rows.next()? {
+        notifications.push(notification_from_row(row)?);
+    }
+    Ok(notifications)
+}
+
+fn notification_from_row(row: &Row) -> Result<QueuedNotification, DbError> {
+    Ok(QueuedNotification {
+        id: row.get(0)?,
+        sink: row.get(1)?,
+        payload: row.get(2)?,
+        attempts: row.get(3)?,
+        next_attempt: row.get(4)?,
+        last_error: row.get(5)?,
+        created: row.get(6)?,
+    })
+}
+
+pub async fn notification_failed(
+    db: Db,
+    id: u64,
+    attempts: u32,
+    next_attempt: u64,
+    error: &str,
+    dead: bool,
+) -> Result<(), DbError> {
+    let db_locked = lock(&db).await;
+    db_locked.execute(
+        UPDATE_STMT_NOTIFICATION_FAILED,
+        params![id, attempts, next_attempt, error, dead],
+    )?;
+    Ok(())
+}
+
+pub async fn delete_notification(db: Db, id: u64) -> Result<(), DbError> {
+    let db_locked = lock(&db).await;
+    db_locked.execute(DELETE_STMT_NOTIFICATION, params![id])?;
+    Ok(())
+}
+
+// Moves a dead notification back into the queue. Returns false if there is
+// no dead notification with this id.
+pub async fn requeue_dead_notification(db: Db, id: u64, now: u64) -> Result<bool, DbError> {
+    let db_locked = lock(&db).await;
+    let changed = db_locked.execute(UPDATE_STMT_NOTIFICATION_REQUEUE, params![id, now])?;
+    Ok(changed > 0)
+}
+
+// Returns false if there is no dead notification with this id.
+pub async fn delete_dead_notification(db: Db, id: u64) -> Result<bool, DbError> {
+    let db_locked = lock(&db).await;
+    let changed = db_locked.execute(DELETE_STMT_DEAD_NOTIFICATION, params![id])?;
+    Ok(changed > 0)
+}
+
+pub async fn write_tip_observations(
+    db: Db,
+    network: u32,
+    node: u32,
+    timestamp: u64,
+    tips: &[ChainTip],
+) -> Result<(), DbError> {
+    let mut db_locked = lock(&db).await;
+    let tx = db_locked.transaction()?;
+    for tip in tips {
+        tx.execute(
+            INSERT_STMT_TIP_OBSERVATION,
+            params![
+                network,
+                node,
+                timestamp,
+                tip.height,
+                tip.hash,
+                tip.status.to_string()
+            ],
+        )?;
+    }
+    tx.commit()?;
+    Ok(())
+}
+
+// Replaces the last chain tips of a node.
+pub async fn write_node_tips(
+    db: Db,
+    network: u32,
+    node: u32,
+    timestamp: u64,
+    tips: &[ChainTip],
+) -> Result<(), DbError> {
+    let mut db_locked = lock(&db).await;
+    let tx = db_locked.transaction()?;
+    tx.execute(DELETE_STMT_NODE_TIPS, params![network, node])?;
+    for tip in tips {
+        tx.execute(
+            INSERT_STMT_NODE_TIP,
+            params![
+                network,
+                node,
+                tip.height,
+                tip.hash,
+                tip.branchlen,
+                tip.status.to_string(),
+                timestamp
+            ],
+        )?;
+    }
+    tx.commit()?;
+    Ok(())
+}
+
+// Loads the last chain tips of the nodes of a network together with the
+// time they last changed, by node id.
+pub async fn load_node_tips(
+    db: Db,
+    network: u32,
+) -> Result<HashMap<u32, (u64, Vec<ChainTip>)>, DbError> {
+    let db_locked = lock(&db).await;
+    let mut stmt = db_locked.prepare(SELECT_STMT_NODE_TIPS)?;
+    let mut rows = stmt.query(params![network])?;
+    let mut node_tips: HashMap<u32, (u64, Vec<ChainTip>)> = HashMap::new();
+    while let Some(row) = rows.next()? {
+        let (_, tips) = node_tips
+            .entry(row.get(0)?)
+            .or_insert((row.get(5)?, vec![]));
+        tips.push(ChainTip {
+            height: row.get(1)?,
+            hash: row.get(2)?,
+            branchlen: row.get(3)?,
+            status: ChainTipStatus::from(row.get::<_, String>(4)?),
+        });
+    }
+    Ok(node_tips)
+}
+
+// Writes a reorg and returns the id assigned to it.
+pub async fn write_reorg(db: Db, reorg: &Reorg) -> Result<u64, DbError> {
+    let db_locked = lock(&db).await;
+    db_locked.execute(
+        INSERT_STMT_REORG,
+        params![
+            reorg.network_id,
+            reorg.node_id,
+            reorg.timestamp,
+            reorg.old_tip_hash,
+            reorg.old_tip_height,
+            reorg.old_tip_time,
+            reorg.new_tip_hash,
+            reorg.new_tip_height,
+            reorg.new_tip_time,
+            reorg.common_ancestor_hash,
+            reorg.common_ancestor_height,
+            reorg.depth
+        ],
+    )?;
+    Ok(db_locked.last_insert_rowid() as u64)
+}
+
+// Loads the most recent reorgs of a network, newest first.
+pub async fn load_recent_reorgs(db: Db, network: u32, limit: u32) -> Result<Vec<Reorg>, DbError> {
+    let db_locked = lock(&db).await;
+    let mut stmt = db_locked.prepare(SELECT_STMT_RECENT_REORGS)?;
+    let mut rows = stmt.query(params![network, limit])?;
+    let mut reorgs: Vec<Reorg> = vec![];
+    while let Some(row) = rows.next()? {
+        reorgs.push(Reorg {
+            id: row.get(0)?,
+            network_id: row.get(1)?,
+            node_id: row.get(2)?,
+            timestamp: row.get(3)?,
+            old_tip_hash: row.get(4)?,
+            old_tip_height: row.get(5)?,
+            old_tip_time: row.get(6)?,
+            new_tip_hash: row.get(7)?,
+            new_tip_height: row.get(8)?,
+            new_tip_time: row.get(9)?,
+            common_ancestor_hash: row.get(10)?,
+            common_ancestor_height: row.get(11)?,
+            depth: row.get(12)?,
+        });
+    }
+    Ok(reorgs)
+}
 
-const SELECT_STMT_HEADER_HEIGHT: &str = "
-SELECT
-    height, header, miner
-FROM
-    headers
-WHERE
-    network = ?1
-ORDER BY
-    height
-    ASC
-";
+pub async fn write_block_stats(db: Db, network: u32, stats: &BlockStats) -> Result<(), DbError> {
+    let db_locked = lock(&db).await;
+    db_locked.execute(
+        INSERT_STMT_BLOCK_STATS,
+        params![
+            network,
+            stats.hash,
+            stats.size,
+            stats.weight,
+            stats.tx_count,
+            stats.fees
+        ],
+    )?;
+    Ok(())
+}
 
-const CREATE_STMT_TABLE_HEADERS: &str = "
-CREATE TABLE IF NOT EXISTS headers (
-    height     INT,
-    network    INT,
-    hash       BLOB,
-    header     BLOB,
-    miner      TEXT,
-    PRIMARY KEY (network, hash, header)
-)
-";
+// Loads the recorded block metadata of the blocks, by hash. Blocks without
+// metadata are missing.
+pub async fn load_block_stats(
+    db: Db,
+    network: u32,
+    hashes: &[String],
+) -> Result<HashMap<String, BlockStats>, DbError> {
+    let db_locked = lock(&db).await;
+    let mut stmt = db_locked.prepare(SELECT_STMT_BLOCK_STATS)?;
+    let mut block_stats: HashMap<String, BlockStats> = HashMap::new();
+    for hash in hashes {
+        let mut rows = stmt.query(params![network, hash])?;
+        if let Some(row) = rows.next()? {
+            block_stats.insert(
+                hash.clone(),
+                BlockStats {
+                    hash: row.get(0)?,
+                    size: row.get(1)?,
+                    weight: row.get(2)?,
+                    tx_count: row.get(3)?,
+                    fees: row.get(4)?,
+                },
+            );
+        }
+    }
+    Ok(block_stats)
+}
 
-const UPDATE_STMT_HEADER_MINER: &str = "
-UPDATE
-    headers
-SET
-    miner = ?1
-WHERE
-    hash = ?2;
-";
+// Records the node as the first to report the blocks unless another node
+// reported them before.
+pub async fn write_first_reports(
+    db: Db,
+    network: u32,
+    node: u32,
+    timestamp: u64,
+    hashes: &[String],
+) -> Result<(), DbError> {
+    let mut db_locked = lock(&db).await;
+    let tx = db_locked.transaction()?;
+    for hash in hashes {
+        tx.execute(
+            INSERT_STMT_FIRST_REPORT,
+            params![network, hash, node, timestamp],
+        )?;
+    }
+    tx.commit()?;
+    Ok(())
+}
 
-pub async fn setup_db(db: Db) -> Result<(), DbError> {
-    db.lock().await.execute(CREATE_STMT_TABLE_HEADERS, [])?;
+// Loads the first reports of the blocks, by hash. Blocks no node reported
+// are missing.
+pub async fn load_first_reports(
+    db: Db,
+    network: u32,
+    hashes: &[String],
+) -> Result<HashMap<String, FirstReport>, DbError> {
+    let db_locked = lock(&db).await;
+    let mut stmt = db_locked.prepare(SELECT_STMT_FIRST_REPORT)?;
+    let mut first_reports: HashMap<String, FirstReport> = HashMap::new();
+    for hash in hashes {
+        let mut rows = stmt.query(params![network, hash])?;
+        if let Some(row) = rows.next()? {
+            first_reports.insert(
+                hash.clone(),
+                FirstReport {
+                    node: row.get(0)?,
+                    timestamp: row.get(1)?,
+                },
+            );
+        }
+    }
+    Ok(first_reports)
+}
+
+// Records why the block was rejected unless it's already recorded.
+pub async fn write_rejection(db: Db, network: u32, rejection: &Rejection) -> Result<(), DbError> {
+    let db_locked = lock(&db).await;
+    db_locked.execute(
+        INSERT_STMT_REJECTION,
+        params![
+            network,
+            rejection.hash,
+            rejection.node,
+            rejection.reason,
+            rejection.timestamp
+        ],
+    )?;
+    Ok(())
+}
+
+// Loads the recorded rejections of the blocks, by hash. Blocks without a
+// recorded rejection are missing.
+pub async fn load_rejections(
+    db: Db,
+    network: u32,
+    hashes: &[String],
+) -> Result<HashMap<String, Rejection>, DbError> {
+    let db_locked = lock(&db).await;
+    let mut stmt = db_locked.prepare(SELECT_STMT_REJECTION)?;
+    let mut rejections: HashMap<String, Rejection> = HashMap::new();
+    for hash in hashes {
+        let mut rows = stmt.query(params![network, hash])?;
+        if let Some(row) = rows.next()? {
+            rejections.insert(
+                hash.clone(),
+                Rejection {
+                    hash: row.get(0)?,
+                    node: row.get(1)?,
+                    reason: row.get(2)?,
+                    timestamp: row.get(3)?,
+                },
+            );
+        }
+    }
+    Ok(rejections)
+}
+
+// Loads the tip observations of a network made between from and to
+// (inclusive), oldest first.
+pub async fn load_tip_observations(
+    db: Db,
+    network: u32,
+    from: u64,
+    to: u64,
+) -> Result<Vec<TipObservation>, DbError> {
+    let db_locked = lock(&db).await;
+    let mut stmt = db_locked.prepare(SELECT_STMT_TIP_OBSERVATIONS)?;
+    let mut rows = stmt.query(params![network, from, to])?;
+    let mut observations: Vec<TipObservation> = vec![];
+    while let Some(row) = rows.next()? {
+        observations.push(TipObservation {
+            network_id: row.get(0)?,
+            node_id: row.get(1)?,
+            timestamp: row.get(2)?,
+            height: row.get(3)?,
+            hash: row.get(4)?,
+            status: ChainTipStatus::from(row.get::<_, String>(5)?),
+        });
+    }
+    Ok(observations)
+}
+
+pub async fn write_tip_history(
+    db: Db,
+    network: u32,
+    node: u32,
+    timestamp: u64,
+    tips: &[ChainTip],
+) -> Result<(), DbError> {
+    let mut db_locked = lock(&db).await;
+    let tx = db_locked.transaction()?;
+    for tip in tips {
+        tx.execute(
+            INSERT_STMT_TIP_HISTORY,
+            params![
+                network,
+                node,
+                timestamp,
+                tip.height,
+                tip.hash,
+                tip.status.to_string()
+            ],
+        )?;
+    }
+    tx.commit()?;
+    Ok(())
+}
+
+// Loads the tip changes of a network, or of one of its nodes, made between
+// from and to (inclusive), oldest first.
+pub async fn load_tip_history(
+    db: Db,
+    network: u32,
+    node: Option<u32>,
+    from: u64,
+    to: u64,
+    limit: u32,
+) -> Result<Vec<TipObservation>, DbError> {
+    let db_locked = lock(&db).await;
+    let mut stmt = db_locked.prepare(SELECT_STMT_TIP_HISTORY)?;
+    let mut rows = stmt.query(params![network, node, from, to, limit])?;
+    let mut changes: Vec<TipObservation> = vec![];
+    while let Some(row) = rows.next()? {
+        changes.push(TipObservation {
+            network_id: row.get(0)?,
+            node_id: row.get(1)?,
+            timestamp: row.get(2)?,
+            height: row.get(3)?,
+            hash: row.get(4)?,
+            status: ChainTipStatus::from(row.get::<_, String>(5)?),
+        });
+    }
+    Ok(changes)
+}
+
+// Loads the time a node first reported each of the blocks as a chain tip or
+// an external observer first sighted it. Blocks never observed are missing
+// from the result.
+pub async fn load_first_seen(
+    db: Db,
+    network: u32,
+    hashes: &[String],
+) -> Result<HashMap<String, u64>, DbError> {
+    let db_locked = lock(&db).await;
+    let mut stmt = db_locked.prepare(SELECT_STMT_FIRST_SEEN)?;
+    let mut first_seen: HashMap<String, u64> = HashMap::new();
+    for hash in hashes {
+        let timestamp: Option<u64> = stmt.query_row(params![network, hash], |row| row.get(0))?;
+        if let Some(timestamp) = timestamp {
+            first_seen.insert(hash.clone(), timestamp);
+        }
+    }
+    Ok(first_seen)
+}
+
+// Writes the sightings of the observer and deletes its oldest sightings
+// beyond the max_sightings most recent ones.
+pub async fn write_external_sightings(
+    db: Db,
+    network: u32,
+    observer: &str,
+    received: u64,
+    sightings: &[Sighting],
+    max_sightings: usize,
+) -> Result<(), DbError> {
+    let mut db_locked = lock(&db).await;
+    let tx = db_locked.transaction()?;
+    for sighting in sightings {
+        tx.execute(
+            INSERT_STMT_EXTERNAL_SIGHTING,
+            params![
+                network,
+                observer,
+                sighting.hash.to_string(),
+                sighting.height,
+                sighting.timestamp,
+                received
+            ],
+        )?;
+    }
+    tx.execute(
+        DELETE_STMT_EXCESS_EXTERNAL_SIGHTINGS,
+        params![network, observer, max_sightings as u64],
+    )?;
+    tx.commit()?;
+    Ok(())
+}
+
+// Loads who first saw the block when, ordered by time: the nodes reporting
+// it as a chain tip and the external observers sighting it.
+pub async fn load_seen_by(db: Db, network: u32, hash: &str) -> Result<Vec<SeenBy>, DbError> {
+    let db_locked = lock(&db).await;
+    let mut seen_by: Vec<SeenBy> = vec![];
+
+    let mut stmt = db_locked.prepare(SELECT_STMT_SEEN_BY_NODES)?;
+    let mut rows = stmt.query(params![network, hash])?;
+    while let Some(row) = rows.next()? {
+        seen_by.push(SeenBy {
+            observer: Observer::Node(row.get(0)?),
+            timestamp: row.get(1)?,
+        });
+    }
+
+    let mut stmt = db_locked.prepare(SELECT_STMT_SEEN_BY_EXTERNAL_OBSERVERS)?;
+    let mut rows = stmt.query(params![network, hash])?;
+    while let Some(row) = rows.next()? {
+        seen_by.push(SeenBy {
+            observer: Observer::External(row.get(0)?),
+            timestamp: row.get(1)?,
+        });
+    }
+
+    seen_by.sort_by_key(|s| s.timestamp);
+    Ok(seen_by)
+}
+
+// Records the configured nodes of all networks and retires the previously
+// configured nodes missing from the configuration. Returns the newly
+// retired nodes as (network, node).
+pub async fn sync_nodes(
+    db: Db,
+    configured: &[(u32, NodeInfo)],
+    now: u64,
+) -> Result<Vec<(u32, u32)>, DbError> {
+    let mut db_locked = lock(&db).await;
+    let tx = db_locked.transaction()?;
+    let previous: Vec<(u32, u32)> = {
+        let mut stmt = tx.prepare(SELECT_STMT_ACTIVE_NODES)?;
+        let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+        rows.collect::<Result<_, _>>()?
+    };
+    for (network, node) in configured {
+        tx.execute(
+            UPSERT_STMT_NODE,
+            params![
+                network,
+                node.id,
+                node.name,
+                node.description,
+                node.implementation,
+                node.purge_after
+            ],
+        )?;
+    }
+    let mut retired: Vec<(u32, u32)> = vec![];
+    for (network, node) in previous {
+        if !configured
+            .iter()
+            .any(|(n, info)| *n == network && info.id == node)
+        {
+            tx.execute(UPDATE_STMT_NODE_RETIRED, params![network, node, now])?;
+            retired.push((network, node));
+        }
+    }
+    tx.commit()?;
+    Ok(retired)
+}
+
+pub async fn load_retired_nodes(db: Db) -> Result<Vec<RetiredNode>, DbError> {
+    let db_locked = lock(&db).await;
+    let mut stmt = db_locked.prepare(SELECT_STMT_RETIRED_NODES)?;
+    let mut rows = stmt.query([])?;
+    let mut nodes: Vec<RetiredNode> = vec![];
+    while let Some(row) = rows.next()? {
+        nodes.push(RetiredNode {
+            network_id: row.get(0)?,
+            node_id: row.get(1)?,
+            name: row.get(2)?,
+            description: row.get(3)?,
+            implementation: row.get(4)?,
+            retired: row.get(5)?,
+            purge_after: row.get(6)?,
+        });
+    }
+    Ok(nodes)
+}
+
+// Deletes a retired node together with its tip observations, last tips and
+// reorgs.
+pub async fn purge_node(db: Db, network: u32, node: u32) -> Result<(), DbError> {
+    let mut db_locked = lock(&db).await;
+    let tx = db_locked.transaction()?;
+    tx.execute(DELETE_STMT_NODE_TIP_OBSERVATIONS, params![network, node])?;
+    tx.execute(DELETE_STMT_NODE_TIPS, params![network, node])?;
+    tx.execute(DELETE_STMT_NODE_REORGS, params![network, node])?;
+    tx.execute(DELETE_STMT_NODE_FIRST_REPORTS, params![network, node])?;
+    tx.execute(DELETE_STMT_NODE_TIP_HISTORY, params![network, node])?;
+    tx.execute(DELETE_STMT_NODE_REJECTIONS, params![network, node])?;
+    tx.execute(DELETE_STMT_NODE, params![network, node])?;
+    tx.commit()?;
+    Ok(())
+}
+
+// Deletes the tip observations of all nodes older than the timestamp and
+// returns how many were deleted.
+pub async fn delete_tip_observations_before(db: Db, timestamp: u64) -> Result<usize, DbError> {
+    let db_locked = lock(&db).await;
+    let deleted = db_locked.execute(DELETE_STMT_TIP_OBSERVATIONS_BEFORE, params![timestamp])?;
+    Ok(deleted)
+}
+
+// Deletes the external sightings received before the timestamp and returns
+// how many were deleted.
+pub async fn delete_external_sightings_before(db: Db, timestamp: u64) -> Result<usize, DbError> {
+    let db_locked = lock(&db).await;
+    let deleted = db_locked.execute(DELETE_STMT_EXTERNAL_SIGHTINGS_BEFORE, params![timestamp])?;
+    Ok(deleted)
+}
+
+// Starts an incident and returns its id.
+pub async fn start_incident(db: Db, network: u32, reason: &str, now: u64) -> Result<u64, DbError> {
+    let db_locked = lock(&db).await;
+    db_locked.execute(INSERT_STMT_INCIDENT, params![network, reason, now])?;
+    Ok(db_locked.last_insert_rowid() as u64)
+}
+
+// Ends the incident and attaches the bundle. The recordings are part of the
+// bundle and deleted.
+pub async fn end_incident(db: Db, id: u64, now: u64, bundle: &[u8]) -> Result<(), DbError> {
+    let mut db_locked = lock(&db).await;
+    let tx = db_locked.transaction()?;
+    tx.execute(UPDATE_STMT_INCIDENT_ENDED, params![id, now, bundle])?;
+    tx.execute(DELETE_STMT_INCIDENT_RECORDINGS, params![id])?;
+    tx.commit()?;
+    Ok(())
+}
+
+pub async fn write_suppressed_tip(db: Db, tip: &SuppressedTip) -> Result<(), DbError> {
+    let db_locked = lock(&db).await;
+    db_locked.execute(
+        INSERT_STMT_SUPPRESSED_TIP,
+        params![tip.network_id, tip.hash, tip.reason, tip.since],
+    )?;
+    Ok(())
+}
+
+// Returns false if the tip wasn't suppressed.
+pub async fn delete_suppressed_tip(db: Db, network: u32, hash: &str) -> Result<bool, DbError> {
+    let db_locked = lock(&db).await;
+    let deleted = db_locked.execute(DELETE_STMT_SUPPRESSED_TIP, params![network, hash])?;
+    Ok(deleted > 0)
+}
+
+pub async fn load_suppressed_tips(db: Db) -> Result<Vec<SuppressedTip>, DbError> {
+    let db_locked = lock(&db).await;
+    let mut stmt = db_locked.prepare(SELECT_STMT_SUPPRESSED_TIPS)?;
+    let mut rows = stmt.query([])?;
+    let mut tips: Vec<SuppressedTip> = vec![];
+    while let Some(row) = rows.next()? {
+        tips.push(SuppressedTip {
+            network_id: row.get(0)?,
+            hash: row.get(1)?,
+            reason: row.get(2)?,
+            since: row.get(3)?,
+        });
+    }
+    Ok(tips)
+}
+
+pub async fn write_prune_horizon(db: Db, header_store: u32, height: u64) -> Result<(), DbError> {
+    let db_locked = lock(&db).await;
+    db_locked.execute(INSERT_STMT_PRUNE_HORIZON, params![header_store, height])?;
+    Ok(())
+}
+
+// Loads the prune horizon of each header store.
+pub async fn load_prune_horizons(db: Db) -> Result<BTreeMap<u32, u64>, DbError> {
+    let db_locked = lock(&db).await;
+    let mut stmt = db_locked.prepare(SELECT_STMT_PRUNE_HORIZONS)?;
+    let mut rows = stmt.query([])?;
+    let mut horizons = BTreeMap::new();
+    while let Some(row) = rows.next()? {
+        horizons.insert(row.get(0)?, row.get(1)?);
+    }
+    Ok(horizons)
+}
+
+// Loads all incidents, newest first.
+pub async fn load_incidents(db: Db) -> Result<Vec<Incident>, DbError> {
+    let db_locked = lock(&db).await;
+    let mut stmt = db_locked.prepare(SELECT_STMT_INCIDENTS)?;
+    let mut rows = stmt.query([])?;
+    let mut incidents: Vec<Incident> = vec![];
+    while let Some(row) = rows.next()? {
+        incidents.push(Incident {
+            id: row.get(0)?,
+            network_id: row.get(1)?,
+            reason: row.get(2)?,
+            started: row.get(3)?,
+            ended: row.get(4)?,
+        });
+    }
+    Ok(incidents)
+}
+
+// Loads the bundle of an incident. None if the incident doesn't exist or
+// hasn't ended yet.
+pub async fn load_incident_bundle(db: Db, id: u64) -> Result<Option<Vec<u8>>, DbError> {
+    let db_locked = lock(&db).await;
+    let mut stmt = db_locked.prepare(SELECT_STMT_INCIDENT_BUNDLE)?;
+    let mut rows = stmt.query(params![id])?;
+    match rows.next()? {
+        Some(row) => Ok(row.get(0)?),
+        None => Ok(None),
+    }
+}
+
+pub async fn write_incident_recording(
+    db: Db,
+    incident: u64,
+    recording: &IncidentRecording,
+) -> Result<(), DbError> {
+    let db_locked = lock(&db).await;
+    db_locked.execute(
+        INSERT_STMT_INCIDENT_RECORDING,
+        params![
+            incident,
+            recording.timestamp,
+            recording.node_id,
+            recording.kind,
+            recording.data
+        ],
+    )?;
     Ok(())
 }
 
+pub async fn load_incident_recordings(
+    db: Db,
+    incident: u64,
+) -> Result<Vec<IncidentRecording>, DbError> {
+    let db_locked = lock(&db).await;
+    let mut stmt = db_locked.prepare(SELECT_STMT_INCIDENT_RECORDINGS)?;
+    let mut rows = stmt.query(params![incident])?;
+    let mut recordings: Vec<IncidentRecording> = vec![];
+    while let Some(row) = rows.next()? {
+        recordings.push(IncidentRecording {
+            timestamp: row.get(0)?,
+            node_id: row.get(1)?,
+            kind: row.get(2)?,
+            data: row.get(3)?,
+        });
+    }
+    Ok(recordings)
+}
+
+// Writes the event and returns the id and the sequence number assigned to
+// it. The sequence numbers of a network increase monotonically.
+pub async fn write_event(db: Db, event: &Event) -> Result<(u64, u64), DbError> {
+    let db_locked = lock(&db).await;
+    let sequence: u64 = db_locked.query_row(
+        SELECT_STMT_NEXT_EVENT_SEQUENCE,
+        params![event.network_id],
+        |row| row.get(0),
+    )?;
+    db_locked.execute(
+        INSERT_STMT_EVENT,
+        params![
+            event.network_id,
+            event.timestamp,
+            event.kind.to_string(),
+            event.severity.to_string(),
+            event.title,
+            event.description,
+            sequence,
+        ],
+    )?;
+    Ok((db_locked.last_insert_rowid() as u64, sequence))
+}
+
+// Loads the sequence number of the last event of each network.
+pub async fn load_event_sequences(db: Db) -> Result<BTreeMap<u32, u64>, DbError> {
+    let db_locked = lock(&db).await;
+    let mut stmt = db_locked.prepare(SELECT_STMT_EVENT_SEQUENCES)?;
+    let mut rows = stmt.query([])?;
+    let mut sequences = BTreeMap::new();
+    while let Some(row) = rows.next()? {
+        sequences.insert(row.get(0)?, row.get(1)?);
+    }
+    Ok(sequences)
+}
+
+// Loads the events of a network with a sequence number after the given one,
+// oldest first.
+pub async fn load_events_after(
+    db: Db,
+    network: u32,
+    sequence: u64,
+    limit: u32,
+) -> Result<Vec<Event>, DbError> {
+    let db_locked = lock(&db).await;
+    let mut stmt = db_locked.prepare(SELECT_STMT_EVENTS_AFTER)?;
+    let mut rows = stmt.query(params![network, sequence, limit])?;
+    let mut events: Vec<Event> = vec![];
+    while let Some(row) = rows.next()? {
+        events.push(event_from_row(row)?);
+    }
+    Ok(events)
+}
+
+// Loads the events of all networks with an id after the given one, in the
+// order they were written.
+pub async fn load_events_after_id(db: Db, id: u64, limit: u32) -> Result<Vec<Event>, DbError> {
+    let db_locked = lock(&db).await;
+    let mut stmt = db_locked.prepare(SELECT_STMT_EVENTS_AFTER_ID)?;
+    let mut rows = stmt.query(params![id, limit])?;
+    let mut events: Vec<Event> = vec![];
+    while let Some(row) = rows.next()? {
+        events.push(event_from_row(row)?);
+    }
+    Ok(events)
+}
+
+// Loads the most recent events of a network, newest first.
+pub async fn load_recent_events(db: Db, network: u32, limit: u32) -> Result<Vec<Event>, DbError> {
+    let db_locked = lock(&db).await;
+    let mut stmt = db_locked.prepare(SELECT_STMT_RECENT_EVENTS)?;
+    let mut rows = stmt.query(params![network, limit])?;
+    let mut events: Vec<Event> = vec![];
+    while let Some(row) = rows.next()? {
+        events.push(event_from_row(row)?);
+    }
+    Ok(events)
+}
+
+fn event_from_row(row: &Row) -> Result<Event, DbError> {
+    Ok(Event {
+        id: row.get(0)?,
+        network_id: row.get(1)?,
+        timestamp: row.get(2)?,
+        kind: EventKind::from(row.get::<_, String>(3)?),
+        severity: EventSeverity::from(row.get::<_, String>(4)?),
+        title: row.get(5)?,
+        description: row.get(6)?,
+        sequence: row.get(7)?,
+    })
+}
+
 pub async fn write_to_db(
     new_headers: &Vec<HeaderInfo>,
     db: Db,
     network: u32,
 ) -> Result<(), DbError> {
-    let mut db_locked = db.lock().await;
+    let mut db_locked = lock(&db).await;
     let tx = db_locked.transaction()?;
     debug!(
         "inserting {} headers from network {} into the database..",
@@ -63,14 +1784,15 @@ pub async fn write_to_db(
     for info in new_headers {
         tx.execute(
             "INSERT OR IGNORE INTO headers
-                   (height, network, hash, header, miner)
-                   values (?1, ?2, ?3, ?4, ?5)",
-            &[
+                   (height, network, hash, header, miner, source)
+                   values (?1, ?2, ?3, ?4, ?5, ?6)",
+            [
                 &info.height.to_string(),
                 &network.to_string(),
-                &info.header.block_hash().to_string(),
-                &bitcoin::consensus::encode::serialize_hex(&info.header),
+                &info.block_hash().to_string(),
+                &info.header_hex(),
                 &info.miner,
+                &info.source.to_string(),
             ],
         )?;
     }
@@ -83,36 +1805,76 @@ pub async fn write_to_db(
     Ok(())
 }
 
-pub async fn update_miner(db: Db, hash: &BlockHash, miner: String) -> Result<(), DbError> {
-    let mut db_locked = db.lock().await;
+// Deletes the headers of a network, e.g. pruned stale branches, and returns
+// how many were deleted.
+pub async fn delete_headers(db: Db, network: u32, hashes: &[BlockHash]) -> Result<usize, DbError> {
+    let mut db_locked = lock(&db).await;
+    let tx = db_locked.transaction()?;
+    let mut deleted = 0;
+    for hash in hashes {
+        deleted += tx.execute(DELETE_STMT_HEADER, params![network, hash.to_string()])?;
+    }
+    tx.commit()?;
+    Ok(deleted)
+}
+
+// Returns the free pages of the database to the file system and truncates
+// the write-ahead-log. Unlike a VACUUM, this doesn't rebuild the database,
+// so it's quick and needs no free disk space. A database created before
+// incremental auto-vacuum was enabled is rebuilt with a VACUUM once instead.
+pub async fn compact(db: Db) -> Result<(), DbError> {
+    let db_locked = lock(&db).await;
+    let auto_vacuum: u32 = db_locked.query_row("PRAGMA auto_vacuum", [], |row| row.get(0))?;
+    if auto_vacuum != AUTO_VACUUM_INCREMENTAL {
+        info!("Enabling incremental auto-vacuum on the database. This might take a while..");
+        db_locked.execute("VACUUM", [])?;
+    }
+    db_locked.execute_batch("PRAGMA wal_checkpoint(TRUNCATE); PRAGMA incremental_vacuum;")?;
+    Ok(())
+}
+
+pub async fn update_miner(
+    db: Db,
+    hash: &BlockHash,
+    miner: String,
+    own: bool,
+) -> Result<(), DbError> {
+    let mut db_locked = lock(&db).await;
     let tx = db_locked.transaction()?;
 
-    tx.execute(UPDATE_STMT_HEADER_MINER, [miner, hash.to_string()])?;
+    tx.execute(
+        UPDATE_STMT_HEADER_MINER,
+        params![miner, own, hash.to_string()],
+    )?;
     tx.commit()?;
     Ok(())
 }
 
 // Loads header and tip information for a specified network from the DB and
 // builds a header-tree from it.
-pub async fn load_treeinfos(db: Db, network: u32) -> Result<TreeInfo, DbError> {
-    let header_infos = load_header_infos(db, network).await?;
+pub async fn load_treeinfos(
+    db: Db,
+    network: u32,
+    chain_kind: ChainKind,
+) -> Result<TreeInfo, DbError> {
+    let header_infos = load_header_infos(db, network, chain_kind).await?;
 
-    let mut tree: DiGraph<HeaderInfo, bool> = DiGraph::new();
+    let mut tree: DiGraph<HeaderInfo, u64> = DiGraph::new();
     let mut hash_index_map: HashMap<BlockHash, NodeIndex> = HashMap::new();
     info!("building header tree for network {}..", network);
     // add headers as nodes
     for h in header_infos.clone() {
         let idx = tree.add_node(h.clone());
-        hash_index_map.insert(h.header.block_hash(), idx);
+        hash_index_map.insert(h.block_hash(), idx);
     }
     info!(".. added headers from network {}", network);
     // add prev-current block relationships as edges
     for current in header_infos {
         let idx_current = hash_index_map
-            .get(&current.header.block_hash())
+            .get(&current.block_hash())
             .expect("current header should be in the map as we just inserted it");
         match hash_index_map.get(&current.header.prev_blockhash) {
-            Some(idx_prev) => tree.update_edge(*idx_prev, *idx_current, false),
+            Some(idx_prev) => tree.update_edge(*idx_prev, *idx_current, 0),
             None => continue,
         };
     }
@@ -120,25 +1882,34 @@ pub async fn load_treeinfos(db: Db, network: u32) -> Result<TreeInfo, DbError> {
         ".. added relationships between headers from network {}",
         network
     );
-    let root_nodes = tree.externals(petgraph::Direction::Incoming).count();
+    let roots: Vec<NodeIndex> = tree.externals(petgraph::Direction::Incoming).collect();
     info!(
         "done building header tree for network {}: roots={}, tips={}",
         network,
-        root_nodes,                                            // root nodes
+        roots.len(),                                           // root nodes
         tree.externals(petgraph::Direction::Outgoing).count(), // tip nodes
     );
-    if root_nodes > 1 {
+    if roots.len() > 1 {
         warn!(
             "header-tree for network {} has more than one ({}) root!",
-            network, root_nodes
+            network,
+            roots.len()
         );
     }
-    Ok((tree, hash_index_map))
+    let mut tree_info: TreeInfo = (tree, hash_index_map, HashMap::new());
+    for root in roots {
+        headertree::accumulate_chainwork(&mut tree_info, root);
+    }
+    Ok(tree_info)
 }
 
-async fn load_header_infos(db: Db, network: u32) -> Result<Vec<HeaderInfo>, DbError> {
+async fn load_header_infos(
+    db: Db,
+    network: u32,
+    chain_kind: ChainKind,
+) -> Result<Vec<HeaderInfo>, DbError> {
     info!("loading headers for network {} from database..", network);
-    let db_locked = db.lock().await;
+    let db_locked = lock(&db).await;
 
     let mut stmt = db_locked.prepare(SELECT_STMT_HEADER_HEIGHT)?;
 
@@ -148,11 +1919,22 @@ async fn load_header_infos(db: Db, network: u32) -> Result<Vec<HeaderInfo>, DbEr
     while let Some(row) = rows.next()? {
         let header_hex: String = row.get(1)?;
         let header_bytes = hex::decode(&header_hex)?;
-        let header = bitcoin::consensus::deserialize(&header_bytes)?;
+        // Elements headers are stored as they are, next to the fields shared
+        // with Bitcoin headers used everywhere else.
+        let (header, elements) = match chain_kind {
+            ChainKind::Bitcoin => (bitcoin::consensus::deserialize(&header_bytes)?, None),
+            ChainKind::Elements => {
+                let elements: ElementsHeader = bitcoin::consensus::deserialize(&header_bytes)?;
+                (elements.to_header(), Some(Box::new(elements)))
+            }
+        };
         headers.push(HeaderInfo {
             height: row.get(0)?,
             header,
             miner: row.get(2)?,
+            own: row.get(3)?,
+            source: HeaderSource::from(row.get::<_, String>(4)?),
+            elements,
         });
     }
 
@@ -164,3 +1946,290 @@ async fn load_header_infos(db: Db, network: u32) -> Result<Vec<HeaderInfo>, DbEr
 
     Ok(headers)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use tokio::sync::Mutex;
+
+    fn tip(height: u64, hash: &str, status: ChainTipStatus) -> ChainTip {
+        ChainTip {
+            height,
+            hash: hash.to_string(),
+            branchlen: 0,
+            status,
+        }
+    }
+
+    fn user_version(connection: &Connection) -> u32 {
+        connection
+            .query_row("PRAGMA user_version", [], |row| row.get(0))
+            .unwrap()
+    }
+
+    fn has_column(connection: &Connection, table: &str, column: &str) -> bool {
+        connection
+            .prepare(&format!("SELECT {} FROM {}", column, table))
+            .is_ok()
+    }
+
+    #[tokio::test]
+    async fn test_migrations() {
+        // a database of schema version 2
+        let connection = Connection::open_in_memory().unwrap();
+        connection.execute(CREATE_STMT_TABLE_HEADERS, []).unwrap();
+        connection.execute(CREATE_STMT_TABLE_EVENTS, []).unwrap();
+        connection.pragma_update(None, "user_version", 2).unwrap();
+        let db: Db = Arc::new(Mutex::new(connection));
+        setup_db(db.clone()).await.unwrap();
+        {
+            let connection = db.lock().await;
+            assert_eq!(user_version(&connection), DB_SCHEMA_VERSION);
+            assert!(has_column(&connection, "headers", "own"));
+            assert!(has_column(&connection, "headers", "source"));
+            assert!(has_column(&connection, "events", "sequence"));
+        }
+        // a migrated database is left as it is
+        setup_db(db).await.unwrap();
+
+        // a failing migration keeps the completed ones
+        let connection = Connection::open_in_memory().unwrap();
+        connection.execute(CREATE_STMT_TABLE_HEADERS, []).unwrap();
+        connection.execute(CREATE_STMT_TABLE_EVENTS, []).unwrap();
+        connection
+            .execute(MIGRATION_STMT_EVENTS_SEQUENCE, [])
+            .unwrap();
+        connection.pragma_update(None, "user_version", 2).unwrap();
+        let db: Db = Arc::new(Mutex::new(connection));
+        assert!(setup_db(db.clone()).await.is_err());
+        let connection = db.lock().await;
+        assert_eq!(user_version(&connection), 3);
+        assert!(has_column(&connection, "headers", "own"));
+        assert!(!has_column(&connection, "headers", "source"));
+        // the failed migration was rolled back
+        assert!(connection.is_autocommit());
+    }
+
+    #[tokio::test]
+    async fn test_auto_vacuum() {
+        let connection = Connection::open_in_memory().unwrap();
+        // an existing database without auto-vacuum
+        connection.execute(CREATE_STMT_TABLE_HEADERS, []).unwrap();
+        let db: Db = Arc::new(Mutex::new(connection));
+        let auto_vacuum = |db: Db| async move {
+            db.lock()
+                .await
+                .query_row("PRAGMA auto_vacuum", [], |row| row.get::<_, u32>(0))
+                .unwrap()
+        };
+        // isn't rebuilt on startup
+        setup_db(db.clone()).await.unwrap();
+        assert_eq!(auto_vacuum(db.clone()).await, 0);
+        compact(db.clone()).await.unwrap();
+        assert_eq!(auto_vacuum(db.clone()).await, AUTO_VACUUM_INCREMENTAL);
+        compact(db).await.unwrap();
+
+        // a new database
+        let db: Db = Arc::new(Mutex::new(Connection::open_in_memory().unwrap()));
+        setup_db(db.clone()).await.unwrap();
+        assert_eq!(auto_vacuum(db).await, AUTO_VACUUM_INCREMENTAL);
+    }
+
+    #[tokio::test]
+    async fn test_header_source() {
+        let db: Db = Arc::new(Mutex::new(Connection::open_in_memory().unwrap()));
+        setup_db(db.clone()).await.unwrap();
+        let mut genesis = crate::testutil::child(None, 1000, 0);
+        genesis.source = HeaderSource::P2P;
+        let mut relayed = crate::testutil::child(Some(&genesis), 1600, 0);
+        relayed.source = HeaderSource::Federation;
+        write_to_db(&vec![genesis.clone(), relayed.clone()], db.clone(), 1)
+            .await
+            .unwrap();
+        // an older header without a recorded source
+        db.lock()
+            .await
+            .execute(
+                "UPDATE headers SET source = 'something else' WHERE hash = ?1",
+                params![relayed.block_hash().to_string()],
+            )
+            .unwrap();
+
+        let header_infos = load_header_infos(db, 1, ChainKind::Bitcoin).await.unwrap();
+        let source = |hash: BlockHash| {
+            header_infos
+                .iter()
+                .find(|h| h.block_hash() == hash)
+                .unwrap()
+                .source
+        };
+        assert_eq!(source(genesis.block_hash()), HeaderSource::P2P);
+        assert_eq!(source(relayed.block_hash()), HeaderSource::Unknown);
+    }
+
+    #[tokio::test]
+    async fn test_node_tips() {
+        let db: Db = Arc::new(Mutex::new(Connection::open_in_memory().unwrap()));
+        setup_db(db.clone()).await.unwrap();
+        let tips = vec![
+            tip(101, "a", ChainTipStatus::Active),
+            tip(100, "b", ChainTipStatus::ValidFork),
+        ];
+        write_node_tips(db.clone(), 1, 0, 1000, &tips)
+            .await
+            .unwrap();
+        write_node_tips(db.clone(), 1, 1, 1000, &tips)
+            .await
+            .unwrap();
+        write_node_tips(db.clone(), 2, 0, 1000, &tips)
+            .await
+            .unwrap();
+        // replaces the previous tips of the node
+        write_node_tips(db.clone(), 1, 1, 2000, &tips[..1])
+            .await
+            .unwrap();
+
+        let node_tips = load_node_tips(db.clone(), 1).await.unwrap();
+        assert_eq!(node_tips.len(), 2);
+        assert_eq!(node_tips[&0], (1000, tips.clone()));
+        assert_eq!(node_tips[&1], (2000, tips[..1].to_vec()));
+
+        purge_node(db.clone(), 1, 0).await.unwrap();
+        let node_tips = load_node_tips(db.clone(), 1).await.unwrap();
+        assert!(!node_tips.contains_key(&0));
+        assert_eq!(load_node_tips(db.clone(), 2).await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_external_sightings() {
+        use bitcoincore_rpc::bitcoin::hashes::Hash;
+
+        let db: Db = Arc::new(Mutex::new(Connection::open_in_memory().unwrap()));
+        setup_db(db.clone()).await.unwrap();
+        let sighting = |timestamp: u64| Sighting {
+            hash: BlockHash::all_zeros(),
+            height: None,
+            timestamp,
+        };
+        let count = |db: Db, observer: &'static str| async move {
+            db.lock()
+                .await
+                .query_row(
+                    "SELECT count(*) FROM external_sightings WHERE observer = ?1",
+                    params![observer],
+                    |row| row.get::<_, u64>(0),
+                )
+                .unwrap()
+        };
+        let sightings = [sighting(10), sighting(20), sighting(30)];
+        write_external_sightings(db.clone(), 1, "a", 100, &sightings, 2)
+            .await
+            .unwrap();
+        write_external_sightings(db.clone(), 1, "b", 200, &sightings[..1], 2)
+            .await
+            .unwrap();
+        assert_eq!(count(db.clone(), "a").await, 2);
+        assert_eq!(count(db.clone(), "b").await, 1);
+        // the oldest sighting of a was dropped
+        let seen_by = load_seen_by(db.clone(), 1, &BlockHash::all_zeros().to_string())
+            .await
+            .unwrap();
+        assert!(seen_by
+            .iter()
+            .any(|s| s.observer == Observer::External("a".to_string()) && s.timestamp == 20));
+
+        assert_eq!(
+            delete_external_sightings_before(db.clone(), 150)
+                .await
+                .unwrap(),
+            2
+        );
+        assert_eq!(count(db.clone(), "b").await, 1);
+    }
+
+    #[tokio::test]
+    async fn test_first_reports() {
+        let db: Db = Arc::new(Mutex::new(Connection::open_in_memory().unwrap()));
+        setup_db(db.clone()).await.unwrap();
+        let hashes = vec!["a".to_string(), "b".to_string()];
+        write_first_reports(db.clone(), 1, 0, 1000, &hashes[..1])
+            .await
+            .unwrap();
+        // only the first report of a block is kept
+        write_first_reports(db.clone(), 1, 1, 2000, &hashes)
+            .await
+            .unwrap();
+        write_first_reports(db.clone(), 2, 1, 3000, &hashes[..1])
+            .await
+            .unwrap();
+
+        let first_reports = load_first_reports(db.clone(), 1, &hashes).await.unwrap();
+        assert_eq!(
+            first_reports["a"],
+            FirstReport {
+                node: 0,
+                timestamp: 1000
+            }
+        );
+        assert_eq!(
+            first_reports["b"],
+            FirstReport {
+                node: 1,
+                timestamp: 2000
+            }
+        );
+
+        purge_node(db.clone(), 1, 0).await.unwrap();
+        let first_reports = load_first_reports(db.clone(), 1, &hashes).await.unwrap();
+        assert!(!first_reports.contains_key("a"));
+        assert_eq!(load_first_reports(db, 2, &hashes).await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_tip_history() {
+        let db: Db = Arc::new(Mutex::new(Connection::open_in_memory().unwrap()));
+        setup_db(db.clone()).await.unwrap();
+        let tips = vec![
+            tip(101, "a", ChainTipStatus::Active),
+            tip(100, "b", ChainTipStatus::ValidFork),
+        ];
+        write_tip_history(db.clone(), 1, 0, 1000, &tips)
+            .await
+            .unwrap();
+        write_tip_history(db.clone(), 1, 1, 2000, &tips[..1])
+            .await
+            .unwrap();
+        write_tip_history(db.clone(), 2, 0, 1500, &tips)
+            .await
+            .unwrap();
+
+        let history = load_tip_history(db.clone(), 1, None, 0, 3000, 10)
+            .await
+            .unwrap();
+        assert_eq!(history.len(), 3);
+        assert_eq!(history[0].node_id, 0);
+        assert_eq!(history[0].hash, "a");
+        assert_eq!(history[1].status, ChainTipStatus::ValidFork);
+        assert_eq!(history[2].timestamp, 2000);
+        let history = load_tip_history(db.clone(), 1, Some(1), 0, 3000, 10)
+            .await
+            .unwrap();
+        assert_eq!(history.len(), 1);
+        assert_eq!(history[0].node_id, 1);
+        let history = load_tip_history(db.clone(), 1, None, 1001, 3000, 10)
+            .await
+            .unwrap();
+        assert_eq!(history.len(), 1);
+        let history = load_tip_history(db.clone(), 1, None, 0, 3000, 1)
+            .await
+            .unwrap();
+        assert_eq!(history.len(), 1);
+
+        purge_node(db.clone(), 1, 0).await.unwrap();
+        let history = load_tip_history(db.clone(), 1, None, 0, 3000, 10)
+            .await
+            .unwrap();
+        assert_eq!(history.len(), 1);
+    }
+}
@@ -0,0 +1,593 @@
+// In-memory domain model shared between the fetching, storage and API
+// parts of fork-observer. These types intentionally don't implement serde
+// traits: the API representations live in `types` and the storage
+// representations in `db`, each with their own conversions, so that
+// neither format is tied to the in-memory layout.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use bitcoincore_rpc::bitcoin::blockdata::block::Header;
+use bitcoincore_rpc::bitcoin::consensus::encode::serialize_hex;
+use bitcoincore_rpc::bitcoin::{BlockHash, Work};
+use bitcoincore_rpc::json::{GetChainTipsResultStatus, GetChainTipsResultTip};
+use petgraph::graph::DiGraph;
+use petgraph::graph::NodeIndex;
+use tokio::sync::Mutex;
+
+use crate::elements::ElementsHeader;
+use crate::retry::RetryPolicy;
+
+// The headers, their index by hash and the cumulative work of the headers
+// from the first header of their branch in the tree, see
+// headertree::accumulate_chainwork(). An edge counts the headers between
+// its two headers that were collapsed by headertree::compact().
+pub type TreeInfo = (
+    DiGraph<HeaderInfo, u64>,
+    HashMap<BlockHash, NodeIndex>,
+    HashMap<BlockHash, Work>,
+);
+pub type Tree = Arc<Mutex<TreeInfo>>;
+
+#[derive(Clone)]
+pub struct NodeInfo {
+    pub id: u32,
+    pub name: String,
+    pub description: String,
+    pub implementation: String,
+    /// Weight of the node's active tip in the consensus tip computation.
+    pub trust: f64,
+    /// Shadow nodes are polled and displayed, but excluded from the
+    /// consensus, alerting, and statistics.
+    pub shadow: bool,
+    /// Seconds after the node's removal from the configuration until its
+    /// historical data is purged. None keeps the data of the retired node.
+    pub purge_after: Option<u64>,
+    /// Retries of failed fetches within a query cycle.
+    pub retry: RetryPolicy,
+}
+
+impl fmt::Display for NodeInfo {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Node(id={}, name='{}', implementation='{}')",
+            self.id, self.name, self.implementation
+        )
+    }
+}
+
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub struct HeaderInfo {
+    pub height: u64,
+    pub header: Header,
+    pub miner: String,
+    /// If the block was mined by the instance operator. See OwnMining.
+    pub own: bool,
+    /// How the header was first received.
+    pub source: HeaderSource,
+    /// The full header of blocks on Elements chains. The header above then
+    /// only holds the fields shared with Bitcoin headers.
+    pub elements: Option<Box<ElementsHeader>>,
+}
+
+// The kind of chain a network follows. Elements chains, e.g. Liquid, have
+// their own header format and block hashes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChainKind {
+    Bitcoin,
+    Elements,
+}
+
+impl FromStr for ChainKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "bitcoin" => Ok(ChainKind::Bitcoin),
+            "elements" => Ok(ChainKind::Elements),
+            _ => Err(format!("unknown chain kind '{}'", s)),
+        }
+    }
+}
+
+impl fmt::Display for ChainKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ChainKind::Bitcoin => write!(f, "bitcoin"),
+            ChainKind::Elements => write!(f, "elements"),
+        }
+    }
+}
+
+// How a header was received: from a node via RPC, its REST interface or the
+// P2P protocol, from another fork-observer instance, or from a replay.
+// Headers stored before the source was recorded are unknown.
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+pub enum HeaderSource {
+    Rpc,
+    Rest,
+    P2P,
+    Federation,
+    Replay,
+    Unknown,
+}
+
+impl From<String> for HeaderSource {
+    fn from(s: String) -> Self {
+        match s.as_str() {
+            "rpc" => HeaderSource::Rpc,
+            "rest" => HeaderSource::Rest,
+            "p2p" => HeaderSource::P2P,
+            "federation" => HeaderSource::Federation,
+            "replay" => HeaderSource::Replay,
+            _ => HeaderSource::Unknown,
+        }
+    }
+}
+
+impl HeaderSource {
+    /// If the header was received from a node of this instance rather than
+    /// relayed by another fork-observer instance or replayed.
+    pub fn is_direct(&self) -> bool {
+        matches!(
+            self,
+            HeaderSource::Rpc | HeaderSource::Rest | HeaderSource::P2P
+        )
+    }
+}
+
+impl fmt::Display for HeaderSource {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            HeaderSource::Rpc => write!(f, "rpc"),
+            HeaderSource::Rest => write!(f, "rest"),
+            HeaderSource::P2P => write!(f, "p2p"),
+            HeaderSource::Federation => write!(f, "federation"),
+            HeaderSource::Replay => write!(f, "replay"),
+            HeaderSource::Unknown => write!(f, "unknown"),
+        }
+    }
+}
+
+impl HeaderInfo {
+    pub fn block_hash(&self) -> BlockHash {
+        match self.elements {
+            Some(ref elements) => elements.block_hash(),
+            None => self.header.block_hash(),
+        }
+    }
+
+    // The header as serialized by the node.
+    pub fn header_hex(&self) -> String {
+        match self.elements {
+            Some(ref elements) => serialize_hex(elements.as_ref()),
+            None => serialize_hex(&self.header),
+        }
+    }
+
+    pub fn update_miner(&mut self, miner: String, own: bool) {
+        self.miner = miner;
+        self.own = own;
+    }
+}
+
+// A summary of a getblocktemplate result.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BlockTemplate {
+    pub height: u64,
+    /// Sum of the fees of the template transactions in sat.
+    pub total_fees: u64,
+    pub tx_count: usize,
+}
+
+// The resource usage reported by a node's uptime and getmemoryinfo RPCs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NodeResources {
+    /// Seconds since the node started.
+    pub uptime: u64,
+    /// Bytes of the node's locked memory pool (getmemoryinfo "locked").
+    pub memory_used: u64,
+    pub memory_free: u64,
+    pub memory_total: u64,
+    /// Bytes the node could lock in memory. Less than memory_total means
+    /// the mlock limit of the node is too low.
+    pub memory_locked: u64,
+}
+
+#[derive(Debug, Clone)]
+pub struct Fork {
+    pub common: HeaderInfo,
+    pub children: Vec<HeaderInfo>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum EventKind {
+    Halving,
+    HalvingCountdown,
+    DifficultyAdjustment,
+    DifficultyAdjustmentCountdown,
+    StaleBlock,
+    // A block mined by the instance operator became stale.
+    OwnStaleBlock,
+    TreeQuotaExceeded,
+    // The block templates of the nodes diverge, see templates.
+    MempoolDivergence,
+    // Blocks were found unusually fast or slow, see anomaly.
+    BlockIntervalAnomaly,
+    // A header has a time far in the future or close to the median time
+    // past, see timestamps.
+    TimestampAnomaly,
+    // A header shares its merkle root with another header, or a node
+    // reported a block at another height than known, see duplicates.
+    DuplicateMerkleRoot,
+    ConflictingHeight,
+    // A header met the condition of an operator-defined canary, see canary.
+    Canary,
+    // An operator switched a network into or out of incident mode.
+    IncidentStarted,
+    IncidentEnded,
+    // A node returned block data inconsistent with the block's header.
+    DataIntegrity,
+    // A node knows of headers far ahead of its validated tip, see headergap.
+    NodeStalled,
+    // The node couldn't be queried, or could be queried again.
+    NodeUnreachable,
+    NodeReachable,
+    // No tip has the majority of the node trust, see consensus.
+    NetworkPartition,
+    Unknown,
+}
+
+impl From<String> for EventKind {
+    fn from(s: String) -> Self {
+        match s.as_str() {
+            "halving" => EventKind::Halving,
+            "halving-countdown" => EventKind::HalvingCountdown,
+            "difficulty-adjustment" => EventKind::DifficultyAdjustment,
+            "difficulty-adjustment-countdown" => EventKind::DifficultyAdjustmentCountdown,
+            "stale-block" => EventKind::StaleBlock,
+            "own-stale-block" => EventKind::OwnStaleBlock,
+            "tree-quota-exceeded" => EventKind::TreeQuotaExceeded,
+            "mempool-divergence" => EventKind::MempoolDivergence,
+            "block-interval-anomaly" => EventKind::BlockIntervalAnomaly,
+            "timestamp-anomaly" => EventKind::TimestampAnomaly,
+            "duplicate-merkle-root" => EventKind::DuplicateMerkleRoot,
+            "conflicting-height" => EventKind::ConflictingHeight,
+            "canary" => EventKind::Canary,
+            "incident-started" => EventKind::IncidentStarted,
+            "incident-ended" => EventKind::IncidentEnded,
+            "data-integrity" => EventKind::DataIntegrity,
+            "node-stalled" => EventKind::NodeStalled,
+            "node-unreachable" => EventKind::NodeUnreachable,
+            "node-reachable" => EventKind::NodeReachable,
+            "network-partition" => EventKind::NetworkPartition,
+            _ => EventKind::Unknown,
+        }
+    }
+}
+
+impl fmt::Display for EventKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EventKind::Halving => write!(f, "halving"),
+            EventKind::HalvingCountdown => write!(f, "halving-countdown"),
+            EventKind::DifficultyAdjustment => write!(f, "difficulty-adjustment"),
+            EventKind::DifficultyAdjustmentCountdown => {
+                write!(f, "difficulty-adjustment-countdown")
+            }
+            EventKind::StaleBlock => write!(f, "stale-block"),
+            EventKind::OwnStaleBlock => write!(f, "own-stale-block"),
+            EventKind::TreeQuotaExceeded => write!(f, "tree-quota-exceeded"),
+            EventKind::MempoolDivergence => write!(f, "mempool-divergence"),
+            EventKind::BlockIntervalAnomaly => write!(f, "block-interval-anomaly"),
+            EventKind::TimestampAnomaly => write!(f, "timestamp-anomaly"),
+            EventKind::DuplicateMerkleRoot => write!(f, "duplicate-merkle-root"),
+            EventKind::ConflictingHeight => write!(f, "conflicting-height"),
+            EventKind::Canary => write!(f, "canary"),
+            EventKind::IncidentStarted => write!(f, "incident-started"),
+            EventKind::IncidentEnded => write!(f, "incident-ended"),
+            EventKind::DataIntegrity => write!(f, "data-integrity"),
+            EventKind::NodeStalled => write!(f, "node-stalled"),
+            EventKind::NodeUnreachable => write!(f, "node-unreachable"),
+            EventKind::NodeReachable => write!(f, "node-reachable"),
+            EventKind::NetworkPartition => write!(f, "network-partition"),
+            EventKind::Unknown => write!(f, "unknown"),
+        }
+    }
+}
+
+// Ordered from least to most severe.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum EventSeverity {
+    Info,
+    Warning,
+    Critical,
+}
+
+impl From<String> for EventSeverity {
+    fn from(s: String) -> Self {
+        match s.as_str() {
+            "warning" => EventSeverity::Warning,
+            "critical" => EventSeverity::Critical,
+            _ => EventSeverity::Info,
+        }
+    }
+}
+
+impl FromStr for EventSeverity {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "info" => Ok(EventSeverity::Info),
+            "warning" => Ok(EventSeverity::Warning),
+            "critical" => Ok(EventSeverity::Critical),
+            _ => Err(format!("unknown event severity '{}'", s)),
+        }
+    }
+}
+
+impl fmt::Display for EventSeverity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EventSeverity::Info => write!(f, "info"),
+            EventSeverity::Warning => write!(f, "warning"),
+            EventSeverity::Critical => write!(f, "critical"),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Event {
+    /// Assigned by the database when the event is written.
+    pub id: u64,
+    pub network_id: u32,
+    /// UTC timestamp when the event was emitted.
+    pub timestamp: u64,
+    pub kind: EventKind,
+    pub severity: EventSeverity,
+    pub title: String,
+    pub description: String,
+    /// Assigned by the database when the event is written. Increases
+    /// monotonically per network.
+    pub sequence: u64,
+}
+
+#[derive(Clone, Debug)]
+pub struct QueuedNotification {
+    pub id: u64,
+    /// Name of the notification sink the notification is delivered to.
+    pub sink: String,
+    /// The request body sent to the sink.
+    pub payload: String,
+    pub attempts: u32,
+    /// UTC timestamp of the next delivery attempt.
+    pub next_attempt: u64,
+    pub last_error: String,
+    /// UTC timestamp when the notification was queued.
+    pub created: u64,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ChainTipStatus {
+    Active,
+    Invalid,
+    ValidFork,
+    HeadersOnly,
+    ValidHeaders,
+    Unknown,
+}
+
+impl From<String> for ChainTipStatus {
+    fn from(s: String) -> Self {
+        match s.as_str() {
+            "active" => ChainTipStatus::Active,
+            "invalid" => ChainTipStatus::Invalid,
+            "headers-only" => ChainTipStatus::HeadersOnly,
+            "valid-headers" => ChainTipStatus::ValidHeaders,
+            "valid-fork" => ChainTipStatus::ValidFork,
+            _ => ChainTipStatus::Unknown,
+        }
+    }
+}
+
+impl From<GetChainTipsResultStatus> for ChainTipStatus {
+    fn from(s: GetChainTipsResultStatus) -> Self {
+        match s {
+            GetChainTipsResultStatus::Active => ChainTipStatus::Active,
+            GetChainTipsResultStatus::Invalid => ChainTipStatus::Invalid,
+            GetChainTipsResultStatus::HeadersOnly => ChainTipStatus::HeadersOnly,
+            GetChainTipsResultStatus::ValidHeaders => ChainTipStatus::ValidHeaders,
+            GetChainTipsResultStatus::ValidFork => ChainTipStatus::ValidFork,
+        }
+    }
+}
+
+impl fmt::Display for ChainTipStatus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ChainTipStatus::Active => write!(f, "active"),
+            ChainTipStatus::Invalid => write!(f, "invalid"),
+            ChainTipStatus::HeadersOnly => write!(f, "headers-only"),
+            ChainTipStatus::ValidHeaders => write!(f, "valid-headers"),
+            ChainTipStatus::ValidFork => write!(f, "valid-fork"),
+            ChainTipStatus::Unknown => write!(f, "unknown"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChainTip {
+    pub height: u64,
+    pub hash: String,
+    pub branchlen: usize,
+    pub status: ChainTipStatus,
+}
+
+impl From<GetChainTipsResultTip> for ChainTip {
+    fn from(t: GetChainTipsResultTip) -> Self {
+        ChainTip {
+            height: t.height,
+            hash: t.hash.to_string(),
+            branchlen: t.branch_length,
+            status: t.status.into(),
+        }
+    }
+}
+
+impl ChainTip {
+    pub fn block_hash(&self) -> BlockHash {
+        BlockHash::from_str(&self.hash).unwrap()
+    }
+}
+
+// A chain tip as reported by a node at a point in time. Recorded whenever
+// the tips of a node change.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TipObservation {
+    pub network_id: u32,
+    pub node_id: u32,
+    /// UTC timestamp of the observation.
+    pub timestamp: u64,
+    pub height: u64,
+    pub hash: String,
+    pub status: ChainTipStatus,
+}
+
+// The size, weight, number of transactions and fees of a block, recorded
+// for the blocks around forks.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BlockStats {
+    pub hash: String,
+    /// Serialized size in bytes, including the witness data.
+    pub size: u64,
+    pub weight: u64,
+    pub tx_count: u64,
+    /// The outputs of the coinbase minus the block subsidy, in sat.
+    pub fees: u64,
+}
+
+// A switch of a node's active tip to a block not building on the previous
+// one. The blocks of the previous active chain above the common ancestor
+// were disconnected by the node.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Reorg {
+    /// Assigned by the database when the reorg is written.
+    pub id: u64,
+    pub network_id: u32,
+    pub node_id: u32,
+    /// UTC timestamp when the reorg was detected.
+    pub timestamp: u64,
+    pub old_tip_hash: String,
+    pub old_tip_height: u64,
+    /// The header time of the old tip.
+    pub old_tip_time: u32,
+    pub new_tip_hash: String,
+    pub new_tip_height: u64,
+    /// The header time of the new tip.
+    pub new_tip_time: u32,
+    pub common_ancestor_hash: String,
+    pub common_ancestor_height: u64,
+    /// The number of disconnected blocks.
+    pub depth: u64,
+}
+
+// A node that was removed from the configuration. Its historical data is
+// kept until it's purged according to the purge_after of the node when it
+// was last configured.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RetiredNode {
+    pub network_id: u32,
+    pub node_id: u32,
+    pub name: String,
+    pub description: String,
+    pub implementation: String,
+    /// UTC timestamp of the first startup without the node.
+    pub retired: u64,
+    pub purge_after: Option<u64>,
+}
+
+impl RetiredNode {
+    pub fn purge_at(&self) -> Option<u64> {
+        self.purge_after.map(|after| self.retired + after)
+    }
+}
+
+// A window in which a network was in incident mode. The collected data is
+// attached as bundle once the incident ends.
+// A chain tip an admin marked as noise, e.g. an ancient headers-only tip a
+// node keeps reporting. It's dropped from the tips of the nodes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SuppressedTip {
+    pub network_id: u32,
+    pub hash: String,
+    pub reason: String,
+    /// UTC timestamp when the tip was suppressed.
+    pub since: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Incident {
+    pub id: u64,
+    pub network_id: u32,
+    pub reason: String,
+    /// UTC timestamp when incident mode was switched on.
+    pub started: u64,
+    /// UTC timestamp when incident mode was switched off. Not set while the
+    /// incident is ongoing.
+    pub ended: Option<u64>,
+}
+
+// Raw data recorded from a node during an incident, e.g. a getchaintips
+// response.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IncidentRecording {
+    pub timestamp: u64,
+    pub node_id: u32,
+    pub kind: String,
+    pub data: String,
+}
+
+// A block sighting submitted by an external observer.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Sighting {
+    pub hash: BlockHash,
+    pub height: Option<u64>,
+    /// UTC timestamp when the observer saw the block.
+    pub timestamp: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Observer {
+    /// A node of the network by id.
+    Node(u32),
+    /// An external observer by name.
+    External(String),
+}
+
+// Why a block of an invalid tip was rejected, as a reject reason of Bitcoin
+// Core, and which node reported the tip when.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rejection {
+    pub hash: String,
+    pub node: u32,
+    pub reason: String,
+    pub timestamp: u64,
+}
+
+// The node that first reported a block to the instance and when.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FirstReport {
+    pub node: u32,
+    pub timestamp: u64,
+}
+
+// When an observer first saw a block.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SeenBy {
+    pub observer: Observer,
+    pub timestamp: u64,
+}
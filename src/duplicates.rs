@@ -0,0 +1,311 @@
+use std::collections::{HashMap, HashSet};
+use std::str::FromStr;
+
+use bitcoincore_rpc::bitcoin::{BlockHash, TxMerkleNode};
+
+use crate::domain::{ChainTip, EventKind, EventSeverity, HeaderInfo, NodeInfo, Tree, TreeInfo};
+use crate::events::EventLog;
+
+// The mainnet blocks 91842 and 91880 duplicate the coinbase transaction of
+// an earlier block from before BIP34. As their coinbase is their only
+// transaction, they also share the merkle root of that block. They are the
+// exceptions to BIP30 in Bitcoin Core.
+const KNOWN_DUPLICATES: [&str; 2] = [
+    "00000000000a4d0a398161ffc163c503763b1f4360639393e0e4c8e300e0caec",
+    "00000000000743f190a18c5577a3c2d2a1f610ae9601ac046a38084ccb7cd721",
+];
+
+fn is_known_duplicate(hash: &BlockHash) -> bool {
+    KNOWN_DUPLICATES
+        .iter()
+        .any(|known| BlockHash::from_str(known).is_ok_and(|known| known == *hash))
+}
+
+// The pairs of a header and another header in the tree with the same merkle
+// root. Two blocks committing to the same transactions can't both be valid
+// unless they share a template, which is worth a look either way. The known
+// duplicates are left out.
+pub fn duplicate_merkle_roots(
+    tree: &TreeInfo,
+    headers: &[HeaderInfo],
+) -> Vec<(HeaderInfo, HeaderInfo)> {
+    let mut by_root: HashMap<TxMerkleNode, Vec<&HeaderInfo>> = HashMap::new();
+    for header_info in headers.iter() {
+        by_root
+            .entry(header_info.header.merkle_root)
+            .or_default()
+            .push(header_info);
+    }
+    let new_hashes: HashSet<BlockHash> = headers.iter().map(|h| h.block_hash()).collect();
+    let mut duplicates: Vec<(HeaderInfo, HeaderInfo)> = vec![];
+    let mut paired: HashSet<(BlockHash, BlockHash)> = HashSet::new();
+    for other in tree.0.node_weights() {
+        let same_root = match by_root.get(&other.header.merkle_root) {
+            Some(same_root) => same_root,
+            None => continue,
+        };
+        let other_hash = other.block_hash();
+        for header_info in same_root.iter() {
+            let hash = header_info.block_hash();
+            if hash == other_hash || is_known_duplicate(&hash) || is_known_duplicate(&other_hash) {
+                continue;
+            }
+            // Two new headers sharing a root are reported once.
+            if new_hashes.contains(&other_hash) && paired.contains(&(other_hash, hash)) {
+                continue;
+            }
+            paired.insert((hash, other_hash));
+            duplicates.push(((*header_info).clone(), other.clone()));
+        }
+    }
+    duplicates
+}
+
+// The block hashes a node reported at another height than they have in the
+// tree, with the height in the tree and the reported height. Besides its
+// tips, a node reports the parent of each new header one below the header.
+pub fn conflicting_heights(
+    tree: &TreeInfo,
+    reported: &[(BlockHash, u64)],
+) -> Vec<(BlockHash, u64, u64)> {
+    reported
+        .iter()
+        .filter_map(|(hash, height)| {
+            let idx = tree.1.get(hash)?;
+            let known_height = tree.0[*idx].height;
+            Some((*hash, known_height, *height)).filter(|_| known_height != *height)
+        })
+        .collect()
+}
+
+// The block hashes and heights the node reported with its tips and new
+// headers.
+pub fn reported_heights(tips: &[ChainTip], new_headers: &[HeaderInfo]) -> Vec<(BlockHash, u64)> {
+    let tip_heights = tips
+        .iter()
+        .filter_map(|tip| Some((tip.hash.parse::<BlockHash>().ok()?, tip.height)));
+    let parent_heights = new_headers
+        .iter()
+        .filter(|h| h.height > 0)
+        .map(|h| (h.header.prev_blockhash, h.height - 1));
+    tip_heights.chain(parent_heights).collect()
+}
+
+// Emits an event for each header newly inserted into the tree sharing its
+// merkle root with another header, and for each block the node reported at
+// a conflicting height. Conflicts already flagged for the node are skipped,
+// as the node keeps reporting its tips. Shadow nodes are excluded from
+// alerting, so they aren't checked.
+#[allow(clippy::too_many_arguments)]
+pub async fn check(
+    network_id: u32,
+    network_name: &str,
+    node: &NodeInfo,
+    tree: &Tree,
+    inserted: &[HeaderInfo],
+    reported: &[(BlockHash, u64)],
+    flagged: &mut HashSet<(BlockHash, u64)>,
+    event_log: &EventLog,
+) {
+    if node.shadow {
+        return;
+    }
+    let (duplicates, conflicts) = {
+        let tree_locked = tree.lock().await;
+        let duplicates = if inserted.is_empty() {
+            vec![]
+        } else {
+            duplicate_merkle_roots(&tree_locked, inserted)
+        };
+        (duplicates, conflicting_heights(&tree_locked, reported))
+    };
+
+    for (header_info, other) in duplicates.iter() {
+        event_log
+            .emit(
+                network_id,
+                EventKind::DuplicateMerkleRoot,
+                EventSeverity::Critical,
+                format!(
+                    "Two blocks on {} share the merkle root {}",
+                    network_name, header_info.header.merkle_root
+                ),
+                format!(
+                    "The block {} at height {} reported by node {} has the same merkle root {} as the block {} at height {} on {}.",
+                    header_info.block_hash(),
+                    header_info.height,
+                    node.name,
+                    header_info.header.merkle_root,
+                    other.block_hash(),
+                    other.height,
+                    network_name
+                ),
+            )
+            .await;
+    }
+
+    for (hash, known_height, height) in conflicts.iter() {
+        if !flagged.insert((*hash, *height)) {
+            continue;
+        }
+        event_log
+            .emit(
+                network_id,
+                EventKind::ConflictingHeight,
+                EventSeverity::Critical,
+                format!(
+                    "Node {} reported a block on {} at a conflicting height",
+                    node.name, network_name
+                ),
+                format!(
+                    "Node {} reported the block {} at height {}, but it's at height {} in the header tree of {}.",
+                    node.name, hash, height, known_height, network_name
+                ),
+            )
+            .await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::{self, header_info};
+    use bitcoincore_rpc::bitcoin::hashes::Hash;
+    use petgraph::graph::DiGraph;
+
+    fn header(height: u64, prev: BlockHash, merkle_root: u8, nonce: u32) -> HeaderInfo {
+        let mut header = testutil::header(prev, 0, nonce);
+        header.merkle_root = TxMerkleNode::from_byte_array([merkle_root; 32]);
+        header_info(height, header)
+    }
+
+    fn insert(tree: &mut TreeInfo, header_info: &HeaderInfo) {
+        let idx = tree.0.add_node(header_info.clone());
+        tree.1.insert(header_info.block_hash(), idx);
+    }
+
+    #[test]
+    fn test_duplicates() {
+        let mut tree: TreeInfo = (DiGraph::new(), HashMap::new(), HashMap::new());
+        let a = header(100, BlockHash::all_zeros(), 1, 0);
+        let b = header(101, a.block_hash(), 2, 0);
+        insert(&mut tree, &a);
+        insert(&mut tree, &b);
+        assert!(duplicate_merkle_roots(&tree, std::slice::from_ref(&b)).is_empty());
+
+        // the same template mined twice
+        let c = header(101, a.block_hash(), 2, 1);
+        insert(&mut tree, &c);
+        let duplicates = duplicate_merkle_roots(&tree, std::slice::from_ref(&c));
+        assert_eq!(duplicates.len(), 1);
+        assert_eq!(duplicates[0].0.block_hash(), c.block_hash());
+        assert_eq!(duplicates[0].1.block_hash(), b.block_hash());
+        // both inserted together are reported once
+        assert_eq!(
+            duplicate_merkle_roots(&tree, &[b.clone(), c.clone()]).len(),
+            1
+        );
+
+        let tips = vec![ChainTip {
+            height: 101,
+            hash: b.block_hash().to_string(),
+            branchlen: 0,
+            status: crate::domain::ChainTipStatus::Active,
+        }];
+        let d = header(103, b.block_hash(), 3, 0);
+        let reported = reported_heights(&tips, std::slice::from_ref(&d));
+        assert_eq!(reported, vec![(b.block_hash(), 101), (b.block_hash(), 102)]);
+        assert_eq!(
+            conflicting_heights(&tree, &reported),
+            vec![(b.block_hash(), 101, 102)]
+        );
+        assert!(conflicting_heights(&tree, &reported_heights(&tips, &[])).is_empty());
+    }
+
+    #[test]
+    fn test_known_duplicates() {
+        let block_91842 = BlockHash::from_str(KNOWN_DUPLICATES[0]).unwrap();
+        let block_91880 = BlockHash::from_str(KNOWN_DUPLICATES[1]).unwrap();
+        assert!(is_known_duplicate(&block_91842));
+        assert!(is_known_duplicate(&block_91880));
+        assert!(!is_known_duplicate(&BlockHash::all_zeros()));
+    }
+
+    #[tokio::test]
+    async fn test_check_skips_shadow_nodes() {
+        use crate::config::Notifications;
+        use crate::retry::RetryPolicy;
+        use crate::types::Db;
+        use rusqlite::Connection;
+        use std::sync::Arc;
+        use tokio::sync::Mutex;
+
+        let db: Db = Arc::new(Mutex::new(Connection::open_in_memory().unwrap()));
+        crate::db::setup_db(db.clone()).await.unwrap();
+        let event_log = EventLog::new(
+            db,
+            Notifications {
+                max_attempts: 1,
+                sinks: vec![],
+            },
+            HashMap::new(),
+        );
+        let mut events = event_log.subscribe();
+
+        let a = header(100, BlockHash::all_zeros(), 1, 0);
+        let b = header(101, a.block_hash(), 2, 0);
+        let c = header(101, a.block_hash(), 2, 1);
+        let mut tree_info: TreeInfo = (DiGraph::new(), HashMap::new(), HashMap::new());
+        for h in [&a, &b, &c] {
+            insert(&mut tree_info, h);
+        }
+        let tree: Tree = Arc::new(Mutex::new(tree_info));
+        let mut node = NodeInfo {
+            id: 1,
+            name: "node".to_string(),
+            description: String::new(),
+            implementation: String::new(),
+            trust: 1.0,
+            shadow: true,
+            purge_after: None,
+            retry: RetryPolicy::default(),
+        };
+        let inserted = vec![c.clone()];
+        let reported = vec![(b.block_hash(), 102)];
+        let mut flagged = HashSet::new();
+        check(
+            1,
+            "net",
+            &node,
+            &tree,
+            &inserted,
+            &reported,
+            &mut flagged,
+            &event_log,
+        )
+        .await;
+        assert!(events.try_recv().is_err());
+        assert!(flagged.is_empty());
+
+        node.shadow = false;
+        check(
+            1,
+            "net",
+            &node,
+            &tree,
+            &inserted,
+            &reported,
+            &mut flagged,
+            &event_log,
+        )
+        .await;
+        assert_eq!(
+            events.try_recv().unwrap().kind,
+            EventKind::DuplicateMerkleRoot
+        );
+        assert_eq!(
+            events.try_recv().unwrap().kind,
+            EventKind::ConflictingHeight
+        );
+    }
+}
@@ -0,0 +1,247 @@
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+use crate::error::JsonRPCError;
+
+use bitcoincore_rpc::bitcoin;
+use bitcoincore_rpc::bitcoin::blockdata::block::Header;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use log::debug;
+
+const JSON_RPC_VERSION: &str = "2.0";
+const JSON_RPC_ID: u64 = 45324;
+const CLIENT_NAME: &str = "fork-observer";
+const PROTOCOL_VERSION: &str = "1.4";
+const TIMEOUT: Duration = Duration::from_secs(8);
+const BITCOIN_BLOCK_HEADER_LENGTH: usize = 80;
+// Electrum servers serve at most 2016 headers per blockchain.block.headers
+// request.
+pub const MAX_HEADERS_PER_REQUEST: u64 = 2016;
+
+#[derive(Serialize, Debug)]
+struct Request<'a> {
+    jsonrpc: &'a str,
+    id: u64,
+    method: &'a str,
+    params: Vec<Value>,
+}
+
+#[derive(Deserialize)]
+struct Response {
+    result: Option<Value>,
+    error: Option<Value>,
+}
+
+#[derive(Deserialize)]
+struct HeadersSubscribeResult {
+    height: u64,
+    hex: String,
+}
+
+#[derive(Deserialize)]
+struct BlockHeadersResult {
+    count: u64,
+    hex: String,
+}
+
+// The tip as returned by blockchain.headers.subscribe.
+pub fn headers_subscribe(address: &str) -> Result<(u64, Header), JsonRPCError> {
+    const METHOD: &str = "blockchain.headers.subscribe";
+
+    let result: HeadersSubscribeResult = parse(METHOD, request(address, METHOD, vec![])?)?;
+    let headers = parse_headers(METHOD, &result.hex)?;
+    match headers.first() {
+        Some(header) if headers.len() == 1 => Ok((result.height, *header)),
+        _ => Err(JsonRPCError::RpcUnexpectedResponseContents(format!(
+            "Electrum response for request '{}' doesn't contain exactly one header",
+            METHOD
+        ))),
+    }
+}
+
+pub fn block_header(address: &str, height: u64) -> Result<Header, JsonRPCError> {
+    const METHOD: &str = "blockchain.block.header";
+
+    let header_hex: String = parse(METHOD, request(address, METHOD, vec![Value::from(height)])?)?;
+    let headers = parse_headers(METHOD, &header_hex)?;
+    match headers.first() {
+        Some(header) if headers.len() == 1 => Ok(*header),
+        _ => Err(JsonRPCError::RpcUnexpectedResponseContents(format!(
+            "Electrum response for request '{}' doesn't contain exactly one header",
+            METHOD
+        ))),
+    }
+}
+
+// Up to count headers of the server's active chain starting at the height.
+pub fn block_headers(address: &str, start: u64, count: u64) -> Result<Vec<Header>, JsonRPCError> {
+    const METHOD: &str = "blockchain.block.headers";
+
+    let result: BlockHeadersResult = parse(
+        METHOD,
+        request(
+            address,
+            METHOD,
+            vec![Value::from(start), Value::from(count)],
+        )?,
+    )?;
+    let headers = parse_headers(METHOD, &result.hex)?;
+    if headers.len() as u64 != result.count {
+        return Err(JsonRPCError::RpcUnexpectedResponseContents(format!(
+            "Electrum response for request '{}' contains {} headers but a count of {}",
+            METHOD,
+            headers.len(),
+            result.count
+        )));
+    }
+    Ok(headers)
+}
+
+// The server software version as returned by server.version.
+pub fn server_version(address: &str) -> Result<String, JsonRPCError> {
+    const METHOD: &str = "server.version";
+
+    let versions: Vec<String> = parse(
+        METHOD,
+        request(
+            address,
+            METHOD,
+            vec![Value::from(CLIENT_NAME), Value::from(PROTOCOL_VERSION)],
+        )?,
+    )?;
+    versions.into_iter().next().ok_or_else(|| {
+        JsonRPCError::RpcUnexpectedResponseContents(format!(
+            "Electrum response for request '{}' is empty",
+            METHOD
+        ))
+    })
+}
+
+fn parse_headers(method: &str, hex: &str) -> Result<Vec<Header>, JsonRPCError> {
+    let bytes = hex::decode(hex)?;
+    if bytes.len() % BITCOIN_BLOCK_HEADER_LENGTH != 0 {
+        return Err(JsonRPCError::RpcUnexpectedResponseContents(format!(
+            "Electrum response for request '{}' has not the correct length for Bitcoin block headers: {} bytes",
+            method,
+            bytes.len()
+        )));
+    }
+    let mut headers: Vec<Header> = vec![];
+    for chunk in bytes.chunks(BITCOIN_BLOCK_HEADER_LENGTH) {
+        headers.push(bitcoin::consensus::deserialize(chunk)?);
+    }
+    Ok(headers)
+}
+
+fn parse<T: for<'de> Deserialize<'de>>(method: &str, result: Value) -> Result<T, JsonRPCError> {
+    serde_json::from_value(result).map_err(|e| {
+        JsonRPCError::RpcUnexpectedResponseContents(format!(
+            "Electrum response for request '{}' could not be parsed: {}",
+            method, e
+        ))
+    })
+}
+
+// Sends a single newline-delimited JSON-RPC request over a new plain TCP
+// connection and returns the result. TLS isn't supported.
+fn request(address: &str, method: &str, params: Vec<Value>) -> Result<Value, JsonRPCError> {
+    let request = Request {
+        jsonrpc: JSON_RPC_VERSION,
+        id: JSON_RPC_ID,
+        method,
+        params,
+    };
+    debug!("Electrum request to {}: {:?}", address, request);
+
+    let socket_address = address.to_socket_addrs()?.next().ok_or_else(|| {
+        JsonRPCError::Http(format!("could not resolve Electrum server {}", address))
+    })?;
+    let mut stream = TcpStream::connect_timeout(&socket_address, TIMEOUT)?;
+    stream.set_read_timeout(Some(TIMEOUT))?;
+    stream.set_write_timeout(Some(TIMEOUT))?;
+
+    let mut line = match serde_json::to_string(&request) {
+        Ok(line) => line,
+        Err(e) => {
+            return Err(JsonRPCError::JsonRpc(format!(
+                "could not serialize request '{}': {}",
+                method, e
+            )))
+        }
+    };
+    line.push('\n');
+    stream.write_all(line.as_bytes())?;
+
+    let mut response_line = String::new();
+    BufReader::new(stream).read_line(&mut response_line)?;
+    debug!("Electrum response for {}: {}", method, response_line);
+
+    let response: Response = serde_json::from_str(&response_line).map_err(|e| {
+        JsonRPCError::RpcUnexpectedResponseContents(format!(
+            "Electrum response for request '{}' is not valid JSON-RPC: {}",
+            method, e
+        ))
+    })?;
+    if let Some(error) = response.error {
+        return Err(JsonRPCError::JsonRpc(format!(
+            "Electrum response for request '{}' contains error: {}",
+            method, error
+        )));
+    }
+    response.result.ok_or_else(|| {
+        JsonRPCError::JsonRpc(format!(
+            "Electrum response for request '{}' was empty.",
+            method
+        ))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+    use std::thread;
+
+    // the genesis block header
+    const GENESIS_HEADER: &str = "0100000000000000000000000000000000000000000000000000000000000000000000003ba3edfd7a7b12b27ac72c3e67768f617fc81bc3888a51323a9fb8aa4b1e5e4a29ab5f49ffff001d1dac2b7c";
+
+    // Serves a single connection with the given response line.
+    fn serve(response: String) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = String::new();
+            BufReader::new(stream.try_clone().unwrap())
+                .read_line(&mut request)
+                .unwrap();
+            assert!(request.contains("blockchain.headers.subscribe"));
+            stream.write_all(response.as_bytes()).unwrap();
+        });
+        address
+    }
+
+    #[test]
+    fn test_headers_subscribe() {
+        let address = serve(format!(
+            "{{\"jsonrpc\": \"2.0\", \"id\": 45324, \"result\": {{\"height\": 0, \"hex\": \"{}\"}}}}\n",
+            GENESIS_HEADER
+        ));
+        let (height, header) = headers_subscribe(&address).unwrap();
+        assert_eq!(height, 0);
+        assert_eq!(
+            header.block_hash().to_string(),
+            "000000000019d6689c085ae165831e934ff763ae46a2a6c172b3f1b60a8ce26f"
+        );
+
+        let address = serve(
+            "{\"jsonrpc\": \"2.0\", \"id\": 45324, \"error\": {\"code\": 1, \"message\": \"x\"}}\n"
+                .to_string(),
+        );
+        assert!(headers_subscribe(&address).is_err());
+    }
+}
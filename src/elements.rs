@@ -0,0 +1,425 @@
+use std::io;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use bitcoincore_rpc::bitcoin::block::{Header, Version};
+use bitcoincore_rpc::bitcoin::consensus::encode::{self, Decodable, Encodable};
+use bitcoincore_rpc::bitcoin::hashes::Hash;
+use bitcoincore_rpc::bitcoin::{BlockHash, CompactTarget, Transaction, TxMerkleNode};
+use serde_json::Value;
+use tokio::sync::Notify;
+
+use crate::domain::{ChainTip, NodeInfo, NodeResources};
+use crate::error::{FetchError, JsonRPCError};
+use crate::node::{BitcoinCoreNode, Node, RestStepSize};
+
+// Set in the version of headers with dynamic federation (dynafed)
+// parameters instead of a fixed challenge and solution.
+const DYNAFED_VERSION_BIT: u32 = 0x8000_0000;
+
+// The federation parameters committed to in a dynafed header.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DynafedParams {
+    Null,
+    // Only the signblockscript and the witness limit, the remaining
+    // parameters are committed to by the elided_root.
+    Compact {
+        signblockscript: Vec<u8>,
+        signblock_witness_limit: u32,
+        elided_root: [u8; 32],
+    },
+    Full {
+        signblockscript: Vec<u8>,
+        signblock_witness_limit: u32,
+        fedpeg_program: Vec<u8>,
+        fedpegscript: Vec<u8>,
+        extension_space: Vec<Vec<u8>>,
+    },
+}
+
+impl DynafedParams {
+    pub fn signblockscript(&self) -> Option<&[u8]> {
+        match self {
+            DynafedParams::Null => None,
+            DynafedParams::Compact {
+                signblockscript, ..
+            }
+            | DynafedParams::Full {
+                signblockscript, ..
+            } => Some(signblockscript),
+        }
+    }
+}
+
+impl Encodable for DynafedParams {
+    fn consensus_encode<W: io::Write + ?Sized>(&self, w: &mut W) -> Result<usize, io::Error> {
+        Ok(match self {
+            DynafedParams::Null => 0u8.consensus_encode(w)?,
+            DynafedParams::Compact {
+                signblockscript,
+                signblock_witness_limit,
+                elided_root,
+            } => {
+                1u8.consensus_encode(w)?
+                    + signblockscript.consensus_encode(w)?
+                    + signblock_witness_limit.consensus_encode(w)?
+                    + elided_root.consensus_encode(w)?
+            }
+            DynafedParams::Full {
+                signblockscript,
+                signblock_witness_limit,
+                fedpeg_program,
+                fedpegscript,
+                extension_space,
+            } => {
+                2u8.consensus_encode(w)?
+                    + signblockscript.consensus_encode(w)?
+                    + signblock_witness_limit.consensus_encode(w)?
+                    + fedpeg_program.consensus_encode(w)?
+                    + fedpegscript.consensus_encode(w)?
+                    + extension_space.consensus_encode(w)?
+            }
+        })
+    }
+}
+
+impl Decodable for DynafedParams {
+    fn consensus_decode<R: io::Read + ?Sized>(r: &mut R) -> Result<Self, encode::Error> {
+        Ok(match u8::consensus_decode(r)? {
+            0 => DynafedParams::Null,
+            1 => DynafedParams::Compact {
+                signblockscript: Decodable::consensus_decode(r)?,
+                signblock_witness_limit: Decodable::consensus_decode(r)?,
+                elided_root: Decodable::consensus_decode(r)?,
+            },
+            2 => DynafedParams::Full {
+                signblockscript: Decodable::consensus_decode(r)?,
+                signblock_witness_limit: Decodable::consensus_decode(r)?,
+                fedpeg_program: Decodable::consensus_decode(r)?,
+                fedpegscript: Decodable::consensus_decode(r)?,
+                extension_space: Decodable::consensus_decode(r)?,
+            },
+            _ => return Err(encode::Error::ParseFailed("invalid dynafed params type")),
+        })
+    }
+}
+
+// How the federation signs a block: with a solution to the fixed challenge
+// or, with dynafed, with a witness satisfying the current signblockscript.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SignBlock {
+    Proof {
+        challenge: Vec<u8>,
+        solution: Vec<u8>,
+    },
+    Dynafed {
+        current: DynafedParams,
+        proposed: DynafedParams,
+        signblock_witness: Vec<Vec<u8>>,
+    },
+}
+
+// An Elements (e.g. Liquid) block header. Unlike Bitcoin headers, these
+// commit to the height and are signed by the federation instead of carrying
+// proof of work, so they aren't 80 bytes long.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ElementsHeader {
+    /// The version as serialized, including the dynafed bit.
+    pub version: u32,
+    pub prev_blockhash: BlockHash,
+    pub merkle_root: TxMerkleNode,
+    pub time: u32,
+    pub height: u32,
+    pub signblock: SignBlock,
+}
+
+impl ElementsHeader {
+    // Serializes the header. The block hash commits to neither the
+    // solution nor the signblock witness, so they are left out for it.
+    fn encode<W: io::Write + ?Sized>(
+        &self,
+        w: &mut W,
+        signatures: bool,
+    ) -> Result<usize, io::Error> {
+        let mut len = self.version.consensus_encode(w)?
+            + self.prev_blockhash.consensus_encode(w)?
+            + self.merkle_root.consensus_encode(w)?
+            + self.time.consensus_encode(w)?
+            + self.height.consensus_encode(w)?;
+        match self.signblock {
+            SignBlock::Proof {
+                ref challenge,
+                ref solution,
+            } => {
+                len += challenge.consensus_encode(w)?;
+                if signatures {
+                    len += solution.consensus_encode(w)?;
+                }
+            }
+            SignBlock::Dynafed {
+                ref current,
+                ref proposed,
+                ref signblock_witness,
+            } => {
+                len += current.consensus_encode(w)? + proposed.consensus_encode(w)?;
+                if signatures {
+                    len += signblock_witness.consensus_encode(w)?;
+                }
+            }
+        }
+        Ok(len)
+    }
+
+    pub fn block_hash(&self) -> BlockHash {
+        let mut bytes: Vec<u8> = Vec::new();
+        self.encode(&mut bytes, false)
+            .expect("writing to a vec doesn't fail");
+        BlockHash::hash(&bytes)
+    }
+
+    // The fields shared with Bitcoin headers. There is no proof of work, so
+    // the bits and the nonce are zero.
+    pub fn to_header(&self) -> Header {
+        Header {
+            version: Version::from_consensus((self.version & !DYNAFED_VERSION_BIT) as i32),
+            prev_blockhash: self.prev_blockhash,
+            merkle_root: self.merkle_root,
+            time: self.time,
+            bits: CompactTarget::from_consensus(0),
+            nonce: 0,
+        }
+    }
+}
+
+impl Encodable for ElementsHeader {
+    fn consensus_encode<W: io::Write + ?Sized>(&self, w: &mut W) -> Result<usize, io::Error> {
+        self.encode(w, true)
+    }
+}
+
+impl Decodable for ElementsHeader {
+    fn consensus_decode<R: io::Read + ?Sized>(r: &mut R) -> Result<Self, encode::Error> {
+        let version = u32::consensus_decode(r)?;
+        let prev_blockhash = BlockHash::consensus_decode(r)?;
+        let merkle_root = TxMerkleNode::consensus_decode(r)?;
+        let time = u32::consensus_decode(r)?;
+        let height = u32::consensus_decode(r)?;
+        let signblock = if version & DYNAFED_VERSION_BIT != 0 {
+            SignBlock::Dynafed {
+                current: Decodable::consensus_decode(r)?,
+                proposed: Decodable::consensus_decode(r)?,
+                signblock_witness: Decodable::consensus_decode(r)?,
+            }
+        } else {
+            SignBlock::Proof {
+                challenge: Decodable::consensus_decode(r)?,
+                solution: Decodable::consensus_decode(r)?,
+            }
+        };
+        Ok(ElementsHeader {
+            version,
+            prev_blockhash,
+            merkle_root,
+            time,
+            height,
+            signblock,
+        })
+    }
+}
+
+fn parse_header(header_hex: &str) -> Result<ElementsHeader, FetchError> {
+    hex::decode(header_hex)
+        .ok()
+        .and_then(|bytes| encode::deserialize(&bytes).ok())
+        .ok_or_else(|| FetchError::DataError(format!("invalid Elements header {}", header_hex)))
+}
+
+// An Elements node, e.g. a Liquid federation member or a node following
+// the Liquid chain. Elements is a fork of Bitcoin Core with the same RPC
+// interface, so the RPC calls are made by a BitcoinCoreNode, except for
+// the headers, blocks and templates, which differ.
+pub struct ElementsNode {
+    core: BitcoinCoreNode,
+}
+
+impl ElementsNode {
+    pub fn new(core: BitcoinCoreNode) -> Self {
+        ElementsNode { core }
+    }
+}
+
+#[async_trait]
+impl Node for ElementsNode {
+    fn info(&self) -> NodeInfo {
+        self.core.info()
+    }
+
+    // The REST headers are Elements headers, so they are always fetched
+    // via RPC.
+    fn use_rest(&self) -> bool {
+        false
+    }
+
+    fn rest_step_size(&self) -> &RestStepSize {
+        self.core.rest_step_size()
+    }
+
+    fn rpc_url(&self) -> String {
+        self.core.rpc_url()
+    }
+
+    fn host(&self) -> Option<String> {
+        self.core.host()
+    }
+
+    async fn version(&self) -> Result<String, FetchError> {
+        self.core.version().await
+    }
+
+    async fn p2p_addresses(&self) -> Result<Vec<(String, u16)>, FetchError> {
+        self.core.p2p_addresses().await
+    }
+
+    async fn block_header(&self, hash: &BlockHash) -> Result<Header, FetchError> {
+        Ok(self.block_headers(&[*hash]).await?.remove(0))
+    }
+
+    async fn block_headers(&self, hashes: &[BlockHash]) -> Result<Vec<Header>, FetchError> {
+        Ok(self
+            .elements_headers(hashes)
+            .await?
+            .unwrap_or_default()
+            .iter()
+            .map(ElementsHeader::to_header)
+            .collect())
+    }
+
+    async fn elements_headers(
+        &self,
+        hashes: &[BlockHash],
+    ) -> Result<Option<Vec<ElementsHeader>>, FetchError> {
+        let headers = self
+            .core
+            .block_headers_hex(hashes)
+            .await?
+            .iter()
+            .map(|header_hex| parse_header(header_hex))
+            .collect::<Result<Vec<ElementsHeader>, FetchError>>()?;
+        if headers.len() != hashes.len() {
+            return Err(FetchError::DataError(format!(
+                "got {} headers for {} hashes",
+                headers.len(),
+                hashes.len()
+            )));
+        }
+        Ok(Some(headers))
+    }
+
+    async fn block_hash(&self, height: u64) -> Result<BlockHash, FetchError> {
+        self.core.block_hash(height).await
+    }
+
+    async fn best_block_hash(&self) -> Result<Option<BlockHash>, FetchError> {
+        self.core.best_block_hash().await
+    }
+
+    fn rpc_batch_size(&self) -> u64 {
+        self.core.rpc_batch_size()
+    }
+
+    async fn block_hashes(&self, heights: &[u64]) -> Result<Vec<BlockHash>, FetchError> {
+        self.core.block_hashes(heights).await
+    }
+
+    // Elements transactions differ from Bitcoin transactions, and the
+    // blocks aren't mined, so there is no miner to identify.
+    async fn coinbase(&self, _hash: &BlockHash) -> Result<Transaction, FetchError> {
+        Err(FetchError::ElementsRPC(JsonRPCError::NotImplemented))
+    }
+
+    async fn header_and_block_heights(&self) -> Result<Option<(u64, u64)>, FetchError> {
+        self.core.header_and_block_heights().await
+    }
+
+    async fn resources(&self) -> Result<Option<NodeResources>, FetchError> {
+        self.core.resources().await
+    }
+
+    fn block_notifications(&self) -> Option<Arc<Notify>> {
+        self.core.block_notifications()
+    }
+
+    async fn tips(&self) -> Result<Vec<ChainTip>, FetchError> {
+        self.core.tips().await
+    }
+
+    async fn tips_raw(&self) -> Result<(Vec<ChainTip>, Value), FetchError> {
+        self.core.tips_raw().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn header(signblock: SignBlock) -> ElementsHeader {
+        ElementsHeader {
+            version: match signblock {
+                SignBlock::Dynafed { .. } => 0x2000_0000 | DYNAFED_VERSION_BIT,
+                SignBlock::Proof { .. } => 0x2000_0000,
+            },
+            prev_blockhash: BlockHash::from_byte_array([1; 32]),
+            merkle_root: TxMerkleNode::from_byte_array([2; 32]),
+            time: 1_700_000_000,
+            height: 2_500_000,
+            signblock,
+        }
+    }
+
+    #[test]
+    fn test_elements_header() {
+        let proof = header(SignBlock::Proof {
+            challenge: vec![0x51],
+            solution: vec![],
+        });
+        let dynafed = header(SignBlock::Dynafed {
+            current: DynafedParams::Compact {
+                signblockscript: vec![0x00, 0x20, 0xab],
+                signblock_witness_limit: 1416,
+                elided_root: [3; 32],
+            },
+            proposed: DynafedParams::Null,
+            signblock_witness: vec![vec![], vec![0x30, 0x44]],
+        });
+        for header in [proof, dynafed] {
+            let bytes = encode::serialize(&header);
+            let decoded: ElementsHeader = encode::deserialize(&bytes).unwrap();
+            assert_eq!(decoded, header);
+
+            let shared = decoded.to_header();
+            assert_eq!(shared.prev_blockhash, header.prev_blockhash);
+            assert_eq!(shared.version.to_consensus(), 0x2000_0000);
+            assert_ne!(shared.block_hash(), header.block_hash());
+        }
+
+        // The signatures aren't part of the block hash.
+        let mut signed = header(SignBlock::Proof {
+            challenge: vec![0x51],
+            solution: vec![],
+        });
+        let hash = signed.block_hash();
+        signed.signblock = SignBlock::Proof {
+            challenge: vec![0x51],
+            solution: vec![0x01, 0x02],
+        };
+        assert_eq!(signed.block_hash(), hash);
+        signed.signblock = SignBlock::Proof {
+            challenge: vec![0x52],
+            solution: vec![0x01, 0x02],
+        };
+        assert_ne!(signed.block_hash(), hash);
+
+        // A Bitcoin header isn't an Elements header.
+        assert!(parse_header(&"00".repeat(80)).is_err());
+    }
+}
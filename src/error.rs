@@ -1,5 +1,6 @@
 use std::fmt;
 use std::net::AddrParseError;
+use std::path::PathBuf;
 use std::{error, io};
 
 use bitcoincore_rpc::bitcoin;
@@ -10,7 +11,16 @@ pub enum FetchError {
     TokioJoin(tokio::task::JoinError),
     BitcoinCoreRPC(bitcoincore_rpc::Error),
     BitcoinCoreREST(String),
+    // The node, or a proxy in front of it, rejected or truncated a REST
+    // response because of its size.
+    BitcoinCoreRESTTooLarge(String),
     BtcdRPC(JsonRPCError),
+    ElectrumRPC(JsonRPCError),
+    CustomRPC(JsonRPCError),
+    ElementsRPC(JsonRPCError),
+    EsploraREST(String),
+    ForkObserver(String),
+    P2P(String),
     MinReq(minreq::Error),
     DataError(String),
 }
@@ -21,7 +31,16 @@ impl fmt::Display for FetchError {
             FetchError::TokioJoin(e) => write!(f, "TokioJoin Error: {:?}", e),
             FetchError::BitcoinCoreRPC(e) => write!(f, "Bitcoin Core RPC Error: {}", e),
             FetchError::BtcdRPC(e) => write!(f, "btcd Error: {}", e),
+            FetchError::ElectrumRPC(e) => write!(f, "Electrum Error: {}", e),
+            FetchError::CustomRPC(e) => write!(f, "Custom RPC Error: {}", e),
+            FetchError::ElementsRPC(e) => write!(f, "Elements Error: {}", e),
             FetchError::BitcoinCoreREST(e) => write!(f, "Bitcoin Core REST Error: {}", e),
+            FetchError::BitcoinCoreRESTTooLarge(e) => {
+                write!(f, "Bitcoin Core REST response too large: {}", e)
+            }
+            FetchError::EsploraREST(e) => write!(f, "Esplora Error: {}", e),
+            FetchError::ForkObserver(e) => write!(f, "fork-observer Error: {}", e),
+            FetchError::P2P(e) => write!(f, "P2P Error: {}", e),
             FetchError::MinReq(e) => write!(f, "MinReq HTTP GET request error: {:?}", e),
             FetchError::DataError(e) => write!(f, "Invalid data response error {}", e),
         }
@@ -34,7 +53,14 @@ impl error::Error for FetchError {
             FetchError::TokioJoin(ref e) => Some(e),
             FetchError::BitcoinCoreRPC(ref e) => Some(e),
             FetchError::BtcdRPC(ref e) => Some(e),
+            FetchError::ElectrumRPC(ref e) => Some(e),
+            FetchError::CustomRPC(ref e) => Some(e),
+            FetchError::ElementsRPC(ref e) => Some(e),
             FetchError::BitcoinCoreREST(_) => None,
+            FetchError::BitcoinCoreRESTTooLarge(_) => None,
+            FetchError::EsploraREST(_) => None,
+            FetchError::ForkObserver(_) => None,
+            FetchError::P2P(_) => None,
             FetchError::MinReq(ref e) => Some(e),
             FetchError::DataError(_) => None,
         }
@@ -64,6 +90,7 @@ pub enum DbError {
     Rusqlite(rusqlite::Error),
     DecodeHex(hex::FromHexError),
     BitcoinDeserialize(bitcoin::consensus::encode::Error),
+    UnsupportedSchemaVersion(u32),
 }
 
 impl fmt::Display for DbError {
@@ -72,6 +99,11 @@ impl fmt::Display for DbError {
             DbError::DecodeHex(e) => write!(f, "hex decoding error: {:?}", e),
             DbError::BitcoinDeserialize(e) => write!(f, "Bitcoin deserialization error: {:?}", e),
             DbError::Rusqlite(e) => write!(f, "Rusqlite SQL error: {:?}", e),
+            DbError::UnsupportedSchemaVersion(v) => write!(
+                f,
+                "the database schema version {} is newer than the supported version. Was it created by a newer fork-observer?",
+                v
+            ),
         }
     }
 }
@@ -82,6 +114,7 @@ impl error::Error for DbError {
             DbError::DecodeHex(ref e) => Some(e),
             DbError::BitcoinDeserialize(ref e) => Some(e),
             DbError::Rusqlite(ref e) => Some(e),
+            DbError::UnsupportedSchemaVersion(_) => None,
         }
     }
 }
@@ -111,9 +144,66 @@ pub enum ConfigError {
     NoBtcdRpcAuth,
     NoNetworks,
     UnknownImplementation,
+    UnknownEventKind(String),
+    UnknownProfile(String),
+    InvalidTipObservationRetention,
+    InvalidNotarizationInterval,
+    InvalidCors(String),
     DuplicateNodeId,
     DuplicateNetworkId,
+    InvalidStorageWarnThreshold,
+    InvalidStorageCheckInterval,
+    InvalidGenesisHash,
+    InvalidChainParams,
+    InvalidPowLimit,
+    DuplicateNotificationSinkName,
+    InvalidRestMaxStepSize,
+    InvalidNotificationSeverity,
+    HttpsNotSupported,
+    RpcHttpsNotSupported,
+    #[cfg_attr(not(feature = "https"), allow(dead_code))]
+    InvalidRpcHttpsCaFile,
+    InvalidRpcHttpsClientCert,
+    Socks5ProxyNotSupported,
+    UnixSocketNotSupported,
+    ZmqNotSupported,
+    InvalidZmqEndpoint(String),
+    InvalidStratumEndpoint(String),
+    RpcLongpollNotSupported,
+    ResourceTelemetryNotSupported,
+    NodeTimeoutNotSupported,
+    InvalidNodeTimeout,
+    InvalidNodeRetryBackoff,
+    InvalidSharedHeaderStore,
+    InvalidComputedField,
+    InvalidOwnMining,
+    InvalidExternalObserver,
+    InvalidTreeQuota,
+    InvalidTemplateComparison,
+    InvalidBlockIntervalAnomalies,
+    InvalidCanary(String),
+    InvalidHeaderGap,
+    InvalidHistoryCache,
+    InvalidReadThrough,
+    InvalidForkPruning,
+    InvalidTreeCompaction,
+    UnknownPoolIdentificationNode(u32),
+    InvalidBlockMetadata,
+    UnknownChainKind(String),
+    NodeNotOnChainKind(String, String),
+    NotSupportedOnElements(String),
+    InvalidNodeTrust,
+    UnknownRemovalPolicy,
+    NoEsploraUrl,
+    NoRemoteNode,
+    NoReplayFile,
+    InvalidCustomRpcParams(String),
+    InvalidReplayFile(PathBuf, String),
+    NoP2PNetwork,
+    IncompleteNotificationSink,
+    UnknownNotificationSinkKind,
     TomlError(toml::de::Error),
+    TomlSerializeError(toml::ser::Error),
     ReadError(io::Error),
     AddrError(AddrParseError),
 }
@@ -126,9 +216,65 @@ impl fmt::Display for ConfigError {
             ConfigError::NoBtcdRpcAuth => write!(f, "no values for rpc_user and rpc_password"),
             ConfigError::NoNetworks => write!(f, "no networks defined in the configuration"),
             ConfigError::UnknownImplementation => write!(f, "the node implementation defined in the config is not supported"),
+            ConfigError::UnknownEventKind(kind) => write!(f, "the event kind '{}' in the event_correlation is unknown", kind),
+            ConfigError::UnknownProfile(profile) => write!(f, "the profile '{}' is unknown, use 'default' or 'low-resource'", profile),
+            ConfigError::InvalidTipObservationRetention => write!(f, "the tip_observation_retention_days must be at least 1"),
+            ConfigError::InvalidNotarizationInterval => write!(f, "the notarization interval must be at least 1 second"),
+            ConfigError::InvalidCors(e) => write!(f, "invalid cors section: {}", e),
             ConfigError::DuplicateNodeId => write!(f, "a node id has been used multiple times in the same network"),
             ConfigError::DuplicateNetworkId => write!(f, "a network id has been used multiple times"),
+            ConfigError::InvalidStorageWarnThreshold => write!(f, "the storage_monitoring warn_threshold_percent must be between 0 and 100"),
+            ConfigError::InvalidStorageCheckInterval => write!(f, "the storage_monitoring check_interval must be at least 1 second"),
+            ConfigError::InvalidGenesisHash => write!(f, "the chain_params genesis_hash is not a valid block hash"),
+            ConfigError::InvalidChainParams => write!(f, "the chain_params halving_interval and difficulty_adjustment_interval must be larger than zero"),
+            ConfigError::InvalidPowLimit => write!(f, "the chain_params pow_limit must be a hex encoded 256-bit target"),
+            ConfigError::DuplicateNotificationSinkName => write!(f, "a notification sink name has been used multiple times"),
+            ConfigError::InvalidRestMaxStepSize => write!(f, "the node rest_max_step_size must be larger than zero"),
+            ConfigError::InvalidNotificationSeverity => write!(f, "the notification sink min_severity must be one of info, warning, or critical"),
+            ConfigError::HttpsNotSupported => write!(f, "a notification sink, mempool_url, notarization calendar or OTLP endpoint uses HTTPS, but fork-observer was built without the 'https' feature"),
+            ConfigError::RpcHttpsNotSupported => write!(f, "rpc_https is only supported for Bitcoin Core and btcd nodes and needs fork-observer to be built with the 'https' feature"),
+            ConfigError::InvalidRpcHttpsCaFile => write!(f, "the rpc_https_ca_file could not be read or contains no valid PEM encoded certificates"),
+            ConfigError::InvalidRpcHttpsClientCert => write!(f, "rpc_https_client_cert and rpc_https_client_key must both be set, need rpc_https, and must contain a PEM encoded certificate chain and a matching private key"),
+            ConfigError::Socks5ProxyNotSupported => write!(f, "a socks5_proxy is only supported for Bitcoin Core nodes"),
+            ConfigError::UnixSocketNotSupported => write!(f, "a unix:// rpc_host is only supported for Bitcoin Core nodes on UNIX systems and can't be combined with rpc_https or a socks5_proxy"),
+            ConfigError::ZmqNotSupported => write!(f, "zmq_hashblock is only supported for Bitcoin Core nodes"),
+            ConfigError::InvalidZmqEndpoint(e) => write!(f, "the zmq_hashblock endpoint '{}' is not of the form tcp://<host>:<port>", e),
+            ConfigError::InvalidStratumEndpoint(e) => write!(f, "the Stratum pool endpoint '{}' is not of the form stratum+tcp://<host>:<port>", e),
+            ConfigError::RpcLongpollNotSupported => write!(f, "rpc_longpoll is only supported for Bitcoin Core nodes"),
+            ConfigError::ResourceTelemetryNotSupported => write!(f, "resource_telemetry is only supported for Bitcoin Core nodes"),
+            ConfigError::NodeTimeoutNotSupported => write!(f, "rpc_timeout and rest_timeout are only supported for Bitcoin Core nodes"),
+            ConfigError::InvalidNodeTimeout => write!(f, "the node rpc_timeout and rest_timeout must be larger than zero"),
+            ConfigError::InvalidNodeRetryBackoff => write!(f, "the node retry_backoff must be larger than zero"),
+            ConfigError::InvalidSharedHeaderStore => write!(f, "a network's share_headers_with must be the id of another network on the same chain that doesn't share its headers itself"),
+            ConfigError::InvalidComputedField => write!(f, "a computed field has an invalid or duplicate name or an invalid expression"),
+            ConfigError::InvalidOwnMining => write!(f, "a network's own_mining needs at least one coinbase tag or hex encoded payout script and a stale_block_severity of info, warning, or critical"),
+            ConfigError::InvalidExternalObserver => write!(f, "each external observer needs a unique, non-empty name and token"),
+            ConfigError::InvalidTreeQuota => write!(f, "the tree_quota max_branches_per_hour and max_headers_per_hour must be larger than zero"),
+            ConfigError::InvalidTemplateComparison => write!(f, "the template_comparison max_fee_divergence_percent and max_tx_count_divergence_percent must be between 1 and 100"),
+            ConfigError::InvalidBlockIntervalAnomalies => write!(f, "the block_interval_anomalies max_probability must be between 0 and 1 (exclusive)"),
+            ConfigError::InvalidCanary(name) => write!(f, "the canary '{}' needs a unique name, a boolean condition and a severity of info, warning, or critical", name),
+            ConfigError::InvalidHeaderGap => write!(f, "the header_gap max_gap must be larger than zero"),
+            ConfigError::InvalidHistoryCache => write!(f, "the history_cache confirmations and chunk_size must be larger than zero"),
+            ConfigError::InvalidReadThrough => write!(f, "the read_through max_fetches_per_minute must be larger than zero"),
+            ConfigError::InvalidForkPruning => write!(f, "the fork_pruning needs a keep_depth or max_forks larger than zero"),
+            ConfigError::InvalidTreeCompaction => write!(f, "the tree_compaction keep_depth must be larger than zero"),
+            ConfigError::UnknownPoolIdentificationNode(id) => write!(f, "the pool_identification node {} is not a node of the network", id),
+            ConfigError::InvalidBlockMetadata => write!(f, "the block_metadata depth must be larger than zero"),
+            ConfigError::UnknownChainKind(chain_kind) => write!(f, "the chain_kind '{}' is unknown, use 'bitcoin' or 'elements'", chain_kind),
+            ConfigError::NodeNotOnChainKind(implementation, chain_kind) => write!(f, "{} nodes can't be used on networks of the chain_kind {}: Elements networks only have Elements nodes and vice versa", implementation, chain_kind),
+            ConfigError::NotSupportedOnElements(option) => write!(f, "{} is not supported on Elements networks", option),
+            ConfigError::InvalidNodeTrust => write!(f, "the trust of a node must be between 0.0 and 1.0"),
+            ConfigError::UnknownRemovalPolicy => write!(f, "the on_removal of a node must be 'retire' or 'purge'"),
+            ConfigError::NoEsploraUrl => write!(f, "an Esplora node needs an esplora_url"),
+            ConfigError::NoRemoteNode => write!(f, "a fork-observer node needs a remote_url, remote_network_id, and remote_node_id"),
+            ConfigError::NoReplayFile => write!(f, "a replay node needs a replay_file"),
+            ConfigError::InvalidCustomRpcParams(e) => write!(f, "the custom_rpc params '{}' are not a JSON array", e),
+            ConfigError::InvalidReplayFile(path, e) => write!(f, "could not load the replay file {:?}: {}", path, e),
+            ConfigError::NoP2PNetwork => write!(f, "a light client or P2P node needs a p2p_network"),
+            ConfigError::IncompleteNotificationSink => write!(f, "a notification sink is missing options: webhooks need an 'url', matrix sinks need a 'homeserver', 'room_id', and 'access_token'"),
+            ConfigError::UnknownNotificationSinkKind => write!(f, "the notification sink kind is not supported (supported: 'webhook', 'matrix')"),
             ConfigError::TomlError(e) => write!(f, "the TOML in the configuration file could not be parsed: {}", e),
+            ConfigError::TomlSerializeError(e) => write!(f, "the configuration could not be serialized as TOML: {}", e),
             ConfigError::ReadError(e) => write!(f, "the configuration file could not be read: {}", e),
             ConfigError::AddrError(e) => write!(f, "the address could not be parsed: {}", e),
         }
@@ -143,11 +289,67 @@ impl error::Error for ConfigError {
             ConfigError::CookieFileDoesNotExist => None,
             ConfigError::NoNetworks => None,
             ConfigError::UnknownImplementation => None,
+            ConfigError::UnknownEventKind(_) => None,
+            ConfigError::UnknownProfile(_) => None,
+            ConfigError::InvalidTipObservationRetention => None,
+            ConfigError::InvalidNotarizationInterval => None,
+            ConfigError::InvalidCors(_) => None,
             ConfigError::TomlError(ref e) => Some(e),
+            ConfigError::TomlSerializeError(ref e) => Some(e),
             ConfigError::ReadError(ref e) => Some(e),
             ConfigError::AddrError(ref e) => Some(e),
             ConfigError::DuplicateNodeId => None,
             ConfigError::DuplicateNetworkId => None,
+            ConfigError::InvalidStorageWarnThreshold => None,
+            ConfigError::InvalidStorageCheckInterval => None,
+            ConfigError::InvalidGenesisHash => None,
+            ConfigError::InvalidChainParams => None,
+            ConfigError::InvalidPowLimit => None,
+            ConfigError::DuplicateNotificationSinkName => None,
+            ConfigError::InvalidRestMaxStepSize => None,
+            ConfigError::InvalidNotificationSeverity => None,
+            ConfigError::HttpsNotSupported => None,
+            ConfigError::RpcHttpsNotSupported => None,
+            ConfigError::InvalidRpcHttpsCaFile => None,
+            ConfigError::InvalidRpcHttpsClientCert => None,
+            ConfigError::Socks5ProxyNotSupported => None,
+            ConfigError::UnixSocketNotSupported => None,
+            ConfigError::ZmqNotSupported => None,
+            ConfigError::InvalidZmqEndpoint(_) => None,
+            ConfigError::InvalidStratumEndpoint(_) => None,
+            ConfigError::RpcLongpollNotSupported => None,
+            ConfigError::ResourceTelemetryNotSupported => None,
+            ConfigError::NodeTimeoutNotSupported => None,
+            ConfigError::InvalidNodeTimeout => None,
+            ConfigError::InvalidNodeRetryBackoff => None,
+            ConfigError::InvalidSharedHeaderStore => None,
+            ConfigError::InvalidComputedField => None,
+            ConfigError::InvalidOwnMining => None,
+            ConfigError::InvalidExternalObserver => None,
+            ConfigError::InvalidTreeQuota => None,
+            ConfigError::InvalidTemplateComparison => None,
+            ConfigError::InvalidBlockIntervalAnomalies => None,
+            ConfigError::InvalidCanary(_) => None,
+            ConfigError::InvalidHeaderGap => None,
+            ConfigError::InvalidHistoryCache => None,
+            ConfigError::InvalidReadThrough => None,
+            ConfigError::InvalidForkPruning => None,
+            ConfigError::InvalidTreeCompaction => None,
+            ConfigError::UnknownPoolIdentificationNode(_) => None,
+            ConfigError::InvalidBlockMetadata => None,
+            ConfigError::UnknownChainKind(_) => None,
+            ConfigError::NodeNotOnChainKind(_, _) => None,
+            ConfigError::NotSupportedOnElements(_) => None,
+            ConfigError::InvalidNodeTrust => None,
+            ConfigError::UnknownRemovalPolicy => None,
+            ConfigError::NoEsploraUrl => None,
+            ConfigError::NoRemoteNode => None,
+            ConfigError::NoReplayFile => None,
+            ConfigError::InvalidCustomRpcParams(_) => None,
+            ConfigError::InvalidReplayFile(..) => None,
+            ConfigError::NoP2PNetwork => None,
+            ConfigError::IncompleteNotificationSink => None,
+            ConfigError::UnknownNotificationSinkKind => None,
         }
     }
 }
@@ -164,17 +366,132 @@ impl From<toml::de::Error> for ConfigError {
     }
 }
 
+impl From<toml::ser::Error> for ConfigError {
+    fn from(err: toml::ser::Error) -> ConfigError {
+        ConfigError::TomlSerializeError(err)
+    }
+}
+
 impl From<AddrParseError> for ConfigError {
     fn from(err: AddrParseError) -> ConfigError {
         ConfigError::AddrError(err)
     }
 }
 
+#[derive(Debug)]
+pub enum ExportError {
+    Db(DbError),
+    Io(io::Error),
+    Json(serde_json::Error),
+    UnknownNetwork(u32),
+    InvalidArguments(String),
+    PathTooLong(String),
+    FileTooLarge(String),
+}
+
+impl fmt::Display for ExportError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ExportError::Db(e) => write!(f, "database error: {}", e),
+            ExportError::Io(e) => write!(f, "I/O error: {}", e),
+            ExportError::Json(e) => write!(f, "JSON serialization error: {}", e),
+            ExportError::UnknownNetwork(id) => write!(f, "no network with id {}", id),
+            ExportError::InvalidArguments(e) => write!(f, "invalid arguments: {}", e),
+            ExportError::PathTooLong(path) => {
+                write!(f, "the path '{}' doesn't fit into a tar header", path)
+            }
+            ExportError::FileTooLarge(path) => {
+                write!(f, "the file '{}' is too large for a tar header", path)
+            }
+        }
+    }
+}
+
+impl error::Error for ExportError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            ExportError::Db(ref e) => Some(e),
+            ExportError::Io(ref e) => Some(e),
+            ExportError::Json(ref e) => Some(e),
+            ExportError::UnknownNetwork(_) => None,
+            ExportError::InvalidArguments(_) => None,
+            ExportError::PathTooLong(_) => None,
+            ExportError::FileTooLarge(_) => None,
+        }
+    }
+}
+
+impl From<DbError> for ExportError {
+    fn from(e: DbError) -> Self {
+        ExportError::Db(e)
+    }
+}
+
+impl From<io::Error> for ExportError {
+    fn from(e: io::Error) -> Self {
+        ExportError::Io(e)
+    }
+}
+
+impl From<serde_json::Error> for ExportError {
+    fn from(e: serde_json::Error) -> Self {
+        ExportError::Json(e)
+    }
+}
+
+#[derive(Debug)]
+pub enum ImportError {
+    Io(io::Error),
+    Config(ConfigError),
+    InvalidInventory(String),
+    InvalidNodes(usize),
+    InvalidArguments(String),
+}
+
+impl fmt::Display for ImportError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ImportError::Io(e) => write!(f, "I/O error: {}", e),
+            ImportError::Config(e) => write!(f, "config error: {}", e),
+            ImportError::InvalidInventory(e) => write!(f, "invalid inventory: {}", e),
+            ImportError::InvalidNodes(n) => write!(f, "{} nodes in the inventory are invalid", n),
+            ImportError::InvalidArguments(e) => write!(f, "invalid arguments: {}", e),
+        }
+    }
+}
+
+impl error::Error for ImportError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            ImportError::Io(ref e) => Some(e),
+            ImportError::Config(ref e) => Some(e),
+            ImportError::InvalidInventory(_) => None,
+            ImportError::InvalidNodes(_) => None,
+            ImportError::InvalidArguments(_) => None,
+        }
+    }
+}
+
+impl From<io::Error> for ImportError {
+    fn from(e: io::Error) -> Self {
+        ImportError::Io(e)
+    }
+}
+
+impl From<ConfigError> for ImportError {
+    fn from(e: ConfigError) -> Self {
+        ImportError::Config(e)
+    }
+}
+
 #[derive(Debug)]
 pub enum MainError {
     Db(DbError),
     Fetch(FetchError),
     Config(ConfigError),
+    Export(ExportError),
+    Import(ImportError),
+    InvalidArguments(String),
 }
 
 impl fmt::Display for MainError {
@@ -183,6 +500,9 @@ impl fmt::Display for MainError {
             MainError::Db(e) => write!(f, "database error: {:?}", e),
             MainError::Fetch(e) => write!(f, "fetch error: {:?}", e),
             MainError::Config(e) => write!(f, "config error: {:?}", e),
+            MainError::Export(e) => write!(f, "export error: {:?}", e),
+            MainError::Import(e) => write!(f, "import error: {:?}", e),
+            MainError::InvalidArguments(e) => write!(f, "invalid arguments: {}", e),
         }
     }
 }
@@ -193,6 +513,9 @@ impl error::Error for MainError {
             MainError::Db(ref e) => Some(e),
             MainError::Fetch(ref e) => Some(e),
             MainError::Config(ref e) => Some(e),
+            MainError::Export(ref e) => Some(e),
+            MainError::Import(ref e) => Some(e),
+            MainError::InvalidArguments(_) => None,
         }
     }
 }
@@ -215,6 +538,18 @@ impl From<ConfigError> for MainError {
     }
 }
 
+impl From<ExportError> for MainError {
+    fn from(e: ExportError) -> Self {
+        MainError::Export(e)
+    }
+}
+
+impl From<ImportError> for MainError {
+    fn from(e: ImportError) -> Self {
+        MainError::Import(e)
+    }
+}
+
 #[derive(Debug)]
 pub enum JsonRPCError {
     Http(String),
@@ -224,6 +559,8 @@ pub enum JsonRPCError {
     FromHex(hex::FromHexError),
     BitcoinFromHex(HexToArrayError),
     BitcoinDeserializeError(bitcoin::consensus::encode::Error),
+    Io(io::Error),
+    WebSocket(Box<tungstenite::Error>),
     NotImplemented,
 }
 
@@ -241,6 +578,8 @@ impl fmt::Display for JsonRPCError {
             }
             JsonRPCError::FromHex(e) => write!(f, "from-hex error: {}", e),
             JsonRPCError::BitcoinFromHex(e) => write!(f, "bitcoin from-hex error: {}", e),
+            JsonRPCError::Io(e) => write!(f, "I/O error: {}", e),
+            JsonRPCError::WebSocket(e) => write!(f, "websocket error: {}", e),
             JsonRPCError::NotImplemented => write!(f, "NotImplemented",),
         }
     }
@@ -257,6 +596,8 @@ impl error::Error for JsonRPCError {
             JsonRPCError::FromHex(ref e) => Some(e),
             JsonRPCError::BitcoinFromHex(ref e) => Some(e),
             JsonRPCError::BitcoinDeserializeError(ref e) => Some(e),
+            JsonRPCError::Io(ref e) => Some(e),
+            JsonRPCError::WebSocket(ref e) => Some(e.as_ref()),
         }
     }
 }
@@ -267,6 +608,18 @@ impl From<minreq::Error> for JsonRPCError {
     }
 }
 
+impl From<tungstenite::Error> for JsonRPCError {
+    fn from(e: tungstenite::Error) -> Self {
+        JsonRPCError::WebSocket(Box::new(e))
+    }
+}
+
+impl From<io::Error> for JsonRPCError {
+    fn from(e: io::Error) -> Self {
+        JsonRPCError::Io(e)
+    }
+}
+
 impl From<hex::FromHexError> for JsonRPCError {
     fn from(e: hex::FromHexError) -> Self {
         JsonRPCError::FromHex(e)
@@ -0,0 +1,371 @@
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use futures_util::stream::{self, Stream};
+use log::{debug, error, info, warn};
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio::sync::Mutex;
+use tokio::time::interval;
+
+use crate::config::Notifications;
+use crate::correlation::Correlator;
+use crate::db;
+use crate::domain::{Event, EventKind, EventSeverity};
+use crate::notifications;
+use crate::types::{now_timestamp, Db};
+
+const EVENT_CHANNEL_CAPACITY: usize = 64;
+// How often the aggregated events of ended correlation windows are emitted.
+const CORRELATION_FLUSH_INTERVAL: Duration = Duration::from_secs(30);
+// Events a resuming consumer missed are replayed up to this number per
+// network.
+pub const MAX_REPLAYED_EVENTS: u32 = 1000;
+
+// The position of an event stream consumer: the sequence number of the last
+// event it received per network. Formatted as e.g. "1:12,2:5" and used as the
+// SSE event id, so reconnecting consumers send it as Last-Event-ID.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct EventCursor(BTreeMap<u32, u64>);
+
+impl EventCursor {
+    fn sequence(&self, network_id: u32) -> u64 {
+        self.0.get(&network_id).copied().unwrap_or(0)
+    }
+
+    // Moves the cursor past the event. Returns false if the consumer already
+    // received it. Events that couldn't be written to the database don't
+    // have a sequence number and are always passed on.
+    fn advance(&mut self, event: &Event) -> bool {
+        if event.sequence == 0 {
+            return true;
+        }
+        if event.sequence <= self.sequence(event.network_id) {
+            return false;
+        }
+        self.0.insert(event.network_id, event.sequence);
+        true
+    }
+}
+
+impl fmt::Display for EventCursor {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let positions: Vec<String> = self
+            .0
+            .iter()
+            .map(|(network_id, sequence)| format!("{}:{}", network_id, sequence))
+            .collect();
+        write!(f, "{}", positions.join(","))
+    }
+}
+
+impl FromStr for EventCursor {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut positions = BTreeMap::new();
+        for position in s.split(',').filter(|p| !p.trim().is_empty()) {
+            let (network_id, sequence) = position
+                .trim()
+                .split_once(':')
+                .ok_or_else(|| format!("invalid event cursor position '{}'", position))?;
+            let network_id = network_id
+                .parse()
+                .map_err(|_| format!("invalid network id '{}'", network_id))?;
+            let sequence = sequence
+                .parse()
+                .map_err(|_| format!("invalid sequence number '{}'", sequence))?;
+            positions.insert(network_id, sequence);
+        }
+        Ok(EventCursor(positions))
+    }
+}
+
+// Persists emitted events, queues notifications about them and broadcasts
+// them to subscribers (e.g. the event SSE stream).
+#[derive(Clone)]
+pub struct EventLog {
+    db: Db,
+    notifications: Notifications,
+    tx: broadcast::Sender<Event>,
+    // Held while an event is written and broadcast, so that subscribers
+    // receive the events of a network in sequence order.
+    sequencing: Arc<Mutex<()>>,
+    correlator: Arc<std::sync::Mutex<Correlator>>,
+}
+
+impl EventLog {
+    pub fn new(
+        db: Db,
+        notifications: Notifications,
+        correlation_windows: HashMap<EventKind, Duration>,
+    ) -> Self {
+        let (tx, _) = broadcast::channel(EVENT_CHANNEL_CAPACITY);
+        EventLog {
+            db,
+            notifications,
+            tx,
+            sequencing: Arc::new(Mutex::new(())),
+            correlator: Arc::new(std::sync::Mutex::new(Correlator::new(correlation_windows))),
+        }
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<Event> {
+        self.tx.subscribe()
+    }
+
+    // Identical events, i.e. of the same kind and with the same title, are
+    // correlated.
+    pub async fn emit(
+        &self,
+        network_id: u32,
+        kind: EventKind,
+        severity: EventSeverity,
+        title: String,
+        description: String,
+    ) {
+        let key = format!("{}:{}", kind, title);
+        self.emit_correlated(network_id, kind, &key, severity, title, description)
+            .await;
+    }
+
+    // Events with the same key are correlated, even if they differ in kind
+    // or title, e.g. the changing reachability of a node.
+    pub async fn emit_correlated(
+        &self,
+        network_id: u32,
+        kind: EventKind,
+        key: &str,
+        severity: EventSeverity,
+        title: String,
+        description: String,
+    ) {
+        let event = Event {
+            id: 0,
+            network_id,
+            timestamp: now_timestamp(),
+            kind,
+            severity,
+            title,
+            description,
+            sequence: 0,
+        };
+        let emit_now = match self.correlator.lock() {
+            Ok(mut correlator) => correlator.correlate(&event, key.to_string()),
+            Err(_) => true,
+        };
+        if emit_now {
+            self.publish(event).await;
+        } else {
+            debug!(
+                "Suppressed correlated event on network {}: {} ({})",
+                event.network_id, event.title, event.kind
+            );
+        }
+    }
+
+    // Emits the aggregated events of the correlation windows that ended.
+    async fn emit_aggregated(&self) {
+        let aggregated = match self.correlator.lock() {
+            Ok(mut correlator) => correlator.expired(now_timestamp()),
+            Err(_) => return,
+        };
+        for event in aggregated {
+            self.publish(event).await;
+        }
+    }
+
+    async fn publish(&self, mut event: Event) {
+        info!(
+            "Event on network {}: {} ({})",
+            event.network_id, event.title, event.kind
+        );
+
+        {
+            let _sequencing = self.sequencing.lock().await;
+            match db::write_event(self.db.clone(), &event).await {
+                Ok((id, sequence)) => {
+                    event.id = id;
+                    event.sequence = sequence;
+                }
+                Err(e) => {
+                    error!("Could not write event '{}' to database: {}", event.title, e);
+                }
+            }
+            if let Err(e) = self.tx.send(event.clone()) {
+                debug!("No subscribers for event: {}", e);
+            }
+        }
+
+        notifications::enqueue(&self.notifications, self.db.clone(), &event).await;
+    }
+
+    // The cursor of a consumer that hasn't received any events yet: the
+    // current sequence numbers of all networks, starting at zero for the
+    // given networks without events.
+    pub async fn current_cursor(&self, network_ids: &[u32]) -> EventCursor {
+        let mut cursor = EventCursor::default();
+        for network_id in network_ids {
+            cursor.0.insert(*network_id, 0);
+        }
+        match db::load_event_sequences(self.db.clone()).await {
+            Ok(sequences) => cursor.0.extend(sequences),
+            Err(e) => error!("Could not load the event sequence numbers: {}", e),
+        }
+        cursor
+    }
+
+    // Loads the events a consumer at the cursor missed, in the order they
+    // were emitted.
+    async fn missed(&self, cursor: &EventCursor) -> Vec<Event> {
+        let sequences = match db::load_event_sequences(self.db.clone()).await {
+            Ok(sequences) => sequences,
+            Err(e) => {
+                error!("Could not load the event sequence numbers: {}", e);
+                return vec![];
+            }
+        };
+        let mut missed = vec![];
+        for (network_id, sequence) in sequences {
+            let after = cursor.sequence(network_id);
+            if sequence <= after {
+                continue;
+            }
+            if sequence - after > MAX_REPLAYED_EVENTS as u64 {
+                warn!(
+                    "Only replaying {} of the {} missed events on network {}",
+                    MAX_REPLAYED_EVENTS,
+                    sequence - after,
+                    network_id
+                );
+            }
+            match db::load_events_after(self.db.clone(), network_id, after, MAX_REPLAYED_EVENTS)
+                .await
+            {
+                Ok(events) => missed.extend(events),
+                Err(e) => error!(
+                    "Could not load the missed events of network {}: {}",
+                    network_id, e
+                ),
+            }
+        }
+        missed.sort_by_key(|event| event.id);
+        missed
+    }
+
+    // Streams the events emitted after the cursor, each with the cursor
+    // following it: first the missed events from the database, then the
+    // newly emitted ones. Events missed because the consumer lagged behind
+    // the broadcast are reloaded from the database as well.
+    pub async fn stream_after(
+        &self,
+        cursor: EventCursor,
+    ) -> impl Stream<Item = (Event, EventCursor)> {
+        // Subscribe before loading the missed events so none are lost in
+        // between. Events received twice are skipped by the cursor.
+        let rx = self.subscribe();
+        let pending: VecDeque<Event> = self.missed(&cursor).await.into();
+        let event_log = self.clone();
+        stream::unfold(
+            (event_log, rx, cursor, pending),
+            |(event_log, mut rx, mut cursor, mut pending)| async move {
+                loop {
+                    if let Some(event) = pending.pop_front() {
+                        if cursor.advance(&event) {
+                            let next = cursor.clone();
+                            return Some(((event, next), (event_log, rx, cursor, pending)));
+                        }
+                        continue;
+                    }
+                    match rx.recv().await {
+                        Ok(event) => pending.push_back(event),
+                        Err(RecvError::Lagged(skipped)) => {
+                            warn!(
+                                "An event stream lagged behind by {} events, reloading them",
+                                skipped
+                            );
+                            pending.extend(event_log.missed(&cursor).await);
+                        }
+                        Err(RecvError::Closed) => return None,
+                    }
+                }
+            },
+        )
+    }
+}
+
+// Periodically emits the aggregated events of the ended correlation windows.
+pub async fn flush_correlated(event_log: EventLog) {
+    let mut interval = interval(CORRELATION_FLUSH_INTERVAL);
+    loop {
+        interval.tick().await;
+        event_log.emit_aggregated().await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures_util::StreamExt;
+    use rusqlite::Connection;
+
+    #[test]
+    fn test_cursor() {
+        let cursor: EventCursor = "1:12, 2:5".parse().unwrap();
+        assert_eq!(cursor.to_string(), "1:12,2:5");
+        assert_eq!(cursor.sequence(2), 5);
+        assert_eq!(cursor.sequence(3), 0);
+        assert_eq!("".parse::<EventCursor>(), Ok(EventCursor::default()));
+        assert!("1".parse::<EventCursor>().is_err());
+        assert!("1:x".parse::<EventCursor>().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_stream_after() {
+        let db: Db = Arc::new(Mutex::new(Connection::open_in_memory().unwrap()));
+        db::setup_db(db.clone()).await.unwrap();
+        let event_log = EventLog::new(
+            db,
+            Notifications {
+                max_attempts: 1,
+                sinks: vec![],
+            },
+            HashMap::new(),
+        );
+        let emit = |network_id: u32, title: &str| {
+            event_log.emit(
+                network_id,
+                EventKind::StaleBlock,
+                EventSeverity::Info,
+                title.to_string(),
+                String::new(),
+            )
+        };
+        emit(1, "a").await;
+        emit(2, "b").await;
+        let cursor = event_log.current_cursor(&[1, 2, 3]).await;
+        assert_eq!(cursor.to_string(), "1:1,2:1,3:0");
+        emit(1, "c").await;
+        emit(3, "d").await;
+        emit(1, "e").await;
+
+        // a consumer at the cursor missed c, d and e
+        let stream = event_log.stream_after(cursor).await;
+        let received: Vec<(Event, EventCursor)> = stream.take(3).collect().await;
+        let titles: Vec<&str> = received.iter().map(|(e, _)| e.title.as_str()).collect();
+        assert_eq!(titles, vec!["c", "d", "e"]);
+        assert_eq!(received[2].0.sequence, 3);
+        assert_eq!(received[2].1.to_string(), "1:3,2:1,3:1");
+
+        // newly emitted events follow the missed ones
+        let mut stream = Box::pin(event_log.stream_after(received[0].1.clone()).await);
+        assert_eq!(stream.next().await.unwrap().0.title, "d");
+        assert_eq!(stream.next().await.unwrap().0.title, "e");
+        emit(2, "f").await;
+        let (event, cursor) = stream.next().await.unwrap();
+        assert_eq!((event.title.as_str(), event.sequence), ("f", 2));
+        assert_eq!(cursor.to_string(), "1:3,2:2,3:1");
+    }
+}
@@ -0,0 +1,48 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::Arc;
+
+use bitcoincore_rpc::bitcoin::BlockHash;
+use tokio::sync::Mutex;
+
+// Old forks expanded on demand. Only the forks in the latest
+// max_interesting_heights are served via the API, while the headers of all
+// forks are kept. Expanding a fork serves it again until it's collapsed or
+// the observer is restarted.
+//
+// The expanded forks per network id by the last common block with the
+// heights they span.
+pub type ExpandedForks = Arc<Mutex<BTreeMap<u32, BTreeMap<BlockHash, (u64, u64)>>>>;
+
+// The heights to serve in addition to the max_interesting_heights of the
+// network.
+pub async fn heights(expanded: &ExpandedForks, network_id: u32) -> BTreeSet<u64> {
+    let expanded_locked = expanded.lock().await;
+    match expanded_locked.get(&network_id) {
+        Some(forks) => forks.values().flat_map(|(from, to)| *from..=*to).collect(),
+        None => BTreeSet::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoincore_rpc::bitcoin::hashes::Hash;
+
+    #[tokio::test]
+    async fn test_heights() {
+        let expanded: ExpandedForks = Arc::new(Mutex::new(BTreeMap::new()));
+        assert!(heights(&expanded, 1).await.is_empty());
+        expanded.lock().await.insert(
+            1,
+            BTreeMap::from([
+                (BlockHash::all_zeros(), (10, 12)),
+                (BlockHash::from_byte_array([1; 32]), (12, 13)),
+            ]),
+        );
+        assert_eq!(
+            heights(&expanded, 1).await,
+            BTreeSet::from([10, 11, 12, 13])
+        );
+        assert!(heights(&expanded, 2).await.is_empty());
+    }
+}
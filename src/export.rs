@@ -0,0 +1,435 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use bitcoincore_rpc::bitcoin::consensus::serialize;
+use bitcoincore_rpc::bitcoin::hashes::{sha256, Hash};
+use log::info;
+use tokio::sync::Mutex;
+
+use crate::config::Network;
+use crate::db;
+use crate::domain::{HeaderInfo, TipObservation};
+use crate::error::ExportError;
+use crate::headertree;
+use crate::types::{
+    now_timestamp, Db, ExportFileJson, ExportForkJson, ExportForksJson, ExportManifestJson,
+    ExportNetworkJson,
+};
+
+pub const EXPORT_FORMAT: &str = "fork-observer-export";
+// Bump on breaking changes to the layout of the bundle or its files.
+pub const EXPORT_FORMAT_VERSION: u32 = 1;
+
+const FILENAME_MANIFEST: &str = "manifest.json";
+const FILENAME_HEADERS_BIN: &str = "headers.bin";
+const FILENAME_HEADERS_CSV: &str = "headers.csv";
+const FILENAME_FORKS: &str = "forks.json";
+const FILENAME_OBSERVATIONS: &str = "observations.csv";
+
+const TAR_BLOCK_SIZE: usize = 512;
+const TAR_NAME_LEN: usize = 100;
+const TAR_PREFIX_LEN: usize = 155;
+
+pub struct ExportFile {
+    pub name: &'static str,
+    pub description: &'static str,
+    pub content: Vec<u8>,
+}
+
+// The arguments of the export command:
+// fork-observer export --network <id> --from <timestamp> [--to <timestamp>] --out <directory>
+#[derive(Debug, PartialEq, Eq)]
+pub struct ExportArgs {
+    pub network: u32,
+    pub from: u64,
+    pub to: u64,
+    pub out: PathBuf,
+}
+
+pub fn parse_args(args: &[String], now: u64) -> Result<ExportArgs, ExportError> {
+    let mut network: Option<u32> = None;
+    let mut from: Option<u64> = None;
+    let mut to: Option<u64> = None;
+    let mut out: Option<PathBuf> = None;
+
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        let value = match iter.next() {
+            Some(value) => value,
+            None => {
+                return Err(ExportError::InvalidArguments(format!(
+                    "missing value for '{}'",
+                    arg
+                )))
+            }
+        };
+        let invalid =
+            |_| ExportError::InvalidArguments(format!("invalid value '{}' for '{}'", value, arg));
+        match arg.as_str() {
+            "--network" => network = Some(value.parse().map_err(invalid)?),
+            "--from" => from = Some(value.parse().map_err(invalid)?),
+            "--to" => to = Some(value.parse().map_err(invalid)?),
+            "--out" => out = Some(PathBuf::from(value)),
+            _ => {
+                return Err(ExportError::InvalidArguments(format!(
+                    "unknown argument '{}'",
+                    arg
+                )))
+            }
+        }
+    }
+
+    let missing = |name: &str| ExportError::InvalidArguments(format!("'{}' is required", name));
+    let args = ExportArgs {
+        network: network.ok_or_else(|| missing("--network"))?,
+        from: from.ok_or_else(|| missing("--from"))?,
+        to: to.unwrap_or(now),
+        out: out.ok_or_else(|| missing("--out"))?,
+    };
+    if args.from > args.to {
+        return Err(ExportError::InvalidArguments(
+            "'--from' must not be after '--to'".to_string(),
+        ));
+    }
+    Ok(args)
+}
+
+// Quotes a CSV field if needed.
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+// The headers as serialized by the nodes. Elements headers carry the
+// signatures of the federation, so they have different lengths.
+fn headers_bin(headers: &[HeaderInfo]) -> Vec<u8> {
+    headers
+        .iter()
+        .flat_map(|hi| match hi.elements {
+            Some(ref elements) => serialize(elements.as_ref()),
+            None => serialize(&hi.header),
+        })
+        .collect()
+}
+
+fn headers_csv(headers: &[HeaderInfo]) -> Vec<u8> {
+    let mut csv = String::from("height,hash,prev_blockhash,time,miner\n");
+    for hi in headers {
+        csv.push_str(&format!(
+            "{},{},{},{},{}\n",
+            hi.height,
+            hi.block_hash(),
+            hi.header.prev_blockhash,
+            hi.header.time,
+            csv_field(&hi.miner)
+        ));
+    }
+    csv.into_bytes()
+}
+
+fn observations_csv(network: &Network, observations: &[TipObservation]) -> Vec<u8> {
+    let mut csv = String::from("timestamp,node_id,node_name,height,hash,status\n");
+    for observation in observations {
+        // Nodes removed from the configuration have no name anymore.
+        let node_name = network
+            .nodes
+            .iter()
+            .find(|n| n.info().id == observation.node_id)
+            .map(|n| n.info().name)
+            .unwrap_or_default();
+        csv.push_str(&format!(
+            "{},{},{},{},{},{}\n",
+            observation.timestamp,
+            observation.node_id,
+            csv_field(&node_name),
+            observation.height,
+            observation.hash,
+            observation.status
+        ));
+    }
+    csv.into_bytes()
+}
+
+// Creates the files of an export bundle with the headers, forks and tip
+// observations of the network between from and to (inclusive). Headers and
+// forks are selected by block time, observations by the time they were
+// made. The extra files, e.g. incident recordings, are added after these.
+// The manifest is the first file.
+pub async fn create(
+    db: Db,
+    network: &Network,
+    from: u64,
+    to: u64,
+    extra: Vec<ExportFile>,
+) -> Result<Vec<ExportFile>, ExportError> {
+    info!(
+        "Exporting network '{}' (id={}) from {} to {}..",
+        network.name, network.id, from, to
+    );
+    let in_range = |hi: &HeaderInfo| (from..=to).contains(&(hi.header.time as u64));
+
+    let tree_info =
+        db::load_treeinfos(db.clone(), network.header_store, network.chain_kind).await?;
+    let mut headers: Vec<HeaderInfo> = tree_info
+        .0
+        .node_weights()
+        .filter(|hi| in_range(hi))
+        .cloned()
+        .collect();
+    headers.sort_by_key(|hi| hi.height);
+
+    let tree = std::sync::Arc::new(Mutex::new(tree_info));
+    let mut forks = headertree::recent_forks(&tree, usize::MAX).await;
+    forks.retain(|fork| fork.children.iter().any(in_range));
+    forks.sort_by_key(|fork| fork.common.height);
+    let forks_json = ExportForksJson {
+        forks: forks.iter().map(ExportForkJson::from).collect(),
+    };
+
+    let observations = db::load_tip_observations(db, network.id, from, to).await?;
+
+    let mut files = vec![
+        ExportFile {
+            name: FILENAME_HEADERS_BIN,
+            description: "The block headers in consensus encoding, concatenated and ordered by height. Includes stale blocks. Bitcoin headers are 80 bytes, Elements headers vary in length.",
+            content: headers_bin(&headers),
+        },
+        ExportFile {
+            name: FILENAME_HEADERS_CSV,
+            description: "One row per header in headers.bin, in the same order, with the identified miner.",
+            content: headers_csv(&headers),
+        },
+        ExportFile {
+            name: FILENAME_FORKS,
+            description: "Forks with the last common block and the first block of each branch, ordered by height.",
+            content: serde_json::to_vec_pretty(&forks_json)?,
+        },
+        ExportFile {
+            name: FILENAME_OBSERVATIONS,
+            description: "The chain tips reported by each node, recorded whenever the tips of a node changed.",
+            content: observations_csv(network, &observations),
+        },
+    ];
+    files.extend(extra);
+
+    let manifest = ExportManifestJson {
+        format: EXPORT_FORMAT.to_string(),
+        format_version: EXPORT_FORMAT_VERSION,
+        generator: format!("fork-observer {}", env!("CARGO_PKG_VERSION")),
+        created: now_timestamp(),
+        network: ExportNetworkJson {
+            id: network.id,
+            name: network.name.clone(),
+            description: network.description.clone(),
+            genesis_hash: network.chain_params.genesis_hash.map(|h| h.to_string()),
+        },
+        from,
+        to,
+        citation: format!(
+            "fork-observer export of the network '{}' from {} to {} (UTC timestamps).",
+            network.name, from, to
+        ),
+        files: files
+            .iter()
+            .map(|file| ExportFileJson {
+                name: file.name.to_string(),
+                description: file.description.to_string(),
+                size: file.content.len(),
+                sha256: sha256::Hash::hash(&file.content).to_string(),
+            })
+            .collect(),
+    };
+    files.insert(
+        0,
+        ExportFile {
+            name: FILENAME_MANIFEST,
+            description: "Describes the bundle and lists the files with their SHA256 hashes.",
+            content: serde_json::to_vec_pretty(&manifest)?,
+        },
+    );
+
+    info!(
+        "Exported network '{}' (id={}): headers={}, forks={}, observations={}",
+        network.name,
+        network.id,
+        headers.len(),
+        forks.len(),
+        observations.len()
+    );
+    Ok(files)
+}
+
+pub fn bundle_name(network: &Network, from: u64, to: u64) -> String {
+    format!("fork-observer-{}-{}-{}", network.id, from, to)
+}
+
+pub fn write_dir(dir: &Path, files: &[ExportFile]) -> Result<(), ExportError> {
+    fs::create_dir_all(dir)?;
+    for file in files {
+        fs::write(dir.join(file.name), &file.content)?;
+    }
+    Ok(())
+}
+
+// Writes the value as zero-padded octal number with a trailing NUL. None if
+// it has more digits than fit into the field, e.g. for files of 8 GiB and
+// larger in the 12 byte size field.
+fn write_octal(field: &mut [u8], value: u64) -> Option<()> {
+    let octal = format!("{:0width$o}\0", value, width = field.len() - 1);
+    if octal.len() > field.len() {
+        return None;
+    }
+    field.copy_from_slice(octal.as_bytes());
+    Some(())
+}
+
+// Splits the path into the 155 byte prefix and the 100 byte name field of a
+// ustar header at a slash. Paths fitting into the name field aren't split.
+fn split_path(path: &str) -> Option<(&str, &str)> {
+    if path.len() <= TAR_NAME_LEN {
+        return Some(("", path));
+    }
+    path.match_indices('/')
+        .map(|(i, _)| (&path[..i], &path[i + 1..]))
+        .find(|(prefix, name)| {
+            prefix.len() <= TAR_PREFIX_LEN && !name.is_empty() && name.len() <= TAR_NAME_LEN
+        })
+}
+
+fn tar_header(path: &str, size: usize, mtime: u64) -> Result<[u8; TAR_BLOCK_SIZE], ExportError> {
+    let (prefix, name) =
+        split_path(path).ok_or_else(|| ExportError::PathTooLong(path.to_string()))?;
+    let too_large = || ExportError::FileTooLarge(path.to_string());
+    let mut header = [0u8; TAR_BLOCK_SIZE];
+    header[..name.len()].copy_from_slice(name.as_bytes());
+    header[345..345 + prefix.len()].copy_from_slice(prefix.as_bytes());
+    write_octal(&mut header[100..108], 0o644).ok_or_else(too_large)?; // mode
+    write_octal(&mut header[108..116], 0).ok_or_else(too_large)?; // uid
+    write_octal(&mut header[116..124], 0).ok_or_else(too_large)?; // gid
+    write_octal(&mut header[124..136], size as u64).ok_or_else(too_large)?;
+    write_octal(&mut header[136..148], mtime).ok_or_else(too_large)?;
+    header[156] = b'0'; // regular file
+    header[257..263].copy_from_slice(b"ustar\0");
+    header[263..265].copy_from_slice(b"00");
+    // The checksum is calculated with the checksum field set to spaces.
+    header[148..156].copy_from_slice(b"        ");
+    let checksum: u64 = header.iter().map(|b| *b as u64).sum();
+    write_octal(&mut header[148..155], checksum).ok_or_else(too_large)?;
+    Ok(header)
+}
+
+// Packs the files into an uncompressed ustar archive with the files in a
+// directory named after the bundle. Fails if a path is too long for the
+// name and prefix fields of the header.
+pub fn tar(dir: &str, files: &[ExportFile], mtime: u64) -> Result<Vec<u8>, ExportError> {
+    let mut archive: Vec<u8> = vec![];
+    for file in files {
+        let path = format!("{}/{}", dir, file.name);
+        archive.extend_from_slice(&tar_header(&path, file.content.len(), mtime)?);
+        archive.extend_from_slice(&file.content);
+        let padding = (TAR_BLOCK_SIZE - file.content.len() % TAR_BLOCK_SIZE) % TAR_BLOCK_SIZE;
+        archive.resize(archive.len() + padding, 0);
+    }
+    // The archive ends with two empty blocks.
+    archive.resize(archive.len() + 2 * TAR_BLOCK_SIZE, 0);
+    Ok(archive)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_args() {
+        let args: Vec<String> = ["--network", "1", "--from", "100", "--out", "export"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        assert_eq!(
+            parse_args(&args, 200).unwrap(),
+            ExportArgs {
+                network: 1,
+                from: 100,
+                to: 200,
+                out: PathBuf::from("export"),
+            }
+        );
+
+        for invalid in [
+            vec!["--network", "1", "--from", "100"],
+            vec!["--network", "1", "--from", "300", "--out", "export"],
+            vec!["--network", "x", "--from", "100", "--out", "export"],
+            vec!["--network", "1", "--from", "100", "--out"],
+            vec!["--foo", "1"],
+        ] {
+            let args: Vec<String> = invalid.iter().map(|s| s.to_string()).collect();
+            assert!(parse_args(&args, 200).is_err(), "{:?}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_tar() {
+        let files = vec![ExportFile {
+            name: "a.csv",
+            description: "",
+            content: b"x,y\n".to_vec(),
+        }];
+        let archive = tar("bundle", &files, 1700000000).unwrap();
+        // header, one content block and two end blocks
+        assert_eq!(archive.len(), 4 * TAR_BLOCK_SIZE);
+        assert_eq!(&archive[..12], b"bundle/a.csv");
+        assert_eq!(&archive[124..136], b"00000000004\0");
+        assert_eq!(&archive[257..263], b"ustar\0");
+        assert_eq!(&archive[TAR_BLOCK_SIZE..TAR_BLOCK_SIZE + 4], b"x,y\n");
+
+        let checksum: u64 = archive[..TAR_BLOCK_SIZE]
+            .iter()
+            .enumerate()
+            .map(|(i, b)| {
+                if (148..156).contains(&i) {
+                    32
+                } else {
+                    *b as u64
+                }
+            })
+            .sum();
+        let stored = std::str::from_utf8(&archive[148..154]).unwrap();
+        assert_eq!(u64::from_str_radix(stored, 8).unwrap(), checksum);
+
+        // the size field holds at most 11 octal digits
+        assert!(tar_header("bundle/a.csv", 0o77777777777, 0).is_ok());
+        assert!(matches!(
+            tar_header("bundle/a.csv", 0o100000000000, 0),
+            Err(ExportError::FileTooLarge(_))
+        ));
+    }
+
+    #[test]
+    fn test_tar_long_paths() {
+        let dir = "d".repeat(120);
+        let files = vec![ExportFile {
+            name: "a.csv",
+            description: "",
+            content: vec![],
+        }];
+        let archive = tar(&dir, &files, 0).unwrap();
+        assert_eq!(&archive[..6], b"a.csv\0");
+        assert_eq!(&archive[345..465], dir.as_bytes());
+
+        assert_eq!(split_path("a/b"), Some(("", "a/b")));
+        assert!(tar(&"d".repeat(200), &files, 0).is_err());
+        assert!(tar(&"d".repeat(600), &files, 0).is_err());
+        let name = "f".repeat(101);
+        assert_eq!(split_path(&format!("{}/{}", "d", name)), None);
+    }
+
+    #[test]
+    fn test_csv_field() {
+        assert_eq!(csv_field("Foundry USA"), "Foundry USA");
+        assert_eq!(csv_field("a,b"), "\"a,b\"");
+        assert_eq!(csv_field("a\"b"), "\"a\"\"b\"");
+    }
+}
@@ -0,0 +1,423 @@
+// A tiny expression language for operator-defined computed fields. It
+// supports integer and boolean values, variables provided by the caller,
+// the usual arithmetic, bitwise, comparison and logical operators and a
+// bit(x, n) function. Expressions are type checked when parsed, so only
+// arithmetic errors like division by zero can happen during evaluation.
+
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Type {
+    Int,
+    Bool,
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Type::Int => write!(f, "integer"),
+            Type::Bool => write!(f, "boolean"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Value {
+    Int(i64),
+    Bool(bool),
+}
+
+impl Value {
+    fn as_int(&self) -> i64 {
+        match self {
+            Value::Int(i) => *i,
+            Value::Bool(b) => *b as i64,
+        }
+    }
+
+    fn as_bool(&self) -> bool {
+        match self {
+            Value::Int(i) => *i != 0,
+            Value::Bool(b) => *b,
+        }
+    }
+}
+
+impl From<Value> for serde_json::Value {
+    fn from(v: Value) -> Self {
+        match v {
+            Value::Int(i) => serde_json::Value::from(i),
+            Value::Bool(b) => serde_json::Value::from(b),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinOp {
+    Or,
+    And,
+    BitOr,
+    BitXor,
+    BitAnd,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Shl,
+    Shr,
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+}
+
+impl BinOp {
+    // Binding power. Higher binds tighter.
+    fn precedence(&self) -> u8 {
+        match self {
+            BinOp::Or => 1,
+            BinOp::And => 2,
+            BinOp::BitOr => 3,
+            BinOp::BitXor => 4,
+            BinOp::BitAnd => 5,
+            BinOp::Eq | BinOp::Ne => 6,
+            BinOp::Lt | BinOp::Le | BinOp::Gt | BinOp::Ge => 7,
+            BinOp::Shl | BinOp::Shr => 8,
+            BinOp::Add | BinOp::Sub => 9,
+            BinOp::Mul | BinOp::Div | BinOp::Rem => 10,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Int(i64),
+    Ident(String),
+    BinOp(BinOp),
+    Not,
+    LParen,
+    RParen,
+    Comma,
+}
+
+#[derive(Debug, Clone)]
+pub enum Expr {
+    Literal(Value),
+    Var(String),
+    Not(Box<Expr>),
+    Neg(Box<Expr>),
+    Binary(BinOp, Box<Expr>, Box<Expr>),
+    Bit(Box<Expr>, Box<Expr>),
+}
+
+fn tokenize(source: &str) -> Result<Vec<Token>, String> {
+    let chars: Vec<char> = source.chars().collect();
+    let mut tokens: Vec<Token> = vec![];
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        let next = chars.get(i + 1).copied();
+        if c.is_whitespace() {
+            i += 1;
+            continue;
+        }
+        if c.is_ascii_digit() {
+            let start = i;
+            while i < chars.len() && (chars[i].is_ascii_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            let literal: String = chars[start..i].iter().filter(|c| **c != '_').collect();
+            let parsed = match literal.strip_prefix("0x") {
+                Some(hex) => i64::from_str_radix(hex, 16),
+                None => literal.parse::<i64>(),
+            };
+            match parsed {
+                Ok(value) => tokens.push(Token::Int(value)),
+                Err(_) => return Err(format!("invalid integer '{}'", literal)),
+            }
+            continue;
+        }
+        if c.is_ascii_alphabetic() || c == '_' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_ascii_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            tokens.push(Token::Ident(chars[start..i].iter().collect()));
+            continue;
+        }
+        let (token, len) = match (c, next) {
+            ('|', Some('|')) => (Token::BinOp(BinOp::Or), 2),
+            ('&', Some('&')) => (Token::BinOp(BinOp::And), 2),
+            ('=', Some('=')) => (Token::BinOp(BinOp::Eq), 2),
+            ('!', Some('=')) => (Token::BinOp(BinOp::Ne), 2),
+            ('<', Some('=')) => (Token::BinOp(BinOp::Le), 2),
+            ('>', Some('=')) => (Token::BinOp(BinOp::Ge), 2),
+            ('<', Some('<')) => (Token::BinOp(BinOp::Shl), 2),
+            ('>', Some('>')) => (Token::BinOp(BinOp::Shr), 2),
+            ('|', _) => (Token::BinOp(BinOp::BitOr), 1),
+            ('^', _) => (Token::BinOp(BinOp::BitXor), 1),
+            ('&', _) => (Token::BinOp(BinOp::BitAnd), 1),
+            ('<', _) => (Token::BinOp(BinOp::Lt), 1),
+            ('>', _) => (Token::BinOp(BinOp::Gt), 1),
+            ('+', _) => (Token::BinOp(BinOp::Add), 1),
+            ('-', _) => (Token::BinOp(BinOp::Sub), 1),
+            ('*', _) => (Token::BinOp(BinOp::Mul), 1),
+            ('/', _) => (Token::BinOp(BinOp::Div), 1),
+            ('%', _) => (Token::BinOp(BinOp::Rem), 1),
+            ('!', _) => (Token::Not, 1),
+            ('(', _) => (Token::LParen, 1),
+            (')', _) => (Token::RParen, 1),
+            (',', _) => (Token::Comma, 1),
+            _ => return Err(format!("unexpected character '{}'", c)),
+        };
+        tokens.push(token);
+        i += len;
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn expect(&mut self, expected: Token) -> Result<(), String> {
+        match self.next() {
+            Some(ref token) if *token == expected => Ok(()),
+            Some(token) => Err(format!("expected {:?}, found {:?}", expected, token)),
+            None => Err(format!("expected {:?}, found the end", expected)),
+        }
+    }
+
+    fn expression(&mut self, min_precedence: u8) -> Result<Expr, String> {
+        let mut lhs = self.unary()?;
+        while let Some(Token::BinOp(op)) = self.peek() {
+            let op = *op;
+            if op.precedence() < min_precedence {
+                break;
+            }
+            self.next();
+            // all operators are left associative
+            let rhs = self.expression(op.precedence() + 1)?;
+            lhs = Expr::Binary(op, Box::new(lhs), Box::new(rhs));
+        }
+        Ok(lhs)
+    }
+
+    fn unary(&mut self) -> Result<Expr, String> {
+        match self.peek() {
+            Some(Token::Not) => {
+                self.next();
+                Ok(Expr::Not(Box::new(self.unary()?)))
+            }
+            Some(Token::BinOp(BinOp::Sub)) => {
+                self.next();
+                Ok(Expr::Neg(Box::new(self.unary()?)))
+            }
+            _ => self.primary(),
+        }
+    }
+
+    fn primary(&mut self) -> Result<Expr, String> {
+        match self.next() {
+            Some(Token::Int(i)) => Ok(Expr::Literal(Value::Int(i))),
+            Some(Token::LParen) => {
+                let expr = self.expression(0)?;
+                self.expect(Token::RParen)?;
+                Ok(expr)
+            }
+            Some(Token::Ident(ident)) => match ident.as_str() {
+                "true" => Ok(Expr::Literal(Value::Bool(true))),
+                "false" => Ok(Expr::Literal(Value::Bool(false))),
+                "bit" => {
+                    self.expect(Token::LParen)?;
+                    let value = self.expression(0)?;
+                    self.expect(Token::Comma)?;
+                    let bit = self.expression(0)?;
+                    self.expect(Token::RParen)?;
+                    Ok(Expr::Bit(Box::new(value), Box::new(bit)))
+                }
+                _ => Ok(Expr::Var(ident)),
+            },
+            Some(token) => Err(format!("unexpected {:?}", token)),
+            None => Err(String::from("unexpected end of expression")),
+        }
+    }
+}
+
+impl Expr {
+    // Parses and type checks the expression. Only the given variables with
+    // their types may be used.
+    pub fn parse(source: &str, variables: &[(&str, Type)]) -> Result<(Expr, Type), String> {
+        let mut parser = Parser {
+            tokens: tokenize(source)?,
+            pos: 0,
+        };
+        let expr = parser.expression(0)?;
+        if let Some(token) = parser.peek() {
+            return Err(format!("unexpected {:?}", token));
+        }
+        let expr_type = expr.check(variables)?;
+        Ok((expr, expr_type))
+    }
+
+    fn check(&self, variables: &[(&str, Type)]) -> Result<Type, String> {
+        let expect = |expr: &Expr, expected: Type| -> Result<(), String> {
+            let actual = expr.check(variables)?;
+            if actual != expected {
+                return Err(format!("expected {}, found {}", expected, actual));
+            }
+            Ok(())
+        };
+        match self {
+            Expr::Literal(Value::Int(_)) => Ok(Type::Int),
+            Expr::Literal(Value::Bool(_)) => Ok(Type::Bool),
+            Expr::Var(name) => match variables.iter().find(|(n, _)| n == name) {
+                Some((_, t)) => Ok(*t),
+                None => Err(format!("unknown variable '{}'", name)),
+            },
+            Expr::Not(e) => expect(e, Type::Bool).map(|_| Type::Bool),
+            Expr::Neg(e) => expect(e, Type::Int).map(|_| Type::Int),
+            Expr::Bit(value, bit) => {
+                expect(value, Type::Int)?;
+                expect(bit, Type::Int)?;
+                Ok(Type::Bool)
+            }
+            Expr::Binary(op, lhs, rhs) => match op {
+                BinOp::Or | BinOp::And => {
+                    expect(lhs, Type::Bool)?;
+                    expect(rhs, Type::Bool)?;
+                    Ok(Type::Bool)
+                }
+                BinOp::Eq | BinOp::Ne => {
+                    let lhs_type = lhs.check(variables)?;
+                    expect(rhs, lhs_type)?;
+                    Ok(Type::Bool)
+                }
+                BinOp::Lt | BinOp::Le | BinOp::Gt | BinOp::Ge => {
+                    expect(lhs, Type::Int)?;
+                    expect(rhs, Type::Int)?;
+                    Ok(Type::Bool)
+                }
+                _ => {
+                    expect(lhs, Type::Int)?;
+                    expect(rhs, Type::Int)?;
+                    Ok(Type::Int)
+                }
+            },
+        }
+    }
+
+    // Evaluates the expression. The variables must contain all variables
+    // the expression was checked against.
+    pub fn eval(&self, variables: &[(&str, Value)]) -> Result<Value, String> {
+        match self {
+            Expr::Literal(v) => Ok(*v),
+            Expr::Var(name) => match variables.iter().find(|(n, _)| n == name) {
+                Some((_, v)) => Ok(*v),
+                None => Err(format!("unknown variable '{}'", name)),
+            },
+            Expr::Not(e) => Ok(Value::Bool(!e.eval(variables)?.as_bool())),
+            Expr::Neg(e) => Ok(Value::Int(e.eval(variables)?.as_int().wrapping_neg())),
+            Expr::Bit(value, bit) => {
+                let value = value.eval(variables)?.as_int();
+                let bit = bit.eval(variables)?.as_int();
+                if !(0..64).contains(&bit) {
+                    return Err(format!("bit {} out of range", bit));
+                }
+                Ok(Value::Bool((value >> bit) & 1 == 1))
+            }
+            Expr::Binary(op, lhs, rhs) => {
+                let lhs = lhs.eval(variables)?;
+                // short-circuit the logical operators
+                match op {
+                    BinOp::Or if lhs.as_bool() => return Ok(Value::Bool(true)),
+                    BinOp::And if !lhs.as_bool() => return Ok(Value::Bool(false)),
+                    _ => (),
+                }
+                let rhs = rhs.eval(variables)?;
+                let (a, b) = (lhs.as_int(), rhs.as_int());
+                Ok(match op {
+                    BinOp::Or | BinOp::And => Value::Bool(rhs.as_bool()),
+                    BinOp::Eq => Value::Bool(lhs == rhs),
+                    BinOp::Ne => Value::Bool(lhs != rhs),
+                    BinOp::Lt => Value::Bool(a < b),
+                    BinOp::Le => Value::Bool(a <= b),
+                    BinOp::Gt => Value::Bool(a > b),
+                    BinOp::Ge => Value::Bool(a >= b),
+                    BinOp::BitOr => Value::Int(a | b),
+                    BinOp::BitXor => Value::Int(a ^ b),
+                    BinOp::BitAnd => Value::Int(a & b),
+                    BinOp::Add => Value::Int(a.wrapping_add(b)),
+                    BinOp::Sub => Value::Int(a.wrapping_sub(b)),
+                    BinOp::Mul => Value::Int(a.wrapping_mul(b)),
+                    BinOp::Div | BinOp::Rem if b == 0 => {
+                        return Err(String::from("division by zero"))
+                    }
+                    BinOp::Div => Value::Int(a.wrapping_div(b)),
+                    BinOp::Rem => Value::Int(a.wrapping_rem(b)),
+                    BinOp::Shl | BinOp::Shr if !(0..64).contains(&b) => {
+                        return Err(format!("shift by {} out of range", b))
+                    }
+                    BinOp::Shl => Value::Int(a << b),
+                    BinOp::Shr => Value::Int(a >> b),
+                })
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn eval(source: &str) -> Result<Value, String> {
+        let variables = [("version", Type::Int), ("reachable", Type::Bool)];
+        let (expr, _) = Expr::parse(source, &variables)?;
+        expr.eval(&[
+            ("version", Value::Int(0x2000_0004)),
+            ("reachable", Value::Bool(true)),
+        ])
+    }
+
+    #[test]
+    fn test_eval() {
+        assert_eq!(eval("bit(version, 2)"), Ok(Value::Bool(true)));
+        assert_eq!(eval("bit(version, 1)"), Ok(Value::Bool(false)));
+        assert_eq!(eval("1 + 2 * 3"), Ok(Value::Int(7)));
+        assert_eq!(eval("(1 + 2) * 3"), Ok(Value::Int(9)));
+        assert_eq!(eval("10 - 4 - 3"), Ok(Value::Int(3)));
+        assert_eq!(eval("version >> 29 == 1"), Ok(Value::Bool(true)));
+        assert_eq!(eval("version & 0xe0000000"), Ok(Value::Int(0x2000_0000)));
+        assert_eq!(eval("!reachable || -1 < 0"), Ok(Value::Bool(true)));
+        assert_eq!(eval("reachable && 2 >= 3"), Ok(Value::Bool(false)));
+        assert!(eval("1 / 0").is_err());
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(eval("").is_err());
+        assert!(eval("1 +").is_err());
+        assert!(eval("(1").is_err());
+        assert!(eval("1 2").is_err());
+        assert!(eval("unknown + 1").is_err());
+        assert!(eval("reachable + 1").is_err());
+        assert!(eval("!version").is_err());
+        assert!(eval("reachable == 1").is_err());
+        assert!(eval("1 $ 2").is_err());
+    }
+}
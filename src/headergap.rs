@@ -0,0 +1,91 @@
+use crate::config::HeaderGap;
+use crate::types::HeaderGapJson;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum GapChange {
+    Unchanged,
+    // The gap exceeded the max_gap for alert_after.
+    Stalled,
+    // The gap of a stalled node closed again.
+    Recovered,
+}
+
+// Tracks since when the gap between the best known header and the validated
+// tip of a node exceeds the max_gap.
+#[derive(Debug, Default)]
+pub struct GapTracker {
+    exceeded_since: Option<u64>,
+    stalled: bool,
+}
+
+impl GapTracker {
+    pub fn update(
+        &mut self,
+        headers: u64,
+        blocks: u64,
+        header_gap: &HeaderGap,
+        now: u64,
+    ) -> (HeaderGapJson, GapChange) {
+        let gap = headers.saturating_sub(blocks);
+        let mut change = GapChange::Unchanged;
+        if gap > header_gap.max_gap {
+            let since = *self.exceeded_since.get_or_insert(now);
+            if !self.stalled && now.saturating_sub(since) >= header_gap.alert_after.as_secs() {
+                self.stalled = true;
+                change = GapChange::Stalled;
+            }
+        } else {
+            self.exceeded_since = None;
+            if self.stalled {
+                self.stalled = false;
+                change = GapChange::Recovered;
+            }
+        }
+        (
+            HeaderGapJson {
+                headers,
+                blocks,
+                gap,
+                exceeded_since: self.exceeded_since,
+            },
+            change,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_gap_tracker() {
+        let header_gap = HeaderGap {
+            max_gap: 6,
+            alert_after: Duration::from_secs(600),
+        };
+        let mut tracker = GapTracker::default();
+
+        // a short gap, e.g. while a new block is validated
+        let (json, change) = tracker.update(101, 100, &header_gap, 1000);
+        assert_eq!((json.gap, json.exceeded_since), (1, None));
+        assert_eq!(change, GapChange::Unchanged);
+
+        // a large gap is only reported after alert_after
+        let (json, change) = tracker.update(120, 100, &header_gap, 1100);
+        assert_eq!((json.gap, json.exceeded_since), (20, Some(1100)));
+        assert_eq!(change, GapChange::Unchanged);
+        assert_eq!(
+            tracker.update(121, 100, &header_gap, 1700).1,
+            GapChange::Stalled
+        );
+        assert_eq!(
+            tracker.update(122, 100, &header_gap, 1800).1,
+            GapChange::Unchanged
+        );
+
+        let (json, change) = tracker.update(122, 122, &header_gap, 1900);
+        assert_eq!((json.gap, json.exceeded_since), (0, None));
+        assert_eq!(change, GapChange::Recovered);
+    }
+}
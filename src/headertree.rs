@@ -1,8 +1,15 @@
+use std::cmp::Reverse;
 use std::collections::BTreeMap;
 use std::collections::BTreeSet;
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::str::FromStr;
 
-use crate::types::{Fork, HeaderInfoJson, Tree};
+use crate::config::ChainParams;
+use crate::domain::{ChainTip, ChainTipStatus, Fork, HeaderInfo, Tree, TreeInfo};
+use crate::types::HeaderInfoJson;
 
+use bitcoincore_rpc::bitcoin::BlockHash;
 use log::{debug, warn};
 use petgraph::graph::NodeIndex;
 use petgraph::visit::{Dfs, EdgeRef};
@@ -34,7 +41,7 @@ pub async fn sorted_interesting_heights(
     // Combine the heights with multiple blocks with the tip_heights.
     let mut interesting_heights_set: BTreeSet<u64> = heights_with_multiple_blocks
         .iter()
-        .map(|i| *i)
+        .copied()
         .chain(tip_heights)
         .collect();
 
@@ -42,13 +49,13 @@ pub async fn sorted_interesting_heights(
     // already have that in `tip_heights`, but include it here just to be
     // sure.
     let max_height: u64 = height_occurences
-        .iter()
-        .map(|(k, _)| *k)
+        .keys()
+        .copied()
         .max()
         .expect("we should have at least one height here as we have blocks");
     interesting_heights_set.insert(max_height);
 
-    let mut interesting_heights: Vec<u64> = interesting_heights_set.iter().map(|h| *h).collect();
+    let mut interesting_heights: Vec<u64> = interesting_heights_set.iter().copied().collect();
     interesting_heights.sort();
 
     // As, for example, testnet has a lot of forks we'd return many headers
@@ -56,7 +63,7 @@ pub async fn sorted_interesting_heights(
     // max_interesting_heights.
     interesting_heights = interesting_heights_set
         .iter()
-        .map(|h| *h)
+        .copied()
         .rev() // reversing: ascending -> descending
         .take(max_interesting_heights) // taking the 'last' max_interesting_heights
         .rev() // reversing: descending -> ascending
@@ -69,13 +76,22 @@ pub async fn sorted_interesting_heights(
 }
 
 // We strip the tree of headers that aren't interesting to us.
+// The expanded heights are kept in addition to the max_interesting_heights,
+// e.g. to show old forks on demand.
 pub async fn strip_tree(
     tree: &Tree,
     max_interesting_heights: usize,
     tip_heights: BTreeSet<u64>,
+    expanded_heights: BTreeSet<u64>,
+    params: &ChainParams,
 ) -> Vec<HeaderInfoJson> {
-    let interesting_heights =
+    let mut interesting_heights =
         sorted_interesting_heights(tree, max_interesting_heights, tip_heights).await;
+    if !expanded_heights.is_empty() {
+        let mut heights: BTreeSet<u64> = interesting_heights.into_iter().collect();
+        heights.extend(expanded_heights);
+        interesting_heights = heights.into_iter().collect();
+    }
 
     let tree_locked = tree.lock().await;
 
@@ -112,7 +128,7 @@ pub async fn strip_tree(
         // If we have a prev_header_to_connect_to, then connect
         // the current root to it.
         if let Some(prev_idx) = prev_header_to_connect_to {
-            striped_tree.add_edge(prev_idx, *root, &false);
+            striped_tree.add_edge(prev_idx, *root, &0);
             prev_header_to_connect_to = None;
         }
 
@@ -146,27 +162,412 @@ pub async fn strip_tree(
     let mut headers: Vec<HeaderInfoJson> = Vec::new();
     for idx in striped_tree.node_indices() {
         let prev_nodes = striped_tree.neighbors_directed(idx, petgraph::Direction::Incoming);
-        let prev_node_index: usize;
-        match prev_nodes.clone().count() {
-            0 => prev_node_index = usize::MAX, // indicates the start in JavaScript
-            1 => {
-                prev_node_index = prev_nodes
-                    .last()
-                    .expect("we should have exactly one previous node")
-                    .index()
-            }
+        let prev_node_index: usize = match prev_nodes.clone().count() {
+            0 => usize::MAX, // indicates the start in JavaScript
+            1 => prev_nodes
+                .last()
+                .expect("we should have exactly one previous node")
+                .index(),
             _ => panic!("got multiple previous nodes. this should not happen."),
-        }
+        };
         headers.push(HeaderInfoJson::new(
             striped_tree[idx],
             idx.index(),
             prev_node_index,
+            params,
         ));
     }
 
     headers
 }
 
+// Returns the headers that weren't in the tree yet. Another node of the
+// network might have inserted some of them in the meantime.
+pub async fn insert_new_headers_into_tree(
+    tree: &Tree,
+    new_headers: &[HeaderInfo],
+) -> Vec<HeaderInfo> {
+    let mut inserted: Vec<HeaderInfo> = vec![];
+    let mut tree_locked = tree.lock().await;
+    // insert headers to tree
+    for h in new_headers {
+        if !tree_locked.1.contains_key(&h.block_hash()) {
+            let idx = tree_locked.0.add_node(h.clone());
+            tree_locked.1.insert(h.block_hash(), idx);
+            inserted.push(h.clone());
+        }
+    }
+    // connect nodes with edges
+    for current in new_headers {
+        let idx_current: NodeIndex;
+        let idx_prev: NodeIndex;
+        {
+            idx_current = *tree_locked
+                    .1
+                    .get(&current.block_hash())
+                    .expect(
+                    "current header should be in the map as we just inserted it or it was already present",
+                );
+            match tree_locked.1.get(&current.header.prev_blockhash) {
+                Some(idx) => idx_prev = *idx,
+                None => {
+                    continue; // the tree's root has no previous block, skip it
+                }
+            }
+        }
+        tree_locked.0.update_edge(idx_prev, idx_current, 0);
+    }
+    // Accumulating from the inserted headers whose parent wasn't inserted
+    // with them covers all inserted headers, in whatever order they came.
+    let inserted_hashes: HashSet<BlockHash> = inserted.iter().map(|h| h.block_hash()).collect();
+    for h in inserted.iter() {
+        if !inserted_hashes.contains(&h.header.prev_blockhash) {
+            let idx = tree_locked.1[&h.block_hash()];
+            accumulate_chainwork(&mut tree_locked, idx);
+        }
+    }
+    inserted
+}
+
+// Sets the cumulative work of the header and the headers above it: the
+// work of its parent plus its own, or only its own for headers without a
+// parent in the tree. The work is thus counted from the first header of the
+// branch in the tree, not from the genesis block. Elements headers have no
+// proof of work and get no cumulative work.
+pub fn accumulate_chainwork(tree: &mut TreeInfo, idx: NodeIndex) {
+    let mut stack: Vec<NodeIndex> = vec![idx];
+    while let Some(idx) = stack.pop() {
+        let header_info = &tree.0[idx];
+        if header_info.elements.is_some() {
+            continue;
+        }
+        let parent_work = tree
+            .0
+            .neighbors_directed(idx, petgraph::Direction::Incoming)
+            .next()
+            .and_then(|parent| tree.2.get(&tree.0[parent].block_hash()))
+            .copied();
+        let work = match parent_work {
+            Some(parent_work) => parent_work + header_info.header.work(),
+            None => header_info.header.work(),
+        };
+        tree.2.insert(header_info.block_hash(), work);
+        stack.extend(
+            tree.0
+                .neighbors_directed(idx, petgraph::Direction::Outgoing),
+        );
+    }
+}
+
+// The last common block of the chains leading up to the two blocks. None if
+// either block isn't in the tree or their chains aren't connected in it.
+pub fn common_ancestor(tree: &TreeInfo, a: &BlockHash, b: &BlockHash) -> Option<NodeIndex> {
+    let mut a_idx = *tree.1.get(a)?;
+    let mut b_idx = *tree.1.get(b)?;
+    while a_idx != b_idx {
+        let higher = if tree.0[a_idx].height >= tree.0[b_idx].height {
+            &mut a_idx
+        } else {
+            &mut b_idx
+        };
+        *higher = tree
+            .0
+            .neighbors_directed(*higher, petgraph::Direction::Incoming)
+            .next()?;
+    }
+    Some(a_idx)
+}
+
+// The headers of the branch leading up to tip above its last common block
+// with the chain of other, lowest first. Empty if tip is on the chain of
+// other or their chains aren't connected in the tree.
+pub async fn branch_above(tree: &Tree, tip: &BlockHash, other: &BlockHash) -> Vec<HeaderInfo> {
+    let tree_locked = tree.lock().await;
+    let common = match common_ancestor(&tree_locked, tip, other) {
+        Some(common) => common,
+        None => return vec![],
+    };
+    let (tree, index, _) = &*tree_locked;
+    let mut branch: Vec<HeaderInfo> = vec![];
+    let mut current = index.get(tip).copied();
+    while let Some(idx) = current.filter(|idx| *idx != common) {
+        branch.push(tree[idx].clone());
+        current = tree
+            .neighbors_directed(idx, petgraph::Direction::Incoming)
+            .next();
+    }
+    branch.reverse();
+    branch
+}
+
+// The stale branches forking off the chains of the active tips, as the
+// first header of each branch and the height of the last common block.
+// Branches with an active tip, e.g. of a node stuck on a fork, aren't stale.
+pub async fn stale_branches(tree: &Tree, active_tips: &[BlockHash]) -> Vec<(BlockHash, u64)> {
+    let tree_locked = tree.lock().await;
+    let (tree, index, _) = &*tree_locked;
+    let mut active: HashSet<NodeIndex> = HashSet::new();
+    for tip in active_tips {
+        let mut next = index.get(tip).copied();
+        while let Some(idx) = next {
+            if !active.insert(idx) {
+                break;
+            }
+            next = tree
+                .neighbors_directed(idx, petgraph::Direction::Incoming)
+                .next();
+        }
+    }
+    let mut branches: Vec<(BlockHash, u64)> = vec![];
+    for idx in active.iter() {
+        for child in tree.neighbors_directed(*idx, petgraph::Direction::Outgoing) {
+            if !active.contains(&child) {
+                branches.push((tree[child].block_hash(), tree[*idx].height));
+            }
+        }
+    }
+    branches.sort_by_key(|(_, height)| *height);
+    branches
+}
+
+// Removes the branches starting with the headers, i.e. the headers and all
+// headers above them, and returns the hashes of the removed headers. The
+// tree is rebuilt, so the node indexes change.
+pub async fn remove_branches(tree: &Tree, firsts: &[BlockHash]) -> Vec<BlockHash> {
+    let mut tree_locked = tree.lock().await;
+    let mut removed: HashSet<NodeIndex> = HashSet::new();
+    for first in firsts {
+        if let Some(idx) = tree_locked.1.get(first) {
+            let mut dfs = Dfs::new(&tree_locked.0, *idx);
+            while let Some(idx) = dfs.next(&tree_locked.0) {
+                removed.insert(idx);
+            }
+        }
+    }
+    if removed.is_empty() {
+        return vec![];
+    }
+
+    let removed_hashes: Vec<BlockHash> = removed
+        .iter()
+        .map(|idx| tree_locked.0[*idx].block_hash())
+        .collect();
+    let graph = tree_locked.0.filter_map(
+        |idx, header_info| (!removed.contains(&idx)).then(|| header_info.clone()),
+        |_, edge| Some(*edge),
+    );
+    let index: HashMap<BlockHash, NodeIndex> = graph
+        .node_indices()
+        .map(|idx| (graph[idx].block_hash(), idx))
+        .collect();
+    for hash in removed_hashes.iter() {
+        tree_locked.2.remove(hash);
+    }
+    tree_locked.0 = graph;
+    tree_locked.1 = index;
+    removed_hashes
+}
+
+// Collapses the linear runs of headers below the horizon into an edge
+// between the headers around them, counting the collapsed headers. The
+// roots, the tips, the headers with more than one child and the headers
+// strip_tree() keeps around heights with more than one header stay. Returns
+// the number of collapsed headers. The tree is rebuilt, so the node indexes
+// change.
+pub async fn compact(tree: &Tree, horizon: u64) -> usize {
+    let mut tree_locked = tree.lock().await;
+    let graph = &tree_locked.0;
+
+    let mut height_occurences: HashMap<u64, usize> = HashMap::new();
+    for header_info in graph.node_weights() {
+        *height_occurences.entry(header_info.height).or_insert(0) += 1;
+    }
+    let kept_heights: HashSet<u64> = height_occurences
+        .iter()
+        .filter(|(_, count)| **count > 1)
+        .flat_map(|(height, _)| height.saturating_sub(2)..=height + 1)
+        .collect();
+    let collapsible = |idx: NodeIndex| {
+        let height = graph[idx].height;
+        height < horizon
+            && !kept_heights.contains(&height)
+            && graph
+                .neighbors_directed(idx, petgraph::Direction::Incoming)
+                .count()
+                == 1
+            && graph
+                .neighbors_directed(idx, petgraph::Direction::Outgoing)
+                .count()
+                == 1
+    };
+
+    // The runs as the headers below and above them and the number of
+    // headers in between, including the ones collapsed before.
+    let mut collapsed: HashSet<NodeIndex> = HashSet::new();
+    let mut runs: Vec<(BlockHash, BlockHash, u64)> = vec![];
+    for idx in graph.node_indices() {
+        let below = match graph
+            .edges_directed(idx, petgraph::Direction::Incoming)
+            .next()
+        {
+            Some(edge) => edge,
+            None => continue,
+        };
+        if !collapsible(idx) || collapsible(below.source()) {
+            continue;
+        }
+        let mut count = *below.weight();
+        let mut next = idx;
+        while collapsible(next) {
+            collapsed.insert(next);
+            let above = graph
+                .edges_directed(next, petgraph::Direction::Outgoing)
+                .next()
+                .expect("a collapsible header has a child");
+            count += 1 + above.weight();
+            next = above.target();
+        }
+        runs.push((
+            graph[below.source()].block_hash(),
+            graph[next].block_hash(),
+            count,
+        ));
+    }
+    if collapsed.is_empty() {
+        return 0;
+    }
+
+    let collapsed_hashes: Vec<BlockHash> = collapsed
+        .iter()
+        .map(|idx| graph[*idx].block_hash())
+        .collect();
+    let mut graph = graph.filter_map(
+        |idx, header_info| (!collapsed.contains(&idx)).then(|| header_info.clone()),
+        |_, edge| Some(*edge),
+    );
+    let index: HashMap<BlockHash, NodeIndex> = graph
+        .node_indices()
+        .map(|idx| (graph[idx].block_hash(), idx))
+        .collect();
+    for (below, above, count) in runs {
+        graph.add_edge(index[&below], index[&above], count);
+    }
+    for hash in collapsed_hashes.iter() {
+        tree_locked.2.remove(hash);
+    }
+    tree_locked.0 = graph;
+    tree_locked.1 = index;
+    collapsed_hashes.len()
+}
+
+// The cumulative work of the tips in the tree, hex encoded like the
+// chainwork of Bitcoin Core, by tip hash. Tips not in the tree are missing.
+pub async fn tips_chainwork(tree: &Tree, tips: &[ChainTip]) -> HashMap<String, String> {
+    let tree_locked = tree.lock().await;
+    tips.iter()
+        .filter_map(|tip| {
+            let hash = BlockHash::from_str(&tip.hash).ok()?;
+            let work = tree_locked.2.get(&hash)?;
+            Some((tip.hash.clone(), hex::encode(work.to_be_bytes())))
+        })
+        .collect()
+}
+
+// Branches overlapping below their tips get the status of the most
+// advanced tip, e.g. the blocks an invalid branch shares with a valid fork
+// are valid.
+fn status_rank(status: &ChainTipStatus) -> u8 {
+    match status {
+        ChainTipStatus::Active => 0,
+        ChainTipStatus::ValidFork => 1,
+        ChainTipStatus::ValidHeaders => 2,
+        ChainTipStatus::HeadersOnly => 3,
+        ChainTipStatus::Invalid => 4,
+        ChainTipStatus::Unknown => 5,
+    }
+}
+
+// The status of the blocks for each node, by node id. The blocks of the
+// active chain of a node are active and the blocks of its other branches
+// have the status of the branch's tip, down to the block the branch joins
+// the active chain at. Nodes only report the status of the tips, so the
+// blocks of an invalid branch below the first invalid block are reported as
+// invalid, too.
+pub async fn node_statuses(
+    tree: &Tree,
+    node_tips: &[(u32, Vec<(BlockHash, ChainTipStatus)>)],
+    hashes: &[BlockHash],
+) -> HashMap<BlockHash, BTreeMap<u32, ChainTipStatus>> {
+    let tree_locked = tree.lock().await;
+    let (tree, index, _) = &*tree_locked;
+    let wanted: Vec<NodeIndex> = hashes
+        .iter()
+        .filter_map(|h| index.get(h))
+        .copied()
+        .collect();
+    let min_height = match wanted.iter().map(|idx| tree[*idx].height).min() {
+        Some(min_height) => min_height,
+        None => return HashMap::new(),
+    };
+    let parent = |idx: NodeIndex| {
+        tree.neighbors_directed(idx, petgraph::Direction::Incoming)
+            .next()
+            .filter(|parent| tree[*parent].height >= min_height)
+    };
+
+    let mut statuses: HashMap<BlockHash, BTreeMap<u32, ChainTipStatus>> = HashMap::new();
+    for (node_id, tips) in node_tips.iter() {
+        // The active chain down to min_height by height. Collapsed headers
+        // leave gaps.
+        let mut active_chain: HashMap<u64, NodeIndex> = HashMap::new();
+        let active_tip = tips
+            .iter()
+            .find(|(_, status)| *status == ChainTipStatus::Active)
+            .and_then(|(hash, _)| index.get(hash))
+            .copied();
+        let mut current = active_tip.filter(|idx| tree[*idx].height >= min_height);
+        while let Some(idx) = current {
+            active_chain.insert(tree[idx].height, idx);
+            current = parent(idx);
+        }
+
+        let mut branches: HashMap<NodeIndex, ChainTipStatus> = HashMap::new();
+        let mut other_tips: Vec<&(BlockHash, ChainTipStatus)> = tips
+            .iter()
+            .filter(|(_, status)| *status != ChainTipStatus::Active)
+            .collect();
+        other_tips.sort_by_key(|(_, status)| status_rank(status));
+        for (hash, status) in other_tips {
+            let mut current = index.get(hash).copied();
+            while let Some(idx) = current {
+                if tree[idx].height < min_height
+                    || active_chain.get(&tree[idx].height) == Some(&idx)
+                    || branches.contains_key(&idx)
+                {
+                    break;
+                }
+                branches.insert(idx, status.clone());
+                current = parent(idx);
+            }
+        }
+
+        for idx in wanted.iter() {
+            let status = if active_chain.get(&tree[*idx].height) == Some(idx) {
+                Some(ChainTipStatus::Active)
+            } else {
+                branches.get(idx).cloned()
+            };
+            if let Some(status) = status {
+                statuses
+                    .entry(tree[*idx].block_hash())
+                    .or_default()
+                    .insert(*node_id, status);
+            }
+        }
+    }
+    statuses
+}
+
 // get recent forks for rss
 pub async fn recent_forks(tree: &Tree, how_many: usize) -> Vec<Fork> {
     let tree_locked = tree.lock().await;
@@ -196,3 +597,619 @@ pub async fn recent_forks(tree: &Tree, how_many: usize) -> Vec<Fork> {
     forks.sort_by_key(|f| f.common.height);
     forks.iter().rev().take(how_many).cloned().collect()
 }
+
+// The closed forks with the lengths of their branches except the longest.
+// A fork is closed once all its branches are at least closed_depth blocks
+// shorter than the longest one. Forks with their last common block in skip
+// are left out.
+pub async fn closed_forks(
+    tree: &Tree,
+    skip: &HashSet<BlockHash>,
+    closed_depth: u64,
+) -> Vec<(BlockHash, Vec<u64>)> {
+    let tree_locked = tree.lock().await;
+    let tree = &tree_locked.0;
+
+    // The maximum height in the subtree of each header, computed from the
+    // highest headers down.
+    let mut order: Vec<NodeIndex> = tree.node_indices().collect();
+    order.sort_by_key(|idx| Reverse(tree[*idx].height));
+    let mut max_heights: Vec<u64> = vec![0; tree.node_count()];
+    for idx in order.iter() {
+        max_heights[idx.index()] = tree
+            .neighbors_directed(*idx, petgraph::Direction::Outgoing)
+            .map(|child| max_heights[child.index()])
+            .chain(std::iter::once(tree[*idx].height))
+            .max()
+            .unwrap_or_default();
+    }
+
+    let mut closed: Vec<(BlockHash, Vec<u64>)> = vec![];
+    for idx in tree.node_indices() {
+        let children = tree.neighbors_directed(idx, petgraph::Direction::Outgoing);
+        if children.clone().count() < 2 {
+            continue;
+        }
+        let common = &tree[idx];
+        let hash = common.block_hash();
+        if skip.contains(&hash) {
+            continue;
+        }
+        let mut lengths: Vec<u64> = children
+            .map(|child| max_heights[child.index()] - common.height)
+            .collect();
+        lengths.sort_unstable();
+        let longest = lengths.pop().unwrap_or_default();
+        if lengths
+            .iter()
+            .all(|length| length + closed_depth <= longest)
+        {
+            closed.push((hash, lengths));
+        }
+    }
+    closed
+}
+
+// The heights from the last common block of a fork up to the highest block
+// of its branches except the longest. None if the block isn't the last
+// common block of a fork.
+pub async fn fork_heights(tree: &Tree, common: &BlockHash) -> Option<(u64, u64)> {
+    let tree_locked = tree.lock().await;
+    let (tree, index, _) = &*tree_locked;
+    let idx = *index.get(common)?;
+    let mut max_heights: Vec<u64> = tree
+        .neighbors_directed(idx, petgraph::Direction::Outgoing)
+        .map(|child| {
+            let mut max_height = tree[child].height;
+            let mut dfs = Dfs::new(tree, child);
+            while let Some(i) = dfs.next(tree) {
+                max_height = max_height.max(tree[i].height);
+            }
+            max_height
+        })
+        .collect();
+    if max_heights.len() < 2 {
+        return None;
+    }
+    max_heights.sort_unstable();
+    Some((tree[idx].height, max_heights[max_heights.len() - 2]))
+}
+
+// The hashes of the blocks of the branch starting with the block, up to
+// depth blocks above it.
+pub async fn branch_hashes(tree: &Tree, first: &BlockHash, depth: u64) -> Vec<BlockHash> {
+    branch_headers(tree, first, depth)
+        .await
+        .iter()
+        .map(|header_info| header_info.block_hash())
+        .collect()
+}
+
+// The headers of the branch starting with the block, up to depth blocks
+// above it.
+pub async fn branch_headers(tree: &Tree, first: &BlockHash, depth: u64) -> Vec<HeaderInfo> {
+    let tree_locked = tree.lock().await;
+    let (tree, index, _) = &*tree_locked;
+    let first_idx = match index.get(first) {
+        Some(idx) => *idx,
+        None => return vec![],
+    };
+    let max_height = tree[first_idx].height + depth;
+    let mut headers: Vec<HeaderInfo> = vec![];
+    let mut next: Vec<NodeIndex> = vec![first_idx];
+    while let Some(idx) = next.pop() {
+        headers.push(tree[idx].clone());
+        next.extend(
+            tree.neighbors_directed(idx, petgraph::Direction::Outgoing)
+                .filter(|child| tree[*child].height <= max_height),
+        );
+    }
+    headers
+}
+
+// Blocks on branches forking off the chain of the active tip in the last
+// depth blocks. Branches reaching above the active tip aren't stale (yet),
+// the node might be lagging behind.
+pub async fn stale_blocks(tree: &Tree, active_tip: &BlockHash, depth: u64) -> Vec<HeaderInfo> {
+    let tree_locked = tree.lock().await;
+    let (tree, index, _) = &*tree_locked;
+    let tip_idx = match index.get(active_tip) {
+        Some(idx) => *idx,
+        None => return vec![],
+    };
+    let tip_height = tree[tip_idx].height;
+    let min_height = tip_height.saturating_sub(depth);
+
+    let mut active_chain: Vec<NodeIndex> = vec![tip_idx];
+    while let Some(prev) = tree
+        .neighbors_directed(*active_chain.last().unwrap(), petgraph::Direction::Incoming)
+        .next()
+    {
+        if tree[prev].height < min_height {
+            break;
+        }
+        active_chain.push(prev);
+    }
+
+    let mut stale: Vec<HeaderInfo> = vec![];
+    for idx in active_chain.iter().skip(1) {
+        for child in tree.neighbors_directed(*idx, petgraph::Direction::Outgoing) {
+            if active_chain.contains(&child) {
+                continue;
+            }
+            let mut branch: Vec<HeaderInfo> = vec![];
+            let mut dfs = Dfs::new(tree, child);
+            while let Some(branch_idx) = dfs.next(tree) {
+                branch.push(tree[branch_idx].clone());
+            }
+            if branch.iter().all(|h| h.height <= tip_height) {
+                stale.extend(branch);
+            }
+        }
+    }
+    stale.sort_by_key(|h| h.height);
+    stale
+}
+
+// The number of blocks of the chain leading up to the active tip and of the
+// stale blocks next to it with a block time from since on. Collapsed headers
+// are counted by their heights, though the chain is only cut off at kept
+// headers. None if the active tip isn't in the tree.
+pub async fn block_counts(tree: &Tree, active_tip: &BlockHash, since: u32) -> Option<(u64, u64)> {
+    let tree_locked = tree.lock().await;
+    let (tree, index, _) = &*tree_locked;
+    let tip_idx = *index.get(active_tip)?;
+    let tip_height = tree[tip_idx].height;
+
+    let mut active_chain: HashSet<NodeIndex> = HashSet::new();
+    let mut lowest = tip_idx;
+    let mut idx = tip_idx;
+    while tree[idx].header.time >= since {
+        active_chain.insert(idx);
+        lowest = idx;
+        match tree
+            .neighbors_directed(idx, petgraph::Direction::Incoming)
+            .next()
+        {
+            Some(prev) => idx = prev,
+            None => break,
+        }
+    }
+    if active_chain.is_empty() {
+        return Some((0, 0));
+    }
+    let min_height = tree[lowest].height;
+
+    let stale = tree
+        .node_indices()
+        .filter(|idx| !active_chain.contains(idx))
+        .filter(|idx| {
+            let header_info = &tree[*idx];
+            header_info.header.time >= since
+                && header_info.height >= min_height
+                && header_info.height <= tip_height
+        })
+        .count();
+    Some((tip_height - min_height + 1, stale as u64))
+}
+
+// The headers of the chain leading up to the active tip down to min_height,
+// ordered by height.
+pub async fn active_chain(tree: &Tree, active_tip: &BlockHash, min_height: u64) -> Vec<HeaderInfo> {
+    let tree_locked = tree.lock().await;
+    let (tree, index, _) = &*tree_locked;
+    let mut idx = match index.get(active_tip) {
+        Some(idx) => *idx,
+        None => return vec![],
+    };
+    let mut chain: Vec<HeaderInfo> = vec![tree[idx].clone()];
+    while let Some(prev) = tree
+        .neighbors_directed(idx, petgraph::Direction::Incoming)
+        .next()
+    {
+        if tree[prev].height < min_height {
+            break;
+        }
+        chain.push(tree[prev].clone());
+        idx = prev;
+    }
+    chain.reverse();
+    chain
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::child;
+    use bitcoincore_rpc::bitcoin::hashes::Hash;
+    use petgraph::graph::DiGraph;
+    use std::sync::Arc;
+    use tokio::sync::Mutex;
+
+    fn add_header(
+        tree: &mut DiGraph<HeaderInfo, u64>,
+        index: &mut HashMap<BlockHash, NodeIndex>,
+        prev: Option<&HeaderInfo>,
+        nonce: u32,
+    ) -> HeaderInfo {
+        let header_info = child(prev, 0, nonce);
+        let idx = tree.add_node(header_info.clone());
+        index.insert(header_info.block_hash(), idx);
+        if let Some(prev) = prev {
+            tree.add_edge(index[&prev.block_hash()], idx, 0);
+        }
+        header_info
+    }
+
+    #[tokio::test]
+    async fn test_chainwork() {
+        let mut graph = DiGraph::new();
+        let mut index = HashMap::new();
+        let a0 = add_header(&mut graph, &mut index, None, 0);
+        let a1 = add_header(&mut graph, &mut index, Some(&a0), 1);
+        let a2 = add_header(&mut graph, &mut index, Some(&a1), 2);
+        let b1 = add_header(&mut graph, &mut index, Some(&a0), 3);
+        let work = a0.header.work();
+        let tip = |header_info: &HeaderInfo| ChainTip {
+            height: header_info.height,
+            hash: header_info.block_hash().to_string(),
+            branchlen: 0,
+            status: ChainTipStatus::Active,
+        };
+        let hex =
+            |blocks: u64| hex::encode((0..blocks).fold(work - work, |w, _| w + work).to_be_bytes());
+
+        let tree: Tree = Arc::new(Mutex::new((DiGraph::new(), HashMap::new(), HashMap::new())));
+        insert_new_headers_into_tree(&tree, &[a0.clone(), b1.clone()]).await;
+        let chainwork = tips_chainwork(&tree, &[tip(&b1)]).await;
+        assert_eq!(chainwork[&b1.block_hash().to_string()], hex(2));
+        // a child before its parent
+        insert_new_headers_into_tree(&tree, &[a2.clone(), a1.clone()]).await;
+        let chainwork = tips_chainwork(&tree, &[tip(&a2), tip(&b1)]).await;
+        assert_eq!(chainwork[&a2.block_hash().to_string()], hex(3));
+        assert_eq!(chainwork[&b1.block_hash().to_string()], hex(2));
+        // unknown tips are left out
+        let unknown = add_header(&mut graph, &mut index, None, 4);
+        assert!(tips_chainwork(&tree, &[tip(&unknown)]).await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_prune_stale_branches() {
+        let mut tree = DiGraph::new();
+        let mut index = HashMap::new();
+        let a0 = add_header(&mut tree, &mut index, None, 0);
+        let a1 = add_header(&mut tree, &mut index, Some(&a0), 1);
+        let a2 = add_header(&mut tree, &mut index, Some(&a1), 2);
+        let a3 = add_header(&mut tree, &mut index, Some(&a2), 3);
+        // a two-block branch with a fork of its own
+        let b1 = add_header(&mut tree, &mut index, Some(&a0), 4);
+        let b2 = add_header(&mut tree, &mut index, Some(&b1), 5);
+        let c2 = add_header(&mut tree, &mut index, Some(&b1), 6);
+        let d3 = add_header(&mut tree, &mut index, Some(&a2), 7);
+        // a node is stuck on e2
+        let e2 = add_header(&mut tree, &mut index, Some(&a1), 8);
+        let tree = Arc::new(Mutex::new((tree, index, HashMap::new())));
+
+        let active = [a3.block_hash(), e2.block_hash()];
+        assert_eq!(
+            stale_branches(&tree, &active).await,
+            vec![(b1.block_hash(), 0), (d3.block_hash(), 2)]
+        );
+
+        let mut removed = remove_branches(&tree, &[b1.block_hash()]).await;
+        removed.sort();
+        let mut expected = vec![b1.block_hash(), b2.block_hash(), c2.block_hash()];
+        expected.sort();
+        assert_eq!(removed, expected);
+        assert_eq!(
+            stale_branches(&tree, &active).await,
+            vec![(d3.block_hash(), 2)]
+        );
+        // the rebuilt index points to the remaining headers
+        let tree_locked = tree.lock().await;
+        assert_eq!(tree_locked.0.node_count(), 6);
+        for header_info in [&a0, &a1, &a2, &a3, &d3, &e2] {
+            let idx = tree_locked.1[&header_info.block_hash()];
+            assert_eq!(&tree_locked.0[idx], header_info);
+        }
+        assert!(!tree_locked.1.contains_key(&b2.block_hash()));
+    }
+
+    #[tokio::test]
+    async fn test_compact() {
+        let mut graph = DiGraph::new();
+        let mut index = HashMap::new();
+        let mut chain = vec![add_header(&mut graph, &mut index, None, 0)];
+        for nonce in 1..=20 {
+            let prev = chain.last().cloned();
+            chain.push(add_header(&mut graph, &mut index, prev.as_ref(), nonce));
+        }
+        let b11 = add_header(&mut graph, &mut index, Some(&chain[10]), 21);
+        let tree: Tree = Arc::new(Mutex::new((graph, index, HashMap::new())));
+        {
+            let mut tree_locked = tree.lock().await;
+            let root = tree_locked.1[&chain[0].block_hash()];
+            accumulate_chainwork(&mut tree_locked, root);
+        }
+        let tip = |header_info: &HeaderInfo| ChainTip {
+            height: header_info.height,
+            hash: header_info.block_hash().to_string(),
+            branchlen: 0,
+            status: ChainTipStatus::Active,
+        };
+        let chainwork = tips_chainwork(&tree, &[tip(&chain[20])]).await;
+
+        // 1-8 and 13-17 collapse, 9-12 are around the fork at height 11
+        assert_eq!(compact(&tree, 18).await, 13);
+        assert_eq!(compact(&tree, 18).await, 0);
+        {
+            let tree_locked = tree.lock().await;
+            let (graph, index, _) = &*tree_locked;
+            assert_eq!(graph.node_count(), 9);
+            let edge = |below: &HeaderInfo, above: &HeaderInfo| {
+                let edge = graph
+                    .find_edge(index[&below.block_hash()], index[&above.block_hash()])
+                    .unwrap();
+                graph[edge]
+            };
+            assert_eq!(edge(&chain[0], &chain[9]), 8);
+            assert_eq!(edge(&chain[12], &chain[18]), 5);
+            assert_eq!(edge(&chain[10], &b11), 0);
+            assert!(!index.contains_key(&chain[5].block_hash()));
+        }
+        // a collapsed edge grows with the run
+        assert_eq!(compact(&tree, 20).await, 2);
+        {
+            let tree_locked = tree.lock().await;
+            let (graph, index, _) = &*tree_locked;
+            let edge = graph
+                .find_edge(
+                    index[&chain[12].block_hash()],
+                    index[&chain[20].block_hash()],
+                )
+                .unwrap();
+            assert_eq!(graph[edge], 7);
+        }
+
+        // the remaining headers keep their chainwork and new headers build
+        // on it
+        assert_eq!(tips_chainwork(&tree, &[tip(&chain[20])]).await, chainwork);
+        assert_eq!(
+            stale_branches(&tree, &[chain[20].block_hash()]).await,
+            vec![(b11.block_hash(), 10)]
+        );
+        let mut a21 = chain[20].clone();
+        a21.height = 21;
+        a21.header.prev_blockhash = chain[20].block_hash();
+        assert_eq!(
+            insert_new_headers_into_tree(&tree, &[a21.clone()])
+                .await
+                .len(),
+            1
+        );
+        let work = a21.header.work();
+        let expected = HashMap::from([(
+            a21.block_hash().to_string(),
+            hex::encode((0..22).fold(work - work, |w, _| w + work).to_be_bytes()),
+        )]);
+        assert_eq!(tips_chainwork(&tree, &[tip(&a21)]).await, expected);
+    }
+
+    #[tokio::test]
+    async fn test_stale_blocks() {
+        let mut tree = DiGraph::new();
+        let mut index = HashMap::new();
+        let a0 = add_header(&mut tree, &mut index, None, 0);
+        let a1 = add_header(&mut tree, &mut index, Some(&a0), 1);
+        let a2 = add_header(&mut tree, &mut index, Some(&a1), 2);
+        let a3 = add_header(&mut tree, &mut index, Some(&a2), 3);
+        // stale
+        let b2 = add_header(&mut tree, &mut index, Some(&a1), 4);
+        // longer than the active chain
+        let c3 = add_header(&mut tree, &mut index, Some(&a2), 5);
+        add_header(&mut tree, &mut index, Some(&c3), 6);
+        let tree = Arc::new(Mutex::new((tree, index, HashMap::new())));
+
+        let tip = a3.block_hash();
+        assert_eq!(stale_blocks(&tree, &tip, 6).await, vec![b2]);
+        // b2 forks off below the depth
+        assert_eq!(stale_blocks(&tree, &tip, 1).await, vec![]);
+    }
+
+    #[test]
+    fn test_common_ancestor() {
+        let mut tree = DiGraph::new();
+        let mut index = HashMap::new();
+        let a0 = add_header(&mut tree, &mut index, None, 0);
+        let a1 = add_header(&mut tree, &mut index, Some(&a0), 1);
+        let a2 = add_header(&mut tree, &mut index, Some(&a1), 2);
+        let b2 = add_header(&mut tree, &mut index, Some(&a1), 3);
+        let b3 = add_header(&mut tree, &mut index, Some(&b2), 4);
+        // not connected to the other headers
+        let c5 = add_header(&mut tree, &mut index, None, 5);
+        let tree = (tree, index, HashMap::new());
+
+        let common = |a: &HeaderInfo, b: &HeaderInfo| {
+            common_ancestor(&tree, &a.block_hash(), &b.block_hash())
+                .map(|idx| tree.0[idx].block_hash())
+        };
+        assert_eq!(common(&a2, &b3), Some(a1.block_hash()));
+        assert_eq!(common(&b3, &a2), Some(a1.block_hash()));
+        assert_eq!(common(&a0, &b3), Some(a0.block_hash()));
+        assert_eq!(common(&a2, &a2), Some(a2.block_hash()));
+        assert_eq!(common(&a2, &c5), None);
+    }
+
+    #[tokio::test]
+    async fn test_block_counts() {
+        // a block every 600 seconds, with a stale block at height 4 and a
+        // stale branch of b4 -> b5 -> b6 reaching above the active tip a5
+        let mut graph = DiGraph::new();
+        let mut index = HashMap::new();
+        let mut add = |prev: Option<&HeaderInfo>, nonce: u32| {
+            let mut header_info = add_header(&mut graph, &mut index, prev, nonce);
+            let idx = index.remove(&header_info.block_hash()).unwrap();
+            header_info.header.time = header_info.height as u32 * 600;
+            graph[idx] = header_info.clone();
+            index.insert(header_info.block_hash(), idx);
+            header_info
+        };
+        let a0 = add(None, 0);
+        let a1 = add(Some(&a0), 1);
+        let a2 = add(Some(&a1), 2);
+        let a3 = add(Some(&a2), 3);
+        let a4 = add(Some(&a3), 4);
+        let a5 = add(Some(&a4), 5);
+        let b4 = add(Some(&a3), 6);
+        let b5 = add(Some(&b4), 7);
+        add(Some(&b5), 8);
+        let tree: Tree = Arc::new(Mutex::new((graph, index, HashMap::new())));
+        let tip = a5.block_hash();
+
+        assert_eq!(block_counts(&tree, &tip, 0).await, Some((6, 2)));
+        assert_eq!(block_counts(&tree, &tip, 5 * 600).await, Some((1, 1)));
+        assert_eq!(block_counts(&tree, &tip, 6 * 600).await, Some((0, 0)));
+        assert_eq!(block_counts(&tree, &BlockHash::all_zeros(), 0).await, None);
+    }
+
+    #[tokio::test]
+    async fn test_node_statuses() {
+        // a0 -> a1 -> a2 with a branch a1 -> b2 -> b3
+        let mut graph = DiGraph::new();
+        let mut index = HashMap::new();
+        let a0 = add_header(&mut graph, &mut index, None, 0);
+        let a1 = add_header(&mut graph, &mut index, Some(&a0), 1);
+        let a2 = add_header(&mut graph, &mut index, Some(&a1), 2);
+        let b2 = add_header(&mut graph, &mut index, Some(&a1), 3);
+        let b3 = add_header(&mut graph, &mut index, Some(&b2), 4);
+        let tree: Tree = Arc::new(Mutex::new((graph, index, HashMap::new())));
+
+        // node 0 considers b3 invalid, node 1 follows the b branch and node
+        // 2 only knows the headers of b2
+        let node_tips = vec![
+            (
+                0,
+                vec![
+                    (b3.block_hash(), ChainTipStatus::Invalid),
+                    (a2.block_hash(), ChainTipStatus::Active),
+                ],
+            ),
+            (
+                1,
+                vec![
+                    (b3.block_hash(), ChainTipStatus::Active),
+                    (a2.block_hash(), ChainTipStatus::ValidFork),
+                ],
+            ),
+            (
+                2,
+                vec![
+                    (a2.block_hash(), ChainTipStatus::Active),
+                    (b2.block_hash(), ChainTipStatus::HeadersOnly),
+                ],
+            ),
+        ];
+        let hashes: Vec<BlockHash> = [&a1, &a2, &b2, &b3]
+            .iter()
+            .map(|h| h.block_hash())
+            .collect();
+        let statuses = node_statuses(&tree, &node_tips, &hashes).await;
+        assert!(!statuses.contains_key(&a0.block_hash()));
+        assert_eq!(
+            statuses[&a1.block_hash()],
+            BTreeMap::from([
+                (0, ChainTipStatus::Active),
+                (1, ChainTipStatus::Active),
+                (2, ChainTipStatus::Active)
+            ])
+        );
+        assert_eq!(
+            statuses[&a2.block_hash()],
+            BTreeMap::from([
+                (0, ChainTipStatus::Active),
+                (1, ChainTipStatus::ValidFork),
+                (2, ChainTipStatus::Active)
+            ])
+        );
+        assert_eq!(
+            statuses[&b2.block_hash()],
+            BTreeMap::from([
+                (0, ChainTipStatus::Invalid),
+                (1, ChainTipStatus::Active),
+                (2, ChainTipStatus::HeadersOnly)
+            ])
+        );
+        assert_eq!(
+            statuses[&b3.block_hash()],
+            BTreeMap::from([(0, ChainTipStatus::Invalid), (1, ChainTipStatus::Active)])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_branch_above() {
+        let mut graph = DiGraph::new();
+        let mut index = HashMap::new();
+        let a0 = add_header(&mut graph, &mut index, None, 0);
+        let a1 = add_header(&mut graph, &mut index, Some(&a0), 1);
+        let a2 = add_header(&mut graph, &mut index, Some(&a1), 2);
+        let b1 = add_header(&mut graph, &mut index, Some(&a0), 3);
+        let b2 = add_header(&mut graph, &mut index, Some(&b1), 4);
+        let tree: Tree = Arc::new(Mutex::new((graph, index, HashMap::new())));
+
+        assert_eq!(
+            branch_above(&tree, &b2.block_hash(), &a2.block_hash()).await,
+            vec![b1.clone(), b2.clone()]
+        );
+        assert_eq!(
+            branch_above(&tree, &a1.block_hash(), &a2.block_hash()).await,
+            vec![]
+        );
+        assert_eq!(
+            branch_above(&tree, &b2.block_hash(), &BlockHash::all_zeros()).await,
+            vec![]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_closed_forks() {
+        let mut tree = DiGraph::new();
+        let mut index = HashMap::new();
+        let a0 = add_header(&mut tree, &mut index, None, 0);
+        let mut tip = add_header(&mut tree, &mut index, Some(&a0), 1);
+        // a branch of two blocks
+        let b1 = add_header(&mut tree, &mut index, Some(&a0), 2);
+        add_header(&mut tree, &mut index, Some(&b1), 3);
+        for nonce in 4..7 {
+            tip = add_header(&mut tree, &mut index, Some(&tip), nonce);
+        }
+        let tree = Arc::new(Mutex::new((tree, index, HashMap::new())));
+
+        // the longest branch is four blocks long
+        assert_eq!(
+            closed_forks(&tree, &HashSet::new(), 2).await,
+            vec![(a0.block_hash(), vec![2])]
+        );
+        assert!(closed_forks(&tree, &HashSet::new(), 3).await.is_empty());
+        let skip = HashSet::from([a0.block_hash()]);
+        assert!(closed_forks(&tree, &skip, 2).await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_fork_heights() {
+        let mut tree = DiGraph::new();
+        let mut index = HashMap::new();
+        let a0 = add_header(&mut tree, &mut index, None, 0);
+        let mut tip = add_header(&mut tree, &mut index, Some(&a0), 1);
+        let b1 = add_header(&mut tree, &mut index, Some(&a0), 2);
+        add_header(&mut tree, &mut index, Some(&b1), 3);
+        for nonce in 4..7 {
+            tip = add_header(&mut tree, &mut index, Some(&tip), nonce);
+        }
+        let tree = Arc::new(Mutex::new((tree, index, HashMap::new())));
+
+        assert_eq!(fork_heights(&tree, &a0.block_hash()).await, Some((0, 2)));
+        // not a fork
+        assert_eq!(fork_heights(&tree, &b1.block_hash()).await, None);
+        assert_eq!(fork_heights(&tree, &BlockHash::all_zeros()).await, None);
+    }
+}
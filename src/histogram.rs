@@ -0,0 +1,71 @@
+use std::collections::{BTreeMap, HashSet};
+use std::convert::Infallible;
+use std::sync::Arc;
+
+use bitcoincore_rpc::bitcoin::BlockHash;
+use tokio::sync::Mutex;
+use warp::Reply;
+
+use crate::domain::Tree;
+use crate::headertree;
+use crate::rss::response_unknown_network;
+use crate::types::{
+    BranchLengthBucketJson, BranchLengthHistogramJsonResponse, NetworkJson, Versioned,
+};
+
+// A fork counts towards the histogram once all its branches but the longest
+// are this many blocks shorter than the longest one.
+pub const FORK_CLOSED_DEPTH: u64 = 6; // blocks
+
+// The lengths of the branches that lost a fork.
+#[derive(Default)]
+pub struct BranchLengthHistogram {
+    // The last common blocks of the forks counted so far.
+    counted: HashSet<BlockHash>,
+    // Number of branches per branch length.
+    lengths: BTreeMap<u64, u64>,
+}
+
+// The branch length histogram per network id.
+pub type BranchLengthHistograms = Arc<Mutex<BTreeMap<u32, BranchLengthHistogram>>>;
+
+// Adds the forks closed since the last update to the histogram of the
+// network.
+pub async fn update(network_id: u32, tree: &Tree, histograms: &BranchLengthHistograms) {
+    let mut histograms_locked = histograms.lock().await;
+    let histogram = histograms_locked.entry(network_id).or_default();
+    for (hash, lengths) in headertree::closed_forks(tree, &histogram.counted, FORK_CLOSED_DEPTH)
+        .await
+        .into_iter()
+    {
+        histogram.counted.insert(hash);
+        for length in lengths {
+            *histogram.lengths.entry(length).or_default() += 1;
+        }
+    }
+}
+
+pub async fn histogram_response(
+    network_id: u32,
+    histograms: BranchLengthHistograms,
+    network_infos: Vec<NetworkJson>,
+) -> Result<Box<dyn Reply>, Infallible> {
+    let histograms_locked = histograms.lock().await;
+    match histograms_locked.get(&network_id) {
+        Some(histogram) => Ok(Box::new(warp::reply::json(&Versioned::new(
+            BranchLengthHistogramJsonResponse {
+                closed_depth: FORK_CLOSED_DEPTH,
+                closed_forks: histogram.counted.len(),
+                branches: histogram
+                    .lengths
+                    .iter()
+                    .map(|(length, count)| BranchLengthBucketJson {
+                        length: *length,
+                        count: *count,
+                    })
+                    .collect(),
+            },
+        )))),
+        None => Ok(Box::new(response_unknown_network(network_infos))),
+    }
+}
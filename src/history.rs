@@ -0,0 +1,165 @@
+use std::collections::{BTreeMap, HashMap};
+use std::convert::Infallible;
+use std::sync::Arc;
+
+use log::error;
+use tokio::sync::Mutex;
+use warp::http::{Response, StatusCode};
+use warp::Reply;
+
+use crate::config::{ChainParams, HistoryCache, Network};
+use crate::domain::Tree;
+use crate::rss::response_unknown_network;
+use crate::types::{HeaderInfoJson, HistoryJsonResponse, NetworkJson, Versioned};
+
+// The rendered immutable chunks of the header history by network id and
+// chunk number. A chunk is rendered once, when it's first requested.
+pub type HistoryChunks = Arc<Mutex<BTreeMap<(u32, u64), String>>>;
+
+// The first and last height of a chunk.
+pub fn chunk_range(chunk: u64, chunk_size: u64) -> Option<(u64, u64)> {
+    let from_height = chunk.checked_mul(chunk_size)?;
+    Some((from_height, from_height.checked_add(chunk_size - 1)?))
+}
+
+// A chunk is immutable once its last height is buried under at least
+// confirmations blocks.
+pub fn is_immutable(to_height: u64, max_height: u64, confirmations: u64) -> bool {
+    to_height
+        .checked_add(confirmations)
+        .is_some_and(|height| height <= max_height)
+}
+
+// The headers of all branches in the height range. The ids are the indexes
+// in the header tree, so they are stable across chunks.
+async fn chunk(
+    tree: &Tree,
+    from_height: u64,
+    to_height: u64,
+    params: &ChainParams,
+) -> (Vec<HeaderInfoJson>, u64) {
+    let tree_locked = tree.lock().await;
+    let graph = &tree_locked.0;
+    let max_height = graph
+        .node_weights()
+        .map(|h| h.height)
+        .max()
+        .unwrap_or_default();
+    let mut headers: Vec<HeaderInfoJson> = graph
+        .node_indices()
+        .filter(|idx| (from_height..=to_height).contains(&graph[*idx].height))
+        .map(|idx| {
+            let prev_id = graph
+                .neighbors_directed(idx, petgraph::Direction::Incoming)
+                .next()
+                .map(|prev| prev.index())
+                // indicates the start in JavaScript
+                .unwrap_or(usize::MAX);
+            HeaderInfoJson::new(&graph[idx], idx.index(), prev_id, params)
+        })
+        .collect();
+    headers.sort_by_key(|h| (h.height, h.id));
+    (headers, max_height)
+}
+
+fn json_response(body: String, cache_control: String) -> Box<dyn Reply> {
+    match Response::builder()
+        .header("content-type", "application/json")
+        .header("cache-control", cache_control)
+        .body(body)
+    {
+        Ok(response) => Box::new(response),
+        Err(e) => {
+            error!("Could not build the history response: {}", e);
+            Box::new(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+fn immutable_response(body: String, history_cache: &HistoryCache) -> Box<dyn Reply> {
+    json_response(
+        body,
+        format!(
+            "public, max-age={}, immutable",
+            history_cache.max_age.as_secs()
+        ),
+    )
+}
+
+pub async fn response(
+    network_id: u32,
+    chunk_file: String,
+    history_chunks: HistoryChunks,
+    networks: Vec<Network>,
+    trees: HashMap<u32, Tree>,
+    network_infos: Vec<NetworkJson>,
+) -> Result<Box<dyn Reply>, Infallible> {
+    let network = match networks.iter().find(|n| n.id == network_id) {
+        Some(network) => network,
+        None => return Ok(Box::new(response_unknown_network(network_infos))),
+    };
+    // The chunk is requested as <chunk number>.json.
+    let chunk_number: u64 = match chunk_file
+        .strip_suffix(".json")
+        .and_then(|n| n.parse().ok())
+    {
+        Some(chunk_number) => chunk_number,
+        None => return Ok(Box::new(StatusCode::NOT_FOUND)),
+    };
+    let (history_cache, tree) = match (&network.history_cache, trees.get(&network.header_store)) {
+        (Some(history_cache), Some(tree)) => (history_cache, tree),
+        _ => return Ok(Box::new(StatusCode::NOT_FOUND)),
+    };
+    if let Some(body) = history_chunks.lock().await.get(&(network_id, chunk_number)) {
+        return Ok(immutable_response(body.clone(), history_cache));
+    }
+    let (from_height, to_height) = match chunk_range(chunk_number, history_cache.chunk_size) {
+        Some(range) => range,
+        None => return Ok(Box::new(StatusCode::NOT_FOUND)),
+    };
+
+    let (header_infos, max_height) =
+        chunk(tree, from_height, to_height, &network.chain_params).await;
+    let immutable = is_immutable(to_height, max_height, history_cache.confirmations);
+    let body = match serde_json::to_string(&Versioned::new(HistoryJsonResponse {
+        chunk: chunk_number,
+        from_height,
+        to_height,
+        immutable,
+        header_infos,
+    })) {
+        Ok(body) => body,
+        Err(e) => {
+            error!(
+                "Could not serialize the history chunk {}: {}",
+                chunk_number, e
+            );
+            return Ok(Box::new(StatusCode::INTERNAL_SERVER_ERROR));
+        }
+    };
+    if immutable {
+        history_chunks
+            .lock()
+            .await
+            .insert((network_id, chunk_number), body.clone());
+        Ok(immutable_response(body, history_cache))
+    } else {
+        Ok(json_response(body, String::from("no-cache")))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_chunk_range_and_immutability() {
+        assert_eq!(chunk_range(0, 1000), Some((0, 999)));
+        assert_eq!(chunk_range(3, 1000), Some((3000, 3999)));
+        assert_eq!(chunk_range(u64::MAX, 1000), None);
+
+        assert!(is_immutable(999, 1099, 100));
+        assert!(!is_immutable(999, 1098, 100));
+        assert!(!is_immutable(u64::MAX, u64::MAX, 1));
+    }
+}
@@ -0,0 +1,560 @@
+use std::io::{self, Read, Write};
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use bitcoincore_rpc::{jsonrpc, Auth};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+#[cfg(feature = "https")]
+use std::convert::TryFrom;
+
+use crate::error::ConfigError;
+use crate::socks;
+
+// Prefix of a rpc_host that is the path of a UNIX domain socket.
+pub const UNIX_SOCKET_PREFIX: &str = "unix://";
+
+// A PEM encoded client certificate chain and its private key to
+// authenticate with at a HTTPS server.
+#[cfg_attr(not(feature = "https"), allow(dead_code))]
+pub struct ClientAuth<'a> {
+    pub cert_file: &'a Path,
+    pub key_file: &'a Path,
+}
+
+// A connection to an endpoint, over TLS for HTTPS endpoints.
+pub trait Stream: Read + Write + Send {}
+
+impl<T: Read + Write + Send> Stream for T {}
+
+pub struct HttpResponse {
+    pub status_code: u16,
+    pub reason_phrase: String,
+    pub body: Vec<u8>,
+}
+
+// The RPC and REST endpoint of a Bitcoin Core node that can't be queried
+// with minreq and the stock RPC client: nodes behind a TLS-terminating proxy
+// like nginx or btc-rpc-proxy, and nodes only reachable through a SOCKS5
+// proxy like Tor, and nodes only reachable via a UNIX domain socket, e.g.
+// forwarded with socat. minreq only trusts its bundled root certificates, so TLS
+// uses a rustls client configured per node. This allows trusting the CA of
+// a self-signed proxy certificate or, for testing, skipping the certificate
+// verification.
+pub struct HttpEndpoint {
+    host: String,
+    port: u16,
+    proxy: Option<SocketAddr>,
+    socket: Option<PathBuf>,
+    #[cfg(feature = "https")]
+    tls: Option<Arc<rustls::ClientConfig>>,
+}
+
+impl HttpEndpoint {
+    pub fn new(host: String, port: u16, proxy: Option<SocketAddr>) -> Self {
+        HttpEndpoint {
+            host,
+            port,
+            proxy,
+            socket: None,
+            #[cfg(feature = "https")]
+            tls: None,
+        }
+    }
+
+    // An endpoint reached via the UNIX domain socket instead of TCP.
+    pub fn unix(socket: PathBuf) -> Self {
+        HttpEndpoint {
+            socket: Some(socket),
+            ..HttpEndpoint::new("localhost".to_string(), 0, None)
+        }
+    }
+
+    #[cfg(feature = "https")]
+    pub fn with_https(
+        mut self,
+        ca_file: Option<&Path>,
+        insecure: bool,
+        client_auth: Option<ClientAuth>,
+    ) -> Result<Self, ConfigError> {
+        self.tls = Some(Arc::new(tls::client_config(
+            ca_file,
+            insecure,
+            client_auth,
+        )?));
+        Ok(self)
+    }
+
+    #[cfg(not(feature = "https"))]
+    pub fn with_https(
+        self,
+        _ca_file: Option<&std::path::Path>,
+        _insecure: bool,
+        _client_auth: Option<ClientAuth>,
+    ) -> Result<Self, ConfigError> {
+        Err(ConfigError::RpcHttpsNotSupported)
+    }
+
+    #[cfg(feature = "https")]
+    pub fn is_https(&self) -> bool {
+        self.tls.is_some()
+    }
+
+    #[cfg(not(feature = "https"))]
+    pub fn is_https(&self) -> bool {
+        false
+    }
+
+    // Makes a HTTP/1.0 request, so the response is neither chunked nor kept
+    // alive.
+    pub fn request(
+        &self,
+        method: &str,
+        path: &str,
+        headers: &[(&str, &str)],
+        body: &[u8],
+        timeout: Duration,
+    ) -> Result<HttpResponse, io::Error> {
+        let host = match self.socket {
+            Some(_) => self.host.clone(),
+            None => format!("{}:{}", self.host, self.port),
+        };
+        let mut request = format!(
+            "{} {} HTTP/1.0\r\nHost: {}\r\nContent-Length: {}\r\n",
+            method,
+            path,
+            host,
+            body.len()
+        );
+        for (name, value) in headers.iter() {
+            request.push_str(&format!("{}: {}\r\n", name, value));
+        }
+        request.push_str("\r\n");
+        let mut request = request.into_bytes();
+        request.extend_from_slice(body);
+
+        if let Some(ref socket) = self.socket {
+            return parse_response(&exchange_unix(socket, &request, timeout)?);
+        }
+        parse_response(&exchange(self.connect(timeout)?, &request)?)
+    }
+
+    // Opens a TCP connection to the endpoint, wrapped in TLS for HTTPS
+    // endpoints. Reads and writes time out after the timeout.
+    pub fn connect(&self, timeout: Duration) -> Result<Box<dyn Stream>, io::Error> {
+        let stream = socks::connect(&self.host, self.port, self.proxy, timeout)?;
+        self.wrap(stream)
+    }
+
+    #[cfg(feature = "https")]
+    fn wrap(&self, stream: std::net::TcpStream) -> Result<Box<dyn Stream>, io::Error> {
+        match self.tls {
+            Some(ref tls) => {
+                let server_name = rustls::ServerName::try_from(self.host.as_str())
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+                let connection = rustls::ClientConnection::new(tls.clone(), server_name)
+                    .map_err(io::Error::other)?;
+                Ok(Box::new(rustls::StreamOwned::new(connection, stream)))
+            }
+            None => Ok(Box::new(stream)),
+        }
+    }
+
+    #[cfg(not(feature = "https"))]
+    fn wrap(&self, stream: std::net::TcpStream) -> Result<Box<dyn Stream>, io::Error> {
+        Ok(Box::new(stream))
+    }
+
+    pub fn url(&self) -> String {
+        if let Some(ref socket) = self.socket {
+            return format!("{}{}", UNIX_SOCKET_PREFIX, socket.display());
+        }
+        let scheme = if self.is_https() { "https" } else { "http" };
+        match self.proxy {
+            Some(proxy) => format!("{}://{}:{} via {}", scheme, self.host, self.port, proxy),
+            None => format!("{}://{}:{}", scheme, self.host, self.port),
+        }
+    }
+
+    // If the endpoint is reached through a SOCKS5 proxy, which resolves
+    // the host itself.
+    pub fn proxied(&self) -> bool {
+        self.proxy.is_some()
+    }
+
+    // host:port of the endpoint, e.g. for the Host header of a websocket
+    // handshake.
+    pub fn authority(&self) -> String {
+        format!("{}:{}", self.host, self.port)
+    }
+}
+
+#[cfg(unix)]
+fn exchange_unix(socket: &Path, request: &[u8], timeout: Duration) -> Result<Vec<u8>, io::Error> {
+    let stream = std::os::unix::net::UnixStream::connect(socket)?;
+    stream.set_read_timeout(Some(timeout))?;
+    stream.set_write_timeout(Some(timeout))?;
+    exchange(stream, request)
+}
+
+#[cfg(not(unix))]
+fn exchange_unix(
+    _socket: &Path,
+    _request: &[u8],
+    _timeout: Duration,
+) -> Result<Vec<u8>, io::Error> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "UNIX domain sockets are only supported on UNIX systems",
+    ))
+}
+
+// The length of the head and the Content-Length of a response, once the
+// head is complete.
+fn head_length(raw: &[u8]) -> Option<(usize, Option<usize>)> {
+    let end = raw.windows(4).position(|w| w == b"\r\n\r\n")? + 4;
+    let content_length = String::from_utf8_lossy(&raw[..end])
+        .lines()
+        .filter_map(|line| line.split_once(':'))
+        .find(|(name, _)| name.trim().eq_ignore_ascii_case("content-length"))
+        .and_then(|(_, value)| value.trim().parse().ok());
+    Some((end, content_length))
+}
+
+// Writes the request and reads the response until the Content-Length is
+// reached or the server closes the connection. Proxies often close TLS
+// connections without a close_notify, which isn't an error for a HTTP/1.0
+// response.
+fn exchange(mut stream: impl Read + Write, request: &[u8]) -> Result<Vec<u8>, io::Error> {
+    stream.write_all(request)?;
+    stream.flush()?;
+    let mut raw = vec![];
+    let mut buffer = [0u8; 8192];
+    loop {
+        match stream.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(n) => raw.extend_from_slice(&buffer[..n]),
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => break,
+            Err(e) => return Err(e),
+        }
+        if let Some((head, Some(content_length))) = head_length(&raw) {
+            if raw.len() >= head + content_length {
+                break;
+            }
+        }
+    }
+    Ok(raw)
+}
+
+fn parse_response(raw: &[u8]) -> Result<HttpResponse, io::Error> {
+    let invalid = |reason: &str| io::Error::new(io::ErrorKind::InvalidData, reason.to_string());
+    let (head, content_length) =
+        head_length(raw).ok_or_else(|| invalid("incomplete HTTP response head"))?;
+    let status_line = String::from_utf8_lossy(&raw[..head])
+        .lines()
+        .next()
+        .unwrap_or_default()
+        .to_string();
+    let mut parts = status_line.splitn(3, ' ');
+    let status_code: u16 = parts
+        .nth(1)
+        .and_then(|code| code.parse().ok())
+        .ok_or_else(|| invalid("invalid HTTP status line"))?;
+    let mut body = raw[head..].to_vec();
+    if let Some(content_length) = content_length {
+        if body.len() < content_length {
+            return Err(invalid("incomplete HTTP response body"));
+        }
+        body.truncate(content_length);
+    }
+    Ok(HttpResponse {
+        status_code,
+        reason_phrase: parts.next().unwrap_or_default().to_string(),
+        body,
+    })
+}
+
+// A JSON-RPC transport for the bitcoincore-rpc client over an HTTP
+// endpoint.
+pub struct HttpTransport {
+    endpoint: Arc<HttpEndpoint>,
+    authorization: Option<String>,
+    timeout: Duration,
+}
+
+impl HttpTransport {
+    pub fn new(
+        endpoint: Arc<HttpEndpoint>,
+        auth: Auth,
+        timeout: Duration,
+    ) -> Result<Self, bitcoincore_rpc::Error> {
+        let authorization = match auth.get_user_pass()? {
+            (Some(user), password) => Some(format!(
+                "Basic {}",
+                base64::encode(format!("{}:{}", user, password.unwrap_or_default()))
+            )),
+            _ => None,
+        };
+        Ok(HttpTransport {
+            endpoint,
+            authorization,
+            timeout,
+        })
+    }
+
+    fn post<R: DeserializeOwned>(&self, body: &impl Serialize) -> Result<R, jsonrpc::Error> {
+        let body = jsonrpc::serde_json::to_vec(body)?;
+        let mut headers = vec![("Content-Type", "application/json")];
+        if let Some(ref authorization) = self.authorization {
+            headers.push(("Authorization", authorization));
+        }
+        let res = self
+            .endpoint
+            .request("POST", "/", &headers, &body, self.timeout)
+            .map_err(|e| jsonrpc::Error::Transport(Box::new(e)))?;
+        // Bitcoin Core responds to failed calls with an error status and the
+        // JSON-RPC error in the body.
+        match jsonrpc::serde_json::from_slice(&res.body) {
+            Ok(response) => Ok(response),
+            Err(_) if res.status_code != 200 => Err(jsonrpc::Error::Transport(Box::new(
+                io::Error::other(format!(
+                    "HTTP request failed: {} {}",
+                    res.status_code, res.reason_phrase
+                )),
+            ))),
+            Err(e) => Err(jsonrpc::Error::Json(e)),
+        }
+    }
+}
+
+impl jsonrpc::Transport for HttpTransport {
+    fn send_request(&self, request: jsonrpc::Request) -> Result<jsonrpc::Response, jsonrpc::Error> {
+        self.post(&request)
+    }
+
+    fn send_batch(
+        &self,
+        requests: &[jsonrpc::Request],
+    ) -> Result<Vec<jsonrpc::Response>, jsonrpc::Error> {
+        self.post(&requests)
+    }
+
+    fn fmt_target(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}/", self.endpoint.url())
+    }
+}
+
+#[cfg(feature = "https")]
+mod tls {
+    use std::fs::File;
+    use std::io::BufReader;
+    use std::path::Path;
+    use std::sync::Arc;
+    use std::time::SystemTime;
+
+    use rustls::client::{ServerCertVerified, ServerCertVerifier, WebPkiVerifier};
+    use rustls::{
+        Certificate, ClientConfig, OwnedTrustAnchor, PrivateKey, RootCertStore, ServerName,
+    };
+
+    use super::ClientAuth;
+    use crate::error::ConfigError;
+
+    // Accepts any server certificate. Only used with rpc_https_insecure.
+    struct NoVerification;
+
+    impl ServerCertVerifier for NoVerification {
+        fn verify_server_cert(
+            &self,
+            _end_entity: &Certificate,
+            _intermediates: &[Certificate],
+            _server_name: &ServerName,
+            _scts: &mut dyn Iterator<Item = &[u8]>,
+            _ocsp_response: &[u8],
+            _now: SystemTime,
+        ) -> Result<ServerCertVerified, rustls::Error> {
+            Ok(ServerCertVerified::assertion())
+        }
+    }
+
+    // Accepts the certificates of the CA file as server certificate and
+    // verifies other server certificates against the trusted roots.
+    // Self-signed certificates like btcd's generated rpc.cert are marked as
+    // CA, which the WebPKI verification rejects for server certificates.
+    struct PinnedOrWebPki {
+        pinned: Vec<Certificate>,
+        webpki: WebPkiVerifier,
+    }
+
+    impl ServerCertVerifier for PinnedOrWebPki {
+        fn verify_server_cert(
+            &self,
+            end_entity: &Certificate,
+            intermediates: &[Certificate],
+            server_name: &ServerName,
+            scts: &mut dyn Iterator<Item = &[u8]>,
+            ocsp_response: &[u8],
+            now: SystemTime,
+        ) -> Result<ServerCertVerified, rustls::Error> {
+            if self.pinned.contains(end_entity) {
+                return Ok(ServerCertVerified::assertion());
+            }
+            self.webpki.verify_server_cert(
+                end_entity,
+                intermediates,
+                server_name,
+                scts,
+                ocsp_response,
+                now,
+            )
+        }
+    }
+
+    fn read_pem(file: &Path) -> Option<Vec<rustls_pemfile::Item>> {
+        let file = File::open(file).ok()?;
+        rustls_pemfile::read_all(&mut BufReader::new(file)).ok()
+    }
+
+    // The certificate chain and the first private key of the PEM files.
+    fn client_cert(client_auth: &ClientAuth) -> Option<(Vec<Certificate>, PrivateKey)> {
+        let certs: Vec<Certificate> = read_pem(client_auth.cert_file)?
+            .into_iter()
+            .filter_map(|item| match item {
+                rustls_pemfile::Item::X509Certificate(cert) => Some(Certificate(cert)),
+                _ => None,
+            })
+            .collect();
+        let key = read_pem(client_auth.key_file)?
+            .into_iter()
+            .find_map(|item| match item {
+                rustls_pemfile::Item::RSAKey(key)
+                | rustls_pemfile::Item::PKCS8Key(key)
+                | rustls_pemfile::Item::ECKey(key) => Some(PrivateKey(key)),
+                _ => None,
+            })?;
+        if certs.is_empty() {
+            return None;
+        }
+        Some((certs, key))
+    }
+
+    // Trusts the certificates in the PEM encoded CA file instead of the
+    // bundled root certificates, if set.
+    pub fn client_config(
+        ca_file: Option<&Path>,
+        insecure: bool,
+        client_auth: Option<ClientAuth>,
+    ) -> Result<ClientConfig, ConfigError> {
+        let mut roots = RootCertStore::empty();
+        let mut pinned = vec![];
+        match ca_file {
+            Some(ca_file) => {
+                let file = File::open(ca_file).map_err(|_| ConfigError::InvalidRpcHttpsCaFile)?;
+                let certs = rustls_pemfile::certs(&mut BufReader::new(file))
+                    .map_err(|_| ConfigError::InvalidRpcHttpsCaFile)?;
+                if certs.is_empty() {
+                    return Err(ConfigError::InvalidRpcHttpsCaFile);
+                }
+                for cert in certs {
+                    let cert = Certificate(cert);
+                    roots
+                        .add(&cert)
+                        .map_err(|_| ConfigError::InvalidRpcHttpsCaFile)?;
+                    pinned.push(cert);
+                }
+            }
+            None => roots.add_trust_anchors(webpki_roots::TLS_SERVER_ROOTS.iter().map(|ta| {
+                OwnedTrustAnchor::from_subject_spki_name_constraints(
+                    ta.subject,
+                    ta.spki,
+                    ta.name_constraints,
+                )
+            })),
+        }
+        let verifier: Arc<dyn ServerCertVerifier> = match insecure {
+            true => Arc::new(NoVerification),
+            false => Arc::new(PinnedOrWebPki {
+                pinned,
+                webpki: WebPkiVerifier::new(roots, None),
+            }),
+        };
+        let builder = ClientConfig::builder()
+            .with_safe_defaults()
+            .with_custom_certificate_verifier(verifier);
+        match client_auth {
+            Some(client_auth) => {
+                let (certs, key) =
+                    client_cert(&client_auth).ok_or(ConfigError::InvalidRpcHttpsClientCert)?;
+                builder
+                    .with_client_auth_cert(certs, key)
+                    .map_err(|_| ConfigError::InvalidRpcHttpsClientCert)
+            }
+            None => Ok(builder.with_no_client_auth()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_response() {
+        let raw = b"HTTP/1.1 200 OK\r\nContent-Type: application/json\r\ncontent-length: 4\r\n\r\n{}\r\n\r\n";
+        let res = parse_response(raw).unwrap();
+        assert_eq!(res.status_code, 200);
+        assert_eq!(res.reason_phrase, "OK");
+        assert_eq!(res.body, b"{}\r\n");
+
+        // without a Content-Length, the body ends with the connection
+        let res = parse_response(b"HTTP/1.0 404 Not Found\r\n\r\nnot found").unwrap();
+        assert_eq!(res.status_code, 404);
+        assert_eq!(res.body, b"not found");
+
+        assert!(parse_response(b"HTTP/1.0 200 OK\r\nContent-Length: 10\r\n\r\n{}").is_err());
+        assert!(parse_response(b"HTTP/1.0 200 OK\r\n").is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_unix_socket() {
+        use std::os::unix::net::UnixListener;
+
+        let socket =
+            std::env::temp_dir().join(format!("fork-observer-{}.sock", std::process::id()));
+        let _ = std::fs::remove_file(&socket);
+        let listener = UnixListener::bind(&socket).unwrap();
+        let server = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = vec![];
+            let mut byte = [0u8; 1];
+            while !request.ends_with(b"\r\n\r\n") {
+                stream.read_exact(&mut byte).unwrap();
+                request.push(byte[0]);
+            }
+            stream.write_all(b"HTTP/1.0 200 OK\r\n\r\n[]").unwrap();
+            String::from_utf8(request).unwrap()
+        });
+
+        let endpoint = HttpEndpoint::unix(socket.clone());
+        assert_eq!(endpoint.url(), format!("unix://{}", socket.display()));
+        let res = endpoint
+            .request(
+                "GET",
+                "/rest/chaininfo.json",
+                &[],
+                &[],
+                Duration::from_secs(5),
+            )
+            .unwrap();
+        assert_eq!((res.status_code, res.body), (200, b"[]".to_vec()));
+        assert!(server
+            .join()
+            .unwrap()
+            .starts_with("GET /rest/chaininfo.json HTTP/1.0\r\nHost: localhost\r\n"));
+        std::fs::remove_file(&socket).unwrap();
+    }
+}
@@ -0,0 +1,235 @@
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use bitcoincore_rpc::bitcoin::consensus::encode::serialize_hex;
+use log::{error, info};
+use tokio::sync::Mutex;
+use tokio::time::Duration;
+
+use crate::config::Network;
+use crate::db;
+use crate::domain::{EventKind, EventSeverity, HeaderInfo, Incident, IncidentRecording};
+use crate::error::{DbError, ExportError};
+use crate::events::EventLog;
+use crate::export::{self, ExportFile};
+use crate::node::Node;
+use crate::types::{now_timestamp, Db, IncidentRecordingJson};
+
+// The nodes of a network in incident mode are queried this often instead of
+// every query_interval.
+pub const INCIDENT_QUERY_INTERVAL: Duration = Duration::from_secs(2);
+
+const FILENAME_RECORDINGS: &str = "recordings.json";
+
+// The ongoing incident per network id.
+pub type ActiveIncidents = Arc<Mutex<BTreeMap<u32, Incident>>>;
+
+// Loads the incidents that were ongoing when the observer was stopped.
+pub async fn load_active(db: Db) -> Result<ActiveIncidents, DbError> {
+    let incidents = db::load_incidents(db).await?;
+    Ok(Arc::new(Mutex::new(
+        incidents
+            .into_iter()
+            .filter(|incident| incident.ended.is_none())
+            .map(|incident| (incident.network_id, incident))
+            .collect(),
+    )))
+}
+
+// The id of the ongoing incident of the network, if any.
+pub async fn active(incidents: &ActiveIncidents, network_id: u32) -> Option<u64> {
+    incidents.lock().await.get(&network_id).map(|i| i.id)
+}
+
+// Switches the network into incident mode. Returns None if the network is
+// already in incident mode.
+pub async fn start(
+    network: &Network,
+    reason: String,
+    db: Db,
+    incidents: &ActiveIncidents,
+    event_log: &EventLog,
+) -> Result<Option<Incident>, DbError> {
+    let incident = {
+        let mut incidents_locked = incidents.lock().await;
+        if incidents_locked.contains_key(&network.id) {
+            return Ok(None);
+        }
+        let now = now_timestamp();
+        let id = db::start_incident(db, network.id, &reason, now).await?;
+        let incident = Incident {
+            id,
+            network_id: network.id,
+            reason,
+            started: now,
+            ended: None,
+        };
+        incidents_locked.insert(network.id, incident.clone());
+        incident
+    };
+    event_log
+        .emit(
+            network.id,
+            EventKind::IncidentStarted,
+            EventSeverity::Warning,
+            format!("Incident mode on {}", network.name),
+            format!(
+                "Network {} was switched into incident mode: {}. The nodes are queried every {} seconds and their responses are recorded.",
+                network.name,
+                incident.reason,
+                INCIDENT_QUERY_INTERVAL.as_secs()
+            ),
+        )
+        .await;
+    Ok(Some(incident))
+}
+
+// Switches the network out of incident mode and attaches the bundle with
+// the data collected during the incident. Returns None if the network isn't
+// in incident mode.
+pub async fn stop(
+    network: &Network,
+    db: Db,
+    incidents: &ActiveIncidents,
+    event_log: &EventLog,
+) -> Result<Option<Incident>, ExportError> {
+    let mut incidents_locked = incidents.lock().await;
+    let mut incident = match incidents_locked.get(&network.id) {
+        Some(incident) => incident.clone(),
+        None => return Ok(None),
+    };
+    let now = now_timestamp();
+    let recordings = db::load_incident_recordings(db.clone(), incident.id).await?;
+    let recordings_json: Vec<IncidentRecordingJson> =
+        recordings.iter().map(IncidentRecordingJson::from).collect();
+    let files = export::create(
+        db.clone(),
+        network,
+        incident.started,
+        now,
+        vec![ExportFile {
+            name: FILENAME_RECORDINGS,
+            description: "The raw responses of the nodes recorded during the incident, e.g. getchaintips results and the headers and coinbase transactions of new blocks.",
+            content: serde_json::to_vec_pretty(&recordings_json)?,
+        }],
+    )
+    .await?;
+    let name = format!(
+        "{}-incident-{}",
+        export::bundle_name(network, incident.started, now),
+        incident.id
+    );
+    db::end_incident(db, incident.id, now, &export::tar(&name, &files, now)?).await?;
+    incidents_locked.remove(&network.id);
+    drop(incidents_locked);
+    incident.ended = Some(now);
+    info!(
+        "Attached a bundle with {} recordings to incident {} of network '{}'",
+        recordings.len(),
+        incident.id,
+        network.name
+    );
+
+    event_log
+        .emit(
+            network.id,
+            EventKind::IncidentEnded,
+            EventSeverity::Info,
+            format!("Incident mode on {} ended", network.name),
+            format!(
+                "Network {} was switched out of incident mode after {} minutes. The collected data is attached to incident {}.",
+                network.name,
+                (now - incident.started) / 60,
+                incident.id
+            ),
+        )
+        .await;
+    Ok(Some(incident))
+}
+
+// The header and the coinbase transaction of a block as recorded during
+// incidents. The coinbase is left out if the node doesn't serve it.
+pub async fn block_recording(node: &dyn Node, header_info: &HeaderInfo) -> String {
+    let hash = header_info.block_hash();
+    let coinbase = match node.coinbase(&hash).await {
+        Ok(coinbase) => serde_json::Value::String(serialize_hex(&coinbase)),
+        Err(_) => serde_json::Value::Null,
+    };
+    serde_json::json!({
+        "hash": hash.to_string(),
+        "height": header_info.height,
+        "header": header_info.header_hex(),
+        "coinbase": coinbase,
+    })
+    .to_string()
+}
+
+// Records raw data from a node if the network is in incident mode.
+pub async fn record(
+    incidents: &ActiveIncidents,
+    db: Db,
+    network_id: u32,
+    node_id: u32,
+    kind: &str,
+    data: String,
+) {
+    let id = match active(incidents, network_id).await {
+        Some(id) => id,
+        None => return,
+    };
+    let recording = IncidentRecording {
+        timestamp: now_timestamp(),
+        node_id,
+        kind: kind.to_string(),
+        data,
+    };
+    if let Err(e) = db::write_incident_recording(db, id, &recording).await {
+        error!(
+            "Could not write a {} recording of node {} for incident {}: {}",
+            kind, node_id, id, e
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rusqlite::Connection;
+
+    #[tokio::test]
+    async fn test_record() {
+        let db: Db = Arc::new(Mutex::new(Connection::open_in_memory().unwrap()));
+        db::setup_db(db.clone()).await.unwrap();
+        let id = db::start_incident(db.clone(), 1, "test", 100)
+            .await
+            .unwrap();
+        let incidents = load_active(db.clone()).await.unwrap();
+        assert_eq!(active(&incidents, 1).await, Some(id));
+        assert_eq!(active(&incidents, 2).await, None);
+
+        record(&incidents, db.clone(), 1, 0, "chaintips", "[]".to_string()).await;
+        // networks not in incident mode aren't recorded
+        record(&incidents, db.clone(), 2, 0, "chaintips", "[]".to_string()).await;
+        let recordings = db::load_incident_recordings(db.clone(), id).await.unwrap();
+        assert_eq!(recordings.len(), 1);
+        assert_eq!(recordings[0].kind, "chaintips");
+
+        db::end_incident(db.clone(), id, 200, b"bundle")
+            .await
+            .unwrap();
+        assert!(load_active(db.clone())
+            .await
+            .unwrap()
+            .lock()
+            .await
+            .is_empty());
+        assert!(db::load_incident_recordings(db.clone(), id)
+            .await
+            .unwrap()
+            .is_empty());
+        assert_eq!(
+            db::load_incident_bundle(db, id).await.unwrap(),
+            Some(b"bundle".to_vec())
+        );
+    }
+}
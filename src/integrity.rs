@@ -0,0 +1,101 @@
+use bitcoincore_rpc::bitcoin::block::Header;
+use bitcoincore_rpc::bitcoin::{Block, Transaction};
+use log::error;
+
+use crate::config::Network;
+use crate::domain::{EventKind, EventSeverity};
+use crate::error::FetchError;
+use crate::events::EventLog;
+use crate::node::Node;
+
+// Checks that a block fetched for enrichment is the block the header in the
+// tree implies: the same header, and transactions committing to its merkle
+// root.
+pub fn check_block(header: &Header, block: &Block) -> Result<(), String> {
+    if block.header != *header {
+        return Err(format!(
+            "the node returned block {} instead of block {}",
+            block.block_hash(),
+            header.block_hash()
+        ));
+    }
+    if block.txdata.is_empty() || !block.check_merkle_root() {
+        return Err(format!(
+            "the merkle root of the {} transactions of block {} doesn't match the merkle root {} of its header",
+            block.txdata.len(),
+            header.block_hash(),
+            header.merkle_root
+        ));
+    }
+    Ok(())
+}
+
+// Fetches the coinbase transaction of the block. With validate_merkle_root,
+// backends serving full blocks have their block checked against the header
+// and a mismatch is reported as a critical event.
+pub async fn coinbase(
+    network: &Network,
+    node: &dyn Node,
+    header: &Header,
+    event_log: &EventLog,
+) -> Result<Transaction, FetchError> {
+    let hash = header.block_hash();
+    if !network.pool_identification.validate_merkle_root {
+        return node.coinbase(&hash).await;
+    }
+    let block = match node.block(&hash).await? {
+        Some(block) => block,
+        None => return node.coinbase(&hash).await,
+    };
+    if let Err(mismatch) = check_block(header, &block) {
+        error!(
+            "Data integrity error for block {} from node {}: {}",
+            hash,
+            node.info(),
+            mismatch
+        );
+        event_log
+            .emit(
+                network.id,
+                EventKind::DataIntegrity,
+                EventSeverity::Critical,
+                format!("Block data mismatch from {}", node.info().name),
+                format!(
+                    "Node {} returned inconsistent data for block {} on network {}: {}.",
+                    node.info().name,
+                    hash,
+                    network.name,
+                    mismatch
+                ),
+            )
+            .await;
+        return Err(FetchError::DataError(mismatch));
+    }
+    Ok(block.txdata[0].clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoincore_rpc::bitcoin::constants::genesis_block;
+    use bitcoincore_rpc::bitcoin::hashes::Hash;
+    use bitcoincore_rpc::bitcoin::{Network, TxMerkleNode};
+
+    #[test]
+    fn test_check_block() {
+        let block = genesis_block(Network::Bitcoin);
+        assert_eq!(check_block(&block.header, &block), Ok(()));
+
+        // a different block than the header implies
+        let mut other_header = block.header;
+        other_header.nonce += 1;
+        assert!(check_block(&other_header, &block).is_err());
+
+        // transactions not matching the merkle root
+        let mut tampered = block.clone();
+        tampered.header.merkle_root = TxMerkleNode::all_zeros();
+        assert!(check_block(&tampered.header, &tampered).is_err());
+        tampered.txdata.clear();
+        assert!(check_block(&tampered.header, &tampered).is_err());
+    }
+}
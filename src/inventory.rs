@@ -0,0 +1,495 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use log::{error, info};
+
+use crate::config::{self, Network};
+use crate::error::ImportError;
+
+const HEADER_NETWORK: &str = "[[networks]]";
+const HEADER_NODE: &str = "[[networks.nodes]]";
+const FIELDS: [&str; 6] = [
+    "name",
+    "url",
+    "auth",
+    "network",
+    "implementation",
+    "description",
+];
+
+pub struct ImportArgs {
+    pub file: PathBuf,
+    // The merged configuration is printed if unset.
+    pub out: Option<PathBuf>,
+}
+
+pub fn parse_args(args: &[String]) -> Result<ImportArgs, ImportError> {
+    let mut file: Option<PathBuf> = None;
+    let mut out: Option<PathBuf> = None;
+
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        let value = match iter.next() {
+            Some(value) => value,
+            None => {
+                return Err(ImportError::InvalidArguments(format!(
+                    "missing value for '{}'",
+                    arg
+                )))
+            }
+        };
+        match arg.as_str() {
+            "--file" => file = Some(PathBuf::from(value)),
+            "--out" => out = Some(PathBuf::from(value)),
+            _ => {
+                return Err(ImportError::InvalidArguments(format!(
+                    "unknown argument '{}'",
+                    arg
+                )))
+            }
+        }
+    }
+
+    Ok(ImportArgs {
+        file: file
+            .ok_or_else(|| ImportError::InvalidArguments("'--file' is required".to_string()))?,
+        out,
+    })
+}
+
+// A node of an inventory with the line it starts on.
+#[derive(Debug, PartialEq)]
+pub struct InventoryNode {
+    pub line: usize,
+    pub name: String,
+    // host:port, optionally prefixed with http:// or https://. The Esplora
+    // base URL for Esplora nodes.
+    pub url: String,
+    // user:password, cookie:<path to the cookie file> or empty.
+    pub auth: String,
+    // The id or the name of the network.
+    pub network: String,
+    pub implementation: Option<String>,
+    pub description: String,
+}
+
+impl InventoryNode {
+    fn new(line: usize, mut fields: BTreeMap<String, String>) -> Result<Self, ImportError> {
+        if let Some(field) = fields.keys().find(|f| !FIELDS.contains(&f.as_str())) {
+            return Err(ImportError::InvalidInventory(format!(
+                "unknown field '{}' on line {}",
+                field, line
+            )));
+        }
+        let mut required = |name: &str| match fields.remove(name).filter(|v| !v.is_empty()) {
+            Some(value) => Ok(value),
+            None => Err(ImportError::InvalidInventory(format!(
+                "missing '{}' for the node on line {}",
+                name, line
+            ))),
+        };
+        Ok(InventoryNode {
+            line,
+            name: required("name")?,
+            url: required("url")?,
+            network: required("network")?,
+            auth: fields.remove("auth").unwrap_or_default(),
+            implementation: fields.remove("implementation").filter(|v| !v.is_empty()),
+            description: fields.remove("description").unwrap_or_default(),
+        })
+    }
+}
+
+// Splits a CSV line into its fields. Fields can be quoted as written by the
+// export.
+fn csv_fields(line: &str) -> Vec<String> {
+    let mut fields = vec![];
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            '"' => quoted = !quoted,
+            ',' if !quoted => fields.push(std::mem::take(&mut field)),
+            _ => field.push(c),
+        }
+    }
+    fields.push(field);
+    fields.iter().map(|f| f.trim().to_string()).collect()
+}
+
+// Parses a CSV inventory with a header line naming the columns, e.g.
+// name,url,auth,network. Empty lines and lines starting with # are skipped.
+pub fn parse_csv(csv: &str) -> Result<Vec<InventoryNode>, ImportError> {
+    let mut lines = csv
+        .lines()
+        .enumerate()
+        .map(|(i, line)| (i + 1, line.trim()))
+        .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'));
+    let columns = match lines.next() {
+        Some((_, header)) => csv_fields(header),
+        None => return Ok(vec![]),
+    };
+    lines
+        .map(|(number, line)| {
+            let values = csv_fields(line);
+            if values.len() != columns.len() {
+                return Err(ImportError::InvalidInventory(format!(
+                    "line {} has {} fields, but the header has {}",
+                    number,
+                    values.len(),
+                    columns.len()
+                )));
+            }
+            InventoryNode::new(number, columns.iter().cloned().zip(values).collect())
+        })
+        .collect()
+}
+
+fn yaml_value(value: &str) -> String {
+    let value = value.trim();
+    for quote in ['"', '\''] {
+        if value.len() >= 2 && value.starts_with(quote) && value.ends_with(quote) {
+            return value[1..value.len() - 1].to_string();
+        }
+    }
+    value.to_string()
+}
+
+// Parses a simple YAML inventory: a list of nodes with one `key: value` per
+// line, optionally below a top-level `nodes:` key. Anchors, nesting and
+// multi-line values aren't supported.
+pub fn parse_yaml(yaml: &str) -> Result<Vec<InventoryNode>, ImportError> {
+    let mut nodes = vec![];
+    let mut current: Option<(usize, BTreeMap<String, String>)> = None;
+    for (i, line) in yaml.lines().enumerate() {
+        let number = i + 1;
+        let trimmed = line.trim();
+        if trimmed.is_empty() || trimmed.starts_with('#') || trimmed == "---" {
+            continue;
+        }
+        let entry = match trimmed.strip_prefix('-') {
+            Some(entry) => {
+                if let Some((line, fields)) = current.take() {
+                    nodes.push(InventoryNode::new(line, fields)?);
+                }
+                current = Some((number, BTreeMap::new()));
+                entry.trim()
+            }
+            None => trimmed,
+        };
+        if entry.is_empty() {
+            continue;
+        }
+        let (key, value) = match entry.split_once(':') {
+            Some((key, value)) => (key.trim(), yaml_value(value)),
+            None => {
+                return Err(ImportError::InvalidInventory(format!(
+                    "expected 'key: value' on line {}",
+                    number
+                )))
+            }
+        };
+        match current {
+            Some((_, ref mut fields)) => {
+                fields.insert(key.to_string(), value);
+            }
+            // A top-level key like `nodes:` introducing the list.
+            None if value.is_empty() => (),
+            None => {
+                return Err(ImportError::InvalidInventory(format!(
+                    "expected a list item on line {}",
+                    number
+                )))
+            }
+        }
+    }
+    if let Some((line, fields)) = current {
+        nodes.push(InventoryNode::new(line, fields)?);
+    }
+    Ok(nodes)
+}
+
+// Parses a CSV inventory or, if the file ends in .yml or .yaml, a YAML
+// inventory.
+pub fn parse(file: &Path, content: &str) -> Result<Vec<InventoryNode>, ImportError> {
+    match file.extension().and_then(|e| e.to_str()) {
+        Some("yml") | Some("yaml") => parse_yaml(content),
+        _ => parse_csv(content),
+    }
+}
+
+// The [[networks.nodes]] table of an inventory node without its header.
+fn node_table(node: &InventoryNode, id: u32) -> Result<String, String> {
+    let string = |s: &str| toml::Value::String(s.to_string());
+    let mut table: Vec<(&str, toml::Value)> = vec![
+        ("id", toml::Value::Integer(id as i64)),
+        ("name", string(&node.name)),
+        ("description", string(&node.description)),
+    ];
+    if let Some(ref implementation) = node.implementation {
+        table.push(("implementation", string(implementation)));
+    }
+
+    if node.implementation.as_deref().map(str::to_lowercase) == Some("esplora".to_string()) {
+        table.push(("esplora_url", string(&node.url)));
+    } else {
+        let address = match node.url.strip_prefix("https://") {
+            Some(address) => {
+                table.push(("rpc_https", toml::Value::Boolean(true)));
+                address
+            }
+            None => node.url.trim_start_matches("http://"),
+        }
+        .trim_end_matches('/');
+        let (host, port) = address
+            .rsplit_once(':')
+            .ok_or_else(|| format!("the URL '{}' has no port", node.url))?;
+        let port: u16 = port
+            .parse()
+            .map_err(|_| format!("the URL '{}' has an invalid port", node.url))?;
+        table.push(("rpc_host", string(host)));
+        table.push(("rpc_port", toml::Value::Integer(port as i64)));
+    }
+
+    if let Some(cookie_file) = node.auth.strip_prefix("cookie:") {
+        table.push(("rpc_cookie_file", string(cookie_file)));
+    } else if let Some((user, password)) = node.auth.split_once(':') {
+        table.push(("rpc_user", string(user)));
+        table.push(("rpc_password", string(password)));
+    } else if !node.auth.is_empty() {
+        return Err("the auth must be user:password or cookie:<path>".to_string());
+    }
+
+    Ok(table
+        .iter()
+        .map(|(key, value)| format!("{} = {}\n", key, value))
+        .collect())
+}
+
+// Merges the inventory nodes into the configuration file. Nodes get the next
+// free ids of their network. Nodes with a name already used in their network
+// are skipped, so an inventory can be imported again after it was extended.
+// Returns the merged configuration and the number of imported nodes.
+pub fn merge(
+    config_str: &str,
+    networks: &[Network],
+    inventory: &[InventoryNode],
+) -> Result<(String, usize), ImportError> {
+    let mut tables: BTreeMap<u32, Vec<String>> = BTreeMap::new();
+    let mut names: BTreeMap<u32, Vec<String>> = networks
+        .iter()
+        .map(|n| (n.id, n.nodes.iter().map(|node| node.info().name).collect()))
+        .collect();
+    let mut next_ids: BTreeMap<u32, u32> = networks
+        .iter()
+        .map(|n| {
+            let max = n.nodes.iter().map(|node| node.info().id).max();
+            (n.id, max.map(|id| id + 1).unwrap_or_default())
+        })
+        .collect();
+
+    let mut invalid = 0;
+    for node in inventory.iter() {
+        let network = match networks
+            .iter()
+            .find(|n| n.id.to_string() == node.network || n.name == node.network)
+        {
+            Some(network) => network,
+            None => {
+                error!(
+                    "Node '{}' on line {}: unknown network '{}'",
+                    node.name, node.line, node.network
+                );
+                invalid += 1;
+                continue;
+            }
+        };
+        let network_names = names.entry(network.id).or_default();
+        if network_names.contains(&node.name) {
+            info!(
+                "Node '{}' on line {} is already configured for network '{}'",
+                node.name, node.line, network.name
+            );
+            continue;
+        }
+        let id = next_ids.get(&network.id).copied().unwrap_or_default();
+        let validated = node_table(node, id).and_then(|table| {
+            config::parse_node(&table)
+                .map(|_| table)
+                .map_err(|e| e.to_string())
+        });
+        match validated {
+            Ok(table) => {
+                network_names.push(node.name.clone());
+                next_ids.insert(network.id, id + 1);
+                tables.entry(network.id).or_default().push(table);
+            }
+            Err(e) => {
+                error!("Node '{}' on line {}: {}", node.name, node.line, e);
+                invalid += 1;
+            }
+        }
+    }
+    if invalid > 0 {
+        return Err(ImportError::InvalidNodes(invalid));
+    }
+
+    // The nodes of a network are appended before the next [[networks]]
+    // table and the comments introducing it.
+    let lines: Vec<&str> = config_str.lines().collect();
+    let network_starts: Vec<usize> = lines
+        .iter()
+        .enumerate()
+        .filter(|(_, line)| line.trim_start().starts_with(HEADER_NETWORK))
+        .map(|(i, _)| i)
+        .collect();
+    if network_starts.len() != networks.len() {
+        return Err(ImportError::InvalidInventory(format!(
+            "found {} {} tables in the configuration file, expected {}",
+            network_starts.len(),
+            HEADER_NETWORK,
+            networks.len()
+        )));
+    }
+    let mut inserts: BTreeMap<usize, &[String]> = BTreeMap::new();
+    for (i, network) in networks.iter().enumerate() {
+        let mut end = network_starts.get(i + 1).copied().unwrap_or(lines.len());
+        while end > network_starts[i] + 1 {
+            let previous = lines[end - 1].trim();
+            if !previous.is_empty() && !previous.starts_with('#') {
+                break;
+            }
+            end -= 1;
+        }
+        if let Some(network_tables) = tables.get(&network.id) {
+            inserts.insert(end, network_tables);
+        }
+    }
+
+    let mut merged = String::new();
+    for i in 0..=lines.len() {
+        if let Some(network_tables) = inserts.get(&i) {
+            for table in network_tables.iter() {
+                merged.push_str(&format!("\n    {}\n", HEADER_NODE));
+                for line in table.lines() {
+                    merged.push_str(&format!("    {}\n", line));
+                }
+            }
+        }
+        if let Some(line) = lines.get(i) {
+            merged.push_str(line);
+            merged.push('\n');
+        }
+    }
+
+    // The merged configuration must still load.
+    config::parse_config(&merged)?;
+    let imported = tables.values().map(Vec::len).sum();
+    Ok((merged, imported))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: &str = r#"
+database_path = ""
+www_path = "./www"
+query_interval = 15
+address = "127.0.0.1:2323"
+footer_html = ""
+
+[[networks]]
+id = 1
+name = "mainnet"
+description = ""
+min_fork_height = 0
+max_interesting_heights = 100
+
+    [[networks.nodes]]
+    id = 4
+    name = "existing"
+    description = ""
+    implementation = "electrum"
+    rpc_host = "127.0.0.1"
+    rpc_port = 50001
+
+# The signet network
+[[networks]]
+id = 2
+name = "signet"
+description = ""
+min_fork_height = 0
+max_interesting_heights = 100
+
+    [[networks.nodes]]
+    id = 0
+    name = "signet existing"
+    description = ""
+    implementation = "electrum"
+    rpc_host = "127.0.0.1"
+    rpc_port = 60601
+"#;
+
+    #[test]
+    fn test_import() {
+        let csv = "name,url,auth,network,implementation\n\
+                   existing,127.0.0.1:50001,,1,electrum\n\
+                   # a comment\n\
+                   core,http://10.0.0.1:8332,\"fork,observer:pass\",mainnet,\n\
+                   signet electrum,10.0.0.2:60601,,2,electrum\n";
+        let csv_nodes = parse_csv(csv).unwrap();
+        let yaml = "nodes:\n\
+                    \x20 - name: existing\n\
+                    \x20   url: 127.0.0.1:50001\n\
+                    \x20   network: 1\n\
+                    \x20   implementation: electrum\n\
+                    \x20 - name: core\n\
+                    \x20   url: \"http://10.0.0.1:8332\"\n\
+                    \x20   auth: 'fork,observer:pass'\n\
+                    \x20   network: mainnet\n\
+                    \x20 - name: signet electrum\n\
+                    \x20   url: 10.0.0.2:60601\n\
+                    \x20   network: 2\n\
+                    \x20   implementation: electrum\n";
+        let yaml_nodes = parse_yaml(yaml).unwrap();
+        assert_eq!(csv_nodes.len(), 3);
+        assert_eq!(yaml_nodes.len(), 3);
+        for (c, y) in csv_nodes.iter().zip(yaml_nodes.iter()) {
+            assert_eq!((&c.name, &c.url, &c.auth), (&y.name, &y.url, &y.auth));
+            assert_eq!(c.implementation, y.implementation);
+        }
+
+        let config = config::parse_config(CONFIG).unwrap();
+        let (merged, imported) = merge(CONFIG, &config.networks, &csv_nodes).unwrap();
+        // the existing node is skipped
+        assert_eq!(imported, 2);
+        let merged_config = config::parse_config(&merged).unwrap();
+        let mainnet_nodes: Vec<(u32, String)> = merged_config.networks[0]
+            .nodes
+            .iter()
+            .map(|n| (n.info().id, n.info().name))
+            .collect();
+        assert_eq!(
+            mainnet_nodes,
+            vec![(4, "existing".to_string()), (5, "core".to_string())]
+        );
+        assert_eq!(merged_config.networks[1].nodes.len(), 2);
+        assert_eq!(merged_config.networks[1].nodes[1].info().id, 1);
+        // the nodes are added before the comment introducing the next network
+        assert!(merged.contains("rpc_password = \"pass\"\n\n# The signet network"));
+
+        let invalid = parse_csv("name,url,network\ncore,10.0.0.1,mainnet\n").unwrap();
+        assert!(matches!(
+            merge(CONFIG, &config.networks, &invalid),
+            Err(ImportError::InvalidNodes(1))
+        ));
+        assert!(parse_csv("name,url\ncore\n").is_err());
+        assert!(parse_yaml("- name: core\n  port: 1\n").is_err());
+    }
+}
@@ -1,16 +1,23 @@
 use std::fmt;
+use std::io;
 use std::str::FromStr;
+use std::time::Duration;
 
+use crate::domain::ChainTip;
 use crate::error::JsonRPCError;
-use crate::types::ChainTip;
+use crate::http::HttpEndpoint;
 
 use bitcoincore_rpc::bitcoin;
 use bitcoincore_rpc::bitcoin::blockdata::block::Header;
 use bitcoincore_rpc::bitcoin::Block;
 
-use base64;
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
+use tungstenite::client::IntoClientRequest;
+use tungstenite::handshake::HandshakeError;
+use tungstenite::http::HeaderValue;
+use tungstenite::Message;
 
 use log::{debug, warn};
 
@@ -18,6 +25,9 @@ const JSON_RPC_VERSION: &str = "1.0";
 const JSON_RPC_ID: u64 = 45324;
 const BITCOIN_BLOCK_HEADER_HEX_LENGTH: usize = 80 * 2;
 const BITCOIN_BLOCK_HASH_HEX_LENGTH: usize = 32 * 2;
+const RPC_TIMEOUT: Duration = Duration::from_secs(8);
+// A quiet websocket connection is pinged after this time.
+const WEBSOCKET_PING_INTERVAL: Duration = Duration::from_secs(60);
 
 #[derive(Serialize, Debug)]
 struct Request {
@@ -39,6 +49,26 @@ impl fmt::Display for Error {
     }
 }
 
+// A chain tip as returned by btcd's getchaintips.
+#[derive(Deserialize)]
+struct ChainTipResult {
+    height: u64,
+    hash: String,
+    branchlen: usize,
+    status: String,
+}
+
+impl From<ChainTipResult> for ChainTip {
+    fn from(t: ChainTipResult) -> Self {
+        ChainTip {
+            height: t.height,
+            hash: t.hash,
+            branchlen: t.branchlen,
+            status: t.status.into(),
+        }
+    }
+}
+
 #[derive(Deserialize)]
 struct Response<T> {
     jsonrpc: String,
@@ -71,31 +101,70 @@ impl<T> Response<T> {
     }
 }
 
+// Calls an arbitrary method and returns its result. Unlike the btcd
+// requests, the response isn't checked for the JSON-RPC version and id, as
+// node software differs in what it returns there.
+pub fn call(
+    endpoint: &HttpEndpoint,
+    user: String,
+    password: String,
+    method: &str,
+    params: Vec<Value>,
+) -> Result<Value, JsonRPCError> {
+    let body = post(method.to_string(), params, endpoint, user, password)?;
+    let mut response: Value = serde_json::from_slice(&body).map_err(|e| {
+        JsonRPCError::JsonRpc(format!(
+            "JSON RPC response for request '{}' could not be parsed: {}",
+            method, e
+        ))
+    })?;
+    match response.get("error") {
+        Some(error) if !error.is_null() => Err(JsonRPCError::JsonRpc(format!(
+            "JSON RPC response for request '{}' contains error: {}",
+            method, error
+        ))),
+        _ => match response.get_mut("result").map(Value::take) {
+            Some(result) if !result.is_null() => Ok(result),
+            _ => Err(JsonRPCError::JsonRpc(format!(
+                "JSON RPC response for request '{}' was empty.",
+                method
+            ))),
+        },
+    }
+}
+
+// Returns the parsed chain tips and the raw getchaintips result.
 pub fn btcd_chaintips(
-    url: String,
+    endpoint: &HttpEndpoint,
     user: String,
     password: String,
-) -> Result<Vec<ChainTip>, JsonRPCError> {
+) -> Result<(Vec<ChainTip>, Value), JsonRPCError> {
     const METHOD: &str = "getchaintips";
 
-    let res = request(METHOD.to_string(), vec![], url, user, password)?;
-    let jsonrpc_response: Response<Vec<ChainTip>> = res.json()?;
+    let jsonrpc_response: Response<Value> =
+        request(METHOD.to_string(), vec![], endpoint, user, password)?;
     if let Some(e) = jsonrpc_response.check(METHOD) {
         return Err(e);
     }
 
-    if let Some(response) = jsonrpc_response.result {
-        return Ok(response);
+    if let Some(raw) = jsonrpc_response.result {
+        match serde_json::from_value::<Vec<ChainTipResult>>(raw.clone()) {
+            Ok(tips) => Ok((tips.into_iter().map(ChainTip::from).collect(), raw)),
+            Err(e) => Err(JsonRPCError::RpcUnexpectedResponseContents(format!(
+                "JSON RPC response for request '{}' could not be parsed: {}",
+                METHOD, e
+            ))),
+        }
     } else {
-        return Err(JsonRPCError::JsonRpc(format!(
+        Err(JsonRPCError::JsonRpc(format!(
             "JSON RPC response for request '{}' was empty.",
             METHOD
-        )));
+        )))
     }
 }
 
 pub fn btcd_blockheader(
-    url: String,
+    endpoint: &HttpEndpoint,
     user: String,
     password: String,
     hash: String,
@@ -103,14 +172,13 @@ pub fn btcd_blockheader(
     const METHOD: &str = "getblockheader";
     const PARAM_VERBOSE: bool = false;
 
-    let res = request(
+    let jsonrpc_response: Response<String> = request(
         METHOD.to_string(),
         vec![Value::from(hash), Value::from(PARAM_VERBOSE)],
-        url,
+        endpoint,
         user,
         password,
     )?;
-    let jsonrpc_response: Response<String> = res.json()?;
     if let Some(e) = jsonrpc_response.check(METHOD) {
         return Err(e);
     }
@@ -127,11 +195,11 @@ pub fn btcd_blockheader(
     let header_bytes = hex::decode(header_hex)?;
 
     let header: Header = bitcoin::consensus::deserialize(&header_bytes)?;
-    return Ok(header);
+    Ok(header)
 }
 
 pub fn btcd_block(
-    url: String,
+    endpoint: &HttpEndpoint,
     user: String,
     password: String,
     hash: String,
@@ -139,14 +207,13 @@ pub fn btcd_block(
     const METHOD: &str = "getblock";
     const PARAM_VERBOSE: i8 = 0; // requests the raw block
 
-    let res = request(
+    let jsonrpc_response: Response<String> = request(
         METHOD.to_string(),
         vec![Value::from(hash), Value::from(PARAM_VERBOSE)],
-        url,
+        endpoint,
         user,
         password,
     )?;
-    let jsonrpc_response: Response<String> = res.json()?;
     if let Some(e) = jsonrpc_response.check(METHOD) {
         return Err(e);
     }
@@ -158,21 +225,20 @@ pub fn btcd_block(
 }
 
 pub fn btcd_blockhash(
-    url: String,
+    endpoint: &HttpEndpoint,
     user: String,
     password: String,
     height: u64,
 ) -> Result<bitcoin::BlockHash, JsonRPCError> {
     const METHOD: &str = "getblockhash";
 
-    let res = request(
+    let jsonrpc_response: Response<String> = request(
         METHOD.to_string(),
         vec![Value::from(height)],
-        url,
+        endpoint,
         user,
         password,
     )?;
-    let jsonrpc_response: Response<String> = res.json()?;
     if let Some(e) = jsonrpc_response.check(METHOD) {
         return Err(e);
     }
@@ -189,13 +255,197 @@ pub fn btcd_blockhash(
     Ok(bitcoin::BlockHash::from_str(&hash_hex)?)
 }
 
-fn request(
+// The agent string of a btcd node, e.g. /btcwire:0.5.0/btcd:0.24.0/, like
+// Bitcoin Core's subversion. Older btcd versions don't implement
+// getnetworkinfo, so we fall back to the version RPC.
+pub fn btcd_version(
+    endpoint: &HttpEndpoint,
+    user: String,
+    password: String,
+) -> Result<String, JsonRPCError> {
+    match btcd_subversion(endpoint, user.clone(), password.clone()) {
+        Ok(subversion) => Ok(subversion),
+        Err(e) => {
+            debug!("Falling back to the version RPC for btcd: {}", e);
+            btcd_api_version(endpoint, user, password)
+        }
+    }
+}
+
+fn btcd_subversion(
+    endpoint: &HttpEndpoint,
+    user: String,
+    password: String,
+) -> Result<String, JsonRPCError> {
+    const METHOD: &str = "getnetworkinfo";
+
+    let jsonrpc_response: Response<Value> =
+        request(METHOD.to_string(), vec![], endpoint, user, password)?;
+    if let Some(e) = jsonrpc_response.check(METHOD) {
+        return Err(e);
+    }
+
+    match jsonrpc_response
+        .result
+        .as_ref()
+        .and_then(|r| r.get("subversion"))
+        .and_then(Value::as_str)
+    {
+        Some(subversion) => Ok(subversion.to_string()),
+        None => Err(JsonRPCError::RpcUnexpectedResponseContents(format!(
+            "JSON RPC response for request '{}' has no subversion.",
+            METHOD
+        ))),
+    }
+}
+
+fn btcd_api_version(
+    endpoint: &HttpEndpoint,
+    user: String,
+    password: String,
+) -> Result<String, JsonRPCError> {
+    const METHOD: &str = "version";
+
+    let jsonrpc_response: Response<Value> =
+        request(METHOD.to_string(), vec![], endpoint, user, password)?;
+    if let Some(e) = jsonrpc_response.check(METHOD) {
+        return Err(e);
+    }
+
+    match jsonrpc_response
+        .result
+        .as_ref()
+        .and_then(agent_from_versions)
+    {
+        Some(agent) => Ok(agent),
+        None => Err(JsonRPCError::RpcUnexpectedResponseContents(format!(
+            "JSON RPC response for request '{}' has no version string.",
+            METHOD
+        ))),
+    }
+}
+
+// Formats the result of btcd's version RPC, a map of component names to
+// versions, as agent string. For example,
+// {"btcdjsonrpcapi": {"versionstring": "1.3.0", ..}} becomes
+// /btcdjsonrpcapi:1.3.0/.
+fn agent_from_versions(versions: &Value) -> Option<String> {
+    let components: Vec<String> = versions
+        .as_object()?
+        .iter()
+        .filter_map(|(name, version)| {
+            version
+                .get("versionstring")
+                .and_then(Value::as_str)
+                .map(|v| format!("{}:{}/", name, v))
+        })
+        .collect();
+    if components.is_empty() {
+        None
+    } else {
+        Some(format!("/{}", components.concat()))
+    }
+}
+
+// Subscribes to block notifications (notifyblocks) via btcd's websocket
+// endpoint and calls on_block for each connected or disconnected block.
+// Only returns on errors, e.g. when the connection is lost. The websocket
+// uses TLS if the RPC endpoint does.
+pub fn btcd_notify_blocks(
+    endpoint: &HttpEndpoint,
+    user: &str,
+    password: &str,
+    on_block: &dyn Fn(),
+) -> Result<(), JsonRPCError> {
+    let scheme = if endpoint.is_https() { "wss" } else { "ws" };
+    let mut ws_request =
+        format!("{}://{}/ws", scheme, endpoint.authority()).into_client_request()?;
+    let token = format!("{}:{}", user, password);
+    match HeaderValue::from_str(&format!("Basic {}", base64::encode(&token))) {
+        Ok(value) => {
+            ws_request.headers_mut().insert("Authorization", value);
+        }
+        Err(e) => return Err(JsonRPCError::Http(e.to_string())),
+    };
+    let stream = endpoint.connect(WEBSOCKET_PING_INTERVAL)?;
+    let (mut socket, _) = match tungstenite::client(ws_request, stream) {
+        Ok(result) => result,
+        Err(HandshakeError::Failure(e)) => return Err(e.into()),
+        Err(HandshakeError::Interrupted(_)) => {
+            return Err(JsonRPCError::Http(String::from(
+                "the websocket handshake was interrupted",
+            )))
+        }
+    };
+
+    let notifyblocks = Request {
+        jsonrpc: String::from(JSON_RPC_VERSION),
+        id: JSON_RPC_ID,
+        method: String::from("notifyblocks"),
+        params: vec![],
+    };
+    match serde_json::to_string(&notifyblocks) {
+        Ok(text) => socket.send(Message::Text(text))?,
+        Err(e) => return Err(JsonRPCError::JsonRpc(e.to_string())),
+    }
+
+    loop {
+        let message = match socket.read() {
+            Ok(message) => message,
+            // Check that the connection is still alive when it's quiet.
+            Err(tungstenite::Error::Io(e))
+                if e.kind() == io::ErrorKind::WouldBlock || e.kind() == io::ErrorKind::TimedOut =>
+            {
+                socket.send(Message::Ping(vec![]))?;
+                continue;
+            }
+            Err(e) => return Err(e.into()),
+        };
+        if let Message::Text(text) = message {
+            debug!("btcd websocket message: {}", text);
+            if is_block_notification(&text) {
+                on_block();
+            }
+        }
+    }
+}
+
+// If the websocket message is a blockconnected or blockdisconnected
+// notification.
+fn is_block_notification(text: &str) -> bool {
+    match serde_json::from_str::<Value>(text) {
+        Ok(message) => matches!(
+            message.get("method").and_then(Value::as_str),
+            Some("blockconnected") | Some("blockdisconnected")
+        ),
+        Err(_) => false,
+    }
+}
+
+fn request<T: DeserializeOwned>(
+    method: String,
+    params: Vec<Value>,
+    endpoint: &HttpEndpoint,
+    user: String,
+    password: String,
+) -> Result<Response<T>, JsonRPCError> {
+    let body = post(method.clone(), params, endpoint, user, password)?;
+    serde_json::from_slice(&body).map_err(|e| {
+        JsonRPCError::JsonRpc(format!(
+            "JSON RPC response for request '{}' could not be parsed: {}",
+            method, e
+        ))
+    })
+}
+
+// Sends the request and returns the body of a successful response.
+fn post(
     method: String,
     params: Vec<Value>,
-    url: String,
+    endpoint: &HttpEndpoint,
     user: String,
     password: String,
-) -> Result<minreq::Response, JsonRPCError> {
+) -> Result<Vec<u8>, JsonRPCError> {
     let jsonrpc_request = Request {
         jsonrpc: String::from(JSON_RPC_VERSION),
         id: JSON_RPC_ID,
@@ -210,23 +460,60 @@ fn request(
         user, jsonrpc_request
     );
 
-    let res = minreq::post(url.clone())
-        .with_header("Authorization", format!("Basic {}", base64::encode(&token)))
-        .with_header("content-type", "plain/text")
-        .with_json(&jsonrpc_request)?
-        .with_timeout(8)
-        .send()?;
+    let body =
+        serde_json::to_vec(&jsonrpc_request).map_err(|e| JsonRPCError::JsonRpc(e.to_string()))?;
+    let authorization = format!("Basic {}", base64::encode(&token));
+    let res = endpoint.request(
+        "POST",
+        "/",
+        &[
+            ("Authorization", &authorization),
+            ("Content-Type", "plain/text"),
+        ],
+        &body,
+        RPC_TIMEOUT,
+    )?;
+    let text = String::from_utf8_lossy(&res.body);
 
-    debug!("JSON-RPC response for {}: {:?}", method, res.as_str());
+    debug!("JSON-RPC response for {}: {}", method, text);
 
     if res.status_code != 200 {
         return Err(JsonRPCError::Http(format!(
             "HTTP request failed: {} {}: {}",
-            res.status_code,
-            res.reason_phrase,
-            res.as_str()?
+            res.status_code, res.reason_phrase, text
         )));
     }
 
-    Ok(res)
+    Ok(res.body)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_agent_from_versions() {
+        let versions = serde_json::json!({
+            "btcdjsonrpcapi": {"versionstring": "1.3.0", "major": 1, "minor": 3, "patch": 0},
+        });
+        assert_eq!(
+            agent_from_versions(&versions),
+            Some("/btcdjsonrpcapi:1.3.0/".to_string())
+        );
+        assert_eq!(agent_from_versions(&serde_json::json!({})), None);
+    }
+
+    #[test]
+    fn test_is_block_notification() {
+        assert!(is_block_notification(
+            r#"{"jsonrpc":"1.0","method":"blockconnected","params":["0000","800000",1690000000]}"#
+        ));
+        assert!(is_block_notification(
+            r#"{"jsonrpc":"1.0","method":"blockdisconnected","params":[]}"#
+        ));
+        assert!(!is_block_notification(
+            r#"{"jsonrpc":"1.0","result":null,"error":null,"id":45324}"#
+        ));
+        assert!(!is_block_notification("not json"));
+    }
 }
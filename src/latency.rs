@@ -0,0 +1,214 @@
+use std::collections::HashMap;
+use std::convert::Infallible;
+
+use log::error;
+use serde::Deserialize;
+use warp::http::StatusCode;
+use warp::Reply;
+
+use crate::config::Network;
+use crate::db;
+use crate::domain::{Fork, Tree};
+use crate::error::DbError;
+use crate::headertree;
+use crate::rss::response_unknown_network;
+use crate::types::{
+    BranchLatencyJson, Caches, Db, ForkLatenciesJsonResponse, ForkLatencyJson, NetworkJson,
+    Versioned,
+};
+
+// Branches first seen more than this many seconds after the block time of
+// their last common block count as late-surfacing.
+pub const DEFAULT_LATE_BRANCH_AGE: u64 = 2 * 60 * 60;
+// A branch is first seen when a node first reports one of its blocks up to
+// this many blocks above its first block as a tip.
+const MAX_BRANCH_DEPTH: u64 = 144;
+
+#[derive(Deserialize)]
+pub struct LatencyQuery {
+    /// In seconds.
+    pub max_age: Option<u64>,
+}
+
+// How old the last common block of each fork was when each of its branches
+// was first seen, by the block time and by the time the block itself was
+// first seen. Branches competing in real time are seen shortly after the
+// last common block, while historical branches surface late.
+pub async fn fork_latencies(
+    forks: &[Fork],
+    tree: &Tree,
+    db: Db,
+    network_id: u32,
+    max_age: u64,
+) -> Result<ForkLatenciesJsonResponse, DbError> {
+    let mut branches: Vec<Vec<String>> = vec![];
+    let mut hashes: Vec<String> = vec![];
+    for fork in forks.iter() {
+        hashes.push(fork.common.block_hash().to_string());
+        for child in fork.children.iter() {
+            let branch: Vec<String> =
+                headertree::branch_hashes(tree, &child.block_hash(), MAX_BRANCH_DEPTH)
+                    .await
+                    .iter()
+                    .map(|hash| hash.to_string())
+                    .collect();
+            hashes.extend(branch.iter().cloned());
+            branches.push(branch);
+        }
+    }
+    let first_seen = db::load_first_seen(db, network_id, &hashes).await?;
+    Ok(latencies(forks, &branches, &first_seen, max_age))
+}
+
+// The branches hold the block hashes of each branch of the forks in order.
+fn latencies(
+    forks: &[Fork],
+    branches: &[Vec<String>],
+    first_seen: &HashMap<String, u64>,
+    max_age: u64,
+) -> ForkLatenciesJsonResponse {
+    let mut branches = branches.iter();
+    let mut forks_json: Vec<ForkLatencyJson> = vec![];
+    for fork in forks.iter() {
+        let common_hash = fork.common.block_hash().to_string();
+        let common_time = fork.common.header.time as u64;
+        let common_first_seen = first_seen.get(&common_hash).copied();
+        let branches_json: Vec<BranchLatencyJson> = fork
+            .children
+            .iter()
+            .zip(branches.by_ref())
+            .map(|(child, branch)| {
+                let branch_first_seen = branch.iter().filter_map(|h| first_seen.get(h)).min();
+                let age = branch_first_seen.map(|seen| *seen as i64 - common_time as i64);
+                BranchLatencyJson {
+                    hash: child.block_hash().to_string(),
+                    first_seen: branch_first_seen.copied(),
+                    age,
+                    observed_age: branch_first_seen
+                        .zip(common_first_seen)
+                        .map(|(seen, common_seen)| seen.saturating_sub(common_seen)),
+                    late: age.map(|age| age > max_age as i64),
+                }
+            })
+            .collect();
+        forks_json.push(ForkLatencyJson {
+            common: common_hash,
+            height: fork.common.height,
+            time: fork.common.header.time,
+            first_seen: common_first_seen,
+            branches: branches_json,
+        });
+    }
+    forks_json.sort_by_key(|f| f.height);
+
+    ForkLatenciesJsonResponse {
+        max_age,
+        late_branches: forks_json
+            .iter()
+            .flat_map(|f| f.branches.iter())
+            .filter(|b| b.late == Some(true))
+            .count(),
+        forks: forks_json,
+    }
+}
+
+pub async fn fork_latency_response(
+    network_id: u32,
+    query: LatencyQuery,
+    caches: Caches,
+    db: Db,
+    networks: Vec<Network>,
+    trees: HashMap<u32, Tree>,
+    network_infos: Vec<NetworkJson>,
+) -> Result<Box<dyn Reply>, Infallible> {
+    let forks = {
+        let caches_locked = caches.lock().await;
+        match caches_locked.get(&network_id) {
+            Some(cache) => cache.forks.clone(),
+            None => return Ok(Box::new(response_unknown_network(network_infos))),
+        }
+    };
+    let tree = match networks
+        .iter()
+        .find(|n| n.id == network_id)
+        .and_then(|n| trees.get(&n.header_store))
+    {
+        Some(tree) => tree,
+        None => return Ok(Box::new(response_unknown_network(network_infos))),
+    };
+    let max_age = query.max_age.unwrap_or(DEFAULT_LATE_BRANCH_AGE);
+    match fork_latencies(&forks, tree, db, network_id, max_age).await {
+        Ok(latencies) => Ok(Box::new(warp::reply::json(&Versioned::new(latencies)))),
+        Err(e) => {
+            error!(
+                "Could not load the fork latencies of network {}: {}",
+                network_id, e
+            );
+            Ok(Box::new(StatusCode::INTERNAL_SERVER_ERROR))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::child;
+    use petgraph::graph::DiGraph;
+    use std::sync::Arc;
+    use tokio::sync::Mutex;
+
+    #[tokio::test]
+    async fn test_latencies() {
+        // a0 is the last common block of a real-time branch a1 and a
+        // branch b1 -> b2 that surfaced a day later, when b2 was reported.
+        let a0 = child(None, 1000, 0);
+        let a1 = child(Some(&a0), 1600, 1);
+        let b1 = child(Some(&a0), 1500, 2);
+        let b2 = child(Some(&b1), 2000, 3);
+        let mut graph = DiGraph::new();
+        let mut index = std::collections::HashMap::new();
+        for h in [&a0, &a1, &b1, &b2] {
+            let idx = graph.add_node(h.clone());
+            index.insert(h.block_hash(), idx);
+            if h.height > 0 {
+                graph.add_edge(index[&h.header.prev_blockhash], idx, 0);
+            }
+        }
+        let tree: Tree = Arc::new(Mutex::new((graph, index, HashMap::new())));
+
+        let fork = Fork {
+            common: a0.clone(),
+            children: vec![a1.clone(), b1.clone()],
+        };
+        let mut branches = vec![];
+        for child in fork.children.iter() {
+            let mut branch: Vec<String> =
+                headertree::branch_hashes(&tree, &child.block_hash(), MAX_BRANCH_DEPTH)
+                    .await
+                    .iter()
+                    .map(|h| h.to_string())
+                    .collect();
+            branch.sort();
+            branches.push(branch);
+        }
+        let mut b_branch = vec![b1.block_hash().to_string(), b2.block_hash().to_string()];
+        b_branch.sort();
+        assert_eq!(branches[1], b_branch);
+
+        let first_seen: HashMap<String, u64> = HashMap::from([
+            (a0.block_hash().to_string(), 1010),
+            (a1.block_hash().to_string(), 1610),
+            (b2.block_hash().to_string(), 90000),
+        ]);
+        let response = latencies(&[fork], &branches, &first_seen, 3600);
+        assert_eq!(response.late_branches, 1);
+        let fork = &response.forks[0];
+        assert_eq!(fork.first_seen, Some(1010));
+        assert_eq!(fork.branches[0].age, Some(610));
+        assert_eq!(fork.branches[0].observed_age, Some(600));
+        assert_eq!(fork.branches[0].late, Some(false));
+        assert_eq!(fork.branches[1].first_seen, Some(90000));
+        assert_eq!(fork.branches[1].age, Some(89000));
+        assert_eq!(fork.branches[1].late, Some(true));
+    }
+}
@@ -0,0 +1,245 @@
+use std::collections::HashMap;
+use std::convert::Infallible;
+
+use log::error;
+use warp::http::StatusCode;
+use warp::Reply;
+
+use crate::config::Network;
+use crate::db;
+use crate::domain::{Fork, HeaderInfo, Tree};
+use crate::error::DbError;
+use crate::headertree;
+use crate::histogram::FORK_CLOSED_DEPTH;
+use crate::rss::response_unknown_network;
+use crate::types::{
+    BranchLifecycleJson, Caches, Db, ForkLifecycleJson, ForkLifecyclesJsonResponse, NetworkJson,
+    Versioned,
+};
+
+// The blocks of a branch are considered up to this many blocks above its
+// first block.
+const MAX_BRANCH_DEPTH: u64 = 144;
+
+// How each fork evolved from the first divergence to its resolution, by the
+// times the nodes first reported the blocks as tips and by the block times.
+// Like for the branch length histogram, a fork is resolved once all
+// branches but the longest are closed_depth blocks shorter than it.
+pub async fn fork_lifecycles(
+    forks: &[Fork],
+    tree: &Tree,
+    db: Db,
+    network_id: u32,
+) -> Result<ForkLifecyclesJsonResponse, DbError> {
+    let mut branches: Vec<Vec<HeaderInfo>> = vec![];
+    let mut hashes: Vec<String> = vec![];
+    for fork in forks.iter() {
+        for child in fork.children.iter() {
+            let branch =
+                headertree::branch_headers(tree, &child.block_hash(), MAX_BRANCH_DEPTH).await;
+            hashes.extend(branch.iter().map(|h| h.block_hash().to_string()));
+            branches.push(branch);
+        }
+    }
+    let first_seen = db::load_first_seen(db, network_id, &hashes).await?;
+    Ok(lifecycles(forks, &branches, &first_seen, FORK_CLOSED_DEPTH))
+}
+
+// The branches hold the headers of each branch of the forks in order.
+fn lifecycles(
+    forks: &[Fork],
+    branches: &[Vec<HeaderInfo>],
+    first_seen: &HashMap<String, u64>,
+    closed_depth: u64,
+) -> ForkLifecyclesJsonResponse {
+    let seen = |header_info: &HeaderInfo| {
+        first_seen
+            .get(&header_info.block_hash().to_string())
+            .copied()
+    };
+    let mut branches = branches.iter();
+    let mut forks_json: Vec<ForkLifecycleJson> = vec![];
+    for fork in forks.iter() {
+        let fork_branches: Vec<&Vec<HeaderInfo>> =
+            branches.by_ref().take(fork.children.len()).collect();
+        let lengths: Vec<u64> = fork_branches
+            .iter()
+            .map(|branch| {
+                branch
+                    .iter()
+                    .map(|h| h.height - fork.common.height)
+                    .max()
+                    .unwrap_or_default()
+            })
+            .collect();
+        let branches_first_seen: Vec<Option<u64>> = fork_branches
+            .iter()
+            .map(|branch| branch.iter().filter_map(seen).min())
+            .collect();
+
+        let longest = lengths.iter().copied().max().unwrap_or_default();
+        let winner = lengths.iter().position(|length| *length == longest);
+        let losing_depth = lengths
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| Some(*i) != winner)
+            .map(|(_, length)| *length)
+            .max();
+        let resolved = losing_depth.is_some_and(|depth| depth + closed_depth <= longest);
+
+        // The second branch appeared when the fork diverged.
+        let mut seen_times: Vec<u64> = branches_first_seen.iter().flatten().copied().collect();
+        seen_times.sort_unstable();
+        let diverged = seen_times.get(1).copied();
+        let mut block_times: Vec<u32> = fork.children.iter().map(|c| c.header.time).collect();
+        block_times.sort_unstable();
+        let diverged_time = block_times.get(1).copied();
+
+        // The winning branch got ahead for good with its first block above
+        // the longest losing branch.
+        let (resolved_at, resolved_time) = match (resolved, winner, losing_depth) {
+            (true, Some(winner), Some(losing_depth)) => {
+                let ahead_height = fork.common.height + losing_depth + 1;
+                let ahead: Vec<&HeaderInfo> = fork_branches[winner]
+                    .iter()
+                    .filter(|h| h.height >= ahead_height)
+                    .collect();
+                (
+                    ahead.iter().filter_map(|h| seen(h)).min(),
+                    ahead
+                        .iter()
+                        .filter(|h| h.height == ahead_height)
+                        .map(|h| h.header.time)
+                        .min(),
+                )
+            }
+            _ => (None, None),
+        };
+
+        forks_json.push(ForkLifecycleJson {
+            common: fork.common.block_hash().to_string(),
+            height: fork.common.height,
+            resolved,
+            diverged,
+            resolved_at,
+            duration: resolved_at
+                .zip(diverged)
+                .map(|(resolved_at, diverged)| resolved_at.saturating_sub(diverged)),
+            block_time_duration: resolved_time
+                .zip(diverged_time)
+                .map(|(resolved_time, diverged_time)| resolved_time as i64 - diverged_time as i64),
+            winning_branch: winner
+                .filter(|_| resolved)
+                .map(|winner| fork.children[winner].block_hash().to_string()),
+            losing_depth: losing_depth.filter(|_| resolved),
+            branches: fork
+                .children
+                .iter()
+                .enumerate()
+                .map(|(i, child)| BranchLifecycleJson {
+                    hash: child.block_hash().to_string(),
+                    length: lengths[i],
+                    first_seen: branches_first_seen[i],
+                    won: resolved.then_some(Some(i) == winner),
+                })
+                .collect(),
+        });
+    }
+    forks_json.sort_by_key(|f| f.height);
+
+    ForkLifecyclesJsonResponse {
+        closed_depth,
+        open_forks: forks_json.iter().filter(|f| !f.resolved).count(),
+        forks: forks_json,
+    }
+}
+
+pub async fn fork_lifecycle_response(
+    network_id: u32,
+    caches: Caches,
+    db: Db,
+    networks: Vec<Network>,
+    trees: HashMap<u32, Tree>,
+    network_infos: Vec<NetworkJson>,
+) -> Result<Box<dyn Reply>, Infallible> {
+    let forks = {
+        let caches_locked = caches.lock().await;
+        match caches_locked.get(&network_id) {
+            Some(cache) => cache.forks.clone(),
+            None => return Ok(Box::new(response_unknown_network(network_infos))),
+        }
+    };
+    let tree = match networks
+        .iter()
+        .find(|n| n.id == network_id)
+        .and_then(|n| trees.get(&n.header_store))
+    {
+        Some(tree) => tree,
+        None => return Ok(Box::new(response_unknown_network(network_infos))),
+    };
+    match fork_lifecycles(&forks, tree, db, network_id).await {
+        Ok(lifecycles) => Ok(Box::new(warp::reply::json(&Versioned::new(lifecycles)))),
+        Err(e) => {
+            error!(
+                "Could not load the fork lifecycles of network {}: {}",
+                network_id, e
+            );
+            Ok(Box::new(StatusCode::INTERNAL_SERVER_ERROR))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::child;
+
+    #[test]
+    fn test_lifecycles() {
+        // a0 is the last common block of a branch a1 -> a2 -> a3 and a
+        // branch b1 -> b2, which are racing until a3 is found.
+        let a0 = child(None, 1000, 0);
+        let a1 = child(Some(&a0), 1600, 1);
+        let a2 = child(Some(&a1), 2200, 2);
+        let a3 = child(Some(&a2), 2800, 3);
+        let b1 = child(Some(&a0), 1650, 4);
+        let b2 = child(Some(&b1), 2100, 5);
+        let fork = Fork {
+            common: a0.clone(),
+            children: vec![b1.clone(), a1.clone()],
+        };
+        let branches = vec![
+            vec![b1.clone(), b2.clone()],
+            vec![a1.clone(), a2.clone(), a3.clone()],
+        ];
+        let first_seen: HashMap<String, u64> = HashMap::from([
+            (a1.block_hash().to_string(), 1610),
+            (b1.block_hash().to_string(), 1660),
+            (a3.block_hash().to_string(), 2810),
+        ]);
+
+        let response = lifecycles(std::slice::from_ref(&fork), &branches, &first_seen, 1);
+        assert_eq!(response.open_forks, 0);
+        let lifecycle = &response.forks[0];
+        assert!(lifecycle.resolved);
+        assert_eq!(lifecycle.diverged, Some(1660));
+        assert_eq!(lifecycle.resolved_at, Some(2810));
+        assert_eq!(lifecycle.duration, Some(1150));
+        assert_eq!(lifecycle.block_time_duration, Some(1150));
+        assert_eq!(lifecycle.winning_branch, Some(a1.block_hash().to_string()));
+        assert_eq!(lifecycle.losing_depth, Some(2));
+        assert_eq!(lifecycle.branches[0].length, 2);
+        assert_eq!(lifecycle.branches[0].won, Some(false));
+        assert_eq!(lifecycle.branches[1].won, Some(true));
+
+        // not resolved before a branch is closed_depth blocks behind
+        let response = lifecycles(&[fork], &branches, &first_seen, 2);
+        assert_eq!(response.open_forks, 1);
+        let lifecycle = &response.forks[0];
+        assert!(!lifecycle.resolved);
+        assert_eq!(lifecycle.diverged, Some(1660));
+        assert_eq!(lifecycle.resolved_at, None);
+        assert_eq!(lifecycle.winning_branch, None);
+        assert_eq!(lifecycle.branches[1].won, None);
+    }
+}
@@ -0,0 +1,147 @@
+use std::collections::BTreeMap;
+use std::str::FromStr;
+use std::sync::RwLock;
+
+use env_logger::filter::{Builder as FilterBuilder, Filter};
+use env_logger::{Env, DEFAULT_FILTER_ENV, DEFAULT_WRITE_STYLE_ENV};
+use log::{LevelFilter, Log, Metadata, Record};
+
+use crate::trace;
+
+const DEFAULT_FILTER: &str = "info";
+
+struct Filters {
+    // Per-module levels set at runtime. They take precedence over the
+    // filter set via the environment.
+    overrides: BTreeMap<String, LevelFilter>,
+    filter: Filter,
+}
+
+// A logger whose per-module levels can be changed at runtime, e.g. via the
+// admin API. The records are formatted and written by env_logger, the
+// filtering is done here.
+pub struct Logger {
+    inner: env_logger::Logger,
+    // The filter set via the RUST_LOG environment variable.
+    base: String,
+    filters: RwLock<Filters>,
+}
+
+fn build_filter(base: &str, overrides: &BTreeMap<String, LevelFilter>) -> Filter {
+    let mut builder = FilterBuilder::new();
+    builder.parse(base);
+    for (module, level) in overrides.iter() {
+        builder.filter_module(module, *level);
+    }
+    builder.build()
+}
+
+impl Logger {
+    pub fn base(&self) -> &str {
+        &self.base
+    }
+
+    pub fn overrides(&self) -> BTreeMap<String, LevelFilter> {
+        match self.filters.read() {
+            Ok(filters) => filters.overrides.clone(),
+            Err(_) => BTreeMap::new(),
+        }
+    }
+
+    // Sets the level of a module (e.g. fork_observer::node) or, with None,
+    // resets it to the level set via the environment.
+    pub fn set_level(&self, module: &str, level: Option<LevelFilter>) {
+        if let Ok(mut filters) = self.filters.write() {
+            match level {
+                Some(level) => filters.overrides.insert(module.to_string(), level),
+                None => filters.overrides.remove(module),
+            };
+            filters.filter = build_filter(&self.base, &filters.overrides);
+            log::set_max_level(filters.filter.filter());
+        }
+    }
+}
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        match self.filters.read() {
+            Ok(filters) => filters.filter.enabled(metadata),
+            Err(_) => false,
+        }
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        // Records logged while answering an API request carry its id.
+        match trace::request_id() {
+            Some(request_id) => self.inner.log(
+                &Record::builder()
+                    .metadata(record.metadata().clone())
+                    .args(format_args!("[{}] {}", request_id, record.args()))
+                    .module_path(record.module_path())
+                    .file(record.file())
+                    .line(record.line())
+                    .build(),
+            ),
+            None => self.inner.log(record),
+        }
+    }
+
+    fn flush(&self) {
+        self.inner.flush();
+    }
+}
+
+// Installs the logger. The returned handle stays valid for the lifetime of
+// the process.
+pub fn init() -> &'static Logger {
+    let base = std::env::var(DEFAULT_FILTER_ENV).unwrap_or_else(|_| DEFAULT_FILTER.to_string());
+    // env_logger itself lets all records pass.
+    let inner = env_logger::Builder::from_env(Env::new().write_style(DEFAULT_WRITE_STYLE_ENV))
+        .filter_level(LevelFilter::Trace)
+        .build();
+    let filter = build_filter(&base, &BTreeMap::new());
+    let max_level = filter.filter();
+    let logger: &'static Logger = Box::leak(Box::new(Logger {
+        inner,
+        base,
+        filters: RwLock::new(Filters {
+            overrides: BTreeMap::new(),
+            filter,
+        }),
+    }));
+    if log::set_logger(logger).is_ok() {
+        log::set_max_level(max_level);
+    }
+    logger
+}
+
+pub fn parse_level(level: &str) -> Option<LevelFilter> {
+    LevelFilter::from_str(level).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_build_filter() {
+        let mut overrides = BTreeMap::new();
+        let filter = build_filter("info", &overrides);
+        assert_eq!(filter.filter(), LevelFilter::Info);
+
+        overrides.insert("fork_observer::node".to_string(), LevelFilter::Debug);
+        let filter = build_filter("info", &overrides);
+        assert_eq!(filter.filter(), LevelFilter::Debug);
+        let debug = |target| {
+            Metadata::builder()
+                .level(log::Level::Debug)
+                .target(target)
+                .build()
+        };
+        assert!(filter.enabled(&debug("fork_observer::node")));
+        assert!(!filter.enabled(&debug("fork_observer::jsonrpc")));
+    }
+}
@@ -3,47 +3,123 @@
 use bitcoin_pool_identification::{default_data, PoolIdentification};
 use bitcoincore_rpc::bitcoin::{BlockHash, Network};
 use bitcoincore_rpc::Error::JsonRpc;
-use env_logger::Env;
 use futures_util::StreamExt;
 use log::{debug, error, info, warn};
 use petgraph::graph::NodeIndex;
 use rusqlite::Connection;
 use std::cmp::max;
-use std::collections::{BTreeMap, BTreeSet, HashMap};
-use std::fmt;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::convert::Infallible;
+use std::net::SocketAddr;
 use std::sync::Arc;
-use tokio::sync::mpsc::unbounded_channel;
+use std::{fmt, fs};
+use tokio::sync::mpsc::{unbounded_channel, UnboundedSender};
 use tokio::sync::{broadcast, Mutex};
 use tokio::task;
-use tokio::time::{interval, interval_at, sleep, Duration, Instant};
+use tokio::time::{interval, interval_at, sleep, Duration, Instant, MissedTickBehavior};
 use tokio_stream::wrappers::BroadcastStream;
-use warp::Filter;
+use warp::hyper::service::{make_service_fn, service_fn};
+use warp::hyper::Server;
+use warp::{Filter, Reply};
 
+mod admin;
+mod anomaly;
 mod api;
+mod backfill;
+#[cfg(feature = "bench")]
+mod bench;
+mod blockmetadata;
+mod canary;
+mod chainparams;
+mod competing;
+mod computed;
 mod config;
+mod consensus;
+mod correlation;
+mod customrpc;
 mod db;
+mod domain;
+mod duplicates;
+mod electrum;
+mod elements;
 mod error;
+mod events;
+mod expanded;
+mod export;
+mod expr;
+mod headergap;
 mod headertree;
+mod histogram;
+mod history;
+mod http;
+mod incident;
+mod integrity;
+mod inventory;
 mod jsonrpc;
+mod latency;
+mod lifecycle;
+mod logging;
+mod metrics;
 mod node;
+mod notarization;
+mod notifications;
+mod p2p;
+mod paused;
+mod peerfetch;
+mod prediction;
+mod preflight;
+mod probes;
+mod pruning;
+mod quota;
+mod readiness;
+mod readthrough;
+mod rejection;
+mod reorg;
+mod replay;
+mod retention;
+mod retired;
+mod retry;
 mod rss;
+mod schema;
+mod sightings;
+mod socks;
+mod software;
+mod stale;
+mod storage;
+mod stratum;
+mod suppressed;
+mod templates;
+#[cfg(test)]
+mod testutil;
+mod timestamps;
+mod tiphistory;
+mod trace;
+mod treecompaction;
+mod treeexport;
 mod types;
+mod widget;
+mod zmq;
 
 use crate::config::BoxedSyncSendNode;
-use crate::error::{DbError, MainError};
+use crate::error::{ConfigError, DbError, ExportError, FetchError, ImportError, MainError};
+use crate::events::EventLog;
+use domain::{
+    ChainKind, ChainTip, ChainTipStatus, EventKind, EventSeverity, Fork, HeaderInfo, NodeInfo, Tree,
+};
 use types::{
-    Cache, Caches, ChainTip, Db, Fork, HeaderInfo, HeaderInfoJson, NetworkJson, NodeData,
-    NodeDataJson, Tree,
+    now_timestamp, Cache, Caches, Db, HeaderGapJson, HeaderInfoJson, NetworkJson, NodeData,
+    NodeDataJson, NodeResourcesJson, P2PProbeJson, RawChainTipsJson, TipInfoJson,
 };
 
 const VERSION_UNKNOWN: &str = "unknown";
 const MINER_UNKNOWN: &str = "Unknown";
 const MAX_FORKS_IN_CACHE: usize = 50;
+const MAX_SIGHTINGS_BODY_SIZE: u64 = 256 * 1024; // bytes
 
 async fn startup() -> Result<(config::Config, Db, Caches), MainError> {
     let config: config::Config = match config::load_config() {
         Ok(config) => {
-            info!("Configuration loaded");
+            info!("Configuration loaded (profile: {})", config.profile);
             config
         }
         Err(e) => {
@@ -82,26 +158,174 @@ async fn startup() -> Result<(config::Config, Db, Caches), MainError> {
     Ok((config, db, caches))
 }
 
-async fn populate_cache(network: &config::Network, tree: &Tree, caches: &Caches) {
-    let forks = headertree::recent_forks(&tree, MAX_FORKS_IN_CACHE).await;
-    let hij = headertree::strip_tree(&tree, network.max_interesting_heights, BTreeSet::new()).await;
+// Updates the cached header tree of the network and notifies clients about
+// the change. The tips are included in the interesting heights in addition
+// to the cached tips of the network's nodes.
+#[allow(clippy::too_many_arguments)]
+async fn refresh_header_tree(
+    network: &config::Network,
+    tree: &Tree,
+    caches: &Caches,
+    tips: &[ChainTip],
+    tipchanges_tx: &broadcast::Sender<u32>,
+    histograms: &histogram::BranchLengthHistograms,
+    expanded_forks: &expanded::ExpandedForks,
+    db: Db,
+) {
+    let mut tip_heights: BTreeSet<u64> = tip_heights(network.id, caches).await;
+    for tip in tips.iter() {
+        tip_heights.insert(tip.height);
+    }
+    let mut header_infos_json = headertree::strip_tree(
+        tree,
+        network.max_interesting_heights,
+        tip_heights,
+        expanded::heights(expanded_forks, network.id).await,
+        &network.chain_params,
+    )
+    .await;
+    if let Some(anomalies) = &network.timestamp_anomalies {
+        timestamps::flag_headers(anomalies, tree, &mut header_infos_json).await;
+    }
+    sightings::add_first_reports(db.clone(), network.id, &mut header_infos_json).await;
+    rejection::add_rejections(db, network.id, &mut header_infos_json).await;
+    let forks = headertree::recent_forks(tree, MAX_FORKS_IN_CACHE).await;
+    histogram::update(network.id, tree, histograms).await;
+
+    update_cache(
+        caches,
+        network.id,
+        CacheUpdate::HeaderTree {
+            header_infos_json,
+            forks,
+        },
+    )
+    .await;
+    refresh_node_statuses(network.id, tree, caches).await;
+
+    match tipchanges_tx.send(network.id) {
+        Ok(_) => debug!("Sent a tip_changed notification."),
+        Err(e) => {
+            debug!("Could not send tip_changed update into the channel: {}", e)
+        }
+    };
+}
+
+// Sets the status of the blocks of the cached header tree for each node of
+// the network. Called when the header tree or the tips of a node change.
+async fn refresh_node_statuses(network_id: u32, tree: &Tree, caches: &Caches) {
+    let (node_tips, hashes) = {
+        let caches_locked = caches.lock().await;
+        let cache = match caches_locked.get(&network_id) {
+            Some(cache) => cache,
+            None => return,
+        };
+        let node_tips: Vec<(u32, Vec<(BlockHash, ChainTipStatus)>)> = cache
+            .node_data
+            .iter()
+            .map(|(node_id, node)| {
+                (
+                    *node_id,
+                    node.tips
+                        .iter()
+                        .filter_map(|tip| {
+                            Some((
+                                tip.hash.parse::<BlockHash>().ok()?,
+                                ChainTipStatus::from(tip.status.clone()),
+                            ))
+                        })
+                        .collect(),
+                )
+            })
+            .collect();
+        let hashes: Vec<BlockHash> = cache
+            .header_infos_json
+            .iter()
+            .filter_map(|h| h.hash.parse().ok())
+            .collect();
+        (node_tips, hashes)
+    };
+    let statuses = headertree::node_statuses(tree, &node_tips, &hashes).await;
+
+    let mut caches_locked = caches.lock().await;
+    if let Some(cache) = caches_locked.get_mut(&network_id) {
+        for header_info in cache.header_infos_json.iter_mut() {
+            header_info.node_statuses = header_info
+                .hash
+                .parse::<BlockHash>()
+                .ok()
+                .and_then(|hash| statuses.get(&hash))
+                .map(|node_statuses| {
+                    node_statuses
+                        .iter()
+                        .map(|(node_id, status)| (*node_id, status.to_string()))
+                        .collect()
+                })
+                .unwrap_or_default();
+        }
+    }
+}
+
+async fn populate_cache(
+    network: &config::Network,
+    tree: &Tree,
+    caches: &Caches,
+    histograms: &histogram::BranchLengthHistograms,
+    db: Db,
+) {
+    let forks = headertree::recent_forks(tree, MAX_FORKS_IN_CACHE).await;
+    histogram::update(network.id, tree, histograms).await;
+    let mut hij = headertree::strip_tree(
+        tree,
+        network.max_interesting_heights,
+        BTreeSet::new(),
+        BTreeSet::new(),
+        &network.chain_params,
+    )
+    .await;
+    if let Some(anomalies) = &network.timestamp_anomalies {
+        timestamps::flag_headers(anomalies, tree, &mut hij).await;
+    }
+    sightings::add_first_reports(db.clone(), network.id, &mut hij).await;
+    rejection::add_rejections(db.clone(), network.id, &mut hij).await;
+    // The tips the nodes reported before the restart are shown until they
+    // are queried again.
+    let mut node_tips = match db::load_node_tips(db, network.id).await {
+        Ok(node_tips) => node_tips,
+        Err(e) => {
+            warn!(
+                "Could not load the last node tips of network '{}' from the database: {}",
+                network.name, e
+            );
+            HashMap::new()
+        }
+    };
+    let min_height = hij.iter().map(|h| h.height).min().unwrap_or_default();
+    let all_tips: Vec<ChainTip> = node_tips
+        .values()
+        .flat_map(|(_, tips)| tips.iter().cloned())
+        .collect();
+    let chainwork = headertree::tips_chainwork(tree, &all_tips).await;
     {
         let mut locked_caches = caches.lock().await;
         let node_data: NodeData = network
             .nodes
             .iter()
-            .cloned()
             .map(|n| {
-                (
-                    n.info().id,
-                    NodeDataJson::new(
-                        n.info(),
-                        &vec![],                     // no chain tips knows yet
-                        VERSION_UNKNOWN.to_string(), // is updated later, when we know it
-                        0,                           // timestamp of last block update
-                        true, // assume the node is reachable, if it isn't we set it to false after the first getchaintips RPC call anyway
-                    ),
-                )
+                let (last_changed, tips) = node_tips.remove(&n.info().id).unwrap_or_default();
+                let mut node_data = NodeDataJson::new(
+                    n.info(),
+                    &[],
+                    VERSION_UNKNOWN.to_string(), // is updated later, when we know it
+                    last_changed,                // timestamp of last block update
+                    true, // assume the node is reachable, if it isn't we set it to false after the first getchaintips RPC call anyway
+                );
+                node_data.tips = tips
+                    .iter()
+                    .filter(|t| t.height >= min_height)
+                    .map(|t| TipInfoJson::new(t, &chainwork))
+                    .collect();
+                (n.info().id, node_data)
             })
             .collect();
         locked_caches.insert(
@@ -111,34 +335,246 @@ async fn populate_cache(network: &config::Network, tree: &Tree, caches: &Caches)
                 node_data,
                 forks,
                 recent_miners: vec![],
+                raw_tips: BTreeMap::new(),
             },
         );
     }
+    refresh_node_statuses(network.id, tree, caches).await;
+}
+
+// Prints Prometheus alerting rules for the configured networks.
+fn alert_rules(args: &[String]) -> Result<(), MainError> {
+    let thresholds = metrics::parse_args(args)?;
+    let config = config::load_config()?;
+    print!("{}", metrics::alert_rules(&config.networks, &thresholds));
+    Ok(())
+}
+
+// Writes an export bundle to a directory instead of running the observer.
+async fn export(args: &[String]) -> Result<(), MainError> {
+    let args = export::parse_args(args, now_timestamp())?;
+    let (config, db, _) = startup().await?;
+    let network = config
+        .networks
+        .iter()
+        .find(|n| n.id == args.network)
+        .ok_or(ExportError::UnknownNetwork(args.network))?;
+    let files = export::create(db, network, args.from, args.to, vec![]).await?;
+    export::write_dir(&args.out, &files)?;
+    info!("Written the export bundle to {:?}", args.out);
+    Ok(())
+}
+
+// Writes the header tree of a network with the tips the nodes reported
+// last: as Graphviz DOT as the fork view would show it, or the whole tree as
+// GraphML or JSON graph.
+async fn export_tree(args: &[String]) -> Result<(), MainError> {
+    let args = treeexport::parse_args(args)?;
+    let (config, db, _) = startup().await?;
+    let network = config
+        .networks
+        .iter()
+        .find(|n| n.id == args.network)
+        .ok_or(ExportError::UnknownNetwork(args.network))?;
+    let tree_info =
+        db::load_treeinfos(db.clone(), network.header_store, network.chain_kind).await?;
+    let tree: Tree = Arc::new(Mutex::new(tree_info));
+    let node_tips = db::load_node_tips(db, network.id).await?;
+    let tip_heights: BTreeSet<u64> = node_tips
+        .values()
+        .flat_map(|(_, tips)| tips.iter().map(|tip| tip.height))
+        .collect();
+    // Nodes removed from the configuration have no name anymore.
+    let node_tips: treeexport::NodeTips = node_tips
+        .iter()
+        .map(|(id, (_, tips))| {
+            let name = network
+                .nodes
+                .iter()
+                .find(|n| n.info().id == *id)
+                .map(|n| n.info().name)
+                .unwrap_or_else(|| format!("node {}", id));
+            let tips = tips
+                .iter()
+                .map(|tip| TipInfoJson::new(tip, &HashMap::new()))
+                .collect();
+            (name, tips)
+        })
+        .collect();
+    let exported = match args.format {
+        treeexport::TreeFormat::Dot => {
+            let headers = headertree::strip_tree(
+                &tree,
+                network.max_interesting_heights,
+                tip_heights,
+                BTreeSet::new(),
+                &network.chain_params,
+            )
+            .await;
+            treeexport::dot(&network.name, &headers, &node_tips)
+        }
+        treeexport::TreeFormat::GraphMl => treeexport::graphml(&treeexport::graph(
+            &network.name,
+            &*tree.lock().await,
+            &node_tips,
+        )),
+        treeexport::TreeFormat::Json => serde_json::to_string_pretty(&treeexport::graph(
+            &network.name,
+            &*tree.lock().await,
+            &node_tips,
+        ))
+        .map_err(ExportError::from)?,
+    };
+    match args.out {
+        Some(out) => {
+            fs::write(&out, exported).map_err(ExportError::from)?;
+            info!("Written the header tree to {:?}", out);
+        }
+        None => print!("{}", exported),
+    }
+    Ok(())
+}
+
+// Merges the nodes of an inventory into the configuration file.
+fn import_nodes(args: &[String]) -> Result<(), MainError> {
+    let args = inventory::parse_args(args)?;
+    let config_path = config::config_file_path();
+    let config_string = fs::read_to_string(&config_path).map_err(ImportError::from)?;
+    let config = config::parse_config(&config_string)?;
+    let inventory_string = fs::read_to_string(&args.file).map_err(ImportError::from)?;
+    let nodes = inventory::parse(&args.file, &inventory_string)?;
+    let (merged, imported) = inventory::merge(&config_string, &config.networks, &nodes)?;
+    match args.out {
+        Some(out) => {
+            fs::write(&out, merged).map_err(ImportError::from)?;
+            info!("Written {} imported nodes to {:?}", imported, out);
+        }
+        None => {
+            print!("{}", merged);
+            info!("Imported {} nodes", imported);
+        }
+    }
+    Ok(())
+}
+
+// Prints the options of the configuration file with their defaults.
+fn print_config_schema() -> Result<(), MainError> {
+    let schema = serde_json::to_string_pretty(&schema::options()).expect("the schema to serialize");
+    println!("{}", schema);
+    Ok(())
+}
+
+// Prints the configuration file with the defaults filled in and the secrets
+// redacted.
+fn print_effective_config() -> Result<(), MainError> {
+    let config_string =
+        fs::read_to_string(config::config_file_path()).map_err(ConfigError::from)?;
+    let config = config::parse_config(&config_string)?;
+    print!("{}", schema::effective_config(&config_string, &config)?);
+    Ok(())
 }
 
 #[tokio::main]
 async fn main() -> Result<(), MainError> {
-    env_logger::Builder::from_env(Env::default().default_filter_or("info")).init();
+    let logger = logging::init();
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.first().map(String::as_str) == Some("export") {
+        return export(&args[1..]).await;
+    }
+    if args.first().map(String::as_str) == Some("export-tree") {
+        return export_tree(&args[1..]).await;
+    }
+    #[cfg(feature = "bench")]
+    if args.first().map(String::as_str) == Some("bench") {
+        return bench::run(&args[1..]);
+    }
+    if args.first().map(String::as_str) == Some("alert-rules") {
+        return alert_rules(&args[1..]);
+    }
+    if args.first().map(String::as_str) == Some("import-nodes") {
+        return import_nodes(&args[1..]);
+    }
+    if args.first().map(String::as_str) == Some("print-config-schema") {
+        return print_config_schema();
+    }
+    if args.first().map(String::as_str) == Some("print-effective-config") {
+        return print_effective_config();
+    }
     let (config, db, caches) = startup().await?;
 
     // A channel to notify about tip changes via ServerSentEvents to clients.
     let (tipchanges_tx, _) = broadcast::channel(16);
     let network_infos: Vec<NetworkJson> = config.networks.iter().map(NetworkJson::new).collect();
+    let chain_params: BTreeMap<u32, config::ChainParams> = config
+        .networks
+        .iter()
+        .map(|network| (network.id, network.chain_params.clone()))
+        .collect();
     let db_clone = db.clone();
+    let event_log = EventLog::new(
+        db.clone(),
+        config.notifications.clone(),
+        config.event_correlation.clone(),
+    );
+    let quarantine: quota::Quarantine = Arc::new(Mutex::new(BTreeMap::new()));
+    let block_templates: templates::BlockTemplates = Arc::new(Mutex::new(BTreeMap::new()));
+    let pool_tips: stratum::PoolTips = Arc::new(Mutex::new(BTreeMap::new()));
+    let history_chunks: history::HistoryChunks = Arc::new(Mutex::new(BTreeMap::new()));
+    let histograms: histogram::BranchLengthHistograms = Arc::new(Mutex::new(BTreeMap::new()));
+    let incidents: incident::ActiveIncidents = incident::load_active(db.clone()).await?;
+    let suppressed_tips: suppressed::SuppressedTips = suppressed::load(db.clone()).await?;
+    let expanded_forks: expanded::ExpandedForks = Arc::new(Mutex::new(BTreeMap::new()));
+    let paused_nodes: paused::PausedNodes = Arc::new(Mutex::new(BTreeSet::new()));
+    let prune_horizons: pruning::PruneHorizons = pruning::load(db.clone()).await?;
+    let compaction_horizons: treecompaction::CompactionHorizons =
+        Arc::new(Mutex::new(BTreeMap::new()));
+    let readiness = readiness::Readiness::new(config.networks.iter().map(|n| n.nodes.len()).sum());
+
+    if !config.notifications.sinks.is_empty() {
+        task::spawn(notifications::deliver(
+            config.notifications.clone(),
+            db.clone(),
+        ));
+    }
+
+    task::spawn(retired::run(db.clone(), config.networks.clone()));
+    task::spawn(retention::run(
+        db.clone(),
+        config.tip_observation_retention,
+        config.profile == config::Profile::LowResource,
+    ));
+    if let Some(notarization) = config.notarization.clone() {
+        task::spawn(notarization::run(db.clone(), notarization));
+    }
+    task::spawn(events::flush_correlated(event_log.clone()));
+
+    if let Some(storage_monitoring) = config.storage_monitoring.clone() {
+        task::spawn(storage::monitor(
+            storage_monitoring,
+            config.database_path.clone(),
+            db.clone(),
+            config.tip_observation_retention,
+        ));
+    }
 
-    for network in config.networks.iter().cloned() {
+    // Networks on the same chain can share a header store. A store is
+    // loaded once by the network owning it, which also runs the pool
+    // identification for the store.
+    let mut trees: HashMap<u32, Tree> = HashMap::new();
+    let mut pool_id_txs: HashMap<u32, UnboundedSender<BlockHash>> = HashMap::new();
+    for network in config.networks.iter().filter(|n| n.header_store == n.id) {
         let network = network.clone();
         let (pool_id_tx, mut pool_id_rx) = unbounded_channel::<BlockHash>();
-
-        info!(
-            "network '{}' (id={}) has {} nodes",
-            network.name,
-            network.id,
-            network.nodes.len()
-        );
+        // All networks using this header store, including this network.
+        let store_networks: Vec<config::Network> = config
+            .networks
+            .iter()
+            .filter(|n| n.header_store == network.id)
+            .cloned()
+            .collect();
 
         let tree: Tree = Arc::new(Mutex::new(
-            match db::load_treeinfos(db_clone.clone(), network.id).await {
+            match db::load_treeinfos(db_clone.clone(), network.id, network.chain_kind).await {
                 Ok(tree) => tree,
                 Err(e) => {
                     error!(
@@ -149,184 +585,8 @@ async fn main() -> Result<(), MainError> {
                 }
             },
         ));
-
-        populate_cache(&network, &tree, &caches).await;
-
-        for node in network.nodes.iter().cloned() {
-            let network = network.clone();
-            // Spread query times equally apart to even out network/CPU load
-            let mut interval = interval_at(
-                Instant::now()
-                    + Duration::from_millis(
-                        (config.query_interval.as_millis() / network.nodes.len() as u128) as u64,
-                    )
-                    + Duration::from_secs((network.id % 10) as u64),
-                config.query_interval,
-            );
-            let db_write = db.clone();
-            let tree_clone = tree.clone();
-            let caches_clone = caches.clone();
-            let tipchanges_tx_cloned = tipchanges_tx.clone();
-            let pool_id_tx_clone = pool_id_tx.clone();
-
-            let mut last_tips: Vec<ChainTip> = vec![];
-            task::spawn(async move {
-                // Try to load the node version an update the cache with it.
-                update_cache(
-                    &caches_clone,
-                    network.id,
-                    CacheUpdate::NodeVersion {
-                        node_id: node.info().id,
-                        version: load_node_version(node.clone(), &network.name).await,
-                    },
-                )
-                .await;
-
-                loop {
-                    // We specifically wait at the beginning of the loop, as we
-                    // are using 'continue' on errors. If we would wait at the end,
-                    // we might skip the waiting.
-                    interval.tick().await;
-                    let tips = match node.tips().await {
-                        Ok(tips) => {
-                            if !is_node_reachable(&caches_clone, network.id, node.info().id).await {
-                                update_cache(
-                                    &caches_clone,
-                                    network.id,
-                                    CacheUpdate::NodeReachability {
-                                        node_id: node.info().id,
-                                        reachable: true,
-                                    },
-                                )
-                                .await;
-                            }
-                            tips
-                        }
-                        Err(e) => {
-                            error!(
-                                "Could not fetch chaintips from {} on network '{}' (id={}): {:?}",
-                                node.info(),
-                                network.name,
-                                network.id,
-                                e
-                            );
-                            if is_node_reachable(&caches_clone, network.id, node.info().id).await {
-                                update_cache(
-                                    &caches_clone,
-                                    network.id,
-                                    CacheUpdate::NodeReachability {
-                                        node_id: node.info().id,
-                                        reachable: false,
-                                    },
-                                )
-                                .await;
-                            }
-                            continue;
-                        }
-                    };
-
-                    if last_tips != tips {
-                        let (new_headers, miners_needed): (Vec<HeaderInfo>, Vec<BlockHash>) =
-                            match node
-                                .new_headers(&tips, &tree_clone, network.min_fork_height)
-                                .await
-                            {
-                                Ok(headers) => headers,
-                                Err(e) => {
-                                    error!(
-                                    "Could not fetch headers from {} on network '{}' (id={}): {}",
-                                    node.info(),
-                                    network.name,
-                                    network.id,
-                                    e
-                                );
-                                    continue;
-                                }
-                            };
-
-                        // Identify the miner of the new header(s)
-                        for hash in miners_needed.iter() {
-                            if let Err(e) = pool_id_tx_clone.send(*hash) {
-                                error!(
-                                    "Could not send a block hash into the pool identification channel: {}",
-                                    e
-                                );
-                            }
-                        }
-
-                        last_tips = tips.clone();
-                        let db_write = db_write.clone();
-                        // We want to avoid stripping the tree (strip_tree()) if it didn't change.
-                        // Keeping tracking of changes:
-                        let mut tree_changed = false;
-                        if !new_headers.is_empty() {
-                            tree_changed =
-                                insert_new_headers_into_tree(&tree_clone, &new_headers).await;
-
-                            match db::write_to_db(&new_headers, db_write, network.id).await {
-                                Ok(_) => info!(
-                                    "Written {} headers to database for network '{}' by node {}",
-                                    new_headers.len(),
-                                    network.name,
-                                    node.info()
-                                ),
-                                Err(e) => {
-                                    error!("Could not write new headers for network '{}' by node {} to database: {}", network.name, node.info(), e);
-                                    return MainError::Db(e);
-                                }
-                            }
-                        }
-
-                        // Update node tips in cache
-                        update_cache(
-                            &caches_clone,
-                            network.id,
-                            CacheUpdate::NodeTips {
-                                node_id: node.info().id,
-                                tips: tips.clone(),
-                            },
-                        )
-                        .await;
-
-                        if tree_changed {
-                            let mut tip_heights: BTreeSet<u64> =
-                                tip_heights(network.id, &caches_clone).await;
-                            for tip in tips.iter() {
-                                tip_heights.insert(tip.height);
-                            }
-                            let header_infos_json = headertree::strip_tree(
-                                &tree_clone,
-                                network.max_interesting_heights,
-                                tip_heights,
-                            )
-                            .await;
-                            let forks =
-                                headertree::recent_forks(&tree_clone, MAX_FORKS_IN_CACHE).await;
-
-                            update_cache(
-                                &caches_clone,
-                                network.id,
-                                CacheUpdate::HeaderTree {
-                                    header_infos_json,
-                                    forks,
-                                },
-                            )
-                            .await;
-
-                            match tipchanges_tx_cloned.clone().send(network.id) {
-                                Ok(_) => debug!("Sent a tip_changed notification."),
-                                Err(e) => {
-                                    debug!(
-                                        "Could not send tip_changed update into the channel: {}",
-                                        e
-                                    )
-                                }
-                            };
-                        }
-                    }
-                }
-            });
-        }
+        trees.insert(network.id, tree.clone());
+        pool_id_txs.insert(network.id, pool_id_tx.clone());
 
         // A one-shot thread trying to identify all unidentified miners. This
         // runs once after startup (with a 5 minutes delay to be sure nodes
@@ -352,7 +612,7 @@ async fn main() -> Result<(), MainError> {
                 .0
                 .raw_nodes()
                 .iter()
-                .filter(|node| node.weight.miner == "" || node.weight.miner == MINER_UNKNOWN)
+                .filter(|node| node.weight.miner.is_empty() || node.weight.miner == MINER_UNKNOWN)
                 .filter(|node| {
                     let h = node.weight.height;
                     interesting_heights.contains(&h)
@@ -362,7 +622,7 @@ async fn main() -> Result<(), MainError> {
                 })
                 .map(|node| node.weight.clone())
             {
-                if let Err(e) = pool_id_tx_clone.send(header_info.header.block_hash()) {
+                if let Err(e) = pool_id_tx_clone.send(header_info.block_hash()) {
                     error!(
                         "Could not send block hash into the pool identification channel: {}",
                         e
@@ -377,6 +637,8 @@ async fn main() -> Result<(), MainError> {
         let db_clone2 = db_clone.clone();
         let caches_clone = caches.clone();
         let network_clone = network.clone();
+        let event_log_clone = event_log.clone();
+        let paused_nodes_clone = paused_nodes.clone();
         task::spawn(async move {
             let pool_identification_network = match network.pool_identification.network {
                 Some(ref network) => network.to_network(),
@@ -390,7 +652,9 @@ async fn main() -> Result<(), MainError> {
                 buffer.clear();
                 pool_id_rx.recv_many(&mut buffer, limit).await;
                 for hash in buffer.iter() {
-                    if !network_clone.pool_identification.enable {
+                    if !network_clone.pool_identification.enable
+                        && network_clone.own_mining.is_none()
+                    {
                         continue;
                     }
 
@@ -411,15 +675,46 @@ async fn main() -> Result<(), MainError> {
                     };
 
                     // skip miner identification if we previously identified a miner
-                    if !(header_info.miner == MINER_UNKNOWN.to_string() || header_info.miner == "")
-                    {
+                    if !(header_info.miner == MINER_UNKNOWN || header_info.miner.is_empty()) {
                         continue;
                     }
 
                     let mut miner = MINER_UNKNOWN.to_string();
-                    for node in network_clone.nodes.iter().cloned() {
-                        match node.coinbase(&header_info.header.block_hash()).await {
+                    let mut own = false;
+                    let paused_now = paused_nodes_clone.lock().await.clone();
+                    let mut nodes: Vec<(u32, &BoxedSyncSendNode)> = store_networks
+                        .iter()
+                        .flat_map(|n| {
+                            let paused_now = &paused_now;
+                            n.nodes
+                                .iter()
+                                .filter(move |node| !paused_now.contains(&(n.id, node.info().id)))
+                                .map(move |node| (n.id, node))
+                        })
+                        .collect();
+                    // The designated node is asked first, the others only
+                    // for the blocks it doesn't know.
+                    if let Some(designated) = network_clone.pool_identification.node {
+                        nodes.sort_by_key(|(network_id, node)| {
+                            !(*network_id == network_clone.id && node.info().id == designated)
+                        });
+                    }
+                    for (_, node) in nodes {
+                        match integrity::coinbase(
+                            &network_clone,
+                            node.as_ref(),
+                            &header_info.header,
+                            &event_log_clone,
+                        )
+                        .await
+                        {
                             Ok(coinbase) => {
+                                if let Some(ref own_mining) = network_clone.own_mining {
+                                    own = own_mining.matches(&coinbase);
+                                }
+                                if !network_clone.pool_identification.enable {
+                                    break;
+                                }
                                 miner = match coinbase.identify_pool(
                                     pool_identification_network,
                                     &pool_identification_data,
@@ -428,59 +723,1180 @@ async fn main() -> Result<(), MainError> {
                                     None => MINER_UNKNOWN.to_string(),
                                 };
                             }
-                            Err(e) => {
-                                warn!(
-                                    "Could not get coinbase for block {} from node {}: {}",
-                                    header_info.header.block_hash().to_string(),
-                                    node.info().name,
-                                    e
-                                );
+                            Err(e) => {
+                                warn!(
+                                    "Could not get coinbase for block {} from node {}: {}",
+                                    header_info.block_hash().to_string(),
+                                    node.info().name,
+                                    e
+                                );
+                            }
+                        }
+                        if miner != MINER_UNKNOWN {
+                            info!(
+                                "Updated miner for block {} from node {}: {}",
+                                header_info.height,
+                                node.info().name,
+                                miner
+                            );
+                            break;
+                        }
+                    }
+                    header_info.update_miner(miner, own);
+
+                    // update in-memory graph, the tree might have been pruned
+                    // in the meantime
+                    {
+                        let mut tree_locked = tree_clone.lock().await;
+                        match tree_locked.1.get(hash).copied() {
+                            Some(idx) => tree_locked.0[idx] = header_info.clone(),
+                            None => continue,
+                        }
+                    }
+                    // write to db
+                    if let Err(e) = db::update_miner(
+                        db_clone2.clone(),
+                        &header_info.block_hash(),
+                        header_info.miner.clone(),
+                        header_info.own,
+                    )
+                    .await
+                    {
+                        warn!(
+                            "Could not update miner to {} for block {}: {}",
+                            header_info.miner.clone(),
+                            &header_info.block_hash(),
+                            e
+                        );
+                    }
+                    // update the cache of all networks using this header store
+                    for store_network in store_networks.iter() {
+                        update_cache(
+                            &caches_clone,
+                            store_network.id,
+                            CacheUpdate::HeaderMiner {
+                                header_info: header_info.clone(),
+                            },
+                        )
+                        .await;
+                    }
+                }
+            }
+        });
+    }
+
+    for network in config.networks.iter() {
+        let network = network.clone();
+        let tree: Tree = trees
+            .get(&network.header_store)
+            .expect("the header store should be loaded")
+            .clone();
+        let pool_id_tx = pool_id_txs
+            .get(&network.header_store)
+            .expect("the header store should have a pool identification channel")
+            .clone();
+        // Other networks sharing the header store with this network. Their
+        // cached header tree needs an update when this network's nodes add
+        // headers to the store.
+        let sibling_networks: Vec<config::Network> = config
+            .networks
+            .iter()
+            .filter(|n| n.header_store == network.header_store && n.id != network.id)
+            .cloned()
+            .collect();
+
+        info!(
+            "network '{}' (id={}) has {} nodes",
+            network.name,
+            network.id,
+            network.nodes.len()
+        );
+        if network.header_store != network.id {
+            info!(
+                "network '{}' (id={}) shares the headers of network id={}",
+                network.name, network.id, network.header_store
+            );
+        }
+
+        populate_cache(&network, &tree, &caches, &histograms, db.clone()).await;
+
+        if let Some(p2p_probes) = config.p2p_probes.clone() {
+            for node in network.nodes.iter() {
+                let node = node.clone();
+                let caches_clone = caches.clone();
+                let network = network.clone();
+                let p2p_probes = p2p_probes.clone();
+                task::spawn(async move {
+                    let mut interval = interval(p2p_probes.check_interval);
+                    let mut reachable: BTreeSet<String> = BTreeSet::new();
+                    loop {
+                        interval.tick().await;
+                        let probes = probes::probe_node(&node, &p2p_probes).await;
+                        for probe in probes.iter() {
+                            if probe.reachable {
+                                reachable.insert(probe.address.clone());
+                            } else if reachable.remove(&probe.address) {
+                                warn!(
+                                    "The P2P address {} of node {} on network '{}' became unreachable: {}",
+                                    probe.address,
+                                    node.info(),
+                                    network.name,
+                                    probe.error.as_deref().unwrap_or_default()
+                                );
+                            }
+                        }
+                        update_cache(
+                            &caches_clone,
+                            network.id,
+                            CacheUpdate::NodeP2PReachability {
+                                node_id: node.info().id,
+                                probes,
+                            },
+                        )
+                        .await;
+                    }
+                });
+            }
+        }
+
+        if let Some(header_gap) = network.header_gap.clone() {
+            for node in network.nodes.iter() {
+                let node = node.clone();
+                let caches_clone = caches.clone();
+                let network = network.clone();
+                let header_gap = header_gap.clone();
+                let event_log = event_log.clone();
+                let query_interval = config.query_interval;
+                let paused_nodes = paused_nodes.clone();
+                task::spawn(async move {
+                    let mut interval = interval(query_interval);
+                    let mut tracker = headergap::GapTracker::default();
+                    loop {
+                        interval.tick().await;
+                        if paused::is_paused(&paused_nodes, network.id, node.info().id).await {
+                            continue;
+                        }
+                        let (headers, blocks) = match node.header_and_block_heights().await {
+                            Ok(Some(heights)) => heights,
+                            // The backend doesn't know its headers and blocks.
+                            Ok(None) => return,
+                            Err(e) => {
+                                warn!(
+                                    "Could not fetch the header and block heights of {} on network '{}': {}",
+                                    node.info(),
+                                    network.name,
+                                    e
+                                );
+                                continue;
+                            }
+                        };
+                        let (json, change) =
+                            tracker.update(headers, blocks, &header_gap, now_timestamp());
+                        match change {
+                            headergap::GapChange::Stalled if !node.info().shadow => {
+                                event_log
+                                    .emit(
+                                        network.id,
+                                        EventKind::NodeStalled,
+                                        EventSeverity::Warning,
+                                        format!("{} stalled on {}", node.info().name, network.name),
+                                        format!(
+                                            "Node {} on {} knows of headers up to height {}, but its validated tip has been more than {} blocks behind for {} minutes and is at height {}. The node might be stalled or unable to download the blocks.",
+                                            node.info().name,
+                                            network.name,
+                                            headers,
+                                            header_gap.max_gap,
+                                            header_gap.alert_after.as_secs() / 60,
+                                            blocks,
+                                        ),
+                                    )
+                                    .await;
+                            }
+                            headergap::GapChange::Recovered => info!(
+                                "The validated tip of {} on network '{}' caught up with its headers",
+                                node.info(),
+                                network.name
+                            ),
+                            _ => (),
+                        }
+                        update_cache(
+                            &caches_clone,
+                            network.id,
+                            CacheUpdate::NodeHeaderGap {
+                                node_id: node.info().id,
+                                header_gap: json,
+                            },
+                        )
+                        .await;
+                    }
+                });
+            }
+        }
+
+        for node in network.nodes.iter() {
+            let node = node.clone();
+            let caches_clone = caches.clone();
+            let network = network.clone();
+            let query_interval = config.query_interval;
+            let paused_nodes = paused_nodes.clone();
+            task::spawn(async move {
+                let mut interval = interval(query_interval);
+                let mut last_uptime: Option<u64> = None;
+                loop {
+                    interval.tick().await;
+                    if paused::is_paused(&paused_nodes, network.id, node.info().id).await {
+                        continue;
+                    }
+                    let resources = match node.resources().await {
+                        Ok(Some(resources)) => resources,
+                        // The resource telemetry is disabled.
+                        Ok(None) => return,
+                        Err(e) => {
+                            warn!(
+                                "Could not fetch the resources of {} on network '{}': {}",
+                                node.info(),
+                                network.name,
+                                e
+                            );
+                            continue;
+                        }
+                    };
+                    if last_uptime.is_some_and(|uptime| resources.uptime < uptime) {
+                        info!(
+                            "{} on network '{}' restarted {}s ago",
+                            node.info(),
+                            network.name,
+                            resources.uptime
+                        );
+                    }
+                    last_uptime = Some(resources.uptime);
+                    update_cache(
+                        &caches_clone,
+                        network.id,
+                        CacheUpdate::NodeResources {
+                            node_id: node.info().id,
+                            resources: NodeResourcesJson::new(&resources, now_timestamp()),
+                        },
+                    )
+                    .await;
+                }
+            });
+        }
+
+        task::spawn(consensus::run(
+            network.id,
+            network.name.clone(),
+            config.query_interval,
+            caches.clone(),
+            event_log.clone(),
+        ));
+
+        if let Some(template_comparison) = network.template_comparison.clone() {
+            task::spawn(templates::run(
+                network.clone(),
+                template_comparison,
+                config.query_interval,
+                block_templates.clone(),
+                event_log.clone(),
+                paused_nodes.clone(),
+            ));
+        }
+
+        if !network.stratum_pools.is_empty() {
+            stratum::spawn(
+                network.id,
+                network.name.clone(),
+                network.stratum_pools.clone(),
+                pool_tips.clone(),
+            )
+            .await;
+        }
+
+        // The active tip height up to which halving and difficulty
+        // adjustment milestones were checked.
+        let milestone_height: Arc<Mutex<Option<u64>>> = Arc::new(Mutex::new(None));
+        // The active tip height up to which the block intervals were checked.
+        let interval_height: Arc<Mutex<Option<u64>>> = Arc::new(Mutex::new(None));
+        let reported_stale_blocks: Arc<Mutex<Option<BTreeSet<BlockHash>>>> =
+            Arc::new(Mutex::new(None));
+        // Own blocks are identified with the own_mining of the network
+        // owning the header store.
+        let own_mining: Option<config::OwnMining> = config
+            .networks
+            .iter()
+            .find(|n| n.id == network.header_store)
+            .and_then(|n| n.own_mining.clone());
+
+        for node in network.nodes.clone() {
+            let network = network.clone();
+            // Spread query times equally apart to even out network/CPU load
+            let mut interval = interval_at(
+                Instant::now()
+                    + Duration::from_millis(
+                        (config.query_interval.as_millis() / network.nodes.len() as u128) as u64,
+                    )
+                    + Duration::from_secs((network.id % 10) as u64),
+                config.query_interval,
+            );
+            let db_write = db.clone();
+            let tree_clone = tree.clone();
+            let caches_clone = caches.clone();
+            let tipchanges_tx_cloned = tipchanges_tx.clone();
+            let histograms_clone = histograms.clone();
+            let prune_horizons_clone = prune_horizons.clone();
+            let compaction_horizons_clone = compaction_horizons.clone();
+            let expanded_forks_clone = expanded_forks.clone();
+            let pool_id_tx_clone = pool_id_tx.clone();
+            let milestone_height_clone = milestone_height.clone();
+            let interval_height_clone = interval_height.clone();
+            let reported_stale_blocks_clone = reported_stale_blocks.clone();
+            let own_mining = own_mining.clone();
+            let event_log_clone = event_log.clone();
+            let sibling_networks = sibling_networks.clone();
+
+            let mut last_tips: Vec<ChainTip> = vec![];
+            let mut flagged_heights: HashSet<(BlockHash, u64)> = HashSet::new();
+            // With backfill_blocks, set on the first query.
+            let mut start_height: Option<u64> = None;
+            let full_query_interval = config.full_query_interval;
+            let mut last_full_query: Option<Instant> = None;
+            let mut last_address: Option<SocketAddr> = None;
+            let mut node_quota: Option<quota::NodeQuota> = network
+                .tree_quota
+                .clone()
+                .map(|tree_quota| quota::NodeQuota::new(tree_quota, Instant::now()));
+            let quarantine_clone = quarantine.clone();
+            let incidents_clone = incidents.clone();
+            let suppressed_tips_clone = suppressed_tips.clone();
+            let readiness_clone = readiness.clone();
+            let paused_nodes_clone = paused_nodes.clone();
+            let mut primed = false;
+            // Without skipping, the ticks missed during an incident would
+            // fire at once afterwards.
+            interval.set_missed_tick_behavior(MissedTickBehavior::Skip);
+            task::spawn(async move {
+                // Try to load the node version an update the cache with it.
+                update_cache(
+                    &caches_clone,
+                    network.id,
+                    CacheUpdate::NodeVersion {
+                        node_id: node.info().id,
+                        version: load_node_version(node.clone(), &network.name).await,
+                    },
+                )
+                .await;
+
+                let block_notify = node.block_notifications();
+                loop {
+                    // We specifically wait at the beginning of the loop, as we
+                    // are using 'continue' on errors. If we would wait at the end,
+                    // we might skip the waiting. The node is primed right
+                    // after loading its version.
+                    if primed {
+                        if incident::active(&incidents_clone, network.id)
+                            .await
+                            .is_some()
+                        {
+                            sleep(incident::INCIDENT_QUERY_INTERVAL).await;
+                        } else if let Some(ref block_notify) = block_notify {
+                            tokio::select! {
+                                _ = interval.tick() => (),
+                                _ = block_notify.notified() => debug!(
+                                    "Node {} on network '{}' notified about a new block",
+                                    node.info(),
+                                    network.name
+                                ),
+                            }
+                        } else {
+                            interval.tick().await;
+                        }
+                    }
+                    // Paused nodes aren't queried at all. The node is
+                    // always primed first, so the loop waits for the
+                    // interval in between.
+                    if primed
+                        && paused::is_paused(&paused_nodes_clone, network.id, node.info().id).await
+                    {
+                        continue;
+                    }
+                    // Re-resolve the node's host, as nodes behind DNS-based
+                    // failover might have moved.
+                    if let Some(host) = node.host() {
+                        match node::resolve_rpc_address(host.clone()).await {
+                            Ok(address) if Some(address) != last_address => {
+                                if let Some(old_address) = last_address {
+                                    info!(
+                                        "Node {} on network '{}' moved from {} to {}",
+                                        node.info(),
+                                        network.name,
+                                        old_address,
+                                        address
+                                    );
+                                }
+                                last_address = Some(address);
+                                update_cache(
+                                    &caches_clone,
+                                    network.id,
+                                    CacheUpdate::NodeAddress {
+                                        node_id: node.info().id,
+                                        address: address.to_string(),
+                                    },
+                                )
+                                .await;
+                            }
+                            Ok(_) => (),
+                            Err(e) => warn!(
+                                "Could not resolve the address {} of {} on network '{}': {}",
+                                host,
+                                node.info(),
+                                network.name,
+                                e
+                            ),
+                        }
+                    }
+                    // Nodes with REST enabled use it once it's available
+                    // and fall back to RPC otherwise.
+                    node.detect_rest().await;
+                    // Skip the chain tips if the node's best block didn't
+                    // change. Forks not changing the best block only show
+                    // up with the next full query.
+                    if let (Some(full_query_interval), Some(last_full_query), Some(active_tip)) = (
+                        full_query_interval,
+                        last_full_query,
+                        last_tips
+                            .iter()
+                            .find(|tip| tip.status == ChainTipStatus::Active),
+                    ) {
+                        if last_full_query.elapsed() < full_query_interval {
+                            match node.best_block_hash().await {
+                                Ok(Some(hash)) if hash.to_string() == active_tip.hash => {
+                                    debug!(
+                                        "The best block of node {} on network '{}' didn't change",
+                                        node.info(),
+                                        network.name
+                                    );
+                                    continue;
+                                }
+                                Ok(_) => (),
+                                Err(e) => debug!(
+                                    "Could not fetch the best block hash from {} on network '{}': {}",
+                                    node.info(),
+                                    network.name,
+                                    e
+                                ),
+                            }
+                        }
+                    }
+                    let tips_result =
+                        retry::retry(&node.info(), "chaintips", || node.tips_raw()).await;
+                    if tips_result.is_ok() {
+                        last_full_query = Some(Instant::now());
+                    }
+                    if !primed {
+                        readiness_clone.node_primed(network.id, node.info().id);
+                        primed = true;
+                    }
+                    let tips = match tips_result {
+                        Ok((tips, raw_tips)) => {
+                            incident::record(
+                                &incidents_clone,
+                                db_write.clone(),
+                                network.id,
+                                node.info().id,
+                                "chaintips",
+                                raw_tips.to_string(),
+                            )
+                            .await;
+                            update_cache(
+                                &caches_clone,
+                                network.id,
+                                CacheUpdate::NodeRawTips {
+                                    node_id: node.info().id,
+                                    raw_tips,
+                                },
+                            )
+                            .await;
+                            if !is_node_reachable(&caches_clone, network.id, node.info().id).await {
+                                update_cache(
+                                    &caches_clone,
+                                    network.id,
+                                    CacheUpdate::NodeReachability {
+                                        node_id: node.info().id,
+                                        reachable: true,
+                                    },
+                                )
+                                .await;
+                                emit_reachability_event(
+                                    &event_log_clone,
+                                    &network,
+                                    &node.info(),
+                                    None,
+                                )
+                                .await;
+                            }
+                            tips
+                        }
+                        Err(e) => {
+                            error!(
+                                "Could not fetch chaintips from {} on network '{}' (id={}): {:?}",
+                                node.info(),
+                                network.name,
+                                network.id,
+                                e
+                            );
+                            if is_node_reachable(&caches_clone, network.id, node.info().id).await {
+                                update_cache(
+                                    &caches_clone,
+                                    network.id,
+                                    CacheUpdate::NodeReachability {
+                                        node_id: node.info().id,
+                                        reachable: false,
+                                    },
+                                )
+                                .await;
+                                emit_reachability_event(
+                                    &event_log_clone,
+                                    &network,
+                                    &node.info(),
+                                    Some(&e),
+                                )
+                                .await;
+                            }
+                            continue;
+                        }
+                    };
+                    let tips = suppressed::filter(
+                        &suppressed_tips_clone,
+                        network.id,
+                        node.info().id,
+                        tips,
+                    )
+                    .await;
+
+                    // Quarantined headers are admitted once the node's quota
+                    // resets, even if its tips didn't change.
+                    let tips_changed = last_tips != tips;
+                    let retry_quarantined = node_quota
+                        .as_ref()
+                        .is_some_and(|node_quota| node_quota.retry_due(Instant::now()));
+                    if tips_changed || retry_quarantined {
+                        if let (None, Some(backfill_blocks)) =
+                            (start_height, network.backfill_blocks)
+                        {
+                            start_height = backfill::first_start_height(
+                                network.min_fork_height,
+                                backfill_blocks,
+                                &tree_clone,
+                                &tips,
+                            )
+                            .await;
+                        }
+                        // The pruned branches aren't fetched again.
+                        let fetch_tips =
+                            pruning::unpruned(&prune_horizons_clone, network.header_store, &tips)
+                                .await;
+                        let fetch_tips = treecompaction::uncompacted(
+                            &compaction_horizons_clone,
+                            network.header_store,
+                            &fetch_tips,
+                        )
+                        .await;
+                        let (mut new_headers, mut miners_needed): (
+                            Vec<HeaderInfo>,
+                            Vec<BlockHash>,
+                        ) = match fetch_tips {
+                            Some(fetch_tips) => match retry::retry(&node.info(), "headers", || {
+                                node.new_headers(
+                                    &fetch_tips,
+                                    &tree_clone,
+                                    start_height.unwrap_or(network.min_fork_height),
+                                )
+                            })
+                            .await
+                            {
+                                Ok(headers) => headers,
+                                Err(e) => {
+                                    error!(
+                                        "Could not fetch headers from {} on network '{}' (id={}): {}",
+                                        node.info(),
+                                        network.name,
+                                        network.id,
+                                        e
+                                    );
+                                    continue;
+                                }
+                            },
+                            // The node is behind the collapsed headers.
+                            None => (vec![], vec![]),
+                        };
+
+                        if let Some(ref mut node_quota) = node_quota {
+                            let admission = {
+                                let tree_locked = tree_clone.lock().await;
+                                node_quota.admit(&tree_locked, &new_headers, Instant::now())
+                            };
+                            if admission.newly_exceeded {
+                                event_log_clone
+                                    .emit(
+                                        network.id,
+                                        EventKind::TreeQuotaExceeded,
+                                        EventSeverity::Warning,
+                                        format!(
+                                            "Node {} on {} exceeded its tree quota",
+                                            node.info().name,
+                                            network.name
+                                        ),
+                                        format!(
+                                            "Node {} introduced more new branches or headers into the header tree of {} than allowed per hour. Further headers from the node are quarantined.",
+                                            node.info().name,
+                                            network.name
+                                        ),
+                                    )
+                                    .await;
+                            }
+                            let quarantined_hashes: Vec<BlockHash> = admission
+                                .quarantined
+                                .iter()
+                                .map(|h| h.block_hash())
+                                .collect();
+                            miners_needed.retain(|hash| !quarantined_hashes.contains(hash));
+                            for hash in admission.released.iter() {
+                                if !miners_needed.contains(hash) {
+                                    miners_needed.push(*hash);
+                                }
+                            }
+                            if !admission.quarantined.is_empty() {
+                                warn!(
+                                    "Quarantined {} headers from {} on network '{}'",
+                                    admission.quarantined.len(),
+                                    node.info(),
+                                    network.name
+                                );
+                            }
+                            quota::set_quarantined(
+                                &quarantine_clone,
+                                network.id,
+                                node.info().id,
+                                admission.quarantined,
+                            )
+                            .await;
+                            new_headers = admission.admitted;
+                        }
+
+                        // Enrich the new blocks with their coinbase during
+                        // incidents.
+                        if incident::active(&incidents_clone, network.id)
+                            .await
+                            .is_some()
+                        {
+                            for header_info in new_headers.iter() {
+                                incident::record(
+                                    &incidents_clone,
+                                    db_write.clone(),
+                                    network.id,
+                                    node.info().id,
+                                    "block",
+                                    incident::block_recording(node.as_ref(), header_info).await,
+                                )
+                                .await;
+                            }
+                        }
+
+                        // Identify the miner of the new header(s)
+                        for hash in miners_needed.iter() {
+                            if let Err(e) = pool_id_tx_clone.send(*hash) {
+                                error!(
+                                    "Could not send a block hash into the pool identification channel: {}",
+                                    e
+                                );
+                            }
+                        }
+
+                        let last_active_tip = last_tips
+                            .iter()
+                            .find(|t| t.status == ChainTipStatus::Active)
+                            .cloned();
+                        tiphistory::record(
+                            network.id,
+                            &network.name,
+                            &node.info(),
+                            &last_tips,
+                            &tips,
+                            now_timestamp(),
+                            db_write.clone(),
+                        )
+                        .await;
+                        last_tips = tips.clone();
+                        if let Err(e) = db::write_node_tips(
+                            db_write.clone(),
+                            network.id,
+                            node.info().id,
+                            now_timestamp(),
+                            &tips,
+                        )
+                        .await
+                        {
+                            error!(
+                                "Could not write the tips of node {} on network '{}' to database: {}",
+                                node.info(),
+                                network.name,
+                                e
+                            );
+                        }
+                        // Shadow nodes don't contribute to the statistics.
+                        if tips_changed && !node.info().shadow {
+                            if let Err(e) = db::write_tip_observations(
+                                db_write.clone(),
+                                network.id,
+                                node.info().id,
+                                now_timestamp(),
+                                &tips,
+                            )
+                            .await
+                            {
+                                error!(
+                                    "Could not write tip observations of node {} on network '{}' to database: {}",
+                                    node.info(),
+                                    network.name,
+                                    e
+                                );
+                            }
+                        }
+                        // We want to avoid stripping the tree (strip_tree()) if it didn't change.
+                        // Keeping tracking of changes:
+                        let mut tree_changed = false;
+                        let mut inserted: Vec<HeaderInfo> = vec![];
+                        if !new_headers.is_empty() {
+                            inserted =
+                                headertree::insert_new_headers_into_tree(&tree_clone, &new_headers)
+                                    .await;
+                            tree_changed = !inserted.is_empty();
+                            // Shadow nodes are excluded from alerting.
+                            if !network.canaries.is_empty() && !node.info().shadow {
+                                canary::check_headers(
+                                    network.id,
+                                    &network.name,
+                                    &network.canaries,
+                                    &tree_clone,
+                                    &inserted,
+                                    &event_log_clone,
+                                )
+                                .await;
+                            }
+                            if let Some(anomalies) = network
+                                .timestamp_anomalies
+                                .as_ref()
+                                .filter(|_| !node.info().shadow)
+                            {
+                                timestamps::check_headers(
+                                    network.id,
+                                    &network.name,
+                                    anomalies,
+                                    &tree_clone,
+                                    &inserted,
+                                    &event_log_clone,
+                                )
+                                .await;
+                            }
+
+                            match db::write_to_db(
+                                &new_headers,
+                                db_write.clone(),
+                                network.header_store,
+                            )
+                            .await
+                            {
+                                Ok(_) => info!(
+                                    "Written {} headers to database for network '{}' by node {}",
+                                    new_headers.len(),
+                                    network.name,
+                                    node.info()
+                                ),
+                                Err(e) => {
+                                    error!("Could not write new headers for network '{}' by node {} to database: {}", network.name, node.info(), e);
+                                    return MainError::Db(e);
+                                }
+                            }
+                        }
+                        let hashes =
+                            sightings::first_reported_hashes(&node.info(), &new_headers, &tips);
+                        if !hashes.is_empty() {
+                            if let Err(e) = db::write_first_reports(
+                                db_write.clone(),
+                                network.id,
+                                node.info().id,
+                                now_timestamp(),
+                                &hashes,
+                            )
+                            .await
+                            {
+                                error!(
+                                    "Could not write the first reports of node {} on network '{}' to database: {}",
+                                    node.info(),
+                                    network.name,
+                                    e
+                                );
+                            }
+                        }
+                        duplicates::check(
+                            network.id,
+                            &network.name,
+                            &node.info(),
+                            &tree_clone,
+                            &inserted,
+                            &duplicates::reported_heights(&tips, &new_headers),
+                            &mut flagged_heights,
+                            &event_log_clone,
+                        )
+                        .await;
+
+                        // Update node tips in cache
+                        update_cache(
+                            &caches_clone,
+                            network.id,
+                            CacheUpdate::NodeTips {
+                                node_id: node.info().id,
+                                tips: tips.clone(),
+                                chainwork: headertree::tips_chainwork(&tree_clone, &tips).await,
+                            },
+                        )
+                        .await;
+                        // With a changed tree, the statuses are refreshed
+                        // together with it.
+                        if !tree_changed {
+                            refresh_node_statuses(network.id, &tree_clone, &caches_clone).await;
+                        }
+
+                        if let Some(active_tip) = tips
+                            .iter()
+                            .find(|t| t.status == ChainTipStatus::Active)
+                            .filter(|_| !node.info().shadow)
+                        {
+                            if let Some(ref last_active_tip) = last_active_tip {
+                                reorg::check_reorg(
+                                    network.id,
+                                    &network.name,
+                                    &node.info(),
+                                    &tree_clone,
+                                    last_active_tip,
+                                    active_tip,
+                                    now_timestamp(),
+                                    db_write.clone(),
+                                )
+                                .await;
+                            }
+                            // Elements chains have neither halvings nor
+                            // difficulty adjustments.
+                            if network.chain_kind == ChainKind::Bitcoin {
+                                chainparams::check_milestones(
+                                    network.id,
+                                    &network.name,
+                                    &network.chain_params,
+                                    &milestone_height_clone,
+                                    active_tip.height,
+                                    &event_log_clone,
+                                )
+                                .await;
+                            }
+                            stale::check_stale_blocks(
+                                network.id,
+                                &network.name,
+                                &own_mining,
+                                &tree_clone,
+                                &active_tip.block_hash(),
+                                &reported_stale_blocks_clone,
+                                &event_log_clone,
+                            )
+                            .await;
+                            if let Some(anomalies) = &network.block_interval_anomalies {
+                                anomaly::check_block_intervals(
+                                    network.id,
+                                    &network.name,
+                                    &network.chain_params,
+                                    anomalies,
+                                    &tree_clone,
+                                    &active_tip.block_hash(),
+                                    active_tip.height,
+                                    &interval_height_clone,
+                                    &event_log_clone,
+                                )
+                                .await;
+                            }
+                        }
+
+                        if tree_changed {
+                            refresh_header_tree(
+                                &network,
+                                &tree_clone,
+                                &caches_clone,
+                                &tips,
+                                &tipchanges_tx_cloned,
+                                &histograms_clone,
+                                &expanded_forks_clone,
+                                db_write.clone(),
+                            )
+                            .await;
+                            for sibling_network in sibling_networks.iter() {
+                                refresh_header_tree(
+                                    sibling_network,
+                                    &tree_clone,
+                                    &caches_clone,
+                                    &[],
+                                    &tipchanges_tx_cloned,
+                                    &histograms_clone,
+                                    &expanded_forks_clone,
+                                    db_write.clone(),
+                                )
+                                .await;
                             }
                         }
-                        if miner != MINER_UNKNOWN.to_string() {
-                            info!(
-                                "Updated miner for block {} from node {}: {}",
-                                header_info.height,
-                                node.info().name,
-                                miner
-                            );
-                            break;
+                    }
+                }
+            });
+        }
+    }
+
+    // Refreshes the cached header tree of a network on request, e.g. after
+    // an admin expanded a fork.
+    let (refresh_tx, mut refresh_rx) = unbounded_channel::<u32>();
+    {
+        let networks = config.networks.clone();
+        let trees = trees.clone();
+        let caches = caches.clone();
+        let tipchanges_tx = tipchanges_tx.clone();
+        let histograms = histograms.clone();
+        let expanded_forks = expanded_forks.clone();
+        let db = db.clone();
+        task::spawn(async move {
+            while let Some(network_id) = refresh_rx.recv().await {
+                let network = match networks.iter().find(|n| n.id == network_id) {
+                    Some(network) => network,
+                    None => continue,
+                };
+                if let Some(tree) = trees.get(&network.header_store) {
+                    refresh_header_tree(
+                        network,
+                        tree,
+                        &caches,
+                        &[],
+                        &tipchanges_tx,
+                        &histograms,
+                        &expanded_forks,
+                        db.clone(),
+                    )
+                    .await;
+                }
+            }
+        });
+    }
+
+    // Prunes the old stale branches of the header stores with fork pruning.
+    // The first pruning runs right away, usually before the nodes are first
+    // queried, so the branches pruned before a restart aren't fetched again.
+    for network in config.networks.iter().filter(|n| n.header_store == n.id) {
+        let fork_pruning = match network.fork_pruning.clone() {
+            Some(fork_pruning) => fork_pruning,
+            None => continue,
+        };
+        let header_store = network.id;
+        let store_network_ids: Vec<u32> = config
+            .networks
+            .iter()
+            .filter(|n| n.header_store == header_store)
+            .map(|n| n.id)
+            .collect();
+        let tree = trees
+            .get(&header_store)
+            .expect("the header store should be loaded")
+            .clone();
+        let caches = caches.clone();
+        let db = db.clone();
+        let prune_horizons = prune_horizons.clone();
+        let refresh_tx = refresh_tx.clone();
+        task::spawn(async move {
+            let mut interval = interval(pruning::PRUNE_INTERVAL);
+            loop {
+                interval.tick().await;
+                let active_tips = pruning::active_tips(&caches, &store_network_ids).await;
+                let consensus_height = pruning::consensus_height(&caches, &store_network_ids).await;
+                let pruned = pruning::prune(
+                    header_store,
+                    &fork_pruning,
+                    &tree,
+                    &active_tips,
+                    consensus_height,
+                    &prune_horizons,
+                    db.clone(),
+                )
+                .await;
+                if pruned > 0 {
+                    for network_id in store_network_ids.iter() {
+                        if let Err(e) = refresh_tx.send(*network_id) {
+                            error!("Could not request a header tree refresh: {}", e);
                         }
                     }
-                    header_info.update_miner(miner);
+                }
+            }
+        });
+    }
 
-                    // update in-memory graph
-                    {
-                        let mut tree_locked = tree_clone.lock().await;
-                        tree_locked.0[idx] = header_info.clone();
+    // Collapses the linear runs of headers of the header stores with tree
+    // compaction. Like the pruning, the first compaction runs right away
+    // with the tips restored from the database.
+    for network in config.networks.iter().filter(|n| n.header_store == n.id) {
+        let tree_compaction = match network.tree_compaction.clone() {
+            Some(tree_compaction) => tree_compaction,
+            None => continue,
+        };
+        let header_store = network.id;
+        let store_network_ids: Vec<u32> = config
+            .networks
+            .iter()
+            .filter(|n| n.header_store == header_store)
+            .map(|n| n.id)
+            .collect();
+        let tree = trees
+            .get(&header_store)
+            .expect("the header store should be loaded")
+            .clone();
+        let caches = caches.clone();
+        let compaction_horizons = compaction_horizons.clone();
+        let refresh_tx = refresh_tx.clone();
+        task::spawn(async move {
+            let mut interval = interval(treecompaction::COMPACTION_INTERVAL);
+            loop {
+                interval.tick().await;
+                let active_tips = pruning::active_tips(&caches, &store_network_ids).await;
+                let collapsed = treecompaction::compact(
+                    header_store,
+                    &tree_compaction,
+                    &tree,
+                    &active_tips,
+                    &compaction_horizons,
+                )
+                .await;
+                if collapsed > 0 {
+                    for network_id in store_network_ids.iter() {
+                        if let Err(e) = refresh_tx.send(*network_id) {
+                            error!("Could not request a header tree refresh: {}", e);
+                        }
                     }
-                    // write to db
-                    if let Err(e) = db::update_miner(
-                        db_clone2.clone(),
-                        &header_info.header.block_hash(),
-                        header_info.miner.clone(),
-                    )
-                    .await
-                    {
-                        warn!(
-                            "Could not update miner to {} for block {}: {}",
-                            header_info.miner.clone(),
-                            &header_info.header.block_hash(),
-                            e
-                        );
+                }
+            }
+        });
+    }
+
+    // Records the metadata of the blocks around the recent forks of the
+    // networks with block_metadata.
+    for network in config.networks.iter() {
+        let block_metadata = match network.block_metadata.clone() {
+            Some(block_metadata) => block_metadata,
+            None => continue,
+        };
+        let network = network.clone();
+        let tree = trees
+            .get(&network.header_store)
+            .expect("the header store should be loaded")
+            .clone();
+        let caches = caches.clone();
+        let db = db.clone();
+        let paused_nodes = paused_nodes.clone();
+        task::spawn(async move {
+            let mut interval = interval(blockmetadata::BLOCK_METADATA_INTERVAL);
+            loop {
+                interval.tick().await;
+                let forks = match caches.lock().await.get(&network.id) {
+                    Some(cache) => cache.forks.clone(),
+                    None => continue,
+                };
+                blockmetadata::record(
+                    &network,
+                    block_metadata.depth,
+                    &tree,
+                    &forks,
+                    db.clone(),
+                    &paused_nodes,
+                )
+                .await;
+            }
+        });
+    }
+
+    // Checks the blocks of the invalid tips the nodes of the Bitcoin
+    // networks report and records why they were rejected.
+    for network in config.networks.iter() {
+        if network.chain_kind != ChainKind::Bitcoin {
+            continue;
+        }
+        let network = network.clone();
+        let tree = trees
+            .get(&network.header_store)
+            .expect("the header store should be loaded")
+            .clone();
+        let caches = caches.clone();
+        let db = db.clone();
+        let paused_nodes = paused_nodes.clone();
+        let refresh_tx = refresh_tx.clone();
+        task::spawn(async move {
+            let mut interval = interval(rejection::REJECTION_CHECK_INTERVAL);
+            let mut checked: HashSet<(u32, BlockHash)> = HashSet::new();
+            loop {
+                interval.tick().await;
+                let recorded = rejection::record(
+                    &network,
+                    &tree,
+                    &caches,
+                    db.clone(),
+                    &paused_nodes,
+                    &mut checked,
+                )
+                .await;
+                if recorded > 0 {
+                    if let Err(e) = refresh_tx.send(network.id) {
+                        error!("Could not request a header tree refresh: {}", e);
                     }
-                    // update cache
-                    update_cache(
-                        &caches_clone,
-                        network.id,
-                        CacheUpdate::HeaderMiner { header_info },
-                    )
-                    .await;
                 }
             }
         });
     }
 
+    // Makes the nodes of the Bitcoin networks download the blocks of their
+    // headers-only tips from their peers.
+    for network in config.networks.iter() {
+        if network.chain_kind != ChainKind::Bitcoin {
+            continue;
+        }
+        let network = network.clone();
+        let tree = trees
+            .get(&network.header_store)
+            .expect("the header store should be loaded")
+            .clone();
+        let caches = caches.clone();
+        let db = db.clone();
+        let paused_nodes = paused_nodes.clone();
+        let pool_id_tx = pool_id_txs
+            .get(&network.header_store)
+            .expect("the header store should have a pool identification channel")
+            .clone();
+        task::spawn(async move {
+            let mut interval = interval(peerfetch::PEER_FETCH_INTERVAL);
+            let mut fetches = peerfetch::PeerFetches::default();
+            loop {
+                interval.tick().await;
+                peerfetch::fetch(
+                    &network,
+                    &tree,
+                    &caches,
+                    db.clone(),
+                    &paused_nodes,
+                    &pool_id_tx,
+                    &mut fetches,
+                )
+                .await;
+            }
+        });
+    }
+
     let www_dir = warp::get()
         .and(warp::path("static"))
         .and(warp::fs::dir(config.www_path.clone()));
@@ -492,15 +1908,256 @@ async fn main() -> Result<(), MainError> {
         .and(warp::fs::file(config.www_path.join("fullscreen.html")));
 
     let info_json = warp::get()
-        .and(warp::path!("api" / "info.json"))
+        .and(api::prefix())
+        .and(warp::path!("info.json"))
         .and(api::with_footer(config.footer_html.clone()))
         .and_then(api::info_response);
 
     let data_json = warp::get()
-        .and(warp::path!("api" / u32 / "data.json"))
+        .and(api::version())
+        .and(warp::path!(u32 / "data.json"))
+        .and(warp::query::<api::DataQuery>())
         .and(api::with_caches(caches.clone()))
+        .and(api::with_computed_fields(config.computed_fields.clone()))
         .and_then(api::data_response);
 
+    let raw_chaintips_json = warp::get()
+        .and(api::explicit_prefix())
+        .and(warp::path!("nodes" / u32 / "chaintips" / "raw"))
+        .and(warp::query::<api::NetworkQuery>())
+        .and(api::with_caches(caches.clone()))
+        .and_then(api::raw_chaintips_response);
+
+    let chain_params_json = warp::get()
+        .and(api::prefix())
+        .and(warp::path!(u32 / "chainparams.json"))
+        .and(api::with_caches(caches.clone()))
+        .and(api::with_chain_params(chain_params))
+        .and(api::with_networks(network_infos.clone()))
+        .and_then(api::chain_params_response);
+
+    let events_json = warp::get()
+        .and(api::version())
+        .and(warp::path!(u32 / "events.json"))
+        .and(warp::query::<api::LimitQuery>())
+        .and(api::with_db(db.clone()))
+        .and_then(api::events_response);
+
+    let reorgs_json = warp::get()
+        .and(api::explicit_prefix())
+        .and(warp::path!(u32 / "reorgs.json"))
+        .and(warp::query::<api::LimitQuery>())
+        .and(api::with_db(db.clone()))
+        .and_then(api::reorgs_response);
+
+    let tip_history_json = warp::get()
+        .and(api::explicit_prefix())
+        .and(warp::path!(u32 / "tip-history.json"))
+        .and(warp::query::<api::TipHistoryQuery>())
+        .and(api::with_db(db.clone()))
+        .and_then(api::tip_history_response);
+
+    let fork_latency_networks = config.networks.clone();
+    let fork_latency_trees = trees.clone();
+    let fork_latency_json = warp::get()
+        .and(api::explicit_prefix())
+        .and(warp::path!(u32 / "fork-latency.json"))
+        .and(warp::query::<latency::LatencyQuery>())
+        .and(api::with_caches(caches.clone()))
+        .and(api::with_db(db.clone()))
+        .and(warp::any().map(move || fork_latency_networks.clone()))
+        .and(warp::any().map(move || fork_latency_trees.clone()))
+        .and(api::with_networks(network_infos.clone()))
+        .and_then(latency::fork_latency_response);
+
+    let fork_lifecycle_networks = config.networks.clone();
+    let fork_lifecycle_trees = trees.clone();
+    let fork_lifecycle_json = warp::get()
+        .and(api::explicit_prefix())
+        .and(warp::path!(u32 / "fork-lifecycle.json"))
+        .and(api::with_caches(caches.clone()))
+        .and(api::with_db(db.clone()))
+        .and(warp::any().map(move || fork_lifecycle_networks.clone()))
+        .and(warp::any().map(move || fork_lifecycle_trees.clone()))
+        .and(api::with_networks(network_infos.clone()))
+        .and_then(lifecycle::fork_lifecycle_response);
+
+    let block_metadata_networks = config.networks.clone();
+    let block_metadata_trees = trees.clone();
+    let block_metadata_json = warp::get()
+        .and(api::explicit_prefix())
+        .and(warp::path!(u32 / "block-metadata.json"))
+        .and(api::with_caches(caches.clone()))
+        .and(api::with_db(db.clone()))
+        .and(warp::any().map(move || block_metadata_networks.clone()))
+        .and(warp::any().map(move || block_metadata_trees.clone()))
+        .and(api::with_networks(network_infos.clone()))
+        .and_then(blockmetadata::block_metadata_response);
+
+    let stale_rate_networks = config.networks.clone();
+    let stale_rate_trees = trees.clone();
+    let stale_rate_json = warp::get()
+        .and(api::explicit_prefix())
+        .and(warp::path!(u32 / "stale-rate.json"))
+        .and(api::with_caches(caches.clone()))
+        .and(warp::any().map(move || stale_rate_networks.clone()))
+        .and(warp::any().map(move || stale_rate_trees.clone()))
+        .and(api::with_networks(network_infos.clone()))
+        .and_then(stale::stale_rate_response);
+
+    let competing_blocks_json = warp::get()
+        .and(api::prefix())
+        .and(warp::path!(u32 / "competing-blocks.json"))
+        .and(warp::query::<api::WindowQuery>())
+        .and(api::with_caches(caches.clone()))
+        .and(api::with_db(db.clone()))
+        .and(api::with_networks(network_infos.clone()))
+        .and_then(api::competing_blocks_response);
+
+    let consensus_json = warp::get()
+        .and(api::explicit_prefix())
+        .and(warp::path!(u32 / "consensus.json"))
+        .and(api::with_caches(caches.clone()))
+        .and(api::with_networks(network_infos.clone()))
+        .and_then(consensus::consensus_response);
+
+    // Embedded into other sites, so the widget.json is served with CORS.
+    let widget_json = warp::get()
+        .and(api::explicit_prefix())
+        .and(warp::path!(u32 / "widget.json"))
+        .and(api::with_caches(caches.clone()))
+        .and(api::with_networks(network_infos.clone()))
+        .and_then(widget::widget_response)
+        .with(warp::cors().allow_any_origin().allow_method("GET"));
+
+    let fork_prediction_json = warp::get()
+        .and(api::explicit_prefix())
+        .and(warp::path!(u32 / "fork-prediction.json"))
+        .and(api::with_caches(caches.clone()))
+        .and(api::with_networks(network_infos.clone()))
+        .and_then(prediction::prediction_response);
+
+    let branch_lengths_json = warp::get()
+        .and(api::explicit_prefix())
+        .and(warp::path!(u32 / "branch-lengths.json"))
+        .and(warp::any().map(move || histograms.clone()))
+        .and(api::with_networks(network_infos.clone()))
+        .and_then(histogram::histogram_response);
+
+    let tree_dot = warp::get()
+        .and(api::explicit_prefix())
+        .and(warp::path!(u32 / "tree.dot"))
+        .and(api::with_caches(caches.clone()))
+        .and(api::with_networks(network_infos.clone()))
+        .and_then(treeexport::dot_response);
+
+    let tree_graphml_networks = config.networks.clone();
+    let tree_graphml_trees = trees.clone();
+    let tree_graphml = warp::get()
+        .and(api::explicit_prefix())
+        .and(warp::path!(u32 / "tree.graphml"))
+        .and(warp::any().map(move || tree_graphml_networks.clone()))
+        .and(warp::any().map(move || tree_graphml_trees.clone()))
+        .and(api::with_caches(caches.clone()))
+        .and(api::with_networks(network_infos.clone()))
+        .and_then(treeexport::graphml_response);
+
+    let tree_json_networks = config.networks.clone();
+    let tree_json_trees = trees.clone();
+    let tree_json = warp::get()
+        .and(api::explicit_prefix())
+        .and(warp::path!(u32 / "tree.json"))
+        .and(warp::any().map(move || tree_json_networks.clone()))
+        .and(warp::any().map(move || tree_json_trees.clone()))
+        .and(api::with_caches(caches.clone()))
+        .and(api::with_networks(network_infos.clone()))
+        .and_then(treeexport::json_graph_response);
+
+    let retired_nodes_json = warp::get()
+        .and(api::explicit_prefix())
+        .and(warp::path!(u32 / "retired-nodes.json"))
+        .and(api::with_db(db.clone()))
+        .and(api::with_networks(network_infos.clone()))
+        .and_then(retired::retired_nodes_response);
+
+    let metrics = warp::get()
+        .and(warp::path!("metrics"))
+        .and(api::with_caches(caches.clone()))
+        .and_then(metrics::metrics_response);
+
+    let ready = warp::get()
+        .and(warp::path!("ready"))
+        .and(warp::any().map(move || readiness.clone()))
+        .and_then(readiness::readiness_response);
+
+    let block_templates_json = warp::get()
+        .and(api::prefix())
+        .and(warp::path!(u32 / "block-templates.json"))
+        .and(warp::any().map(move || block_templates.clone()))
+        .and(api::with_networks(network_infos.clone()))
+        .and_then(templates::response);
+
+    let pools_json = warp::get()
+        .and(api::prefix())
+        .and(warp::path!(u32 / "pools.json"))
+        .and(warp::any().map(move || pool_tips.clone()))
+        .and(api::with_networks(network_infos.clone()))
+        .and_then(stratum::response);
+
+    let history_networks = config.networks.clone();
+    let history_trees = trees.clone();
+    let history_json = warp::get()
+        .and(api::explicit_prefix())
+        .and(warp::path!(u32 / "history" / String))
+        .and(warp::any().map(move || history_chunks.clone()))
+        .and(warp::any().map(move || history_networks.clone()))
+        .and(warp::any().map(move || history_trees.clone()))
+        .and(api::with_networks(network_infos.clone()))
+        .and_then(history::response);
+
+    let block_header_networks = config.networks.clone();
+    let block_header_trees = trees.clone();
+    let fetch_windows: readthrough::FetchWindows = Arc::new(Mutex::new(BTreeMap::new()));
+    let block_header_json = warp::get()
+        .and(api::explicit_prefix())
+        .and(warp::path!(u32 / "blocks" / String / "header.json"))
+        .and(warp::any().map(move || block_header_networks.clone()))
+        .and(warp::any().map(move || block_header_trees.clone()))
+        .and(api::with_caches(caches.clone()))
+        .and(warp::any().map(move || fetch_windows.clone()))
+        .and(api::with_networks(network_infos.clone()))
+        .and_then(readthrough::response);
+
+    let sightings_submit = warp::post()
+        .and(api::prefix())
+        .and(warp::path!(u32 / "sightings"))
+        .and(warp::header::optional::<String>("authorization"))
+        .and(warp::body::content_length_limit(MAX_SIGHTINGS_BODY_SIZE))
+        .and(warp::body::json())
+        .and(sightings::with_external_observers(
+            config.external_observers.clone(),
+        ))
+        .and(api::with_db(db.clone()))
+        .and(api::with_networks(network_infos.clone()))
+        .and_then(sightings::submit_response);
+
+    let seen_by_json = warp::get()
+        .and(api::prefix())
+        .and(warp::path!(u32 / "blocks" / String / "seen-by.json"))
+        .and(api::with_caches(caches.clone()))
+        .and(api::with_db(db.clone()))
+        .and(api::with_networks(network_infos.clone()))
+        .and_then(sightings::seen_by_response);
+
+    let events_sse = api::version()
+        .and(warp::path!("events"))
+        .and(warp::get())
+        .and(warp::header::optional::<String>("last-event-id"))
+        .and(warp::query::<api::EventsQuery>())
+        .and(api::with_event_log(event_log.clone()))
+        .and(api::with_networks(network_infos.clone()))
+        .and_then(api::events_sse_response);
+
     let forks_rss = warp::get()
         .and(warp::path!("rss" / u32 / "forks.xml"))
         .and(api::with_caches(caches.clone()))
@@ -529,12 +2186,21 @@ async fn main() -> Result<(), MainError> {
         .and(rss::with_rss_base_url(config.rss_base_url.clone()))
         .and_then(rss::unreachable_nodes_response);
 
+    let status_json = warp::get()
+        .and(api::explicit_prefix())
+        .and(warp::path!("status.json"))
+        .and(api::with_caches(caches.clone()))
+        .and(api::with_networks(network_infos.clone()))
+        .and_then(widget::status_response);
+
     let networks_json = warp::get()
-        .and(warp::path!("api" / "networks.json"))
+        .and(api::prefix())
+        .and(warp::path!("networks.json"))
         .and(api::with_networks(network_infos))
         .and_then(api::networks_response);
 
-    let change_sse = warp::path!("api" / "changes")
+    let change_sse = api::prefix()
+        .and(warp::path!("changes"))
         .and(warp::get())
         .map(move || {
             let tipchanges_rx = tipchanges_tx.clone().subscribe();
@@ -550,19 +2216,293 @@ async fn main() -> Result<(), MainError> {
             warp::sse::reply(stream)
         });
 
-    let routes = www_dir
-        .or(index_html)
-        .or(fullscreen_html)
-        .or(data_json)
+    let admin_dead_letter = warp::get()
+        .and(warp::path!("admin" / "notifications" / "dead-letter"))
+        .and(admin::with_admin_auth(config.admin_token.clone()))
+        .and(api::with_db(db.clone()))
+        .and_then(admin::dead_letter_response);
+
+    let admin_dead_letter_retry = warp::post()
+        .and(warp::path!(
+            "admin" / "notifications" / "dead-letter" / u64 / "retry"
+        ))
+        .and(admin::with_admin_auth(config.admin_token.clone()))
+        .and(api::with_db(db.clone()))
+        .and_then(admin::dead_letter_retry_response);
+
+    let admin_dead_letter_delete = warp::delete()
+        .and(warp::path!("admin" / "notifications" / "dead-letter" / u64))
+        .and(admin::with_admin_auth(config.admin_token.clone()))
+        .and(api::with_db(db.clone()))
+        .and_then(admin::dead_letter_delete_response);
+
+    let running_nodes = preflight::descriptors(&config.networks);
+    let admin_preflight = warp::post()
+        .and(warp::path!("admin" / "config" / "preflight"))
+        .and(admin::with_admin_auth(config.admin_token.clone()))
+        .and(warp::any().map(move || running_nodes.clone()))
+        .and_then(admin::preflight_response);
+
+    let export_networks = config.networks.clone();
+    let admin_export = warp::get()
+        .and(warp::path!("admin" / "export" / u32))
+        .and(warp::query::<admin::ExportQuery>())
+        .and(admin::with_admin_auth(config.admin_token.clone()))
+        .and(api::with_db(db.clone()))
+        .and(warp::any().map(move || export_networks.clone()))
+        .and_then(admin::export_response);
+
+    let admin_nodes_networks = config.networks.clone();
+    let admin_nodes = warp::get()
+        .and(warp::path!("admin" / "nodes.json"))
+        .and(admin::with_admin_auth(config.admin_token.clone()))
+        .and(warp::any().map(move || admin_nodes_networks.clone()))
+        .and(api::with_caches(caches.clone()))
+        .and_then(admin::nodes_response);
+
+    let node_pause_networks = config.networks.clone();
+    let node_pause_paused = paused_nodes.clone();
+    let admin_node_pause = warp::put()
+        .and(warp::path!("admin" / "nodes" / u32 / u32 / "pause"))
+        .and(admin::with_admin_auth(config.admin_token.clone()))
+        .and(warp::any().map(|| true))
+        .and(warp::any().map(move || node_pause_networks.clone()))
+        .and(api::with_caches(caches.clone()))
+        .and(warp::any().map(move || node_pause_paused.clone()))
+        .and_then(admin::node_pause_response);
+
+    let node_resume_networks = config.networks.clone();
+    let admin_node_resume = warp::delete()
+        .and(warp::path!("admin" / "nodes" / u32 / u32 / "pause"))
+        .and(admin::with_admin_auth(config.admin_token.clone()))
+        .and(warp::any().map(|| false))
+        .and(warp::any().map(move || node_resume_networks.clone()))
+        .and(api::with_caches(caches.clone()))
+        .and(warp::any().map(move || paused_nodes.clone()))
+        .and_then(admin::node_pause_response);
+
+    let admin_quarantine = warp::get()
+        .and(warp::path!("admin" / "quarantine.json"))
+        .and(admin::with_admin_auth(config.admin_token.clone()))
+        .and(warp::any().map(move || quarantine.clone()))
+        .and_then(admin::quarantine_response);
+
+    let admin_log_levels = warp::get()
+        .and(warp::path!("admin" / "log-levels.json"))
+        .and(admin::with_admin_auth(config.admin_token.clone()))
+        .and(warp::any().map(move || logger))
+        .and_then(admin::log_levels_response);
+
+    let admin_log_level_set = warp::put()
+        .and(warp::path!("admin" / "log-levels" / String))
+        .and(warp::query::<admin::LogLevelQuery>())
+        .and(admin::with_admin_auth(config.admin_token.clone()))
+        .and(warp::any().map(move || logger))
+        .and_then(admin::log_level_set_response);
+
+    let admin_log_level_reset = warp::delete()
+        .and(warp::path!("admin" / "log-levels" / String))
+        .and(admin::with_admin_auth(config.admin_token.clone()))
+        .and(warp::any().map(move || logger))
+        .and_then(admin::log_level_reset_response);
+
+    let admin_incidents = warp::get()
+        .and(warp::path!("admin" / "incidents.json"))
+        .and(admin::with_admin_auth(config.admin_token.clone()))
+        .and(api::with_db(db.clone()))
+        .and_then(admin::incidents_response);
+
+    let incident_start_networks = config.networks.clone();
+    let incident_start_incidents = incidents.clone();
+    let incident_start_event_log = event_log.clone();
+    let admin_incident_start = warp::put()
+        .and(warp::path!("admin" / "incidents" / u32))
+        .and(warp::query::<admin::IncidentQuery>())
+        .and(admin::with_admin_auth(config.admin_token.clone()))
+        .and(api::with_db(db.clone()))
+        .and(warp::any().map(move || incident_start_networks.clone()))
+        .and(warp::any().map(move || incident_start_incidents.clone()))
+        .and(warp::any().map(move || incident_start_event_log.clone()))
+        .and_then(admin::incident_start_response);
+
+    let incident_stop_networks = config.networks.clone();
+    let incident_stop_incidents = incidents.clone();
+    let incident_stop_event_log = event_log.clone();
+    let admin_incident_stop = warp::delete()
+        .and(warp::path!("admin" / "incidents" / u32))
+        .and(admin::with_admin_auth(config.admin_token.clone()))
+        .and(api::with_db(db.clone()))
+        .and(warp::any().map(move || incident_stop_networks.clone()))
+        .and(warp::any().map(move || incident_stop_incidents.clone()))
+        .and(warp::any().map(move || incident_stop_event_log.clone()))
+        .and_then(admin::incident_stop_response);
+
+    let admin_incident_bundle = warp::get()
+        .and(warp::path!("admin" / "incidents" / u64 / "bundle.tar"))
+        .and(admin::with_admin_auth(config.admin_token.clone()))
+        .and(api::with_db(db.clone()))
+        .and_then(admin::incident_bundle_response);
+
+    let admin_suppressed_tips_list = suppressed_tips.clone();
+    let admin_suppressed_tips = warp::get()
+        .and(warp::path!("admin" / "suppressed-tips.json"))
+        .and(admin::with_admin_auth(config.admin_token.clone()))
+        .and(warp::any().map(move || admin_suppressed_tips_list.clone()))
+        .and_then(admin::suppressed_tips_response);
+
+    let tip_suppress_networks = config.networks.clone();
+    let tip_suppress_suppressed = suppressed_tips.clone();
+    let admin_tip_suppress = warp::put()
+        .and(warp::path!("admin" / "suppressed-tips" / u32 / String))
+        .and(warp::query::<admin::SuppressQuery>())
+        .and(admin::with_admin_auth(config.admin_token.clone()))
+        .and(api::with_db(db.clone()))
+        .and(warp::any().map(move || tip_suppress_networks.clone()))
+        .and(warp::any().map(move || tip_suppress_suppressed.clone()))
+        .and_then(admin::tip_suppress_response);
+
+    let admin_tip_unsuppress = warp::delete()
+        .and(warp::path!("admin" / "suppressed-tips" / u32 / String))
+        .and(admin::with_admin_auth(config.admin_token.clone()))
+        .and(api::with_db(db.clone()))
+        .and(warp::any().map(move || suppressed_tips.clone()))
+        .and_then(admin::tip_unsuppress_response);
+
+    let admin_expanded_forks_list = expanded_forks.clone();
+    let admin_expanded_forks = warp::get()
+        .and(warp::path!("admin" / "expanded-forks.json"))
+        .and(admin::with_admin_auth(config.admin_token.clone()))
+        .and(warp::any().map(move || admin_expanded_forks_list.clone()))
+        .and_then(admin::expanded_forks_response);
+
+    let fork_expand_networks = config.networks.clone();
+    let fork_expand_trees = trees.clone();
+    let fork_expand_expanded = expanded_forks.clone();
+    let fork_expand_refresh_tx = refresh_tx.clone();
+    let admin_fork_expand = warp::put()
+        .and(warp::path!("admin" / "forks" / u32 / String / "expand"))
+        .and(admin::with_admin_auth(config.admin_token.clone()))
+        .and(warp::any().map(move || fork_expand_networks.clone()))
+        .and(warp::any().map(move || fork_expand_trees.clone()))
+        .and(warp::any().map(move || fork_expand_expanded.clone()))
+        .and(warp::any().map(move || fork_expand_refresh_tx.clone()))
+        .and_then(admin::fork_expand_response);
+
+    let fork_collapse_expanded = expanded_forks.clone();
+    let admin_fork_collapse = warp::delete()
+        .and(warp::path!("admin" / "forks" / u32 / String / "expand"))
+        .and(admin::with_admin_auth(config.admin_token.clone()))
+        .and(warp::any().map(move || fork_collapse_expanded.clone()))
+        .and(warp::any().map(move || refresh_tx.clone()))
+        .and_then(admin::fork_collapse_response);
+
+    let admin_routes = admin_dead_letter
+        .or(admin_dead_letter_retry)
+        .or(admin_dead_letter_delete)
+        .or(admin_preflight)
+        .or(admin_export)
+        .or(admin_nodes)
+        .or(admin_node_pause)
+        .or(admin_node_resume)
+        .or(admin_quarantine)
+        .or(admin_log_levels)
+        .or(admin_log_level_set)
+        .or(admin_log_level_reset)
+        .or(admin_incidents)
+        .or(admin_incident_start)
+        .or(admin_incident_stop)
+        .or(admin_incident_bundle)
+        .or(admin_suppressed_tips)
+        .or(admin_tip_suppress)
+        .or(admin_tip_unsuppress)
+        .or(admin_expanded_forks)
+        .or(admin_fork_expand)
+        .or(admin_fork_collapse)
+        .recover(admin::handle_rejection);
+
+    // The read-only JSON API and its event streams, served with the
+    // configured CORS policy.
+    let data_api_routes = data_json
         .or(info_json)
+        .or(raw_chaintips_json)
+        .or(chain_params_json)
         .or(networks_json)
         .or(change_sse)
+        .or(events_json)
+        .or(reorgs_json)
+        .or(tip_history_json)
+        .or(competing_blocks_json)
+        .or(fork_latency_json)
+        .or(fork_lifecycle_json)
+        .or(block_metadata_json)
+        .or(stale_rate_json)
+        .or(block_templates_json)
+        .or(pools_json)
+        .or(history_json)
+        .or(block_header_json)
+        .or(consensus_json)
+        .or(fork_prediction_json)
+        .or(status_json)
+        .or(seen_by_json)
+        .or(retired_nodes_json)
+        .or(branch_lengths_json)
+        .or(tree_dot)
+        .or(tree_graphml)
+        .or(tree_json)
+        .or(events_sse)
+        .map(Reply::into_response);
+    let data_api_routes = match config.cors {
+        Some(ref cors) => data_api_routes
+            .with(api::cors(cors))
+            .map(Reply::into_response)
+            .boxed(),
+        None => data_api_routes.boxed(),
+    };
+
+    let routes = www_dir
+        .or(index_html)
+        .or(fullscreen_html)
+        .or(data_api_routes)
+        .or(widget_json)
+        .or(metrics)
+        .or(ready)
+        .or(sightings_submit)
         .or(forks_rss)
         .or(lagging_nodes_rss)
         .or(unreachable_nodes_rss)
-        .or(invalid_blocks_rss);
-
-    warp::serve(routes).run(config.address).await;
+        .or(invalid_blocks_rss)
+        .or(admin_routes);
+
+    let routes = warp::path::full()
+        .and(routes)
+        .map(api::with_deprecation_headers);
+
+    // The routes are served through a hyper service so each request is
+    // answered within its trace, see trace::handle().
+    let trace_exporter = match config.tracing.clone() {
+        Some(tracing) => {
+            let (finished_tx, finished_rx) = unbounded_channel();
+            task::spawn(trace::export(tracing, finished_rx));
+            Some(finished_tx)
+        }
+        None => None,
+    };
+    // Boxed, as the type of the combined routes is too deep for the
+    // compiler to lay out the traced future.
+    let service = warp::service(routes.boxed());
+    let make_service = make_service_fn(move |_| {
+        let service = service.clone();
+        let trace_exporter = trace_exporter.clone();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |request| {
+                trace::handle(service.clone(), request, trace_exporter.clone())
+            }))
+        }
+    });
+    info!("Serving on {}", config.address);
+    if let Err(e) = Server::bind(&config.address).serve(make_service).await {
+        error!("The web server failed: {}", e);
+    }
     Ok(())
 }
 
@@ -600,6 +2540,12 @@ enum CacheUpdate {
     NodeTips {
         node_id: u32,
         tips: Vec<ChainTip>,
+        // The cumulative work of the tips, see headertree::tips_chainwork().
+        chainwork: HashMap<String, String>,
+    },
+    NodeRawTips {
+        node_id: u32,
+        raw_tips: serde_json::Value,
     },
     NodeReachability {
         node_id: u32,
@@ -609,6 +2555,22 @@ enum CacheUpdate {
         node_id: u32,
         version: String,
     },
+    NodeAddress {
+        node_id: u32,
+        address: String,
+    },
+    NodeP2PReachability {
+        node_id: u32,
+        probes: Vec<P2PProbeJson>,
+    },
+    NodeHeaderGap {
+        node_id: u32,
+        header_gap: HeaderGapJson,
+    },
+    NodeResources {
+        node_id: u32,
+        resources: NodeResourcesJson,
+    },
 }
 
 impl fmt::Display for CacheUpdate {
@@ -618,7 +2580,7 @@ impl fmt::Display for CacheUpdate {
                 write!(
                     f,
                     "Setting miner of block {} to miner={}",
-                    header_info.header.block_hash(),
+                    header_info.block_hash(),
                     header_info.miner
                 )
             }
@@ -639,9 +2601,32 @@ impl fmt::Display for CacheUpdate {
             CacheUpdate::NodeTips { node_id, .. } => {
                 write!(f, "Update tips of node={}", node_id,)
             }
+            CacheUpdate::NodeRawTips { node_id, .. } => {
+                write!(f, "Update raw tips of node={}", node_id,)
+            }
             CacheUpdate::NodeVersion { node_id, version } => {
                 write!(f, "Update node={} version={}", node_id, version)
             }
+            CacheUpdate::NodeAddress { node_id, address } => {
+                write!(f, "Update node={} address={}", node_id, address)
+            }
+            CacheUpdate::NodeP2PReachability { node_id, probes } => {
+                write!(
+                    f,
+                    "Update node={} P2P reachability of {} addresses",
+                    node_id,
+                    probes.len()
+                )
+            }
+            CacheUpdate::NodeHeaderGap {
+                node_id,
+                header_gap,
+            } => {
+                write!(f, "Update node={} header gap={}", node_id, header_gap.gap)
+            }
+            CacheUpdate::NodeResources { node_id, resources } => {
+                write!(f, "Update node={} uptime={}", node_id, resources.uptime)
+            }
             CacheUpdate::NodeReachability { node_id, reachable } => {
                 write!(f, "Setting node {} to reachable={}", node_id, reachable)
             }
@@ -671,17 +2656,18 @@ async fn update_cache(caches: &Caches, network_id: u32, update: CacheUpdate) {
             let mut old = network.header_infos_json.clone();
             if let Some(index) = old
                 .iter()
-                .position(|h| h.hash == header_info.header.block_hash().to_string())
+                .position(|h| h.hash == header_info.block_hash().to_string())
             {
-                old[index].update_miner(header_info.miner.clone());
+                old[index].update_miner(header_info.miner.clone(), header_info.own);
             }
 
             locked_cache.entry(network_id).and_modify(|cache| {
                 cache.header_infos_json = old;
 
                 cache.recent_miners.push((
-                    header_info.header.block_hash().to_string(),
+                    header_info.block_hash().to_string(),
                     header_info.miner,
+                    header_info.own,
                 ));
                 if cache.recent_miners.len() > 5 {
                     cache.recent_miners.remove(0);
@@ -698,9 +2684,9 @@ async fn update_cache(caches: &Caches, network_id: u32, update: CacheUpdate) {
                 .collect();
             // we might have new miner infos. Make sure to not overwrite headers
             // that already have a miner.
-            for (hash, miner) in network.recent_miners.iter() {
+            for (hash, miner, own) in network.recent_miners.iter() {
                 new_header_infos_map.entry(hash.clone()).and_modify(|new| {
-                    new.update_miner(miner.clone());
+                    new.update_miner(miner.clone(), *own);
                     debug!(
                         "During CacheUpdate::HeaderTree, updated miner of block {}: {}",
                         hash, miner
@@ -709,14 +2695,15 @@ async fn update_cache(caches: &Caches, network_id: u32, update: CacheUpdate) {
             }
 
             locked_cache.entry(network_id).and_modify(|e| {
-                e.header_infos_json = new_header_infos_map
-                    .iter()
-                    .map(|(_, header)| header.clone())
-                    .collect();
+                e.header_infos_json = new_header_infos_map.values().cloned().collect();
                 e.forks = forks;
             });
         }
-        CacheUpdate::NodeTips { node_id, tips } => {
+        CacheUpdate::NodeTips {
+            node_id,
+            tips,
+            chainwork,
+        } => {
             let min_height = match network.header_infos_json.iter().min_by_key(|h| h.height) {
                 Some(header) => header.height,
                 None => 0,
@@ -731,7 +2718,20 @@ async fn update_cache(caches: &Caches, network_id: u32, update: CacheUpdate) {
                 network
                     .node_data
                     .entry(node_id)
-                    .and_modify(|e| e.tips(&relevant_tips));
+                    .and_modify(|e| e.tips(&relevant_tips, &chainwork));
+            });
+        }
+        CacheUpdate::NodeRawTips { node_id, raw_tips } => {
+            locked_cache.entry(network_id).and_modify(|network| {
+                network.raw_tips.insert(
+                    node_id,
+                    RawChainTipsJson {
+                        network_id,
+                        node_id,
+                        timestamp: now_timestamp(),
+                        chaintips: raw_tips,
+                    },
+                );
             });
         }
         CacheUpdate::NodeReachability { node_id, reachable } => {
@@ -750,6 +2750,41 @@ async fn update_cache(caches: &Caches, network_id: u32, update: CacheUpdate) {
                     .and_modify(|e| e.version(version));
             });
         }
+        CacheUpdate::NodeAddress { node_id, address } => {
+            locked_cache.entry(network_id).and_modify(|network| {
+                network
+                    .node_data
+                    .entry(node_id)
+                    .and_modify(|e| e.address(address));
+            });
+        }
+        CacheUpdate::NodeP2PReachability { node_id, probes } => {
+            locked_cache.entry(network_id).and_modify(|network| {
+                network
+                    .node_data
+                    .entry(node_id)
+                    .and_modify(|e| e.p2p_reachability = Some(probes));
+            });
+        }
+        CacheUpdate::NodeHeaderGap {
+            node_id,
+            header_gap,
+        } => {
+            locked_cache.entry(network_id).and_modify(|network| {
+                network
+                    .node_data
+                    .entry(node_id)
+                    .and_modify(|e| e.header_gap = Some(header_gap));
+            });
+        }
+        CacheUpdate::NodeResources { node_id, resources } => {
+            locked_cache.entry(network_id).and_modify(|network| {
+                network
+                    .node_data
+                    .entry(node_id)
+                    .and_modify(|e| e.resources = Some(resources));
+            });
+        }
     }
 }
 
@@ -788,47 +2823,61 @@ async fn load_node_version(node: BoxedSyncSendNode, network: &str) -> String {
         network,
         VERSION_UNKNOWN
     );
-    return VERSION_UNKNOWN.to_string();
+    VERSION_UNKNOWN.to_string()
 }
 
-async fn insert_new_headers_into_tree(tree: &Tree, new_headers: &[HeaderInfo]) -> bool {
-    let mut tree_changed: bool = false;
-    let mut tree_locked = tree.lock().await;
-    // insert headers to tree
-    for h in new_headers {
-        if !tree_locked.1.contains_key(&h.header.block_hash()) {
-            let idx = tree_locked.0.add_node(h.clone());
-            tree_locked.1.insert(h.header.block_hash(), idx);
-            tree_changed = true;
-        }
-    }
-    // connect nodes with edges
-    for current in new_headers {
-        let idx_current: NodeIndex;
-        let idx_prev: NodeIndex;
-        {
-            idx_current = *tree_locked
-                    .1
-                    .get(&current.header.block_hash())
-                    .expect(
-                    "current header should be in the map as we just inserted it or it was already present",
-                );
-            match tree_locked.1.get(&current.header.prev_blockhash) {
-                Some(idx) => idx_prev = *idx,
-                None => {
-                    continue; // the tree's root has no previous block, skip it
-                }
-            }
+// The reachability events of a node are correlated, so a flapping node
+// results in an aggregated event instead of one per change. Shadow nodes
+// don't emit events.
+async fn emit_reachability_event(
+    event_log: &EventLog,
+    network: &config::Network,
+    node: &NodeInfo,
+    error: Option<&FetchError>,
+) {
+    if node.shadow {
+        return;
+    }
+    let key = format!("node-reachability:{}", node.id);
+    match error {
+        Some(e) => {
+            event_log
+                .emit_correlated(
+                    network.id,
+                    EventKind::NodeUnreachable,
+                    &key,
+                    EventSeverity::Warning,
+                    format!("Node {} on {} is unreachable", node.name, network.name),
+                    format!(
+                        "The chain tips of node {} on {} could not be fetched: {}",
+                        node.name, network.name, e
+                    ),
+                )
+                .await
+        }
+        None => {
+            event_log
+                .emit_correlated(
+                    network.id,
+                    EventKind::NodeReachable,
+                    &key,
+                    EventSeverity::Info,
+                    format!("Node {} on {} is reachable again", node.name, network.name),
+                    format!(
+                        "The chain tips of node {} on {} could be fetched again.",
+                        node.name, network.name
+                    ),
+                )
+                .await
         }
-        tree_locked.0.update_edge(idx_prev, idx_current, false);
     }
-    tree_changed
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::node::NodeInfo;
+    use crate::domain::NodeInfo;
+    use crate::retry::RetryPolicy;
 
     async fn get_test_node_reachable(caches: &Caches, net_id: u32, node_id: u32) -> bool {
         let locked_caches = caches.lock().await;
@@ -850,6 +2899,10 @@ mod tests {
             name: "".to_string(),
             description: "".to_string(),
             implementation: "".to_string(),
+            trust: 1.0,
+            shadow: false,
+            purge_after: None,
+            retry: RetryPolicy::default(),
         };
         {
             // populate data
@@ -857,7 +2910,7 @@ mod tests {
             let mut node_data: NodeData = BTreeMap::new();
             node_data.insert(
                 node.id,
-                NodeDataJson::new(node.clone(), &vec![], "".to_string(), 0, true),
+                NodeDataJson::new(node.clone(), &[], "".to_string(), 0, true),
             );
             locked_caches.insert(
                 network_id,
@@ -866,13 +2919,11 @@ mod tests {
                     node_data,
                     forks: vec![],
                     recent_miners: vec![],
+                    raw_tips: BTreeMap::new(),
                 },
             );
         }
-        assert_eq!(
-            get_test_node_reachable(&caches, network_id, node.id).await,
-            true
-        );
+        assert!(get_test_node_reachable(&caches, network_id, node.id).await);
 
         update_cache(
             &caches,
@@ -883,10 +2934,7 @@ mod tests {
             },
         )
         .await;
-        assert_eq!(
-            get_test_node_reachable(&caches, network_id, node.id).await,
-            false
-        );
+        assert!(!get_test_node_reachable(&caches, network_id, node.id).await);
 
         update_cache(
             &caches,
@@ -897,9 +2945,6 @@ mod tests {
             },
         )
         .await;
-        assert_eq!(
-            get_test_node_reachable(&caches, network_id, node.id).await,
-            true
-        );
+        assert!(get_test_node_reachable(&caches, network_id, node.id).await);
     }
 }
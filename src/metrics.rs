@@ -0,0 +1,352 @@
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::fmt::Write;
+
+use warp::Reply;
+
+use crate::config::Network;
+use crate::domain::Fork;
+use crate::error::MainError;
+use crate::types::{Cache, Caches, HeaderInfoJson};
+
+// The metric names are referenced by the generated alerting rules. Renaming
+// them breaks existing Prometheus setups.
+const METRIC_NODE_REACHABLE: &str = "forkobserver_node_reachable";
+const METRIC_NODE_TIP_HEIGHT: &str = "forkobserver_node_tip_height";
+const METRIC_NETWORK_TIP_HEIGHT: &str = "forkobserver_network_tip_height";
+const METRIC_FORK_DEPTH: &str = "forkobserver_fork_depth";
+const METRIC_NODE_HEADER_GAP: &str = "forkobserver_node_header_gap";
+const METRIC_NODE_UPTIME: &str = "forkobserver_node_uptime_seconds";
+
+// Only forks starting this many blocks below the network tip count towards
+// the fork depth.
+const RECENT_FORK_BLOCKS: u64 = 144;
+
+const DEFAULT_MAX_LAG: u64 = 3; // blocks
+const DEFAULT_MAX_FORK_DEPTH: u64 = 2; // blocks
+
+// How long a node has to be unreachable or lagging before alerting.
+const DEFAULT_FOR_MINUTES: u64 = 5; // minutes
+
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+// Length of the longest competing branch of the recent forks, i.e. the
+// branches except the longest one of each fork.
+fn fork_depth(header_infos: &[HeaderInfoJson], forks: &[Fork], tip_height: u64) -> u64 {
+    let ids: HashMap<&str, usize> = header_infos
+        .iter()
+        .map(|h| (h.hash.as_str(), h.id))
+        .collect();
+    let mut children: HashMap<usize, Vec<&HeaderInfoJson>> = HashMap::new();
+    for header_info in header_infos.iter().filter(|h| h.id != h.prev_id) {
+        children
+            .entry(header_info.prev_id)
+            .or_default()
+            .push(header_info);
+    }
+    let max_height = |id: usize, height: u64| -> u64 {
+        let mut max_height = height;
+        let mut stack: Vec<usize> = vec![id];
+        while let Some(id) = stack.pop() {
+            for child in children.get(&id).into_iter().flatten() {
+                max_height = max_height.max(child.height);
+                stack.push(child.id);
+            }
+        }
+        max_height
+    };
+
+    forks
+        .iter()
+        .filter(|fork| fork.common.height + RECENT_FORK_BLOCKS >= tip_height)
+        .map(|fork| {
+            let mut branch_lengths: Vec<u64> = fork
+                .children
+                .iter()
+                .filter_map(|child| {
+                    ids.get(child.block_hash().to_string().as_str())
+                        .map(|id| max_height(*id, child.height) - fork.common.height)
+                })
+                .collect();
+            branch_lengths.sort_unstable();
+            branch_lengths.pop();
+            branch_lengths.pop().unwrap_or_default()
+        })
+        .max()
+        .unwrap_or_default()
+}
+
+// Renders the metrics of a network in the Prometheus text format.
+fn render(out: &mut String, network_id: u32, cache: &Cache) -> std::fmt::Result {
+    let mut network_tip_height: Option<u64> = None;
+    // Shadow nodes are excluded, so they don't trigger alerts.
+    for node in cache.node_data.values().filter(|node| !node.shadow) {
+        let labels = format!(
+            "network_id=\"{}\",node_id=\"{}\",node=\"{}\"",
+            network_id,
+            node.id,
+            escape_label(&node.name)
+        );
+        writeln!(
+            out,
+            "{}{{{}}} {}",
+            METRIC_NODE_REACHABLE, labels, node.reachable as u8
+        )?;
+        if let Some(tip) = node.tips.iter().find(|t| t.status == "active") {
+            writeln!(
+                out,
+                "{}{{{}}} {}",
+                METRIC_NODE_TIP_HEIGHT, labels, tip.height
+            )?;
+            network_tip_height = network_tip_height.max(Some(tip.height));
+        }
+        if let Some(ref header_gap) = node.header_gap {
+            writeln!(
+                out,
+                "{}{{{}}} {}",
+                METRIC_NODE_HEADER_GAP, labels, header_gap.gap
+            )?;
+        }
+        if let Some(ref resources) = node.resources {
+            writeln!(
+                out,
+                "{}{{{}}} {}",
+                METRIC_NODE_UPTIME, labels, resources.uptime
+            )?;
+        }
+    }
+    if let Some(tip_height) = network_tip_height {
+        writeln!(
+            out,
+            "{}{{network_id=\"{}\"}} {}",
+            METRIC_NETWORK_TIP_HEIGHT, network_id, tip_height
+        )?;
+        writeln!(
+            out,
+            "{}{{network_id=\"{}\"}} {}",
+            METRIC_FORK_DEPTH,
+            network_id,
+            fork_depth(&cache.header_infos_json, &cache.forks, tip_height)
+        )?;
+    }
+    Ok(())
+}
+
+pub async fn metrics_response(caches: Caches) -> Result<impl Reply, Infallible> {
+    let caches_locked = caches.lock().await;
+    let mut out = String::new();
+    for (help, name) in [
+        (
+            "If the last getchaintips RPC reached the node.",
+            METRIC_NODE_REACHABLE,
+        ),
+        ("Height of the node's active tip.", METRIC_NODE_TIP_HEIGHT),
+        (
+            "Highest active tip height of the network's nodes.",
+            METRIC_NETWORK_TIP_HEIGHT,
+        ),
+        (
+            "Length of the longest competing branch of the forks in the last 144 blocks.",
+            METRIC_FORK_DEPTH,
+        ),
+        (
+            "Headers the node knows of beyond its validated tip. Only with header_gap enabled.",
+            METRIC_NODE_HEADER_GAP,
+        ),
+        (
+            "Seconds since the node started. Only with resource_telemetry enabled.",
+            METRIC_NODE_UPTIME,
+        ),
+    ]
+    .iter()
+    {
+        out.push_str(&format!(
+            "# HELP {} {}\n# TYPE {} gauge\n",
+            name, help, name
+        ));
+    }
+    for (network_id, cache) in caches_locked.iter() {
+        // writing to a String doesn't fail
+        let _ = render(&mut out, *network_id, cache);
+    }
+    Ok(warp::reply::with_header(
+        out,
+        "content-type",
+        "text/plain; version=0.0.4",
+    ))
+}
+
+// The arguments of the alert-rules command:
+// fork-observer alert-rules [--max-lag <blocks>] [--max-fork-depth <blocks>] [--for <minutes>]
+#[derive(Debug, PartialEq, Eq)]
+pub struct AlertThresholds {
+    pub max_lag: u64,
+    pub max_fork_depth: u64,
+    pub for_minutes: u64,
+}
+
+pub fn parse_args(args: &[String]) -> Result<AlertThresholds, MainError> {
+    let mut thresholds = AlertThresholds {
+        max_lag: DEFAULT_MAX_LAG,
+        max_fork_depth: DEFAULT_MAX_FORK_DEPTH,
+        for_minutes: DEFAULT_FOR_MINUTES,
+    };
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        let value: u64 = match iter.next().map(|value| value.parse()) {
+            Some(Ok(value)) if value > 0 => value,
+            _ => {
+                return Err(MainError::InvalidArguments(format!(
+                    "'{}' needs a number larger than zero",
+                    arg
+                )))
+            }
+        };
+        match arg.as_str() {
+            "--max-lag" => thresholds.max_lag = value,
+            "--max-fork-depth" => thresholds.max_fork_depth = value,
+            "--for" => thresholds.for_minutes = value,
+            _ => {
+                return Err(MainError::InvalidArguments(format!(
+                    "unknown argument '{}'",
+                    arg
+                )))
+            }
+        }
+    }
+    Ok(thresholds)
+}
+
+fn yaml_string(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+// Generates Prometheus alerting rules for the metrics served under /metrics
+// with a rule group per configured network.
+pub fn alert_rules(networks: &[Network], thresholds: &AlertThresholds) -> String {
+    let mut out = String::from(
+        "# Prometheus alerting rules generated by `fork-observer alert-rules`.\n\
+         # The rules expect fork-observer's /metrics endpoint to be scraped.\n\
+         groups:\n",
+    );
+    for network in networks.iter() {
+        let selector = format!("{{network_id=\"{}\"}}", network.id);
+        let rules = vec![
+            (
+                "ForkObserverNodeDown",
+                format!("{}{} == 0", METRIC_NODE_REACHABLE, selector),
+                thresholds.for_minutes,
+                "warning",
+                format!(
+                    "Node {{{{ $labels.node }}}} on {} is unreachable",
+                    network.name
+                ),
+            ),
+            (
+                "ForkObserverNodeLagging",
+                format!(
+                    "{}{} - on(network_id) group_right {}{} > {}",
+                    METRIC_NETWORK_TIP_HEIGHT,
+                    selector,
+                    METRIC_NODE_TIP_HEIGHT,
+                    selector,
+                    thresholds.max_lag
+                ),
+                thresholds.for_minutes,
+                "warning",
+                format!(
+                    "Node {{{{ $labels.node }}}} on {} is {{{{ $value }}}} blocks behind the other nodes",
+                    network.name
+                ),
+            ),
+            (
+                "ForkObserverDeepFork",
+                format!(
+                    "{}{} >= {}",
+                    METRIC_FORK_DEPTH, selector, thresholds.max_fork_depth
+                ),
+                0,
+                "critical",
+                format!(
+                    "A competing branch of {{{{ $value }}}} blocks was observed on {}",
+                    network.name
+                ),
+            ),
+        ];
+
+        out.push_str(&format!(
+            "  - name: {}\n    rules:\n",
+            yaml_string(&format!("fork-observer-{}", network.id))
+        ));
+        for (alert, expr, for_minutes, severity, summary) in rules {
+            out.push_str(&format!("      - alert: {}\n", alert));
+            out.push_str(&format!("        expr: {}\n", yaml_string(&expr)));
+            if for_minutes > 0 {
+                out.push_str(&format!("        for: {}m\n", for_minutes));
+            }
+            out.push_str(&format!(
+                "        labels:\n          severity: {}\n",
+                severity
+            ));
+            out.push_str(&format!(
+                "        annotations:\n          summary: {}\n",
+                yaml_string(&summary)
+            ));
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ChainParams;
+    use crate::domain::HeaderInfo;
+    use crate::testutil::at_height;
+
+    #[test]
+    fn test_fork_depth() {
+        // 0 <- 1 <- 2 <- 3 <- 4 (main chain)
+        //  \<- 5 <- 6          (competing branch of two blocks)
+        let headers: Vec<HeaderInfo> = (0..7)
+            .map(|i| at_height(if i < 5 { i as u64 } else { i as u64 - 4 }, 0, i))
+            .collect();
+        let prev_ids = [0, 0, 1, 2, 3, 0, 5];
+        let header_infos: Vec<HeaderInfoJson> = headers
+            .iter()
+            .enumerate()
+            .map(|(id, h)| HeaderInfoJson::new(h, id, prev_ids[id], &ChainParams::default()))
+            .collect();
+        let forks = vec![Fork {
+            common: headers[0].clone(),
+            children: vec![headers[1].clone(), headers[5].clone()],
+        }];
+        assert_eq!(fork_depth(&header_infos, &forks, 4), 2);
+        // the fork is too old to count
+        assert_eq!(
+            fork_depth(&header_infos, &forks, 4 + RECENT_FORK_BLOCKS + 1),
+            0
+        );
+    }
+
+    #[test]
+    fn test_parse_args() {
+        let args = |args: &[&str]| -> Vec<String> { args.iter().map(|a| a.to_string()).collect() };
+        assert_eq!(
+            parse_args(&args(&["--max-lag", "6"])).unwrap(),
+            AlertThresholds {
+                max_lag: 6,
+                max_fork_depth: DEFAULT_MAX_FORK_DEPTH,
+                for_minutes: DEFAULT_FOR_MINUTES,
+            }
+        );
+        assert!(parse_args(&args(&["--max-lag", "0"])).is_err());
+        assert!(parse_args(&args(&["--max-lag"])).is_err());
+        assert!(parse_args(&args(&["--unknown", "1"])).is_err());
+    }
+}
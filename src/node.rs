@@ -1,34 +1,419 @@
+use crate::domain::{
+    BlockTemplate, ChainTip, ChainTipStatus, HeaderInfo, HeaderSource, NodeInfo, NodeResources,
+    Tree,
+};
+use crate::elements::ElementsHeader;
 use crate::error::{FetchError, JsonRPCError};
-use crate::types::{ChainTip, ChainTipStatus, HeaderInfo, Tree};
+use crate::http::{HttpEndpoint, HttpTransport, UNIX_SOCKET_PREFIX};
+use crate::p2p::{HeaderChain, Session};
+use crate::types::ChainTipJson;
+use crate::zmq::{self, ZmqEndpoint};
 use async_trait::async_trait;
 use bitcoincore_rpc::bitcoin;
 use bitcoincore_rpc::bitcoin::blockdata::block::Header;
-use bitcoincore_rpc::bitcoin::{BlockHash, Transaction};
-use bitcoincore_rpc::Auth;
+use bitcoincore_rpc::bitcoin::{Block, BlockHash, Transaction};
+use bitcoincore_rpc::json::GetChainTipsResultTip;
 use bitcoincore_rpc::Client;
 use bitcoincore_rpc::RpcApi;
-use log::{debug, error};
-use std::cmp::max;
-use std::fmt;
+use bitcoincore_rpc::{jsonrpc, Auth};
+use log::{debug, error, info, warn};
+use serde_json::value::RawValue;
+use serde_json::Value;
+use std::cmp::{max, min};
+use std::io;
+use std::net::{SocketAddr, ToSocketAddrs};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Once};
+use std::time::{Duration, Instant};
+use tokio::sync::Notify;
 use tokio::task;
 
 const BTCD_USE_REST: bool = false;
+const BTCD_WEBSOCKET_RECONNECT_DELAY: Duration = Duration::from_secs(10);
+const ZMQ_RECONNECT_DELAY: Duration = Duration::from_secs(10);
+const BITCOIN_CORE_RPC_BATCH_SIZE: u64 = 500;
+// The node answers waitfornewblock after this without a new block. The
+// dedicated longpoll client allows for it on top of the rpc_timeout.
+const WAITFORNEWBLOCK_TIMEOUT: Duration = Duration::from_secs(10 * 60);
+const WAITFORNEWBLOCK_RETRY_DELAY: Duration = Duration::from_secs(10);
 const DEFAULT_EMPTY_MINER: &str = "";
+// Bitcoin Core serves at most 2000 headers per REST request.
+pub const DEFAULT_REST_MAX_STEP_SIZE: u64 = 2000;
+const MIN_REST_STEP_SIZE: u64 = 100;
+// REST responses faster than this grow the step size, responses slower than
+// this shrink it. The default REST request timeout is 8 seconds.
+const REST_FAST_RESPONSE: Duration = Duration::from_secs(1);
+const REST_SLOW_RESPONSE: Duration = Duration::from_secs(4);
+// A node whose REST interface isn't available is probed again after this.
+const REST_PROBE_INTERVAL: Duration = Duration::from_secs(600);
+// getblockfrompeer was added in Bitcoin Core v23.
+const MIN_GETBLOCKFROMPEER_VERSION: u64 = 230000;
+// The peers serving blocks are queried again after this.
+const BLOCK_SERVING_PEERS_TTL: Duration = Duration::from_secs(60);
+
+// If a failed /rest/headers/ request was rejected because of the number of
+// requested headers: Bitcoin Core answers counts out of its range with 400,
+// and proxies capping the body size answer with 413 or cut the body off.
+fn rest_headers_too_large(status_code: i32, body_len: usize) -> bool {
+    match status_code {
+        200 => !body_len.is_multiple_of(80),
+        400 | 413 => true,
+        _ => false,
+    }
+}
+
+// Deserializes the body of a binary /rest/headers/ response, the 80 byte
+// headers one after another.
+pub fn parse_rest_headers(body: &[u8]) -> Result<Vec<Header>, FetchError> {
+    body.chunks(80)
+        .map(bitcoin::consensus::deserialize::<Header>)
+        .collect::<Result<Vec<Header>, _>>()
+        .map_err(|e| {
+            FetchError::BitcoinCoreREST(format!(
+                "could not deserialize REST header response: {}",
+                e
+            ))
+        })
+}
+
+// Resolves the host of a node's RPC address (host:port). The RPC and REST
+// clients are created per request and resolve the host on each request
+// using the first address, so this is the address the node is currently
+// queried at. Caching according to the DNS TTL is left to the system
+// resolver.
+pub async fn resolve_rpc_address(rpc_url: String) -> Result<SocketAddr, io::Error> {
+    match task::spawn_blocking(move || rpc_url.to_socket_addrs()).await {
+        Ok(result) => result?
+            .next()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "the host has no addresses")),
+        Err(e) => Err(io::Error::other(e)),
+    }
+}
+
+// Summarizes a getblocktemplate result. Only the fields needed to compare
+// the templates of nodes are parsed.
+fn parse_block_template(raw: &Value) -> Result<BlockTemplate, FetchError> {
+    let height = raw["height"].as_u64().ok_or_else(|| {
+        FetchError::DataError("getblocktemplate result without a height".to_string())
+    })?;
+    let transactions = raw["transactions"].as_array().ok_or_else(|| {
+        FetchError::DataError("getblocktemplate result without transactions".to_string())
+    })?;
+    let mut total_fees: u64 = 0;
+    for tx in transactions.iter() {
+        total_fees += tx["fee"].as_u64().ok_or_else(|| {
+            FetchError::DataError("getblocktemplate transaction without a fee".to_string())
+        })?;
+    }
+    Ok(BlockTemplate {
+        height,
+        total_fees,
+        tx_count: transactions.len(),
+    })
+}
+
+// If a waitfornewblock result has another tip than the last one. Also
+// returned on a timeout, then with the same tip.
+fn is_new_tip(tip: &Value, last_hash: &mut Option<String>) -> bool {
+    let hash = tip.get("hash").and_then(Value::as_str).map(String::from);
+    if hash == *last_hash {
+        return false;
+    }
+    *last_hash = hash;
+    true
+}
+
+// The ids of the peers in a getpeerinfo result serving the full block
+// history, the ones a block of an old fork can be requested from. Outbound
+// peers, which the node chose itself, come first.
+fn parse_block_serving_peers(raw: &Value) -> Result<Vec<u64>, FetchError> {
+    let peers = raw
+        .as_array()
+        .ok_or_else(|| FetchError::DataError("getpeerinfo result is not an array".to_string()))?;
+    let mut serving: Vec<(bool, u64)> = peers
+        .iter()
+        .filter(|peer| {
+            peer["servicesnames"]
+                .as_array()
+                .is_some_and(|names| names.iter().any(|name| name == "NETWORK"))
+        })
+        .filter_map(|peer| {
+            Some((
+                peer["inbound"].as_bool().unwrap_or(true),
+                peer["id"].as_u64()?,
+            ))
+        })
+        .collect();
+    serving.sort();
+    Ok(serving.into_iter().map(|(_, id)| id).collect())
+}
+
+// Summarizes a getmemoryinfo result with the uptime of the node.
+fn parse_memory_info(raw: &Value, uptime: u64) -> Result<NodeResources, FetchError> {
+    let locked = &raw["locked"];
+    let field = |name: &str| {
+        locked[name].as_u64().ok_or_else(|| {
+            FetchError::DataError(format!("getmemoryinfo result without locked.{}", name))
+        })
+    };
+    Ok(NodeResources {
+        uptime,
+        memory_used: field("used")?,
+        memory_free: field("free")?,
+        memory_total: field("total")?,
+        memory_locked: field("locked")?,
+    })
+}
+
+/// Number of headers requested per REST call. Grows while the node answers
+/// quickly and shrinks when responses are slow or fail, e.g. because a
+/// reverse proxy caps the response size.
+pub struct RestStepSize {
+    current: AtomicU64,
+    // Lowered when a step size was rejected by the node (or a proxy in
+    // front of it) to avoid growing into the rejected size again.
+    ceiling: AtomicU64,
+    max: u64,
+}
+
+impl RestStepSize {
+    pub fn new(max: u64) -> Self {
+        RestStepSize {
+            current: AtomicU64::new(max),
+            ceiling: AtomicU64::new(max),
+            max,
+        }
+    }
+
+    pub fn get(&self) -> u64 {
+        self.current.load(Ordering::Relaxed)
+    }
+
+    fn set(&self, step_size: u64) {
+        let lower = min(MIN_REST_STEP_SIZE, self.max);
+        let upper = max(lower, self.ceiling.load(Ordering::Relaxed));
+        self.current
+            .store(step_size.clamp(lower, upper), Ordering::Relaxed);
+    }
+
+    /// Adjusts the step size based on the latency of a successful response.
+    pub fn on_success(&self, elapsed: Duration) {
+        let current = self.get();
+        if elapsed <= REST_FAST_RESPONSE {
+            self.set(current.saturating_mul(2));
+        } else if elapsed >= REST_SLOW_RESPONSE {
+            self.set(current / 2);
+        }
+    }
+
+    /// Halves the step size after a failed request with `step_size` headers.
+    /// If the node rejected the request, the step size won't grow back to
+    /// the rejected size. Returns false if the step size is already at its
+    /// minimum and retrying with a smaller one is pointless.
+    pub fn on_failure(&self, step_size: u64, rejected: bool) -> bool {
+        if rejected {
+            self.ceiling
+                .fetch_min(step_size.saturating_sub(1), Ordering::Relaxed);
+        }
+        self.set(step_size / 2);
+        self.get() < step_size
+    }
+}
 
 #[async_trait]
 pub trait Node: Sync {
     fn info(&self) -> NodeInfo;
     fn use_rest(&self) -> bool;
+    fn rest_step_size(&self) -> &RestStepSize;
     fn rpc_url(&self) -> String;
+
+    /// The host:port the node is reached at over the network, resolved
+    /// again before each query to follow DNS-based failover. None for
+    /// backends without a network host, e.g. a UNIX domain socket, and
+    /// for nodes whose host is resolved by a SOCKS5 proxy.
+    fn host(&self) -> Option<String> {
+        Some(self.rpc_url())
+    }
+
     async fn version(&self) -> Result<String, FetchError>;
     async fn block_header(&self, hash: &BlockHash) -> Result<Header, FetchError>;
     async fn block_hash(&self, height: u64) -> Result<BlockHash, FetchError>;
     async fn tips(&self) -> Result<Vec<ChainTip>, FetchError>;
     async fn coinbase(&self, hash: &BlockHash) -> Result<Transaction, FetchError>;
 
+    /// The block template the node would mine on. Backends without a
+    /// mempool or getblocktemplate return none.
+    async fn block_template(&self) -> Result<Option<BlockTemplate>, FetchError> {
+        Ok(None)
+    }
+
+    /// Fetches the full block. Backends that only serve the coinbase
+    /// transaction return none.
+    async fn block(&self, _hash: &BlockHash) -> Result<Option<Block>, FetchError> {
+        Ok(None)
+    }
+
+    /// The height of the node's best known header and of its validated tip
+    /// (getblockchaininfo headers and blocks). Backends without them
+    /// return none.
+    async fn header_and_block_heights(&self) -> Result<Option<(u64, u64)>, FetchError> {
+        Ok(None)
+    }
+
+    /// Asks the node to download the block, whose header it knows, from one
+    /// of its peers. The block arrives later. Returns false if the backend
+    /// can't request blocks from peers, e.g. Bitcoin Core before v23.
+    async fn request_block_from_peer(&self, _hash: &BlockHash) -> Result<bool, FetchError> {
+        Ok(false)
+    }
+
+    /// The node's uptime and memory usage. Backends without them, or with
+    /// resource_telemetry disabled, return none.
+    async fn resources(&self) -> Result<Option<NodeResources>, FetchError> {
+        Ok(None)
+    }
+
+    /// How the headers fetched via block_header() and block_headers() are
+    /// received. Headers fetched via REST are always from the REST
+    /// interface.
+    fn header_source(&self) -> HeaderSource {
+        HeaderSource::Rpc
+    }
+
+    /// If the headers are currently fetched via REST. Unlike use_rest(),
+    /// which is configured, this reflects whether the node's REST interface
+    /// is actually available.
+    fn rest_available(&self) -> bool {
+        self.use_rest()
+    }
+
+    /// The hash of the node's best block, cheaper to fetch than the chain
+    /// tips. Backends without a cheaper call return none, so their tips
+    /// are always queried.
+    async fn best_block_hash(&self) -> Result<Option<BlockHash>, FetchError> {
+        Ok(None)
+    }
+
+    /// Probes whether the node's REST interface is available, if REST is
+    /// enabled for the node. Called before each query of the node, so
+    /// backends rate-limit the probing themselves.
+    async fn detect_rest(&self) {}
+
+    /// Called when a REST request failed and can't be retried. Returns
+    /// true if the node falls back to RPC until REST is detected again.
+    fn on_rest_failure(&self) -> bool {
+        false
+    }
+
+    /// Notified when the node pushes a new block, so its tips can be
+    /// queried without waiting for the next query interval. Backends
+    /// without push notifications return none.
+    fn block_notifications(&self) -> Option<Arc<Notify>> {
+        None
+    }
+
+    /// Number of block hashes or headers fetched per batch request when the
+    /// active chain headers are loaded via RPC. Backends without batch
+    /// requests fetch them one by one.
+    fn rpc_batch_size(&self) -> u64 {
+        1
+    }
+
+    /// Fetches the hashes of the blocks at the heights, in order.
+    async fn block_hashes(&self, heights: &[u64]) -> Result<Vec<BlockHash>, FetchError> {
+        let mut hashes = Vec::with_capacity(heights.len());
+        for height in heights {
+            hashes.push(self.block_hash(*height).await?);
+        }
+        Ok(hashes)
+    }
+
+    /// Fetches the headers of the blocks, in order.
+    async fn block_headers(&self, hashes: &[BlockHash]) -> Result<Vec<Header>, FetchError> {
+        let mut headers = Vec::with_capacity(hashes.len());
+        for hash in hashes {
+            headers.push(self.block_header(hash).await?);
+        }
+        Ok(headers)
+    }
+
+    /// Fetches the Elements headers of the blocks, in order. Only Elements
+    /// backends return them, the headers of other backends are fetched via
+    /// block_headers().
+    async fn elements_headers(
+        &self,
+        _hashes: &[BlockHash],
+    ) -> Result<Option<Vec<ElementsHeader>>, FetchError> {
+        Ok(None)
+    }
+
+    /// Fetches the headers of the blocks at the heights.
+    async fn header_infos(
+        &self,
+        blocks: &[(u64, BlockHash)],
+    ) -> Result<Vec<HeaderInfo>, FetchError> {
+        let hashes: Vec<BlockHash> = blocks.iter().map(|(_, hash)| *hash).collect();
+        let headers: Vec<(Header, Option<Box<ElementsHeader>>)> =
+            match self.elements_headers(&hashes).await? {
+                Some(elements_headers) => elements_headers
+                    .into_iter()
+                    .map(|elements| (elements.to_header(), Some(Box::new(elements))))
+                    .collect(),
+                None => self
+                    .block_headers(&hashes)
+                    .await?
+                    .into_iter()
+                    .map(|header| (header, None))
+                    .collect(),
+            };
+        Ok(blocks
+            .iter()
+            .zip(headers)
+            .map(|((height, _), (header, elements))| HeaderInfo {
+                height: *height,
+                header,
+                miner: DEFAULT_EMPTY_MINER.to_string(),
+                own: false,
+                source: self.header_source(),
+                elements,
+            })
+            .collect())
+    }
+
+    /// Fetches a path of the node's REST interface and returns the status
+    /// code, the reason phrase and the body of the response.
+    fn rest_get(&self, path: &str) -> Result<(i32, String, Vec<u8>), FetchError> {
+        let res = minreq::get(format!("http://{}{}", self.rpc_url(), path))
+            .with_timeout(8)
+            .send()?;
+        Ok((res.status_code, res.reason_phrase.clone(), res.into_bytes()))
+    }
+
+    /// The P2P listening addresses (host, port) the node advertises.
+    /// Backends without advertised addresses return none.
+    async fn p2p_addresses(&self) -> Result<Vec<(String, u16)>, FetchError> {
+        Ok(vec![])
+    }
+
+    /// Returns the chain tips together with the raw JSON result as received
+    /// from the node. Backends without a raw getchaintips result return the
+    /// serialized chain tips.
+    async fn tips_raw(&self) -> Result<(Vec<ChainTip>, Value), FetchError> {
+        let tips = self.tips().await?;
+        let tips_json: Vec<ChainTipJson> = tips.iter().map(ChainTipJson::from).collect();
+        match serde_json::to_value(&tips_json) {
+            Ok(raw) => Ok((tips, raw)),
+            Err(e) => Err(FetchError::DataError(format!(
+                "could not serialize chain tips: {}",
+                e
+            ))),
+        }
+    }
+
     async fn new_headers(
         &self,
-        tips: &Vec<ChainTip>,
+        tips: &[ChainTip],
         tree: &Tree,
         min_fork_height: u64,
     ) -> Result<(Vec<HeaderInfo>, Vec<BlockHash>), FetchError> {
@@ -40,7 +425,7 @@ pub trait Node: Sync {
         // We only want miners for active headers if they are (smaller) tip updates.
         if active_new_headers.len() <= 20 {
             for h in active_new_headers.iter() {
-                headers_needing_miners.push(h.header.block_hash());
+                headers_needing_miners.push(h.block_hash());
             }
         }
         new_headers.append(&mut active_new_headers);
@@ -50,7 +435,7 @@ pub trait Node: Sync {
             .await?;
         // We want miners for all headers in a non-active chain.
         for h in nonactive_new_headers.iter() {
-            headers_needing_miners.push(h.header.block_hash());
+            headers_needing_miners.push(h.block_hash());
         }
         new_headers.append(&mut nonactive_new_headers);
         Ok((new_headers, headers_needing_miners))
@@ -58,7 +443,7 @@ pub trait Node: Sync {
 
     async fn new_active_headers(
         &self,
-        tips: &Vec<ChainTip>,
+        tips: &[ChainTip],
         tree: &Tree,
         min_fork_height: u64,
     ) -> Result<Vec<HeaderInfo>, FetchError> {
@@ -66,8 +451,7 @@ pub trait Node: Sync {
 
         let active_tip = match tips
             .iter()
-            .filter(|tip| tip.status == ChainTipStatus::Active)
-            .last()
+            .rfind(|tip| tip.status == ChainTipStatus::Active)
         {
             Some(active_tip) => active_tip,
             None => {
@@ -76,22 +460,54 @@ pub trait Node: Sync {
                 )))
             }
         };
-        const STEP_SIZE: i64 = 2000;
         let mut query_height: i64 = active_tip.height as i64;
+        let mut rpc_batch_size: i64 = 1;
         loop {
-            if self.use_rest() {
+            if self.rest_available() {
+                let step_size = self.rest_step_size().get() as i64;
                 // We want to either start to query blocks at the `min_fork_height` or
-                // the `tip height - STEP_SIZE + 1` which ever is larger.
+                // the `tip height - step_size + 1` which ever is larger.
                 // (+ 1 as we would otherwise not query the tip)
-                let rest_query_height = max(min_fork_height as i64, query_height - STEP_SIZE + 1);
+                let rest_query_height = max(min_fork_height as i64, query_height - step_size + 1);
                 let mut already_knew_a_header = false;
-                // get the header hash for a header STEP_SIZE away from query_height
+                // get the header hash for a header step_size away from query_height
                 let header_hash = self.block_hash(rest_query_height as u64).await?;
 
-                // get STEP_SIZE headers
-                let headers = self
-                    .active_chain_headers_rest(STEP_SIZE as u64, header_hash)
-                    .await?;
+                // get step_size headers
+                let request_start = Instant::now();
+                let headers = match self
+                    .active_chain_headers_rest(step_size as u64, header_hash)
+                    .await
+                {
+                    Ok(headers) => {
+                        self.rest_step_size().on_success(request_start.elapsed());
+                        headers
+                    }
+                    Err(e) => {
+                        // Only a size-related failure caps the step size,
+                        // not e.g. a timeout or an unavailable node.
+                        let rejected = matches!(e, FetchError::BitcoinCoreRESTTooLarge(_));
+                        if self.rest_step_size().on_failure(step_size as u64, rejected) {
+                            warn!(
+                                "Could not load {} headers via REST from node {}, retrying with {} headers: {}",
+                                step_size,
+                                self.info(),
+                                self.rest_step_size().get(),
+                                e
+                            );
+                            continue;
+                        }
+                        if self.on_rest_failure() {
+                            warn!(
+                                "Could not load headers via REST from node {}, falling back to RPC: {}",
+                                self.info(),
+                                e
+                            );
+                            continue;
+                        }
+                        return Err(e);
+                    }
+                };
 
                 // zip heights and headers up and to iterate through them by descending height
                 // newest first
@@ -108,6 +524,9 @@ pub trait Node: Sync {
                             header: *height_header_pair.0,
                             height: height_header_pair.1 as u64,
                             miner: DEFAULT_EMPTY_MINER.to_string(),
+                            own: false,
+                            source: HeaderSource::Rest,
+                            elements: None,
                         });
                     } else {
                         already_knew_a_header = true;
@@ -118,23 +537,38 @@ pub trait Node: Sync {
                     break;
                 }
 
-                query_height -= STEP_SIZE;
+                query_height -= step_size;
             } else {
                 // using RPC, not using REST
-                let header_hash = self.block_hash(query_height as u64).await?;
-                {
+                // The batch size doubles with each round, so a tip update
+                // stays a single request while a sync catches up quickly.
+                let batch_size = max(
+                    1,
+                    min(rpc_batch_size, query_height - min_fork_height as i64 + 1),
+                );
+                let heights: Vec<u64> = (query_height - batch_size + 1..=query_height)
+                    .rev()
+                    .map(|height| height as u64)
+                    .collect();
+                let hashes = self.block_hashes(&heights).await?;
+                // newest first, up to the first header we already know
+                let unknown: Vec<(u64, BlockHash)> = {
                     let locked_tree = tree.lock().await;
-                    if locked_tree.1.contains_key(&header_hash) {
-                        break;
-                    }
+                    heights
+                        .into_iter()
+                        .zip(hashes)
+                        .take_while(|(_, hash)| !locked_tree.1.contains_key(hash))
+                        .collect()
+                };
+                let already_knew_a_header = (unknown.len() as i64) < batch_size;
+                if !unknown.is_empty() {
+                    new_headers.append(&mut self.header_infos(&unknown).await?);
+                }
+                if already_knew_a_header {
+                    break;
                 }
-                let header = self.block_header(&header_hash).await?;
-                new_headers.push(HeaderInfo {
-                    height: query_height as u64,
-                    header,
-                    miner: DEFAULT_EMPTY_MINER.to_string(),
-                });
-                query_height -= 1;
+                query_height -= batch_size;
+                rpc_batch_size = min(rpc_batch_size * 2, self.rpc_batch_size() as i64);
             }
 
             if query_height < min_fork_height as i64 {
@@ -147,7 +581,7 @@ pub trait Node: Sync {
 
     async fn new_nonactive_headers(
         &self,
-        tips: &Vec<ChainTip>,
+        tips: &[ChainTip],
         tree: &Tree,
         min_fork_height: u64,
     ) -> Result<Vec<HeaderInfo>, FetchError> {
@@ -172,14 +606,17 @@ pub trait Node: Sync {
                     next_header, height
                 );
 
-                let header = self.block_header(&next_header).await?;
-
-                new_headers.push(HeaderInfo {
-                    height,
-                    header,
-                    miner: DEFAULT_EMPTY_MINER.to_string(),
-                });
-                next_header = header.prev_blockhash;
+                let header_info = match self.header_infos(&[(height, next_header)]).await?.pop() {
+                    Some(header_info) => header_info,
+                    None => {
+                        return Err(FetchError::DataError(format!(
+                            "no header for {}",
+                            next_header
+                        )))
+                    }
+                };
+                next_header = header_info.header.prev_blockhash;
+                new_headers.push(header_info);
             }
         }
         Ok(new_headers)
@@ -190,48 +627,36 @@ pub trait Node: Sync {
         count: u64,
         start: BlockHash,
     ) -> Result<Vec<Header>, FetchError> {
-        assert!(self.use_rest());
+        assert!(self.rest_available());
         debug!(
             "loading active-chain headers starting from {}",
             start.to_string()
         );
 
-        let url = format!(
-            "http://{}/rest/headers/{}/{}.bin",
-            self.rpc_url(),
-            count,
-            start
-        );
-        let res = minreq::get(url.clone()).with_timeout(8).send()?;
+        let path = format!("/rest/headers/{}/{}.bin", count, start);
+        let (status_code, reason_phrase, body) = self.rest_get(&path)?;
 
-        if res.status_code != 200 {
+        if rest_headers_too_large(status_code, body.len()) {
+            return Err(FetchError::BitcoinCoreRESTTooLarge(format!(
+                "could not load {} headers from REST path ({}): {} {} with {} bytes",
+                count,
+                path,
+                status_code,
+                reason_phrase,
+                body.len(),
+            )));
+        }
+        if status_code != 200 {
             return Err(FetchError::BitcoinCoreREST(format!(
-                "could not load headers from REST URL ({}): {} {}: {:?}",
-                url,
-                res.status_code,
-                res.reason_phrase,
-                res.as_str(),
+                "could not load headers from REST path ({}): {} {}: {:?}",
+                path,
+                status_code,
+                reason_phrase,
+                String::from_utf8_lossy(&body),
             )));
         }
 
-        let header_results: Result<
-            Vec<Header>,
-            bitcoincore_rpc::bitcoin::consensus::encode::Error,
-        > = res
-            .as_bytes()
-            .chunks(80)
-            .map(bitcoin::consensus::deserialize::<Header>)
-            .collect();
-
-        let headers = match header_results {
-            Ok(headers) => headers,
-            Err(e) => {
-                return Err(FetchError::BitcoinCoreREST(format!(
-                    "could not deserialize REST header response: {}",
-                    e
-                )))
-            }
-        };
+        let headers = parse_rest_headers(&body)?;
 
         debug!(
             "loaded {} active-chain headers starting from {}",
@@ -241,46 +666,260 @@ pub trait Node: Sync {
 
         Ok(headers)
     }
-}
 
-#[derive(Hash, Clone)]
-pub struct NodeInfo {
-    pub id: u32,
-    pub name: String,
-    pub description: String,
-    pub implementation: String,
-}
+    async fn best_block_hash_rest(&self) -> Result<BlockHash, FetchError> {
+        assert!(self.rest_available());
+        let path = "/rest/chaininfo.json";
+        let (status_code, reason_phrase, body) = self.rest_get(path)?;
 
-impl fmt::Display for NodeInfo {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(
-            f,
-            "Node(id={}, name='{}', implementation='{}')",
-            self.id, self.name, self.implementation
-        )
+        if status_code != 200 {
+            return Err(FetchError::BitcoinCoreREST(format!(
+                "could not load the chain info from REST path ({}): {} {}: {:?}",
+                path,
+                status_code,
+                reason_phrase,
+                String::from_utf8_lossy(&body),
+            )));
+        }
+
+        serde_json::from_slice::<Value>(&body)
+            .ok()
+            .and_then(|info| {
+                info.get("bestblockhash")
+                    .and_then(Value::as_str)
+                    .and_then(|hash| BlockHash::from_str(hash).ok())
+            })
+            .ok_or_else(|| {
+                FetchError::BitcoinCoreREST(format!(
+                    "no bestblockhash in REST chain info response: {:?}",
+                    String::from_utf8_lossy(&body),
+                ))
+            })
+    }
+
+    async fn block_hash_rest(&self, height: u64) -> Result<BlockHash, FetchError> {
+        assert!(self.rest_available());
+        let path = format!("/rest/blockhashbyheight/{}.bin", height);
+        let (status_code, reason_phrase, body) = self.rest_get(&path)?;
+
+        if status_code != 200 {
+            return Err(FetchError::BitcoinCoreREST(format!(
+                "could not load the block hash from REST path ({}): {} {}: {:?}",
+                path,
+                status_code,
+                reason_phrase,
+                String::from_utf8_lossy(&body),
+            )));
+        }
+
+        bitcoin::consensus::deserialize::<BlockHash>(&body).map_err(|e| {
+            FetchError::BitcoinCoreREST(format!(
+                "could not deserialize REST block hash response: {}",
+                e
+            ))
+        })
     }
 }
 
-#[derive(Hash, Clone)]
 pub struct BitcoinCoreNode {
     info: NodeInfo,
     rpc_url: String,
     rpc_auth: Auth,
     use_rest: bool,
+    // Whether the node's REST interface answered the last probe and didn't
+    // fail since, and when it was last probed or failed.
+    rest_detected: AtomicBool,
+    rest_probed: std::sync::Mutex<Option<Instant>>,
+    rest_step_size: RestStepSize,
+    // Set if the RPC and REST interfaces are served over HTTPS or reached
+    // through a SOCKS5 proxy.
+    endpoint: Option<Arc<HttpEndpoint>>,
+    // Subscribe to new block hashes via the node's -zmqpubhashblock socket.
+    zmq_hashblock: Option<ZmqEndpoint>,
+    // Wait for new blocks with waitfornewblock in a dedicated thread.
+    longpoll: bool,
+    // Query the uptime and getmemoryinfo RPCs.
+    resource_telemetry: bool,
+    rpc_timeout: Duration,
+    rest_timeout: Duration,
+    // The RPC client shared by the calls, so its HTTP connection to the
+    // node is kept alive. Dropped after transport errors and recreated on
+    // the next call.
+    rpc: std::sync::Mutex<Option<Arc<Client>>>,
+    // Whether the node's version has getblockfrompeer, once known, and its
+    // peers serving blocks with when they were queried.
+    getblockfrompeer: std::sync::Mutex<Option<bool>>,
+    block_serving_peers: std::sync::Mutex<Option<(Instant, Vec<u64>)>>,
+    block_notify: Arc<Notify>,
+    subscribe: Once,
 }
 
 impl BitcoinCoreNode {
-    pub fn new(info: NodeInfo, rpc_url: String, rpc_auth: Auth, use_rest: bool) -> Self {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        info: NodeInfo,
+        rpc_url: String,
+        rpc_auth: Auth,
+        use_rest: bool,
+        rest_max_step_size: u64,
+        endpoint: Option<HttpEndpoint>,
+        zmq_hashblock: Option<ZmqEndpoint>,
+        longpoll: bool,
+        resource_telemetry: bool,
+        rpc_timeout: Duration,
+        rest_timeout: Duration,
+    ) -> Self {
         BitcoinCoreNode {
             info,
             rpc_url,
             rpc_auth,
             use_rest,
+            rest_detected: AtomicBool::new(false),
+            rest_probed: std::sync::Mutex::new(None),
+            rest_step_size: RestStepSize::new(rest_max_step_size),
+            endpoint: endpoint.map(Arc::new),
+            zmq_hashblock,
+            longpoll,
+            resource_telemetry,
+            rpc_timeout,
+            rest_timeout,
+            rpc: std::sync::Mutex::new(None),
+            getblockfrompeer: std::sync::Mutex::new(None),
+            block_serving_peers: std::sync::Mutex::new(None),
+            block_notify: Arc::new(Notify::new()),
+            subscribe: Once::new(),
+        }
+    }
+
+    // Whether the node has getblockfrompeer. The version is only queried
+    // until it's known.
+    async fn supports_getblockfrompeer(&self) -> Result<bool, FetchError> {
+        if let Some(supported) = self.getblockfrompeer.lock().ok().and_then(|s| *s) {
+            return Ok(supported);
         }
+        let info = self.rpc_call(|rpc| rpc.get_network_info()).await?;
+        let supported = info.version as u64 >= MIN_GETBLOCKFROMPEER_VERSION;
+        if let Ok(mut getblockfrompeer) = self.getblockfrompeer.lock() {
+            *getblockfrompeer = Some(supported);
+        }
+        Ok(supported)
+    }
+
+    // The ids of the node's peers serving blocks, queried at most every
+    // BLOCK_SERVING_PEERS_TTL.
+    async fn block_serving_peers(&self) -> Result<Vec<u64>, FetchError> {
+        if let Ok(block_serving_peers) = self.block_serving_peers.lock() {
+            if let Some((queried, peers)) = block_serving_peers.as_ref() {
+                if queried.elapsed() < BLOCK_SERVING_PEERS_TTL {
+                    return Ok(peers.clone());
+                }
+            }
+        }
+        let raw = self
+            .rpc_call(|rpc| rpc.call::<Value>("getpeerinfo", &[]))
+            .await?;
+        let peers = parse_block_serving_peers(&raw)?;
+        if let Ok(mut block_serving_peers) = self.block_serving_peers.lock() {
+            *block_serving_peers = Some((Instant::now(), peers.clone()));
+        }
+        Ok(peers)
+    }
+
+    // Keeps a ZMQ subscription to the node's new block hashes in a thread,
+    // reconnecting after errors.
+    fn spawn_subscription(&self, endpoint: ZmqEndpoint) {
+        let info = self.info.clone();
+        let block_notify = self.block_notify.clone();
+        std::thread::spawn(move || loop {
+            let on_block = || block_notify.notify_one();
+            if let Err(e) = zmq::subscribe(&endpoint, zmq::TOPIC_HASHBLOCK, &on_block) {
+                warn!(
+                    "The ZMQ hashblock subscription of {} failed: {}. Reconnecting in {}s..",
+                    info,
+                    e,
+                    ZMQ_RECONNECT_DELAY.as_secs()
+                );
+            }
+            std::thread::sleep(ZMQ_RECONNECT_DELAY);
+        });
+    }
+
+    // Calls waitfornewblock in a loop in a thread. It returns the tip once a
+    // new block is connected or the timeout passed.
+    fn spawn_longpoll(&self) {
+        // A dedicated client, as waitfornewblock blocks the connection.
+        let client = match self
+            .new_rpc_client_with_timeout(self.rpc_timeout + WAITFORNEWBLOCK_TIMEOUT)
+        {
+            Ok(client) => client,
+            Err(e) => {
+                error!(
+                    "Could not create the waitfornewblock RPC client of {}: {}. New blocks are only detected each query interval.",
+                    self.info(),
+                    e
+                );
+                return;
+            }
+        };
+        let info = self.info.clone();
+        let block_notify = self.block_notify.clone();
+        let timeout = WAITFORNEWBLOCK_TIMEOUT.as_millis() as u64;
+        std::thread::spawn(move || {
+            let mut last_hash: Option<String> = None;
+            loop {
+                match client.call::<Value>("waitfornewblock", &[timeout.into()]) {
+                    Ok(tip) => {
+                        if is_new_tip(&tip, &mut last_hash) {
+                            debug!("waitfornewblock on {} returned a new tip", info);
+                            block_notify.notify_one();
+                        }
+                    }
+                    Err(e) => {
+                        warn!(
+                            "The waitfornewblock RPC of {} failed: {}. Retrying in {}s..",
+                            info,
+                            e,
+                            WAITFORNEWBLOCK_RETRY_DELAY.as_secs()
+                        );
+                        std::thread::sleep(WAITFORNEWBLOCK_RETRY_DELAY);
+                    }
+                }
+            }
+        });
+    }
+
+    // The transport of the stock RPC client, as created by Client::new but
+    // with the node's timeout.
+    fn simple_http_transport(
+        &self,
+        timeout: Duration,
+    ) -> Result<jsonrpc::simple_http::SimpleHttpTransport, bitcoincore_rpc::Error> {
+        let mut builder = jsonrpc::simple_http::Builder::new()
+            .url(&self.rpc_url)
+            .map_err(|e| bitcoincore_rpc::Error::JsonRpc(e.into()))?
+            .timeout(timeout);
+        if let (Some(user), password) = self.rpc_auth.clone().get_user_pass()? {
+            builder = builder.auth(user, password);
+        }
+        Ok(builder.build())
+    }
+
+    fn new_rpc_client(&self) -> Result<Client, FetchError> {
+        self.new_rpc_client_with_timeout(self.rpc_timeout)
     }
 
-    fn rpc_client(&self) -> Result<Client, FetchError> {
-        match Client::new(&self.rpc_url, self.rpc_auth.clone()) {
+    fn new_rpc_client_with_timeout(&self, timeout: Duration) -> Result<Client, FetchError> {
+        let client = match self.endpoint {
+            Some(ref endpoint) => {
+                HttpTransport::new(endpoint.clone(), self.rpc_auth.clone(), timeout).map(
+                    |transport| Client::from_jsonrpc(jsonrpc::Client::with_transport(transport)),
+                )
+            }
+            None => self
+                .simple_http_transport(timeout)
+                .map(|transport| Client::from_jsonrpc(jsonrpc::Client::with_transport(transport))),
+        };
+        match client {
             Ok(c) => Ok(c),
             Err(e) => {
                 error!(
@@ -292,6 +931,74 @@ impl BitcoinCoreNode {
             }
         }
     }
+
+    fn rpc_client(&self) -> Result<Arc<Client>, FetchError> {
+        let mut rpc = self.rpc.lock().expect("poisoned RPC client mutex");
+        match *rpc {
+            Some(ref client) => Ok(client.clone()),
+            None => {
+                let client = Arc::new(self.new_rpc_client()?);
+                *rpc = Some(client.clone());
+                Ok(client)
+            }
+        }
+    }
+
+    // Makes RPC calls with the shared client in a blocking task. After a
+    // transport error, e.g. a connection reset or a rejected cookie after a
+    // restart of the node, the next call uses a new client.
+    async fn rpc_call<T, F>(&self, call: F) -> Result<T, FetchError>
+    where
+        T: Send + 'static,
+        F: FnOnce(&Client) -> Result<T, bitcoincore_rpc::Error> + Send + 'static,
+    {
+        let rpc = self.rpc_client()?;
+        match task::spawn_blocking(move || call(&rpc)).await {
+            Ok(Ok(result)) => Ok(result),
+            Ok(Err(e)) => {
+                if let bitcoincore_rpc::Error::JsonRpc(jsonrpc::Error::Transport(_)) = e {
+                    *self.rpc.lock().expect("poisoned RPC client mutex") = None;
+                }
+                Err(e.into())
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    // Fetches the hex encoded headers of the blocks in a batch request.
+    pub async fn block_headers_hex(&self, hashes: &[BlockHash]) -> Result<Vec<String>, FetchError> {
+        let params: Vec<Vec<Box<RawValue>>> = hashes
+            .iter()
+            .map(|hash| vec![jsonrpc::arg(hash), jsonrpc::arg(false)])
+            .collect();
+        self.rpc_call(move |rpc| batch_call(rpc, "getblockheader", &params))
+            .await
+    }
+}
+
+// Sends one call of the method per parameter list as a single JSON-RPC batch
+// request and returns the results in order.
+fn batch_call<T: serde::de::DeserializeOwned>(
+    rpc: &Client,
+    method: &str,
+    params: &[Vec<Box<RawValue>>],
+) -> Result<Vec<T>, bitcoincore_rpc::Error> {
+    let client = rpc.get_jsonrpc_client();
+    let requests: Vec<jsonrpc::Request> = params
+        .iter()
+        .map(|params| client.build_request(method, params))
+        .collect();
+    client
+        .send_batch(&requests)?
+        .into_iter()
+        .map(|response| match response {
+            Some(response) => Ok(response.result()?),
+            None => Err(bitcoincore_rpc::Error::ReturnedError(format!(
+                "no response to a {} call of a batch request",
+                method
+            ))),
+        })
+        .collect()
 }
 
 #[async_trait]
@@ -304,113 +1011,389 @@ impl Node for BitcoinCoreNode {
         self.use_rest
     }
 
-    fn rpc_url(&self) -> String {
-        self.rpc_url.clone()
+    fn rest_available(&self) -> bool {
+        self.use_rest && self.rest_detected.load(Ordering::Relaxed)
     }
 
-    async fn version(&self) -> Result<String, FetchError> {
-        let rpc = self.rpc_client()?;
-        match task::spawn_blocking(move || rpc.get_network_info()).await {
-            Ok(result) => match result {
-                Ok(result) => Ok(result.subversion),
-                Err(e) => Err(e.into()),
-            },
-            Err(e) => Err(e.into()),
+    async fn detect_rest(&self) {
+        if !self.use_rest || self.rest_detected.load(Ordering::Relaxed) {
+            return;
+        }
+        let first_probe = match self.rest_probed.lock() {
+            Ok(mut rest_probed) => {
+                if let Some(last) = *rest_probed {
+                    if last.elapsed() < REST_PROBE_INTERVAL {
+                        return;
+                    }
+                }
+                rest_probed.replace(Instant::now()).is_none()
+            }
+            Err(_) => return,
+        };
+        let unavailable = match self.rest_get("/rest/chaininfo.json") {
+            Ok((200, _, _)) => {
+                info!("Using the REST interface of node {}", self.info);
+                self.rest_detected.store(true, Ordering::Relaxed);
+                return;
+            }
+            Ok((status_code, reason_phrase, _)) => format!("{} {}", status_code, reason_phrase),
+            Err(e) => e.to_string(),
+        };
+        if first_probe {
+            warn!(
+                "The REST interface of node {} isn't available ({}), using RPC instead. Start the node with -rest or set use_rest = false.",
+                self.info, unavailable
+            );
+        } else {
+            debug!(
+                "The REST interface of node {} is still not available: {}",
+                self.info, unavailable
+            );
         }
     }
 
-    async fn block_hash(&self, height: u64) -> Result<BlockHash, FetchError> {
-        let rpc = self.rpc_client()?;
-        match task::spawn_blocking(move || rpc.get_block_hash(height)).await {
-            Ok(result) => match result {
-                Ok(result) => Ok(result),
-                Err(e) => Err(e.into()),
-            },
-            Err(e) => Err(e.into()),
+    fn on_rest_failure(&self) -> bool {
+        if let Ok(mut rest_probed) = self.rest_probed.lock() {
+            *rest_probed = Some(Instant::now());
         }
+        self.rest_detected.swap(false, Ordering::Relaxed)
     }
 
-    async fn block_header(&self, hash: &BlockHash) -> Result<Header, FetchError> {
-        let rpc = self.rpc_client()?;
-        let hash_clone = hash.clone();
-        match task::spawn_blocking(move || rpc.get_block_header(&hash_clone)).await {
-            Ok(result) => match result {
-                Ok(result) => Ok(result),
-                Err(e) => Err(e.into()),
-            },
-            Err(e) => Err(e.into()),
+    fn rest_step_size(&self) -> &RestStepSize {
+        &self.rest_step_size
+    }
+
+    fn rpc_url(&self) -> String {
+        self.rpc_url.clone()
+    }
+
+    // The host of a node behind a SOCKS5 proxy, e.g. an onion service, is
+    // resolved by the proxy and must not leak to the system resolver.
+    fn host(&self) -> Option<String> {
+        if self.endpoint.as_ref().is_some_and(|e| e.proxied()) {
+            return None;
         }
+        Some(self.rpc_url.clone()).filter(|url| !url.starts_with(UNIX_SOCKET_PREFIX))
     }
 
-    async fn coinbase(&self, hash: &BlockHash) -> Result<Transaction, FetchError> {
-        let rpc = self.rpc_client()?;
-        let hash_clone = hash.clone();
-        match task::spawn_blocking(move || rpc.get_block(&hash_clone)).await {
-            Ok(result) => match result {
-                Ok(result) => Ok(result
-                    .txdata
-                    .first()
-                    .expect("Block should have a coinbase transaction")
-                    .clone()),
-                Err(e) => Err(e.into()),
-            },
-            Err(e) => Err(e.into()),
+    fn block_notifications(&self) -> Option<Arc<Notify>> {
+        if self.zmq_hashblock.is_none() && !self.longpoll {
+            return None;
         }
+        self.subscribe.call_once(|| {
+            if let Some(ref endpoint) = self.zmq_hashblock {
+                self.spawn_subscription(endpoint.clone());
+            }
+            if self.longpoll {
+                self.spawn_longpoll();
+            }
+        });
+        Some(self.block_notify.clone())
     }
 
-    async fn tips(&self) -> Result<Vec<ChainTip>, FetchError> {
-        let rpc = self.rpc_client()?;
-        match task::spawn_blocking(move || rpc.get_chain_tips()).await {
-            Ok(tips_result) => match tips_result {
-                Ok(tips) => Ok(tips.iter().map(|t| t.clone().into()).collect()),
-                Err(e) => Err(e.into()),
-            },
-            Err(e) => Err(e.into()),
+    fn rest_get(&self, path: &str) -> Result<(i32, String, Vec<u8>), FetchError> {
+        match self.endpoint {
+            Some(ref endpoint) => {
+                let res = endpoint
+                    .request("GET", path, &[], &[], self.rest_timeout)
+                    .map_err(|e| {
+                        FetchError::BitcoinCoreREST(format!(
+                            "could not request {}{}: {}",
+                            endpoint.url(),
+                            path,
+                            e
+                        ))
+                    })?;
+                Ok((res.status_code as i32, res.reason_phrase, res.body))
+            }
+            None => {
+                let res = minreq::get(format!("http://{}{}", self.rpc_url, path))
+                    .with_timeout(self.rest_timeout.as_secs())
+                    .send()?;
+                Ok((res.status_code, res.reason_phrase.clone(), res.into_bytes()))
+            }
         }
     }
-}
 
-#[derive(Hash, Clone)]
-pub struct BtcdNode {
-    info: NodeInfo,
-    rpc_url: String,
-    rpc_user: String,
-    rpc_password: String,
-}
+    async fn version(&self) -> Result<String, FetchError> {
+        let info = self.rpc_call(|rpc| rpc.get_network_info()).await?;
+        Ok(info.subversion)
+    }
 
-impl BtcdNode {
-    pub fn new(info: NodeInfo, rpc_url: String, rpc_user: String, rpc_password: String) -> Self {
-        BtcdNode {
-            info,
-            rpc_url,
-            rpc_user,
-            rpc_password,
+    async fn p2p_addresses(&self) -> Result<Vec<(String, u16)>, FetchError> {
+        let info = self.rpc_call(|rpc| rpc.get_network_info()).await?;
+        Ok(info
+            .local_addresses
+            .into_iter()
+            .map(|a| (a.address, a.port as u16))
+            .collect())
+    }
+
+    async fn block_hash(&self, height: u64) -> Result<BlockHash, FetchError> {
+        if self.rest_available() {
+            match self.block_hash_rest(height).await {
+                Ok(hash) => return Ok(hash),
+                Err(e) if self.on_rest_failure() => warn!(
+                    "Could not load the block hash at height {} via REST from node {}, falling back to RPC: {}",
+                    height, self.info, e
+                ),
+                Err(e) => return Err(e),
+            }
         }
+        self.rpc_call(move |rpc| rpc.get_block_hash(height)).await
     }
-}
 
-#[async_trait]
-impl Node for BtcdNode {
-    fn info(&self) -> NodeInfo {
-        self.info.clone()
+    async fn best_block_hash(&self) -> Result<Option<BlockHash>, FetchError> {
+        if self.rest_available() {
+            match self.best_block_hash_rest().await {
+                Ok(hash) => return Ok(Some(hash)),
+                Err(e) if self.on_rest_failure() => warn!(
+                    "Could not load the best block hash via REST from node {}, falling back to RPC: {}",
+                    self.info, e
+                ),
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(Some(self.rpc_call(|rpc| rpc.get_best_block_hash()).await?))
     }
 
-    fn use_rest(&self) -> bool {
-        BTCD_USE_REST
+    fn rpc_batch_size(&self) -> u64 {
+        BITCOIN_CORE_RPC_BATCH_SIZE
     }
 
-    fn rpc_url(&self) -> String {
-        self.rpc_url.clone()
+    async fn block_hashes(&self, heights: &[u64]) -> Result<Vec<BlockHash>, FetchError> {
+        let params: Vec<Vec<Box<RawValue>>> = heights
+            .iter()
+            .map(|height| vec![jsonrpc::arg(height)])
+            .collect();
+        self.rpc_call(move |rpc| batch_call(rpc, "getblockhash", &params))
+            .await
     }
 
-    async fn version(&self) -> Result<String, FetchError> {
-        Err(FetchError::BtcdRPC(JsonRPCError::NotImplemented))
+    async fn block_headers(&self, hashes: &[BlockHash]) -> Result<Vec<Header>, FetchError> {
+        self.block_headers_hex(hashes)
+            .await?
+            .iter()
+            .map(|header_hex| {
+                hex::decode(header_hex)
+                    .ok()
+                    .and_then(|bytes| bitcoin::consensus::deserialize(&bytes).ok())
+                    .ok_or_else(|| {
+                        FetchError::DataError(format!("invalid block header {}", header_hex))
+                    })
+            })
+            .collect()
     }
 
     async fn block_header(&self, hash: &BlockHash) -> Result<Header, FetchError> {
-        let url = format!("http://{}/", self.rpc_url);
-        match crate::jsonrpc::btcd_blockheader(
-            url,
+        let hash = *hash;
+        self.rpc_call(move |rpc| rpc.get_block_header(&hash)).await
+    }
+
+    async fn coinbase(&self, hash: &BlockHash) -> Result<Transaction, FetchError> {
+        self.block(hash)
+            .await?
+            .and_then(|block| block.txdata.into_iter().next())
+            .ok_or_else(|| FetchError::DataError(format!("block {} without a coinbase", hash)))
+    }
+
+    async fn block(&self, hash: &BlockHash) -> Result<Option<Block>, FetchError> {
+        let hash = *hash;
+        Ok(Some(self.rpc_call(move |rpc| rpc.get_block(&hash)).await?))
+    }
+
+    async fn request_block_from_peer(&self, hash: &BlockHash) -> Result<bool, FetchError> {
+        if !self.supports_getblockfrompeer().await? {
+            return Ok(false);
+        }
+        let peers = self.block_serving_peers().await?;
+        let mut last_error = FetchError::DataError(format!(
+            "no peer of node {} serves block {}",
+            self.info, hash
+        ));
+        for peer in peers {
+            let hash = *hash;
+            match self
+                .rpc_call(move |rpc| {
+                    rpc.call::<Value>(
+                        "getblockfrompeer",
+                        &[serde_json::json!(hash), serde_json::json!(peer)],
+                    )
+                })
+                .await
+            {
+                Ok(_) => return Ok(true),
+                Err(e) => {
+                    debug!(
+                        "Could not request block {} from peer {} of node {}: {}",
+                        hash, peer, self.info, e
+                    );
+                    last_error = e;
+                }
+            }
+        }
+        // The peers may have disconnected.
+        if let Ok(mut block_serving_peers) = self.block_serving_peers.lock() {
+            *block_serving_peers = None;
+        }
+        Err(last_error)
+    }
+
+    async fn header_and_block_heights(&self) -> Result<Option<(u64, u64)>, FetchError> {
+        // Only the two fields are parsed, the rest of the result differs
+        // between Bitcoin Core versions.
+        let info = self
+            .rpc_call(|rpc| rpc.call::<Value>("getblockchaininfo", &[]))
+            .await?;
+        match (info["headers"].as_u64(), info["blocks"].as_u64()) {
+            (Some(headers), Some(blocks)) => Ok(Some((headers, blocks))),
+            _ => Err(FetchError::DataError(
+                "getblockchaininfo result without headers or blocks".to_string(),
+            )),
+        }
+    }
+
+    async fn resources(&self) -> Result<Option<NodeResources>, FetchError> {
+        if !self.resource_telemetry {
+            return Ok(None);
+        }
+        let uptime = self.rpc_call(|rpc| rpc.call::<u64>("uptime", &[])).await?;
+        let raw = self
+            .rpc_call(|rpc| rpc.call::<Value>("getmemoryinfo", &[]))
+            .await?;
+        Ok(Some(parse_memory_info(&raw, uptime)?))
+    }
+
+    async fn block_template(&self) -> Result<Option<BlockTemplate>, FetchError> {
+        let rules = serde_json::json!({"rules": ["segwit"]});
+        let raw = self
+            .rpc_call(move |rpc| rpc.call::<Value>("getblocktemplate", &[rules]))
+            .await?;
+        Ok(Some(parse_block_template(&raw)?))
+    }
+
+    async fn tips(&self) -> Result<Vec<ChainTip>, FetchError> {
+        Ok(self.tips_raw().await?.0)
+    }
+
+    async fn tips_raw(&self) -> Result<(Vec<ChainTip>, Value), FetchError> {
+        let raw: Value = self
+            .rpc_call(|rpc| rpc.call::<Value>("getchaintips", &[]))
+            .await?;
+        match serde_json::from_value::<Vec<GetChainTipsResultTip>>(raw.clone()) {
+            Ok(tips) => Ok((tips.iter().map(|t| t.clone().into()).collect(), raw)),
+            Err(e) => Err(FetchError::DataError(format!(
+                "could not parse getchaintips result: {}",
+                e
+            ))),
+        }
+    }
+}
+
+pub struct BtcdNode {
+    info: NodeInfo,
+    rpc_url: String,
+    endpoint: Arc<HttpEndpoint>,
+    rpc_user: String,
+    rpc_password: String,
+    rest_step_size: RestStepSize,
+    // Subscribe to block notifications via btcd's websocket endpoint.
+    websocket: bool,
+    block_notify: Arc<Notify>,
+    subscribe: Once,
+}
+
+impl BtcdNode {
+    pub fn new(
+        info: NodeInfo,
+        rpc_url: String,
+        endpoint: HttpEndpoint,
+        rpc_user: String,
+        rpc_password: String,
+        websocket: bool,
+    ) -> Self {
+        BtcdNode {
+            info,
+            rpc_url,
+            endpoint: Arc::new(endpoint),
+            rpc_user,
+            rpc_password,
+            rest_step_size: RestStepSize::new(DEFAULT_REST_MAX_STEP_SIZE),
+            websocket,
+            block_notify: Arc::new(Notify::new()),
+            subscribe: Once::new(),
+        }
+    }
+
+    // Keeps a websocket subscription to the node's block notifications in a
+    // thread, reconnecting after errors.
+    fn spawn_subscription(&self) {
+        let info = self.info.clone();
+        let endpoint = self.endpoint.clone();
+        let user = self.rpc_user.clone();
+        let password = self.rpc_password.clone();
+        let block_notify = self.block_notify.clone();
+        std::thread::spawn(move || loop {
+            let on_block = || block_notify.notify_one();
+            if let Err(e) =
+                crate::jsonrpc::btcd_notify_blocks(&endpoint, &user, &password, &on_block)
+            {
+                warn!(
+                    "The block notifications of {} failed: {}. Reconnecting in {}s..",
+                    info,
+                    e,
+                    BTCD_WEBSOCKET_RECONNECT_DELAY.as_secs()
+                );
+            }
+            std::thread::sleep(BTCD_WEBSOCKET_RECONNECT_DELAY);
+        });
+    }
+}
+
+#[async_trait]
+impl Node for BtcdNode {
+    fn info(&self) -> NodeInfo {
+        self.info.clone()
+    }
+
+    fn use_rest(&self) -> bool {
+        BTCD_USE_REST
+    }
+
+    fn rest_step_size(&self) -> &RestStepSize {
+        &self.rest_step_size
+    }
+
+    fn rpc_url(&self) -> String {
+        self.rpc_url.clone()
+    }
+
+    fn host(&self) -> Option<String> {
+        Some(self.rpc_url.clone()).filter(|_| !self.endpoint.proxied())
+    }
+
+    fn block_notifications(&self) -> Option<Arc<Notify>> {
+        if !self.websocket {
+            return None;
+        }
+        self.subscribe.call_once(|| self.spawn_subscription());
+        Some(self.block_notify.clone())
+    }
+
+    async fn version(&self) -> Result<String, FetchError> {
+        match crate::jsonrpc::btcd_version(
+            &self.endpoint,
+            self.rpc_user.clone(),
+            self.rpc_password.clone(),
+        ) {
+            Ok(version) => Ok(version),
+            Err(error) => Err(FetchError::BtcdRPC(error)),
+        }
+    }
+
+    async fn block_header(&self, hash: &BlockHash) -> Result<Header, FetchError> {
+        match crate::jsonrpc::btcd_blockheader(
+            &self.endpoint,
             self.rpc_user.clone(),
             self.rpc_password.clone(),
             hash.to_string(),
@@ -421,26 +1404,27 @@ impl Node for BtcdNode {
     }
 
     async fn coinbase(&self, hash: &BlockHash) -> Result<Transaction, FetchError> {
-        let url = format!("http://{}/", self.rpc_url);
+        self.block(hash)
+            .await?
+            .and_then(|block| block.txdata.into_iter().next())
+            .ok_or_else(|| FetchError::DataError(format!("block {} without a coinbase", hash)))
+    }
+
+    async fn block(&self, hash: &BlockHash) -> Result<Option<Block>, FetchError> {
         match crate::jsonrpc::btcd_block(
-            url,
+            &self.endpoint,
             self.rpc_user.clone(),
             self.rpc_password.clone(),
             hash.to_string(),
         ) {
-            Ok(block) => Ok(block
-                .txdata
-                .first()
-                .expect("Block should have a coinbase transaction")
-                .clone()),
+            Ok(block) => Ok(Some(block)),
             Err(error) => Err(FetchError::BtcdRPC(error)),
         }
     }
 
     async fn block_hash(&self, height: u64) -> Result<BlockHash, FetchError> {
-        let url = format!("http://{}/", self.rpc_url);
         match crate::jsonrpc::btcd_blockhash(
-            url,
+            &self.endpoint,
             self.rpc_user.clone(),
             self.rpc_password.clone(),
             height,
@@ -451,11 +1435,1254 @@ impl Node for BtcdNode {
     }
 
     async fn tips(&self) -> Result<Vec<ChainTip>, FetchError> {
-        let url = format!("http://{}/", self.rpc_url);
-        match crate::jsonrpc::btcd_chaintips(url, self.rpc_user.clone(), self.rpc_password.clone())
-        {
+        Ok(self.tips_raw().await?.0)
+    }
+
+    async fn tips_raw(&self) -> Result<(Vec<ChainTip>, Value), FetchError> {
+        match crate::jsonrpc::btcd_chaintips(
+            &self.endpoint,
+            self.rpc_user.clone(),
+            self.rpc_password.clone(),
+        ) {
             Ok(tips) => Ok(tips),
             Err(error) => Err(FetchError::BtcdRPC(error)),
         }
     }
 }
+
+// An Electrum server. The Electrum protocol only exposes the server's active
+// chain and has no getchaintips, so the node only reports its active tip.
+// Headers can only be looked up by height, and blocks aren't served, so
+// miners can't be identified via Electrum servers.
+pub struct ElectrumNode {
+    info: NodeInfo,
+    address: String,
+    rest_step_size: RestStepSize,
+}
+
+impl ElectrumNode {
+    pub fn new(info: NodeInfo, address: String) -> Self {
+        ElectrumNode {
+            info,
+            address,
+            rest_step_size: RestStepSize::new(DEFAULT_REST_MAX_STEP_SIZE),
+        }
+    }
+}
+
+#[async_trait]
+impl Node for ElectrumNode {
+    fn info(&self) -> NodeInfo {
+        self.info.clone()
+    }
+
+    fn use_rest(&self) -> bool {
+        false
+    }
+
+    fn rest_step_size(&self) -> &RestStepSize {
+        &self.rest_step_size
+    }
+
+    fn rpc_url(&self) -> String {
+        self.address.clone()
+    }
+
+    async fn version(&self) -> Result<String, FetchError> {
+        let address = self.address.clone();
+        match task::spawn_blocking(move || crate::electrum::server_version(&address)).await {
+            Ok(result) => result.map_err(FetchError::ElectrumRPC),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    async fn block_header(&self, _hash: &BlockHash) -> Result<Header, FetchError> {
+        Err(FetchError::ElectrumRPC(JsonRPCError::NotImplemented))
+    }
+
+    async fn coinbase(&self, _hash: &BlockHash) -> Result<Transaction, FetchError> {
+        Err(FetchError::ElectrumRPC(JsonRPCError::NotImplemented))
+    }
+
+    async fn block_hash(&self, height: u64) -> Result<BlockHash, FetchError> {
+        let address = self.address.clone();
+        match task::spawn_blocking(move || crate::electrum::block_header(&address, height)).await {
+            Ok(result) => Ok(result.map_err(FetchError::ElectrumRPC)?.block_hash()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    async fn tips(&self) -> Result<Vec<ChainTip>, FetchError> {
+        let address = self.address.clone();
+        match task::spawn_blocking(move || crate::electrum::headers_subscribe(&address)).await {
+            Ok(result) => {
+                let (height, header) = result.map_err(FetchError::ElectrumRPC)?;
+                Ok(vec![ChainTip {
+                    height,
+                    hash: header.block_hash().to_string(),
+                    branchlen: 0,
+                    status: ChainTipStatus::Active,
+                }])
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    // Electrum servers can't look up headers by hash, so the active chain
+    // headers are loaded by height in batches.
+    async fn new_active_headers(
+        &self,
+        tips: &[ChainTip],
+        tree: &Tree,
+        min_fork_height: u64,
+    ) -> Result<Vec<HeaderInfo>, FetchError> {
+        let active_tip = match tips
+            .iter()
+            .rfind(|tip| tip.status == ChainTipStatus::Active)
+        {
+            Some(active_tip) => active_tip,
+            None => {
+                return Err(FetchError::DataError(String::from(
+                    "No 'active' chain tip returned",
+                )))
+            }
+        };
+
+        let mut new_headers: Vec<HeaderInfo> = Vec::new();
+        // Nothing to load when the active tip is below the minimum fork height.
+        if active_tip.height < min_fork_height {
+            return Ok(new_headers);
+        }
+        let mut end_height: u64 = active_tip.height;
+        loop {
+            let start_height = max(
+                min_fork_height,
+                (end_height + 1).saturating_sub(crate::electrum::MAX_HEADERS_PER_REQUEST),
+            );
+            let address = self.address.clone();
+            let count = end_height + 1 - start_height;
+            let headers = match task::spawn_blocking(move || {
+                crate::electrum::block_headers(&address, start_height, count)
+            })
+            .await
+            {
+                Ok(result) => result.map_err(FetchError::ElectrumRPC)?,
+                Err(e) => return Err(e.into()),
+            };
+
+            let mut already_knew_a_header = false;
+            {
+                let locked_tree = tree.lock().await;
+                for (header, height) in headers.iter().zip(start_height..) {
+                    if locked_tree.1.contains_key(&header.block_hash()) {
+                        already_knew_a_header = true;
+                    } else {
+                        new_headers.push(HeaderInfo {
+                            header: *header,
+                            height,
+                            miner: DEFAULT_EMPTY_MINER.to_string(),
+                            own: false,
+                            source: self.header_source(),
+                            elements: None,
+                        });
+                    }
+                }
+            }
+
+            if already_knew_a_header || start_height <= min_fork_height {
+                break;
+            }
+            end_height = start_height - 1;
+        }
+        new_headers.sort_by_key(|h| h.height);
+        Ok(new_headers)
+    }
+}
+
+// An Esplora instance (e.g. blockstream.info or self-hosted) queried via
+// its HTTP API. Esplora only exposes the active chain, so the node only
+// reports its active tip.
+pub struct EsploraNode {
+    info: NodeInfo,
+    url: String,
+    address: String,
+    // mempool.space serves the Esplora API and reports its version.
+    mempool: bool,
+    rest_step_size: RestStepSize,
+}
+
+// The host:port of an HTTP(S) URL. The port defaults to the one of the
+// scheme.
+fn url_address(url: &str) -> String {
+    let (default_port, rest) = match url.split_once("://") {
+        Some(("https", rest)) => (443, rest),
+        Some((_, rest)) => (80, rest),
+        None => (80, url),
+    };
+    let authority = rest.split('/').next().unwrap_or_default();
+    match authority.rsplit_once(':') {
+        Some((_, port)) if port.parse::<u16>().is_ok() => authority.to_string(),
+        _ => format!("{}:{}", authority, default_port),
+    }
+}
+
+impl EsploraNode {
+    pub fn new(info: NodeInfo, url: String) -> Self {
+        let url = url.trim_end_matches('/').to_string();
+        EsploraNode {
+            info,
+            address: url_address(&url),
+            url,
+            mempool: false,
+            rest_step_size: RestStepSize::new(DEFAULT_REST_MAX_STEP_SIZE),
+        }
+    }
+
+    // A mempool.space instance, e.g. the public explorer as reference for
+    // the tip the big explorers see. The url is the base URL of its API.
+    pub fn mempool(info: NodeInfo, url: String) -> Self {
+        EsploraNode {
+            mempool: true,
+            ..EsploraNode::new(info, url)
+        }
+    }
+
+    fn get(&self, path: &str) -> Result<String, FetchError> {
+        let url = format!("{}/{}", self.url, path);
+        let res = minreq::get(url.clone()).with_timeout(8).send()?;
+        if res.status_code != 200 {
+            return Err(FetchError::EsploraREST(format!(
+                "could not load {}: {} {}: {:?}",
+                url,
+                res.status_code,
+                res.reason_phrase,
+                res.as_str(),
+            )));
+        }
+        match res.as_str() {
+            Ok(body) => Ok(body.trim().to_string()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn get_hex<T: bitcoin::consensus::Decodable>(&self, path: &str) -> Result<T, FetchError> {
+        let body = self.get(path)?;
+        let bytes = match hex::decode(&body) {
+            Ok(bytes) => bytes,
+            Err(e) => {
+                return Err(FetchError::EsploraREST(format!(
+                    "could not decode the hex response of {}: {}",
+                    path, e
+                )))
+            }
+        };
+        match bitcoin::consensus::deserialize(&bytes) {
+            Ok(decoded) => Ok(decoded),
+            Err(e) => Err(FetchError::EsploraREST(format!(
+                "could not deserialize the response of {}: {}",
+                path, e
+            ))),
+        }
+    }
+
+    fn get_block_hash(&self, path: &str) -> Result<BlockHash, FetchError> {
+        let body = self.get(path)?;
+        match BlockHash::from_str(&body) {
+            Ok(hash) => Ok(hash),
+            Err(e) => Err(FetchError::EsploraREST(format!(
+                "invalid block hash '{}' in the response of {}: {}",
+                body, path, e
+            ))),
+        }
+    }
+}
+
+#[async_trait]
+impl Node for EsploraNode {
+    fn info(&self) -> NodeInfo {
+        self.info.clone()
+    }
+
+    fn use_rest(&self) -> bool {
+        false
+    }
+
+    fn rest_step_size(&self) -> &RestStepSize {
+        &self.rest_step_size
+    }
+
+    fn rpc_url(&self) -> String {
+        self.address.clone()
+    }
+
+    fn header_source(&self) -> HeaderSource {
+        HeaderSource::Rest
+    }
+
+    // Esplora doesn't expose its version. mempool.space formats it as agent
+    // string, e.g. /mempool:3.0.0/.
+    async fn version(&self) -> Result<String, FetchError> {
+        if !self.mempool {
+            return Ok(String::from("Esplora"));
+        }
+        let info: Value = match serde_json::from_str(&self.get("v1/backend-info")?) {
+            Ok(info) => info,
+            Err(e) => {
+                return Err(FetchError::EsploraREST(format!(
+                    "could not parse the backend info: {}",
+                    e
+                )))
+            }
+        };
+        match info["version"].as_str() {
+            Some(version) => Ok(format!("/mempool:{}/", version)),
+            None => Err(FetchError::DataError(String::from(
+                "the backend info has no version",
+            ))),
+        }
+    }
+
+    async fn block_header(&self, hash: &BlockHash) -> Result<Header, FetchError> {
+        self.get_hex(&format!("block/{}/header", hash))
+    }
+
+    async fn coinbase(&self, hash: &BlockHash) -> Result<Transaction, FetchError> {
+        let txid = self.get(&format!("block/{}/txid/0", hash))?;
+        self.get_hex(&format!("tx/{}/hex", txid))
+    }
+
+    async fn block_hash(&self, height: u64) -> Result<BlockHash, FetchError> {
+        self.get_block_hash(&format!("block-height/{}", height))
+    }
+
+    async fn tips(&self) -> Result<Vec<ChainTip>, FetchError> {
+        // The height is looked up by the hash, as the tip might change
+        // between two requests.
+        let hash = self.get_block_hash("blocks/tip/hash")?;
+        let block: Value = match serde_json::from_str(&self.get(&format!("block/{}", hash))?) {
+            Ok(block) => block,
+            Err(e) => {
+                return Err(FetchError::EsploraREST(format!(
+                    "could not parse block {}: {}",
+                    hash, e
+                )))
+            }
+        };
+        match block["height"].as_u64() {
+            Some(height) => Ok(vec![ChainTip {
+                height,
+                hash: hash.to_string(),
+                branchlen: 0,
+                status: ChainTipStatus::Active,
+            }]),
+            None => Err(FetchError::DataError(format!(
+                "block {} has no height",
+                hash
+            ))),
+        }
+    }
+}
+
+// A BIP157 light client syncing headers from a peer serving compact block
+// filters. Shows what a light client sees. The synced headers are kept in
+// memory and the node only reports its active tip.
+pub struct LightClientNode {
+    info: NodeInfo,
+    address: String,
+    network: bitcoin::Network,
+    chain: Arc<std::sync::Mutex<HeaderChain>>,
+    peer_user_agent: Arc<std::sync::Mutex<Option<String>>>,
+    rest_step_size: RestStepSize,
+}
+
+impl LightClientNode {
+    pub fn new(info: NodeInfo, address: String, network: bitcoin::Network) -> Self {
+        LightClientNode {
+            info,
+            address,
+            network,
+            chain: Arc::new(std::sync::Mutex::new(HeaderChain::new(network))),
+            peer_user_agent: Arc::new(std::sync::Mutex::new(None)),
+            rest_step_size: RestStepSize::new(DEFAULT_REST_MAX_STEP_SIZE),
+        }
+    }
+
+    fn locked_chain(&self) -> Result<std::sync::MutexGuard<'_, HeaderChain>, FetchError> {
+        self.chain
+            .lock()
+            .map_err(|_| FetchError::P2P(String::from("the header chain is poisoned")))
+    }
+}
+
+#[async_trait]
+impl Node for LightClientNode {
+    fn info(&self) -> NodeInfo {
+        self.info.clone()
+    }
+
+    fn use_rest(&self) -> bool {
+        false
+    }
+
+    fn rest_step_size(&self) -> &RestStepSize {
+        &self.rest_step_size
+    }
+
+    fn rpc_url(&self) -> String {
+        self.address.clone()
+    }
+
+    fn header_source(&self) -> HeaderSource {
+        HeaderSource::P2P
+    }
+
+    // The user agent of the peer the headers are synced from.
+    async fn version(&self) -> Result<String, FetchError> {
+        match self.peer_user_agent.lock() {
+            Ok(user_agent) => user_agent
+                .clone()
+                .ok_or_else(|| FetchError::P2P(String::from("not synced yet"))),
+            Err(_) => Err(FetchError::P2P(String::from("the user agent is poisoned"))),
+        }
+    }
+
+    async fn block_header(&self, hash: &BlockHash) -> Result<Header, FetchError> {
+        let chain = self.locked_chain()?;
+        chain
+            .height(hash)
+            .and_then(|height| chain.header(height))
+            .ok_or_else(|| FetchError::P2P(format!("unknown header {}", hash)))
+    }
+
+    // Light clients only download the blocks relevant to their wallet.
+    async fn coinbase(&self, _hash: &BlockHash) -> Result<Transaction, FetchError> {
+        Err(FetchError::P2P(String::from(
+            "a light client doesn't download blocks",
+        )))
+    }
+
+    async fn block_hash(&self, height: u64) -> Result<BlockHash, FetchError> {
+        self.locked_chain()?
+            .header(height)
+            .map(|header| header.block_hash())
+            .ok_or_else(|| FetchError::P2P(format!("no header at height {}", height)))
+    }
+
+    async fn tips(&self) -> Result<Vec<ChainTip>, FetchError> {
+        let address = self.address.clone();
+        let network = self.network;
+        let chain = self.chain.clone();
+        let (user_agent, (height, tip)) = task::spawn_blocking(move || {
+            let mut chain = chain
+                .lock()
+                .map_err(|_| FetchError::P2P(String::from("the header chain is poisoned")))?;
+            let user_agent = crate::p2p::sync(&address, network, &mut chain)?;
+            Ok::<_, FetchError>((user_agent, chain.tip()))
+        })
+        .await??;
+        if let Ok(mut peer_user_agent) = self.peer_user_agent.lock() {
+            *peer_user_agent = Some(user_agent);
+        }
+        Ok(vec![ChainTip {
+            height,
+            hash: tip.block_hash().to_string(),
+            branchlen: 0,
+            status: ChainTipStatus::Active,
+        }])
+    }
+}
+
+// A node without RPC access that is followed over the P2P protocol. The
+// connection is kept open and the node announces new blocks with headers
+// messages. The headers are kept in memory and the node only reports the
+// tip it announced.
+pub struct P2PNode {
+    info: NodeInfo,
+    address: String,
+    network: bitcoin::Network,
+    chain: Arc<std::sync::Mutex<HeaderChain>>,
+    session: Arc<std::sync::Mutex<Option<Session>>>,
+    peer_user_agent: Arc<std::sync::Mutex<Option<String>>>,
+    rest_step_size: RestStepSize,
+}
+
+impl P2PNode {
+    pub fn new(info: NodeInfo, address: String, network: bitcoin::Network) -> Self {
+        P2PNode {
+            info,
+            address,
+            network,
+            chain: Arc::new(std::sync::Mutex::new(HeaderChain::new(network))),
+            session: Arc::new(std::sync::Mutex::new(None)),
+            peer_user_agent: Arc::new(std::sync::Mutex::new(None)),
+            rest_step_size: RestStepSize::new(DEFAULT_REST_MAX_STEP_SIZE),
+        }
+    }
+
+    fn locked_chain(&self) -> Result<std::sync::MutexGuard<'_, HeaderChain>, FetchError> {
+        self.chain
+            .lock()
+            .map_err(|_| FetchError::P2P(String::from("the header chain is poisoned")))
+    }
+}
+
+#[async_trait]
+impl Node for P2PNode {
+    fn info(&self) -> NodeInfo {
+        self.info.clone()
+    }
+
+    fn use_rest(&self) -> bool {
+        false
+    }
+
+    fn rest_step_size(&self) -> &RestStepSize {
+        &self.rest_step_size
+    }
+
+    fn rpc_url(&self) -> String {
+        self.address.clone()
+    }
+
+    fn header_source(&self) -> HeaderSource {
+        HeaderSource::P2P
+    }
+
+    async fn version(&self) -> Result<String, FetchError> {
+        match self.peer_user_agent.lock() {
+            Ok(user_agent) => user_agent
+                .clone()
+                .ok_or_else(|| FetchError::P2P(String::from("not connected yet"))),
+            Err(_) => Err(FetchError::P2P(String::from("the user agent is poisoned"))),
+        }
+    }
+
+    async fn block_header(&self, hash: &BlockHash) -> Result<Header, FetchError> {
+        let chain = self.locked_chain()?;
+        chain
+            .height(hash)
+            .and_then(|height| chain.header(height))
+            .ok_or_else(|| FetchError::P2P(format!("unknown header {}", hash)))
+    }
+
+    async fn coinbase(&self, _hash: &BlockHash) -> Result<Transaction, FetchError> {
+        Err(FetchError::P2P(String::from(
+            "blocks aren't downloaded over P2P",
+        )))
+    }
+
+    async fn block_hash(&self, height: u64) -> Result<BlockHash, FetchError> {
+        self.locked_chain()?
+            .header(height)
+            .map(|header| header.block_hash())
+            .ok_or_else(|| FetchError::P2P(format!("no header at height {}", height)))
+    }
+
+    async fn tips(&self) -> Result<Vec<ChainTip>, FetchError> {
+        let address = self.address.clone();
+        let network = self.network;
+        let chain = self.chain.clone();
+        let session = self.session.clone();
+        let (user_agent, (height, tip)) = task::spawn_blocking(move || {
+            let mut chain = chain
+                .lock()
+                .map_err(|_| FetchError::P2P(String::from("the header chain is poisoned")))?;
+            let mut session = session
+                .lock()
+                .map_err(|_| FetchError::P2P(String::from("the session is poisoned")))?;
+            if session.is_none() {
+                *session = Some(Session::open(&address, network)?);
+            }
+            let open = session.as_mut().expect("an open session");
+            if let Err(e) = open.sync(&address, &mut chain) {
+                // Reconnect on the next query.
+                *session = None;
+                return Err(e);
+            }
+            Ok::<_, FetchError>((open.user_agent.clone(), chain.tip()))
+        })
+        .await??;
+        if let Ok(mut peer_user_agent) = self.peer_user_agent.lock() {
+            *peer_user_agent = Some(user_agent);
+        }
+        Ok(vec![ChainTip {
+            height,
+            hash: tip.block_hash().to_string(),
+            branchlen: 0,
+            status: ChainTipStatus::Active,
+        }])
+    }
+}
+
+// Mirrors a node of another fork-observer instance via its data.json, so
+// instances can be federated. The tips are the ones the remote instance
+// reports for the node, and the headers are the ones the remote instance
+// serves, i.e. the headers around its forks and tips, with their miners.
+pub struct ForkObserverNode {
+    info: NodeInfo,
+    url: String,
+    address: String,
+    remote_network_id: u32,
+    remote_node_id: u32,
+    remote: std::sync::Mutex<RemoteNode>,
+    rest_step_size: RestStepSize,
+}
+
+// The headers and the version of the remote node of the last query.
+#[derive(Default)]
+struct RemoteNode {
+    headers: Vec<HeaderInfo>,
+    version: Option<String>,
+}
+
+// The tips, version and headers of a remote node as served in the data.json
+// of another fork-observer instance.
+fn parse_remote_data(
+    body: &str,
+    remote_node_id: u32,
+) -> Result<(Vec<ChainTip>, String, Vec<HeaderInfo>), FetchError> {
+    let data: Value = match serde_json::from_str(body) {
+        Ok(data) => data,
+        Err(e) => {
+            return Err(FetchError::ForkObserver(format!(
+                "could not parse the data.json: {}",
+                e
+            )))
+        }
+    };
+    let node = match data["nodes"].as_array().and_then(|nodes| {
+        nodes
+            .iter()
+            .find(|node| node["id"].as_u64() == Some(remote_node_id as u64))
+    }) {
+        Some(node) => node,
+        None => {
+            return Err(FetchError::ForkObserver(format!(
+                "the remote instance has no node with id {}",
+                remote_node_id
+            )))
+        }
+    };
+    if node["reachable"] == Value::Bool(false) {
+        return Err(FetchError::ForkObserver(format!(
+            "the remote instance can't reach node {}",
+            remote_node_id
+        )));
+    }
+    let mut tips = vec![];
+    for tip in node["tips"].as_array().into_iter().flatten() {
+        match (
+            tip["hash"]
+                .as_str()
+                .filter(|hash| BlockHash::from_str(hash).is_ok()),
+            tip["status"].as_str(),
+            tip["height"].as_u64(),
+        ) {
+            (Some(hash), Some(status), Some(height)) => tips.push(ChainTip {
+                height,
+                hash: hash.to_string(),
+                branchlen: 0,
+                status: ChainTipStatus::from(status.to_string()),
+            }),
+            _ => {
+                return Err(FetchError::DataError(format!(
+                    "invalid tip of remote node {}: {}",
+                    remote_node_id, tip
+                )))
+            }
+        }
+    }
+    let version = node["version"].as_str().unwrap_or_default().to_string();
+
+    let mut headers = vec![];
+    for header_info in data["header_infos"].as_array().into_iter().flatten() {
+        let field = |name: &str| {
+            header_info[name].as_u64().ok_or_else(|| {
+                FetchError::DataError(format!("remote header without {}: {}", name, header_info))
+            })
+        };
+        let hash = |name: &str| {
+            header_info[name]
+                .as_str()
+                .and_then(|hash| BlockHash::from_str(hash).ok())
+                .ok_or_else(|| {
+                    FetchError::DataError(format!(
+                        "remote header without {}: {}",
+                        name, header_info
+                    ))
+                })
+        };
+        let header = Header {
+            version: bitcoin::block::Version::from_consensus(field("version")? as i32),
+            prev_blockhash: hash("prev_blockhash")?,
+            merkle_root: bitcoin::TxMerkleNode::from_raw_hash(hash("merkle_root")?.to_raw_hash()),
+            time: field("time")? as u32,
+            bits: bitcoin::CompactTarget::from_consensus(field("bits")? as u32),
+            nonce: field("nonce")? as u32,
+        };
+        // Don't trust the remote instance with the hash.
+        if header.block_hash() != hash("hash")? {
+            return Err(FetchError::DataError(format!(
+                "the remote header {} doesn't hash to its hash",
+                header_info["hash"]
+            )));
+        }
+        headers.push(HeaderInfo {
+            height: field("height")?,
+            header,
+            miner: header_info["miner"]
+                .as_str()
+                .unwrap_or_default()
+                .to_string(),
+            own: false,
+            source: HeaderSource::Federation,
+            elements: None,
+        });
+    }
+    Ok((tips, version, headers))
+}
+
+impl ForkObserverNode {
+    pub fn new(info: NodeInfo, url: String, remote_network_id: u32, remote_node_id: u32) -> Self {
+        let url = url.trim_end_matches('/').to_string();
+        ForkObserverNode {
+            info,
+            address: url_address(&url),
+            url,
+            remote_network_id,
+            remote_node_id,
+            remote: std::sync::Mutex::new(RemoteNode::default()),
+            rest_step_size: RestStepSize::new(DEFAULT_REST_MAX_STEP_SIZE),
+        }
+    }
+
+    fn locked_remote(&self) -> Result<std::sync::MutexGuard<'_, RemoteNode>, FetchError> {
+        self.remote
+            .lock()
+            .map_err(|_| FetchError::ForkObserver(String::from("the remote headers are poisoned")))
+    }
+
+    fn remote_header(&self, hash: &BlockHash) -> Result<Option<HeaderInfo>, FetchError> {
+        Ok(self
+            .locked_remote()?
+            .headers
+            .iter()
+            .find(|header_info| header_info.block_hash() == *hash)
+            .cloned())
+    }
+}
+
+#[async_trait]
+impl Node for ForkObserverNode {
+    fn info(&self) -> NodeInfo {
+        self.info.clone()
+    }
+
+    fn use_rest(&self) -> bool {
+        false
+    }
+
+    fn rest_step_size(&self) -> &RestStepSize {
+        &self.rest_step_size
+    }
+
+    fn rpc_url(&self) -> String {
+        self.address.clone()
+    }
+
+    async fn version(&self) -> Result<String, FetchError> {
+        if self.locked_remote()?.version.is_none() {
+            self.tips().await?;
+        }
+        self.locked_remote()?
+            .version
+            .clone()
+            .ok_or_else(|| FetchError::ForkObserver(String::from("no version yet")))
+    }
+
+    async fn block_header(&self, hash: &BlockHash) -> Result<Header, FetchError> {
+        self.remote_header(hash)?
+            .map(|header_info| header_info.header)
+            .ok_or_else(|| FetchError::ForkObserver(format!("unknown header {}", hash)))
+    }
+
+    async fn coinbase(&self, _hash: &BlockHash) -> Result<Transaction, FetchError> {
+        Err(FetchError::ForkObserver(String::from(
+            "fork-observer doesn't serve blocks",
+        )))
+    }
+
+    async fn block_hash(&self, height: u64) -> Result<BlockHash, FetchError> {
+        Err(FetchError::ForkObserver(format!(
+            "fork-observer doesn't serve the block hash at height {}",
+            height
+        )))
+    }
+
+    async fn tips(&self) -> Result<Vec<ChainTip>, FetchError> {
+        let url = format!("{}/api/v1/{}/data.json", self.url, self.remote_network_id);
+        let res = minreq::get(url.clone()).with_timeout(8).send()?;
+        if res.status_code != 200 {
+            return Err(FetchError::ForkObserver(format!(
+                "could not load {}: {} {}",
+                url, res.status_code, res.reason_phrase,
+            )));
+        }
+        let (tips, version, headers) = parse_remote_data(res.as_str()?, self.remote_node_id)?;
+        *self.locked_remote()? = RemoteNode {
+            headers,
+            version: Some(version),
+        };
+        Ok(tips)
+    }
+
+    // The remote headers not in the tree yet. Their miners are already
+    // identified by the remote instance.
+    async fn new_headers(
+        &self,
+        _tips: &[ChainTip],
+        tree: &Tree,
+        min_fork_height: u64,
+    ) -> Result<(Vec<HeaderInfo>, Vec<BlockHash>), FetchError> {
+        let remote_headers = self.locked_remote()?.headers.clone();
+        let tree_locked = tree.lock().await;
+        let new_headers = remote_headers
+            .into_iter()
+            .filter(|header_info| {
+                header_info.height >= min_fork_height
+                    && !tree_locked.1.contains_key(&header_info.block_hash())
+            })
+            .collect();
+        Ok((new_headers, vec![]))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ChainParams;
+    use crate::retry::RetryPolicy;
+
+    #[test]
+    fn test_parse_block_template() {
+        let raw = serde_json::json!({
+            "height": 800000,
+            "transactions": [{"txid": "a", "fee": 1000}, {"txid": "b", "fee": 250}],
+        });
+        assert_eq!(
+            parse_block_template(&raw).unwrap(),
+            BlockTemplate {
+                height: 800000,
+                total_fees: 1250,
+                tx_count: 2,
+            }
+        );
+        assert!(parse_block_template(&serde_json::json!({"height": 1})).is_err());
+    }
+
+    #[test]
+    fn test_parse_rest_headers() {
+        let genesis =
+            bitcoin::blockdata::constants::genesis_block(bitcoin::Network::Bitcoin).header;
+        let mut body = bitcoin::consensus::serialize(&genesis);
+        body.extend(bitcoin::consensus::serialize(&genesis));
+        assert_eq!(parse_rest_headers(&body).unwrap(), vec![genesis, genesis]);
+        assert!(parse_rest_headers(&[]).unwrap().is_empty());
+        // a truncated header
+        body.truncate(159);
+        assert!(parse_rest_headers(&body).is_err());
+    }
+
+    #[test]
+    fn test_is_new_tip() {
+        let tip = |hash: &str| serde_json::json!({"hash": hash, "height": 100});
+        let mut last_hash = None;
+        assert!(is_new_tip(&tip("a"), &mut last_hash));
+        // a timeout returns the same tip
+        assert!(!is_new_tip(&tip("a"), &mut last_hash));
+        assert!(is_new_tip(&tip("b"), &mut last_hash));
+        assert_eq!(last_hash.as_deref(), Some("b"));
+    }
+
+    #[test]
+    fn test_parse_block_serving_peers() {
+        let raw = serde_json::json!([
+            {"id": 3, "inbound": true, "servicesnames": ["NETWORK", "WITNESS"]},
+            {"id": 5, "inbound": false, "servicesnames": ["NETWORK_LIMITED", "WITNESS"]},
+            {"id": 7, "inbound": false, "servicesnames": ["NETWORK", "WITNESS"]},
+            {"id": 8, "inbound": false, "servicesnames": []},
+        ]);
+        assert_eq!(parse_block_serving_peers(&raw).unwrap(), vec![7, 3]);
+        assert!(parse_block_serving_peers(&serde_json::json!({})).is_err());
+    }
+
+    #[test]
+    fn test_parse_memory_info() {
+        let raw = serde_json::json!({
+            "locked": {
+                "used": 912,
+                "free": 261232,
+                "total": 262144,
+                "locked": 262144,
+                "chunks_used": 9,
+                "chunks_free": 1,
+            }
+        });
+        assert_eq!(
+            parse_memory_info(&raw, 3600).unwrap(),
+            NodeResources {
+                uptime: 3600,
+                memory_used: 912,
+                memory_free: 261232,
+                memory_total: 262144,
+                memory_locked: 262144,
+            }
+        );
+        assert!(parse_memory_info(&serde_json::json!({"locked": {"used": 1}}), 0).is_err());
+    }
+
+    #[test]
+    fn test_rest_headers_too_large() {
+        assert!(!rest_headers_too_large(200, 160));
+        assert!(rest_headers_too_large(200, 150));
+        assert!(rest_headers_too_large(400, 0));
+        assert!(rest_headers_too_large(413, 0));
+        assert!(!rest_headers_too_large(404, 0));
+        assert!(!rest_headers_too_large(503, 0));
+    }
+
+    #[test]
+    fn test_rest_step_size() {
+        let step_size = RestStepSize::new(4000);
+        assert_eq!(step_size.get(), 4000);
+        step_size.on_success(Duration::from_millis(100));
+        assert_eq!(step_size.get(), 4000);
+        step_size.on_success(Duration::from_secs(5));
+        assert_eq!(step_size.get(), 2000);
+        step_size.on_success(Duration::from_secs(2));
+        assert_eq!(step_size.get(), 2000);
+
+        // a rejected step size is not reached again
+        assert!(step_size.on_failure(2000, true));
+        assert_eq!(step_size.get(), 1000);
+        step_size.on_success(Duration::from_millis(100));
+        assert_eq!(step_size.get(), 1999);
+
+        // don't shrink below the minimum
+        let step_size = RestStepSize::new(150);
+        assert!(step_size.on_failure(150, false));
+        assert_eq!(step_size.get(), MIN_REST_STEP_SIZE);
+        assert!(!step_size.on_failure(MIN_REST_STEP_SIZE, false));
+        step_size.on_success(Duration::from_millis(100));
+        assert_eq!(step_size.get(), 150);
+
+        // a maximum below the minimum is respected
+        let step_size = RestStepSize::new(10);
+        assert!(!step_size.on_failure(10, true));
+        assert_eq!(step_size.get(), 10);
+    }
+
+    #[test]
+    fn test_url_address() {
+        assert_eq!(
+            url_address("https://blockstream.info/api"),
+            "blockstream.info:443"
+        );
+        assert_eq!(url_address("http://127.0.0.1:3002/api"), "127.0.0.1:3002");
+        assert_eq!(url_address("esplora.local"), "esplora.local:80");
+    }
+
+    #[tokio::test]
+    async fn test_resolve_rpc_address() {
+        assert_eq!(
+            resolve_rpc_address("127.0.0.1:8332".to_string())
+                .await
+                .unwrap(),
+            SocketAddr::from(([127, 0, 0, 1], 8332))
+        );
+        assert!(resolve_rpc_address("missing-port".to_string())
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_batch_requests() {
+        use bitcoincore_rpc::bitcoin::consensus::encode::serialize_hex;
+        use std::io::{BufRead, BufReader, Read, Write};
+        use std::net::TcpListener;
+
+        let genesis = bitcoin::constants::genesis_block(bitcoin::Network::Bitcoin);
+        let genesis_header_hex = serialize_hex(&genesis.header);
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        // Answers two batch requests on the same connection, as the client
+        // is reused, in reverse order to check that the responses are
+        // matched by their ids.
+        let server = std::thread::spawn(move || {
+            let mut methods = vec![];
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream);
+            for _ in 0..2 {
+                let mut content_length = 0;
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    if let Some(length) = line.to_lowercase().strip_prefix("content-length:") {
+                        content_length = length.trim().parse().unwrap();
+                    }
+                    if line == "\r\n" {
+                        break;
+                    }
+                }
+                let mut body = vec![0u8; content_length];
+                reader.read_exact(&mut body).unwrap();
+                let requests: Vec<Value> = serde_json::from_slice(&body).unwrap();
+                let responses: Vec<Value> = requests
+                    .iter()
+                    .rev()
+                    .map(|request| {
+                        methods.push(request["method"].as_str().unwrap().to_string());
+                        let result = match request["method"].as_str().unwrap() {
+                            "getblockhash" => Value::from(format!(
+                                "{:064x}",
+                                request["params"][0].as_u64().unwrap()
+                            )),
+                            _ => Value::from(genesis_header_hex.clone()),
+                        };
+                        serde_json::json!({"id": request["id"], "result": result, "error": null})
+                    })
+                    .collect();
+                let body = serde_json::to_string(&responses).unwrap();
+                write!(
+                    reader.get_mut(),
+                    "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
+                    body.len(),
+                    body
+                )
+                .unwrap();
+            }
+            methods
+        });
+
+        let node = BitcoinCoreNode::new(
+            NodeInfo {
+                id: 0,
+                name: "node".to_string(),
+                description: String::new(),
+                implementation: "Bitcoin Core".to_string(),
+                trust: 1.0,
+                shadow: false,
+                purge_after: None,
+                retry: RetryPolicy::default(),
+            },
+            format!("127.0.0.1:{}", port),
+            Auth::None,
+            false,
+            DEFAULT_REST_MAX_STEP_SIZE,
+            None,
+            None,
+            false,
+            false,
+            Duration::from_secs(5),
+            Duration::from_secs(5),
+        );
+        let hashes = node.block_hashes(&[3, 2, 1]).await.unwrap();
+        assert_eq!(
+            hashes,
+            vec![
+                BlockHash::from_str(&format!("{:064x}", 3)).unwrap(),
+                BlockHash::from_str(&format!("{:064x}", 2)).unwrap(),
+                BlockHash::from_str(&format!("{:064x}", 1)).unwrap(),
+            ]
+        );
+        let headers = node.block_headers(&hashes[..2]).await.unwrap();
+        assert_eq!(headers, vec![genesis.header, genesis.header]);
+        assert_eq!(
+            server.join().unwrap(),
+            vec![
+                "getblockhash",
+                "getblockhash",
+                "getblockhash",
+                "getblockheader",
+                "getblockheader"
+            ]
+        );
+    }
+
+    // Answers the REST requests of a node with the given status codes and
+    // bodies, one connection each, and returns the request lines.
+    fn serve_rest(responses: Vec<(u16, Vec<u8>)>) -> (u16, std::thread::JoinHandle<Vec<String>>) {
+        use std::io::{BufRead, BufReader, Write};
+        use std::net::TcpListener;
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = std::thread::spawn(move || {
+            let mut request_lines = vec![];
+            for (status_code, body) in responses {
+                let (stream, _) = listener.accept().unwrap();
+                let mut reader = BufReader::new(stream);
+                let mut request_line = String::new();
+                reader.read_line(&mut request_line).unwrap();
+                request_lines.push(request_line);
+                write!(
+                    reader.get_mut(),
+                    "HTTP/1.1 {} Status\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                    status_code,
+                    body.len()
+                )
+                .unwrap();
+                reader.get_mut().write_all(&body).unwrap();
+            }
+            request_lines
+        });
+        (port, server)
+    }
+
+    fn rest_node(port: u16) -> BitcoinCoreNode {
+        BitcoinCoreNode::new(
+            NodeInfo {
+                id: 0,
+                name: "node".to_string(),
+                description: String::new(),
+                implementation: "Bitcoin Core".to_string(),
+                trust: 1.0,
+                shadow: false,
+                purge_after: None,
+                retry: RetryPolicy::default(),
+            },
+            format!("127.0.0.1:{}", port),
+            Auth::None,
+            true,
+            DEFAULT_REST_MAX_STEP_SIZE,
+            None,
+            None,
+            false,
+            false,
+            Duration::from_secs(5),
+            Duration::from_secs(5),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_block_hash_rest() {
+        let hash = BlockHash::from_str(&format!("{:064x}", 800000)).unwrap();
+        let (port, server) = serve_rest(vec![
+            (200, b"{}".to_vec()),
+            (200, bitcoin::consensus::serialize(&hash)),
+        ]);
+        let node = rest_node(port);
+        assert!(!node.rest_available());
+        node.detect_rest().await;
+        assert!(node.rest_available());
+        assert_eq!(node.block_hash(800000).await.unwrap(), hash);
+        let request_lines = server.join().unwrap();
+        assert!(request_lines[0].starts_with("GET /rest/chaininfo.json "));
+        assert!(request_lines[1].starts_with("GET /rest/blockhashbyheight/800000.bin "));
+    }
+
+    #[tokio::test]
+    async fn test_best_block_hash_rest() {
+        let hash = BlockHash::from_str(&format!("{:064x}", 800000)).unwrap();
+        let chain_info = serde_json::json!({"chain": "main", "bestblockhash": hash.to_string()});
+        let (port, server) = serve_rest(vec![
+            (200, chain_info.to_string().into_bytes()),
+            (200, chain_info.to_string().into_bytes()),
+        ]);
+        let node = rest_node(port);
+        node.detect_rest().await;
+        assert_eq!(node.best_block_hash().await.unwrap(), Some(hash));
+        let request_lines = server.join().unwrap();
+        assert!(request_lines[1].starts_with("GET /rest/chaininfo.json "));
+    }
+
+    #[tokio::test]
+    async fn test_detect_rest() {
+        let (port, server) = serve_rest(vec![(404, vec![])]);
+        let node = rest_node(port);
+        node.detect_rest().await;
+        assert!(!node.rest_available());
+        // not probed again within the probe interval
+        node.detect_rest().await;
+        assert_eq!(server.join().unwrap().len(), 1);
+
+        node.rest_detected.store(true, Ordering::Relaxed);
+        assert!(node.on_rest_failure());
+        assert!(!node.rest_available());
+        assert!(!node.on_rest_failure());
+    }
+
+    #[test]
+    fn test_parse_remote_data() {
+        use crate::types::HeaderInfoJson;
+
+        let mut header_info = crate::testutil::at_height(1, 1_600_000_000, 7);
+        header_info.miner = "Pool".to_string();
+        header_info.own = true;
+        let hash = header_info.block_hash().to_string();
+        let mut data = serde_json::json!({
+            "header_infos": [HeaderInfoJson::new(&header_info, 0, 0, &ChainParams::default())],
+            "nodes": [
+                {"id": 1, "reachable": false, "version": "", "tips": []},
+                {
+                    "id": 2,
+                    "reachable": true,
+                    "version": "/Satoshi:27.0.0/",
+                    "tips": [{"hash": hash, "status": "active", "height": 1}],
+                },
+            ],
+        });
+
+        let (tips, version, headers) = parse_remote_data(&data.to_string(), 2).unwrap();
+        assert_eq!(tips[0].status, ChainTipStatus::Active);
+        assert_eq!(tips[0].hash, hash);
+        assert_eq!(version, "/Satoshi:27.0.0/");
+        assert_eq!(headers[0].header, header_info.header);
+        assert_eq!(headers[0].miner, "Pool");
+        // only the remote operator's own blocks
+        assert!(!headers[0].own);
+
+        assert!(parse_remote_data(&data.to_string(), 1).is_err());
+        assert!(parse_remote_data(&data.to_string(), 3).is_err());
+
+        // a tip with a malformed hash is rejected
+        let mut malformed = data.clone();
+        malformed["nodes"][1]["tips"][0]["hash"] = "aa".into();
+        assert!(parse_remote_data(&malformed.to_string(), 2).is_err());
+
+        // a header not matching its hash is rejected
+        data["header_infos"][0]["nonce"] = 8.into();
+        assert!(parse_remote_data(&data.to_string(), 2).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_electrum_active_headers_below_min_fork_height() {
+        // The address is never connected to, as there is nothing to load.
+        let node = ElectrumNode::new(
+            NodeInfo {
+                id: 0,
+                name: "node".to_string(),
+                description: String::new(),
+                implementation: "Electrum".to_string(),
+                trust: 1.0,
+                shadow: false,
+                purge_after: None,
+                retry: RetryPolicy::default(),
+            },
+            "127.0.0.1:1".to_string(),
+        );
+        let tips = vec![ChainTip {
+            height: 5,
+            hash: String::new(),
+            branchlen: 0,
+            status: ChainTipStatus::Active,
+        }];
+        let tree: Tree = Arc::new(tokio::sync::Mutex::new((
+            petgraph::graph::DiGraph::new(),
+            std::collections::HashMap::new(),
+            std::collections::HashMap::new(),
+        )));
+        let headers = node.new_active_headers(&tips, &tree, 10).await.unwrap();
+        assert!(headers.is_empty());
+    }
+}
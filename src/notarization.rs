@@ -0,0 +1,207 @@
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+
+use bitcoincore_rpc::bitcoin::hashes::{sha256, Hash, HashEngine};
+use log::{error, info, warn};
+use serde_json::json;
+use tokio::task;
+use tokio::time::interval;
+
+use crate::config::Notarization;
+use crate::db;
+use crate::domain::Event;
+use crate::types::{now_timestamp, Db};
+
+const LOAD_BATCH_SIZE: u32 = 1000;
+const CALENDAR_TIMEOUT: u64 = 10; // seconds
+const LOG_FILE: &str = "notarizations.log";
+
+// The header of a .ots file: magic bytes, the file format version 1 and the
+// SHA256 operation the timestamped digest is the result of.
+const OTS_HEADER: &[u8] =
+    b"\x00OpenTimestamps\x00\x00Proof\x00\xbf\x89\xe2\xe8\x84\xe8\x92\x94\x01\x08";
+
+// The head of the hash chain before the first event.
+pub fn genesis() -> sha256::Hash {
+    sha256::Hash::all_zeros()
+}
+
+// The canonical representation of an event in the hash chain: its JSON
+// object with sorted keys.
+fn event_line(event: &Event) -> String {
+    json!({
+        "id": event.id,
+        "network_id": event.network_id,
+        "sequence": event.sequence,
+        "timestamp": event.timestamp,
+        "kind": event.kind.to_string(),
+        "severity": event.severity.to_string(),
+        "title": event.title,
+        "description": event.description,
+    })
+    .to_string()
+}
+
+// The head of the hash chain after the event: SHA256 of the previous head
+// followed by the event line.
+pub fn chain(head: sha256::Hash, event: &Event) -> sha256::Hash {
+    let mut engine = sha256::Hash::engine();
+    engine.input(head.as_byte_array());
+    engine.input(event_line(event).as_bytes());
+    sha256::Hash::from_engine(engine)
+}
+
+// A .ots file with the pending attestation a calendar returned for the
+// digest. `ots upgrade` completes it once the calendar committed it to
+// the Bitcoin blockchain.
+pub fn ots_file(digest: &sha256::Hash, calendar_response: &[u8]) -> Vec<u8> {
+    let mut file = OTS_HEADER.to_vec();
+    file.extend_from_slice(digest.as_byte_array());
+    file.extend_from_slice(calendar_response);
+    file
+}
+
+// The host of a calendar URL, used in the .ots file names.
+fn calendar_host(calendar: &str) -> &str {
+    let rest = calendar
+        .split_once("://")
+        .map_or(calendar, |(_, rest)| rest);
+    rest.split(['/', ':']).next().unwrap_or_default()
+}
+
+fn submit(calendar: &str, digest: sha256::Hash) -> Result<Vec<u8>, String> {
+    match minreq::post(format!("{}/digest", calendar.trim_end_matches('/')))
+        .with_header("Accept", "application/vnd.opentimestamps.v1")
+        .with_header("Content-Type", "application/x-www-form-urlencoded")
+        .with_body(digest.as_byte_array().to_vec())
+        .with_timeout(CALENDAR_TIMEOUT)
+        .send()
+    {
+        Ok(res) if res.status_code == 200 => Ok(res.into_bytes()),
+        Ok(res) => Err(format!("HTTP {} {}", res.status_code, res.reason_phrase)),
+        Err(e) => Err(format!("{}", e)),
+    }
+}
+
+// Appends the head to the log and timestamps it with each calendar.
+async fn notarize(
+    notarization: &Notarization,
+    last_id: u64,
+    head: sha256::Hash,
+) -> Result<(), std::io::Error> {
+    let dir: &Path = &notarization.output_dir;
+    fs::create_dir_all(dir)?;
+    let mut log = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(dir.join(LOG_FILE))?;
+    writeln!(log, "{} {} {}", now_timestamp(), last_id, head)?;
+
+    for calendar in notarization.calendars.iter() {
+        let url = calendar.clone();
+        let response = match task::spawn_blocking(move || submit(&url, head)).await {
+            Ok(response) => response,
+            Err(e) => Err(format!("{}", e)),
+        };
+        match response {
+            Ok(response) => fs::write(
+                dir.join(format!("{}-{}.ots", last_id, calendar_host(calendar))),
+                ots_file(&head, &response),
+            )?,
+            Err(e) => warn!(
+                "Could not timestamp the event log head {} with the calendar {}: {}",
+                head, calendar, e
+            ),
+        }
+    }
+    Ok(())
+}
+
+// Keeps a hash chain over the event log and periodically notarizes its head
+// if events were written since the last notarization.
+pub async fn run(db: Db, notarization: Notarization) {
+    info!(
+        "Notarizing the event log into {:?} with {} calendar(s)",
+        notarization.output_dir,
+        notarization.calendars.len()
+    );
+    let mut head = genesis();
+    let mut last_id: u64 = 0;
+    let mut notarized_id: u64 = 0;
+    let mut interval = interval(notarization.interval);
+    loop {
+        interval.tick().await;
+        loop {
+            let events = match db::load_events_after_id(db.clone(), last_id, LOAD_BATCH_SIZE).await
+            {
+                Ok(events) => events,
+                Err(e) => {
+                    error!("Could not load the events to notarize: {}", e);
+                    break;
+                }
+            };
+            for event in events.iter() {
+                head = chain(head, event);
+                last_id = event.id;
+            }
+            if events.len() < LOAD_BATCH_SIZE as usize {
+                break;
+            }
+        }
+        if last_id == notarized_id {
+            continue;
+        }
+        match notarize(&notarization, last_id, head).await {
+            Ok(()) => notarized_id = last_id,
+            Err(e) => error!("Could not notarize the event log head {}: {}", head, e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::{EventKind, EventSeverity};
+
+    #[test]
+    fn test_chain() {
+        let event = Event {
+            id: 1,
+            network_id: 2,
+            timestamp: 1700000000,
+            kind: EventKind::StaleBlock,
+            severity: EventSeverity::Warning,
+            title: "Stale block".to_string(),
+            description: "".to_string(),
+            sequence: 1,
+        };
+        assert_eq!(
+            event_line(&event),
+            r#"{"description":"","id":1,"kind":"stale-block","network_id":2,"sequence":1,"severity":"warning","timestamp":1700000000,"title":"Stale block"}"#
+        );
+        let head = chain(genesis(), &event);
+        let mut preimage = vec![0u8; 32];
+        preimage.extend_from_slice(event_line(&event).as_bytes());
+        assert_eq!(head, sha256::Hash::hash(&preimage));
+        assert_ne!(chain(head, &event), head);
+    }
+
+    #[test]
+    fn test_ots_file() {
+        let digest = sha256::Hash::hash(b"");
+        let file = ots_file(&digest, &[0x00, 0x01]);
+        assert_eq!(file.len(), 31 + 1 + 1 + 32 + 2);
+        assert!(file.starts_with(b"\x00OpenTimestamps\x00\x00Proof\x00"));
+        assert_eq!(&file[33..65], digest.as_byte_array());
+    }
+
+    #[test]
+    fn test_calendar_host() {
+        assert_eq!(
+            calendar_host("https://a.pool.opentimestamps.org"),
+            "a.pool.opentimestamps.org"
+        );
+        assert_eq!(calendar_host("http://localhost:14788/"), "localhost");
+    }
+}
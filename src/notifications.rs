@@ -0,0 +1,220 @@
+use log::{debug, error, info, warn};
+use serde_json::json;
+use tokio::task;
+use tokio::time::{interval, Duration};
+
+use crate::config::{NotificationSink, NotificationTarget, Notifications};
+use crate::db;
+use crate::domain::{Event, QueuedNotification};
+use crate::types::{now_timestamp, Db, EventJson, Versioned};
+
+const DELIVERY_INTERVAL: Duration = Duration::from_secs(5);
+const DELIVERY_BATCH_SIZE: u32 = 50;
+const DELIVERY_TIMEOUT: u64 = 10; // seconds
+const BACKOFF_BASE: u64 = 30; // seconds
+const BACKOFF_MAX: u64 = 60 * 60; // seconds
+
+// Seconds to wait before the next delivery attempt after `attempts` failed
+// attempts. Doubles with each attempt up to BACKOFF_MAX.
+pub fn backoff(attempts: u32) -> u64 {
+    let exponent = attempts.saturating_sub(1).min(32);
+    BACKOFF_BASE
+        .saturating_mul(1u64 << exponent)
+        .min(BACKOFF_MAX)
+}
+
+fn payload(sink: &NotificationSink, event: &Event) -> String {
+    match sink.target {
+        NotificationTarget::Webhook { .. } => {
+            json!(Versioned::new(EventJson::from(event))).to_string()
+        }
+        NotificationTarget::Matrix { .. } => json!({
+            "msgtype": "m.text",
+            "body": format!("[{}] {}: {}", event.severity, event.title, event.description),
+        })
+        .to_string(),
+    }
+}
+
+// Queues a notification about the event for each sink interested in events
+// of this severity.
+pub async fn enqueue(notifications: &Notifications, db: Db, event: &Event) {
+    for sink in notifications
+        .sinks
+        .iter()
+        .filter(|sink| event.severity >= sink.min_severity)
+    {
+        if let Err(e) = db::enqueue_notification(
+            db.clone(),
+            &sink.name,
+            &payload(sink, event),
+            now_timestamp(),
+        )
+        .await
+        {
+            error!(
+                "Could not queue a notification for sink '{}' about event '{}': {}",
+                sink.name, event.title, e
+            );
+        }
+    }
+}
+
+// Percent-encodes everything but unreserved characters. Used for Matrix room
+// ids (e.g. '!abc:example.com') in URL paths.
+fn percent_encode(s: &str) -> String {
+    s.bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+fn send(target: &NotificationTarget, id: u64, payload: String) -> Result<(), String> {
+    let request = match target {
+        NotificationTarget::Webhook { url } => minreq::post(url),
+        NotificationTarget::Matrix {
+            homeserver,
+            room_id,
+            access_token,
+        } => minreq::put(format!(
+            // The queue id is used as transaction id. This makes retries
+            // idempotent if a previous attempt reached the homeserver.
+            "{}/_matrix/client/v3/rooms/{}/send/m.room.message/fork-observer-{}",
+            homeserver,
+            percent_encode(room_id),
+            id
+        ))
+        .with_header("Authorization", format!("Bearer {}", access_token)),
+    };
+    match request
+        .with_header("content-type", "application/json")
+        .with_body(payload)
+        .with_timeout(DELIVERY_TIMEOUT)
+        .send()
+    {
+        Ok(res) if (200..300).contains(&res.status_code) => Ok(()),
+        Ok(res) => Err(format!(
+            "HTTP {} {}: {}",
+            res.status_code,
+            res.reason_phrase,
+            res.as_str().unwrap_or_default()
+        )),
+        Err(e) => Err(format!("{}", e)),
+    }
+}
+
+async fn deliver_notification(
+    notifications: &Notifications,
+    db: Db,
+    notification: QueuedNotification,
+) {
+    let result = match notifications
+        .sinks
+        .iter()
+        .find(|sink| sink.name == notification.sink)
+    {
+        Some(sink) => {
+            let target = sink.target.clone();
+            let payload = notification.payload.clone();
+            let id = notification.id;
+            match task::spawn_blocking(move || send(&target, id, payload)).await {
+                Ok(result) => result,
+                Err(e) => Err(format!("{}", e)),
+            }
+        }
+        None => Err(format!("unknown notification sink '{}'", notification.sink)),
+    };
+
+    match result {
+        Ok(_) => {
+            debug!(
+                "Delivered notification {} to sink '{}'",
+                notification.id, notification.sink
+            );
+            if let Err(e) = db::delete_notification(db, notification.id).await {
+                error!(
+                    "Could not remove delivered notification {} from the queue: {}",
+                    notification.id, e
+                );
+            }
+        }
+        Err(delivery_error) => {
+            let attempts = notification.attempts + 1;
+            let dead = attempts >= notifications.max_attempts;
+            if dead {
+                error!(
+                    "Could not deliver notification {} to sink '{}' after {} attempts. Moving it to the dead-letter queue: {}",
+                    notification.id, notification.sink, attempts, delivery_error
+                );
+            } else {
+                warn!(
+                    "Could not deliver notification {} to sink '{}' (attempt {}): {}",
+                    notification.id, notification.sink, attempts, delivery_error
+                );
+            }
+            if let Err(e) = db::notification_failed(
+                db,
+                notification.id,
+                attempts,
+                now_timestamp() + backoff(attempts),
+                &delivery_error,
+                dead,
+            )
+            .await
+            {
+                error!(
+                    "Could not update failed notification {}: {}",
+                    notification.id, e
+                );
+            }
+        }
+    }
+}
+
+// Delivers queued notifications. Notifications queued before a restart are
+// picked up again.
+pub async fn deliver(notifications: Notifications, db: Db) {
+    info!(
+        "Delivering notifications to {} sink(s)",
+        notifications.sinks.len()
+    );
+    let mut interval = interval(DELIVERY_INTERVAL);
+    loop {
+        interval.tick().await;
+        let due = match db::load_due_notifications(db.clone(), now_timestamp(), DELIVERY_BATCH_SIZE)
+            .await
+        {
+            Ok(due) => due,
+            Err(e) => {
+                error!("Could not load queued notifications: {}", e);
+                continue;
+            }
+        };
+        for notification in due {
+            deliver_notification(&notifications, db.clone(), notification).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backoff() {
+        assert_eq!(backoff(1), 30);
+        assert_eq!(backoff(2), 60);
+        assert_eq!(backoff(3), 120);
+        assert_eq!(backoff(8), BACKOFF_MAX);
+        assert_eq!(backoff(u32::MAX), BACKOFF_MAX);
+    }
+
+    #[test]
+    fn test_percent_encode() {
+        assert_eq!(percent_encode("!abc:example.com"), "%21abc%3Aexample.com");
+    }
+}
@@ -0,0 +1,418 @@
+use std::collections::HashMap;
+use std::io::{BufReader, Write};
+use std::net::{SocketAddr, TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+use bitcoincore_rpc::bitcoin::blockdata::block::Header;
+use bitcoincore_rpc::bitcoin::blockdata::constants::genesis_block;
+use bitcoincore_rpc::bitcoin::consensus::{encode, Decodable};
+use bitcoincore_rpc::bitcoin::hashes::Hash;
+use bitcoincore_rpc::bitcoin::p2p::message::{NetworkMessage, RawNetworkMessage};
+use bitcoincore_rpc::bitcoin::p2p::message_blockdata::GetHeadersMessage;
+use bitcoincore_rpc::bitcoin::p2p::message_network::VersionMessage;
+use bitcoincore_rpc::bitcoin::p2p::{Address, Magic, ServiceFlags};
+use bitcoincore_rpc::bitcoin::pow::Work;
+use bitcoincore_rpc::bitcoin::{BlockHash, Network};
+
+use log::debug;
+
+use crate::error::FetchError;
+use crate::types::now_timestamp;
+
+const USER_AGENT: &str = "/fork-observer:0.1.0/";
+const TIMEOUT: Duration = Duration::from_secs(8);
+// Peers send at most 2000 headers per headers message.
+const MAX_HEADERS_PER_MESSAGE: usize = 2000;
+// Messages not related to the header sync are skipped. Give up if the peer
+// doesn't answer after this many messages.
+const MAX_SKIPPED_MESSAGES: usize = 100;
+
+fn error(message: String) -> FetchError {
+    FetchError::P2P(message)
+}
+
+// The headers of the chain with the most work known, starting at the
+// genesis block. Only the headers' proof of work and their linking are
+// validated, as a light client would.
+pub struct HeaderChain {
+    headers: Vec<Header>,
+    heights: HashMap<BlockHash, u64>,
+}
+
+impl HeaderChain {
+    pub fn new(network: Network) -> Self {
+        let genesis = genesis_block(network).header;
+        HeaderChain {
+            headers: vec![genesis],
+            heights: HashMap::from([(genesis.block_hash(), 0)]),
+        }
+    }
+
+    pub fn tip(&self) -> (u64, Header) {
+        let height = self.headers.len() as u64 - 1;
+        (height, self.headers[height as usize])
+    }
+
+    pub fn header(&self, height: u64) -> Option<Header> {
+        self.headers.get(height as usize).copied()
+    }
+
+    pub fn height(&self, hash: &BlockHash) -> Option<u64> {
+        self.heights.get(hash).copied()
+    }
+
+    // If the headers start on top of a known header.
+    fn connects(&self, headers: &[Header]) -> bool {
+        match headers.first() {
+            Some(first) => self.height(&first.prev_blockhash).is_some(),
+            None => true,
+        }
+    }
+
+    // The hashes of the last ten headers and then exponentially fewer
+    // headers down to the genesis block.
+    fn locator(&self) -> Vec<BlockHash> {
+        let mut locator: Vec<BlockHash> = vec![];
+        let mut height = self.headers.len() - 1;
+        let mut step = 1;
+        loop {
+            locator.push(self.headers[height].block_hash());
+            if height == 0 {
+                break;
+            }
+            if locator.len() >= 10 {
+                step *= 2;
+            }
+            height = height.saturating_sub(step);
+        }
+        locator
+    }
+
+    // Connects the headers to the chain if this results in a chain with
+    // more work, reorganizing the chain if needed. Returns the number of
+    // headers connected.
+    pub fn connect(&mut self, headers: &[Header]) -> Result<usize, FetchError> {
+        let first = match headers.first() {
+            Some(first) => first,
+            None => return Ok(0),
+        };
+        let fork_height = self.height(&first.prev_blockhash).ok_or_else(|| {
+            error(format!(
+                "header {} doesn't connect to a known header",
+                first.block_hash()
+            ))
+        })? as usize;
+        for (i, header) in headers.iter().enumerate() {
+            if i > 0 && header.prev_blockhash != headers[i - 1].block_hash() {
+                return Err(error(format!(
+                    "header {} doesn't connect to the previous header",
+                    header.block_hash()
+                )));
+            }
+            if let Err(e) = header.validate_pow(header.target()) {
+                return Err(error(format!(
+                    "header {} has an invalid proof of work: {}",
+                    header.block_hash(),
+                    e
+                )));
+            }
+        }
+
+        let zero = Work::from_be_bytes([0; 32]);
+        let replaced_work = self.headers[fork_height + 1..]
+            .iter()
+            .fold(zero, |work, h| work + h.work());
+        let new_work = headers.iter().fold(zero, |work, h| work + h.work());
+        if new_work <= replaced_work {
+            return Ok(0);
+        }
+
+        for header in self.headers.drain(fork_height + 1..) {
+            self.heights.remove(&header.block_hash());
+        }
+        for header in headers {
+            self.heights
+                .insert(header.block_hash(), self.headers.len() as u64);
+            self.headers.push(*header);
+        }
+        Ok(headers.len())
+    }
+}
+
+struct Peer {
+    stream: TcpStream,
+    reader: BufReader<TcpStream>,
+    magic: Magic,
+}
+
+impl Peer {
+    fn send(&mut self, message: NetworkMessage) -> Result<(), FetchError> {
+        debug!("Sending '{}' to a P2P peer", message.cmd());
+        let raw = RawNetworkMessage::new(self.magic, message);
+        self.stream
+            .write_all(&encode::serialize(&raw))
+            .map_err(|e| error(format!("could not send a message: {}", e)))
+    }
+
+    // Receives the next message, answering pings on the way.
+    fn receive(&mut self) -> Result<NetworkMessage, FetchError> {
+        loop {
+            let raw = RawNetworkMessage::consensus_decode(&mut self.reader)
+                .map_err(|e| error(format!("could not receive a message: {}", e)))?;
+            if *raw.magic() != self.magic {
+                return Err(error(String::from("the peer is on a different network")));
+            }
+            debug!("Received '{}' from a P2P peer", raw.cmd());
+            match raw.payload() {
+                NetworkMessage::Ping(nonce) => self.send(NetworkMessage::Pong(*nonce))?,
+                payload => return Ok(payload.clone()),
+            }
+        }
+    }
+}
+
+// Connects to the peer and completes the version handshake. The peer must
+// offer the required services. Returns the peer's user agent.
+fn connect(
+    address: &str,
+    network: Network,
+    required: ServiceFlags,
+) -> Result<(Peer, String), FetchError> {
+    let socket_address: SocketAddr = address
+        .to_socket_addrs()
+        .map_err(|e| error(format!("could not resolve {}: {}", address, e)))?
+        .next()
+        .ok_or_else(|| error(format!("could not resolve {}", address)))?;
+    let stream = TcpStream::connect_timeout(&socket_address, TIMEOUT)
+        .map_err(|e| error(format!("could not connect to {}: {}", address, e)))?;
+    for result in [
+        stream.set_read_timeout(Some(TIMEOUT)),
+        stream.set_write_timeout(Some(TIMEOUT)),
+    ] {
+        result.map_err(|e| error(format!("could not set a timeout: {}", e)))?;
+    }
+    let reader = BufReader::new(
+        stream
+            .try_clone()
+            .map_err(|e| error(format!("could not clone the stream: {}", e)))?,
+    );
+    let mut peer = Peer {
+        stream,
+        reader,
+        magic: network.magic(),
+    };
+
+    let unspecified = SocketAddr::from(([0, 0, 0, 0], 0));
+    let mut version = VersionMessage::new(
+        ServiceFlags::NONE,
+        now_timestamp() as i64,
+        Address::new(&socket_address, ServiceFlags::NONE),
+        Address::new(&unspecified, ServiceFlags::NONE),
+        rand_nonce(),
+        USER_AGENT.to_string(),
+        0,
+    );
+    version.relay = false;
+    peer.send(NetworkMessage::Version(version))?;
+
+    let mut user_agent: Option<String> = None;
+    let mut verack = false;
+    for _ in 0..MAX_SKIPPED_MESSAGES {
+        match peer.receive()? {
+            NetworkMessage::Version(version) => {
+                if !version.services.has(required) {
+                    return Err(error(format!(
+                        "{} doesn't offer the services {}",
+                        address, required
+                    )));
+                }
+                user_agent = Some(version.user_agent);
+                peer.send(NetworkMessage::Verack)?;
+            }
+            NetworkMessage::Verack => verack = true,
+            _ => (),
+        }
+        if let (true, Some(user_agent)) = (verack, user_agent.as_ref()) {
+            return Ok((peer, user_agent.clone()));
+        }
+    }
+    Err(error(format!("no handshake with {}", address)))
+}
+
+// Not cryptographically secure, only used to detect connections to self.
+fn rand_nonce() -> u64 {
+    use std::collections::hash_map::RandomState;
+    use std::hash::{BuildHasher, Hasher};
+    RandomState::new().build_hasher().finish()
+}
+
+// Requests headers from the peer until the chain is synced with the peer's
+// chain. Headers announced by the peer (sendheaders) on the way are
+// connected too.
+fn sync_headers(peer: &mut Peer, address: &str, chain: &mut HeaderChain) -> Result<(), FetchError> {
+    loop {
+        peer.send(NetworkMessage::GetHeaders(GetHeadersMessage::new(
+            chain.locator(),
+            BlockHash::all_zeros(),
+        )))?;
+        let mut headers: Option<Vec<Header>> = None;
+        for _ in 0..MAX_SKIPPED_MESSAGES {
+            if let NetworkMessage::Headers(h) = peer.receive()? {
+                // Announcements of headers we can't connect yet are
+                // skipped. They are part of the response to our request.
+                if chain.connects(&h) {
+                    headers = Some(h);
+                    break;
+                }
+            }
+        }
+        let headers = headers.ok_or_else(|| error(format!("no headers from {}", address)))?;
+        let connected = chain.connect(&headers)?;
+        debug!(
+            "Connected {} of {} headers from {}",
+            connected,
+            headers.len(),
+            address
+        );
+        if headers.len() < MAX_HEADERS_PER_MESSAGE || connected == 0 {
+            return Ok(());
+        }
+    }
+}
+
+// Syncs the headers of the peer into the chain like a BIP157 light client
+// and returns the peer's user agent. A light client can only sync from peers
+// serving compact block filters.
+pub fn sync(
+    address: &str,
+    network: Network,
+    chain: &mut HeaderChain,
+) -> Result<String, FetchError> {
+    let (mut peer, user_agent) = connect(address, network, ServiceFlags::COMPACT_FILTERS)?;
+    sync_headers(&mut peer, address, chain)?;
+    Ok(user_agent)
+}
+
+// A connection to a peer that is kept open between syncs. The peer announces
+// new blocks with headers messages (sendheaders).
+pub struct Session {
+    peer: Peer,
+    pub user_agent: String,
+}
+
+impl Session {
+    pub fn open(address: &str, network: Network) -> Result<Self, FetchError> {
+        let (mut peer, user_agent) = connect(address, network, ServiceFlags::NONE)?;
+        peer.send(NetworkMessage::SendHeaders)?;
+        Ok(Session { peer, user_agent })
+    }
+
+    // Syncs the headers of the peer into the chain. The session should be
+    // dropped on errors.
+    pub fn sync(&mut self, address: &str, chain: &mut HeaderChain) -> Result<(), FetchError> {
+        sync_headers(&mut self.peer, address, chain)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::header;
+
+    // Mines a regtest header on top of prev.
+    fn mine(prev: &Header, time: u32) -> Header {
+        let mut header = header(prev.block_hash(), time, 0);
+        while header.validate_pow(header.target()).is_err() {
+            header.nonce += 1;
+        }
+        header
+    }
+
+    #[test]
+    fn test_header_chain() {
+        let mut chain = HeaderChain::new(Network::Regtest);
+        let (_, genesis) = chain.tip();
+        let a1 = mine(&genesis, 1);
+        let a2 = mine(&a1, 2);
+        assert_eq!(chain.connect(&[a1, a2]).unwrap(), 2);
+        assert_eq!(chain.tip(), (2, a2));
+
+        // a branch with less or equal work is ignored
+        let b1 = mine(&genesis, 3);
+        assert_eq!(chain.connect(&[b1]).unwrap(), 0);
+        let b2 = mine(&b1, 4);
+        assert_eq!(chain.connect(&[b1, b2]).unwrap(), 0);
+
+        // a branch with more work reorganizes the chain
+        let b3 = mine(&b2, 5);
+        assert_eq!(chain.connect(&[b1, b2, b3]).unwrap(), 3);
+        assert_eq!(chain.tip(), (3, b3));
+        assert_eq!(chain.height(&a1.block_hash()), None);
+        assert_eq!(chain.header(1), Some(b1));
+
+        // headers not connecting are rejected
+        assert!(!chain.connects(&[a2]));
+        assert!(chain.connect(&[a2]).is_err());
+    }
+
+    #[test]
+    fn test_session() {
+        let genesis = genesis_block(Network::Regtest).header;
+        let a1 = mine(&genesis, 1);
+        let a2 = mine(&a1, 2);
+        let a3 = mine(&a2, 3);
+
+        // A peer serving a1 and a2 and then announcing a3.
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let peer = std::thread::spawn(move || {
+            let (stream, socket_address) = listener.accept().unwrap();
+            let mut peer = Peer {
+                reader: BufReader::new(stream.try_clone().unwrap()),
+                stream,
+                magic: Network::Regtest.magic(),
+            };
+            assert!(matches!(
+                peer.receive().unwrap(),
+                NetworkMessage::Version(_)
+            ));
+            peer.send(NetworkMessage::Version(VersionMessage::new(
+                ServiceFlags::NETWORK,
+                0,
+                Address::new(&socket_address, ServiceFlags::NONE),
+                Address::new(&socket_address, ServiceFlags::NONE),
+                0,
+                "/peer/".to_string(),
+                2,
+            )))
+            .unwrap();
+            peer.send(NetworkMessage::Verack).unwrap();
+            assert!(matches!(peer.receive().unwrap(), NetworkMessage::Verack));
+            assert!(matches!(
+                peer.receive().unwrap(),
+                NetworkMessage::SendHeaders
+            ));
+            assert!(matches!(
+                peer.receive().unwrap(),
+                NetworkMessage::GetHeaders(_)
+            ));
+            peer.send(NetworkMessage::Headers(vec![a1, a2])).unwrap();
+            peer.send(NetworkMessage::Headers(vec![a3])).unwrap();
+            assert!(matches!(
+                peer.receive().unwrap(),
+                NetworkMessage::GetHeaders(_)
+            ));
+            peer.send(NetworkMessage::Headers(vec![])).unwrap();
+        });
+
+        let mut chain = HeaderChain::new(Network::Regtest);
+        let mut session = Session::open(&address, Network::Regtest).unwrap();
+        assert_eq!(session.user_agent, "/peer/");
+        session.sync(&address, &mut chain).unwrap();
+        assert_eq!(chain.tip(), (2, a2));
+        // the announced header is connected on the next sync
+        session.sync(&address, &mut chain).unwrap();
+        assert_eq!(chain.tip(), (3, a3));
+        peer.join().unwrap();
+    }
+}
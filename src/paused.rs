@@ -0,0 +1,29 @@
+use std::collections::BTreeSet;
+use std::sync::Arc;
+
+use tokio::sync::Mutex;
+
+// Nodes an operator paused via the admin API, e.g. to take load off a
+// struggling node. Unlike unreachable nodes, paused nodes aren't queried at
+// all. Pauses are lost on restart.
+//
+// The paused nodes by network id and node id.
+pub type PausedNodes = Arc<Mutex<BTreeSet<(u32, u32)>>>;
+
+pub async fn is_paused(paused: &PausedNodes, network_id: u32, node_id: u32) -> bool {
+    paused.lock().await.contains(&(network_id, node_id))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_is_paused() {
+        let paused: PausedNodes = Arc::new(Mutex::new(BTreeSet::new()));
+        assert!(!is_paused(&paused, 1, 2).await);
+        paused.lock().await.insert((1, 2));
+        assert!(is_paused(&paused, 1, 2).await);
+        assert!(!is_paused(&paused, 2, 1).await);
+    }
+}
@@ -0,0 +1,269 @@
+use std::collections::{HashMap, HashSet};
+
+use bitcoincore_rpc::bitcoin::BlockHash;
+use log::{debug, error, info};
+use tokio::sync::mpsc::UnboundedSender;
+use tokio::time::{Duration, Instant};
+
+use crate::blockmetadata;
+use crate::config::Network;
+use crate::db;
+use crate::domain::{ChainTipStatus, Tree};
+use crate::headertree;
+use crate::paused::{self, PausedNodes};
+use crate::types::{Caches, Db};
+
+pub const PEER_FETCH_INTERVAL: Duration = Duration::from_secs(60);
+
+// At most this many blocks of a headers-only branch are requested, lowest
+// first.
+const MAX_REQUESTED_BLOCKS: usize = 10;
+
+// A block that didn't arrive is requested again after this, at most
+// MAX_REQUEST_ATTEMPTS times.
+const REQUEST_RETRY_DELAY: Duration = Duration::from_secs(10 * 60);
+const MAX_REQUEST_ATTEMPTS: u32 = 3;
+
+// A node whose requests failed this many times in a row is treated like a
+// node that can't request blocks from its peers.
+const MAX_CONSECUTIVE_ERRORS: u32 = 3;
+
+// Requests are forgotten this long after the last attempt, e.g. for blocks
+// of branches the nodes no longer report, so their attempts start over.
+const REQUEST_EXPIRY: Duration = Duration::from_secs(24 * 60 * 60);
+
+// A node that couldn't request blocks from its peers is tried again after
+// this, e.g. after it was upgraded or its connection issues were resolved.
+const UNSUPPORTED_RETRY_DELAY: Duration = Duration::from_secs(6 * 60 * 60);
+
+// What was requested from and archived for the nodes of a network.
+#[derive(Default)]
+pub struct PeerFetches {
+    // By node id and block hash: when the block was last requested and how
+    // often.
+    requested: HashMap<(u32, BlockHash), (Instant, u32)>,
+    // Blocks archived from any node.
+    archived: HashSet<BlockHash>,
+    // By node id: since when the node can't request blocks from its peers.
+    unsupported: HashMap<u32, Instant>,
+    // By node id: the number of requests that failed since the last one
+    // that didn't.
+    errors: HashMap<u32, u32>,
+}
+
+// Forgets the requests older than REQUEST_EXPIRY and the unsupported nodes
+// due for another try.
+fn expire(fetches: &mut PeerFetches, now: Instant) {
+    fetches
+        .requested
+        .retain(|_, (requested_at, _)| now.duration_since(*requested_at) < REQUEST_EXPIRY);
+    let errors = &mut fetches.errors;
+    fetches.unsupported.retain(|node_id, since| {
+        let retry = now.duration_since(*since) >= UNSUPPORTED_RETRY_DELAY;
+        if retry {
+            errors.remove(node_id);
+        }
+        !retry
+    });
+}
+
+// If all attempts to request the block from the node's peers were made.
+fn exhausted(fetches: &PeerFetches, node_id: u32, hash: &BlockHash) -> bool {
+    fetches
+        .requested
+        .get(&(node_id, *hash))
+        .is_some_and(|(_, attempts)| *attempts >= MAX_REQUEST_ATTEMPTS)
+}
+
+// If the block should be requested (again) from the node's peers.
+fn due(fetches: &PeerFetches, node_id: u32, hash: &BlockHash, now: Instant) -> bool {
+    match fetches.requested.get(&(node_id, *hash)) {
+        Some((requested_at, attempts)) => {
+            *attempts < MAX_REQUEST_ATTEMPTS
+                && now.duration_since(*requested_at) >= REQUEST_RETRY_DELAY
+        }
+        None => true,
+    }
+}
+
+// Makes the nodes of the network download the blocks of their headers-only
+// tips from their peers. Once a block arrived, its metadata is archived like
+// with block_metadata and its miner is identified. Returns the number of
+// archived blocks.
+pub async fn fetch(
+    network: &Network,
+    tree: &Tree,
+    caches: &Caches,
+    db: Db,
+    paused_nodes: &PausedNodes,
+    pool_id_tx: &UnboundedSender<BlockHash>,
+    fetches: &mut PeerFetches,
+) -> usize {
+    expire(fetches, Instant::now());
+    let node_tips: Vec<(u32, BlockHash, Vec<BlockHash>)> = {
+        let caches_locked = caches.lock().await;
+        let cache = match caches_locked.get(&network.id) {
+            Some(cache) => cache,
+            None => return 0,
+        };
+        cache
+            .node_data
+            .iter()
+            .filter(|(node_id, _)| !fetches.unsupported.contains_key(node_id))
+            .filter_map(|(node_id, node)| {
+                let active_tip = *node.tip_hashes(ChainTipStatus::Active).first()?;
+                let headers_only = node.tip_hashes(ChainTipStatus::HeadersOnly);
+                Some((*node_id, active_tip, headers_only)).filter(|(_, _, tips)| !tips.is_empty())
+            })
+            .collect()
+    };
+
+    let mut archived = 0;
+    for (node_id, active_tip, headers_only_tips) in node_tips.iter() {
+        let node = match network.nodes.iter().find(|n| n.info().id == *node_id) {
+            Some(node) => node,
+            None => continue,
+        };
+        if paused::is_paused(paused_nodes, network.id, *node_id).await {
+            continue;
+        }
+        for tip in headers_only_tips.iter() {
+            let branch = headertree::branch_above(tree, tip, active_tip).await;
+            for header_info in branch.iter().take(MAX_REQUESTED_BLOCKS) {
+                let hash = header_info.block_hash();
+                if fetches.archived.contains(&hash) || exhausted(fetches, *node_id, &hash) {
+                    continue;
+                }
+                // Blocks of the branch the node already has don't need to
+                // be requested.
+                if let Ok(Some(block)) = node.block(&hash).await {
+                    if block.block_hash() != hash {
+                        continue;
+                    }
+                    let block_stats =
+                        blockmetadata::stats(&block, header_info.height, &network.chain_params);
+                    if let Err(e) =
+                        db::write_block_stats(db.clone(), network.header_store, &block_stats).await
+                    {
+                        error!(
+                            "Could not write the metadata of block {} to database: {}",
+                            hash, e
+                        );
+                        continue;
+                    }
+                    if let Err(e) = pool_id_tx.send(hash) {
+                        error!(
+                            "Could not send a block hash into the pool identification channel: {}",
+                            e
+                        );
+                    }
+                    fetches.archived.insert(hash);
+                    archived += 1;
+                    continue;
+                }
+                let now = Instant::now();
+                if !due(fetches, *node_id, &hash, now) {
+                    continue;
+                }
+                match node.request_block_from_peer(&hash).await {
+                    Ok(true) => {
+                        fetches.errors.remove(node_id);
+                        debug!(
+                            "Node {} on network '{}' requested block {} from a peer",
+                            node.info(),
+                            network.name,
+                            hash
+                        );
+                        let attempts = fetches
+                            .requested
+                            .get(&(*node_id, hash))
+                            .map_or(0, |(_, attempts)| *attempts);
+                        fetches
+                            .requested
+                            .insert((*node_id, hash), (now, attempts + 1));
+                    }
+                    Ok(false) => {
+                        fetches.unsupported.insert(*node_id, now);
+                        break;
+                    }
+                    Err(e) => {
+                        debug!(
+                            "Could not request block {} from the peers of node {} on network '{}': {}",
+                            hash,
+                            node.info(),
+                            network.name,
+                            e
+                        );
+                        let errors = fetches.errors.entry(*node_id).or_default();
+                        *errors += 1;
+                        if *errors >= MAX_CONSECUTIVE_ERRORS {
+                            info!(
+                                "Not requesting blocks from the peers of node {} on network '{}' after {} failed requests",
+                                node.info(),
+                                network.name,
+                                errors
+                            );
+                            fetches.unsupported.insert(*node_id, now);
+                        }
+                        break;
+                    }
+                }
+            }
+        }
+    }
+    if archived > 0 {
+        info!(
+            "Archived {} blocks of headers-only tips on network '{}'",
+            archived, network.name
+        );
+    }
+    archived
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoincore_rpc::bitcoin::hashes::Hash;
+
+    #[test]
+    fn test_due() {
+        let mut fetches = PeerFetches::default();
+        let hash = BlockHash::all_zeros();
+        let now = Instant::now();
+        assert!(due(&fetches, 1, &hash, now));
+
+        fetches.requested.insert((1, hash), (now, 1));
+        assert!(!due(&fetches, 1, &hash, now));
+        assert!(due(&fetches, 2, &hash, now));
+        assert!(due(&fetches, 1, &hash, now + REQUEST_RETRY_DELAY));
+
+        assert!(!exhausted(&fetches, 1, &hash));
+        fetches
+            .requested
+            .insert((1, hash), (now, MAX_REQUEST_ATTEMPTS));
+        assert!(!due(&fetches, 1, &hash, now + REQUEST_RETRY_DELAY));
+        assert!(exhausted(&fetches, 1, &hash));
+        assert!(!exhausted(&fetches, 2, &hash));
+    }
+
+    #[test]
+    fn test_expire() {
+        let mut fetches = PeerFetches::default();
+        let hash = BlockHash::all_zeros();
+        let now = Instant::now();
+        fetches
+            .requested
+            .insert((1, hash), (now, MAX_REQUEST_ATTEMPTS));
+        fetches.unsupported.insert(1, now);
+        fetches.errors.insert(1, MAX_CONSECUTIVE_ERRORS);
+
+        expire(&mut fetches, now + UNSUPPORTED_RETRY_DELAY);
+        assert!(exhausted(&fetches, 1, &hash));
+        assert!(fetches.unsupported.is_empty());
+        assert!(fetches.errors.is_empty());
+
+        expire(&mut fetches, now + REQUEST_EXPIRY);
+        assert!(!exhausted(&fetches, 1, &hash));
+        assert!(due(&fetches, 1, &hash, now + REQUEST_EXPIRY));
+    }
+}
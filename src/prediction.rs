@@ -0,0 +1,308 @@
+use std::collections::{BTreeSet, HashMap};
+use std::convert::Infallible;
+
+use bitcoincore_rpc::bitcoin::{CompactTarget, Target};
+use warp::Reply;
+
+use crate::rss::response_unknown_network;
+use crate::types::{
+    Caches, ForkPredictionJsonResponse, HeaderInfoJson, NetworkJson, NodeDataJson, RaceBranchJson,
+    RaceJson, Versioned,
+};
+
+// A branch more than this many blocks behind the longest branch has lost
+// the race.
+const RACE_MAX_LAG: u64 = 2;
+const MINER_UNKNOWN: &str = "Unknown";
+
+// How much each signal contributes to the win probability. Signals without
+// data on any branch (e.g. no identified miners) are left out and the
+// others are weighted up.
+const WEIGHT_WORK: f64 = 0.4;
+const WEIGHT_WORK_RATE: f64 = 0.2;
+const WEIGHT_NODES: f64 = 0.25;
+const WEIGHT_MINERS: f64 = 0.15;
+
+fn work(bits: u32) -> f64 {
+    Target::from_compact(CompactTarget::from_consensus(bits))
+        .to_work()
+        .log2()
+        .exp2()
+}
+
+fn shares(values: &[f64]) -> Option<Vec<f64>> {
+    let total: f64 = values.iter().sum();
+    if total <= 0.0 {
+        return None;
+    }
+    Some(values.iter().map(|v| v / total).collect())
+}
+
+struct Branch<'a> {
+    tip: &'a HeaderInfoJson,
+    // The blocks from the first block after the fork point to the tip.
+    blocks: Vec<&'a HeaderInfoJson>,
+    // All blocks building on the branch, including ones not leading to the
+    // tip.
+    hashes: BTreeSet<&'a str>,
+}
+
+// Estimates which branch of the most recent fork is likely to win while its
+// branches race. A heuristic combining the work of the branches, the rate
+// at which they accrued it, and the share of nodes and distinct miners
+// extending each branch. It doesn't know about the hash rate of the miners
+// and is easily misled by timestamps, so it's only an indication.
+pub fn predict<'a>(
+    header_infos: &[HeaderInfoJson],
+    nodes: impl Iterator<Item = &'a NodeDataJson>,
+) -> ForkPredictionJsonResponse {
+    ForkPredictionJsonResponse {
+        heuristic: true,
+        race: race(header_infos, nodes),
+    }
+}
+
+fn race<'a>(
+    header_infos: &[HeaderInfoJson],
+    nodes: impl Iterator<Item = &'a NodeDataJson>,
+) -> Option<RaceJson> {
+    let by_id: HashMap<usize, &HeaderInfoJson> = header_infos.iter().map(|h| (h.id, h)).collect();
+    let mut children: HashMap<usize, Vec<&HeaderInfoJson>> = HashMap::new();
+    for h in header_infos.iter().filter(|h| h.prev_id != h.id) {
+        if by_id.contains_key(&h.prev_id) {
+            children.entry(h.prev_id).or_default().push(h);
+        }
+    }
+    let fork_point = children
+        .iter()
+        .filter(|(_, c)| c.len() > 1)
+        .filter_map(|(id, _)| by_id.get(id))
+        .max_by_key(|h| (h.height, std::cmp::Reverse(h.id)))?;
+
+    let mut branches: Vec<Branch> = children[&fork_point.id]
+        .iter()
+        .map(|first| {
+            let mut hashes = BTreeSet::new();
+            let mut tip = *first;
+            let mut stack = vec![*first];
+            while let Some(h) = stack.pop() {
+                hashes.insert(h.hash.as_str());
+                if h.height > tip.height || (h.height == tip.height && h.id < tip.id) {
+                    tip = h;
+                }
+                stack.extend(children.get(&h.id).into_iter().flatten());
+            }
+            let mut blocks = vec![tip];
+            while blocks.last().is_some_and(|h| h.id != first.id) {
+                match blocks.last().and_then(|h| by_id.get(&h.prev_id)) {
+                    Some(prev) => blocks.push(prev),
+                    None => break,
+                }
+            }
+            blocks.reverse();
+            Branch {
+                tip,
+                blocks,
+                hashes,
+            }
+        })
+        .collect();
+
+    let best_height = branches.iter().map(|b| b.tip.height).max()?;
+    branches.retain(|b| b.tip.height + RACE_MAX_LAG >= best_height);
+    if branches.len() < 2 {
+        return None;
+    }
+
+    let works: Vec<f64> = branches
+        .iter()
+        .map(|b| b.blocks.iter().map(|h| work(h.bits)).sum())
+        .collect();
+    let work_rates: Vec<f64> = branches
+        .iter()
+        .zip(works.iter())
+        .map(|(b, work)| {
+            let elapsed = (b.tip.time as i64 - fork_point.time as i64).max(1);
+            work / elapsed as f64
+        })
+        .collect();
+
+    let mut node_ids: Vec<Vec<u32>> = vec![vec![]; branches.len()];
+    let mut node_weights: Vec<f64> = vec![0.0; branches.len()];
+    for node in nodes.filter(|node| node.reachable && !node.shadow) {
+        let active_tip = match node.tips.iter().rfind(|tip| tip.status == "active") {
+            Some(tip) => tip,
+            None => continue,
+        };
+        if let Some(i) = branches
+            .iter()
+            .position(|b| b.hashes.contains(active_tip.hash.as_str()))
+        {
+            node_ids[i].push(node.id);
+            node_weights[i] += node.trust;
+        }
+    }
+
+    let miners: Vec<Vec<String>> = branches
+        .iter()
+        .map(|b| {
+            b.blocks
+                .iter()
+                .map(|h| h.miner.clone())
+                .filter(|miner| !miner.is_empty() && miner != MINER_UNKNOWN)
+                .collect::<BTreeSet<String>>()
+                .into_iter()
+                .collect()
+        })
+        .collect();
+    let miner_counts: Vec<f64> = miners.iter().map(|m| m.len() as f64).collect();
+
+    let signals = [
+        (WEIGHT_WORK, shares(&works)),
+        (WEIGHT_WORK_RATE, shares(&work_rates)),
+        (WEIGHT_NODES, shares(&node_weights)),
+        (WEIGHT_MINERS, shares(&miner_counts)),
+    ];
+    let total_weight: f64 = signals
+        .iter()
+        .filter(|(_, shares)| shares.is_some())
+        .map(|(weight, _)| weight)
+        .sum();
+    let share = |signal: usize, branch: usize| {
+        signals[signal]
+            .1
+            .as_ref()
+            .map_or(0.0, |shares| shares[branch])
+    };
+
+    let mut branches_json: Vec<RaceBranchJson> = branches
+        .iter()
+        .enumerate()
+        .map(|(i, b)| RaceBranchJson {
+            tip_hash: b.tip.hash.clone(),
+            tip_height: b.tip.height,
+            length: b.tip.height - fork_point.height,
+            work_share: share(0, i),
+            work_rate_share: share(1, i),
+            node_ids: node_ids[i].clone(),
+            node_share: share(2, i),
+            miners: miners[i].clone(),
+            miner_share: share(3, i),
+            win_probability: signals
+                .iter()
+                .map(|(weight, shares)| shares.as_ref().map_or(0.0, |s| weight * s[i]))
+                .sum::<f64>()
+                / total_weight,
+        })
+        .collect();
+    branches_json.sort_by(|a, b| b.win_probability.total_cmp(&a.win_probability));
+
+    Some(RaceJson {
+        fork_height: fork_point.height,
+        fork_hash: fork_point.hash.clone(),
+        likely_winner: branches_json[0].tip_hash.clone(),
+        branches: branches_json,
+    })
+}
+
+pub async fn prediction_response(
+    network_id: u32,
+    caches: Caches,
+    network_infos: Vec<NetworkJson>,
+) -> Result<Box<dyn Reply>, Infallible> {
+    let caches_locked = caches.lock().await;
+    match caches_locked.get(&network_id) {
+        Some(cache) => Ok(Box::new(warp::reply::json(&Versioned::new(predict(
+            &cache.header_infos_json,
+            cache.node_data.values(),
+        ))))),
+        None => Ok(Box::new(response_unknown_network(network_infos))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ChainParams;
+    use crate::domain::NodeInfo;
+    use crate::retry::RetryPolicy;
+    use crate::testutil::at_height;
+    use crate::types::TipInfoJson;
+
+    fn header_infos(heights: &[u64], prev_ids: &[usize]) -> Vec<HeaderInfoJson> {
+        heights
+            .iter()
+            .zip(prev_ids.iter())
+            .enumerate()
+            .map(|(id, (height, prev_id))| {
+                let mut header_info =
+                    at_height(*height, 1_700_000_000 + *height as u32 * 600, id as u32);
+                header_info.miner = format!("Pool {}", id);
+                HeaderInfoJson::new(&header_info, id, *prev_id, &ChainParams::default())
+            })
+            .collect()
+    }
+
+    fn node(id: u32, hash: &str) -> NodeDataJson {
+        let mut node = NodeDataJson::new(
+            NodeInfo {
+                id,
+                name: String::new(),
+                description: String::new(),
+                implementation: String::new(),
+                trust: 1.0,
+                shadow: false,
+                purge_after: None,
+                retry: RetryPolicy::default(),
+            },
+            &[],
+            String::new(),
+            0,
+            true,
+        );
+        node.tips = vec![TipInfoJson {
+            hash: hash.to_string(),
+            status: "active".to_string(),
+            height: 0,
+            chainwork: None,
+        }];
+        node
+    }
+
+    #[test]
+    fn test_predict() {
+        // 0 <- 1 <- 2
+        //  \<- 3
+        let headers = header_infos(&[0, 1, 2, 1], &[usize::MAX, 0, 1, 0]);
+        let nodes = [
+            node(0, &headers[2].hash),
+            node(1, &headers[2].hash),
+            node(2, &headers[3].hash),
+        ];
+        let prediction = predict(&headers, nodes.iter());
+        assert!(prediction.heuristic);
+        let race = prediction.race.unwrap();
+        assert_eq!(race.fork_height, 0);
+        assert_eq!(race.likely_winner, headers[2].hash);
+        assert_eq!(race.branches[0].length, 2);
+        assert_eq!(race.branches[0].node_ids, vec![0, 1]);
+        assert_eq!(race.branches[1].node_ids, vec![2]);
+        assert!((race.branches[0].work_share - 2.0 / 3.0).abs() < 1e-9);
+        assert!(race.branches[0].win_probability > 0.5);
+        let total: f64 = race.branches.iter().map(|b| b.win_probability).sum();
+        assert!((total - 1.0).abs() < 1e-9);
+
+        // a tie is decided by the nodes
+        let headers = header_infos(&[0, 1, 1], &[usize::MAX, 0, 0]);
+        let nodes = [node(0, &headers[2].hash), node(1, &headers[2].hash)];
+        let race = predict(&headers, nodes.iter()).race.unwrap();
+        assert_eq!(race.likely_winner, headers[2].hash);
+
+        // a branch three blocks behind lost the race
+        let headers = header_infos(&[0, 1, 2, 3, 4, 1], &[usize::MAX, 0, 1, 2, 3, 0]);
+        assert!(predict(&headers, [].iter()).race.is_none());
+        // no fork
+        let headers = header_infos(&[0, 1], &[usize::MAX, 0]);
+        assert!(predict(&headers, [].iter()).race.is_none());
+    }
+}
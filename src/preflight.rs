@@ -0,0 +1,187 @@
+use futures_util::future::join_all;
+use log::{info, warn};
+
+use crate::config::{self, BoxedSyncSendNode, Network};
+use crate::types::{PreflightJsonResponse, PreflightNodeJson};
+
+const CHANGE_ADDED: &str = "added";
+const CHANGE_CHANGED: &str = "changed";
+
+// Identifies the configuration of a node. A node in the configuration file
+// is new or changed if no running node has the same descriptor.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct NodeDescriptor {
+    network_id: u32,
+    node_id: u32,
+    name: String,
+    implementation: String,
+    rpc_url: String,
+    use_rest: bool,
+}
+
+impl NodeDescriptor {
+    fn new(network_id: u32, node: &BoxedSyncSendNode) -> Self {
+        let info = node.info();
+        NodeDescriptor {
+            network_id,
+            node_id: info.id,
+            name: info.name,
+            implementation: info.implementation,
+            rpc_url: node.rpc_url(),
+            use_rest: node.use_rest(),
+        }
+    }
+}
+
+pub fn descriptors(networks: &[Network]) -> Vec<NodeDescriptor> {
+    networks
+        .iter()
+        .flat_map(|network| {
+            network
+                .nodes
+                .iter()
+                .map(move |node| NodeDescriptor::new(network.id, node))
+        })
+        .collect()
+}
+
+// How the node changed compared to the running nodes. None if a running
+// node has the same descriptor.
+fn change(running: &[NodeDescriptor], descriptor: &NodeDescriptor) -> Option<&'static str> {
+    if running.contains(descriptor) {
+        return None;
+    }
+    if running
+        .iter()
+        .any(|r| r.network_id == descriptor.network_id && r.node_id == descriptor.node_id)
+    {
+        Some(CHANGE_CHANGED)
+    } else {
+        Some(CHANGE_ADDED)
+    }
+}
+
+// Checks that the node is reachable, accepts our credentials and, if the
+// network has a genesis hash configured, is on the expected chain.
+async fn check_node(
+    network: &Network,
+    node: &BoxedSyncSendNode,
+    change: &str,
+) -> PreflightNodeJson {
+    let mut result = PreflightNodeJson {
+        network_id: network.id,
+        node_id: node.info().id,
+        node_name: node.info().name,
+        change: change.to_string(),
+        reachable: false,
+        genesis_hash: None,
+        network_matches: None,
+        error: None,
+    };
+
+    if let Err(e) = node.tips().await {
+        result.error = Some(format!("could not fetch the chain tips: {}", e));
+        return result;
+    }
+    result.reachable = true;
+
+    match node.block_hash(0).await {
+        Ok(genesis_hash) => {
+            result.genesis_hash = Some(genesis_hash.to_string());
+            if let Some(expected) = network.chain_params.genesis_hash {
+                result.network_matches = Some(expected == genesis_hash);
+                if expected != genesis_hash {
+                    result.error = Some(format!(
+                        "the node's genesis block {} doesn't match the network's genesis block {}",
+                        genesis_hash, expected
+                    ));
+                }
+            }
+        }
+        Err(e) => {
+            result.error = Some(format!("could not fetch the genesis block hash: {}", e));
+        }
+    }
+    result
+}
+
+// Loads the configuration file as a reload would and checks the nodes that
+// were added or changed compared to the running nodes. The running nodes
+// are neither checked nor touched.
+pub async fn run(running: &[NodeDescriptor]) -> PreflightJsonResponse {
+    let config = match config::load_config() {
+        Ok(config) => config,
+        Err(e) => {
+            warn!("Pre-flight: could not load the configuration: {}", e);
+            return PreflightJsonResponse {
+                config_error: Some(e.to_string()),
+                unchanged_nodes: 0,
+                nodes: vec![],
+            };
+        }
+    };
+
+    let mut unchanged_nodes = 0;
+    let mut checks = vec![];
+    for network in config.networks.iter() {
+        for node in network.nodes.iter() {
+            match change(running, &NodeDescriptor::new(network.id, node)) {
+                Some(change) => checks.push(check_node(network, node, change)),
+                None => unchanged_nodes += 1,
+            }
+        }
+    }
+
+    let nodes = join_all(checks).await;
+    for node in nodes.iter() {
+        match node.error {
+            Some(ref e) => warn!(
+                "Pre-flight: {} node '{}' (id={}) on network id={} failed: {}",
+                node.change, node.node_name, node.node_id, node.network_id, e
+            ),
+            None => info!(
+                "Pre-flight: {} node '{}' (id={}) on network id={} is healthy",
+                node.change, node.node_name, node.node_id, node.network_id
+            ),
+        }
+    }
+
+    PreflightJsonResponse {
+        config_error: None,
+        unchanged_nodes,
+        nodes,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_change() {
+        let descriptor = |network_id: u32, node_id: u32, rpc_url: &str| NodeDescriptor {
+            network_id,
+            node_id,
+            name: format!("node {}", node_id),
+            implementation: "bitcoincore".to_string(),
+            rpc_url: rpc_url.to_string(),
+            use_rest: false,
+        };
+        let running = vec![descriptor(1, 0, "127.0.0.1:8332")];
+
+        assert_eq!(change(&running, &descriptor(1, 0, "127.0.0.1:8332")), None);
+        assert_eq!(
+            change(&running, &descriptor(1, 0, "127.0.0.1:18332")),
+            Some(CHANGE_CHANGED)
+        );
+        assert_eq!(
+            change(&running, &descriptor(1, 1, "127.0.0.1:8332")),
+            Some(CHANGE_ADDED)
+        );
+        // node ids are per network
+        assert_eq!(
+            change(&running, &descriptor(2, 0, "127.0.0.1:8332")),
+            Some(CHANGE_ADDED)
+        );
+    }
+}
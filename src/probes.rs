@@ -0,0 +1,117 @@
+use std::net::{IpAddr, SocketAddr, TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+use log::warn;
+use tokio::task;
+
+use crate::config::{BoxedSyncSendNode, P2PProbes};
+use crate::socks;
+use crate::types::P2PProbeJson;
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+// Connections via Tor take longer to establish.
+const TOR_CONNECT_TIMEOUT: Duration = Duration::from_secs(30);
+
+const NETWORK_IPV4: &str = "ipv4";
+const NETWORK_IPV6: &str = "ipv6";
+const NETWORK_TOR: &str = "tor";
+
+fn network(host: &str) -> Option<&'static str> {
+    if host.ends_with(".onion") {
+        return Some(NETWORK_TOR);
+    }
+    match host.parse::<IpAddr>() {
+        Ok(IpAddr::V4(_)) => Some(NETWORK_IPV4),
+        Ok(IpAddr::V6(_)) => Some(NETWORK_IPV6),
+        Err(_) => None,
+    }
+}
+
+fn connect(address: SocketAddr, timeout: Duration) -> Result<TcpStream, String> {
+    let stream = TcpStream::connect_timeout(&address, timeout).map_err(|e| e.to_string())?;
+    stream
+        .set_read_timeout(Some(timeout))
+        .and_then(|_| stream.set_write_timeout(Some(timeout)))
+        .map_err(|e| e.to_string())?;
+    Ok(stream)
+}
+
+fn probe(host: &str, port: u16, tor_proxy: Option<SocketAddr>) -> Result<(), String> {
+    match tor_proxy {
+        Some(proxy) if network(host) == Some(NETWORK_TOR) => {
+            socks::connect(host, port, Some(proxy), TOR_CONNECT_TIMEOUT)
+                .map(|_| ())
+                .map_err(|e| format!("Tor proxy error: {}", e))
+        }
+        _ => {
+            let address = (host, port)
+                .to_socket_addrs()
+                .map_err(|e| e.to_string())?
+                .next()
+                .ok_or_else(|| format!("could not resolve {}", host))?;
+            connect(address, CONNECT_TIMEOUT).map(|_| ())
+        }
+    }
+}
+
+// Probes if the P2P addresses advertised by the node accept connections
+// from the observer. Onion addresses are only probed with a Tor proxy.
+pub async fn probe_node(node: &BoxedSyncSendNode, config: &P2PProbes) -> Vec<P2PProbeJson> {
+    let addresses = match node.p2p_addresses().await {
+        Ok(addresses) => addresses,
+        Err(e) => {
+            warn!(
+                "Could not load the P2P addresses of node {}: {}",
+                node.info(),
+                e
+            );
+            return vec![];
+        }
+    };
+
+    let mut probes: Vec<P2PProbeJson> = vec![];
+    for (host, port) in addresses {
+        let network = match network(&host) {
+            Some(NETWORK_TOR) if config.tor_proxy.is_none() => continue,
+            Some(network) => network,
+            None => continue,
+        };
+        let tor_proxy = config.tor_proxy;
+        let probe_host = host.clone();
+        let result = match task::spawn_blocking(move || probe(&probe_host, port, tor_proxy)).await {
+            Ok(result) => result,
+            Err(e) => Err(e.to_string()),
+        };
+        let address = match network {
+            NETWORK_IPV6 => format!("[{}]:{}", host, port),
+            _ => format!("{}:{}", host, port),
+        };
+        probes.push(P2PProbeJson {
+            address,
+            network: network.to_string(),
+            reachable: result.is_ok(),
+            error: result.err(),
+        });
+    }
+    probes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+
+    #[test]
+    fn test_probe() {
+        assert_eq!(network("127.0.0.1"), Some(NETWORK_IPV4));
+        assert_eq!(network("::1"), Some(NETWORK_IPV6));
+        assert_eq!(network("duskgytldkxiuqc6.onion"), Some(NETWORK_TOR));
+        assert_eq!(network("example.com"), None);
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        assert!(probe("127.0.0.1", port, None).is_ok());
+        drop(listener);
+        assert!(probe("127.0.0.1", port, None).is_err());
+    }
+}
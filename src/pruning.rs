@@ -0,0 +1,275 @@
+use std::collections::BTreeMap;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use bitcoincore_rpc::bitcoin::BlockHash;
+use log::{error, info};
+use tokio::sync::Mutex;
+use tokio::time::Duration;
+
+use crate::config::ForkPruning;
+use crate::consensus;
+use crate::db;
+use crate::domain::{ChainTip, ChainTipStatus, Tree};
+use crate::error::DbError;
+use crate::headertree;
+use crate::types::{Caches, Db};
+
+pub const PRUNE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+// The height of the last common block a stale branch needs to be kept, by
+// header store. The tips of branches forking off below it aren't fetched
+// again. Persisted, so pruned branches aren't fetched again after a restart.
+pub type PruneHorizons = Arc<Mutex<BTreeMap<u32, u64>>>;
+
+pub async fn load(db: Db) -> Result<PruneHorizons, DbError> {
+    Ok(Arc::new(Mutex::new(db::load_prune_horizons(db).await?)))
+}
+
+// The horizon given the heights of the last common blocks of the stale
+// branches, sorted ascending. Branches forking off at the same height
+// belong to the same fork.
+fn horizon(pruning: &ForkPruning, best_height: u64, fork_heights: &[u64]) -> u64 {
+    let mut horizon = 0;
+    if let Some(keep_depth) = pruning.keep_depth {
+        horizon = best_height.saturating_sub(keep_depth);
+    }
+    if let Some(max_forks) = pruning.max_forks {
+        let mut heights = fork_heights.to_vec();
+        heights.dedup();
+        if heights.len() > max_forks {
+            horizon = horizon.max(heights[heights.len() - max_forks]);
+        }
+    }
+    horizon
+}
+
+// The hashes and heights of the active tips of the nodes of the networks.
+// Shadow nodes are ignored.
+pub async fn active_tips(caches: &Caches, network_ids: &[u32]) -> Vec<(BlockHash, u64)> {
+    let caches_locked = caches.lock().await;
+    network_ids
+        .iter()
+        .filter_map(|id| caches_locked.get(id))
+        .flat_map(|cache| cache.node_data.values())
+        .filter(|node| !node.shadow)
+        .flat_map(|node| node.tips.iter())
+        .filter(|tip| tip.status == ChainTipStatus::Active.to_string())
+        .filter_map(|tip| Some((BlockHash::from_str(&tip.hash).ok()?, tip.height)))
+        .collect()
+}
+
+// The height of the highest consensus tip of the networks. Unlike the
+// highest active tip, a single node reporting a bogus height can't move it.
+pub async fn consensus_height(caches: &Caches, network_ids: &[u32]) -> Option<u64> {
+    let caches_locked = caches.lock().await;
+    network_ids
+        .iter()
+        .filter_map(|id| caches_locked.get(id))
+        .filter_map(|cache| consensus::consensus(cache.node_data.values()).consensus_tip)
+        .map(|tip| tip.height)
+        .max()
+}
+
+// Prunes the stale branches of the header store forking off below the
+// horizon from the tree and the database. The horizon is derived from the
+// consensus height. Returns the number of pruned headers. Nothing is pruned
+// before there is a consensus tip.
+pub async fn prune(
+    header_store: u32,
+    pruning: &ForkPruning,
+    tree: &Tree,
+    active_tips: &[(BlockHash, u64)],
+    consensus_height: Option<u64>,
+    horizons: &PruneHorizons,
+    db: Db,
+) -> usize {
+    let best_height = match consensus_height {
+        Some(height) => height,
+        None => return 0,
+    };
+    let hashes: Vec<BlockHash> = active_tips.iter().map(|(hash, _)| *hash).collect();
+    let branches = headertree::stale_branches(tree, &hashes).await;
+    let fork_heights: Vec<u64> = branches.iter().map(|(_, height)| *height).collect();
+    let horizon = horizon(pruning, best_height, &fork_heights);
+    if horizons.lock().await.insert(header_store, horizon) != Some(horizon) {
+        if let Err(e) = db::write_prune_horizon(db.clone(), header_store, horizon).await {
+            error!(
+                "Could not write the prune horizon of header store {} to the database: {}",
+                header_store, e
+            );
+        }
+    }
+
+    let firsts: Vec<BlockHash> = branches
+        .iter()
+        .filter(|(_, height)| *height < horizon)
+        .map(|(first, _)| *first)
+        .collect();
+    let pruned = headertree::remove_branches(tree, &firsts).await;
+    if pruned.is_empty() {
+        return 0;
+    }
+    info!(
+        "Pruned {} stale branches with {} headers forking off below height {} from header store {}",
+        firsts.len(),
+        pruned.len(),
+        horizon,
+        header_store
+    );
+    if let Err(e) = db::delete_headers(db, header_store, &pruned).await {
+        error!(
+            "Could not delete the pruned headers of header store {} from the database: {}",
+            header_store, e
+        );
+    }
+    pruned.len()
+}
+
+// Drops the non-active tips forking off below the horizon of the header
+// store, so their pruned branches aren't fetched again.
+pub async fn unpruned(
+    horizons: &PruneHorizons,
+    header_store: u32,
+    tips: &[ChainTip],
+) -> Vec<ChainTip> {
+    let horizon = horizons
+        .lock()
+        .await
+        .get(&header_store)
+        .copied()
+        .unwrap_or_default();
+    tips.iter()
+        .filter(|tip| {
+            tip.status == ChainTipStatus::Active
+                || tip.height.saturating_sub(tip.branchlen as u64) >= horizon
+        })
+        .cloned()
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::{child, node_data};
+    use crate::types::Cache;
+    use petgraph::graph::DiGraph;
+    use rusqlite::Connection;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_horizon() {
+        let pruning = |keep_depth: Option<u64>, max_forks: Option<usize>| ForkPruning {
+            keep_depth,
+            max_forks,
+        };
+        let forks = [100, 500, 500, 900, 990];
+        assert_eq!(horizon(&pruning(Some(200), None), 1000, &forks), 800);
+        assert_eq!(horizon(&pruning(Some(2000), None), 1000, &forks), 0);
+        // two branches at 500 are one fork
+        assert_eq!(horizon(&pruning(None, Some(3)), 1000, &forks), 500);
+        assert_eq!(horizon(&pruning(None, Some(4)), 1000, &forks), 0);
+        // the stricter limit applies
+        assert_eq!(horizon(&pruning(Some(200), Some(3)), 1000, &forks), 800);
+        assert_eq!(horizon(&pruning(Some(800), Some(2)), 1000, &forks), 900);
+    }
+
+    #[tokio::test]
+    async fn test_unpruned() {
+        let tip = |height: u64, branchlen: usize, status: ChainTipStatus| ChainTip {
+            height,
+            hash: String::new(),
+            branchlen,
+            status,
+        };
+        let tips = vec![
+            tip(1000, 0, ChainTipStatus::Active),
+            tip(101, 1, ChainTipStatus::ValidFork),
+            tip(901, 1, ChainTipStatus::ValidFork),
+        ];
+        let horizons: PruneHorizons = Arc::new(Mutex::new(BTreeMap::new()));
+        assert_eq!(unpruned(&horizons, 1, &tips).await, tips);
+        horizons.lock().await.insert(1, 800);
+        assert_eq!(
+            unpruned(&horizons, 1, &tips).await,
+            vec![tips[0].clone(), tips[2].clone()]
+        );
+        assert_eq!(unpruned(&horizons, 2, &tips).await, tips);
+    }
+
+    #[tokio::test]
+    async fn test_outlier_nodes() {
+        let hash = |n: u8| format!("{:064x}", n);
+        let mut shadow = node_data(3, 1.0, &hash(3), 2_000_000);
+        shadow.shadow = true;
+        // two trusted nodes agree, a low-trust node and a shadow node
+        // report bogus heights
+        let nodes = vec![
+            node_data(0, 1.0, &hash(1), 1000),
+            node_data(1, 1.0, &hash(1), 1000),
+            node_data(2, 0.3, &hash(2), 1_000_000),
+            shadow,
+        ];
+        let mut caches = BTreeMap::new();
+        caches.insert(
+            1,
+            Cache {
+                header_infos_json: vec![],
+                node_data: nodes.into_iter().map(|node| (node.id, node)).collect(),
+                forks: vec![],
+                recent_miners: vec![],
+                raw_tips: BTreeMap::new(),
+            },
+        );
+        let caches: Caches = Arc::new(Mutex::new(caches));
+
+        assert_eq!(consensus_height(&caches, &[1]).await, Some(1000));
+        assert_eq!(consensus_height(&caches, &[2]).await, None);
+        let heights: Vec<u64> = active_tips(&caches, &[1])
+            .await
+            .iter()
+            .map(|(_, height)| *height)
+            .collect();
+        assert_eq!(heights, vec![1000, 1000, 1_000_000]);
+
+        // the stale branch b1 is within the keep depth of the consensus
+        // tip, but not of the bogus height
+        let a0 = child(None, 0, 0);
+        let a1 = child(Some(&a0), 0, 1);
+        let b1 = child(Some(&a0), 0, 2);
+        let tree: Tree = Arc::new(Mutex::new((DiGraph::new(), HashMap::new(), HashMap::new())));
+        headertree::insert_new_headers_into_tree(&tree, &[a0, a1.clone(), b1]).await;
+        let db: Db = Arc::new(Mutex::new(Connection::open_in_memory().unwrap()));
+        db::setup_db(db.clone()).await.unwrap();
+        let pruning = ForkPruning {
+            keep_depth: Some(1000),
+            max_forks: None,
+        };
+        let horizons: PruneHorizons = Arc::new(Mutex::new(BTreeMap::new()));
+        let active_tips = vec![(a1.block_hash(), a1.height)];
+        let height = consensus_height(&caches, &[1]).await;
+        let pruned = prune(
+            1,
+            &pruning,
+            &tree,
+            &active_tips,
+            height,
+            &horizons,
+            db.clone(),
+        )
+        .await;
+        assert_eq!(pruned, 0);
+        assert_eq!(horizons.lock().await[&1], 0);
+        let pruned = prune(
+            1,
+            &pruning,
+            &tree,
+            &active_tips,
+            Some(1_000_000),
+            &horizons,
+            db,
+        )
+        .await;
+        assert_eq!(pruned, 1);
+    }
+}
@@ -0,0 +1,247 @@
+use std::collections::{BTreeMap, HashSet};
+use std::sync::Arc;
+
+use bitcoincore_rpc::bitcoin::BlockHash;
+use tokio::sync::Mutex;
+use tokio::time::{Duration, Instant};
+
+use crate::config::TreeQuota;
+use crate::domain::{HeaderInfo, TreeInfo};
+
+const QUOTA_WINDOW: Duration = Duration::from_secs(60 * 60);
+
+// The headers currently quarantined per (network id, node id). They are
+// kept by the node's quota and admitted once the quota allows it, see
+// NodeQuota::retry_due.
+pub type Quarantine = Arc<Mutex<BTreeMap<(u32, u32), Vec<HeaderInfo>>>>;
+
+pub async fn set_quarantined(
+    quarantine: &Quarantine,
+    network_id: u32,
+    node_id: u32,
+    headers: Vec<HeaderInfo>,
+) {
+    let mut quarantine_locked = quarantine.lock().await;
+    if headers.is_empty() {
+        quarantine_locked.remove(&(network_id, node_id));
+    } else {
+        quarantine_locked.insert((network_id, node_id), headers);
+    }
+}
+
+// Counts the branches and headers a node introduced into the shared header
+// tree in the current hour.
+pub struct NodeQuota {
+    quota: TreeQuota,
+    window_start: Instant,
+    branches: u32,
+    headers: u32,
+    exceeded: bool,
+    // Admitted together with the next new headers. Stale branches don't
+    // change the node's tips, so they wouldn't be fetched again.
+    quarantined: Vec<HeaderInfo>,
+}
+
+pub struct Admission {
+    pub admitted: Vec<HeaderInfo>,
+    // All headers of the node currently quarantined, including the ones
+    // quarantined before.
+    pub quarantined: Vec<HeaderInfo>,
+    // Headers quarantined before that are admitted now.
+    pub released: Vec<BlockHash>,
+    // Set when the quota was exceeded for the first time in this window.
+    pub newly_exceeded: bool,
+}
+
+impl NodeQuota {
+    pub fn new(quota: TreeQuota, now: Instant) -> Self {
+        NodeQuota {
+            quota,
+            window_start: now,
+            branches: 0,
+            headers: 0,
+            exceeded: false,
+            quarantined: vec![],
+        }
+    }
+
+    // If headers are quarantined and a new window started, so they can be
+    // admitted without waiting for the node's tips to change.
+    pub fn retry_due(&self, now: Instant) -> bool {
+        !self.quarantined.is_empty() && now.duration_since(self.window_start) >= QUOTA_WINDOW
+    }
+
+    // Splits the new headers of the node, together with the ones quarantined
+    // before, into the ones admitted into the tree and the ones quarantined
+    // because the node exceeded its quota. Headers building on quarantined
+    // headers are quarantined too. A header
+    // starts a new branch if its parent already has a child or if its parent
+    // is unknown.
+    pub fn admit(
+        &mut self,
+        tree: &TreeInfo,
+        new_headers: &[HeaderInfo],
+        now: Instant,
+    ) -> Admission {
+        if now.duration_since(self.window_start) >= QUOTA_WINDOW {
+            self.window_start = now;
+            self.branches = 0;
+            self.headers = 0;
+            self.exceeded = false;
+        }
+
+        let held = std::mem::take(&mut self.quarantined);
+        let held_hashes: HashSet<BlockHash> = held.iter().map(|h| h.block_hash()).collect();
+        let new_hashes: HashSet<BlockHash> = new_headers.iter().map(|h| h.block_hash()).collect();
+        let mut sorted: Vec<&HeaderInfo> = new_headers
+            .iter()
+            .chain(
+                held.iter()
+                    .filter(|h| !new_hashes.contains(&h.block_hash())),
+            )
+            .collect();
+        sorted.sort_by_key(|h| h.height);
+
+        let mut admitted: Vec<HeaderInfo> = vec![];
+        let mut quarantined: Vec<HeaderInfo> = vec![];
+        let mut admitted_hashes: HashSet<BlockHash> = HashSet::new();
+        let mut admitted_parents: HashSet<BlockHash> = HashSet::new();
+        let mut quarantined_hashes: HashSet<BlockHash> = HashSet::new();
+        let mut newly_exceeded = false;
+
+        for header_info in sorted {
+            let hash = header_info.block_hash();
+            let prev = header_info.header.prev_blockhash;
+            if tree.1.contains_key(&hash) {
+                admitted.push(header_info.clone());
+                continue;
+            }
+            if quarantined_hashes.contains(&prev) {
+                quarantined_hashes.insert(hash);
+                quarantined.push(header_info.clone());
+                continue;
+            }
+
+            let new_branch = match tree.1.get(&prev) {
+                Some(idx) => {
+                    tree.0
+                        .neighbors_directed(*idx, petgraph::Direction::Outgoing)
+                        .next()
+                        .is_some()
+                        || admitted_parents.contains(&prev)
+                }
+                None => {
+                    if admitted_hashes.contains(&prev) {
+                        admitted_parents.contains(&prev)
+                    } else {
+                        // The first headers of an empty tree don't count
+                        // as a new branch.
+                        tree.0.node_count() > 0 || !admitted.is_empty()
+                    }
+                }
+            };
+
+            let over_quota = (new_branch
+                && self
+                    .quota
+                    .max_branches_per_hour
+                    .is_some_and(|max| self.branches >= max))
+                || self
+                    .quota
+                    .max_headers_per_hour
+                    .is_some_and(|max| self.headers >= max);
+            if over_quota {
+                if !self.exceeded {
+                    self.exceeded = true;
+                    newly_exceeded = true;
+                }
+                quarantined_hashes.insert(hash);
+                quarantined.push(header_info.clone());
+                continue;
+            }
+
+            if new_branch {
+                self.branches += 1;
+            }
+            self.headers += 1;
+            admitted_hashes.insert(hash);
+            admitted_parents.insert(prev);
+            admitted.push(header_info.clone());
+        }
+
+        let released = admitted
+            .iter()
+            .map(|h| h.block_hash())
+            .filter(|hash| held_hashes.contains(hash))
+            .collect();
+        self.quarantined = quarantined.clone();
+        Admission {
+            admitted,
+            quarantined,
+            released,
+            newly_exceeded,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::child;
+    use petgraph::graph::DiGraph;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_admit() {
+        let a0 = child(None, 0, 0);
+        let a1 = child(Some(&a0), 0, 1);
+        let mut graph = DiGraph::new();
+        let mut index = HashMap::new();
+        let idx0 = graph.add_node(a0.clone());
+        let idx1 = graph.add_node(a1.clone());
+        graph.add_edge(idx0, idx1, 0);
+        index.insert(a0.block_hash(), idx0);
+        index.insert(a1.block_hash(), idx1);
+        let tree: TreeInfo = (graph, index, HashMap::new());
+
+        let now = Instant::now();
+        let mut quota = NodeQuota::new(
+            TreeQuota {
+                max_branches_per_hour: Some(1),
+                max_headers_per_hour: None,
+            },
+            now,
+        );
+
+        // extending the chain isn't a new branch
+        let a2 = child(Some(&a1), 0, 2);
+        // two competing blocks: the first one is a new branch
+        let b1 = child(Some(&a0), 0, 3);
+        let c1 = child(Some(&a0), 0, 4);
+        // builds on a quarantined header
+        let c2 = child(Some(&c1), 0, 5);
+        let admission = quota.admit(
+            &tree,
+            &[a2.clone(), b1.clone(), c1.clone(), c2.clone()],
+            now,
+        );
+        assert_eq!(admission.admitted, vec![b1, a2]);
+        assert_eq!(admission.quarantined, vec![c1.clone(), c2.clone()]);
+        assert!(admission.newly_exceeded);
+
+        // the quarantined headers are kept
+        let admission = quota.admit(&tree, std::slice::from_ref(&c1), now);
+        assert!(!admission.newly_exceeded);
+        assert_eq!(admission.quarantined, vec![c1.clone(), c2.clone()]);
+        assert!(!quota.retry_due(now));
+        assert!(quota.retry_due(now + QUOTA_WINDOW));
+
+        // the quota resets after an hour, and the quarantined headers are
+        // admitted without being fetched again
+        let admission = quota.admit(&tree, &[], now + QUOTA_WINDOW);
+        assert_eq!(admission.admitted, vec![c1.clone(), c2.clone()]);
+        assert_eq!(admission.released, vec![c1.block_hash(), c2.block_hash()]);
+        assert!(admission.quarantined.is_empty());
+        assert!(!quota.retry_due(now + QUOTA_WINDOW * 2));
+    }
+}
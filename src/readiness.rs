@@ -0,0 +1,80 @@
+use std::collections::BTreeSet;
+use std::convert::Infallible;
+use std::sync::{Arc, Mutex};
+
+use warp::http::StatusCode;
+use warp::Reply;
+
+use crate::types::ReadinessJson;
+
+// Tracks the priming of the nodes after a start. A node is primed once its
+// version is loaded and its chain tips were queried for the first time,
+// whether it was reachable or not. Until all nodes are primed, the
+// readiness endpoint reports the instance as not ready, so load balancers
+// don't send traffic to a half-initialised instance.
+pub struct Readiness {
+    nodes: usize,
+    // The primed nodes as (network id, node id).
+    primed: Mutex<BTreeSet<(u32, u32)>>,
+}
+
+impl Readiness {
+    pub fn new(nodes: usize) -> Arc<Self> {
+        Arc::new(Readiness {
+            nodes,
+            primed: Mutex::new(BTreeSet::new()),
+        })
+    }
+
+    pub fn node_primed(&self, network_id: u32, node_id: u32) {
+        if let Ok(mut primed) = self.primed.lock() {
+            primed.insert((network_id, node_id));
+        }
+    }
+
+    // The number of primed nodes and the number of nodes.
+    pub fn progress(&self) -> (usize, usize) {
+        let primed = self.primed.lock().map(|p| p.len()).unwrap_or_default();
+        (primed, self.nodes)
+    }
+
+    pub fn is_ready(&self) -> bool {
+        let (primed, nodes) = self.progress();
+        primed >= nodes
+    }
+}
+
+pub async fn readiness_response(readiness: Arc<Readiness>) -> Result<impl Reply, Infallible> {
+    let (primed_nodes, nodes) = readiness.progress();
+    let ready = readiness.is_ready();
+    Ok(warp::reply::with_status(
+        warp::reply::json(&ReadinessJson {
+            ready,
+            primed_nodes,
+            nodes,
+        }),
+        if ready {
+            StatusCode::OK
+        } else {
+            StatusCode::SERVICE_UNAVAILABLE
+        },
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_readiness() {
+        let readiness = Readiness::new(2);
+        assert!(!readiness.is_ready());
+        readiness.node_primed(1, 0);
+        // priming a node twice counts once
+        readiness.node_primed(1, 0);
+        assert_eq!(readiness.progress(), (1, 2));
+        assert!(!readiness.is_ready());
+        readiness.node_primed(1, 1);
+        assert!(readiness.is_ready());
+    }
+}
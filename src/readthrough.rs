@@ -0,0 +1,251 @@
+use std::collections::{BTreeMap, HashMap};
+use std::convert::Infallible;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use bitcoincore_rpc::bitcoin::blockdata::block::Header;
+use bitcoincore_rpc::bitcoin::BlockHash;
+use log::{debug, info};
+use tokio::sync::Mutex;
+use tokio::time::{Duration, Instant};
+use warp::http::StatusCode;
+use warp::Reply;
+
+use crate::config::{ChainParams, Network};
+use crate::domain::{HeaderInfo, HeaderSource, Tree};
+use crate::rss::response_unknown_network;
+use crate::types::{BlockHeaderJsonResponse, Caches, HeaderInfoJson, NetworkJson, Versioned};
+
+const FETCH_WINDOW: Duration = Duration::from_secs(60);
+
+// The start of the current window and the fetches in it per network id.
+pub type FetchWindows = Arc<Mutex<BTreeMap<u32, (Instant, u32)>>>;
+
+// Counts a fetch against the network's limit. False if the limit of the
+// current window is exhausted.
+fn take_fetch(
+    windows: &mut BTreeMap<u32, (Instant, u32)>,
+    network_id: u32,
+    max_fetches: u32,
+    now: Instant,
+) -> bool {
+    let (window_start, fetches) = windows.entry(network_id).or_insert((now, 0));
+    if now.duration_since(*window_start) >= FETCH_WINDOW {
+        *window_start = now;
+        *fetches = 0;
+    }
+    if *fetches >= max_fetches {
+        return false;
+    }
+    *fetches += 1;
+    true
+}
+
+// The header with the hash and the tree indexes of it and its parent.
+async fn lookup(tree: &Tree, hash: &BlockHash, params: &ChainParams) -> Option<HeaderInfoJson> {
+    let tree_locked = tree.lock().await;
+    let idx = *tree_locked.1.get(hash)?;
+    let prev_id = tree_locked
+        .0
+        .neighbors_directed(idx, petgraph::Direction::Incoming)
+        .next()
+        .map(|prev| prev.index())
+        // indicates the start in JavaScript
+        .unwrap_or(usize::MAX);
+    Some(HeaderInfoJson::new(
+        &tree_locked.0[idx],
+        idx.index(),
+        prev_id,
+        params,
+    ))
+}
+
+// Asks the reachable, not paused nodes of the network for the header one
+// after another. A node answering with a different header, e.g. a
+// misbehaving custom node, is skipped.
+async fn fetch(
+    network: &Network,
+    caches: &Caches,
+    hash: &BlockHash,
+) -> Option<(Header, HeaderSource)> {
+    let healthy: Vec<u32> = {
+        let caches_locked = caches.lock().await;
+        caches_locked
+            .get(&network.id)?
+            .node_data
+            .values()
+            .filter(|node| node.reachable && !node.paused)
+            .map(|node| node.id)
+            .collect()
+    };
+    for node in network
+        .nodes
+        .iter()
+        .filter(|node| healthy.contains(&node.info().id))
+    {
+        match node.block_header(hash).await {
+            Ok(header) if header.block_hash() == *hash => {
+                return Some((header, node.header_source()))
+            }
+            Ok(header) => debug!(
+                "Node {} returned the header {} when asked for {}",
+                node.info(),
+                header.block_hash(),
+                hash
+            ),
+            Err(e) => debug!(
+                "Could not fetch the header {} from node {}: {}",
+                hash,
+                node.info(),
+                e
+            ),
+        }
+    }
+    None
+}
+
+// A fetched header building on a header in the tree, without inserting it.
+// It has no index in the tree, so its id is usize::MAX. None if its parent
+// isn't in the tree.
+async fn unpersisted(
+    tree: &Tree,
+    header: Header,
+    source: HeaderSource,
+    params: &ChainParams,
+) -> Option<HeaderInfoJson> {
+    let tree_locked = tree.lock().await;
+    let prev_idx = *tree_locked.1.get(&header.prev_blockhash)?;
+    let header_info = HeaderInfo {
+        height: tree_locked.0[prev_idx].height + 1,
+        header,
+        miner: String::new(),
+        own: false,
+        source,
+        elements: None,
+    };
+    Some(HeaderInfoJson::new(
+        &header_info,
+        usize::MAX,
+        prev_idx.index(),
+        params,
+    ))
+}
+
+// Responds with the header if it's in the tree. With read_through enabled,
+// an unknown header is fetched from a node and served if its parent is in
+// the tree, i.e. it's within the tracked heights. Fetched headers aren't
+// inserted into the tree or the database: headers only enter the tree via
+// the node queries, with their quotas and checks.
+pub async fn response(
+    network_id: u32,
+    hash: String,
+    networks: Vec<Network>,
+    trees: HashMap<u32, Tree>,
+    caches: Caches,
+    windows: FetchWindows,
+    network_infos: Vec<NetworkJson>,
+) -> Result<Box<dyn Reply>, Infallible> {
+    let network = match networks.iter().find(|n| n.id == network_id) {
+        Some(network) => network,
+        None => return Ok(Box::new(response_unknown_network(network_infos))),
+    };
+    let tree = match trees.get(&network.header_store) {
+        Some(tree) => tree,
+        None => return Ok(Box::new(StatusCode::NOT_FOUND)),
+    };
+    let hash = match BlockHash::from_str(&hash) {
+        Ok(hash) => hash,
+        Err(_) => return Ok(Box::new(StatusCode::BAD_REQUEST)),
+    };
+    let reply = |header_info: HeaderInfoJson, fetched: bool| -> Box<dyn Reply> {
+        Box::new(warp::reply::json(&Versioned::new(
+            BlockHeaderJsonResponse {
+                header_info,
+                fetched,
+            },
+        )))
+    };
+
+    if let Some(header_info) = lookup(tree, &hash, &network.chain_params).await {
+        return Ok(reply(header_info, false));
+    }
+    let read_through = match network.read_through {
+        Some(ref read_through) => read_through,
+        None => return Ok(Box::new(StatusCode::NOT_FOUND)),
+    };
+    if !take_fetch(
+        &mut *windows.lock().await,
+        network.id,
+        read_through.max_fetches_per_minute,
+        Instant::now(),
+    ) {
+        return Ok(Box::new(StatusCode::TOO_MANY_REQUESTS));
+    }
+
+    let (header, source) = match fetch(network, &caches, &hash).await {
+        Some(fetched) => fetched,
+        None => return Ok(Box::new(StatusCode::NOT_FOUND)),
+    };
+    match unpersisted(tree, header, source, &network.chain_params).await {
+        Some(header_info) => {
+            info!(
+                "Fetched the requested header {} at height {} on network '{}'",
+                hash, header_info.height, network.name
+            );
+            Ok(reply(header_info, true))
+        }
+        // Below the tracked heights or on a branch we don't know.
+        None => Ok(Box::new(StatusCode::NOT_FOUND)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::headertree;
+    use crate::testutil;
+    use petgraph::graph::DiGraph;
+
+    #[test]
+    fn test_take_fetch() {
+        let mut windows = BTreeMap::new();
+        let start = Instant::now();
+        assert!(take_fetch(&mut windows, 1, 2, start));
+        assert!(take_fetch(&mut windows, 1, 2, start));
+        assert!(!take_fetch(
+            &mut windows,
+            1,
+            2,
+            start + Duration::from_secs(59)
+        ));
+        // the limit is per network
+        assert!(take_fetch(&mut windows, 2, 2, start));
+        // a new window
+        assert!(take_fetch(&mut windows, 1, 2, start + FETCH_WINDOW));
+    }
+
+    #[tokio::test]
+    async fn test_unpersisted() {
+        let parent = testutil::child(None, 1000, 0);
+        let tree: Tree = Arc::new(Mutex::new((DiGraph::new(), HashMap::new(), HashMap::new())));
+        headertree::insert_new_headers_into_tree(&tree, std::slice::from_ref(&parent)).await;
+        let params = ChainParams::default();
+
+        let block = testutil::header(parent.block_hash(), 1600, 0);
+        let header_info = unpersisted(&tree, block, HeaderSource::Rpc, &params)
+            .await
+            .unwrap();
+        assert_eq!(header_info.hash, block.block_hash().to_string());
+        assert_eq!(header_info.height, 1);
+        assert_eq!(header_info.prev_id, 0);
+        // the tree is unchanged
+        assert_eq!(tree.lock().await.0.node_count(), 1);
+
+        let unknown_parent = testutil::header(block.block_hash(), 2200, 0);
+        assert!(
+            unpersisted(&tree, unknown_parent, HeaderSource::Rpc, &params)
+                .await
+                .is_none()
+        );
+    }
+}
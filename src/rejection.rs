@@ -0,0 +1,305 @@
+use std::collections::HashSet;
+
+use bitcoincore_rpc::bitcoin::{Amount, Block, BlockHash, Txid, Weight};
+use log::{debug, info, warn};
+use tokio::time::Duration;
+
+use crate::chainparams;
+use crate::config::{ChainParams, Network};
+use crate::db;
+use crate::domain::{ChainTipStatus, HeaderInfo, Rejection, Tree};
+use crate::headertree;
+use crate::paused::{self, PausedNodes};
+use crate::timestamps;
+use crate::types::{now_timestamp, Caches, Db, HeaderInfoJson};
+
+pub const REJECTION_CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+// At most this many blocks of an invalid branch are fetched, lowest first.
+const MAX_CHECKED_BLOCKS: usize = 10;
+
+// The reason recorded for the invalid tip when all blocks of its branch pass
+// the checks, e.g. because of an invalid script or a spent input.
+pub const UNKNOWN_REASON: &str = "unknown";
+
+// The checks of a block that need no UTXO set, in the order of Bitcoin
+// Core's CheckBlock() and ContextualCheckBlock(). Returns the reject reason
+// Bitcoin Core uses for the first failing check. The median time past is
+// None if it isn't known from the tree.
+pub fn check(
+    block: &Block,
+    header_info: &HeaderInfo,
+    median_time_past: Option<u32>,
+    params: &ChainParams,
+) -> Option<&'static str> {
+    if chainparams::pow_verified(params, header_info) == Some(false) {
+        return Some("high-hash");
+    }
+    if median_time_past.is_some_and(|mtp| block.header.time <= mtp) {
+        return Some("time-too-old");
+    }
+    if block.txdata.is_empty() {
+        return Some("bad-blk-length");
+    }
+    if !block.check_merkle_root() {
+        return Some("bad-txnmrklroot");
+    }
+    let mut txids: HashSet<Txid> = HashSet::new();
+    if !block.txdata.iter().all(|tx| txids.insert(tx.txid())) {
+        return Some("bad-txns-duplicate");
+    }
+    if block.weight() > Weight::MAX_BLOCK {
+        return Some("bad-blk-weight");
+    }
+    if !block.txdata[0].is_coinbase() {
+        return Some("bad-cb-missing");
+    }
+    if block.txdata[1..].iter().any(|tx| tx.is_coinbase()) {
+        return Some("bad-cb-multiple");
+    }
+    for (i, tx) in block.txdata.iter().enumerate() {
+        if tx.input.is_empty() {
+            return Some("bad-txns-vin-empty");
+        }
+        if tx.output.is_empty() {
+            return Some("bad-txns-vout-empty");
+        }
+        if tx.output.iter().any(|out| out.value > Amount::MAX_MONEY) {
+            return Some("bad-txns-vout-toolarge");
+        }
+        let total = tx
+            .output
+            .iter()
+            .try_fold(Amount::ZERO, |total, out| total.checked_add(out.value));
+        if total.is_none_or(|total| total > Amount::MAX_MONEY) {
+            return Some("bad-txns-txouttotal-toolarge");
+        }
+        let mut outpoints = HashSet::new();
+        if !tx
+            .input
+            .iter()
+            .all(|input| outpoints.insert(input.previous_output))
+        {
+            return Some("bad-txns-inputs-duplicate");
+        }
+        if i == 0 {
+            let script_len = tx.input[0].script_sig.len();
+            if !(2..=100).contains(&script_len) {
+                return Some("bad-cb-length");
+            }
+        } else if tx.input.iter().any(|input| input.previous_output.is_null()) {
+            return Some("bad-txns-prevout-null");
+        }
+    }
+    if block
+        .bip34_block_height()
+        .is_ok_and(|height| height != header_info.height)
+    {
+        return Some("bad-cb-height");
+    }
+    if !block.check_witness_commitment() {
+        return Some("bad-witness-merkle-match");
+    }
+    // Without other transactions, there are no fees to claim.
+    let coinbase_value: u64 = block.txdata[0]
+        .output
+        .iter()
+        .map(|out| out.value.to_sat())
+        .sum();
+    if block.txdata.len() == 1 && coinbase_value > chainparams::subsidy(params, header_info.height)
+    {
+        return Some("bad-cb-amount");
+    }
+    None
+}
+
+// Checks the invalid tips the nodes of the network reported since the last
+// call. The blocks of the branch of an invalid tip are fetched from the
+// node reporting it and checked, lowest first, and the reason of the first
+// failing block is recorded. If all blocks pass, the reason is unknown and
+// recorded for the tip. Returns the number of recorded rejections.
+pub async fn record(
+    network: &Network,
+    tree: &Tree,
+    caches: &Caches,
+    db: Db,
+    paused_nodes: &PausedNodes,
+    checked: &mut HashSet<(u32, BlockHash)>,
+) -> usize {
+    let node_tips: Vec<(u32, BlockHash, Vec<BlockHash>)> = {
+        let caches_locked = caches.lock().await;
+        let cache = match caches_locked.get(&network.id) {
+            Some(cache) => cache,
+            None => return 0,
+        };
+        cache
+            .node_data
+            .iter()
+            .filter_map(|(node_id, node)| {
+                let active_tip = *node.tip_hashes(ChainTipStatus::Active).first()?;
+                Some((
+                    *node_id,
+                    active_tip,
+                    node.tip_hashes(ChainTipStatus::Invalid),
+                ))
+            })
+            .collect()
+    };
+
+    let mut recorded = 0;
+    for (node_id, active_tip, invalid_tips) in node_tips.iter() {
+        let node = match network.nodes.iter().find(|n| n.info().id == *node_id) {
+            Some(node) => node,
+            None => continue,
+        };
+        if paused::is_paused(paused_nodes, network.id, *node_id).await {
+            continue;
+        }
+        for invalid_tip in invalid_tips.iter() {
+            if !checked.insert((*node_id, *invalid_tip)) {
+                continue;
+            }
+            let branch = headertree::branch_above(tree, invalid_tip, active_tip).await;
+            let mut rejected: Option<(BlockHash, &str)> = None;
+            let mut all_checked = true;
+            for header_info in branch.iter().take(MAX_CHECKED_BLOCKS) {
+                let hash = header_info.block_hash();
+                let block = match node.block(&hash).await {
+                    Ok(Some(block)) if block.block_hash() == hash => block,
+                    Ok(_) => {
+                        all_checked = false;
+                        break;
+                    }
+                    Err(e) => {
+                        debug!(
+                            "Could not fetch the invalid block {} from node {} on network '{}': {}",
+                            hash,
+                            node.info(),
+                            network.name,
+                            e
+                        );
+                        all_checked = false;
+                        break;
+                    }
+                };
+                let median_time_past = {
+                    let tree_locked = tree.lock().await;
+                    tree_locked
+                        .1
+                        .get(&hash)
+                        .and_then(|idx| timestamps::median_time_past(&tree_locked, *idx))
+                };
+                if let Some(reason) =
+                    check(&block, header_info, median_time_past, &network.chain_params)
+                {
+                    rejected = Some((hash, reason));
+                    break;
+                }
+            }
+            let (hash, reason) = match rejected {
+                Some(rejected) => rejected,
+                None if all_checked && branch.len() <= MAX_CHECKED_BLOCKS => {
+                    (*invalid_tip, UNKNOWN_REASON)
+                }
+                None => continue,
+            };
+            info!(
+                "Block {} of the invalid tip {} of node {} on network '{}' was rejected: {}",
+                hash,
+                invalid_tip,
+                node.info(),
+                network.name,
+                reason
+            );
+            let rejection = Rejection {
+                hash: hash.to_string(),
+                node: *node_id,
+                reason: reason.to_string(),
+                timestamp: now_timestamp(),
+            };
+            match db::write_rejection(db.clone(), network.id, &rejection).await {
+                Ok(_) => recorded += 1,
+                Err(e) => warn!(
+                    "Could not write the rejection of block {} to database: {}",
+                    hash, e
+                ),
+            }
+        }
+    }
+    recorded
+}
+
+// Sets the recorded rejection reasons of the blocks of the tree data.
+pub async fn add_rejections(db: Db, network_id: u32, header_infos: &mut [HeaderInfoJson]) {
+    let hashes: Vec<String> = header_infos.iter().map(|h| h.hash.clone()).collect();
+    let rejections = match db::load_rejections(db, network_id, &hashes).await {
+        Ok(rejections) => rejections,
+        Err(e) => {
+            warn!(
+                "Could not load the rejections of network {}: {}",
+                network_id, e
+            );
+            return;
+        }
+    };
+    for header_info in header_infos.iter_mut() {
+        header_info.rejection_reason = rejections
+            .get(&header_info.hash)
+            .map(|rejection| rejection.reason.clone());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::header_info;
+    use bitcoincore_rpc::bitcoin::blockdata::constants::genesis_block;
+
+    #[test]
+    fn test_check() {
+        let params = ChainParams::default();
+        let genesis = genesis_block(bitcoincore_rpc::bitcoin::Network::Bitcoin);
+        assert_eq!(
+            check(&genesis, &header_info(0, genesis.header), None, &params),
+            None
+        );
+
+        // with a time before the median time past
+        let mtp = Some(genesis.header.time);
+        assert_eq!(
+            check(&genesis, &header_info(0, genesis.header), mtp, &params),
+            Some("time-too-old")
+        );
+
+        // with a second coinbase, the merkle root doesn't match anymore
+        let mut block = genesis.clone();
+        block.txdata.push(block.txdata[0].clone());
+        assert_eq!(
+            check(&block, &header_info(0, block.header), None, &params),
+            Some("bad-txnmrklroot")
+        );
+        // without proof of work for the new merkle root
+        block.header.merkle_root = block.compute_merkle_root().unwrap();
+        assert_eq!(
+            check(&block, &header_info(0, block.header), None, &params),
+            Some("high-hash")
+        );
+        let params = ChainParams {
+            validate_pow: false,
+            ..ChainParams::default()
+        };
+        assert_eq!(
+            check(&block, &header_info(0, block.header), None, &params),
+            Some("bad-txns-duplicate")
+        );
+
+        // a coinbase claiming more than the subsidy without transactions
+        let mut block = genesis.clone();
+        block.txdata[0].output[0].value = Amount::from_sat(50 * 100_000_000 + 1);
+        block.header.merkle_root = block.compute_merkle_root().unwrap();
+        assert_eq!(
+            check(&block, &header_info(0, block.header), None, &params),
+            Some("bad-cb-amount")
+        );
+    }
+}
@@ -0,0 +1,155 @@
+use log::{error, info};
+
+use crate::db;
+use crate::domain::{ChainTip, NodeInfo, Reorg, Tree};
+use crate::headertree;
+use crate::types::Db;
+
+// The reorg if the node's active tip switched from old_tip to new_tip, a
+// block not building on it. None if the new tip extends the old one or
+// their chains aren't connected in the tree, e.g. because the headers of
+// the new tip were quarantined. A node rolling back its active chain, e.g.
+// after invalidateblock, disconnects blocks as well and is a reorg without
+// a new branch.
+pub async fn detect(
+    network_id: u32,
+    node_id: u32,
+    tree: &Tree,
+    old_tip: &ChainTip,
+    new_tip: &ChainTip,
+    timestamp: u64,
+) -> Option<Reorg> {
+    if old_tip.hash == new_tip.hash {
+        return None;
+    }
+    let tree_locked = tree.lock().await;
+    let old_hash = old_tip.block_hash();
+    let new_hash = new_tip.block_hash();
+    let common_idx = headertree::common_ancestor(&tree_locked, &old_hash, &new_hash)?;
+    let (tree, index, _) = &*tree_locked;
+    let common = &tree[common_idx];
+    if common.block_hash() == old_hash {
+        return None;
+    }
+    let old = &tree[index[&old_hash]];
+    let new = &tree[index[&new_hash]];
+    Some(Reorg {
+        id: 0,
+        network_id,
+        node_id,
+        timestamp,
+        old_tip_hash: old_tip.hash.clone(),
+        old_tip_height: old.height,
+        old_tip_time: old.header.time,
+        new_tip_hash: new_tip.hash.clone(),
+        new_tip_height: new.height,
+        new_tip_time: new.header.time,
+        common_ancestor_hash: common.block_hash().to_string(),
+        common_ancestor_height: common.height,
+        depth: old.height - common.height,
+    })
+}
+
+// Detects and records a reorg of the node's active tip.
+#[allow(clippy::too_many_arguments)]
+pub async fn check_reorg(
+    network_id: u32,
+    network_name: &str,
+    node: &NodeInfo,
+    tree: &Tree,
+    old_tip: &ChainTip,
+    new_tip: &ChainTip,
+    timestamp: u64,
+    db: Db,
+) {
+    let reorg = match detect(network_id, node.id, tree, old_tip, new_tip, timestamp).await {
+        Some(reorg) => reorg,
+        None => return,
+    };
+    info!(
+        "Node {} on network '{}' reorged {} blocks from {} to {} at height {}",
+        node,
+        network_name,
+        reorg.depth,
+        reorg.old_tip_hash,
+        reorg.new_tip_hash,
+        reorg.common_ancestor_height
+    );
+    if let Err(e) = db::write_reorg(db, &reorg).await {
+        error!(
+            "Could not write a reorg of node {} on network '{}' to database: {}",
+            node, network_name, e
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::{ChainTipStatus, HeaderInfo};
+    use crate::testutil::child;
+    use petgraph::graph::DiGraph;
+    use std::collections::HashMap;
+    use std::sync::Arc;
+    use tokio::sync::Mutex;
+
+    fn tip(header_info: &HeaderInfo) -> ChainTip {
+        ChainTip {
+            height: header_info.height,
+            hash: header_info.block_hash().to_string(),
+            branchlen: 0,
+            status: ChainTipStatus::Active,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_detect() {
+        let a0 = child(None, 0, 0);
+        let a1 = child(Some(&a0), 600, 1);
+        let a2 = child(Some(&a1), 1200, 2);
+        let b2 = child(Some(&a1), 1800, 3);
+        let b3 = child(Some(&b2), 2400, 4);
+        let mut graph = DiGraph::new();
+        let mut index = HashMap::new();
+        for header_info in [&a0, &a1, &a2, &b2, &b3] {
+            let idx = graph.add_node(header_info.clone());
+            index.insert(header_info.block_hash(), idx);
+            if let Some(prev) = index.get(&header_info.header.prev_blockhash) {
+                graph.add_edge(*prev, idx, 0);
+            }
+        }
+        let tree: Tree = Arc::new(Mutex::new((graph, index, HashMap::new())));
+
+        // extending the active chain
+        assert_eq!(detect(1, 2, &tree, &tip(&a1), &tip(&a2), 100).await, None);
+        assert_eq!(detect(1, 2, &tree, &tip(&a2), &tip(&a2), 100).await, None);
+        // switching to the longer branch
+        assert_eq!(
+            detect(1, 2, &tree, &tip(&a2), &tip(&b3), 100).await,
+            Some(Reorg {
+                id: 0,
+                network_id: 1,
+                node_id: 2,
+                timestamp: 100,
+                old_tip_hash: a2.block_hash().to_string(),
+                old_tip_height: 2,
+                old_tip_time: 1200,
+                new_tip_hash: b3.block_hash().to_string(),
+                new_tip_height: 3,
+                new_tip_time: 2400,
+                common_ancestor_hash: a1.block_hash().to_string(),
+                common_ancestor_height: 1,
+                depth: 1,
+            })
+        );
+        // rolling back
+        let rollback = detect(1, 2, &tree, &tip(&b3), &tip(&a1), 100)
+            .await
+            .unwrap();
+        assert_eq!(rollback.common_ancestor_hash, a1.block_hash().to_string());
+        assert_eq!(rollback.depth, 2);
+        // the new tip isn't in the tree
+        let c4 = child(Some(&b3), 3000, 5);
+        assert_eq!(detect(1, 2, &tree, &tip(&a2), &tip(&c4), 100).await, None);
+    }
+}
@@ -0,0 +1,266 @@
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+use bitcoincore_rpc::bitcoin::blockdata::block::Header;
+use bitcoincore_rpc::bitcoin::{self, BlockHash, Transaction};
+use serde::Deserialize;
+
+use crate::domain::{ChainTip, ChainTipStatus, HeaderInfo, HeaderSource, NodeInfo, Tree};
+use crate::error::FetchError;
+use crate::node::{Node, RestStepSize, DEFAULT_REST_MAX_STEP_SIZE};
+
+#[derive(Debug, Deserialize)]
+struct ReplayFile {
+    steps: Vec<ReplayStep>,
+}
+
+// The state of the node from `after` seconds after the first query on.
+// Headers are revealed at the step they are listed in and stay known.
+#[derive(Debug, Deserialize)]
+struct ReplayStep {
+    after: u64,
+    #[serde(default)]
+    headers: Vec<ReplayHeader>,
+    tips: Vec<ReplayTip>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ReplayHeader {
+    height: u64,
+    // The hex encoded 80-byte header.
+    header: String,
+    #[serde(default)]
+    miner: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct ReplayTip {
+    hash: String,
+    height: u64,
+    status: String,
+    #[serde(default)]
+    branchlen: usize,
+}
+
+struct Step {
+    after: Duration,
+    headers: Vec<HeaderInfo>,
+    tips: Vec<ChainTip>,
+}
+
+// Parses and validates a replay file. The steps must be ordered by time.
+fn parse_replay(content: &str) -> Result<Vec<Step>, String> {
+    let file: ReplayFile = serde_json::from_str(content).map_err(|e| e.to_string())?;
+    if file.steps.is_empty() {
+        return Err(String::from("no steps"));
+    }
+    let mut steps: Vec<Step> = vec![];
+    for step in file.steps {
+        if steps
+            .last()
+            .is_some_and(|last| last.after > Duration::from_secs(step.after))
+        {
+            return Err(String::from("the steps aren't ordered by time"));
+        }
+        let mut headers = vec![];
+        for header in step.headers {
+            let bytes = hex::decode(&header.header)
+                .map_err(|e| format!("invalid header '{}': {}", header.header, e))?;
+            headers.push(HeaderInfo {
+                height: header.height,
+                header: bitcoin::consensus::deserialize(&bytes)
+                    .map_err(|e| format!("invalid header '{}': {}", header.header, e))?,
+                miner: header.miner,
+                own: false,
+                source: HeaderSource::Replay,
+                elements: None,
+            });
+        }
+        let mut tips = vec![];
+        for tip in step.tips {
+            BlockHash::from_str(&tip.hash)
+                .map_err(|e| format!("invalid tip hash '{}': {}", tip.hash, e))?;
+            tips.push(ChainTip {
+                height: tip.height,
+                hash: tip.hash,
+                branchlen: tip.branchlen,
+                status: ChainTipStatus::from(tip.status),
+            });
+        }
+        steps.push(Step {
+            after: Duration::from_secs(step.after),
+            headers,
+            tips,
+        });
+    }
+    Ok(steps)
+}
+
+// Plays back a recorded sequence of chain tips and headers, e.g. to demo a
+// fork scenario or for tests without regtest nodes. The replay starts with
+// the first query of the node and stays at the last step once played.
+pub struct ReplayNode {
+    info: NodeInfo,
+    path: String,
+    steps: Vec<Step>,
+    started: Mutex<Option<Instant>>,
+    rest_step_size: RestStepSize,
+}
+
+impl ReplayNode {
+    pub fn new(info: NodeInfo, path: &Path) -> Result<Self, String> {
+        let content = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+        Ok(ReplayNode {
+            info,
+            path: path.display().to_string(),
+            steps: parse_replay(&content)?,
+            started: Mutex::new(None),
+            rest_step_size: RestStepSize::new(DEFAULT_REST_MAX_STEP_SIZE),
+        })
+    }
+
+    // The number of steps played back by now.
+    fn played(&self) -> usize {
+        let elapsed = match self.started.lock() {
+            Ok(mut started) => started.get_or_insert_with(Instant::now).elapsed(),
+            Err(_) => Duration::ZERO,
+        };
+        self.steps
+            .iter()
+            .take_while(|step| step.after <= elapsed)
+            .count()
+            .max(1)
+    }
+
+    fn known_headers(&self) -> impl Iterator<Item = &HeaderInfo> {
+        self.steps[..self.played()]
+            .iter()
+            .flat_map(|step| step.headers.iter())
+    }
+}
+
+#[async_trait]
+impl Node for ReplayNode {
+    fn info(&self) -> NodeInfo {
+        self.info.clone()
+    }
+
+    fn use_rest(&self) -> bool {
+        false
+    }
+
+    fn rest_step_size(&self) -> &RestStepSize {
+        &self.rest_step_size
+    }
+
+    fn rpc_url(&self) -> String {
+        self.path.clone()
+    }
+
+    // The replay file isn't a network address.
+    fn host(&self) -> Option<String> {
+        None
+    }
+
+    async fn version(&self) -> Result<String, FetchError> {
+        Ok(format!("Replay of {}", self.path))
+    }
+
+    async fn block_header(&self, hash: &BlockHash) -> Result<Header, FetchError> {
+        self.known_headers()
+            .find(|header_info| header_info.block_hash() == *hash)
+            .map(|header_info| header_info.header)
+            .ok_or_else(|| FetchError::DataError(format!("unknown header {}", hash)))
+    }
+
+    async fn coinbase(&self, _hash: &BlockHash) -> Result<Transaction, FetchError> {
+        Err(FetchError::DataError(String::from(
+            "a replay doesn't contain blocks",
+        )))
+    }
+
+    async fn block_hash(&self, height: u64) -> Result<BlockHash, FetchError> {
+        Err(FetchError::DataError(format!(
+            "a replay doesn't index the block hash at height {}",
+            height
+        )))
+    }
+
+    async fn tips(&self) -> Result<Vec<ChainTip>, FetchError> {
+        Ok(self.steps[self.played() - 1].tips.clone())
+    }
+
+    // The headers revealed by now that aren't in the tree yet. Their
+    // miners are taken from the replay.
+    async fn new_headers(
+        &self,
+        _tips: &[ChainTip],
+        tree: &Tree,
+        min_fork_height: u64,
+    ) -> Result<(Vec<HeaderInfo>, Vec<BlockHash>), FetchError> {
+        let tree_locked = tree.lock().await;
+        let new_headers = self
+            .known_headers()
+            .filter(|header_info| {
+                header_info.height >= min_fork_height
+                    && !tree_locked.1.contains_key(&header_info.block_hash())
+            })
+            .cloned()
+            .collect();
+        Ok((new_headers, vec![]))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::header;
+    use bitcoincore_rpc::bitcoin::hashes::Hash;
+
+    #[test]
+    fn test_parse_replay() {
+        let a = header(BlockHash::all_zeros(), 1_600_000_000, 1);
+        let b = header(a.block_hash(), 1_600_000_000, 2);
+        let b2 = header(a.block_hash(), 1_600_000_000, 3);
+        let replay = serde_json::json!({
+            "steps": [
+                {
+                    "after": 0,
+                    "headers": [
+                        {"height": 1, "header": bitcoin::consensus::encode::serialize_hex(&a)},
+                        {"height": 2, "header": bitcoin::consensus::encode::serialize_hex(&b), "miner": "Pool"},
+                    ],
+                    "tips": [{"hash": b.block_hash().to_string(), "height": 2, "status": "active"}],
+                },
+                {
+                    "after": 60,
+                    "headers": [{"height": 2, "header": bitcoin::consensus::encode::serialize_hex(&b2)}],
+                    "tips": [
+                        {"hash": b.block_hash().to_string(), "height": 2, "status": "active"},
+                        {"hash": b2.block_hash().to_string(), "height": 2, "status": "valid-fork", "branchlen": 1},
+                    ],
+                },
+            ],
+        });
+        let steps = parse_replay(&replay.to_string()).unwrap();
+        assert_eq!(steps.len(), 2);
+        assert_eq!(steps[0].headers[1].header, b);
+        assert_eq!(steps[0].headers[1].miner, "Pool");
+        assert_eq!(steps[1].after, Duration::from_secs(60));
+        assert_eq!(steps[1].tips[1].status, ChainTipStatus::ValidFork);
+        assert_eq!(steps[1].tips[1].branchlen, 1);
+
+        let mut unordered = replay.clone();
+        unordered["steps"][1]["after"] = 0.into();
+        unordered["steps"][0]["after"] = 60.into();
+        assert!(parse_replay(&unordered.to_string()).is_err());
+
+        let mut invalid = replay;
+        invalid["steps"][0]["headers"][0]["header"] = "00".into();
+        assert!(parse_replay(&invalid.to_string()).is_err());
+        assert!(parse_replay(r#"{"steps": []}"#).is_err());
+    }
+}
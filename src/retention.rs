@@ -0,0 +1,90 @@
+use log::{error, info};
+use tokio::time::{interval, Duration};
+
+use crate::db;
+use crate::types::{now_timestamp, Db};
+
+const PRUNE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+// Periodically deletes the tip observations and the external sightings
+// older than the retention window. With compact, the database is compacted
+// afterwards to return the freed space to the file system.
+pub async fn run(db: Db, retention: Duration, compact: bool) {
+    let mut interval = interval(PRUNE_INTERVAL);
+    loop {
+        interval.tick().await;
+        let deleted = delete_expired(db.clone(), retention, now_timestamp()).await;
+        if compact && deleted > 0 {
+            if let Err(e) = db::compact(db.clone()).await {
+                error!("Could not compact the database: {}", e);
+            }
+        }
+    }
+}
+
+// Deletes the tip observations and the external sightings older than the
+// retention window and returns how many were deleted.
+pub async fn delete_expired(db: Db, retention: Duration, now: u64) -> usize {
+    let before = now.saturating_sub(retention.as_secs());
+    let days = retention.as_secs() / (24 * 60 * 60);
+    let mut deleted = 0;
+    match db::delete_tip_observations_before(db.clone(), before).await {
+        Ok(0) => (),
+        Ok(observations) => {
+            info!(
+                "Deleted {} tip observations older than {} days",
+                observations, days
+            );
+            deleted += observations;
+        }
+        Err(e) => error!("Could not delete old tip observations: {}", e),
+    }
+    match db::delete_external_sightings_before(db, before).await {
+        Ok(0) => (),
+        Ok(sightings) => {
+            info!(
+                "Deleted {} external sightings older than {} days",
+                sightings, days
+            );
+            deleted += sightings;
+        }
+        Err(e) => error!("Could not delete old external sightings: {}", e),
+    }
+    deleted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::{ChainTip, ChainTipStatus};
+    use rusqlite::Connection;
+    use std::sync::Arc;
+    use tokio::sync::Mutex;
+
+    #[tokio::test]
+    async fn test_delete_expired() {
+        let db: Db = Arc::new(Mutex::new(Connection::open_in_memory().unwrap()));
+        db::setup_db(db.clone()).await.unwrap();
+        let day = Duration::from_secs(24 * 60 * 60);
+        let tips = vec![ChainTip {
+            height: 100,
+            hash: "a".to_string(),
+            branchlen: 0,
+            status: ChainTipStatus::Active,
+        }];
+        let now = 10 * day.as_secs();
+        for timestamp in &[now - 2 * day.as_secs(), now - 1000] {
+            db::write_tip_observations(db.clone(), 1, 0, *timestamp, &tips)
+                .await
+                .unwrap();
+        }
+
+        assert_eq!(delete_expired(db.clone(), day, now).await, 1);
+        let observations = db::load_tip_observations(db.clone(), 1, 0, now)
+            .await
+            .unwrap();
+        assert_eq!(observations.len(), 1);
+        assert_eq!(observations[0].timestamp, now - 1000);
+        assert_eq!(delete_expired(db, day, now).await, 0);
+    }
+}
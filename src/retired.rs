@@ -0,0 +1,130 @@
+use std::convert::Infallible;
+
+use log::{error, info};
+use tokio::time::{interval, Duration};
+use warp::http::StatusCode;
+use warp::Reply;
+
+use crate::config::Network;
+use crate::db;
+use crate::domain::RetiredNode;
+use crate::rss::response_unknown_network;
+use crate::types::{
+    now_timestamp, Db, NetworkJson, RetiredNodeJson, RetiredNodesJsonResponse, Versioned,
+};
+
+const PURGE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+// The retired nodes whose data is due to be purged.
+fn due(nodes: &[RetiredNode], now: u64) -> Vec<&RetiredNode> {
+    nodes
+        .iter()
+        .filter(|node| node.purge_at().is_some_and(|purge_at| purge_at <= now))
+        .collect()
+}
+
+// Records the configured nodes and retires the nodes removed from the
+// configuration since the last startup. Afterwards, periodically purges the
+// data of retired nodes once their retention window has passed.
+pub async fn run(db: Db, networks: Vec<Network>) {
+    let configured: Vec<(u32, _)> = networks
+        .iter()
+        .flat_map(|network| {
+            network
+                .nodes
+                .iter()
+                .map(move |node| (network.id, node.info()))
+        })
+        .collect();
+    match db::sync_nodes(db.clone(), &configured, now_timestamp()).await {
+        Ok(retired) => {
+            for (network, node) in retired {
+                info!(
+                    "Node id={} on network id={} was removed from the configuration and is now retired",
+                    node, network
+                );
+            }
+        }
+        Err(e) => error!("Could not record the configured nodes: {}", e),
+    }
+
+    let mut interval = interval(PURGE_INTERVAL);
+    loop {
+        interval.tick().await;
+        let nodes = match db::load_retired_nodes(db.clone()).await {
+            Ok(nodes) => nodes,
+            Err(e) => {
+                error!("Could not load the retired nodes: {}", e);
+                continue;
+            }
+        };
+        for node in due(&nodes, now_timestamp()) {
+            match db::purge_node(db.clone(), node.network_id, node.node_id).await {
+                Ok(_) => info!(
+                    "Purged the data of the retired node '{}' (id={}) on network id={}",
+                    node.name, node.node_id, node.network_id
+                ),
+                Err(e) => error!(
+                    "Could not purge the data of the retired node '{}' (id={}) on network id={}: {}",
+                    node.name, node.node_id, node.network_id, e
+                ),
+            }
+        }
+    }
+}
+
+pub async fn retired_nodes_response(
+    network_id: u32,
+    db: Db,
+    network_infos: Vec<NetworkJson>,
+) -> Result<Box<dyn Reply>, Infallible> {
+    // Retired nodes of removed networks are kept in the database, but
+    // can't be queried.
+    if !network_infos.iter().any(|network| network.id == network_id) {
+        return Ok(Box::new(response_unknown_network(network_infos)));
+    }
+    match db::load_retired_nodes(db).await {
+        Ok(nodes) => Ok(Box::new(warp::reply::json(&Versioned::new(
+            RetiredNodesJsonResponse {
+                nodes: nodes
+                    .iter()
+                    .filter(|node| node.network_id == network_id)
+                    .map(RetiredNodeJson::new)
+                    .collect(),
+            },
+        )))),
+        Err(e) => {
+            error!("Could not load the retired nodes: {}", e);
+            Ok(Box::new(StatusCode::INTERNAL_SERVER_ERROR))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn node(node_id: u32, retired: u64, purge_after: Option<u64>) -> RetiredNode {
+        RetiredNode {
+            network_id: 0,
+            node_id,
+            name: String::new(),
+            description: String::new(),
+            implementation: String::new(),
+            retired,
+            purge_after,
+        }
+    }
+
+    #[test]
+    fn test_due() {
+        let nodes = [
+            node(0, 100, None),
+            node(1, 100, Some(50)),
+            node(2, 100, Some(51)),
+        ];
+        let due_nodes: Vec<u32> = due(&nodes, 150).iter().map(|n| n.node_id).collect();
+        assert_eq!(due_nodes, vec![1]);
+        assert!(due(&nodes, 149).is_empty());
+    }
+}
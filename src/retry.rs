@@ -0,0 +1,135 @@
+use std::collections::hash_map::RandomState;
+use std::fmt;
+use std::future::Future;
+use std::hash::{BuildHasher, Hasher};
+use std::time::Duration;
+
+use log::debug;
+use tokio::time::sleep;
+
+use crate::domain::NodeInfo;
+
+const MAX_RETRY_BACKOFF: Duration = Duration::from_secs(60);
+
+// How often and after which delay a failed fetch from a node is retried
+// within a query cycle before the node is reported unreachable.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct RetryPolicy {
+    pub max_retries: u32,
+    /// Delay before the first retry. Doubles with each further retry up to
+    /// MAX_RETRY_BACKOFF.
+    pub backoff: Duration,
+}
+
+impl RetryPolicy {
+    // The delay before the retry number `retry` (starting at 1) without
+    // jitter.
+    pub fn backoff(&self, retry: u32) -> Duration {
+        let exponent = retry.saturating_sub(1).min(16);
+        self.backoff
+            .saturating_mul(1 << exponent)
+            .min(MAX_RETRY_BACKOFF)
+    }
+}
+
+// Adds up to half of the delay as jitter, so nodes behind the same failing
+// proxy aren't retried in lockstep. Not cryptographically secure.
+fn jitter(delay: Duration) -> Duration {
+    let random = RandomState::new().build_hasher().finish();
+    delay + delay.mul_f64((random % 1000) as f64 / 2000.0)
+}
+
+// Calls fetch until it succeeds or the retries of the node's policy are used
+// up. Only the last error is returned, the failed attempts before are logged
+// at debug level.
+pub async fn retry<T, E, F, Fut>(node: &NodeInfo, what: &str, mut fetch: F) -> Result<T, E>
+where
+    E: fmt::Display,
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, E>>,
+{
+    let mut retries = 0;
+    loop {
+        match fetch().await {
+            Ok(result) => {
+                if retries > 0 {
+                    debug!("Fetched {} from {} after {} retries", what, node, retries);
+                }
+                return Ok(result);
+            }
+            Err(e) if retries < node.retry.max_retries => {
+                retries += 1;
+                let delay = jitter(node.retry.backoff(retries));
+                debug!(
+                    "Could not fetch {} from {}, retrying in {:?} ({}/{}): {}",
+                    what, node, delay, retries, node.retry.max_retries, e
+                );
+                sleep(delay).await;
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backoff() {
+        let policy = RetryPolicy {
+            max_retries: 3,
+            backoff: Duration::from_secs(2),
+        };
+        assert_eq!(policy.backoff(1), Duration::from_secs(2));
+        assert_eq!(policy.backoff(2), Duration::from_secs(4));
+        assert_eq!(policy.backoff(3), Duration::from_secs(8));
+        assert_eq!(policy.backoff(u32::MAX), MAX_RETRY_BACKOFF);
+
+        for _ in 0..100 {
+            let delay = jitter(Duration::from_secs(2));
+            assert!(delay >= Duration::from_secs(2) && delay < Duration::from_secs(3));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_retry() {
+        let mut node = NodeInfo {
+            id: 0,
+            name: String::new(),
+            description: String::new(),
+            implementation: String::new(),
+            trust: 1.0,
+            shadow: false,
+            purge_after: None,
+            retry: RetryPolicy {
+                max_retries: 2,
+                backoff: Duration::from_millis(1),
+            },
+        };
+
+        // a transient failure is absorbed
+        let mut attempts = 0;
+        let result: Result<u32, String> = retry(&node, "tips", || {
+            attempts += 1;
+            let result = if attempts < 3 {
+                Err("connection refused".to_string())
+            } else {
+                Ok(attempts)
+            };
+            async move { result }
+        })
+        .await;
+        assert_eq!(result, Ok(3));
+
+        node.retry.max_retries = 1;
+        let mut attempts = 0;
+        let result: Result<u32, String> = retry(&node, "tips", || {
+            attempts += 1;
+            async { Err("connection refused".to_string()) }
+        })
+        .await;
+        assert_eq!(result, Err("connection refused".to_string()));
+        assert_eq!(attempts, 2);
+    }
+}
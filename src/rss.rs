@@ -5,7 +5,9 @@ use warp::Filter;
 use std::collections::HashMap;
 use std::convert::Infallible;
 
-use crate::types::{Caches, ChainTipStatus, Fork, NetworkJson, NodeDataJson, TipInfoJson};
+use crate::consensus;
+use crate::domain::{ChainTipStatus, Fork};
+use crate::types::{Caches, NetworkJson, NodeDataJson, TipInfoJson};
 
 const THREASHOLD_NODE_LAGGING: u64 = 3; // blocks
 
@@ -100,9 +102,9 @@ impl From<Fork> for Item {
             description: format!(
                 "There are {} blocks building on-top of block {}.",
                 fork.children.len(),
-                fork.common.header.block_hash().to_string()
+                fork.common.block_hash()
             ),
-            guid: fork.common.header.block_hash().to_string(),
+            guid: fork.common.block_hash().to_string(),
         }
     }
 }
@@ -110,7 +112,7 @@ impl From<Fork> for Item {
 impl From<(&TipInfoJson, &Vec<NodeDataJson>)> for Item {
     fn from(invalid_block: (&TipInfoJson, &Vec<NodeDataJson>)) -> Self {
         let mut nodes = invalid_block.1.clone();
-        nodes.sort_by(|a, b| a.id.cmp(&b.id));
+        nodes.sort_by_key(|a| a.id);
 
         Item {
             title: format!("Invalid block at height {}", invalid_block.0.height,),
@@ -219,30 +221,37 @@ pub async fn lagging_nodes_response(
             if cache.node_data.len() > 1 {
                 let nodes_with_active_height: Vec<(&NodeDataJson, u64)> = cache
                     .node_data
-                    .iter()
-                    .map(|(_, node)| {
+                    .values()
+                    .filter(|node| !node.shadow)
+                    .map(|node| {
                         (
                             node,
                             node.tips
                                 .iter()
-                                .filter(|tip| tip.status == "active".to_string())
-                                .last()
+                                .rfind(|tip| tip.status == "active")
                                 .unwrap_or(&TipInfoJson {
                                     height: 0,
                                     status: "active".to_string(),
                                     hash: "dummy".to_string(),
+                                    chainwork: None,
                                 })
                                 .height,
                         )
                     })
                     .collect();
-                let max_height: u64 = *nodes_with_active_height
-                    .iter()
-                    .map(|(_, height)| height)
-                    .max()
-                    .unwrap_or(&0);
+                // Nodes are lagging behind the consensus tip. Without one,
+                // they are compared to the highest active tip.
+                let tip_height: u64 =
+                    match consensus::consensus(cache.node_data.values()).consensus_tip {
+                        Some(tip) => tip.height,
+                        None => *nodes_with_active_height
+                            .iter()
+                            .map(|(_, height)| height)
+                            .max()
+                            .unwrap_or(&0),
+                    };
                 for (node, height) in nodes_with_active_height.iter() {
-                    if height + THREASHOLD_NODE_LAGGING < max_height {
+                    if height + THREASHOLD_NODE_LAGGING < tip_height {
                         lagging_nodes.push(Item::lagging_node_item(node, *height));
                     }
                 }
@@ -305,7 +314,7 @@ pub async fn invalid_blocks_response(
 
             let mut invalid_blocks: Vec<(&TipInfoJson, &Vec<NodeDataJson>)> =
                 invalid_blocks_to_node_id.iter().collect();
-            invalid_blocks.sort_by(|a, b| b.0.height.cmp(&a.0.height));
+            invalid_blocks.sort_by_key(|b| std::cmp::Reverse(b.0.height));
             let feed = Feed {
                 channel: Channel {
                     title: format!("Invalid Blocks - {}", network_name),
@@ -326,9 +335,9 @@ pub async fn invalid_blocks_response(
                 },
             };
 
-            return Ok(Response::builder()
+            Ok(Response::builder()
                 .header("content-type", "application/rss+xml")
-                .body(feed.to_string()));
+                .body(feed.to_string()))
         }
         None => Ok(Ok(response_unknown_network(network_infos))),
     }
@@ -357,8 +366,8 @@ pub async fn unreachable_nodes_response(
             let unreachable_node_items: Vec<Item> = cache
                 .node_data
                 .values()
-                .filter(|node| !node.reachable)
-                .map(|node| Item::unreachable_node_item(node))
+                .filter(|node| !node.reachable && !node.shadow)
+                .map(Item::unreachable_node_item)
                 .collect();
             let feed = Feed {
                 channel: Channel {
@@ -377,9 +386,9 @@ pub async fn unreachable_nodes_response(
                 },
             };
 
-            return Ok(Response::builder()
+            Ok(Response::builder()
                 .header("content-type", "application/rss+xml")
-                .body(feed.to_string()));
+                .body(feed.to_string()))
         }
         None => Ok(Ok(response_unknown_network(network_infos))),
     }
@@ -388,7 +397,7 @@ pub async fn unreachable_nodes_response(
 pub fn response_unknown_network(network_infos: Vec<NetworkJson>) -> Response<String> {
     let avaliable_networks = network_infos
         .iter()
-        .map(|net| format!("{} ({})", net.id.to_string(), net.name))
+        .map(|net| format!("{} ({})", net.id, net.name))
         .collect::<Vec<String>>();
 
     Response::builder()
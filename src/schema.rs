@@ -0,0 +1,1090 @@
+use serde::Serialize;
+use toml::value::{Table, Value};
+
+use crate::config::{
+    Config, NodeImplementation, DEFAULT_ANOMALY_MAX_PROBABILITY, DEFAULT_BLOCK_METADATA_DEPTH,
+    DEFAULT_BTCD_WEBSOCKET, DEFAULT_CANARY_SEVERITY, DEFAULT_CHAIN_KIND,
+    DEFAULT_CORS_ALLOWED_METHODS, DEFAULT_DIFFICULTY_ADJUSTMENT_INTERVAL,
+    DEFAULT_FULL_QUERY_INTERVAL, DEFAULT_HALVING_INTERVAL, DEFAULT_HEADER_GAP_ALERT_AFTER,
+    DEFAULT_HEADER_GAP_MAX_GAP, DEFAULT_HISTORY_CACHE_CHUNK_SIZE,
+    DEFAULT_HISTORY_CACHE_CONFIRMATIONS, DEFAULT_HISTORY_CACHE_MAX_AGE, DEFAULT_INITIAL_SUBSIDY,
+    DEFAULT_MAX_FEE_DIVERGENCE_PERCENT, DEFAULT_MAX_TX_COUNT_DIVERGENCE_PERCENT,
+    DEFAULT_MEMPOOL_URL, DEFAULT_NODE_IMPL, DEFAULT_NODE_MAX_RETRIES, DEFAULT_NODE_ON_REMOVAL,
+    DEFAULT_NODE_PURGE_AFTER_DAYS, DEFAULT_NODE_REACHABILITY_CORRELATION_WINDOW,
+    DEFAULT_NODE_RETRY_BACKOFF, DEFAULT_NODE_TRUST, DEFAULT_NOTARIZATION_CALENDARS,
+    DEFAULT_NOTARIZATION_INTERVAL, DEFAULT_NOTIFICATION_MAX_ATTEMPTS,
+    DEFAULT_NOTIFICATION_MIN_SEVERITY, DEFAULT_OWN_STALE_BLOCK_SEVERITY,
+    DEFAULT_P2P_PROBES_CHECK_INTERVAL, DEFAULT_PROFILE,
+    DEFAULT_READ_THROUGH_MAX_FETCHES_PER_MINUTE, DEFAULT_RESOURCE_TELEMETRY, DEFAULT_REST_TIMEOUT,
+    DEFAULT_RPC_HTTPS, DEFAULT_RPC_LONGPOLL, DEFAULT_RPC_TIMEOUT, DEFAULT_STORAGE_CHECK_INTERVAL,
+    DEFAULT_STORAGE_EMERGENCY_COMPACTION, DEFAULT_STORAGE_WARN_THRESHOLD_PERCENT,
+    DEFAULT_STRATUM_PASSWORD, DEFAULT_STRATUM_USER, DEFAULT_TARGET_SPACING,
+    DEFAULT_TIMESTAMP_MAX_FUTURE, DEFAULT_TIMESTAMP_MTP_MARGIN,
+    DEFAULT_TIP_OBSERVATION_RETENTION_DAYS, DEFAULT_TRACING_SERVICE_NAME,
+    DEFAULT_TREE_COMPACTION_KEEP_DEPTH, DEFAULT_USE_REST, DEFAULT_VALIDATE_POW,
+};
+use crate::customrpc::{
+    DEFAULT_BLOCK_HASH_METHOD, DEFAULT_BLOCK_HASH_PARAMS, DEFAULT_HEADER_METHOD,
+    DEFAULT_HEADER_PARAMS, DEFAULT_TIPS_METHOD, DEFAULT_VERSION_METHOD, DEFAULT_VERSION_PATH,
+};
+use crate::error::ConfigError;
+use crate::node::DEFAULT_REST_MAX_STEP_SIZE;
+
+const REDACTED: &str = "***";
+
+// An option of the configuration file. Entries of arrays of tables are
+// denoted by [] in the path, e.g. networks[].nodes[].name.
+#[derive(Serialize)]
+pub struct ConfigOption {
+    pub path: &'static str,
+    #[serde(rename = "type")]
+    pub kind: &'static str,
+    pub required: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub default: Option<Value>,
+    pub secret: bool,
+    pub description: &'static str,
+    // Node options only used by these implementations. Used by all if
+    // empty.
+    #[serde(skip)]
+    implementations: Vec<NodeImplementation>,
+}
+
+fn option(path: &'static str, kind: &'static str, description: &'static str) -> ConfigOption {
+    ConfigOption {
+        path,
+        kind,
+        required: false,
+        default: None,
+        secret: false,
+        description,
+        implementations: vec![],
+    }
+}
+
+fn int(value: u64) -> Value {
+    Value::Integer(value as i64)
+}
+
+impl ConfigOption {
+    fn required(mut self) -> Self {
+        self.required = true;
+        self
+    }
+
+    fn default(mut self, default: Value) -> Self {
+        self.default = Some(default);
+        self
+    }
+
+    fn secret(mut self) -> Self {
+        self.secret = true;
+        self
+    }
+
+    fn only_for(mut self, implementation: NodeImplementation) -> Self {
+        self.implementations.push(implementation);
+        self
+    }
+}
+
+// All options of the configuration file, see config.toml.example for
+// details.
+pub fn options() -> Vec<ConfigOption> {
+    vec![
+        option(
+            "address",
+            "string",
+            "Address the web server listens on, e.g. 127.0.0.1:2323.",
+        )
+        .required(),
+        option("database_path", "string", "Path of the SQLite database.").required(),
+        option("www_path", "string", "Directory of the static web site.").required(),
+        option(
+            "query_interval",
+            "integer",
+            "Seconds between two queries of a node.",
+        )
+        .required(),
+        option(
+            "full_query_interval",
+            "integer",
+            "Seconds between two chain tip queries of a node whose best block didn't change. 0 always queries them.",
+        )
+        .default(int(DEFAULT_FULL_QUERY_INTERVAL)),
+        option(
+            "footer_html",
+            "string",
+            "HTML shown in the footer of the site.",
+        )
+        .required(),
+        option(
+            "rss_base_url",
+            "string",
+            "Base URL of the links in the RSS feeds.",
+        )
+        .default("".into()),
+        option(
+            "admin_token",
+            "string",
+            "Bearer token of the admin API. The admin API is disabled if unset.",
+        )
+        .secret(),
+        option(
+            "profile",
+            "string",
+            "Either default or low-resource, which caps the resource usage for constrained hardware.",
+        )
+        .default(DEFAULT_PROFILE.to_string().into()),
+        option(
+            "tip_observation_retention_days",
+            "integer",
+            "Days after which tip observations and external sightings are deleted. 7 days with the low-resource profile.",
+        )
+        .default(int(DEFAULT_TIP_OBSERVATION_RETENTION_DAYS)),
+        option(
+            "storage_monitoring.max_database_size_mb",
+            "integer",
+            "Size limit of the database.",
+        ),
+        option(
+            "storage_monitoring.log_paths",
+            "array",
+            "Log files or directories to monitor.",
+        ),
+        option(
+            "storage_monitoring.max_log_size_mb",
+            "integer",
+            "Size limit of the log_paths combined.",
+        ),
+        option(
+            "storage_monitoring.warn_threshold_percent",
+            "integer",
+            "Percentage of a limit at which to warn.",
+        )
+        .default(int(DEFAULT_STORAGE_WARN_THRESHOLD_PERCENT as u64)),
+        option(
+            "storage_monitoring.check_interval",
+            "integer",
+            "Seconds between two storage checks.",
+        )
+        .default(int(DEFAULT_STORAGE_CHECK_INTERVAL)),
+        option(
+            "storage_monitoring.emergency_compaction",
+            "boolean",
+            "Delete the tip observations and external sightings older than a day and compact the database when it's over its limit.",
+        )
+        .default(DEFAULT_STORAGE_EMERGENCY_COMPACTION.into()),
+        option(
+            "p2p_probes.check_interval",
+            "integer",
+            "Seconds between two probes of the P2P addresses of a node.",
+        )
+        .default(int(DEFAULT_P2P_PROBES_CHECK_INTERVAL)),
+        option(
+            "p2p_probes.tor_proxy",
+            "string",
+            "SOCKS5 proxy to probe onion addresses with.",
+        ),
+        option(
+            "notarization.output_dir",
+            "string",
+            "Directory the notarizations of the event log are written to.",
+        ),
+        option(
+            "notarization.interval",
+            "integer",
+            "Seconds between two notarizations of the event log.",
+        )
+        .default(int(DEFAULT_NOTARIZATION_INTERVAL)),
+        option(
+            "notarization.calendars",
+            "array",
+            "OpenTimestamps calendars the event log is timestamped with.",
+        )
+        .default(DEFAULT_NOTARIZATION_CALENDARS.to_vec().into()),
+        option(
+            "cors.allowed_origins",
+            "array",
+            "Origins allowed to use the JSON API from browsers, * for any.",
+        ),
+        option(
+            "cors.allowed_methods",
+            "array",
+            "Methods allowed in cross-origin requests.",
+        )
+        .default(DEFAULT_CORS_ALLOWED_METHODS.to_vec().into()),
+        option(
+            "cors.allowed_headers",
+            "array",
+            "Request headers allowed in cross-origin requests.",
+        ),
+        option(
+            "cors.max_age",
+            "integer",
+            "Seconds browsers may cache a preflight response.",
+        ),
+        option(
+            "tracing.otlp_endpoint",
+            "string",
+            "OTLP/HTTP endpoint of an OpenTelemetry collector the traces of the API requests are exported to.",
+        ),
+        option(
+            "tracing.service_name",
+            "string",
+            "Service name of the exported traces.",
+        )
+        .default(DEFAULT_TRACING_SERVICE_NAME.into()),
+        option(
+            "event_correlation.node-unreachable",
+            "integer",
+            "Seconds in which node-unreachable events are correlated.",
+        )
+        .default(int(DEFAULT_NODE_REACHABILITY_CORRELATION_WINDOW)),
+        option(
+            "event_correlation.node-reachable",
+            "integer",
+            "Seconds in which node-reachable events are correlated.",
+        )
+        .default(int(DEFAULT_NODE_REACHABILITY_CORRELATION_WINDOW)),
+        option(
+            "notifications.max_attempts",
+            "integer",
+            "Delivery attempts before a notification is dead-lettered.",
+        )
+        .default(int(DEFAULT_NOTIFICATION_MAX_ATTEMPTS as u64)),
+        option(
+            "notifications.sinks[].name",
+            "string",
+            "Unique name of the sink.",
+        )
+        .required(),
+        option(
+            "notifications.sinks[].kind",
+            "string",
+            "Either webhook or matrix.",
+        )
+        .required(),
+        option(
+            "notifications.sinks[].min_severity",
+            "string",
+            "Minimum severity of the delivered events: info, warning, or critical.",
+        )
+        .default(DEFAULT_NOTIFICATION_MIN_SEVERITY.to_string().into()),
+        option(
+            "notifications.sinks[].url",
+            "string",
+            "URL of a webhook sink.",
+        )
+        .secret(),
+        option(
+            "notifications.sinks[].homeserver",
+            "string",
+            "Homeserver of a matrix sink.",
+        ),
+        option(
+            "notifications.sinks[].room_id",
+            "string",
+            "Room of a matrix sink.",
+        ),
+        option(
+            "notifications.sinks[].access_token",
+            "string",
+            "Access token of a matrix sink.",
+        )
+        .secret(),
+        option(
+            "computed_fields[].name",
+            "string",
+            "Name of the computed field.",
+        )
+        .required(),
+        option(
+            "computed_fields[].target",
+            "string",
+            "Either block or node.",
+        )
+        .required(),
+        option(
+            "computed_fields[].expression",
+            "string",
+            "Expression computing the field.",
+        )
+        .required(),
+        option(
+            "external_observers[].name",
+            "string",
+            "Unique name of the observer.",
+        )
+        .required(),
+        option(
+            "external_observers[].token",
+            "string",
+            "Bearer token the observer authenticates with.",
+        )
+        .required()
+        .secret(),
+        option("networks[].id", "integer", "Unique id of the network.").required(),
+        option("networks[].name", "string", "Name of the network.").required(),
+        option(
+            "networks[].description",
+            "string",
+            "Description of the network.",
+        )
+        .required(),
+        option(
+            "networks[].min_fork_height",
+            "integer",
+            "Forks below this height are ignored.",
+        )
+        .required(),
+        option(
+            "networks[].backfill_blocks",
+            "integer",
+            "Without headers in the database, headers are fetched from this many blocks below the active tip on instead of from the min_fork_height.",
+        ),
+        option(
+            "networks[].max_interesting_heights",
+            "integer",
+            "Number of recent heights with forks served.",
+        )
+        .required(),
+        option(
+            "networks[].share_headers_with",
+            "integer",
+            "Id of a network on the same chain whose headers are used.",
+        ),
+        option(
+            "networks[].chain_kind",
+            "string",
+            "bitcoin, or elements for Elements chains like Liquid.",
+        )
+        .default(DEFAULT_CHAIN_KIND.into()),
+        option(
+            "networks[].pool_identification.enable",
+            "boolean",
+            "Identify the miners of blocks.",
+        )
+        .default(false.into()),
+        option(
+            "networks[].pool_identification.network",
+            "string",
+            "Pool data to use: Mainnet, Testnet, or Signet.",
+        ),
+        option(
+            "networks[].pool_identification.validate_merkle_root",
+            "boolean",
+            "Check the fetched blocks against their headers.",
+        )
+        .default(false.into()),
+        option(
+            "networks[].pool_identification.node",
+            "integer",
+            "Id of the node asked for the coinbases first.",
+        ),
+        option(
+            "networks[].chain_params.network",
+            "string",
+            "Mainnet, Testnet, Signet, or Regtest.",
+        ),
+        option(
+            "networks[].chain_params.genesis_hash",
+            "string",
+            "Genesis block hash. Defaults to the genesis block of the chain_params network.",
+        ),
+        option(
+            "networks[].chain_params.target_spacing",
+            "integer",
+            "Target seconds between blocks.",
+        )
+        .default(int(DEFAULT_TARGET_SPACING)),
+        option(
+            "networks[].chain_params.halving_interval",
+            "integer",
+            "Blocks between halvings. 150 on Regtest.",
+        )
+        .default(int(DEFAULT_HALVING_INTERVAL)),
+        option(
+            "networks[].chain_params.initial_subsidy",
+            "integer",
+            "Block subsidy in sat before the first halving.",
+        )
+        .default(int(DEFAULT_INITIAL_SUBSIDY)),
+        option(
+            "networks[].chain_params.difficulty_adjustment_interval",
+            "integer",
+            "Blocks between difficulty adjustments.",
+        )
+        .default(int(DEFAULT_DIFFICULTY_ADJUSTMENT_INTERVAL)),
+        option(
+            "networks[].chain_params.validate_pow",
+            "boolean",
+            "Check the header hashes against their target. Disable for non-SHA256d chains.",
+        )
+        .default(DEFAULT_VALIDATE_POW.into()),
+        option(
+            "networks[].chain_params.pow_limit",
+            "string",
+            "Hex encoded easiest target allowed, big-endian.",
+        ),
+        option(
+            "networks[].own_mining.coinbase_tags",
+            "array",
+            "Coinbase tags of blocks mined by the operator.",
+        ),
+        option(
+            "networks[].own_mining.payout_scripts",
+            "array",
+            "Hex encoded payout scripts of blocks mined by the operator.",
+        ),
+        option(
+            "networks[].own_mining.stale_block_severity",
+            "string",
+            "Severity of the event when an own block becomes stale.",
+        )
+        .default(DEFAULT_OWN_STALE_BLOCK_SEVERITY.to_string().into()),
+        option(
+            "networks[].tree_quota.max_branches_per_hour",
+            "integer",
+            "New branches a node may introduce per hour.",
+        ),
+        option(
+            "networks[].tree_quota.max_headers_per_hour",
+            "integer",
+            "New headers a node may introduce per hour.",
+        ),
+        option(
+            "networks[].template_comparison.max_fee_divergence_percent",
+            "integer",
+            "Allowed divergence of the template fees.",
+        )
+        .default(int(DEFAULT_MAX_FEE_DIVERGENCE_PERCENT as u64)),
+        option(
+            "networks[].template_comparison.max_tx_count_divergence_percent",
+            "integer",
+            "Allowed divergence of the template transaction counts.",
+        )
+        .default(int(DEFAULT_MAX_TX_COUNT_DIVERGENCE_PERCENT as u64)),
+        option(
+            "networks[].block_interval_anomalies.max_probability",
+            "float",
+            "Block intervals less likely than this are reported.",
+        )
+        .default(DEFAULT_ANOMALY_MAX_PROBABILITY.into()),
+        option(
+            "networks[].timestamp_anomalies.max_future",
+            "integer",
+            "Headers with a time more than this many seconds ahead of the current time are flagged.",
+        )
+        .default(int(DEFAULT_TIMESTAMP_MAX_FUTURE)),
+        option(
+            "networks[].timestamp_anomalies.mtp_margin",
+            "integer",
+            "Headers with a time at most this many seconds after the median time past are flagged.",
+        )
+        .default(int(DEFAULT_TIMESTAMP_MTP_MARGIN)),
+        option(
+            "networks[].canaries[].name",
+            "string",
+            "Name of the canary.",
+        )
+        .required(),
+        option(
+            "networks[].canaries[].condition",
+            "string",
+            "Boolean expression evaluated against each new header and its parent.",
+        )
+        .required(),
+        option(
+            "networks[].canaries[].severity",
+            "string",
+            "Severity of the event when the condition holds.",
+        )
+        .default(DEFAULT_CANARY_SEVERITY.to_string().into()),
+        option(
+            "networks[].header_gap.max_gap",
+            "integer",
+            "Headers a node may know of beyond its validated tip.",
+        )
+        .default(int(DEFAULT_HEADER_GAP_MAX_GAP)),
+        option(
+            "networks[].header_gap.alert_after",
+            "integer",
+            "Seconds the gap has to exceed max_gap before alerting.",
+        )
+        .default(int(DEFAULT_HEADER_GAP_ALERT_AFTER)),
+        option(
+            "networks[].history_cache.confirmations",
+            "integer",
+            "Confirmations after which a chunk of the header history is immutable.",
+        )
+        .default(int(DEFAULT_HISTORY_CACHE_CONFIRMATIONS)),
+        option(
+            "networks[].history_cache.chunk_size",
+            "integer",
+            "Heights per chunk of the header history.",
+        )
+        .default(int(DEFAULT_HISTORY_CACHE_CHUNK_SIZE)),
+        option(
+            "networks[].history_cache.max_age",
+            "integer",
+            "Seconds clients and CDNs may cache an immutable chunk.",
+        )
+        .default(int(DEFAULT_HISTORY_CACHE_MAX_AGE)),
+        option(
+            "networks[].read_through.max_fetches_per_minute",
+            "integer",
+            "Unknown headers fetched from the nodes per minute for API requests.",
+        )
+        .default(int(DEFAULT_READ_THROUGH_MAX_FETCHES_PER_MINUTE as u64)),
+        option(
+            "networks[].fork_pruning.keep_depth",
+            "integer",
+            "Stale branches forking off more than this many blocks below the active tip are pruned.",
+        ),
+        option(
+            "networks[].fork_pruning.max_forks",
+            "integer",
+            "Stale branches of forks older than the max_forks most recent ones are pruned.",
+        ),
+        option(
+            "networks[].tree_compaction.keep_depth",
+            "integer",
+            "Linear runs of headers more than this many blocks below the lowest active tip of the nodes are collapsed in memory.",
+        )
+        .default(int(DEFAULT_TREE_COMPACTION_KEEP_DEPTH)),
+        option(
+            "networks[].block_metadata.depth",
+            "integer",
+            "The size, weight, transactions and fees of the blocks of each fork branch up to this many blocks above the last common block are recorded.",
+        )
+        .default(int(DEFAULT_BLOCK_METADATA_DEPTH)),
+        option(
+            "networks[].stratum_pools[].name",
+            "string",
+            "Name of the mining pool.",
+        )
+        .required(),
+        option(
+            "networks[].stratum_pools[].endpoint",
+            "string",
+            "Stratum V1 endpoint of the pool, e.g. stratum+tcp://pool.example.com:3333.",
+        )
+        .required(),
+        option(
+            "networks[].stratum_pools[].user",
+            "string",
+            "Worker name the jobs are subscribed with.",
+        )
+        .default(DEFAULT_STRATUM_USER.into()),
+        option(
+            "networks[].stratum_pools[].password",
+            "string",
+            "Worker password the jobs are subscribed with.",
+        )
+        .default(DEFAULT_STRATUM_PASSWORD.into())
+        .secret(),
+        option(
+            "networks[].nodes[].id",
+            "integer",
+            "Unique id of the node in the network.",
+        )
+        .required(),
+        option("networks[].nodes[].name", "string", "Name of the node.").required(),
+        option(
+            "networks[].nodes[].description",
+            "string",
+            "Description of the node.",
+        )
+        .required(),
+        option(
+            "networks[].nodes[].implementation",
+            "string",
+            "bitcoincore, btcd, electrum, esplora, lightclient, p2p, customrpc, or elements.",
+        )
+        .default("bitcoincore".into()),
+        option(
+            "networks[].nodes[].rpc_host",
+            "string",
+            "Host of the RPC interface, a unix:// socket path, or, depending on the implementation, the server.",
+        ),
+        option(
+            "networks[].nodes[].rpc_port",
+            "integer",
+            "Port of the RPC interface or, depending on the implementation, the server.",
+        ),
+        option(
+            "networks[].nodes[].rpc_cookie_file",
+            "string",
+            "Cookie file to authenticate with.",
+        ),
+        option("networks[].nodes[].rpc_user", "string", "RPC user."),
+        option("networks[].nodes[].rpc_password", "string", "RPC password.").secret(),
+        option(
+            "networks[].nodes[].rpc_https",
+            "boolean",
+            "Query the RPC and REST interfaces over HTTPS.",
+        )
+        .default(DEFAULT_RPC_HTTPS.into())
+        .only_for(NodeImplementation::BitcoinCore)
+        .only_for(NodeImplementation::Btcd)
+        .only_for(NodeImplementation::CustomRpc)
+        .only_for(NodeImplementation::Elements),
+        option(
+            "networks[].nodes[].rpc_https_ca_file",
+            "string",
+            "CA or server certificates to verify the HTTPS server certificate with.",
+        )
+        .only_for(NodeImplementation::BitcoinCore)
+        .only_for(NodeImplementation::Btcd)
+        .only_for(NodeImplementation::CustomRpc)
+        .only_for(NodeImplementation::Elements),
+        option(
+            "networks[].nodes[].rpc_https_insecure",
+            "boolean",
+            "Skip the verification of the HTTPS server certificate.",
+        )
+        .default(false.into())
+        .only_for(NodeImplementation::BitcoinCore)
+        .only_for(NodeImplementation::Btcd)
+        .only_for(NodeImplementation::CustomRpc)
+        .only_for(NodeImplementation::Elements),
+        option(
+            "networks[].nodes[].rpc_https_client_cert",
+            "string",
+            "Client certificate chain to authenticate with at the HTTPS server.",
+        )
+        .only_for(NodeImplementation::BitcoinCore)
+        .only_for(NodeImplementation::Btcd)
+        .only_for(NodeImplementation::CustomRpc)
+        .only_for(NodeImplementation::Elements),
+        option(
+            "networks[].nodes[].rpc_https_client_key",
+            "string",
+            "Private key of the rpc_https_client_cert.",
+        )
+        .only_for(NodeImplementation::BitcoinCore)
+        .only_for(NodeImplementation::Btcd)
+        .only_for(NodeImplementation::CustomRpc)
+        .only_for(NodeImplementation::Elements),
+        option(
+            "networks[].nodes[].socks5_proxy",
+            "string",
+            "SOCKS5 proxy to reach the node through.",
+        )
+        .only_for(NodeImplementation::BitcoinCore)
+        .only_for(NodeImplementation::Elements),
+        option(
+            "networks[].nodes[].zmq_hashblock",
+            "string",
+            "ZMQ endpoint publishing new block hashes.",
+        )
+        .only_for(NodeImplementation::BitcoinCore)
+        .only_for(NodeImplementation::Elements),
+        option(
+            "networks[].nodes[].rpc_longpoll",
+            "boolean",
+            "Wait for new blocks with waitfornewblock.",
+        )
+        .default(DEFAULT_RPC_LONGPOLL.into())
+        .only_for(NodeImplementation::BitcoinCore)
+        .only_for(NodeImplementation::Elements),
+        option(
+            "networks[].nodes[].resource_telemetry",
+            "boolean",
+            "Query the uptime and memory usage of the node.",
+        )
+        .default(DEFAULT_RESOURCE_TELEMETRY.into())
+        .only_for(NodeImplementation::BitcoinCore)
+        .only_for(NodeImplementation::Elements),
+        option(
+            "networks[].nodes[].rpc_timeout",
+            "integer",
+            "Timeout of RPC requests in seconds.",
+        )
+        .default(int(DEFAULT_RPC_TIMEOUT))
+        .only_for(NodeImplementation::BitcoinCore)
+        .only_for(NodeImplementation::Elements),
+        option(
+            "networks[].nodes[].use_rest",
+            "boolean",
+            "Fetch headers via the REST interface when it's available.",
+        )
+        .default(DEFAULT_USE_REST.into())
+        .only_for(NodeImplementation::BitcoinCore),
+        option(
+            "networks[].nodes[].rest_timeout",
+            "integer",
+            "Timeout of REST requests in seconds.",
+        )
+        .default(int(DEFAULT_REST_TIMEOUT))
+        .only_for(NodeImplementation::BitcoinCore),
+        option(
+            "networks[].nodes[].rest_max_step_size",
+            "integer",
+            "Maximum headers requested per REST call.",
+        )
+        .default(int(DEFAULT_REST_MAX_STEP_SIZE))
+        .only_for(NodeImplementation::BitcoinCore),
+        option(
+            "networks[].nodes[].btcd_websocket",
+            "boolean",
+            "Subscribe to block notifications via websocket.",
+        )
+        .default(DEFAULT_BTCD_WEBSOCKET.into())
+        .only_for(NodeImplementation::Btcd),
+        option(
+            "networks[].nodes[].custom_rpc.tips_method",
+            "string",
+            "Method returning the chain tips of a custom-rpc node.",
+        )
+        .default(DEFAULT_TIPS_METHOD.into()),
+        option(
+            "networks[].nodes[].custom_rpc.tips_params",
+            "string",
+            "JSON array of the params of the tips_method.",
+        )
+        .default("[]".into()),
+        option(
+            "networks[].nodes[].custom_rpc.tips_path",
+            "string",
+            "Path to the array of tips in the result. Empty for the result itself.",
+        )
+        .default("".into()),
+        option(
+            "networks[].nodes[].custom_rpc.tip_height",
+            "string",
+            "Path to the height in a tip.",
+        )
+        .default("height".into()),
+        option(
+            "networks[].nodes[].custom_rpc.tip_hash",
+            "string",
+            "Path to the block hash in a tip.",
+        )
+        .default("hash".into()),
+        option(
+            "networks[].nodes[].custom_rpc.tip_branchlen",
+            "string",
+            "Path to the branch length in a tip. Tips without it have a branch length of 0.",
+        )
+        .default("branchlen".into()),
+        option(
+            "networks[].nodes[].custom_rpc.tip_status",
+            "string",
+            "Path to the status in a tip.",
+        )
+        .default("status".into()),
+        option(
+            "networks[].nodes[].custom_rpc.statuses",
+            "table",
+            "Maps the tip statuses of the node to the Bitcoin Core ones.",
+        ),
+        option(
+            "networks[].nodes[].custom_rpc.header_method",
+            "string",
+            "Method returning the hex encoded header of the block $hash.",
+        )
+        .default(DEFAULT_HEADER_METHOD.into()),
+        option(
+            "networks[].nodes[].custom_rpc.header_params",
+            "string",
+            "JSON array of the params of the header_method.",
+        )
+        .default(DEFAULT_HEADER_PARAMS.into()),
+        option(
+            "networks[].nodes[].custom_rpc.header_path",
+            "string",
+            "Path to the header in the result.",
+        )
+        .default("".into()),
+        option(
+            "networks[].nodes[].custom_rpc.block_hash_method",
+            "string",
+            "Method returning the hash of the active block at $height.",
+        )
+        .default(DEFAULT_BLOCK_HASH_METHOD.into()),
+        option(
+            "networks[].nodes[].custom_rpc.block_hash_params",
+            "string",
+            "JSON array of the params of the block_hash_method.",
+        )
+        .default(DEFAULT_BLOCK_HASH_PARAMS.into()),
+        option(
+            "networks[].nodes[].custom_rpc.block_hash_path",
+            "string",
+            "Path to the block hash in the result.",
+        )
+        .default("".into()),
+        option(
+            "networks[].nodes[].custom_rpc.version_method",
+            "string",
+            "Method returning the user agent of the node.",
+        )
+        .default(DEFAULT_VERSION_METHOD.into()),
+        option(
+            "networks[].nodes[].custom_rpc.version_params",
+            "string",
+            "JSON array of the params of the version_method.",
+        )
+        .default("[]".into()),
+        option(
+            "networks[].nodes[].custom_rpc.version_path",
+            "string",
+            "Path to the user agent in the result.",
+        )
+        .default(DEFAULT_VERSION_PATH.into()),
+        option(
+            "networks[].nodes[].esplora_url",
+            "string",
+            "Base URL of the Esplora API.",
+        )
+        .only_for(NodeImplementation::Esplora),
+        option(
+            "networks[].nodes[].mempool_url",
+            "string",
+            "Base URL of the mempool.space API.",
+        )
+        .default(DEFAULT_MEMPOOL_URL.into())
+        .only_for(NodeImplementation::Mempool),
+        option(
+            "networks[].nodes[].remote_url",
+            "string",
+            "Base URL of the mirrored fork-observer instance.",
+        )
+        .only_for(NodeImplementation::ForkObserver),
+        option(
+            "networks[].nodes[].remote_network_id",
+            "integer",
+            "Id of the network on the mirrored fork-observer instance.",
+        )
+        .only_for(NodeImplementation::ForkObserver),
+        option(
+            "networks[].nodes[].remote_node_id",
+            "integer",
+            "Id of the mirrored node on the remote fork-observer instance.",
+        )
+        .only_for(NodeImplementation::ForkObserver),
+        option(
+            "networks[].nodes[].replay_file",
+            "string",
+            "JSON file with the chain tips and headers to play back.",
+        )
+        .only_for(NodeImplementation::Replay),
+        option(
+            "networks[].nodes[].p2p_network",
+            "string",
+            "Network of a lightclient or p2p node: Mainnet, Testnet, Signet, or Regtest.",
+        ),
+        option(
+            "networks[].nodes[].trust",
+            "float",
+            "Weight of the node's tip in the consensus, 0.0 to 1.0.",
+        )
+        .default(DEFAULT_NODE_TRUST.into()),
+        option(
+            "networks[].nodes[].shadow",
+            "boolean",
+            "Exclude the node from the consensus, events, and metrics.",
+        )
+        .default(false.into()),
+        option(
+            "networks[].nodes[].on_removal",
+            "string",
+            "Either retire or purge the data of the node once it's removed.",
+        )
+        .default(DEFAULT_NODE_ON_REMOVAL.into()),
+        option(
+            "networks[].nodes[].purge_after_days",
+            "integer",
+            "Days after which the data of a removed node is purged.",
+        )
+        .default(int(DEFAULT_NODE_PURGE_AFTER_DAYS)),
+        option(
+            "networks[].nodes[].max_retries",
+            "integer",
+            "Retries of a failed fetch before the node is unreachable.",
+        )
+        .default(int(DEFAULT_NODE_MAX_RETRIES as u64)),
+        option(
+            "networks[].nodes[].retry_backoff",
+            "integer",
+            "Seconds before the first retry, doubling with each retry.",
+        )
+        .default(int(DEFAULT_NODE_RETRY_BACKOFF)),
+    ]
+}
+
+// Applies the default or redacts the secret of the option in the table and
+// the tables below it. Only existing tables are filled, as some sections
+// enable a feature by being present.
+fn resolve(table: &mut Table, path: &[&str], option: &ConfigOption) {
+    let (key, rest) = match path.split_first() {
+        Some(split) => split,
+        None => return,
+    };
+    if rest.is_empty() {
+        if !option.implementations.is_empty() {
+            let node_implementation = match table.get("implementation") {
+                Some(Value::String(s)) => s.parse::<NodeImplementation>().ok(),
+                _ => Some(DEFAULT_NODE_IMPL),
+            };
+            if !node_implementation.is_some_and(|i| option.implementations.contains(&i)) {
+                return;
+            }
+        }
+        match table.get_mut(*key) {
+            Some(value) if option.secret => *value = REDACTED.into(),
+            Some(_) => (),
+            None => {
+                if let Some(ref default) = option.default {
+                    table.insert(key.to_string(), default.clone());
+                }
+            }
+        }
+        return;
+    }
+    match key.strip_suffix("[]") {
+        Some(key) => {
+            if let Some(Value::Array(entries)) = table.get_mut(key) {
+                for entry in entries.iter_mut() {
+                    if let Value::Table(entry) = entry {
+                        resolve(entry, rest, option);
+                    }
+                }
+            }
+        }
+        None => {
+            if let Some(Value::Table(table)) = table.get_mut(*key) {
+                resolve(table, rest, option);
+            }
+        }
+    }
+}
+
+// The configuration with the defaults of all unset options filled in and the
+// secrets redacted. The chain_params are those resolved for the networks.
+pub fn effective_config(config_str: &str, config: &Config) -> Result<String, ConfigError> {
+    let mut root: Table = toml::from_str(config_str)?;
+    for option in options().iter() {
+        let path: Vec<&str> = option.path.split('.').collect();
+        resolve(&mut root, &path, option);
+    }
+    if let Some(Value::Array(networks)) = root.get_mut("networks") {
+        for (entry, network) in networks.iter_mut().zip(config.networks.iter()) {
+            if let Value::Table(entry) = entry {
+                let params = &network.chain_params;
+                let mut chain_params = match entry.remove("chain_params") {
+                    Some(Value::Table(chain_params)) => chain_params,
+                    _ => Table::new(),
+                };
+                if let Some(genesis_hash) = params.genesis_hash {
+                    chain_params
+                        .insert("genesis_hash".to_string(), genesis_hash.to_string().into());
+                }
+                for (key, value) in [
+                    ("target_spacing", params.target_spacing),
+                    ("halving_interval", params.halving_interval),
+                    ("initial_subsidy", params.initial_subsidy),
+                    (
+                        "difficulty_adjustment_interval",
+                        params.difficulty_adjustment_interval,
+                    ),
+                ]
+                .iter()
+                {
+                    chain_params.insert(key.to_string(), int(*value));
+                }
+                entry.insert("chain_params".to_string(), Value::Table(chain_params));
+            }
+        }
+    }
+    Ok(toml::to_string(&Value::Table(root))?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::parse_config;
+
+    #[test]
+    fn test_effective_config() {
+        let config_str = r#"
+            database_path = ""
+            www_path = "./www"
+            query_interval = 15
+            address = "127.0.0.1:2323"
+            footer_html = ""
+            admin_token = "secret-token"
+
+            [[networks]]
+            id = 1
+            name = "regtest"
+            description = ""
+            min_fork_height = 0
+            max_interesting_heights = 10
+
+                [networks.chain_params]
+                network = "Regtest"
+
+                [[networks.nodes]]
+                id = 0
+                name = "Node A"
+                description = ""
+                rpc_host = "127.0.0.1"
+                rpc_port = 18443
+                rpc_user = "user"
+                rpc_password = "password"
+                rpc_timeout = 30
+
+                [[networks.nodes]]
+                id = 1
+                name = "Explorer"
+                description = ""
+                implementation = "esplora"
+                esplora_url = "https://example.com/api"
+        "#;
+        let config = parse_config(config_str).unwrap();
+        let effective: Table =
+            toml::from_str(&effective_config(config_str, &config).unwrap()).unwrap();
+
+        assert_eq!(effective["admin_token"].as_str(), Some(REDACTED));
+        assert_eq!(effective["rss_base_url"].as_str(), Some(""));
+        assert!(
+            !effective.contains_key("storage_monitoring"),
+            "optional sections aren't added"
+        );
+        let network = &effective["networks"][0];
+        assert_eq!(
+            network["chain_params"]["halving_interval"].as_integer(),
+            Some(150)
+        );
+        let core = &network["nodes"][0];
+        assert_eq!(core["rpc_password"].as_str(), Some(REDACTED));
+        assert_eq!(core["rpc_timeout"].as_integer(), Some(30));
+        assert_eq!(
+            core["rest_timeout"].as_integer(),
+            Some(DEFAULT_REST_TIMEOUT as i64)
+        );
+        assert_eq!(core["implementation"].as_str(), Some("bitcoincore"));
+        let esplora = &network["nodes"][1];
+        assert_eq!(esplora["trust"].as_float(), Some(DEFAULT_NODE_TRUST));
+        assert!(
+            !esplora.as_table().unwrap().contains_key("rpc_timeout"),
+            "options of other implementations aren't added"
+        );
+
+        // the effective configuration is a valid configuration
+        let mut unredacted = effective.clone();
+        unredacted.remove("admin_token");
+        let nodes = unredacted["networks"][0]["nodes"]
+            .as_array()
+            .unwrap()
+            .clone();
+        let mut core = nodes[0].as_table().unwrap().clone();
+        core.insert("rpc_password".to_string(), "password".into());
+        unredacted["networks"][0]["nodes"] =
+            Value::Array(vec![Value::Table(core), nodes[1].clone()]);
+        assert!(parse_config(&toml::to_string(&Value::Table(unredacted)).unwrap()).is_ok());
+    }
+}
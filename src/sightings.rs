@@ -0,0 +1,287 @@
+use std::convert::Infallible;
+use std::str::FromStr;
+
+use bitcoincore_rpc::bitcoin::BlockHash;
+use log::{error, info, warn};
+use warp::http::StatusCode;
+use warp::{Filter, Reply};
+
+use crate::admin;
+use crate::config::ExternalObserver;
+use crate::db;
+use crate::domain::{ChainTip, HeaderInfo, NodeInfo, Observer, Sighting};
+use crate::rss::response_unknown_network;
+use crate::types::{
+    now_timestamp, Caches, Db, HeaderInfoJson, NetworkJson, SeenByJson, SeenByJsonResponse,
+    SightingsJsonRequest, SightingsJsonResponse, Versioned,
+};
+
+pub const MAX_SIGHTINGS_PER_REQUEST: usize = 1000;
+// The oldest sightings of an observer beyond this many are deleted.
+const MAX_SIGHTINGS_PER_OBSERVER: usize = 100_000;
+// Sightings further in the future than this are rejected.
+const MAX_CLOCK_SKEW: u64 = 10 * 60; // seconds
+
+pub fn with_external_observers(
+    observers: Vec<ExternalObserver>,
+) -> impl Filter<Extract = (Vec<ExternalObserver>,), Error = Infallible> + Clone {
+    warp::any().map(move || observers.clone())
+}
+
+// Sets which node first reported the blocks of the tree data and when.
+pub async fn add_first_reports(db: Db, network_id: u32, header_infos: &mut [HeaderInfoJson]) {
+    let hashes: Vec<String> = header_infos.iter().map(|h| h.hash.clone()).collect();
+    let first_reports = match db::load_first_reports(db, network_id, &hashes).await {
+        Ok(first_reports) => first_reports,
+        Err(e) => {
+            warn!(
+                "Could not load the first reports of network {}: {}",
+                network_id, e
+            );
+            return;
+        }
+    };
+    for header_info in header_infos.iter_mut() {
+        if let Some(first_report) = first_reports.get(&header_info.hash) {
+            header_info.first_reported_by = Some(first_report.node);
+            header_info.first_reported = Some(first_report.timestamp);
+        }
+    }
+}
+
+// The blocks to attribute to the node as first reporter: the headers its
+// query brought into the tree and its tips. Blocks already attributed keep
+// their first report. Shadow nodes aren't attributed any blocks, so a block a
+// shadow node brought into the tree goes to the first other node reporting it
+// as a tip.
+pub fn first_reported_hashes(
+    node: &NodeInfo,
+    new_headers: &[HeaderInfo],
+    tips: &[ChainTip],
+) -> Vec<String> {
+    if node.shadow {
+        return vec![];
+    }
+    new_headers
+        .iter()
+        .map(|h| h.block_hash().to_string())
+        .chain(tips.iter().map(|tip| tip.hash.clone()))
+        .collect()
+}
+
+fn authenticate<'a>(
+    observers: &'a [ExternalObserver],
+    authorization: &Option<String>,
+) -> Option<&'a ExternalObserver> {
+    observers
+        .iter()
+        .find(|o| admin::bearer_token_matches(authorization.as_deref(), &o.token))
+}
+
+// Validates the submitted sightings. Either all sightings are accepted or
+// none.
+fn parse_sightings(request: &SightingsJsonRequest, now: u64) -> Result<Vec<Sighting>, String> {
+    if request.sightings.len() > MAX_SIGHTINGS_PER_REQUEST {
+        return Err(format!(
+            "at most {} sightings can be submitted per request",
+            MAX_SIGHTINGS_PER_REQUEST
+        ));
+    }
+    request
+        .sightings
+        .iter()
+        .map(|sighting| {
+            let hash = BlockHash::from_str(&sighting.hash)
+                .map_err(|e| format!("invalid block hash '{}': {}", sighting.hash, e))?;
+            if sighting.timestamp > now + MAX_CLOCK_SKEW {
+                return Err(format!(
+                    "the sighting of block {} is in the future",
+                    sighting.hash
+                ));
+            }
+            Ok(Sighting {
+                hash,
+                height: sighting.height,
+                timestamp: sighting.timestamp,
+            })
+        })
+        .collect()
+}
+
+pub async fn submit_response(
+    network_id: u32,
+    authorization: Option<String>,
+    request: SightingsJsonRequest,
+    observers: Vec<ExternalObserver>,
+    db: Db,
+    network_infos: Vec<NetworkJson>,
+) -> Result<Box<dyn Reply>, Infallible> {
+    let observer = match authenticate(&observers, &authorization) {
+        Some(observer) => observer,
+        None => {
+            return Ok(Box::new(warp::reply::with_status(
+                "Unauthorized".to_string(),
+                StatusCode::UNAUTHORIZED,
+            )))
+        }
+    };
+    if !network_infos.iter().any(|n| n.id == network_id) {
+        return Ok(Box::new(response_unknown_network(network_infos)));
+    }
+    let now = now_timestamp();
+    let sightings = match parse_sightings(&request, now) {
+        Ok(sightings) => sightings,
+        Err(e) => {
+            return Ok(Box::new(warp::reply::with_status(
+                e,
+                StatusCode::BAD_REQUEST,
+            )))
+        }
+    };
+    match db::write_external_sightings(
+        db,
+        network_id,
+        &observer.name,
+        now,
+        &sightings,
+        MAX_SIGHTINGS_PER_OBSERVER,
+    )
+    .await
+    {
+        Ok(_) => {
+            info!(
+                "Received {} sightings from external observer '{}' for network {}",
+                sightings.len(),
+                observer.name,
+                network_id
+            );
+            Ok(Box::new(warp::reply::json(&Versioned::new(
+                SightingsJsonResponse {
+                    accepted: sightings.len(),
+                },
+            ))))
+        }
+        Err(e) => {
+            error!(
+                "Could not write sightings of external observer '{}': {}",
+                observer.name, e
+            );
+            Ok(Box::new(StatusCode::INTERNAL_SERVER_ERROR))
+        }
+    }
+}
+
+pub async fn seen_by_response(
+    network_id: u32,
+    hash: String,
+    caches: Caches,
+    db: Db,
+    network_infos: Vec<NetworkJson>,
+) -> Result<Box<dyn Reply>, Infallible> {
+    // Node names are looked up in the cache. Nodes removed from the
+    // configuration have no name anymore.
+    let node_names: Vec<(u32, String)> = {
+        let caches_locked = caches.lock().await;
+        match caches_locked.get(&network_id) {
+            Some(cache) => cache
+                .node_data
+                .values()
+                .map(|node| (node.id, node.name.clone()))
+                .collect(),
+            None => return Ok(Box::new(response_unknown_network(network_infos))),
+        }
+    };
+    match db::load_seen_by(db, network_id, &hash).await {
+        Ok(seen_by) => Ok(Box::new(warp::reply::json(&Versioned::new(
+            SeenByJsonResponse {
+                hash,
+                seen_by: seen_by
+                    .into_iter()
+                    .map(|s| match s.observer {
+                        Observer::Node(id) => SeenByJson {
+                            kind: "node".to_string(),
+                            node_id: Some(id),
+                            name: node_names
+                                .iter()
+                                .find(|(node_id, _)| *node_id == id)
+                                .map(|(_, name)| name.clone())
+                                .unwrap_or_default(),
+                            first_seen: s.timestamp,
+                        },
+                        Observer::External(name) => SeenByJson {
+                            kind: "external".to_string(),
+                            node_id: None,
+                            name,
+                            first_seen: s.timestamp,
+                        },
+                    })
+                    .collect(),
+            },
+        )))),
+        Err(e) => {
+            error!("Could not load the sightings of block {}: {}", hash, e);
+            Ok(Box::new(StatusCode::INTERNAL_SERVER_ERROR))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::ChainTipStatus;
+    use crate::testutil;
+    use crate::types::SightingJson;
+
+    #[test]
+    fn test_parse_sightings() {
+        let hash = "000000000019d6689c085ae165831e934ff763ae46a2a6c172b3f1b60a8ce26f";
+        let request = |hash: &str, timestamp: u64| SightingsJsonRequest {
+            sightings: vec![SightingJson {
+                hash: hash.to_string(),
+                height: Some(0),
+                timestamp,
+            }],
+        };
+
+        let sightings = parse_sightings(&request(hash, 1000), 1000).unwrap();
+        assert_eq!(sightings[0].hash.to_string(), hash);
+        assert!(parse_sightings(&request(hash, 1000 + MAX_CLOCK_SKEW), 1000).is_ok());
+        assert!(parse_sightings(&request(hash, 1001 + MAX_CLOCK_SKEW), 1000).is_err());
+        assert!(parse_sightings(&request("not a hash", 1000), 1000).is_err());
+    }
+
+    #[test]
+    fn test_first_reported_hashes() {
+        let parent = testutil::child(None, 1000, 0);
+        let block = testutil::child(Some(&parent), 1600, 0);
+        let tips = vec![ChainTip {
+            height: 2,
+            hash: "000000000019d6689c085ae165831e934ff763ae46a2a6c172b3f1b60a8ce26f".to_string(),
+            branchlen: 0,
+            status: ChainTipStatus::Active,
+        }];
+        let mut node = testutil::node_info(0, 1.0);
+
+        assert_eq!(
+            first_reported_hashes(&node, &[parent.clone(), block.clone()], &tips),
+            vec![
+                parent.block_hash().to_string(),
+                block.block_hash().to_string(),
+                tips[0].hash.clone(),
+            ]
+        );
+        node.shadow = true;
+        assert!(first_reported_hashes(&node, &[parent, block], &tips).is_empty());
+    }
+
+    #[test]
+    fn test_authenticate() {
+        let observers = vec![ExternalObserver {
+            name: "probe".to_string(),
+            token: "secret".to_string(),
+        }];
+        assert!(authenticate(&observers, &Some("Bearer secret".to_string())).is_some());
+        assert!(authenticate(&observers, &Some("Bearer other".to_string())).is_none());
+        assert!(authenticate(&observers, &None).is_none());
+    }
+}
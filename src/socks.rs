@@ -0,0 +1,149 @@
+use std::io::{self, Read, Write};
+use std::net::{IpAddr, SocketAddr, TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+const SOCKS_VERSION: u8 = 5;
+const METHOD_NO_AUTHENTICATION: u8 = 0;
+const COMMAND_CONNECT: u8 = 1;
+const ADDRESS_IPV4: u8 = 1;
+const ADDRESS_DOMAIN: u8 = 3;
+const ADDRESS_IPV6: u8 = 4;
+const REPLY_SUCCEEDED: u8 = 0;
+
+fn error(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::ConnectionRefused, message)
+}
+
+// Connects to host and port, through the SOCKS5 proxy if set. Host names
+// are resolved by the proxy, which allows connecting to Tor onion services.
+pub fn connect(
+    host: &str,
+    port: u16,
+    proxy: Option<SocketAddr>,
+    timeout: Duration,
+) -> Result<TcpStream, io::Error> {
+    let stream = match proxy {
+        Some(proxy) => TcpStream::connect_timeout(&proxy, timeout)?,
+        None => {
+            let mut last_error = error(format!("could not resolve {}", host));
+            let mut connected = None;
+            for address in (host, port).to_socket_addrs()? {
+                match TcpStream::connect_timeout(&address, timeout) {
+                    Ok(stream) => {
+                        connected = Some(stream);
+                        break;
+                    }
+                    Err(e) => last_error = e,
+                }
+            }
+            connected.ok_or(last_error)?
+        }
+    };
+    stream.set_read_timeout(Some(timeout))?;
+    stream.set_write_timeout(Some(timeout))?;
+    if proxy.is_some() {
+        handshake(&stream, host, port)?;
+    }
+    Ok(stream)
+}
+
+// The SOCKS5 CONNECT handshake without authentication (RFC 1928).
+fn handshake(mut stream: &TcpStream, host: &str, port: u16) -> Result<(), io::Error> {
+    stream.write_all(&[SOCKS_VERSION, 1, METHOD_NO_AUTHENTICATION])?;
+    let mut method = [0u8; 2];
+    stream.read_exact(&mut method)?;
+    if method != [SOCKS_VERSION, METHOD_NO_AUTHENTICATION] {
+        return Err(error(
+            "the SOCKS5 proxy requires an unsupported authentication".to_string(),
+        ));
+    }
+
+    let mut request = vec![SOCKS_VERSION, COMMAND_CONNECT, 0];
+    match host.parse::<IpAddr>() {
+        Ok(IpAddr::V4(ip)) => {
+            request.push(ADDRESS_IPV4);
+            request.extend_from_slice(&ip.octets());
+        }
+        Ok(IpAddr::V6(ip)) => {
+            request.push(ADDRESS_IPV6);
+            request.extend_from_slice(&ip.octets());
+        }
+        Err(_) => {
+            if host.len() > u8::MAX as usize {
+                return Err(error(format!("the host name {} is too long", host)));
+            }
+            request.push(ADDRESS_DOMAIN);
+            request.push(host.len() as u8);
+            request.extend_from_slice(host.as_bytes());
+        }
+    }
+    request.extend_from_slice(&port.to_be_bytes());
+    stream.write_all(&request)?;
+
+    let mut reply = [0u8; 4];
+    stream.read_exact(&mut reply)?;
+    if reply[0] != SOCKS_VERSION || reply[1] != REPLY_SUCCEEDED {
+        return Err(error(format!(
+            "the SOCKS5 proxy could not connect to {}:{} (reply {})",
+            host, port, reply[1]
+        )));
+    }
+    // Skip the bound address and port.
+    let address_length = match reply[3] {
+        ADDRESS_IPV4 => 4,
+        ADDRESS_IPV6 => 16,
+        ADDRESS_DOMAIN => {
+            let mut length = [0u8; 1];
+            stream.read_exact(&mut length)?;
+            length[0] as usize
+        }
+        _ => return Err(error("invalid SOCKS5 reply".to_string())),
+    };
+    let mut bound = vec![0u8; address_length + 2];
+    stream.read_exact(&mut bound)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+    use std::thread;
+
+    #[test]
+    fn test_connect_via_proxy() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let proxy = listener.local_addr().unwrap();
+        let server = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut greeting = [0u8; 3];
+            stream.read_exact(&mut greeting).unwrap();
+            stream.write_all(&[5, 0]).unwrap();
+            let mut request = [0u8; 5];
+            stream.read_exact(&mut request).unwrap();
+            let mut host = vec![0u8; request[4] as usize];
+            stream.read_exact(&mut host).unwrap();
+            let mut port = [0u8; 2];
+            stream.read_exact(&mut port).unwrap();
+            stream
+                .write_all(&[5, 0, 0, 1, 127, 0, 0, 1, 0, 0, b'o', b'k'])
+                .unwrap();
+            (
+                greeting,
+                request[3],
+                String::from_utf8(host).unwrap(),
+                u16::from_be_bytes(port),
+            )
+        });
+
+        let mut stream =
+            connect("example.onion", 8332, Some(proxy), Duration::from_secs(5)).unwrap();
+        let mut response = [0u8; 2];
+        stream.read_exact(&mut response).unwrap();
+        assert_eq!(&response, b"ok");
+        assert_eq!(
+            server.join().unwrap(),
+            ([5, 1, 0], ADDRESS_DOMAIN, "example.onion".to_string(), 8332)
+        );
+    }
+}
@@ -0,0 +1,138 @@
+use std::fmt;
+
+// The node software identified by its BIP14 user agent (subversion), e.g.
+// /Satoshi:27.1.0/Knots:20240801/.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Implementation {
+    BitcoinCore,
+    BitcoinKnots,
+    Btcd,
+    Bcoin,
+    Libbitcoin,
+    Elements,
+    Unknown,
+}
+
+impl fmt::Display for Implementation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Implementation::BitcoinCore => write!(f, "bitcoin-core"),
+            Implementation::BitcoinKnots => write!(f, "bitcoin-knots"),
+            Implementation::Btcd => write!(f, "btcd"),
+            Implementation::Bcoin => write!(f, "bcoin"),
+            Implementation::Libbitcoin => write!(f, "libbitcoin"),
+            Implementation::Elements => write!(f, "elements"),
+            Implementation::Unknown => write!(f, "unknown"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Software {
+    pub implementation: Implementation,
+    pub version: Option<String>,
+}
+
+// The name:version components of a user agent. Comments in parentheses,
+// e.g. set with -uacomment, are dropped.
+fn components(subversion: &str) -> Vec<(&str, &str)> {
+    subversion
+        .split('/')
+        .filter_map(|component| {
+            let component = match component.find('(') {
+                Some(index) => &component[..index],
+                None => component,
+            };
+            let (name, version) = component.split_once(':')?;
+            Some((name.trim(), version.trim()))
+        })
+        .collect()
+}
+
+impl Software {
+    // Derivatives of Bitcoin Core keep the Satoshi component and append
+    // their own, so the more specific components win.
+    pub fn from_subversion(subversion: &str) -> Self {
+        let components = components(subversion);
+        let version_of = |name: &str| -> Option<String> {
+            components
+                .iter()
+                .find(|(n, _)| n.eq_ignore_ascii_case(name))
+                .map(|(_, version)| version.to_string())
+        };
+        let satoshi = version_of("Satoshi");
+        let (implementation, version) = if let Some(knots) = version_of("Knots") {
+            // Knots versions are named like 27.1.knots20240801.
+            let version = match satoshi {
+                Some(satoshi) => format!("{}.knots{}", satoshi, knots),
+                None => knots,
+            };
+            (Implementation::BitcoinKnots, Some(version))
+        } else if let Some(version) = version_of("btcd") {
+            (Implementation::Btcd, Some(version))
+        } else if let Some(version) = version_of("bcoin") {
+            (Implementation::Bcoin, Some(version))
+        } else if let Some(version) = version_of("libbitcoin") {
+            (Implementation::Libbitcoin, Some(version))
+        } else if let Some(version) = version_of("Elements Core") {
+            (Implementation::Elements, Some(version))
+        } else if let Some(version) = satoshi {
+            (Implementation::BitcoinCore, Some(version))
+        } else {
+            (Implementation::Unknown, None)
+        };
+        Software {
+            implementation,
+            version,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_subversion() {
+        let cases = [
+            (
+                "/Satoshi:27.0.0/",
+                Implementation::BitcoinCore,
+                Some("27.0.0"),
+            ),
+            (
+                "/Satoshi:26.1.0(fork-observer)/",
+                Implementation::BitcoinCore,
+                Some("26.1.0"),
+            ),
+            (
+                "/Satoshi:27.1.0/Knots:20240801/",
+                Implementation::BitcoinKnots,
+                Some("27.1.0.knots20240801"),
+            ),
+            (
+                "/btcwire:0.5.0/btcd:0.24.0/",
+                Implementation::Btcd,
+                Some("0.24.0"),
+            ),
+            ("/bcoin:2.2.0/", Implementation::Bcoin, Some("2.2.0")),
+            (
+                "/libbitcoin:3.8.0/",
+                Implementation::Libbitcoin,
+                Some("3.8.0"),
+            ),
+            (
+                "/Elements Core:23.2.1/",
+                Implementation::Elements,
+                Some("23.2.1"),
+            ),
+            ("/mempool:3.0.0/", Implementation::Unknown, None),
+            ("unknown", Implementation::Unknown, None),
+        ];
+        for (subversion, implementation, version) in cases.iter() {
+            let software = Software::from_subversion(subversion);
+            assert_eq!(software.implementation, *implementation, "{}", subversion);
+            assert_eq!(software.version.as_deref(), *version, "{}", subversion);
+        }
+    }
+}
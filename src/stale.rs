@@ -0,0 +1,164 @@
+use std::collections::BTreeSet;
+use std::collections::HashMap;
+use std::convert::Infallible;
+
+use bitcoincore_rpc::bitcoin::BlockHash;
+use tokio::sync::Mutex;
+use warp::Reply;
+
+use crate::config::{Network, OwnMining};
+use crate::domain::{EventKind, EventSeverity, Tree};
+use crate::events::EventLog;
+use crate::headertree;
+use crate::pruning;
+use crate::rss::response_unknown_network;
+use crate::types::{
+    now_timestamp, Caches, NetworkJson, StaleRateJsonResponse, StaleRateWindowJson, Versioned,
+};
+
+// Blocks becoming stale deeper than this below the active tip aren't
+// reported.
+pub const STALE_BLOCK_DEPTH: u64 = 6;
+
+// The windows of the stale block rates by their length in seconds.
+const STALE_RATE_WINDOWS: [(&str, u64); 3] = [
+    ("day", 24 * 60 * 60),
+    ("week", 7 * 24 * 60 * 60),
+    ("month", 30 * 24 * 60 * 60),
+];
+
+// Emits an event for each block that became stale from the point of view of
+// a node with the active tip. Blocks mined by the operator are reported with
+// the severity configured in own_mining. The first check after startup
+// only records the already stale blocks, so they aren't reported again.
+pub async fn check_stale_blocks(
+    network_id: u32,
+    network_name: &str,
+    own_mining: &Option<OwnMining>,
+    tree: &Tree,
+    active_tip: &BlockHash,
+    reported: &Mutex<Option<BTreeSet<BlockHash>>>,
+    event_log: &EventLog,
+) {
+    let stale_blocks = headertree::stale_blocks(tree, active_tip, STALE_BLOCK_DEPTH).await;
+
+    let new_stale_blocks = {
+        let mut reported_locked = reported.lock().await;
+        match *reported_locked {
+            Some(ref mut reported) => stale_blocks
+                .into_iter()
+                .filter(|h| reported.insert(h.block_hash()))
+                .collect(),
+            None => {
+                *reported_locked = Some(stale_blocks.iter().map(|h| h.block_hash()).collect());
+                vec![]
+            }
+        }
+    };
+
+    for header_info in new_stale_blocks {
+        let hash = header_info.block_hash();
+        let miner = if header_info.miner.is_empty() {
+            "an unknown miner"
+        } else {
+            &header_info.miner
+        };
+        match own_mining {
+            Some(own_mining) if header_info.own => {
+                event_log
+                    .emit(
+                        network_id,
+                        EventKind::OwnStaleBlock,
+                        own_mining.stale_block_severity.clone(),
+                        format!(
+                            "Own block at height {} on {} became stale",
+                            header_info.height, network_name
+                        ),
+                        format!(
+                            "Our block {} at height {} on {} is no longer part of the active chain.",
+                            hash, header_info.height, network_name
+                        ),
+                    )
+                    .await
+            }
+            _ => {
+                event_log
+                    .emit(
+                        network_id,
+                        EventKind::StaleBlock,
+                        EventSeverity::Info,
+                        format!(
+                            "Stale block at height {} on {}",
+                            header_info.height, network_name
+                        ),
+                        format!(
+                            "The block {} at height {} on {} mined by {} is no longer part of the active chain.",
+                            hash, header_info.height, network_name, miner
+                        ),
+                    )
+                    .await
+            }
+        }
+    }
+}
+
+// The share of stale blocks among the blocks with a block time in the last
+// day, week and month. Stale blocks are counted from the tree, so pruned
+// branches and blocks the nodes never saw are missing.
+pub async fn stale_rate_response(
+    network_id: u32,
+    caches: Caches,
+    networks: Vec<Network>,
+    trees: HashMap<u32, Tree>,
+    network_infos: Vec<NetworkJson>,
+) -> Result<Box<dyn Reply>, Infallible> {
+    let tree = match networks
+        .iter()
+        .find(|n| n.id == network_id)
+        .and_then(|n| trees.get(&n.header_store))
+    {
+        Some(tree) => tree,
+        None => return Ok(Box::new(response_unknown_network(network_infos))),
+    };
+    let (tip_hash, tip_height) = match pruning::active_tips(&caches, &[network_id])
+        .await
+        .into_iter()
+        .max_by_key(|(_, height)| *height)
+    {
+        Some(tip) => tip,
+        None => {
+            return Ok(Box::new(warp::reply::json(&Versioned::new(
+                StaleRateJsonResponse {
+                    tip_hash: None,
+                    tip_height: None,
+                    windows: vec![],
+                },
+            ))))
+        }
+    };
+
+    let now = now_timestamp();
+    let mut windows: Vec<StaleRateWindowJson> = vec![];
+    for (window, seconds) in STALE_RATE_WINDOWS.iter() {
+        let since = now.saturating_sub(*seconds).min(u32::MAX as u64) as u32;
+        let (blocks, stale_blocks) = headertree::block_counts(tree, &tip_hash, since)
+            .await
+            .unwrap_or_default();
+        windows.push(StaleRateWindowJson {
+            window: window.to_string(),
+            seconds: *seconds,
+            blocks,
+            stale_blocks,
+            stale_rate: (blocks + stale_blocks > 0)
+                .then(|| stale_blocks as f64 / (blocks + stale_blocks) as f64),
+        });
+    }
+
+    Ok(Box::new(warp::reply::json(&Versioned::new(
+        StaleRateJsonResponse {
+            tip_hash: Some(tip_hash.to_string()),
+            tip_height: Some(tip_height),
+            windows,
+        },
+    ))))
+}
@@ -0,0 +1,194 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use log::{debug, error, info, warn};
+use tokio::time::{interval, Duration};
+
+use crate::config::StorageMonitoring;
+use crate::db;
+use crate::retention;
+use crate::types::{now_timestamp, Db};
+
+// The retention window applied when the database is over its limit, unless
+// the configured one is shorter.
+const EMERGENCY_RETENTION: Duration = Duration::from_secs(24 * 60 * 60);
+
+// SQLite might keep data next to the database file in a write-ahead-log
+// or a rollback journal. These count towards the database size too.
+const SQLITE_SIDE_FILE_SUFFIXES: [&str; 2] = ["-wal", "-journal"];
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum UsageLevel {
+    Ok,
+    Warning,
+    Exceeded,
+}
+
+pub fn usage_level(size: u64, limit: u64, warn_threshold_percent: u8) -> UsageLevel {
+    if size >= limit {
+        UsageLevel::Exceeded
+    } else if size as u128 * 100 >= limit as u128 * warn_threshold_percent as u128 {
+        UsageLevel::Warning
+    } else {
+        UsageLevel::Ok
+    }
+}
+
+// Returns the size of a file or the recursive size of a directory in bytes.
+fn path_size(path: &Path) -> io::Result<u64> {
+    let metadata = fs::metadata(path)?;
+    if metadata.is_dir() {
+        let mut size: u64 = 0;
+        for entry in fs::read_dir(path)? {
+            size += path_size(&entry?.path())?;
+        }
+        Ok(size)
+    } else {
+        Ok(metadata.len())
+    }
+}
+
+fn database_size(database_path: &Path) -> io::Result<u64> {
+    let mut size = path_size(database_path)?;
+    for suffix in SQLITE_SIDE_FILE_SUFFIXES.iter() {
+        let mut side_file = database_path.as_os_str().to_owned();
+        side_file.push(suffix);
+        // side files only exist while they are in use
+        if let Ok(side_file_size) = path_size(Path::new(&side_file)) {
+            size += side_file_size;
+        }
+    }
+    Ok(size)
+}
+
+fn log_size(log_paths: &[PathBuf]) -> u64 {
+    log_paths
+        .iter()
+        .map(|path| match path_size(path) {
+            Ok(size) => size,
+            Err(e) => {
+                warn!("Could not determine the size of log path {:?}: {}", path, e);
+                0
+            }
+        })
+        .sum()
+}
+
+fn to_mb(bytes: u64) -> f64 {
+    bytes as f64 / (1024.0 * 1024.0)
+}
+
+// Periodically checks the size of the database and the log files. Logs a
+// warning when approaching the configured limits and, if enabled, deletes
+// the tip observations and external sightings older than a day (or the
+// shorter retention) and compacts the database when it is over its limit.
+pub async fn monitor(
+    config: StorageMonitoring,
+    database_path: PathBuf,
+    db: Db,
+    retention: Duration,
+) {
+    let mut interval = interval(config.check_interval);
+    loop {
+        interval.tick().await;
+
+        if let Some(limit) = config.max_database_size {
+            match database_size(&database_path) {
+                Ok(size) => {
+                    debug!(
+                        "database {:?} uses {:.1} MB of {:.1} MB",
+                        database_path,
+                        to_mb(size),
+                        to_mb(limit)
+                    );
+                    match usage_level(size, limit, config.warn_threshold_percent) {
+                        UsageLevel::Ok => (),
+                        UsageLevel::Warning => warn!(
+                            "The database {:?} is approaching its size limit: {:.1} MB of {:.1} MB used.",
+                            database_path,
+                            to_mb(size),
+                            to_mb(limit)
+                        ),
+                        UsageLevel::Exceeded => {
+                            error!(
+                                "The database {:?} exceeds its size limit: {:.1} MB of {:.1} MB used.",
+                                database_path,
+                                to_mb(size),
+                                to_mb(limit)
+                            );
+                            if config.emergency_compaction {
+                                emergency_compaction(
+                                    &database_path,
+                                    db.clone(),
+                                    retention.min(EMERGENCY_RETENTION),
+                                )
+                                .await;
+                            }
+                        }
+                    }
+                }
+                Err(e) => error!(
+                    "Could not determine the size of the database {:?}: {}",
+                    database_path, e
+                ),
+            }
+        }
+
+        if let Some(limit) = config.max_log_size {
+            let size = log_size(&config.log_paths);
+            match usage_level(size, limit, config.warn_threshold_percent) {
+                UsageLevel::Ok => (),
+                UsageLevel::Warning => warn!(
+                    "The log files are approaching their size limit: {:.1} MB of {:.1} MB used.",
+                    to_mb(size),
+                    to_mb(limit)
+                ),
+                UsageLevel::Exceeded => error!(
+                    "The log files exceed their size limit: {:.1} MB of {:.1} MB used. Consider rotating them.",
+                    to_mb(size),
+                    to_mb(limit)
+                ),
+            }
+        }
+    }
+}
+
+async fn emergency_compaction(database_path: &Path, db: Db, retention: Duration) {
+    let size_before = database_size(database_path).unwrap_or_default();
+    info!(
+        "Starting emergency compaction of the database {:?}..",
+        database_path
+    );
+    retention::delete_expired(db.clone(), retention, now_timestamp()).await;
+    match db::compact(db).await {
+        Ok(_) => {
+            let size_after = database_size(database_path).unwrap_or_default();
+            info!(
+                "Emergency compaction of the database {:?} done: {:.1} MB -> {:.1} MB",
+                database_path,
+                to_mb(size_before),
+                to_mb(size_after)
+            );
+        }
+        Err(e) => error!("Could not compact the database {:?}: {}", database_path, e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_usage_level() {
+        assert_eq!(usage_level(0, 100, 90), UsageLevel::Ok);
+        assert_eq!(usage_level(89, 100, 90), UsageLevel::Ok);
+        assert_eq!(usage_level(90, 100, 90), UsageLevel::Warning);
+        assert_eq!(usage_level(99, 100, 90), UsageLevel::Warning);
+        assert_eq!(usage_level(100, 100, 90), UsageLevel::Exceeded);
+        assert_eq!(
+            usage_level(u64::MAX, u64::MAX - 1, 100),
+            UsageLevel::Exceeded
+        );
+    }
+}
@@ -0,0 +1,341 @@
+use std::collections::BTreeMap;
+use std::convert::Infallible;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::sync::Arc;
+use std::time::Duration;
+
+use bitcoincore_rpc::bitcoin::hashes::Hash;
+use bitcoincore_rpc::bitcoin::BlockHash;
+use log::{debug, info, warn};
+use serde_json::{json, Value};
+use tokio::sync::Mutex;
+use warp::Reply;
+
+use crate::config::StratumPool;
+use crate::rss::response_unknown_network;
+use crate::socks;
+use crate::types::{now_timestamp, NetworkJson, PoolTipJson, PoolsJsonResponse, Versioned};
+
+// The tips of the monitored mining pools per network id, in the order the
+// pools are configured.
+pub type PoolTips = Arc<Mutex<BTreeMap<u32, Vec<PoolTipJson>>>>;
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+// Pools send a new job at least every minute or two. A connection without a
+// message for this long is reconnected, in case it silently broke.
+const QUIET_TIMEOUT: Duration = Duration::from_secs(10 * 60);
+const RECONNECT_DELAY: Duration = Duration::from_secs(30);
+// Jobs with a large coinb1, e.g. with many outputs, fit easily.
+const MAX_LINE_LENGTH: u64 = 1024 * 1024;
+
+const ID_SUBSCRIBE: u64 = 1;
+const ID_AUTHORIZE: u64 = 2;
+
+fn error(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+// A Stratum V1 endpoint of a mining pool.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct StratumEndpoint {
+    pub host: String,
+    pub port: u16,
+}
+
+impl StratumEndpoint {
+    // Parses a Stratum endpoint as published by pools, e.g.
+    // "stratum+tcp://pool.example.com:3333". The stratum+tcp:// prefix is
+    // optional.
+    pub fn parse(endpoint: &str) -> Option<Self> {
+        let address = endpoint.strip_prefix("stratum+tcp://").unwrap_or(endpoint);
+        let (host, port) = address.rsplit_once(':')?;
+        let host = host.trim_start_matches('[').trim_end_matches(']');
+        if host.is_empty() {
+            return None;
+        }
+        Some(StratumEndpoint {
+            host: host.to_string(),
+            port: port.parse().ok()?,
+        })
+    }
+}
+
+// The block a pool lets its miners build on.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Job {
+    pub prev_hash: BlockHash,
+    // Height of the previous block. Unknown if the coinbase doesn't commit
+    // to the height as per BIP34.
+    pub prev_height: Option<u64>,
+}
+
+// Stratum sends the previous block hash as eight 4-byte words in internal
+// byte order, with the bytes of each word reversed.
+fn parse_prev_hash(prev_hash: &str) -> Option<BlockHash> {
+    let mut bytes: Vec<u8> = hex::decode(prev_hash).ok()?;
+    if bytes.len() != 32 {
+        return None;
+    }
+    for word in bytes.chunks_mut(4) {
+        word.reverse();
+    }
+    let mut array = [0u8; 32];
+    array.copy_from_slice(&bytes);
+    Some(BlockHash::from_byte_array(array))
+}
+
+// The height the coinbase commits to as per BIP34. coinb1 is the beginning
+// of the serialized coinbase transaction: version, one input spending the
+// null outpoint and the length of the scriptSig, which starts with the
+// height push.
+fn coinbase_height(coinb1: &str) -> Option<u64> {
+    let bytes = hex::decode(coinb1).ok()?;
+    // version (4), input count (1), null outpoint (32 + 4)
+    let script = bytes.get(41..)?;
+    let script = match script.first()? {
+        0xfd => script.get(3..)?,
+        0xfe | 0xff => return None,
+        _ => script.get(1..)?,
+    };
+    match *script.first()? {
+        // OP_0
+        0x00 => Some(0),
+        // OP_1 to OP_16, used for the first blocks of a chain
+        op @ 0x51..=0x60 => Some((op - 0x50) as u64),
+        len @ 1..=8 => {
+            let push = script.get(1..1 + len as usize)?;
+            let mut height = 0u64;
+            for (i, byte) in push.iter().enumerate() {
+                height |= (*byte as u64) << (8 * i);
+            }
+            Some(height)
+        }
+        _ => None,
+    }
+}
+
+// Parses the params of a mining.notify message: job_id, prevhash, coinb1,
+// coinb2, merkle_branch, version, nbits, ntime and clean_jobs.
+pub fn parse_notify(params: &Value) -> Option<Job> {
+    let params = params.as_array()?;
+    let prev_hash = parse_prev_hash(params.get(1)?.as_str()?)?;
+    let prev_height = params
+        .get(2)
+        .and_then(Value::as_str)
+        .and_then(coinbase_height)
+        .and_then(|height| height.checked_sub(1));
+    Some(Job {
+        prev_hash,
+        prev_height,
+    })
+}
+
+fn send<W: Write>(writer: &mut W, id: u64, method: &str, params: Value) -> Result<(), io::Error> {
+    let mut message = json!({"id": id, "method": method, "params": params}).to_string();
+    message.push('\n');
+    writer.write_all(message.as_bytes())
+}
+
+// Subscribes to the jobs of the pool and calls on_job for each job. Only
+// returns on error.
+pub fn subscribe(pool: &StratumPool, on_job: &dyn Fn(Job)) -> Result<(), io::Error> {
+    let mut stream = socks::connect(
+        &pool.endpoint.host,
+        pool.endpoint.port,
+        None,
+        CONNECT_TIMEOUT,
+    )?;
+    stream.set_read_timeout(Some(QUIET_TIMEOUT))?;
+    stream.set_write_timeout(Some(CONNECT_TIMEOUT))?;
+    send(
+        &mut stream,
+        ID_SUBSCRIBE,
+        "mining.subscribe",
+        json!(["fork-observer"]),
+    )?;
+    send(
+        &mut stream,
+        ID_AUTHORIZE,
+        "mining.authorize",
+        json!([pool.user, pool.password]),
+    )?;
+
+    let mut reader = BufReader::new(stream);
+    loop {
+        let mut line = String::new();
+        let read = reader.by_ref().take(MAX_LINE_LENGTH).read_line(&mut line)?;
+        if read == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "the pool closed the connection",
+            ));
+        }
+        if !line.ends_with('\n') {
+            return Err(error(format!(
+                "a message is larger than {} bytes",
+                MAX_LINE_LENGTH
+            )));
+        }
+        let message: Value = match serde_json::from_str(line.trim()) {
+            Ok(message) => message,
+            Err(e) => return Err(error(format!("invalid message: {}", e))),
+        };
+        match message.get("method").and_then(Value::as_str) {
+            Some("mining.notify") => match parse_notify(&message["params"]) {
+                Some(job) => on_job(job),
+                None => debug!("Ignoring an invalid mining.notify from {}", pool.name),
+            },
+            Some(_) => (),
+            None => {
+                if message.get("id").and_then(Value::as_u64) == Some(ID_AUTHORIZE)
+                    && message.get("result") != Some(&Value::Bool(true))
+                {
+                    return Err(error(format!(
+                        "the pool rejected the authorization of '{}'",
+                        pool.user
+                    )));
+                }
+            }
+        }
+    }
+}
+
+fn update(
+    pool_tips: &PoolTips,
+    network_id: u32,
+    index: usize,
+    update: impl FnOnce(&mut PoolTipJson),
+) {
+    if let Some(tip) = pool_tips
+        .blocking_lock()
+        .get_mut(&network_id)
+        .and_then(|tips| tips.get_mut(index))
+    {
+        update(tip);
+    }
+}
+
+// Subscribes to each pool of the network in a thread, reconnecting on
+// errors, and keeps the block the pool builds on in pool_tips.
+pub async fn spawn(
+    network_id: u32,
+    network_name: String,
+    pools: Vec<StratumPool>,
+    pool_tips: PoolTips,
+) {
+    pool_tips.lock().await.insert(
+        network_id,
+        pools
+            .iter()
+            .map(|pool| PoolTipJson {
+                name: pool.name.clone(),
+                hash: None,
+                height: None,
+                last_job: None,
+                connected: false,
+            })
+            .collect(),
+    );
+    for (index, pool) in pools.into_iter().enumerate() {
+        let pool_tips = pool_tips.clone();
+        let network_name = network_name.clone();
+        std::thread::spawn(move || loop {
+            let on_job = |job: Job| {
+                update(&pool_tips, network_id, index, |tip| {
+                    let hash = job.prev_hash.to_string();
+                    if tip.hash.as_ref() != Some(&hash) {
+                        info!(
+                            "Pool {} on network '{}' builds on {}",
+                            pool.name, network_name, hash
+                        );
+                    }
+                    tip.hash = Some(hash);
+                    tip.height = job.prev_height;
+                    tip.last_job = Some(now_timestamp());
+                    tip.connected = true;
+                })
+            };
+            if let Err(e) = subscribe(&pool, &on_job) {
+                warn!(
+                    "The Stratum subscription to pool {} on network '{}' failed: {}. Reconnecting in {}s..",
+                    pool.name,
+                    network_name,
+                    e,
+                    RECONNECT_DELAY.as_secs()
+                );
+            }
+            update(&pool_tips, network_id, index, |tip| tip.connected = false);
+            std::thread::sleep(RECONNECT_DELAY);
+        });
+    }
+}
+
+pub async fn response(
+    network_id: u32,
+    pool_tips: PoolTips,
+    network_infos: Vec<NetworkJson>,
+) -> Result<Box<dyn Reply>, Infallible> {
+    if !network_infos.iter().any(|n| n.id == network_id) {
+        return Ok(Box::new(response_unknown_network(network_infos)));
+    }
+    let pools = pool_tips
+        .lock()
+        .await
+        .get(&network_id)
+        .cloned()
+        .unwrap_or_default();
+    Ok(Box::new(warp::reply::json(&Versioned::new(
+        PoolsJsonResponse { pools },
+    ))))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_notify() {
+        // The example of the Stratum V1 documentation.
+        let params = json!([
+            "bf",
+            "4d16b6f85af6e2198f44ae2a6de67f78487ae5611b77c6c0440b921e00000000",
+            "01000000010000000000000000000000000000000000000000000000000000000000000000ffffffff20020862062f503253482f04b8864e5008",
+            "072f736c7573682f000000000100f2052a010000001976a914d23fcdf86f7e756a64a7a9688ef9903327048ed988ac00000000",
+            [],
+            "00000002",
+            "1c2ac4af",
+            "504e86b9",
+            false
+        ]);
+        let job = parse_notify(&params).unwrap();
+        assert_eq!(
+            job.prev_hash.to_string(),
+            "00000000440b921e1b77c6c0487ae5616de67f788f44ae2a5af6e2194d16b6f8"
+        );
+        assert_eq!(job.prev_height, Some(25095));
+
+        assert_eq!(parse_notify(&json!(["bf", "00"])), None);
+    }
+
+    #[test]
+    fn test_coinbase_height() {
+        let prefix =
+            "01000000010000000000000000000000000000000000000000000000000000000000000000ffffffff";
+        assert_eq!(
+            coinbase_height(&format!("{}0403a08601", prefix)),
+            Some(100_000)
+        );
+        assert_eq!(coinbase_height(&format!("{}0155", prefix)), Some(5));
+        assert_eq!(coinbase_height(&format!("{}00", prefix)), None);
+        assert_eq!(coinbase_height("0100"), None);
+
+        assert_eq!(
+            StratumEndpoint::parse("stratum+tcp://pool.example.com:3333"),
+            Some(StratumEndpoint {
+                host: "pool.example.com".to_string(),
+                port: 3333
+            })
+        );
+        assert_eq!(StratumEndpoint::parse("pool.example.com"), None);
+    }
+}
@@ -0,0 +1,166 @@
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use tokio::sync::Mutex;
+
+use crate::db;
+use crate::domain::{ChainTip, ChainTipStatus, SuppressedTip};
+use crate::error::DbError;
+use crate::types::{now_timestamp, Db};
+
+// A suppressed tip and the nodes that still report it.
+#[derive(Debug, Clone)]
+pub struct SuppressedState {
+    pub tip: SuppressedTip,
+    /// The height of the tip by the id of the nodes whose last chain tips
+    /// included it.
+    pub reported_by: BTreeMap<u32, u64>,
+}
+
+// The suppressed tips per network id by their hash.
+pub type SuppressedTips = Arc<Mutex<BTreeMap<u32, BTreeMap<String, SuppressedState>>>>;
+
+pub async fn load(db: Db) -> Result<SuppressedTips, DbError> {
+    let mut suppressed: BTreeMap<u32, BTreeMap<String, SuppressedState>> = BTreeMap::new();
+    for tip in db::load_suppressed_tips(db).await? {
+        suppressed.entry(tip.network_id).or_default().insert(
+            tip.hash.clone(),
+            SuppressedState {
+                tip,
+                reported_by: BTreeMap::new(),
+            },
+        );
+    }
+    Ok(Arc::new(Mutex::new(suppressed)))
+}
+
+pub async fn suppress(
+    suppressed: &SuppressedTips,
+    db: Db,
+    network_id: u32,
+    hash: String,
+    reason: String,
+) -> Result<SuppressedTip, DbError> {
+    let tip = SuppressedTip {
+        network_id,
+        hash,
+        reason,
+        since: now_timestamp(),
+    };
+    db::write_suppressed_tip(db, &tip).await?;
+    suppressed
+        .lock()
+        .await
+        .entry(network_id)
+        .or_default()
+        .insert(
+            tip.hash.clone(),
+            SuppressedState {
+                tip: tip.clone(),
+                reported_by: BTreeMap::new(),
+            },
+        );
+    Ok(tip)
+}
+
+// Returns false if the tip wasn't suppressed.
+pub async fn unsuppress(
+    suppressed: &SuppressedTips,
+    db: Db,
+    network_id: u32,
+    hash: &str,
+) -> Result<bool, DbError> {
+    let deleted = db::delete_suppressed_tip(db, network_id, hash).await?;
+    if let Some(tips) = suppressed.lock().await.get_mut(&network_id) {
+        tips.remove(hash);
+    }
+    Ok(deleted)
+}
+
+// Drops the suppressed tips from the chain tips a node reported and notes
+// that the node reported them. Active tips are never dropped, as the node
+// follows them.
+pub async fn filter(
+    suppressed: &SuppressedTips,
+    network_id: u32,
+    node_id: u32,
+    tips: Vec<ChainTip>,
+) -> Vec<ChainTip> {
+    let mut suppressed_locked = suppressed.lock().await;
+    let network_tips = match suppressed_locked.get_mut(&network_id) {
+        Some(network_tips) if !network_tips.is_empty() => network_tips,
+        _ => return tips,
+    };
+    for state in network_tips.values_mut() {
+        state.reported_by.remove(&node_id);
+    }
+    tips.into_iter()
+        .filter(|tip| match network_tips.get_mut(&tip.hash) {
+            Some(state) if tip.status != ChainTipStatus::Active => {
+                state.reported_by.insert(node_id, tip.height);
+                false
+            }
+            _ => true,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rusqlite::Connection;
+
+    fn tip(hash: &str, status: ChainTipStatus) -> ChainTip {
+        ChainTip {
+            height: 100,
+            hash: hash.to_string(),
+            branchlen: 1,
+            status,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_filter() {
+        let db: Db = Arc::new(Mutex::new(Connection::open_in_memory().unwrap()));
+        db::setup_db(db.clone()).await.unwrap();
+        let suppressed = load(db.clone()).await.unwrap();
+        let tips = vec![
+            tip("a", ChainTipStatus::Active),
+            tip("b", ChainTipStatus::HeadersOnly),
+        ];
+        assert_eq!(filter(&suppressed, 1, 0, tips.clone()).await, tips);
+
+        suppress(&suppressed, db.clone(), 1, "a".to_string(), "".to_string())
+            .await
+            .unwrap();
+        suppress(
+            &suppressed,
+            db.clone(),
+            1,
+            "b".to_string(),
+            "noise".to_string(),
+        )
+        .await
+        .unwrap();
+        // active tips aren't dropped and other networks aren't affected
+        assert_eq!(
+            filter(&suppressed, 1, 0, tips.clone()).await,
+            vec![tips[0].clone()]
+        );
+        assert_eq!(filter(&suppressed, 2, 0, tips.clone()).await, tips);
+
+        // the suppressed tips persist, the reporting nodes don't
+        let reloaded = load(db.clone()).await.unwrap();
+        assert_eq!(reloaded.lock().await[&1]["b"].tip.reason, "noise");
+        assert_eq!(
+            suppressed.lock().await[&1]["b"].reported_by,
+            BTreeMap::from([(0, 100)])
+        );
+        filter(&suppressed, 1, 0, vec![tips[0].clone()]).await;
+        assert!(suppressed.lock().await[&1]["b"].reported_by.is_empty());
+
+        assert!(unsuppress(&suppressed, db.clone(), 1, "b").await.unwrap());
+        assert!(!unsuppress(&suppressed, db.clone(), 1, "b").await.unwrap());
+        assert_eq!(filter(&suppressed, 1, 0, tips.clone()).await, tips);
+    }
+}
@@ -0,0 +1,206 @@
+use std::collections::BTreeMap;
+use std::convert::Infallible;
+use std::sync::Arc;
+
+use log::{info, warn};
+use tokio::sync::Mutex;
+use tokio::time::{interval, Duration};
+use warp::Reply;
+
+use crate::config::{Network, TemplateComparison};
+use crate::domain::{EventKind, EventSeverity};
+use crate::events::EventLog;
+use crate::paused::{self, PausedNodes};
+use crate::rss::response_unknown_network;
+use crate::types::{
+    now_timestamp, BlockTemplateJson, BlockTemplatesJsonResponse, NetworkJson, Versioned,
+};
+
+// The latest block template comparison per network id.
+pub type BlockTemplates = Arc<Mutex<BTreeMap<u32, BlockTemplatesJsonResponse>>>;
+
+fn divergence_percent(values: &[u64]) -> Option<u64> {
+    if values.len() < 2 {
+        return None;
+    }
+    let max = *values.iter().max()?;
+    let min = *values.iter().min()?;
+    if max == 0 {
+        return Some(0);
+    }
+    Some((max - min) * 100 / max)
+}
+
+// Compares the templates built on the highest height. Nodes building on
+// other tips naturally have different templates.
+pub fn compare(
+    templates: Vec<BlockTemplateJson>,
+    template_comparison: &TemplateComparison,
+    timestamp: u64,
+) -> BlockTemplatesJsonResponse {
+    let height = templates.iter().map(|t| t.height).max();
+    let compared: Vec<&BlockTemplateJson> = templates
+        .iter()
+        .filter(|t| Some(t.height) == height)
+        .collect();
+    let fee_divergence_percent =
+        divergence_percent(&compared.iter().map(|t| t.total_fees).collect::<Vec<u64>>());
+    let tx_count_divergence_percent = divergence_percent(
+        &compared
+            .iter()
+            .map(|t| t.tx_count as u64)
+            .collect::<Vec<u64>>(),
+    );
+    let divergent = fee_divergence_percent
+        .is_some_and(|p| p > template_comparison.max_fee_divergence_percent as u64)
+        || tx_count_divergence_percent
+            .is_some_and(|p| p > template_comparison.max_tx_count_divergence_percent as u64);
+    BlockTemplatesJsonResponse {
+        timestamp,
+        height,
+        templates,
+        fee_divergence_percent,
+        tx_count_divergence_percent,
+        divergent,
+    }
+}
+
+// Fetches the block templates of all nodes of the network each query
+// interval and emits a mempool-divergence event when the templates start to
+// diverge.
+pub async fn run(
+    network: Network,
+    template_comparison: TemplateComparison,
+    query_interval: Duration,
+    block_templates: BlockTemplates,
+    event_log: EventLog,
+    paused_nodes: PausedNodes,
+) {
+    let mut interval = interval(query_interval);
+    let mut was_divergent = false;
+    loop {
+        interval.tick().await;
+        let mut templates: Vec<BlockTemplateJson> = vec![];
+        for node in network.nodes.iter().filter(|node| !node.info().shadow) {
+            if paused::is_paused(&paused_nodes, network.id, node.info().id).await {
+                continue;
+            }
+            match node.block_template().await {
+                // Backends without block templates aren't compared.
+                Ok(None) => (),
+                Ok(Some(template)) => templates.push(BlockTemplateJson {
+                    node_id: node.info().id,
+                    name: node.info().name,
+                    height: template.height,
+                    total_fees: template.total_fees,
+                    tx_count: template.tx_count,
+                }),
+                Err(e) => warn!(
+                    "Could not fetch a block template from {} on network '{}': {}",
+                    node.info(),
+                    network.name,
+                    e
+                ),
+            }
+        }
+
+        let comparison = compare(templates, &template_comparison, now_timestamp());
+        if comparison.divergent && !was_divergent {
+            event_log
+                .emit(
+                    network.id,
+                    EventKind::MempoolDivergence,
+                    EventSeverity::Warning,
+                    format!("Block templates on {} diverge", network.name),
+                    format!(
+                        "The block templates of the nodes on {} for height {} differ by {}% in total fees and {}% in transaction count. The nodes' mempools are inconsistent, e.g. due to policy differences.",
+                        network.name,
+                        comparison.height.unwrap_or_default(),
+                        comparison.fee_divergence_percent.unwrap_or_default(),
+                        comparison.tx_count_divergence_percent.unwrap_or_default(),
+                    ),
+                )
+                .await;
+        } else if !comparison.divergent && was_divergent {
+            info!("Block templates on network '{}' converged", network.name);
+        }
+        was_divergent = comparison.divergent;
+        block_templates.lock().await.insert(network.id, comparison);
+    }
+}
+
+pub async fn response(
+    network_id: u32,
+    block_templates: BlockTemplates,
+    network_infos: Vec<NetworkJson>,
+) -> Result<Box<dyn Reply>, Infallible> {
+    if !network_infos.iter().any(|n| n.id == network_id) {
+        return Ok(Box::new(response_unknown_network(network_infos)));
+    }
+    match block_templates.lock().await.get(&network_id) {
+        Some(comparison) => Ok(Box::new(warp::reply::json(&Versioned::new(
+            comparison.clone(),
+        )))),
+        // The comparison is disabled or didn't run yet.
+        None => Ok(Box::new(warp::reply::json(&Versioned::new(
+            BlockTemplatesJsonResponse {
+                timestamp: now_timestamp(),
+                height: None,
+                templates: vec![],
+                fee_divergence_percent: None,
+                tx_count_divergence_percent: None,
+                divergent: false,
+            },
+        )))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn template(node_id: u32, height: u64, total_fees: u64, tx_count: usize) -> BlockTemplateJson {
+        BlockTemplateJson {
+            node_id,
+            name: format!("node {}", node_id),
+            height,
+            total_fees,
+            tx_count,
+        }
+    }
+
+    #[test]
+    fn test_compare() {
+        let template_comparison = TemplateComparison {
+            max_fee_divergence_percent: 10,
+            max_tx_count_divergence_percent: 20,
+        };
+
+        let comparison = compare(
+            vec![template(0, 100, 1000, 100), template(1, 100, 950, 90)],
+            &template_comparison,
+            0,
+        );
+        assert_eq!(comparison.fee_divergence_percent, Some(5));
+        assert_eq!(comparison.tx_count_divergence_percent, Some(10));
+        assert!(!comparison.divergent);
+
+        let comparison = compare(
+            vec![template(0, 100, 1000, 100), template(1, 100, 800, 100)],
+            &template_comparison,
+            0,
+        );
+        assert_eq!(comparison.fee_divergence_percent, Some(20));
+        assert!(comparison.divergent);
+
+        // a node behind the others isn't compared
+        let comparison = compare(
+            vec![template(0, 100, 1000, 100), template(1, 99, 10, 1)],
+            &template_comparison,
+            0,
+        );
+        assert_eq!(comparison.height, Some(100));
+        assert_eq!(comparison.fee_divergence_percent, None);
+        assert!(!comparison.divergent);
+    }
+}
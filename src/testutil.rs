@@ -0,0 +1,80 @@
+// Fixtures shared by the tests of the other modules.
+
+use bitcoincore_rpc::bitcoin::block::{Header, Version};
+use bitcoincore_rpc::bitcoin::hashes::Hash;
+use bitcoincore_rpc::bitcoin::{BlockHash, CompactTarget, TxMerkleNode};
+
+use crate::domain::{HeaderInfo, HeaderSource, NodeInfo};
+use crate::retry::RetryPolicy;
+use crate::types::{NodeDataJson, TipInfoJson};
+
+// A header with the regtest target. It isn't mined, so its proof of work is
+// usually invalid. Different nonces give different block hashes.
+pub fn header(prev_blockhash: BlockHash, time: u32, nonce: u32) -> Header {
+    Header {
+        version: Version::ONE,
+        prev_blockhash,
+        merkle_root: TxMerkleNode::all_zeros(),
+        time,
+        bits: CompactTarget::from_consensus(0x207fffff),
+        nonce,
+    }
+}
+
+// A header received via RPC without a known miner.
+pub fn header_info(height: u64, header: Header) -> HeaderInfo {
+    HeaderInfo {
+        height,
+        header,
+        miner: String::new(),
+        own: false,
+        source: HeaderSource::Rpc,
+        elements: None,
+    }
+}
+
+// A header info at height that doesn't build on another header, for tests
+// that don't need a connected chain.
+pub fn at_height(height: u64, time: u32, nonce: u32) -> HeaderInfo {
+    header_info(height, header(BlockHash::all_zeros(), time, nonce))
+}
+
+// A header info building on prev. Without prev, it's at height 0 and has an
+// all-zero prev_blockhash.
+pub fn child(prev: Option<&HeaderInfo>, time: u32, nonce: u32) -> HeaderInfo {
+    header_info(
+        prev.map(|p| p.height + 1).unwrap_or_default(),
+        header(
+            prev.map(|p| p.block_hash())
+                .unwrap_or_else(BlockHash::all_zeros),
+            time,
+            nonce,
+        ),
+    )
+}
+
+// A node that isn't a shadow node.
+pub fn node_info(id: u32, trust: f64) -> NodeInfo {
+    NodeInfo {
+        id,
+        name: format!("node {}", id),
+        description: String::new(),
+        implementation: String::new(),
+        trust,
+        shadow: false,
+        purge_after: None,
+        retry: RetryPolicy::default(),
+    }
+}
+
+// The data of a reachable node with an active tip.
+pub fn node_data(id: u32, trust: f64, hash: &str, height: u64) -> NodeDataJson {
+    let mut node = NodeDataJson::new(node_info(id, trust), &[], String::new(), 0, true);
+    node.tips = vec![TipInfoJson {
+        hash: hash.to_string(),
+        status: "active".to_string(),
+        height,
+        chainwork: None,
+    }];
+    node
+}
@@ -0,0 +1,241 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use bitcoincore_rpc::bitcoin::BlockHash;
+use petgraph::graph::NodeIndex;
+
+use crate::config::TimestampAnomalies;
+use crate::domain::{EventKind, EventSeverity, HeaderInfo, Tree, TreeInfo};
+use crate::events::EventLog;
+use crate::types::{now_timestamp, HeaderInfoJson};
+
+// The median time past is the median of the times of this many blocks
+// ending with the parent.
+const MEDIAN_TIME_SPAN: usize = 11;
+
+#[derive(Debug, PartialEq)]
+pub enum TimestampFlag {
+    // The time is more than max_future seconds ahead of the current time.
+    Future,
+    // The time is at most mtp_margin seconds after the median time past.
+    Past,
+}
+
+impl fmt::Display for TimestampFlag {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TimestampFlag::Future => write!(f, "future"),
+            TimestampFlag::Past => write!(f, "past"),
+        }
+    }
+}
+
+// The median time past a header at idx has to exceed. None if fewer than
+// MEDIAN_TIME_SPAN ancestors are in the tree above the genesis block, e.g.
+// at the root of the tree or below collapsed headers.
+pub fn median_time_past(tree: &TreeInfo, idx: NodeIndex) -> Option<u32> {
+    let (graph, _, _) = tree;
+    let mut times: Vec<u32> = vec![];
+    let mut current = idx;
+    while times.len() < MEDIAN_TIME_SPAN {
+        let parent = graph
+            .neighbors_directed(current, petgraph::Direction::Incoming)
+            .next();
+        match parent {
+            Some(parent) if graph[parent].height + 1 == graph[current].height => {
+                times.push(graph[parent].header.time);
+                current = parent;
+            }
+            _ => break,
+        }
+    }
+    if times.is_empty() || (times.len() < MEDIAN_TIME_SPAN && graph[current].height != 0) {
+        return None;
+    }
+    times.sort_unstable();
+    Some(times[times.len() / 2])
+}
+
+pub fn flags(
+    config: &TimestampAnomalies,
+    time: u32,
+    median_time_past: Option<u32>,
+    now: u64,
+) -> Vec<TimestampFlag> {
+    let mut flags: Vec<TimestampFlag> = vec![];
+    if time as u64 > now + config.max_future {
+        flags.push(TimestampFlag::Future);
+    }
+    if median_time_past.is_some_and(|mtp| time as u64 <= mtp as u64 + config.mtp_margin) {
+        flags.push(TimestampFlag::Past);
+    }
+    flags
+}
+
+// Sets the timestamp flags of the headers of the tree data. The future flag
+// is relative to the current time, so it's dropped once the time has passed.
+pub async fn flag_headers(
+    config: &TimestampAnomalies,
+    tree: &Tree,
+    header_infos: &mut [HeaderInfoJson],
+) {
+    let now = now_timestamp();
+    let tree_locked = tree.lock().await;
+    let index: &HashMap<BlockHash, NodeIndex> = &tree_locked.1;
+    for header_info in header_infos.iter_mut() {
+        let idx = match header_info
+            .hash
+            .parse::<BlockHash>()
+            .ok()
+            .and_then(|hash| index.get(&hash))
+        {
+            Some(idx) => *idx,
+            None => continue,
+        };
+        header_info.timestamp_flags = flags(
+            config,
+            header_info.time,
+            median_time_past(&tree_locked, idx),
+            now,
+        )
+        .iter()
+        .map(|flag| flag.to_string())
+        .collect();
+    }
+}
+
+// Emits an event for each header newly inserted into the tree with an
+// anomalous timestamp.
+pub async fn check_headers(
+    network_id: u32,
+    network_name: &str,
+    config: &TimestampAnomalies,
+    tree: &Tree,
+    new_headers: &[HeaderInfo],
+    event_log: &EventLog,
+) {
+    let now = now_timestamp();
+    let flagged: Vec<(&HeaderInfo, Option<u32>, Vec<TimestampFlag>)> = {
+        let tree_locked = tree.lock().await;
+        new_headers
+            .iter()
+            .filter_map(|header_info| {
+                let idx = tree_locked.1.get(&header_info.block_hash())?;
+                let mtp = median_time_past(&tree_locked, *idx);
+                let flags = flags(config, header_info.header.time, mtp, now);
+                Some((header_info, mtp, flags)).filter(|(_, _, flags)| !flags.is_empty())
+            })
+            .collect()
+    };
+
+    for (header_info, mtp, flags) in flagged.iter() {
+        let time = header_info.header.time;
+        for flag in flags.iter() {
+            let (title, description) = match flag {
+                TimestampFlag::Future => (
+                    format!(
+                        "Block at height {} on {} has a time in the future",
+                        header_info.height, network_name
+                    ),
+                    format!(
+                        "The block {} at height {} on {} has the time {}, {} seconds ahead of the current time.",
+                        header_info.block_hash(),
+                        header_info.height,
+                        network_name,
+                        time,
+                        time as u64 - now
+                    ),
+                ),
+                TimestampFlag::Past => (
+                    format!(
+                        "Block at height {} on {} has a time close to the median time past",
+                        header_info.height, network_name
+                    ),
+                    format!(
+                        "The block {} at height {} on {} has the time {}, {} seconds after the median time past {} of its parent.",
+                        header_info.block_hash(),
+                        header_info.height,
+                        network_name,
+                        time,
+                        time as i64 - mtp.unwrap_or_default() as i64,
+                        mtp.unwrap_or_default()
+                    ),
+                ),
+            };
+            event_log
+                .emit(
+                    network_id,
+                    EventKind::TimestampAnomaly,
+                    EventSeverity::Warning,
+                    title,
+                    description,
+                )
+                .await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::child;
+    use petgraph::graph::DiGraph;
+
+    fn add_header(tree: &mut TreeInfo, prev: Option<NodeIndex>, time: u32) -> NodeIndex {
+        let (graph, index, _) = tree;
+        let header_info = child(prev.map(|p| &graph[p]), time, 0);
+        let idx = graph.add_node(header_info.clone());
+        index.insert(header_info.block_hash(), idx);
+        if let Some(prev) = prev {
+            graph.add_edge(prev, idx, 0);
+        }
+        idx
+    }
+
+    #[test]
+    fn test_timestamp_flags() {
+        let mut tree: TreeInfo = (DiGraph::new(), HashMap::new(), HashMap::new());
+        let genesis = add_header(&mut tree, None, 1000);
+        assert_eq!(median_time_past(&tree, genesis), None);
+
+        // blocks every 600 seconds, one of them with a time far behind
+        let mut tip = genesis;
+        for height in 1..=12u32 {
+            let time = if height == 6 {
+                500
+            } else {
+                1000 + height * 600
+            };
+            tip = add_header(&mut tree, Some(tip), time);
+        }
+        // the median of the times of the heights 1 to 11
+        assert_eq!(median_time_past(&tree, tip), Some(1000 + 5 * 600));
+        let near_genesis = tree.0.neighbors(genesis).next().unwrap();
+        assert_eq!(median_time_past(&tree, near_genesis), Some(1000));
+
+        let config = TimestampAnomalies {
+            max_future: 3600,
+            mtp_margin: 0,
+        };
+        let now = 100_000;
+        assert_eq!(flags(&config, 10_000, Some(9_000), now), vec![]);
+        assert_eq!(
+            flags(&config, 9_000, Some(9_000), now),
+            vec![TimestampFlag::Past]
+        );
+        assert_eq!(flags(&config, 9_000, None, now), vec![]);
+        assert_eq!(flags(&config, 103_600, None, now), vec![]);
+        assert_eq!(
+            flags(&config, 103_601, Some(9_000), now),
+            vec![TimestampFlag::Future]
+        );
+        let config = TimestampAnomalies {
+            max_future: 3600,
+            mtp_margin: 60,
+        };
+        assert_eq!(
+            flags(&config, 9_060, Some(9_000), now),
+            vec![TimestampFlag::Past]
+        );
+    }
+}
@@ -0,0 +1,84 @@
+use log::error;
+
+use crate::db;
+use crate::domain::{ChainTip, NodeInfo};
+use crate::types::Db;
+
+// The tips that appeared since the last tips of the node or changed their
+// status, e.g. a valid-fork tip becoming active. Tips that are gone, e.g.
+// the previous active tip after a new block, aren't changes themselves.
+pub fn changes(old_tips: &[ChainTip], new_tips: &[ChainTip]) -> Vec<ChainTip> {
+    new_tips
+        .iter()
+        .filter(|tip| {
+            !old_tips
+                .iter()
+                .any(|old| old.hash == tip.hash && old.status == tip.status)
+        })
+        .cloned()
+        .collect()
+}
+
+// Appends the tip changes of the node to its tip history.
+pub async fn record(
+    network_id: u32,
+    network_name: &str,
+    node: &NodeInfo,
+    old_tips: &[ChainTip],
+    new_tips: &[ChainTip],
+    timestamp: u64,
+    db: Db,
+) {
+    let changes = changes(old_tips, new_tips);
+    if changes.is_empty() {
+        return;
+    }
+    if let Err(e) = db::write_tip_history(db, network_id, node.id, timestamp, &changes).await {
+        error!(
+            "Could not write the tip history of node {} on network '{}' to database: {}",
+            node, network_name, e
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::ChainTipStatus;
+
+    fn tip(height: u64, hash: &str, status: ChainTipStatus) -> ChainTip {
+        ChainTip {
+            height,
+            hash: hash.to_string(),
+            branchlen: 0,
+            status,
+        }
+    }
+
+    #[test]
+    fn test_changes() {
+        let old_tips = vec![
+            tip(100, "a", ChainTipStatus::Active),
+            tip(100, "b", ChainTipStatus::ValidHeaders),
+        ];
+        assert_eq!(changes(&[], &old_tips), old_tips);
+        assert_eq!(changes(&old_tips, &old_tips), vec![]);
+
+        // b is validated and becomes active, a is left as a fork
+        let new_tips = vec![
+            tip(100, "b", ChainTipStatus::Active),
+            tip(100, "a", ChainTipStatus::ValidFork),
+        ];
+        assert_eq!(changes(&old_tips, &new_tips), new_tips);
+
+        // a new block on top of b
+        let newer_tips = vec![
+            tip(101, "c", ChainTipStatus::Active),
+            tip(100, "a", ChainTipStatus::ValidFork),
+        ];
+        assert_eq!(
+            changes(&new_tips, &newer_tips),
+            vec![tip(101, "c", ChainTipStatus::Active)]
+        );
+    }
+}
@@ -0,0 +1,406 @@
+use std::collections::hash_map::RandomState;
+use std::convert::Infallible;
+use std::future::Future;
+use std::hash::{BuildHasher, Hasher};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use log::{debug, warn};
+use serde_json::{json, Value};
+use tokio::sync::mpsc;
+use tokio::task;
+use warp::http::HeaderValue;
+use warp::hyper::service::Service;
+use warp::hyper::{Body, Request, Response};
+
+use crate::config::Tracing;
+
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+const TRACEPARENT_HEADER: &str = "traceparent";
+const MAX_REQUEST_ID_LENGTH: usize = 64;
+// Requests slower than this are logged with their slowest spans.
+const SLOW_REQUEST: Duration = Duration::from_secs(1);
+const EXPORT_INTERVAL: Duration = Duration::from_secs(5);
+const EXPORT_TIMEOUT: u64 = 10; // seconds
+
+// OTLP span kinds.
+const SPAN_KIND_INTERNAL: u8 = 1;
+const SPAN_KIND_SERVER: u8 = 2;
+
+tokio::task_local! {
+    static CURRENT: Arc<RequestTrace>;
+}
+
+static ID_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+// A random, not cryptographically secure id.
+fn random_u64() -> u64 {
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u64(ID_COUNTER.fetch_add(1, Ordering::Relaxed));
+    hasher.finish()
+}
+
+fn unix_nanos(time: SystemTime) -> u128 {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos()
+}
+
+#[derive(Clone, Debug)]
+pub struct Span {
+    pub name: String,
+    pub span_id: u64,
+    pub start: SystemTime,
+    pub duration: Duration,
+}
+
+// The trace of an API request: the request id that is logged and returned
+// to the client, and the lock waits and storage queries made while
+// answering the request.
+#[derive(Debug)]
+pub struct RequestTrace {
+    pub request_id: String,
+    pub trace_id: u128,
+    pub span_id: u64,
+    // The span of the client calling us, if it sent a traceparent header.
+    pub parent_span_id: Option<u64>,
+    pub name: String,
+    pub start: SystemTime,
+    spans: Mutex<Vec<Span>>,
+}
+
+impl RequestTrace {
+    pub fn new(name: String, request_id: Option<&str>, traceparent: Option<&str>) -> Self {
+        let (trace_id, parent_span_id) = match traceparent.and_then(parse_traceparent) {
+            Some((trace_id, parent_span_id)) => (trace_id, Some(parent_span_id)),
+            None => ((random_u64() as u128) << 64 | random_u64() as u128, None),
+        };
+        RequestTrace {
+            request_id: match request_id {
+                Some(request_id) if is_valid_request_id(request_id) => request_id.to_string(),
+                _ => format!("{:016x}", random_u64()),
+            },
+            trace_id,
+            span_id: random_u64(),
+            parent_span_id,
+            name,
+            start: SystemTime::now(),
+            spans: Mutex::new(vec![]),
+        }
+    }
+
+    pub fn spans(&self) -> Vec<Span> {
+        match self.spans.lock() {
+            Ok(spans) => spans.clone(),
+            Err(_) => vec![],
+        }
+    }
+
+    fn record(&self, span: Span) {
+        if let Ok(mut spans) = self.spans.lock() {
+            spans.push(span);
+        }
+    }
+}
+
+// Request ids passed by clients or proxies are used if they are reasonably
+// short and can't be used to inject anything into the logs.
+fn is_valid_request_id(request_id: &str) -> bool {
+    !request_id.is_empty()
+        && request_id.len() <= MAX_REQUEST_ID_LENGTH
+        && request_id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.')
+}
+
+// Parses the trace id and parent span id of a W3C traceparent header,
+// e.g. 00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01.
+fn parse_traceparent(traceparent: &str) -> Option<(u128, u64)> {
+    let parts: Vec<&str> = traceparent.trim().split('-').collect();
+    match parts.as_slice() {
+        [_version, trace_id, parent_id, _flags]
+            if trace_id.len() == 32 && parent_id.len() == 16 =>
+        {
+            let trace_id = u128::from_str_radix(trace_id, 16).ok()?;
+            let parent_id = u64::from_str_radix(parent_id, 16).ok()?;
+            if trace_id == 0 || parent_id == 0 {
+                return None;
+            }
+            Some((trace_id, parent_id))
+        }
+        _ => None,
+    }
+}
+
+// The id of the request currently being answered, if any.
+pub fn request_id() -> Option<String> {
+    CURRENT.try_with(|trace| trace.request_id.clone()).ok()
+}
+
+// Records the future as a span of the current request, if any, e.g. a lock
+// wait or a storage query.
+pub async fn span<F: Future>(name: &str, future: F) -> F::Output {
+    if CURRENT.try_with(|_| ()).is_err() {
+        return future.await;
+    }
+    let start = SystemTime::now();
+    let started = Instant::now();
+    let output = future.await;
+    let span = Span {
+        name: name.to_string(),
+        span_id: random_u64(),
+        start,
+        duration: started.elapsed(),
+    };
+    let _ = CURRENT.try_with(|trace| trace.record(span));
+    output
+}
+
+// A finished request as exported via OTLP.
+#[derive(Debug)]
+pub struct FinishedTrace {
+    trace: Arc<RequestTrace>,
+    duration: Duration,
+    status: u16,
+}
+
+// Answers the request with the service while tracing it: logs and spans
+// recorded while answering it are attributed to the request, and the
+// request id is returned in the X-Request-Id header.
+pub async fn handle<S>(
+    mut service: S,
+    request: Request<Body>,
+    exporter: Option<mpsc::UnboundedSender<FinishedTrace>>,
+) -> Result<Response<Body>, Infallible>
+where
+    S: Service<Request<Body>, Response = Response<Body>, Error = Infallible>,
+{
+    let header = |name| {
+        request
+            .headers()
+            .get(name)
+            .and_then(|value: &HeaderValue| value.to_str().ok())
+    };
+    let trace = Arc::new(RequestTrace::new(
+        format!("{} {}", request.method(), request.uri().path()),
+        header(REQUEST_ID_HEADER),
+        header(TRACEPARENT_HEADER),
+    ));
+    let started = Instant::now();
+    let mut response = CURRENT.scope(trace.clone(), service.call(request)).await?;
+    let duration = started.elapsed();
+    let status = response.status().as_u16();
+
+    if let Ok(request_id) = HeaderValue::from_str(&trace.request_id) {
+        response.headers_mut().insert(REQUEST_ID_HEADER, request_id);
+    }
+    if duration >= SLOW_REQUEST {
+        let mut spans = trace.spans();
+        spans.sort_by_key(|span| std::cmp::Reverse(span.duration));
+        let slowest: Vec<String> = spans
+            .iter()
+            .take(3)
+            .map(|span| format!("{} {}ms", span.name, span.duration.as_millis()))
+            .collect();
+        warn!(
+            "[{}] Slow request {} ({}) took {}ms: {}",
+            trace.request_id,
+            trace.name,
+            status,
+            duration.as_millis(),
+            slowest.join(", ")
+        );
+    } else {
+        debug!(
+            "[{}] {} ({}) took {}ms",
+            trace.request_id,
+            trace.name,
+            status,
+            duration.as_millis()
+        );
+    }
+    if let Some(exporter) = exporter {
+        let _ = exporter.send(FinishedTrace {
+            trace,
+            duration,
+            status,
+        });
+    }
+    Ok(response)
+}
+
+fn attribute(key: &str, value: Value) -> Value {
+    json!({"key": key, "value": value})
+}
+
+fn otlp_span(
+    trace_id: u128,
+    parent_span_id: Option<u64>,
+    span: &Span,
+    kind: u8,
+    attributes: Vec<Value>,
+) -> Value {
+    let start = unix_nanos(span.start);
+    json!({
+        "traceId": format!("{:032x}", trace_id),
+        "spanId": format!("{:016x}", span.span_id),
+        "parentSpanId": parent_span_id.map(|id| format!("{:016x}", id)).unwrap_or_default(),
+        "name": span.name,
+        "kind": kind,
+        "startTimeUnixNano": start.to_string(),
+        "endTimeUnixNano": (start + span.duration.as_nanos()).to_string(),
+        "attributes": attributes,
+    })
+}
+
+// The OTLP/HTTP JSON request body of the finished requests and their spans.
+pub fn otlp_request(service_name: &str, finished: &[FinishedTrace]) -> Value {
+    let mut spans: Vec<Value> = vec![];
+    for f in finished.iter() {
+        let trace = &f.trace;
+        let request_span = Span {
+            name: trace.name.clone(),
+            span_id: trace.span_id,
+            start: trace.start,
+            duration: f.duration,
+        };
+        spans.push(otlp_span(
+            trace.trace_id,
+            trace.parent_span_id,
+            &request_span,
+            SPAN_KIND_SERVER,
+            vec![
+                attribute("http.request.id", json!({"stringValue": trace.request_id})),
+                attribute(
+                    "http.response.status_code",
+                    json!({"intValue": f.status.to_string()}),
+                ),
+            ],
+        ));
+        for span in trace.spans() {
+            spans.push(otlp_span(
+                trace.trace_id,
+                Some(trace.span_id),
+                &span,
+                SPAN_KIND_INTERNAL,
+                vec![],
+            ));
+        }
+    }
+    json!({
+        "resourceSpans": [{
+            "resource": {
+                "attributes": [attribute("service.name", json!({"stringValue": service_name}))],
+            },
+            "scopeSpans": [{
+                "scope": {"name": "fork-observer"},
+                "spans": spans,
+            }],
+        }],
+    })
+}
+
+// Exports the finished requests in batches to an OpenTelemetry collector
+// via OTLP/HTTP with JSON encoding.
+pub async fn export(tracing: Tracing, mut finished_rx: mpsc::UnboundedReceiver<FinishedTrace>) {
+    let url = format!("{}/v1/traces", tracing.otlp_endpoint.trim_end_matches('/'));
+    let mut interval = tokio::time::interval(EXPORT_INTERVAL);
+    let mut batch: Vec<FinishedTrace> = vec![];
+    loop {
+        interval.tick().await;
+        while let Ok(finished) = finished_rx.try_recv() {
+            batch.push(finished);
+        }
+        if batch.is_empty() {
+            continue;
+        }
+        let body = otlp_request(&tracing.service_name, &batch).to_string();
+        batch.clear();
+        let url = url.clone();
+        let result = task::spawn_blocking(move || {
+            minreq::post(url)
+                .with_header("Content-Type", "application/json")
+                .with_body(body)
+                .with_timeout(EXPORT_TIMEOUT)
+                .send()
+        })
+        .await;
+        match result {
+            Ok(Ok(res)) if (200..300).contains(&res.status_code) => (),
+            Ok(Ok(res)) => warn!(
+                "Could not export traces to {}: HTTP {} {}",
+                tracing.otlp_endpoint, res.status_code, res.reason_phrase
+            ),
+            Ok(Err(e)) => warn!(
+                "Could not export traces to {}: {}",
+                tracing.otlp_endpoint, e
+            ),
+            Err(e) => warn!("Could not export traces: {}", e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_request_id() {
+        let trace = RequestTrace::new("GET /".to_string(), Some("abc-123"), None);
+        assert_eq!(trace.request_id, "abc-123");
+        let trace = RequestTrace::new("GET /".to_string(), Some("abc\n123"), None);
+        assert_eq!(trace.request_id.len(), 16);
+        assert_ne!(
+            RequestTrace::new("GET /".to_string(), None, None).request_id,
+            trace.request_id
+        );
+    }
+
+    #[test]
+    fn test_parse_traceparent() {
+        assert_eq!(
+            parse_traceparent("00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01"),
+            Some((0x4bf92f3577b34da6a3ce929d0e0e4736, 0x00f067aa0ba902b7))
+        );
+        assert_eq!(
+            parse_traceparent("00-00000000000000000000000000000000-00f067aa0ba902b7-01"),
+            None
+        );
+        assert_eq!(parse_traceparent("00-4bf92f35-00f067aa0ba902b7-01"), None);
+    }
+
+    #[tokio::test]
+    async fn test_span() {
+        // Outside of a request, nothing is recorded.
+        assert_eq!(span("outside", async { 1 }).await, 1);
+
+        let trace = Arc::new(RequestTrace::new(
+            "GET /".to_string(),
+            None,
+            Some("00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01"),
+        ));
+        let request_id = CURRENT
+            .scope(trace.clone(), async {
+                span("db lock", async {}).await;
+                request_id()
+            })
+            .await;
+        assert_eq!(request_id, Some(trace.request_id.clone()));
+        assert_eq!(trace.spans().len(), 1);
+
+        let request = otlp_request(
+            "fork-observer",
+            &[FinishedTrace {
+                trace: trace.clone(),
+                duration: Duration::from_millis(5),
+                status: 200,
+            }],
+        );
+        let spans = &request["resourceSpans"][0]["scopeSpans"][0]["spans"];
+        assert_eq!(spans[0]["traceId"], "4bf92f3577b34da6a3ce929d0e0e4736");
+        assert_eq!(spans[0]["parentSpanId"], "00f067aa0ba902b7");
+        assert_eq!(spans[1]["name"], "db lock");
+        assert_eq!(spans[1]["parentSpanId"], spans[0]["spanId"]);
+    }
+}
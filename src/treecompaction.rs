@@ -0,0 +1,116 @@
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use bitcoincore_rpc::bitcoin::BlockHash;
+use log::info;
+use tokio::sync::Mutex;
+use tokio::time::Duration;
+
+use crate::config::TreeCompaction;
+use crate::domain::{ChainTip, ChainTipStatus, Tree};
+use crate::headertree;
+
+pub const COMPACTION_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+// The height below which the linear runs of headers are collapsed, by
+// header store. It only ever rises: headers below it that a node reports
+// aren't known anymore and aren't fetched again.
+pub type CompactionHorizons = Arc<Mutex<BTreeMap<u32, u64>>>;
+
+pub async fn horizon(horizons: &CompactionHorizons, header_store: u32) -> u64 {
+    horizons
+        .lock()
+        .await
+        .get(&header_store)
+        .copied()
+        .unwrap_or_default()
+}
+
+// Collapses the linear runs of headers of the header store more than
+// keep_depth blocks below the lowest active tip of the nodes. Returns the
+// number of collapsed headers. Nothing is collapsed before the nodes
+// reported an active tip.
+pub async fn compact(
+    header_store: u32,
+    compaction: &TreeCompaction,
+    tree: &Tree,
+    active_tips: &[(BlockHash, u64)],
+    horizons: &CompactionHorizons,
+) -> usize {
+    let lowest_tip = match active_tips.iter().map(|(_, height)| *height).min() {
+        Some(height) => height,
+        None => return 0,
+    };
+    let horizon = {
+        let mut horizons_locked = horizons.lock().await;
+        let horizon = horizons_locked.entry(header_store).or_default();
+        *horizon = (*horizon).max(lowest_tip.saturating_sub(compaction.keep_depth));
+        *horizon
+    };
+    let collapsed = headertree::compact(tree, horizon).await;
+    if collapsed > 0 {
+        info!(
+            "Collapsed {} headers below height {} in header store {}",
+            collapsed, horizon, header_store
+        );
+    }
+    collapsed
+}
+
+// The tips to fetch the headers of. None if the node's active tip is below
+// the horizon, e.g. while it's reindexing, as its headers were collapsed.
+// The non-active tips forking off below the horizon are dropped, their
+// branches couldn't be connected to the collapsed headers.
+pub async fn uncompacted(
+    horizons: &CompactionHorizons,
+    header_store: u32,
+    tips: &[ChainTip],
+) -> Option<Vec<ChainTip>> {
+    let horizon = horizon(horizons, header_store).await;
+    if tips
+        .iter()
+        .any(|tip| tip.status == ChainTipStatus::Active && tip.height < horizon)
+    {
+        return None;
+    }
+    Some(
+        tips.iter()
+            .filter(|tip| {
+                tip.status == ChainTipStatus::Active
+                    || tip.height.saturating_sub(tip.branchlen as u64) >= horizon
+            })
+            .cloned()
+            .collect(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_uncompacted() {
+        let tip = |height: u64, branchlen: usize, status: ChainTipStatus| ChainTip {
+            height,
+            hash: String::new(),
+            branchlen,
+            status,
+        };
+        let tips = vec![
+            tip(1000, 0, ChainTipStatus::Active),
+            tip(101, 1, ChainTipStatus::ValidFork),
+            tip(901, 1, ChainTipStatus::ValidFork),
+        ];
+        let horizons: CompactionHorizons = Arc::new(Mutex::new(BTreeMap::new()));
+        assert_eq!(uncompacted(&horizons, 1, &tips).await, Some(tips.clone()));
+        horizons.lock().await.insert(1, 800);
+        assert_eq!(
+            uncompacted(&horizons, 1, &tips).await,
+            Some(vec![tips[0].clone(), tips[2].clone()])
+        );
+        // a node behind the horizon
+        let behind = vec![tip(700, 0, ChainTipStatus::Active)];
+        assert_eq!(uncompacted(&horizons, 1, &behind).await, None);
+        assert_eq!(uncompacted(&horizons, 2, &behind).await, Some(behind));
+    }
+}
@@ -0,0 +1,574 @@
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use bitcoincore_rpc::bitcoin::BlockHash;
+use petgraph::graph::NodeIndex;
+use petgraph::visit::EdgeRef;
+use warp::Reply;
+
+use crate::config::Network;
+use crate::domain::{Tree, TreeInfo};
+use crate::error::ExportError;
+use crate::rss::response_unknown_network;
+use crate::types::{
+    Caches, HeaderInfoJson, NetworkJson, TipInfoJson, TreeGraphInfoJson, TreeGraphJson,
+    TreeGraphLinkJson, TreeGraphNodeJson,
+};
+
+pub const DOT_CONTENT_TYPE: &str = "text/vnd.graphviz";
+pub const GRAPHML_CONTENT_TYPE: &str = "application/graphml+xml";
+const STATUS_STALE: &str = "stale";
+
+// The tips of the nodes by node name.
+pub type NodeTips = Vec<(String, Vec<TipInfoJson>)>;
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum TreeFormat {
+    // The header tree of the fork view.
+    Dot,
+    // The whole header tree.
+    GraphMl,
+    Json,
+}
+
+// The arguments of the export-tree command:
+// fork-observer export-tree --network <id> [--format dot|graphml|json] [--out <file>]
+#[derive(Debug, PartialEq, Eq)]
+pub struct ExportTreeArgs {
+    pub network: u32,
+    pub format: TreeFormat,
+    pub out: Option<PathBuf>,
+}
+
+pub fn parse_args(args: &[String]) -> Result<ExportTreeArgs, ExportError> {
+    let mut network: Option<u32> = None;
+    let mut format = TreeFormat::Dot;
+    let mut out: Option<PathBuf> = None;
+
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        let value = match iter.next() {
+            Some(value) => value,
+            None => {
+                return Err(ExportError::InvalidArguments(format!(
+                    "missing value for '{}'",
+                    arg
+                )))
+            }
+        };
+        let invalid =
+            || ExportError::InvalidArguments(format!("invalid value '{}' for '{}'", value, arg));
+        match arg.as_str() {
+            "--network" => network = Some(value.parse().map_err(|_| invalid())?),
+            "--format" => {
+                format = match value.as_str() {
+                    "dot" => TreeFormat::Dot,
+                    "graphml" => TreeFormat::GraphMl,
+                    "json" => TreeFormat::Json,
+                    _ => return Err(invalid()),
+                }
+            }
+            "--out" => out = Some(PathBuf::from(value)),
+            _ => {
+                return Err(ExportError::InvalidArguments(format!(
+                    "unknown argument '{}'",
+                    arg
+                )))
+            }
+        }
+    }
+
+    Ok(ExportTreeArgs {
+        network: network
+            .ok_or_else(|| ExportError::InvalidArguments("'--network' is required".to_string()))?,
+        format,
+        out,
+    })
+}
+
+// Quotes a DOT ID.
+fn quote(id: &str) -> String {
+    format!("\"{}\"", id.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+// A label with one line per entry.
+fn label(lines: &[String]) -> String {
+    let lines: Vec<String> = lines
+        .iter()
+        .map(|line| line.replace('\\', "\\\\").replace('"', "\\\""))
+        .collect();
+    format!("\"{}\"", lines.join("\\n"))
+}
+
+// The end of the hash, the start is mostly zeros.
+fn short_hash(hash: &str) -> String {
+    format!("..{}", &hash[hash.len().saturating_sub(12)..])
+}
+
+// Invalid tips stand out the most, as a node rejecting a block another node
+// accepted is a consensus split.
+fn fill_color(statuses: &[&str]) -> &'static str {
+    if statuses.contains(&"invalid") {
+        "lightcoral"
+    } else if statuses.contains(&"active") {
+        "palegreen"
+    } else {
+        "lightgoldenrod"
+    }
+}
+
+// Renders the header tree of the fork view as a Graphviz digraph. Headers
+// are labeled with their height, hash and miner, fork points with a double
+// border and the tips with the nodes and their status. Headers left out
+// between two headers are shown as a dashed edge.
+pub fn dot(network_name: &str, headers: &[HeaderInfoJson], node_tips: &NodeTips) -> String {
+    let mut headers: Vec<&HeaderInfoJson> = headers.iter().collect();
+    headers.sort_by(|a, b| (a.height, &a.hash).cmp(&(b.height, &b.hash)));
+    let by_id: HashMap<usize, &HeaderInfoJson> = headers.iter().map(|h| (h.id, *h)).collect();
+    let mut children: HashMap<usize, usize> = HashMap::new();
+    for header in headers.iter().filter(|h| by_id.contains_key(&h.prev_id)) {
+        *children.entry(header.prev_id).or_default() += 1;
+    }
+    let mut tips: HashMap<&str, Vec<(&str, &str)>> = HashMap::new();
+    for (name, node_tips) in node_tips.iter() {
+        for tip in node_tips.iter() {
+            tips.entry(tip.hash.as_str())
+                .or_default()
+                .push((name.as_str(), tip.status.as_str()));
+        }
+    }
+
+    let mut dot = format!("digraph {} {{\n", quote(network_name));
+    dot.push_str("    rankdir=LR;\n");
+    dot.push_str("    node [shape=box, style=filled, fillcolor=white, fontname=monospace];\n");
+    for header in headers.iter() {
+        let mut lines = vec![header.height.to_string(), short_hash(&header.hash)];
+        if !header.miner.is_empty() {
+            lines.push(header.miner.clone());
+        }
+        let mut attributes = vec![format!("tooltip={}", quote(&header.hash))];
+        if children.get(&header.id).copied().unwrap_or_default() > 1 {
+            lines.push("fork point".to_string());
+            attributes.push("peripheries=2".to_string());
+        }
+        if let Some(tips) = tips.get(header.hash.as_str()) {
+            for (name, status) in tips.iter() {
+                lines.push(format!("{}: {}", name, status));
+            }
+            let statuses: Vec<&str> = tips.iter().map(|(_, status)| *status).collect();
+            attributes.push(format!("fillcolor={}", fill_color(&statuses)));
+        }
+        dot.push_str(&format!(
+            "    {} [label={}, {}];\n",
+            quote(&header.hash),
+            label(&lines),
+            attributes.join(", ")
+        ));
+    }
+    for header in headers.iter() {
+        let prev = match by_id.get(&header.prev_id) {
+            Some(prev) => prev,
+            None => continue,
+        };
+        let left_out = header.height.saturating_sub(prev.height + 1);
+        let attributes = match left_out {
+            0 => String::new(),
+            1 => " [style=dashed, label=\"1 block\"]".to_string(),
+            _ => format!(" [style=dashed, label=\"{} blocks\"]", left_out),
+        };
+        dot.push_str(&format!(
+            "    {} -> {}{};\n",
+            quote(&prev.hash),
+            quote(&header.hash),
+            attributes
+        ));
+    }
+    dot.push_str("}\n");
+    dot
+}
+
+pub async fn dot_response(
+    network_id: u32,
+    caches: Caches,
+    network_infos: Vec<NetworkJson>,
+) -> Result<Box<dyn Reply>, Infallible> {
+    let network_name = match network_infos.iter().find(|n| n.id == network_id) {
+        Some(network) => network.name.clone(),
+        None => return Ok(Box::new(response_unknown_network(network_infos))),
+    };
+    let caches_locked = caches.lock().await;
+    let (headers, node_tips): (&[HeaderInfoJson], NodeTips) = match caches_locked.get(&network_id) {
+        Some(cache) => (
+            &cache.header_infos_json,
+            cache
+                .node_data
+                .values()
+                .map(|node| (node.name.clone(), node.tips.clone()))
+                .collect(),
+        ),
+        None => (&[], vec![]),
+    };
+    Ok(Box::new(warp::reply::with_header(
+        dot(&network_name, headers, &node_tips),
+        "content-type",
+        DOT_CONTENT_TYPE,
+    )))
+}
+
+// The status of the headers on the chains of the tips. The active chains
+// are marked first, then the branches of the other tips down to the chain
+// they fork off, the invalid ones first.
+fn statuses(tree: &TreeInfo, node_tips: &NodeTips) -> HashMap<NodeIndex, String> {
+    let (graph, index, _) = tree;
+    let mut tips: Vec<&TipInfoJson> = node_tips.iter().flat_map(|(_, tips)| tips.iter()).collect();
+    tips.sort_by_key(|tip| match tip.status.as_str() {
+        "active" => 0,
+        "invalid" => 1,
+        _ => 2,
+    });
+    let mut statuses: HashMap<NodeIndex, String> = HashMap::new();
+    for tip in tips {
+        let mut next = BlockHash::from_str(&tip.hash)
+            .ok()
+            .and_then(|hash| index.get(&hash).copied());
+        while let Some(idx) = next {
+            if statuses.contains_key(&idx) {
+                break;
+            }
+            statuses.insert(idx, tip.status.clone());
+            next = graph
+                .neighbors_directed(idx, petgraph::Direction::Incoming)
+                .next();
+        }
+    }
+    statuses
+}
+
+// The whole header tree with the status of each header, ordered by height.
+pub fn graph(network_name: &str, tree: &TreeInfo, node_tips: &NodeTips) -> TreeGraphJson {
+    let statuses = statuses(tree, node_tips);
+    let graph = &tree.0;
+    let mut order: Vec<(u64, BlockHash, NodeIndex)> = graph
+        .node_indices()
+        .map(|idx| (graph[idx].height, graph[idx].block_hash(), idx))
+        .collect();
+    order.sort();
+
+    let mut nodes: Vec<TreeGraphNodeJson> = vec![];
+    let mut links: Vec<TreeGraphLinkJson> = vec![];
+    for (height, hash, idx) in order.iter() {
+        let header_info = &graph[*idx];
+        nodes.push(TreeGraphNodeJson {
+            id: hash.to_string(),
+            height: *height,
+            version: header_info.header.version.to_consensus() as u32,
+            prev_blockhash: header_info.header.prev_blockhash.to_string(),
+            time: header_info.header.time,
+            bits: header_info.header.bits.to_consensus(),
+            nonce: header_info.header.nonce,
+            miner: header_info.miner.clone(),
+            status: statuses
+                .get(idx)
+                .cloned()
+                .unwrap_or_else(|| STATUS_STALE.to_string()),
+        });
+        for edge in graph.edges_directed(*idx, petgraph::Direction::Incoming) {
+            links.push(TreeGraphLinkJson {
+                source: graph[edge.source()].block_hash().to_string(),
+                target: hash.to_string(),
+                collapsed: *edge.weight(),
+            });
+        }
+    }
+    TreeGraphJson {
+        directed: true,
+        multigraph: false,
+        graph: TreeGraphInfoJson {
+            name: network_name.to_string(),
+        },
+        nodes,
+        links,
+    }
+}
+
+fn xml_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+// Renders the graph as GraphML with the attributes of the JSON graph.
+pub fn graphml(graph: &TreeGraphJson) -> String {
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    xml.push_str("<graphml xmlns=\"http://graphml.graphdrawing.org/xmlns\">\n");
+    for (name, domain, kind) in [
+        ("height", "node", "long"),
+        ("version", "node", "long"),
+        ("prev_blockhash", "node", "string"),
+        ("time", "node", "long"),
+        ("bits", "node", "long"),
+        ("nonce", "node", "long"),
+        ("miner", "node", "string"),
+        ("status", "node", "string"),
+        ("collapsed", "edge", "long"),
+    ] {
+        xml.push_str(&format!(
+            "  <key id=\"{0}\" for=\"{1}\" attr.name=\"{0}\" attr.type=\"{2}\"/>\n",
+            name, domain, kind
+        ));
+    }
+    xml.push_str(&format!(
+        "  <graph id=\"{}\" edgedefault=\"directed\">\n",
+        xml_escape(&graph.graph.name)
+    ));
+    for node in graph.nodes.iter() {
+        xml.push_str(&format!("    <node id=\"{}\">\n", node.id));
+        for (key, value) in [
+            ("height", node.height.to_string()),
+            ("version", node.version.to_string()),
+            ("prev_blockhash", node.prev_blockhash.clone()),
+            ("time", node.time.to_string()),
+            ("bits", node.bits.to_string()),
+            ("nonce", node.nonce.to_string()),
+            ("miner", xml_escape(&node.miner)),
+            ("status", node.status.clone()),
+        ] {
+            xml.push_str(&format!("      <data key=\"{}\">{}</data>\n", key, value));
+        }
+        xml.push_str("    </node>\n");
+    }
+    for link in graph.links.iter() {
+        xml.push_str(&format!(
+            "    <edge source=\"{}\" target=\"{}\"><data key=\"collapsed\">{}</data></edge>\n",
+            link.source, link.target, link.collapsed
+        ));
+    }
+    xml.push_str("  </graph>\n</graphml>\n");
+    xml
+}
+
+// The graph of the header tree of the network with the tips of its nodes.
+async fn network_graph(
+    network: &Network,
+    trees: &HashMap<u32, Tree>,
+    caches: &Caches,
+) -> Option<TreeGraphJson> {
+    let node_tips: NodeTips = caches
+        .lock()
+        .await
+        .get(&network.id)?
+        .node_data
+        .values()
+        .map(|node| (node.name.clone(), node.tips.clone()))
+        .collect();
+    let tree_locked = trees.get(&network.header_store)?.lock().await;
+    Some(graph(&network.name, &tree_locked, &node_tips))
+}
+
+pub async fn graphml_response(
+    network_id: u32,
+    networks: Vec<Network>,
+    trees: HashMap<u32, Tree>,
+    caches: Caches,
+    network_infos: Vec<NetworkJson>,
+) -> Result<Box<dyn Reply>, Infallible> {
+    let network = match networks.iter().find(|n| n.id == network_id) {
+        Some(network) => network,
+        None => return Ok(Box::new(response_unknown_network(network_infos))),
+    };
+    match network_graph(network, &trees, &caches).await {
+        Some(graph) => Ok(Box::new(warp::reply::with_header(
+            graphml(&graph),
+            "content-type",
+            GRAPHML_CONTENT_TYPE,
+        ))),
+        None => Ok(Box::new(warp::http::StatusCode::NOT_FOUND)),
+    }
+}
+
+// Served without the schema_version, so networkx can read it as is.
+pub async fn json_graph_response(
+    network_id: u32,
+    networks: Vec<Network>,
+    trees: HashMap<u32, Tree>,
+    caches: Caches,
+    network_infos: Vec<NetworkJson>,
+) -> Result<Box<dyn Reply>, Infallible> {
+    let network = match networks.iter().find(|n| n.id == network_id) {
+        Some(network) => network,
+        None => return Ok(Box::new(response_unknown_network(network_infos))),
+    };
+    match network_graph(network, &trees, &caches).await {
+        Some(graph) => Ok(Box::new(warp::reply::json(&graph))),
+        None => Ok(Box::new(warp::http::StatusCode::NOT_FOUND)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::HeaderInfo;
+    use crate::testutil::child;
+    use petgraph::graph::DiGraph;
+
+    fn header(id: usize, prev_id: usize, height: u64, hash: &str) -> HeaderInfoJson {
+        HeaderInfoJson {
+            id,
+            prev_id,
+            height,
+            hash: hash.to_string(),
+            version: 0,
+            prev_blockhash: String::new(),
+            merkle_root: String::new(),
+            time: 0,
+            bits: 0,
+            nonce: 0,
+            miner: String::new(),
+            own: false,
+            computed: Default::default(),
+            source: None,
+            pow_verified: None,
+            fully_verified: false,
+            signblock: None,
+            timestamp_flags: vec![],
+            first_reported_by: None,
+            first_reported: None,
+            node_statuses: Default::default(),
+            rejection_reason: None,
+        }
+    }
+
+    #[test]
+    fn test_parse_args() {
+        let args: Vec<String> = ["--network", "1", "--out", "tree.dot"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        assert_eq!(
+            parse_args(&args).unwrap(),
+            ExportTreeArgs {
+                network: 1,
+                format: TreeFormat::Dot,
+                out: Some(PathBuf::from("tree.dot")),
+            }
+        );
+        let args: Vec<String> = ["--format", "graphml", "--network", "1"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        assert_eq!(parse_args(&args).unwrap().format, TreeFormat::GraphMl);
+        for invalid in [
+            vec!["--out", "tree.dot"],
+            vec!["--network"],
+            vec!["--network", "1", "--format", "svg"],
+            vec!["--foo", "1"],
+        ] {
+            let args: Vec<String> = invalid.iter().map(|s| s.to_string()).collect();
+            assert!(parse_args(&args).is_err(), "{:?}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_graph() {
+        // a0 -> a1 -> a2 with the stale b1 and the invalid c1, and a3
+        // after a collapsed header
+        let a0 = child(None, 0, 0);
+        let a1 = child(Some(&a0), 0, 1);
+        let a2 = child(Some(&a1), 0, 2);
+        let b1 = child(Some(&a0), 0, 3);
+        let c1 = child(Some(&a0), 0, 4);
+        let mut a3 = child(Some(&a2), 0, 5);
+        a3.height = 4;
+        let mut tree = DiGraph::new();
+        let mut index = HashMap::new();
+        for h in [&a0, &a1, &a2, &b1, &c1, &a3] {
+            index.insert(h.block_hash(), tree.add_node(h.clone()));
+        }
+        for (prev, h, collapsed) in [
+            (&a0, &a1, 0),
+            (&a1, &a2, 0),
+            (&a0, &b1, 0),
+            (&a0, &c1, 0),
+            (&a2, &a3, 1),
+        ] {
+            tree.add_edge(index[&prev.block_hash()], index[&h.block_hash()], collapsed);
+        }
+        let tree: TreeInfo = (tree, index, HashMap::new());
+        let tip = |h: &HeaderInfo, status: &str| TipInfoJson {
+            hash: h.block_hash().to_string(),
+            status: status.to_string(),
+            height: h.height,
+            chainwork: None,
+        };
+        let node_tips: NodeTips = vec![
+            (
+                "A".to_string(),
+                vec![tip(&c1, "invalid"), tip(&a3, "active")],
+            ),
+            ("B".to_string(), vec![tip(&a2, "active")]),
+        ];
+
+        let graph = graph("Regtest", &tree, &node_tips);
+        let status = |h: &HeaderInfo| {
+            graph
+                .nodes
+                .iter()
+                .find(|n| n.id == h.block_hash().to_string())
+                .map(|n| n.status.clone())
+                .unwrap()
+        };
+        assert_eq!(status(&a0), "active");
+        assert_eq!(status(&a3), "active");
+        assert_eq!(status(&b1), "stale");
+        assert_eq!(status(&c1), "invalid");
+        assert_eq!(graph.nodes.len(), 6);
+        assert_eq!(graph.nodes[0].id, a0.block_hash().to_string());
+        assert_eq!(graph.links.len(), 5);
+        assert!(graph.links.contains(&TreeGraphLinkJson {
+            source: a2.block_hash().to_string(),
+            target: a3.block_hash().to_string(),
+            collapsed: 1,
+        }));
+
+        let xml = graphml(&graph);
+        assert_eq!(xml.matches("<node id=").count(), 6);
+        assert_eq!(xml.matches("<edge source=").count(), 5);
+        assert!(xml.contains("<graph id=\"Regtest\" edgedefault=\"directed\">"));
+    }
+
+    #[test]
+    fn test_dot() {
+        let mut a0 = header(0, usize::MAX, 100, "a0");
+        a0.miner = "Pool \"A\"".to_string();
+        let headers = vec![header(2, 0, 104, "b4"), a0, header(1, 0, 101, "a1")];
+        let tip = |hash: &str, status: &str| TipInfoJson {
+            hash: hash.to_string(),
+            status: status.to_string(),
+            height: 0,
+            chainwork: None,
+        };
+        let node_tips: NodeTips = vec![
+            ("node A".to_string(), vec![tip("a1", "active")]),
+            (
+                "node B".to_string(),
+                vec![tip("a1", "invalid"), tip("b4", "active")],
+            ),
+        ];
+        assert_eq!(
+            dot("Regtest", &headers, &node_tips),
+            r#"digraph "Regtest" {
+    rankdir=LR;
+    node [shape=box, style=filled, fillcolor=white, fontname=monospace];
+    "a0" [label="100\n..a0\nPool \"A\"\nfork point", tooltip="a0", peripheries=2];
+    "a1" [label="101\n..a1\nnode A: active\nnode B: invalid", tooltip="a1", fillcolor=lightcoral];
+    "b4" [label="104\n..b4\nnode B: active", tooltip="b4", fillcolor=palegreen];
+    "a0" -> "a1";
+    "a0" -> "b4" [style=dashed, label="3 blocks"];
+}
+"#
+        );
+    }
+}
@@ -1,22 +1,44 @@
 use std::collections::{BTreeMap, HashMap};
-use std::fmt;
-use std::str::FromStr;
 use std::sync::Arc;
 use std::time::SystemTime;
 
-use crate::config::Network;
-use crate::node::NodeInfo;
+use crate::chainparams;
+use crate::config::{ChainParams, Network};
+use crate::domain::{
+    ChainTip, ChainTipStatus, Event, Fork, HeaderInfo, Incident, IncidentRecording, NodeInfo,
+    NodeResources, QueuedNotification, Reorg, RetiredNode, TipObservation,
+};
+use crate::elements::{ElementsHeader, SignBlock};
+use crate::software::Software;
+use crate::suppressed::SuppressedState;
 
-use bitcoincore_rpc::bitcoin::blockdata::block::Header;
 use bitcoincore_rpc::bitcoin::BlockHash;
-use bitcoincore_rpc::json::{GetChainTipsResultStatus, GetChainTipsResultTip};
 use log::warn;
-use petgraph::graph::DiGraph;
-use petgraph::graph::NodeIndex;
 use rusqlite::Connection;
 use serde::{Deserialize, Serialize};
 use tokio::sync::Mutex;
 
+/// Version of the JSON representations served by the API and sent to SSE
+/// subscribers and notification sinks. Bumped on breaking changes to them.
+pub const JSON_SCHEMA_VERSION: u32 = 1;
+
+/// Wraps a JSON representation and adds the schema_version field to it.
+#[derive(Serialize)]
+pub struct Versioned<T: Serialize> {
+    pub schema_version: u32,
+    #[serde(flatten)]
+    pub data: T,
+}
+
+impl<T: Serialize> Versioned<T> {
+    pub fn new(data: T) -> Self {
+        Versioned {
+            schema_version: JSON_SCHEMA_VERSION,
+            data,
+        }
+    }
+}
+
 #[derive(Clone)]
 pub struct Cache {
     pub header_infos_json: Vec<HeaderInfoJson>,
@@ -24,29 +46,17 @@ pub struct Cache {
     pub forks: Vec<Fork>,
     /// Since strip_tree and identifying miners runs in parallel,
     /// the strip_tree result might not contain a miner yet. Keeping
-    /// recent miners here and use + manage them when updating the cache.
-    pub recent_miners: Vec<(String, String)>,
+    /// recent miners (hash, miner, own) here and use + manage them when
+    /// updating the cache.
+    pub recent_miners: Vec<(String, String, bool)>,
+    /// The latest getchaintips result of each node as received from the node.
+    pub raw_tips: BTreeMap<u32, RawChainTipsJson>,
 }
 
 pub type NodeData = BTreeMap<u32, NodeDataJson>;
 pub type Caches = Arc<Mutex<BTreeMap<u32, Cache>>>;
-pub type TreeInfo = (DiGraph<HeaderInfo, bool>, HashMap<BlockHash, NodeIndex>);
-pub type Tree = Arc<Mutex<TreeInfo>>;
 pub type Db = Arc<Mutex<Connection>>;
 
-#[derive(Debug, Eq, PartialEq, Clone)]
-pub struct HeaderInfo {
-    pub height: u64,
-    pub header: Header,
-    pub miner: String,
-}
-
-impl HeaderInfo {
-    pub fn update_miner(&mut self, miner: String) {
-        self.miner = miner;
-    }
-}
-
 #[derive(Serialize, Clone)]
 pub struct NetworkJson {
     pub id: u32,
@@ -82,15 +92,90 @@ pub struct HeaderInfoJson {
     pub bits: u32,
     pub nonce: u32,
     pub miner: String,
+    /// If the block was mined by the instance operator.
+    pub own: bool,
+    /// Operator-defined computed fields.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub computed: BTreeMap<String, serde_json::Value>,
+    /// How the header was first received, see HeaderSource. Added in API v2.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
+    /// If the header hash meets the target encoded in its bits and the pow
+    /// limit of the chain. None if the proof of work isn't checked. Added in
+    /// API v2.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pow_verified: Option<bool>,
+    /// If the header was received directly from a node of this instance
+    /// and its proof of work is verified.
+    #[serde(skip)]
+    pub fully_verified: bool,
+    /// How the federation signed the block, for Elements headers. Added in
+    /// API v2.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub signblock: Option<SignBlockJson>,
+    /// The timestamp anomalies of the header, future or past, see
+    /// timestamps. Added in API v2.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub timestamp_flags: Vec<String>,
+    /// The id of the node that first reported the block and when, see
+    /// sightings. Added in API v2.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub first_reported_by: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub first_reported: Option<u64>,
+    /// The status of the block for each node by node id, derived from the
+    /// node's chain tips. Nodes not knowing of the block are missing. Added
+    /// in API v2.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub node_statuses: BTreeMap<u32, String>,
+    /// Why a node rejected the block as invalid, as Bitcoin Core reject
+    /// reason, see rejection. Added in API v2.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rejection_reason: Option<String>,
+}
+
+#[derive(Debug, Eq, PartialEq, Clone, Serialize)]
+pub struct SignBlockJson {
+    pub dynafed: bool,
+    /// Hex encoded script the signatures satisfy: the challenge or, with
+    /// dynafed, the current signblockscript.
+    pub challenge: String,
+    /// Hex encoded solution or, with dynafed, the signblock witness stack.
+    pub solution: Vec<String>,
+}
+
+impl From<&ElementsHeader> for SignBlockJson {
+    fn from(header: &ElementsHeader) -> Self {
+        match header.signblock {
+            SignBlock::Proof {
+                ref challenge,
+                ref solution,
+            } => SignBlockJson {
+                dynafed: false,
+                challenge: hex::encode(challenge),
+                solution: vec![hex::encode(solution)],
+            },
+            SignBlock::Dynafed {
+                ref current,
+                ref signblock_witness,
+                ..
+            } => SignBlockJson {
+                dynafed: true,
+                challenge: hex::encode(current.signblockscript().unwrap_or_default()),
+                solution: signblock_witness.iter().map(hex::encode).collect(),
+            },
+        }
+    }
 }
 
 impl HeaderInfoJson {
-    pub fn new(hi: &HeaderInfo, id: usize, prev_id: usize) -> Self {
+    pub fn new(hi: &HeaderInfo, id: usize, prev_id: usize, params: &ChainParams) -> Self {
+        let pow_verified = chainparams::pow_verified(params, hi);
         HeaderInfoJson {
             id,
             prev_id,
             height: hi.height,
-            hash: hi.header.block_hash().to_string(),
+            hash: hi.block_hash().to_string(),
             version: hi.header.version.to_consensus() as u32,
             prev_blockhash: hi.header.prev_blockhash.to_string(),
             merkle_root: hi.header.merkle_root.to_string(),
@@ -98,11 +183,36 @@ impl HeaderInfoJson {
             bits: hi.header.bits.to_consensus(),
             nonce: hi.header.nonce,
             miner: hi.miner.clone(),
+            own: hi.own,
+            computed: BTreeMap::new(),
+            source: Some(hi.source.to_string()),
+            pow_verified,
+            fully_verified: hi.source.is_direct() && pow_verified != Some(false),
+            signblock: hi.elements.as_deref().map(SignBlockJson::from),
+            timestamp_flags: vec![],
+            first_reported_by: None,
+            first_reported: None,
+            node_statuses: BTreeMap::new(),
+            rejection_reason: None,
         }
     }
 
-    pub fn update_miner(&mut self, miner: String) {
+    // Drops the fields added in API v2 for v1 responses.
+    pub fn without_v2_fields(mut self) -> Self {
+        self.source = None;
+        self.pow_verified = None;
+        self.signblock = None;
+        self.timestamp_flags = vec![];
+        self.first_reported_by = None;
+        self.first_reported = None;
+        self.node_statuses = BTreeMap::new();
+        self.rejection_reason = None;
+        self
+    }
+
+    pub fn update_miner(&mut self, miner: String, own: bool) {
         self.miner = miner;
+        self.own = own;
     }
 }
 
@@ -113,6 +223,9 @@ pub struct InfoJsonResponse {
 
 #[derive(Serialize)]
 pub struct DataJsonResponse {
+    /// Added in API v2.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub network_id: Option<u32>,
     pub header_infos: Vec<HeaderInfoJson>,
     pub nodes: Vec<NodeDataJson>,
 }
@@ -122,20 +235,21 @@ pub struct TipInfoJson {
     pub hash: String,
     pub status: String,
     pub height: u64,
-}
-
-#[derive(Debug, Clone)]
-pub struct Fork {
-    pub common: HeaderInfo,
-    pub children: Vec<HeaderInfo>,
+    /// Hex encoded cumulative work of the tip's branch, counted from the
+    /// first header the instance tracks. Comparable between the tips of a
+    /// network, but lower than the chainwork reported by Bitcoin Core. Only
+    /// set for tips in the header tree. Added in API v2.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub chainwork: Option<String>,
 }
 
 impl TipInfoJson {
-    pub fn new(tip: &ChainTip) -> Self {
+    pub fn new(tip: &ChainTip, chainwork: &HashMap<String, String>) -> Self {
         TipInfoJson {
             hash: tip.hash.clone(),
             status: tip.status.to_string(),
             height: tip.height,
+            chainwork: chainwork.get(&tip.hash).cloned(),
         }
     }
 }
@@ -154,12 +268,116 @@ pub struct NodeDataJson {
     pub version: String,
     /// If the last getchaintips RPC reached the node.
     pub reachable: bool,
+    /// The address the node's RPC host currently resolves to. Not part of
+    /// the public API, it's served by the admin API.
+    #[serde(skip)]
+    pub address: Option<String>,
+    /// See NodeInfo. Served by the consensus API.
+    #[serde(skip)]
+    pub trust: f64,
+    /// See NodeInfo.
+    #[serde(skip)]
+    pub shadow: bool,
+    /// Operator-defined computed fields.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub computed: BTreeMap<String, serde_json::Value>,
+    /// Reachability of the node's advertised P2P addresses. Only set with
+    /// P2P probes enabled.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub p2p_reachability: Option<Vec<P2PProbeJson>>,
+    /// The headers the node knows of beyond its validated tip. Only set
+    /// with the header_gap of the network enabled.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub header_gap: Option<HeaderGapJson>,
+    /// If an operator paused the polling of the node via the admin API.
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub paused: bool,
+    /// The implementation and version parsed from the subversion. Only set
+    /// for v2 requests.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub software: Option<NodeSoftwareJson>,
+    /// The uptime and memory usage of the node. Only set with the
+    /// resource_telemetry of the node enabled and for v2 requests.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub resources: Option<NodeResourcesJson>,
+}
+
+#[derive(Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct NodeResourcesJson {
+    /// Seconds since the node started.
+    pub uptime: u64,
+    /// UTC timestamp when the node started.
+    pub started_at: u64,
+    /// Bytes of the node's locked memory pool.
+    pub memory_used: u64,
+    pub memory_free: u64,
+    pub memory_total: u64,
+    /// Bytes the node could lock in memory.
+    pub memory_locked: u64,
+    /// UTC timestamp when the resources were queried.
+    pub timestamp: u64,
+}
+
+impl NodeResourcesJson {
+    pub fn new(resources: &NodeResources, timestamp: u64) -> Self {
+        NodeResourcesJson {
+            uptime: resources.uptime,
+            started_at: timestamp.saturating_sub(resources.uptime),
+            memory_used: resources.memory_used,
+            memory_free: resources.memory_free,
+            memory_total: resources.memory_total,
+            memory_locked: resources.memory_locked,
+            timestamp,
+        }
+    }
+}
+
+#[derive(Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct NodeSoftwareJson {
+    /// One of "bitcoin-core", "bitcoin-knots", "btcd", "bcoin",
+    /// "libbitcoin", or "unknown".
+    pub implementation: String,
+    pub version: Option<String>,
+}
+
+impl NodeSoftwareJson {
+    pub fn from_subversion(subversion: &str) -> Self {
+        let software = Software::from_subversion(subversion);
+        NodeSoftwareJson {
+            implementation: software.implementation.to_string(),
+            version: software.version,
+        }
+    }
+}
+
+#[derive(Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct HeaderGapJson {
+    /// Height of the node's best known header.
+    pub headers: u64,
+    /// Height of the node's validated tip.
+    pub blocks: u64,
+    pub gap: u64,
+    /// UTC timestamp since when the gap exceeds the max_gap.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub exceeded_since: Option<u64>,
+}
+
+#[derive(Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct P2PProbeJson {
+    /// host:port
+    pub address: String,
+    /// Either "ipv4", "ipv6", or "tor".
+    pub network: String,
+    pub reachable: bool,
+    /// Why the address isn't reachable.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
 }
 
 impl NodeDataJson {
     pub fn new(
         info: NodeInfo,
-        tips: &Vec<ChainTip>,
+        tips: &[ChainTip],
         version: String,
         last_changed_timestamp: u64,
         reachable: bool,
@@ -169,113 +387,980 @@ impl NodeDataJson {
             name: info.name,
             description: info.description,
             implementation: info.implementation,
-            tips: tips.iter().map(TipInfoJson::new).collect(),
+            tips: tips
+                .iter()
+                .map(|tip| TipInfoJson::new(tip, &HashMap::new()))
+                .collect(),
             last_changed_timestamp,
-            version,
             reachable,
+            address: None,
+            trust: info.trust,
+            shadow: info.shadow,
+            computed: BTreeMap::new(),
+            p2p_reachability: None,
+            header_gap: None,
+            paused: false,
+            software: Some(NodeSoftwareJson::from_subversion(&version)),
+            resources: None,
+            version,
         }
     }
 
+    pub fn without_v2_fields(mut self) -> Self {
+        self.software = None;
+        self.resources = None;
+        for tip in self.tips.iter_mut() {
+            tip.chainwork = None;
+        }
+        self
+    }
+
     pub fn reachable(&mut self, r: bool) {
         self.reachable = r;
     }
 
     pub fn version(&mut self, v: String) {
+        self.software = Some(NodeSoftwareJson::from_subversion(&v));
         self.version = v;
     }
 
-    pub fn tips(&mut self, tips: &[ChainTip]) {
-        self.tips = tips.iter().map(TipInfoJson::new).collect();
-        self.last_changed_timestamp = match SystemTime::now().duration_since(SystemTime::UNIX_EPOCH)
-        {
-            Ok(n) => n.as_secs(),
-            Err(_) => {
-                warn!("SystemTime is before UNIX_EPOCH time. Node last_change_timestamp set to 0.");
-                0u64
-            }
-        };
+    pub fn address(&mut self, a: String) {
+        self.address = Some(a);
+    }
+
+    // Keeps the timestamp if the tips are the same, e.g. the tips restored
+    // at startup.
+    pub fn tips(&mut self, tips: &[ChainTip], chainwork: &HashMap<String, String>) {
+        let tips: Vec<TipInfoJson> = tips
+            .iter()
+            .map(|tip| TipInfoJson::new(tip, chainwork))
+            .collect();
+        if tips != self.tips {
+            self.tips = tips;
+            self.last_changed_timestamp = now_timestamp();
+        }
+    }
+
+    // The hashes of the node's tips with the status.
+    pub fn tip_hashes(&self, status: ChainTipStatus) -> Vec<BlockHash> {
+        let status = status.to_string();
+        self.tips
+            .iter()
+            .filter(|tip| tip.status == status)
+            .filter_map(|tip| tip.hash.parse::<BlockHash>().ok())
+            .collect()
+    }
+}
+
+/// Current UTC timestamp in seconds. Falls back to 0 if the system time is
+/// before the UNIX epoch.
+pub fn now_timestamp() -> u64 {
+    match SystemTime::now().duration_since(SystemTime::UNIX_EPOCH) {
+        Ok(n) => n.as_secs(),
+        Err(_) => {
+            warn!("SystemTime is before UNIX_EPOCH time. Using timestamp 0.");
+            0u64
+        }
+    }
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct RawChainTipsJson {
+    pub network_id: u32,
+    pub node_id: u32,
+    /// UTC timestamp when the chain tips were received from the node.
+    pub timestamp: u64,
+    pub chaintips: serde_json::Value,
+}
+
+#[derive(Serialize)]
+pub struct RawChainTipsJsonResponse {
+    pub raw_chaintips: Vec<RawChainTipsJson>,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct ChainParamsJson {
+    pub network_id: u32,
+    pub genesis_hash: Option<String>,
+    /// Target time between blocks in seconds.
+    pub target_spacing: u64,
+    pub halving_interval: u64,
+    /// Block subsidy in sat before the first halving.
+    pub initial_subsidy: u64,
+    pub difficulty_adjustment_interval: u64,
+    /// Height of the highest known header.
+    pub tip_height: Option<u64>,
+    /// Block subsidy in sat at tip_height.
+    pub current_subsidy: Option<u64>,
+    pub next_halving_height: Option<u64>,
+    pub blocks_until_next_halving: Option<u64>,
+    /// UTC timestamp estimate based on the target_spacing.
+    pub next_halving_estimated_timestamp: Option<u64>,
+    pub next_difficulty_adjustment_height: Option<u64>,
+    pub blocks_until_next_difficulty_adjustment: Option<u64>,
+    /// UTC timestamp estimate based on the target_spacing.
+    pub next_difficulty_adjustment_estimated_timestamp: Option<u64>,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct EventJson {
+    pub id: u64,
+    pub network_id: u32,
+    /// UTC timestamp when the event was emitted.
+    pub timestamp: u64,
+    pub kind: String,
+    pub severity: String,
+    pub title: String,
+    pub description: String,
+    /// Added in API v2.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sequence: Option<u64>,
+}
+
+impl From<&Event> for EventJson {
+    fn from(event: &Event) -> Self {
+        EventJson {
+            id: event.id,
+            network_id: event.network_id,
+            timestamp: event.timestamp,
+            kind: event.kind.to_string(),
+            severity: event.severity.to_string(),
+            title: event.title.clone(),
+            description: event.description.clone(),
+            sequence: None,
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct EventsJsonResponse {
+    pub events: Vec<EventJson>,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct ReorgJson {
+    pub id: u64,
+    pub network_id: u32,
+    pub node_id: u32,
+    /// UTC timestamp when the reorg was detected.
+    pub timestamp: u64,
+    pub old_tip_hash: String,
+    pub old_tip_height: u64,
+    pub old_tip_time: u32,
+    pub new_tip_hash: String,
+    pub new_tip_height: u64,
+    pub new_tip_time: u32,
+    pub common_ancestor_hash: String,
+    pub common_ancestor_height: u64,
+    pub depth: u64,
+}
+
+impl From<&Reorg> for ReorgJson {
+    fn from(reorg: &Reorg) -> Self {
+        ReorgJson {
+            id: reorg.id,
+            network_id: reorg.network_id,
+            node_id: reorg.node_id,
+            timestamp: reorg.timestamp,
+            old_tip_hash: reorg.old_tip_hash.clone(),
+            old_tip_height: reorg.old_tip_height,
+            old_tip_time: reorg.old_tip_time,
+            new_tip_hash: reorg.new_tip_hash.clone(),
+            new_tip_height: reorg.new_tip_height,
+            new_tip_time: reorg.new_tip_time,
+            common_ancestor_hash: reorg.common_ancestor_hash.clone(),
+            common_ancestor_height: reorg.common_ancestor_height,
+            depth: reorg.depth,
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct ReorgsJsonResponse {
+    pub reorgs: Vec<ReorgJson>,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct TipChangeJson {
+    pub node_id: u32,
+    /// UTC timestamp of the query that saw the change.
+    pub timestamp: u64,
+    pub height: u64,
+    pub hash: String,
+    pub status: String,
+}
+
+impl From<&TipObservation> for TipChangeJson {
+    fn from(change: &TipObservation) -> Self {
+        TipChangeJson {
+            node_id: change.node_id,
+            timestamp: change.timestamp,
+            height: change.height,
+            hash: change.hash.clone(),
+            status: change.status.to_string(),
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct TipHistoryJsonResponse {
+    pub from: u64,
+    pub to: u64,
+    pub changes: Vec<TipChangeJson>,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct QueuedNotificationJson {
+    pub id: u64,
+    pub sink: String,
+    pub payload: String,
+    pub attempts: u32,
+    /// UTC timestamp of the next delivery attempt.
+    pub next_attempt: u64,
+    pub last_error: String,
+    /// UTC timestamp when the notification was queued.
+    pub created: u64,
+}
+
+impl From<&QueuedNotification> for QueuedNotificationJson {
+    fn from(notification: &QueuedNotification) -> Self {
+        QueuedNotificationJson {
+            id: notification.id,
+            sink: notification.sink.clone(),
+            payload: notification.payload.clone(),
+            attempts: notification.attempts,
+            next_attempt: notification.next_attempt,
+            last_error: notification.last_error.clone(),
+            created: notification.created,
+        }
     }
 }
 
+#[derive(Serialize)]
+pub struct DeadLetterJsonResponse {
+    pub notifications: Vec<QueuedNotificationJson>,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct PreflightNodeJson {
+    pub network_id: u32,
+    pub node_id: u32,
+    pub node_name: String,
+    /// If the node was "added" or "changed" compared to the running nodes.
+    pub change: String,
+    /// If the node answered getchaintips with our credentials.
+    pub reachable: bool,
+    pub genesis_hash: Option<String>,
+    /// If the node's genesis block matches the one of the network. Unknown
+    /// if the network has no genesis hash configured.
+    pub network_matches: Option<bool>,
+    pub error: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct PreflightJsonResponse {
+    /// Set if the configuration file could not be loaded.
+    pub config_error: Option<String>,
+    pub unchanged_nodes: usize,
+    pub nodes: Vec<PreflightNodeJson>,
+}
+
 #[derive(Serialize, Clone)]
 pub struct DataChanged {
     pub network_id: u32,
 }
 
-#[derive(Deserialize, Clone, Debug, PartialEq, Eq)]
-pub enum ChainTipStatus {
-    #[serde(rename = "active")]
-    Active,
-    #[serde(rename = "invalid")]
-    Invalid,
-    #[serde(rename = "valid-fork")]
-    ValidFork,
-    #[serde(rename = "headers-only")]
-    HeadersOnly,
-    #[serde(rename = "valid-headers")]
-    ValidHeaders,
-    Unknown,
-}
-
-impl From<String> for ChainTipStatus {
-    fn from(s: String) -> Self {
-        match s.as_str() {
-            "active" => ChainTipStatus::Active,
-            "invalid" => ChainTipStatus::Invalid,
-            "headers-only" => ChainTipStatus::HeadersOnly,
-            "valid-headers" => ChainTipStatus::ValidHeaders,
-            "valid-fork" => ChainTipStatus::ValidFork,
-            _ => ChainTipStatus::Unknown,
+#[derive(Serialize, Clone, Debug)]
+pub struct ChainTipJson {
+    pub height: u64,
+    pub hash: String,
+    pub branchlen: usize,
+    pub status: String,
+}
+
+impl From<&ChainTip> for ChainTipJson {
+    fn from(tip: &ChainTip) -> Self {
+        ChainTipJson {
+            height: tip.height,
+            hash: tip.hash.clone(),
+            branchlen: tip.branchlen,
+            status: tip.status.to_string(),
         }
     }
 }
 
-impl From<GetChainTipsResultStatus> for ChainTipStatus {
-    fn from(s: GetChainTipsResultStatus) -> Self {
-        match s {
-            GetChainTipsResultStatus::Active => ChainTipStatus::Active,
-            GetChainTipsResultStatus::Invalid => ChainTipStatus::Invalid,
-            GetChainTipsResultStatus::HeadersOnly => ChainTipStatus::HeadersOnly,
-            GetChainTipsResultStatus::ValidHeaders => ChainTipStatus::ValidHeaders,
-            GetChainTipsResultStatus::ValidFork => ChainTipStatus::ValidFork,
+#[derive(Serialize)]
+pub struct ExportBlockJson {
+    pub height: u64,
+    pub hash: String,
+    pub time: u32,
+    pub miner: String,
+}
+
+impl From<&HeaderInfo> for ExportBlockJson {
+    fn from(hi: &HeaderInfo) -> Self {
+        ExportBlockJson {
+            height: hi.height,
+            hash: hi.block_hash().to_string(),
+            time: hi.header.time,
+            miner: hi.miner.clone(),
         }
     }
 }
 
-impl fmt::Display for ChainTipStatus {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        match self {
-            ChainTipStatus::Active => write!(f, "active"),
-            ChainTipStatus::Invalid => write!(f, "invalid"),
-            ChainTipStatus::HeadersOnly => write!(f, "headers-only"),
-            ChainTipStatus::ValidHeaders => write!(f, "valid-headers"),
-            ChainTipStatus::ValidFork => write!(f, "valid-fork"),
-            ChainTipStatus::Unknown => write!(f, "unknown"),
+#[derive(Serialize)]
+pub struct ExportForkJson {
+    /// The last block common to all branches.
+    pub common: ExportBlockJson,
+    /// The first block of each branch.
+    pub branches: Vec<ExportBlockJson>,
+}
+
+impl From<&Fork> for ExportForkJson {
+    fn from(fork: &Fork) -> Self {
+        ExportForkJson {
+            common: ExportBlockJson::from(&fork.common),
+            branches: fork.children.iter().map(ExportBlockJson::from).collect(),
         }
     }
 }
 
-#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
-pub struct ChainTip {
+#[derive(Serialize)]
+pub struct ExportForksJson {
+    pub forks: Vec<ExportForkJson>,
+}
+
+#[derive(Serialize)]
+pub struct ExportNetworkJson {
+    pub id: u32,
+    pub name: String,
+    pub description: String,
+    pub genesis_hash: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct ExportFileJson {
+    pub name: String,
+    pub description: String,
+    /// Size in bytes.
+    pub size: usize,
+    /// Hex encoded SHA256 of the file.
+    pub sha256: String,
+}
+
+#[derive(Serialize)]
+pub struct ExportManifestJson {
+    pub format: String,
+    pub format_version: u32,
+    pub generator: String,
+    /// UTC timestamp when the bundle was created.
+    pub created: u64,
+    pub network: ExportNetworkJson,
+    /// UTC timestamps of the exported time range (inclusive).
+    pub from: u64,
+    pub to: u64,
+    pub citation: String,
+    pub files: Vec<ExportFileJson>,
+}
+
+/// The header tree in the node-link format of networkx.
+#[derive(Serialize, Debug, PartialEq)]
+pub struct TreeGraphJson {
+    pub directed: bool,
+    pub multigraph: bool,
+    pub graph: TreeGraphInfoJson,
+    pub nodes: Vec<TreeGraphNodeJson>,
+    pub links: Vec<TreeGraphLinkJson>,
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+pub struct TreeGraphInfoJson {
+    pub name: String,
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+pub struct TreeGraphNodeJson {
+    /// The block hash.
+    pub id: String,
     pub height: u64,
+    pub version: u32,
+    pub prev_blockhash: String,
+    pub time: u32,
+    pub bits: u32,
+    pub nonce: u32,
+    pub miner: String,
+    /// "active" on the active chain of a node, the status of the tip a node
+    /// reports for the branch, e.g. "valid-fork" or "invalid", or "stale"
+    /// for branches no node reports anymore.
+    pub status: String,
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+pub struct TreeGraphLinkJson {
+    /// The hash of the parent.
+    pub source: String,
+    pub target: String,
+    /// The headers between the two that were collapsed by the tree
+    /// compaction.
+    pub collapsed: u64,
+}
+
+#[derive(Serialize)]
+pub struct CompetingBlockJson {
     pub hash: String,
-    pub branchlen: usize,
-    pub status: ChainTipStatus,
+    pub miner: String,
+    /// Timestamp in the block header, as set by the miner.
+    pub time: u32,
+    /// UTC timestamp when a node first reported the block as a chain tip.
+    pub first_seen: Option<u64>,
 }
 
-impl From<GetChainTipsResultTip> for ChainTip {
-    fn from(t: GetChainTipsResultTip) -> Self {
-        ChainTip {
-            height: t.height,
-            hash: t.hash.to_string(),
-            branchlen: t.branch_length,
-            status: t.status.into(),
+#[derive(Serialize)]
+pub struct CompetitionJson {
+    pub height: u64,
+    /// Ordered by first_seen. Blocks never seen as a tip come last.
+    pub blocks: Vec<CompetingBlockJson>,
+    /// Seconds between the first and the last block being first seen.
+    /// Unknown if less than two of the blocks were seen as a tip.
+    pub first_seen_delta: Option<u64>,
+    pub within_window: Option<bool>,
+}
+
+#[derive(Serialize)]
+pub struct BranchLatencyJson {
+    /// Hash of the first block of the branch.
+    pub hash: String,
+    /// UTC timestamp when a node first reported a block of the branch as a
+    /// chain tip.
+    pub first_seen: Option<u64>,
+    /// Seconds between the block time of the last common block and the
+    /// branch being first seen.
+    pub age: Option<i64>,
+    /// Seconds between the last common block and the branch being first
+    /// seen. Unknown if the last common block was never seen as a tip.
+    pub observed_age: Option<u64>,
+    /// If the branch surfaced more than max_age seconds after the block
+    /// time of the last common block.
+    pub late: Option<bool>,
+}
+
+#[derive(Serialize)]
+pub struct ForkLatencyJson {
+    /// Hash of the last common block of the fork.
+    pub common: String,
+    pub height: u64,
+    /// Timestamp in the header of the last common block.
+    pub time: u32,
+    /// UTC timestamp when a node first reported the last common block as a
+    /// chain tip.
+    pub first_seen: Option<u64>,
+    pub branches: Vec<BranchLatencyJson>,
+}
+
+#[derive(Serialize)]
+pub struct ForkLatenciesJsonResponse {
+    pub max_age: u64,
+    pub late_branches: usize,
+    pub forks: Vec<ForkLatencyJson>,
+}
+
+#[derive(Serialize)]
+pub struct BranchLifecycleJson {
+    /// Hash of the first block of the branch.
+    pub hash: String,
+    /// Number of blocks of the branch, counted up to 144.
+    pub length: u64,
+    /// UTC timestamp when a node first reported a block of the branch as a
+    /// chain tip.
+    pub first_seen: Option<u64>,
+    /// If the branch won the fork. Unknown while the fork is open.
+    pub won: Option<bool>,
+}
+
+#[derive(Serialize)]
+pub struct ForkLifecycleJson {
+    /// Hash of the last common block of the fork.
+    pub common: String,
+    pub height: u64,
+    pub resolved: bool,
+    /// UTC timestamp when a second branch was first seen.
+    pub diverged: Option<u64>,
+    /// UTC timestamp when the block that put the winning branch ahead for
+    /// good was first seen.
+    pub resolved_at: Option<u64>,
+    /// Seconds from diverged to resolved_at.
+    pub duration: Option<u64>,
+    /// Seconds between the block times of the first block of the second
+    /// branch and the block that put the winning branch ahead for good.
+    pub block_time_duration: Option<i64>,
+    /// Hash of the first block of the winning branch.
+    pub winning_branch: Option<String>,
+    /// Length of the longest losing branch.
+    pub losing_depth: Option<u64>,
+    pub branches: Vec<BranchLifecycleJson>,
+}
+
+#[derive(Serialize)]
+pub struct ForkLifecyclesJsonResponse {
+    /// A fork is resolved once all branches but the longest are this many
+    /// blocks shorter than the longest one.
+    pub closed_depth: u64,
+    pub open_forks: usize,
+    pub forks: Vec<ForkLifecycleJson>,
+}
+
+#[derive(Serialize)]
+pub struct BlockMetadataJson {
+    pub hash: String,
+    pub height: u64,
+    /// Height of the last common block of the fork.
+    pub fork_height: u64,
+    pub miner: String,
+    /// Serialized size in bytes, including the witness data. Unknown until
+    /// the block was fetched.
+    pub size: Option<u64>,
+    pub weight: Option<u64>,
+    pub tx_count: Option<u64>,
+    /// The outputs of the coinbase minus the block subsidy, in sat.
+    pub fees: Option<u64>,
+}
+
+#[derive(Serialize)]
+pub struct BlockMetadataJsonResponse {
+    /// The blocks of each branch up to this many blocks above the last
+    /// common block are listed.
+    pub depth: u64,
+    pub blocks: Vec<BlockMetadataJson>,
+}
+
+#[derive(Serialize)]
+pub struct StaleRateWindowJson {
+    /// day, week or month
+    pub window: String,
+    pub seconds: u64,
+    /// Blocks of the active chain in the window.
+    pub blocks: u64,
+    pub stale_blocks: u64,
+    /// stale_blocks / (blocks + stale_blocks), null without blocks.
+    pub stale_rate: Option<f64>,
+}
+
+#[derive(Serialize)]
+pub struct StaleRateJsonResponse {
+    /// The highest active tip of the nodes, null before the nodes reported
+    /// one.
+    pub tip_hash: Option<String>,
+    pub tip_height: Option<u64>,
+    pub windows: Vec<StaleRateWindowJson>,
+}
+
+#[derive(Serialize)]
+pub struct CompetingBlocksJsonResponse {
+    /// Blocks first seen within this many seconds count as competing
+    /// within the window.
+    pub window: u64,
+    pub competitions: usize,
+    pub competitions_with_timing: usize,
+    pub competitions_within_window: usize,
+    pub details: Vec<CompetitionJson>,
+}
+
+#[derive(Deserialize)]
+pub struct SightingJson {
+    pub hash: String,
+    pub height: Option<u64>,
+    /// UTC timestamp when the observer saw the block.
+    pub timestamp: u64,
+}
+
+#[derive(Deserialize)]
+pub struct SightingsJsonRequest {
+    pub sightings: Vec<SightingJson>,
+}
+
+#[derive(Serialize)]
+pub struct SightingsJsonResponse {
+    pub accepted: usize,
+}
+
+#[derive(Serialize)]
+pub struct SeenByJson {
+    /// Either "node" or "external".
+    pub kind: String,
+    /// Set for nodes.
+    pub node_id: Option<u32>,
+    pub name: String,
+    /// UTC timestamp when the observer first saw the block.
+    pub first_seen: u64,
+}
+
+#[derive(Serialize)]
+pub struct SeenByJsonResponse {
+    pub hash: String,
+    /// Ordered by first_seen.
+    pub seen_by: Vec<SeenByJson>,
+}
+
+#[derive(Serialize)]
+pub struct RetiredNodeJson {
+    pub id: u32,
+    pub name: String,
+    pub description: String,
+    pub implementation: String,
+    /// UTC timestamp of the first startup without the node.
+    pub retired: u64,
+    /// UTC timestamp when the node's data is purged. Not set if the data is
+    /// kept.
+    pub purge_at: Option<u64>,
+}
+
+impl RetiredNodeJson {
+    pub fn new(node: &RetiredNode) -> Self {
+        RetiredNodeJson {
+            id: node.node_id,
+            name: node.name.clone(),
+            description: node.description.clone(),
+            implementation: node.implementation.clone(),
+            retired: node.retired,
+            purge_at: node.purge_at(),
         }
     }
 }
 
-impl ChainTip {
-    pub fn block_hash(&self) -> BlockHash {
-        BlockHash::from_str(&self.hash).unwrap()
+#[derive(Serialize)]
+pub struct RetiredNodesJsonResponse {
+    pub nodes: Vec<RetiredNodeJson>,
+}
+
+#[derive(Serialize)]
+pub struct ReadinessJson {
+    pub ready: bool,
+    pub primed_nodes: usize,
+    pub nodes: usize,
+}
+
+#[derive(Serialize)]
+pub struct ExpandedForkJson {
+    pub network_id: u32,
+    /// Hash of the last common block of the fork.
+    pub common: String,
+    /// The heights served in addition to the max_interesting_heights.
+    pub from: u64,
+    pub to: u64,
+}
+
+#[derive(Serialize)]
+pub struct ExpandedForksJsonResponse {
+    pub forks: Vec<ExpandedForkJson>,
+}
+
+#[derive(Serialize)]
+pub struct SuppressedTipJson {
+    pub network_id: u32,
+    pub hash: String,
+    pub reason: String,
+    /// UTC timestamp when the tip was suppressed.
+    pub since: u64,
+    /// The nodes whose last chain tips included the tip.
+    pub reported_by: Vec<SuppressedTipReporterJson>,
+}
+
+#[derive(Serialize)]
+pub struct SuppressedTipReporterJson {
+    pub node_id: u32,
+    pub height: u64,
+}
+
+impl From<&SuppressedState> for SuppressedTipJson {
+    fn from(state: &SuppressedState) -> Self {
+        SuppressedTipJson {
+            network_id: state.tip.network_id,
+            hash: state.tip.hash.clone(),
+            reason: state.tip.reason.clone(),
+            since: state.tip.since,
+            reported_by: state
+                .reported_by
+                .iter()
+                .map(|(node_id, height)| SuppressedTipReporterJson {
+                    node_id: *node_id,
+                    height: *height,
+                })
+                .collect(),
+        }
     }
 }
+
+#[derive(Serialize)]
+pub struct SuppressedTipsJsonResponse {
+    pub tips: Vec<SuppressedTipJson>,
+}
+
+#[derive(Serialize)]
+pub struct IncidentJson {
+    pub id: u64,
+    pub network_id: u32,
+    pub reason: String,
+    /// UTC timestamp when incident mode was switched on.
+    pub started: u64,
+    /// UTC timestamp when incident mode was switched off. Not set while the
+    /// incident is ongoing.
+    pub ended: Option<u64>,
+}
+
+impl From<&Incident> for IncidentJson {
+    fn from(incident: &Incident) -> Self {
+        IncidentJson {
+            id: incident.id,
+            network_id: incident.network_id,
+            reason: incident.reason.clone(),
+            started: incident.started,
+            ended: incident.ended,
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct IncidentsJsonResponse {
+    pub incidents: Vec<IncidentJson>,
+}
+
+#[derive(Serialize)]
+pub struct IncidentRecordingJson {
+    pub timestamp: u64,
+    pub node_id: u32,
+    pub kind: String,
+    pub data: serde_json::Value,
+}
+
+impl From<&IncidentRecording> for IncidentRecordingJson {
+    fn from(recording: &IncidentRecording) -> Self {
+        IncidentRecordingJson {
+            timestamp: recording.timestamp,
+            node_id: recording.node_id,
+            kind: recording.kind.clone(),
+            // Recordings are stored as JSON, but aren't lost if they aren't.
+            data: serde_json::from_str(&recording.data)
+                .unwrap_or_else(|_| serde_json::Value::String(recording.data.clone())),
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct AdminNodeJson {
+    pub network_id: u32,
+    pub node_id: u32,
+    pub name: String,
+    /// The configured host:port.
+    pub rpc_url: String,
+    /// The address the host currently resolves to. Unknown until the first
+    /// successful resolution.
+    pub address: Option<String>,
+    pub reachable: bool,
+    pub paused: bool,
+}
+
+#[derive(Serialize)]
+pub struct AdminNodesJsonResponse {
+    pub nodes: Vec<AdminNodeJson>,
+}
+
+#[derive(Serialize)]
+pub struct LogLevelJson {
+    pub module: String,
+    pub level: String,
+}
+
+#[derive(Serialize)]
+pub struct LogLevelsJsonResponse {
+    /// The filter set via the RUST_LOG environment variable.
+    pub default: String,
+    /// Module levels set via the admin API until the next restart.
+    pub overrides: Vec<LogLevelJson>,
+}
+
+#[derive(Serialize)]
+pub struct QuarantinedHeaderJson {
+    pub height: u64,
+    pub hash: String,
+    pub prev_blockhash: String,
+}
+
+#[derive(Serialize)]
+pub struct QuarantinedNodeJson {
+    pub network_id: u32,
+    pub node_id: u32,
+    pub headers: Vec<QuarantinedHeaderJson>,
+}
+
+#[derive(Serialize)]
+pub struct QuarantineJsonResponse {
+    pub nodes: Vec<QuarantinedNodeJson>,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct BlockTemplateJson {
+    pub node_id: u32,
+    pub name: String,
+    pub height: u64,
+    /// Sum of the fees of the template transactions in sat.
+    pub total_fees: u64,
+    pub tx_count: usize,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct BlockTemplatesJsonResponse {
+    /// UTC timestamp of the comparison.
+    pub timestamp: u64,
+    /// Only templates for the highest height are compared. Nodes still
+    /// building on an older tip are listed but not compared.
+    pub height: Option<u64>,
+    pub templates: Vec<BlockTemplateJson>,
+    /// Difference between the highest and the lowest value relative to the
+    /// highest value, in percent.
+    pub fee_divergence_percent: Option<u64>,
+    pub tx_count_divergence_percent: Option<u64>,
+    pub divergent: bool,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct PoolTipJson {
+    pub name: String,
+    /// Hash of the block the pool currently lets its miners build on.
+    pub hash: Option<String>,
+    pub height: Option<u64>,
+    /// UTC timestamp of the last job received from the pool.
+    pub last_job: Option<u64>,
+    pub connected: bool,
+}
+
+#[derive(Serialize)]
+pub struct HistoryJsonResponse {
+    pub chunk: u64,
+    pub from_height: u64,
+    pub to_height: u64,
+    /// If the chunk is buried deep enough to not change anymore.
+    pub immutable: bool,
+    pub header_infos: Vec<HeaderInfoJson>,
+}
+
+#[derive(Serialize)]
+pub struct BlockHeaderJsonResponse {
+    pub header_info: HeaderInfoJson,
+    /// If the header wasn't known and was fetched from a node for this
+    /// request.
+    pub fetched: bool,
+}
+
+#[derive(Serialize)]
+pub struct PoolsJsonResponse {
+    pub pools: Vec<PoolTipJson>,
+}
+
+#[derive(Serialize)]
+pub struct BranchLengthBucketJson {
+    /// Number of blocks of the branch above the last common block.
+    pub length: u64,
+    pub count: u64,
+}
+
+#[derive(Serialize)]
+pub struct BranchLengthHistogramJsonResponse {
+    /// Forks are counted once all branches but the longest are this many
+    /// blocks shorter than the longest one.
+    pub closed_depth: u64,
+    pub closed_forks: usize,
+    /// The branches that lost a fork, ordered by length.
+    pub branches: Vec<BranchLengthBucketJson>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct ConsensusTipJson {
+    pub hash: String,
+    pub height: u64,
+    /// Sum of the trust of the nodes with this active tip.
+    pub weight: f64,
+    pub node_ids: Vec<u32>,
+}
+
+#[derive(Serialize)]
+pub struct ConsensusJsonResponse {
+    /// The active tip with the highest weight.
+    pub consensus_tip: Option<ConsensusTipJson>,
+    /// Share of the total weight agreeing on the consensus tip.
+    pub agreement: Option<f64>,
+    /// If no active tip has more than half of the total weight.
+    pub partitioned: bool,
+    /// The active tips of the reachable nodes.
+    pub tips: Vec<ConsensusTipJson>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct RaceBranchJson {
+    pub tip_hash: String,
+    pub tip_height: u64,
+    /// Blocks since the fork point.
+    pub length: u64,
+    /// Share of the work of all branches since the fork point.
+    pub work_share: f64,
+    /// Share of the work accrued per second (by header timestamps).
+    pub work_rate_share: f64,
+    /// The reachable nodes whose active tip is on the branch.
+    pub node_ids: Vec<u32>,
+    /// Share of the trust of the nodes on any of the branches.
+    pub node_share: f64,
+    /// The identified miners of the branch's blocks.
+    pub miners: Vec<String>,
+    pub miner_share: f64,
+    /// The heuristic estimate of the branch winning the race.
+    pub win_probability: f64,
+}
+
+#[derive(Serialize, Debug)]
+pub struct RaceJson {
+    /// Height of the last block the branches have in common.
+    pub fork_height: u64,
+    pub fork_hash: String,
+    /// The tip hash of the branch with the highest win_probability.
+    pub likely_winner: String,
+    pub branches: Vec<RaceBranchJson>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct ForkPredictionJsonResponse {
+    /// Always true: the prediction is a heuristic, not a guarantee.
+    pub heuristic: bool,
+    /// The ongoing race between the branches of the most recent fork. None
+    /// if there is no fork whose branches are close enough to race.
+    pub race: Option<RaceJson>,
+}
+
+#[derive(Serialize)]
+pub struct WidgetTipJson {
+    pub hash: String,
+    pub height: u64,
+}
+
+#[derive(Serialize, Debug, PartialEq, Eq)]
+pub struct WidgetNodesJson {
+    /// Number of nodes, without shadow nodes.
+    pub total: usize,
+    pub reachable: usize,
+    /// Reachable nodes with the consensus tip as active tip.
+    pub in_consensus: usize,
+}
+
+#[derive(Serialize)]
+pub struct WidgetJsonResponse {
+    pub network_id: u32,
+    pub network_name: String,
+    pub consensus_tip: Option<WidgetTipJson>,
+    /// Forks within the last 144 blocks of the consensus tip.
+    pub recent_forks: usize,
+    pub nodes: WidgetNodesJson,
+}
+
+#[derive(Serialize)]
+pub struct StatusJsonResponse {
+    /// The widget.json summary of each network.
+    pub networks: Vec<WidgetJsonResponse>,
+}
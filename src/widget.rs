@@ -0,0 +1,206 @@
+use std::convert::Infallible;
+
+use warp::Reply;
+
+use crate::consensus::consensus;
+use crate::domain::Fork;
+use crate::rss::response_unknown_network;
+use crate::types::{
+    Caches, NetworkJson, NodeDataJson, StatusJsonResponse, Versioned, WidgetJsonResponse,
+    WidgetNodesJson, WidgetTipJson,
+};
+
+const RECENT_FORK_BLOCKS: u64 = 144;
+
+// A compact summary of a network for embedding a status widget into other
+// sites. Unlike the data.json, it stays small with many nodes and forks.
+pub fn widget<'a>(
+    network: &NetworkJson,
+    nodes: impl Iterator<Item = &'a NodeDataJson> + Clone,
+    forks: &[Fork],
+) -> WidgetJsonResponse {
+    let consensus_tip = consensus(nodes.clone()).consensus_tip;
+    let nodes: Vec<&NodeDataJson> = nodes.filter(|node| !node.shadow).collect();
+    let in_consensus = consensus_tip.as_ref().map_or(0, |tip| {
+        nodes
+            .iter()
+            .filter(|node| tip.node_ids.contains(&node.id))
+            .count()
+    });
+    let recent_forks = consensus_tip.as_ref().map_or(0, |tip| {
+        forks
+            .iter()
+            .filter(|fork| fork.common.height + RECENT_FORK_BLOCKS >= tip.height)
+            .count()
+    });
+    WidgetJsonResponse {
+        network_id: network.id,
+        network_name: network.name.clone(),
+        consensus_tip: consensus_tip.map(|tip| WidgetTipJson {
+            hash: tip.hash,
+            height: tip.height,
+        }),
+        recent_forks,
+        nodes: WidgetNodesJson {
+            total: nodes.len(),
+            reachable: nodes.iter().filter(|node| node.reachable).count(),
+            in_consensus,
+        },
+    }
+}
+
+pub async fn widget_response(
+    network_id: u32,
+    caches: Caches,
+    network_infos: Vec<NetworkJson>,
+) -> Result<Box<dyn Reply>, Infallible> {
+    let network = match network_infos.iter().find(|n| n.id == network_id) {
+        Some(network) => network,
+        None => return Ok(Box::new(response_unknown_network(network_infos))),
+    };
+    let caches_locked = caches.lock().await;
+    match caches_locked.get(&network_id) {
+        Some(cache) => Ok(Box::new(warp::reply::json(&Versioned::new(widget(
+            network,
+            cache.node_data.values(),
+            &cache.forks,
+        ))))),
+        None => Ok(Box::new(response_unknown_network(network_infos))),
+    }
+}
+
+// Summarizes all networks, so dashboards of a fleet of networks can poll a
+// single URL.
+pub async fn status_response(
+    caches: Caches,
+    network_infos: Vec<NetworkJson>,
+) -> Result<impl Reply, Infallible> {
+    let caches_locked = caches.lock().await;
+    let networks = network_infos
+        .iter()
+        .filter_map(|network| {
+            caches_locked
+                .get(&network.id)
+                .map(|cache| widget(network, cache.node_data.values(), &cache.forks))
+        })
+        .collect();
+    Ok(warp::reply::json(&Versioned::new(StatusJsonResponse {
+        networks,
+    })))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::NodeInfo;
+    use crate::retry::RetryPolicy;
+    use crate::testutil::at_height;
+    use crate::types::{Cache, TipInfoJson};
+    use std::collections::BTreeMap;
+    use std::sync::Arc;
+    use tokio::sync::Mutex;
+
+    fn node(id: u32, hash: &str, height: u64) -> NodeDataJson {
+        let mut node = NodeDataJson::new(
+            NodeInfo {
+                id,
+                name: String::new(),
+                description: String::new(),
+                implementation: String::new(),
+                trust: 1.0,
+                shadow: false,
+                purge_after: None,
+                retry: RetryPolicy::default(),
+            },
+            &[],
+            String::new(),
+            0,
+            true,
+        );
+        node.tips = vec![TipInfoJson {
+            hash: hash.to_string(),
+            status: "active".to_string(),
+            height,
+            chainwork: None,
+        }];
+        node
+    }
+
+    fn fork(height: u64) -> Fork {
+        let header_info = at_height(height, 0, 0);
+        Fork {
+            common: header_info.clone(),
+            children: vec![header_info],
+        }
+    }
+
+    #[test]
+    fn test_widget() {
+        let network = NetworkJson {
+            id: 1,
+            name: "mainnet".to_string(),
+            description: String::new(),
+        };
+        let mut unreachable = node(2, "b", 999);
+        unreachable.reachable = false;
+        let mut shadow = node(3, "a", 1000);
+        shadow.shadow = true;
+        let nodes = [node(0, "a", 1000), node(1, "a", 1000), unreachable, shadow];
+        let forks = [fork(800), fork(856), fork(990)];
+
+        let result = widget(&network, nodes.iter(), &forks);
+        assert_eq!(result.consensus_tip.unwrap().height, 1000);
+        assert_eq!(result.recent_forks, 2);
+        assert_eq!(
+            result.nodes,
+            WidgetNodesJson {
+                total: 3,
+                reachable: 2,
+                in_consensus: 2,
+            }
+        );
+
+        let result = widget(&network, [].iter(), &forks);
+        assert!(result.consensus_tip.is_none());
+        assert_eq!(result.recent_forks, 0);
+    }
+
+    #[tokio::test]
+    async fn test_status_response() {
+        let network = |id: u32, name: &str| NetworkJson {
+            id,
+            name: name.to_string(),
+            description: String::new(),
+        };
+        let node_data = vec![(0, node(0, "a", 1000)), (1, node(1, "b", 999))]
+            .into_iter()
+            .collect();
+        let mut caches = BTreeMap::new();
+        caches.insert(
+            1,
+            Cache {
+                header_infos_json: vec![],
+                node_data,
+                forks: vec![fork(990)],
+                recent_miners: vec![],
+                raw_tips: BTreeMap::new(),
+            },
+        );
+        let caches: Caches = Arc::new(Mutex::new(caches));
+
+        // networks without a cache yet are left out
+        let response = status_response(caches, vec![network(1, "mainnet"), network(2, "signet")])
+            .await
+            .unwrap()
+            .into_response();
+        let body = warp::hyper::body::to_bytes(response.into_body())
+            .await
+            .unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        let networks = json["networks"].as_array().unwrap();
+        assert_eq!(networks.len(), 1);
+        assert_eq!(networks[0]["network_name"], "mainnet");
+        assert_eq!(networks[0]["recent_forks"], 1);
+        assert_eq!(networks[0]["nodes"]["total"], 2);
+    }
+}
@@ -0,0 +1,230 @@
+use std::io::{self, Read, Write};
+use std::net::{SocketAddr, TcpStream};
+use std::time::Duration;
+
+use log::debug;
+
+use crate::socks;
+
+// The topic Bitcoin Core publishes new block hashes under
+// (-zmqpubhashblock).
+pub const TOPIC_HASHBLOCK: &[u8] = b"hashblock";
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+// ZMTP 3.0 has no heartbeats. A connection without a message for this long
+// is reconnected, in case it silently broke.
+const QUIET_TIMEOUT: Duration = Duration::from_secs(60 * 60);
+
+const GREETING_LENGTH: usize = 64;
+const FLAG_MORE: u8 = 0x01;
+const FLAG_LONG: u8 = 0x02;
+const FLAG_COMMAND: u8 = 0x04;
+// Larger frames, e.g. rawblock messages, aren't expected on a hashblock
+// subscription.
+const MAX_FRAME_SIZE: u64 = 1024 * 1024;
+
+fn error(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+// A ZMQ PUB socket, reached through the SOCKS5 proxy if set.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ZmqEndpoint {
+    pub host: String,
+    pub port: u16,
+    pub proxy: Option<SocketAddr>,
+}
+
+impl ZmqEndpoint {
+    // Parses a ZMQ endpoint as configured in Bitcoin Core, e.g.
+    // "tcp://127.0.0.1:28332". The tcp:// prefix is optional.
+    pub fn parse(endpoint: &str, proxy: Option<SocketAddr>) -> Option<Self> {
+        let address = endpoint.strip_prefix("tcp://").unwrap_or(endpoint);
+        let (host, port) = address.rsplit_once(':')?;
+        let host = host.trim_start_matches('[').trim_end_matches(']');
+        if host.is_empty() {
+            return None;
+        }
+        Some(ZmqEndpoint {
+            host: host.to_string(),
+            port: port.parse().ok()?,
+            proxy,
+        })
+    }
+}
+
+// The ZMTP 3.0 greeting of a client using the NULL security mechanism.
+fn greeting() -> [u8; GREETING_LENGTH] {
+    let mut greeting = [0u8; GREETING_LENGTH];
+    greeting[0] = 0xFF;
+    greeting[9] = 0x7F;
+    greeting[10] = 3; // major version
+    greeting[11] = 0; // minor version
+    greeting[12..16].copy_from_slice(b"NULL");
+    greeting
+}
+
+// The READY command announcing a SUB socket.
+fn ready() -> Vec<u8> {
+    let mut body = vec![5];
+    body.extend_from_slice(b"READY");
+    body.push(11);
+    body.extend_from_slice(b"Socket-Type");
+    body.extend_from_slice(&3u32.to_be_bytes());
+    body.extend_from_slice(b"SUB");
+    frame(FLAG_COMMAND, &body)
+}
+
+fn frame(flags: u8, body: &[u8]) -> Vec<u8> {
+    let mut frame = vec![];
+    if body.len() > u8::MAX as usize {
+        frame.push(flags | FLAG_LONG);
+        frame.extend_from_slice(&(body.len() as u64).to_be_bytes());
+    } else {
+        frame.push(flags);
+        frame.push(body.len() as u8);
+    }
+    frame.extend_from_slice(body);
+    frame
+}
+
+// Reads a frame and returns its flags and body.
+fn read_frame(stream: &mut impl Read) -> Result<(u8, Vec<u8>), io::Error> {
+    let mut flags = [0u8; 1];
+    stream.read_exact(&mut flags)?;
+    let size = if flags[0] & FLAG_LONG != 0 {
+        let mut size = [0u8; 8];
+        stream.read_exact(&mut size)?;
+        u64::from_be_bytes(size)
+    } else {
+        let mut size = [0u8; 1];
+        stream.read_exact(&mut size)?;
+        size[0] as u64
+    };
+    if size > MAX_FRAME_SIZE {
+        return Err(error(format!("the ZMQ frame size {} is too large", size)));
+    }
+    let mut body = vec![0u8; size as usize];
+    stream.read_exact(&mut body)?;
+    Ok((flags[0], body))
+}
+
+// Subscribes to a topic of a ZMQ PUB socket and calls on_message for each
+// message published under it. Only returns on errors, e.g. when the
+// connection is lost or was quiet for too long.
+pub fn subscribe(
+    endpoint: &ZmqEndpoint,
+    topic: &[u8],
+    on_message: &dyn Fn(),
+) -> Result<(), io::Error> {
+    let mut stream: TcpStream = socks::connect(
+        &endpoint.host,
+        endpoint.port,
+        endpoint.proxy,
+        CONNECT_TIMEOUT,
+    )?;
+    stream.set_read_timeout(Some(QUIET_TIMEOUT))?;
+    handshake(&mut stream, topic)?;
+    receive(&mut stream, topic, on_message)
+}
+
+fn handshake(stream: &mut (impl Read + Write), topic: &[u8]) -> Result<(), io::Error> {
+    stream.write_all(&greeting())?;
+    let mut peer_greeting = [0u8; GREETING_LENGTH];
+    stream.read_exact(&mut peer_greeting)?;
+    if peer_greeting[0] != 0xFF || peer_greeting[9] != 0x7F || peer_greeting[10] < 3 {
+        return Err(error("the peer doesn't speak ZMTP 3".to_string()));
+    }
+    if &peer_greeting[12..16] != b"NULL" {
+        return Err(error(
+            "the peer requires an unsupported security mechanism".to_string(),
+        ));
+    }
+
+    stream.write_all(&ready())?;
+    let (flags, body) = read_frame(stream)?;
+    if flags & FLAG_COMMAND == 0 || !body.starts_with(b"\x05READY") {
+        return Err(error("the peer didn't send a READY command".to_string()));
+    }
+
+    // ZMTP 3.0 subscribes with a message starting with 0x01.
+    let mut subscription = vec![1];
+    subscription.extend_from_slice(topic);
+    stream.write_all(&frame(0, &subscription))?;
+    Ok(())
+}
+
+// Reads the published messages. A message consists of frames and starts
+// with the topic.
+fn receive(stream: &mut impl Read, topic: &[u8], on_message: &dyn Fn()) -> Result<(), io::Error> {
+    let mut first_frame = true;
+    loop {
+        let (flags, body) = read_frame(stream)?;
+        if flags & FLAG_COMMAND != 0 {
+            continue;
+        }
+        if first_frame && body == topic {
+            debug!("Received a ZMQ {} message", String::from_utf8_lossy(topic));
+            on_message();
+        }
+        first_frame = flags & FLAG_MORE == 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+    use std::net::TcpListener;
+    use std::thread;
+
+    #[test]
+    fn test_subscribe() {
+        assert_eq!(
+            ZmqEndpoint::parse("[::1]:28332", None).map(|e| (e.host, e.port)),
+            Some(("::1".to_string(), 28332))
+        );
+        assert_eq!(ZmqEndpoint::parse("tcp://127.0.0.1", None), None);
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let publisher = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut client_greeting = [0u8; GREETING_LENGTH];
+            stream.read_exact(&mut client_greeting).unwrap();
+            let mut server_greeting = greeting();
+            server_greeting[32] = 1; // as-server
+            stream.write_all(&server_greeting).unwrap();
+            let (_, client_ready) = read_frame(&mut stream).unwrap();
+            stream
+                .write_all(&frame(FLAG_COMMAND, b"\x05READY"))
+                .unwrap();
+            let (_, subscription) = read_frame(&mut stream).unwrap();
+            // Two hashblock messages with a hash and a sequence number each
+            // and a message of another topic in between.
+            for topic in [&b"hashblock"[..], b"hashtx", b"hashblock"] {
+                stream.write_all(&frame(FLAG_MORE, topic)).unwrap();
+                stream.write_all(&frame(FLAG_MORE, &[0u8; 32])).unwrap();
+                stream.write_all(&frame(0, &[0u8; 4])).unwrap();
+            }
+            (client_greeting, client_ready, subscription)
+        });
+
+        let messages = Cell::new(0);
+        let endpoint = ZmqEndpoint::parse(&format!("tcp://127.0.0.1:{}", port), None).unwrap();
+        let result = subscribe(&endpoint, TOPIC_HASHBLOCK, &|| {
+            messages.set(messages.get() + 1)
+        });
+        assert_eq!(
+            result.unwrap_err().kind(),
+            io::ErrorKind::UnexpectedEof,
+            "returns when the publisher disconnects"
+        );
+        assert_eq!(messages.get(), 2);
+
+        let (client_greeting, client_ready, subscription) = publisher.join().unwrap();
+        assert_eq!(client_greeting, greeting());
+        assert!(client_ready.ends_with(b"Socket-Type\x00\x00\x00\x03SUB"));
+        assert_eq!(subscription, b"\x01hashblock");
+    }
+}
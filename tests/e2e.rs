@@ -0,0 +1,331 @@
+// End-to-end tests against real bitcoind nodes running in containers. Each
+// bitcoind version of the matrix is started in regtest mode, forked with
+// invalidateblock and reconsiderblock, and the API of a fork-observer
+// instance watching all of them is checked for the fork.
+//
+// Needs docker (or a compatible CLI, see E2E_DOCKER), so it's only built with
+// the e2e feature and ignored unless asked for, e.g. with --all-features:
+//
+//   cargo test --features e2e --test e2e -- --ignored
+//
+// Environment variables:
+//   E2E_BITCOIND_VERSIONS  comma-separated versions, see DEFAULT_VERSIONS
+//   E2E_BITCOIND_IMAGE     image with a {version} placeholder, see DEFAULT_IMAGE
+//   E2E_DOCKER             container CLI, defaults to docker
+
+use std::env;
+use std::fs;
+use std::net::TcpListener;
+use std::path::PathBuf;
+use std::process::{Child, Command};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use bitcoincore_rpc::{Auth, Client, RpcApi};
+use serde_json::{json, Value};
+
+const DEFAULT_VERSIONS: &str = "25.2,26.2,27.1,28.0";
+const DEFAULT_IMAGE: &str = "bitcoin/bitcoin:{version}";
+const DEFAULT_DOCKER: &str = "docker";
+
+const RPC_PORT: u16 = 18443;
+const RPC_USER: &str = "e2e";
+const RPC_PASSWORD: &str = "e2e";
+
+// P2WSH of OP_TRUE on regtest, so blocks can be mined without a wallet.
+const MINING_ADDRESS: &str = "bcrt1qft5p2uhsdcdc3l2ua4ap5qqfg4pjaqlp250x7us7a8qqhrxrxfsqseac85";
+
+const STARTUP_TIMEOUT: Duration = Duration::from_secs(60);
+const OBSERVER_TIMEOUT: Duration = Duration::from_secs(90);
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+fn docker() -> String {
+    env::var("E2E_DOCKER").unwrap_or_else(|_| DEFAULT_DOCKER.to_string())
+}
+
+fn versions() -> Vec<String> {
+    env::var("E2E_BITCOIND_VERSIONS")
+        .unwrap_or_else(|_| DEFAULT_VERSIONS.to_string())
+        .split(',')
+        .map(|version| version.trim().to_string())
+        .filter(|version| !version.is_empty())
+        .collect()
+}
+
+fn image(version: &str) -> String {
+    env::var("E2E_BITCOIND_IMAGE")
+        .unwrap_or_else(|_| DEFAULT_IMAGE.to_string())
+        .replace("{version}", version)
+}
+
+// Runs the container CLI and returns its trimmed stdout.
+fn run_docker(args: &[&str]) -> String {
+    let output = Command::new(docker())
+        .args(args)
+        .output()
+        .unwrap_or_else(|e| panic!("could not run {} {:?}: {}", docker(), args, e));
+    assert!(
+        output.status.success(),
+        "{} {:?} failed: {}",
+        docker(),
+        args,
+        String::from_utf8_lossy(&output.stderr)
+    );
+    String::from_utf8_lossy(&output.stdout).trim().to_string()
+}
+
+// Polls f until it returns Some or the timeout is reached.
+fn wait_for<T>(what: &str, timeout: Duration, mut f: impl FnMut() -> Option<T>) -> T {
+    let start = Instant::now();
+    loop {
+        if let Some(value) = f() {
+            return value;
+        }
+        if start.elapsed() > timeout {
+            panic!("timed out after {:?} waiting for {}", timeout, what);
+        }
+        thread::sleep(POLL_INTERVAL);
+    }
+}
+
+fn free_port() -> u16 {
+    TcpListener::bind("127.0.0.1:0")
+        .and_then(|listener| listener.local_addr())
+        .expect("could not find a free port")
+        .port()
+}
+
+// A regtest bitcoind container, removed when dropped.
+struct Bitcoind {
+    version: String,
+    container: String,
+    rpc_port: u16,
+    client: Client,
+}
+
+impl Bitcoind {
+    fn start(version: &str) -> Self {
+        let image = image(version);
+        let container = run_docker(&[
+            "run",
+            "--detach",
+            "--publish",
+            &format!("127.0.0.1::{}", RPC_PORT),
+            &image,
+            "bitcoind",
+            "-regtest",
+            "-printtoconsole",
+            "-rpcbind=0.0.0.0",
+            "-rpcallowip=0.0.0.0/0",
+            &format!("-rpcport={}", RPC_PORT),
+            &format!("-rpcuser={}", RPC_USER),
+            &format!("-rpcpassword={}", RPC_PASSWORD),
+        ]);
+        // e.g. 127.0.0.1:49153
+        let published = run_docker(&["port", &container, &RPC_PORT.to_string()]);
+        let rpc_port = published
+            .lines()
+            .next()
+            .and_then(|line| line.rsplit(':').next())
+            .and_then(|port| port.parse().ok())
+            .unwrap_or_else(|| panic!("could not parse the published port '{}'", published));
+        let client = Client::new(
+            &format!("http://127.0.0.1:{}", rpc_port),
+            Auth::UserPass(RPC_USER.to_string(), RPC_PASSWORD.to_string()),
+        )
+        .expect("could not create the RPC client");
+        let bitcoind = Bitcoind {
+            version: version.to_string(),
+            container,
+            rpc_port,
+            client,
+        };
+        wait_for(
+            &format!("bitcoind {} to start", version),
+            STARTUP_TIMEOUT,
+            || bitcoind.client.get_blockchain_info().ok(),
+        );
+        bitcoind
+    }
+
+    fn call(&self, method: &str, params: &[Value]) -> Value {
+        self.client
+            .call(method, params)
+            .unwrap_or_else(|e| panic!("{} on bitcoind {}: {}", method, self.version, e))
+    }
+
+    fn generate(&self, blocks: u64) -> Vec<String> {
+        serde_json::from_value(
+            self.call("generatetoaddress", &[json!(blocks), json!(MINING_ADDRESS)]),
+        )
+        .expect("generatetoaddress didn't return block hashes")
+    }
+
+    // Mines a chain with a stale block at its second to last height and
+    // returns the hashes of the stale block and of the active tip.
+    fn fork(&self) -> (String, String) {
+        self.generate(10);
+        let stale = self.generate(1).remove(0);
+        self.call("invalidateblock", &[json!(stale)]);
+        let active = self.generate(2).pop().unwrap();
+        // The stale block is valid again, but has less work than the
+        // active chain.
+        self.call("reconsiderblock", &[json!(stale)]);
+        (stale, active)
+    }
+}
+
+impl Drop for Bitcoind {
+    fn drop(&mut self) {
+        let _ = Command::new(docker())
+            .args(["rm", "--force", &self.container])
+            .output();
+    }
+}
+
+// A fork-observer instance with one regtest network per bitcoind, killed
+// when dropped.
+struct Observer {
+    dir: PathBuf,
+    address: String,
+    process: Child,
+}
+
+impl Observer {
+    fn start(nodes: &[Bitcoind]) -> Self {
+        let dir = env::temp_dir().join(format!("fork-observer-e2e-{}", std::process::id()));
+        fs::create_dir_all(&dir).expect("could not create the temporary directory");
+        let address = format!("127.0.0.1:{}", free_port());
+
+        let mut config = format!(
+            r#"
+database_path = "{}"
+www_path = "{}"
+query_interval = 1
+address = "{}"
+footer_html = ""
+"#,
+            dir.join("db.sqlite").display(),
+            concat!(env!("CARGO_MANIFEST_DIR"), "/www"),
+            address,
+        );
+        for (id, node) in nodes.iter().enumerate() {
+            config.push_str(&format!(
+                r#"
+[[networks]]
+id = {id}
+name = "regtest {version}"
+description = ""
+min_fork_height = 0
+max_interesting_heights = 100
+
+    [[networks.nodes]]
+    id = 0
+    name = "bitcoind {version}"
+    description = ""
+    rpc_host = "127.0.0.1"
+    rpc_port = {port}
+    rpc_user = "{user}"
+    rpc_password = "{password}"
+"#,
+                id = id,
+                version = node.version,
+                port = node.rpc_port,
+                user = RPC_USER,
+                password = RPC_PASSWORD,
+            ));
+        }
+        let config_path = dir.join("config.toml");
+        fs::write(&config_path, config).expect("could not write the configuration");
+
+        let process = Command::new(env!("CARGO_BIN_EXE_fork-observer"))
+            .env("CONFIG_FILE", &config_path)
+            .spawn()
+            .expect("could not start fork-observer");
+        Observer {
+            dir,
+            address,
+            process,
+        }
+    }
+
+    fn data(&self, network: usize) -> Option<Value> {
+        let url = format!("http://{}/api/v2/{}/data.json", self.address, network);
+        let response = minreq::get(url).with_timeout(5).send().ok()?;
+        if response.status_code != 200 {
+            return None;
+        }
+        response.json().ok()
+    }
+}
+
+impl Drop for Observer {
+    fn drop(&mut self) {
+        let _ = self.process.kill();
+        let _ = self.process.wait();
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+fn tip_status<'a>(data: &'a Value, hash: &str) -> Option<&'a str> {
+    data["nodes"][0]["tips"]
+        .as_array()?
+        .iter()
+        .find(|tip| tip["hash"] == hash)
+        .and_then(|tip| tip["status"].as_str())
+}
+
+fn header<'a>(data: &'a Value, hash: &str) -> Option<&'a Value> {
+    data["header_infos"]
+        .as_array()?
+        .iter()
+        .find(|header| header["hash"] == hash)
+}
+
+#[test]
+#[ignore = "needs docker, run with --ignored"]
+fn test_fork_across_versions() {
+    let nodes: Vec<Bitcoind> = versions().iter().map(|v| Bitcoind::start(v)).collect();
+    let forks: Vec<(String, String)> = nodes.iter().map(Bitcoind::fork).collect();
+    let observer = Observer::start(&nodes);
+
+    for (network, (node, (stale, active))) in nodes.iter().zip(forks.iter()).enumerate() {
+        let data = wait_for(
+            &format!("the fork on bitcoind {}", node.version),
+            OBSERVER_TIMEOUT,
+            || {
+                observer.data(network).filter(|data| {
+                    tip_status(data, active) == Some("active") && header(data, stale).is_some()
+                })
+            },
+        );
+        assert_eq!(
+            tip_status(&data, stale),
+            Some("valid-fork"),
+            "bitcoind {}",
+            node.version
+        );
+        assert_eq!(
+            data["nodes"][0]["reachable"], true,
+            "bitcoind {}",
+            node.version
+        );
+
+        // Both branches share the parent of the stale block.
+        let stale_header = header(&data, stale).unwrap();
+        let active_header = header(&data, active).unwrap();
+        assert_eq!(stale_header["height"], 11, "bitcoind {}", node.version);
+        assert_eq!(active_header["height"], 12, "bitcoind {}", node.version);
+        let fork_parent = data["header_infos"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|header| header["height"] == 11 && header["hash"] != *stale)
+            .unwrap_or_else(|| panic!("no active block at height 11 on {}", node.version));
+        assert_eq!(
+            stale_header["prev_blockhash"], fork_parent["prev_blockhash"],
+            "bitcoind {}",
+            node.version
+        );
+    }
+}
@@ -28,6 +28,7 @@ const status_to_color = {
   "valid-fork": "cyan",
   "valid-headers": "red",
   "headers-only": "yellow",
+  "pool": "gold",
 }
 
 function draw() {
@@ -50,6 +51,18 @@ function draw() {
     });
   });
 
+  // the blocks mining pools let their miners build on
+  state_pools.filter(pool => pool.hash != null).forEach(pool => {
+    if (!(pool.hash in hash_to_tipstatus)) {
+      hash_to_tipstatus[pool.hash] = {}
+    }
+    if (!("pool" in hash_to_tipstatus[pool.hash])) {
+      hash_to_tipstatus[pool.hash]["pool"] = { status: "pool", count: 0, nodes: [] }
+    }
+    hash_to_tipstatus[pool.hash]["pool"].count++
+    hash_to_tipstatus[pool.hash]["pool"].nodes.push(pool)
+  });
+
   header_infos.forEach(header_info => {
     let status = hash_to_tipstatus[header_info.hash];
     header_info.status = status == undefined? "in-chain" : Object.values(status)
@@ -237,6 +250,7 @@ function draw() {
                       <span class="col-2">nonce</span><span class="col-4 font-monospace">0x${d.data.data.nonce.toString(16)}</span>
                       <span class="col-2">bits</span><span class="col-4 font-monospace">0x${d.data.data.bits.toString(16)}</span>
                       ${ d.data.data.miner != "" ? '<span class="col-2">miner</span><span class="col-4 font-monospace">' + d.data.data.miner + '</span>' : '' }
+                      ${ d.data.data.own ? '<span class="col-2">mined by</span><span class="col-4">us</span>' : '' }
                     </div>
                     <div class="row"><span class="col">${status_text}</span></div>
                   </div>
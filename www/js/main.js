@@ -1,6 +1,6 @@
-const getNetworks = new Request('api/networks.json');
-const getInfo = new Request('api/info.json');
-const changeSSE = new EventSource('api/changes');
+const getNetworks = new Request('api/v1/networks.json');
+const getInfo = new Request('api/v1/info.json');
+const changeSSE = new EventSource('api/v1/changes');
 
 const networkSelect = d3.select("#network")
 const nodeInfoRow = d3.select("#node_infos")
@@ -21,6 +21,7 @@ const PAGE_NAME = "fork-observer"
 var state_selected_network_id = 0
 var state_networks = []
 var state_data = {}
+var state_pools = []
 
 async function fetch_info() {
   console.debug("called fetch_info()")
@@ -33,10 +34,14 @@ async function fetch_info() {
 
 async function fetch_data() {
   console.debug("called fetch_data()")
-  await fetch(`api/${state_selected_network_id}/data.json`)
+  await fetch(`api/v1/${state_selected_network_id}/data.json`)
     .then(response => response.json())
     .then(data => state_data = data)
     .catch(console.error);
+  await fetch(`api/v1/${state_selected_network_id}/pools.json`)
+    .then(response => response.json())
+    .then(pools => state_pools = pools.pools)
+    .catch(console.error);
 }
 
 async function fetch_networks() {